@@ -0,0 +1,106 @@
+//! Measures how fast responses can be written, by sending lots of keep-alive requests over loopback.
+//! Run with `cargo run --release --example write_bench`.
+
+use std::{
+    io::{BufRead, BufReader, Cursor, Read, Write},
+    net::TcpStream,
+    thread,
+    time::{Duration, Instant},
+};
+
+use afire::{
+    prelude::*,
+    trace::{set_log_level, Level},
+};
+
+const PORT: u16 = 8080;
+const CONNECTIONS: usize = 8;
+const REQUESTS: usize = 5_000;
+
+fn main() {
+    set_log_level(Level::Error);
+    let mut server = Server::<()>::new([127, 0, 0, 1], PORT);
+    server.route(Method::GET, "/static", |_req| {
+        Response::new()
+            .text("Hello, World!")
+            .header("X-Bench", "static")
+    });
+    server.route(Method::GET, "/stream", |_req| {
+        Response::new().stream(Cursor::new(vec![b'a'; 64 * 1024]))
+    });
+    thread::spawn(move || server.start_threaded(CONNECTIONS).unwrap());
+    thread::sleep(Duration::from_millis(100));
+
+    for path in ["/static", "/stream"] {
+        let start = Instant::now();
+        let clients = (0..CONNECTIONS)
+            .map(|_| thread::spawn(move || client(path)))
+            .collect::<Vec<_>>();
+        let bytes = clients
+            .into_iter()
+            .map(|x| x.join().unwrap())
+            .sum::<usize>();
+
+        let elapsed = start.elapsed().as_secs_f64();
+        let requests = (CONNECTIONS * REQUESTS) as f64;
+        println!(
+            "{path:<8} {:>10.0} req/s {:>8.1} MiB/s",
+            requests / elapsed,
+            bytes as f64 / elapsed / 1024.0 / 1024.0
+        );
+    }
+}
+
+/// Sends [`REQUESTS`] requests over one connection, returning how many body bytes were received.
+fn client(path: &str) -> usize {
+    let stream = TcpStream::connect(("127.0.0.1", PORT)).unwrap();
+    let mut stream = BufReader::new(stream);
+    let request = format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    let mut bytes = 0;
+
+    for _ in 0..REQUESTS {
+        stream.get_mut().write_all(request.as_bytes()).unwrap();
+        bytes += read_body(&mut stream);
+    }
+
+    bytes
+}
+
+/// Reads a response, returning the length of its body.
+fn read_body(stream: &mut BufReader<TcpStream>) -> usize {
+    let mut length = None;
+    let mut chunked = false;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        stream.read_line(&mut line).unwrap();
+        let lower = line.trim_end().to_ascii_lowercase();
+        if lower.is_empty() {
+            break;
+        }
+
+        if let Some(len) = lower.strip_prefix("content-length:") {
+            length = Some(len.trim().parse().unwrap());
+        }
+        chunked |= lower == "transfer-encoding: chunked";
+    }
+
+    if !chunked {
+        let mut body = vec![0; length.unwrap_or(0)];
+        stream.read_exact(&mut body).unwrap();
+        return body.len();
+    }
+
+    let mut total = 0;
+    loop {
+        line.clear();
+        stream.read_line(&mut line).unwrap();
+        let size = usize::from_str_radix(line.trim_end(), 16).unwrap();
+        let mut chunk = vec![0; size + 2];
+        stream.read_exact(&mut chunk).unwrap();
+        if size == 0 {
+            return total;
+        }
+        total += size;
+    }
+}
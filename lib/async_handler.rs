@@ -0,0 +1,120 @@
+//! Support for async route handlers.
+//! Enabled with the `async` feature.
+//!
+//! afire itself is synchronous, so async handlers added with [`crate::Server::route_async`] are run to completion on the worker thread that is handling the request.
+//! By default this is done with [`block_on`], a minimal executor that just parks the thread while the future is pending.
+//! This works with any future that doesn't depend on a specific runtime (like one from a channel or another thread).
+//!
+//! Futures that need a runtime's reactor (like most tokio based database clients) can be run on that runtime with [`crate::Server::async_executor`] instead.
+//!
+//! Handlers are `AsyncFn`s, so async closures (`async |req| ...`) can be used directly; these need Rust 1.85 or newer, which is afire's minimum supported version.
+//!
+//! ## Example
+//! ```rust,ignore
+//! # use afire::{Server, Method, Response};
+//! let runtime = tokio::runtime::Runtime::new().unwrap();
+//! let handle = runtime.handle().clone();
+//!
+//! let mut server = Server::<()>::new("localhost", 8080)
+//!     // Run async handlers on the tokio runtime
+//!     .async_executor(move |future| handle.block_on(future));
+//!
+//! server.route_async(Method::GET, "/", async |req| {
+//!     let user = database.get_user(req.param("id").unwrap()).await;
+//!     Response::new().text(user.name)
+//! });
+//! ```
+
+use std::{
+    future::Future,
+    pin::{pin, Pin},
+    sync::Arc,
+    task::{Context, Poll, Wake, Waker},
+    thread::{self, Thread},
+};
+
+use crate::Response;
+
+/// A boxed future that resolves to a response, as passed to the [`crate::Server::async_executor`].
+pub type BoxFuture<'a> = Pin<Box<dyn Future<Output = Response> + 'a>>;
+
+pub(crate) type AsyncExecutor = Box<dyn Fn(BoxFuture<'_>) -> Response + Send + Sync>;
+
+/// Runs a future to completion on the current thread, parking it while the future is pending.
+/// This is the default [`crate::Server::async_executor`].
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(out) => return out,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+/// Wakes a thread parked in [`block_on`].
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        future::Future,
+        pin::Pin,
+        sync::{Arc, Mutex},
+        task::{Context, Poll, Waker},
+        thread,
+        time::Duration,
+    };
+
+    use super::block_on;
+
+    /// A future that is completed from another thread.
+    #[derive(Default)]
+    struct Remote(Arc<Mutex<(Option<u32>, Option<Waker>)>>);
+
+    impl Future for Remote {
+        type Output = u32;
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<u32> {
+            let mut inner = self.0.lock().unwrap();
+            match inner.0 {
+                Some(i) => Poll::Ready(i),
+                None => {
+                    inner.1 = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_block_on() {
+        assert_eq!(block_on(async { 1 + 1 }), 2);
+
+        let future = Remote::default();
+        let inner = future.0.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            let mut inner = inner.lock().unwrap();
+            inner.0 = Some(5);
+            if let Some(waker) = inner.1.take() {
+                waker.wake();
+            }
+        });
+
+        assert_eq!(block_on(async { future.await * 2 }), 10);
+    }
+}
@@ -3,11 +3,13 @@
 use std::{
     error,
     fmt::{self, Display, Formatter},
+    io::ErrorKind,
     rc::Rc,
     result,
+    str::FromStr,
 };
 
-use crate::{Method, Request};
+use crate::{Content, Method, Request, Response, Status};
 
 /// Easy way to use a Result<T, [`crate::Error`]>
 pub type Result<T> = result::Result<T, Error>;
@@ -27,6 +29,9 @@ pub enum Error {
     /// Error while parsing request HTTP
     Parse(ParseError),
 
+    /// Error while starting a WebSocket connection
+    WebSocket(WebSocketError),
+
     /// IO Errors
     Io(String),
 
@@ -34,6 +39,37 @@ pub enum Error {
     None,
 }
 
+/// Errors from getting a typed value out of a request, like with [`Request::param_parsed`] or [`crate::Query::get_parsed`].
+/// These are the client's fault, so they can be turned into a `400 Bad Request` response that says what was wrong.
+/// ## Example
+/// ```rust
+/// # use afire::{Server, Response, Method};
+/// # let mut server = Server::<()>::new("localhost", 8080);
+/// server.route(Method::GET, "/user/{id}", |req| {
+///     let id = match req.param_parsed::<u32>("id") {
+///         Ok(id) => id,
+///         // 400 Bad Request: Invalid path parameter `id` ("abc"): invalid digit found in string
+///         Err(e) => return e.into(),
+///     };
+///
+///     Response::new().text(format!("User #{id}"))
+/// });
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RouteError {
+    /// A parameter was not in the request.
+    /// Holds a description of the parameter, like ``path parameter `id` ``.
+    Missing(String),
+
+    /// A parameter couldn't be parsed into the requested type.
+    /// Holds a description of the parameter, its value and the parse error.
+    Invalid(String, String, String),
+
+    /// The request body couldn't be decoded, like with [`Request::json`].
+    /// Holds the reason, like the content type being wrong or the decoder's error message.
+    InvalidBody(String),
+}
+
 /// Errors that can occur while starting the server
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum StartupError {
@@ -53,6 +89,11 @@ pub enum HandleError {
     /// Route matching request path not found
     NotFound(Method, String),
 
+    /// Routes match the request path, but not its method.
+    /// Holds the request method, the path and the methods the matching routes accept.
+    /// Only used if [`crate::Server::method_not_allowed`] is enabled.
+    MethodNotAllowed(Method, String, Vec<Method>),
+
     /// A route or middleware panicked while running
     Panic(Box<Result<Rc<Request>>>, String),
 }
@@ -83,6 +124,54 @@ pub enum ParseError {
 
     /// Invalid Header in Request HTTP
     InvalidHeader,
+
+    /// Invalid url encoding in a path parameter
+    InvalidPathParam,
+
+    /// The request is for an HTTP version other than 1.x
+    UnsupportedVersion,
+
+    /// Invalid chunk or trailer in a `Transfer-Encoding: chunked` body
+    InvalidChunkedBody,
+
+    /// The request has more headers than [`crate::Server::max_header_count`]
+    TooManyHeaders,
+
+    /// A header line is longer than [`crate::Server::max_header_size`]
+    HeaderTooLarge,
+
+    /// The request line and headers are longer than [`crate::Server::max_head_size`]
+    HeadTooLarge,
+
+    /// The request target is longer than [`crate::Server::max_uri_length`]
+    UriTooLong,
+
+    /// The request has an `Expect` header with an expectation other than `100-continue`
+    ExpectationFailed,
+
+    /// The request has a `Content-Length` that isn't a number, or several that don't match
+    InvalidContentLength,
+
+    /// The request has a `Transfer-Encoding` that doesn't end in `chunked`, or has both a `Transfer-Encoding` and a `Content-Length`
+    InvalidTransferEncoding,
+
+    /// The request body is larger than [`crate::Server::max_body_size`]
+    BodyTooLarge,
+}
+
+/// Errors that can occur while starting a WebSocket connection with [`crate::web_socket::WebSocketExt::ws`].
+/// These are the client's fault, so they can be turned into an error response with [`Response::from`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WebSocketError {
+    /// The request doesn't have an `Upgrade: websocket` header.
+    NotUpgrade,
+
+    /// The request doesn't have a `Sec-WebSocket-Key` header.
+    MissingKey,
+
+    /// The client asked for a WebSocket version other than 13, the only one supported.
+    /// Holds the version the client sent.
+    UnsupportedVersion(String),
 }
 
 /// Error that can occur while reading or writing to a stream
@@ -90,6 +179,19 @@ pub enum ParseError {
 pub enum StreamError {
     /// The stream ended unexpectedly
     UnexpectedEof,
+
+    /// The client stopped reading data, so a write could not be completed within the socket timeout
+    ClientDisconnected,
+
+    /// A read from the client did not complete within the socket timeout
+    TimedOut,
+
+    /// The client reset or aborted the connection
+    ConnectionReset,
+
+    /// The client didn't start sending a request within the keep-alive timeout (or the socket timeout for the first request).
+    /// Unlike [`StreamError::TimedOut`], the connection is closed without sending a response.
+    IdleTimeout,
 }
 
 impl error::Error for Error {}
@@ -100,6 +202,7 @@ impl Display for Error {
             Error::Startup(e) => fmt::Display::fmt(e, f),
             Error::Stream(e) => fmt::Display::fmt(e, f),
             Error::Parse(e) => fmt::Display::fmt(e, f),
+            Error::WebSocket(e) => fmt::Display::fmt(e, f),
             Error::Io(e) => f.write_str(e),
             Error::None => f.write_str("None"),
         }
@@ -112,6 +215,9 @@ impl Display for HandleError {
             HandleError::NotFound(method, path) => {
                 f.write_fmt(format_args!("No route found at {method} {path}"))
             }
+            HandleError::MethodNotAllowed(method, path, _) => {
+                f.write_fmt(format_args!("Method {method} not allowed at {path}"))
+            }
             HandleError::Panic(_req, err) => {
                 f.write_fmt(format_args!("Route handler panicked: {err}"))
             }
@@ -135,6 +241,12 @@ impl Display for StreamError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         f.write_str(match self {
             StreamError::UnexpectedEof => "The stream ended unexpectedly",
+            StreamError::ClientDisconnected => {
+                "The client stopped reading data before the write could be completed"
+            }
+            StreamError::TimedOut => "Timed out waiting for data from the client",
+            StreamError::ConnectionReset => "The connection was reset by the client",
+            StreamError::IdleTimeout => "Timed out waiting for the client to send a request",
         })
     }
 }
@@ -152,10 +264,83 @@ impl Display for ParseError {
             ParseError::InvalidQuery => "Invalid Query in Path",
             ParseError::InvalidMethod => "Invalid Method in Request HTTP",
             ParseError::InvalidHeader => "Invalid Header in Request HTTP",
+            ParseError::InvalidPathParam => "Invalid url encoding in a path parameter",
+            ParseError::UnsupportedVersion => "Unsupported HTTP version in Request HTTP",
+            ParseError::InvalidChunkedBody => "Invalid chunked body in Request HTTP",
+            ParseError::TooManyHeaders => "Too many headers in Request HTTP",
+            ParseError::HeaderTooLarge => "Header too large in Request HTTP",
+            ParseError::HeadTooLarge => "Request line and headers too large in Request HTTP",
+            ParseError::UriTooLong => "Request target too long in Request HTTP",
+            ParseError::ExpectationFailed => "Unsupported expectation in Request HTTP",
+            ParseError::InvalidContentLength => "Invalid Content-Length in Request HTTP",
+            ParseError::InvalidTransferEncoding => "Invalid Transfer-Encoding in Request HTTP",
+            ParseError::BodyTooLarge => "Body too large in Request HTTP",
         })
     }
 }
 
+impl Display for WebSocketError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            WebSocketError::NotUpgrade => f.write_str("Not a WebSocket upgrade request"),
+            WebSocketError::MissingKey => f.write_str("Missing Sec-WebSocket-Key header"),
+            WebSocketError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported WebSocket version `{version}`")
+            }
+        }
+    }
+}
+
+impl From<WebSocketError> for Response {
+    fn from(e: WebSocketError) -> Self {
+        let res = Response::new().text(&e).content(Content::TXT);
+        match e {
+            // RFC 6455 says to send the supported versions
+            WebSocketError::UnsupportedVersion(_) => res
+                .status(Status::UpgradeRequired)
+                .header("Sec-WebSocket-Version", "13"),
+            _ => res.status(Status::BadRequest),
+        }
+    }
+}
+
+impl error::Error for RouteError {}
+impl Display for RouteError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RouteError::Missing(name) => write!(f, "Missing {name}"),
+            RouteError::Invalid(name, value, err) => write!(f, "Invalid {name} ({value:?}): {err}"),
+            RouteError::InvalidBody(err) => write!(f, "Invalid body: {err}"),
+        }
+    }
+}
+
+impl From<RouteError> for Response {
+    fn from(e: RouteError) -> Self {
+        Response::new()
+            .status(Status::BadRequest)
+            .text(e)
+            .content(Content::TXT)
+    }
+}
+
+impl RouteError {
+    /// Parses a parameter from a request, with `name` describing it for error messages.
+    pub(crate) fn parse<T>(
+        name: impl Fn() -> String,
+        value: Option<&str>,
+    ) -> result::Result<T, Self>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let value = value.ok_or_else(|| RouteError::Missing(name()))?;
+        value
+            .parse()
+            .map_err(|e: T::Err| RouteError::Invalid(name(), value.to_owned(), e.to_string()))
+    }
+}
+
 impl From<StartupError> for Error {
     fn from(e: StartupError) -> Self {
         Error::Startup(e)
@@ -174,6 +359,12 @@ impl From<ParseError> for Error {
     }
 }
 
+impl From<WebSocketError> for Error {
+    fn from(e: WebSocketError) -> Self {
+        Error::WebSocket(e)
+    }
+}
+
 impl From<HandleError> for Error {
     fn from(e: HandleError) -> Self {
         Error::Handle(Box::new(e))
@@ -182,7 +373,13 @@ impl From<HandleError> for Error {
 
 impl From<std::io::Error> for Error {
     fn from(e: std::io::Error) -> Self {
-        Error::Io(e.to_string())
+        match e.kind() {
+            ErrorKind::TimedOut | ErrorKind::WouldBlock => StreamError::TimedOut.into(),
+            ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted | ErrorKind::BrokenPipe => {
+                StreamError::ConnectionReset.into()
+            }
+            _ => Error::Io(e.to_string()),
+        }
     }
 }
 
@@ -191,6 +388,10 @@ impl PartialEq for HandleError {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
             (HandleError::NotFound(m1, p1), HandleError::NotFound(m2, p2)) => m1 == m2 && p1 == p2,
+            (
+                HandleError::MethodNotAllowed(m1, p1, a1),
+                HandleError::MethodNotAllowed(m2, p2, a2),
+            ) => m1 == m2 && p1 == p2 && a1 == a2,
             (HandleError::Panic(_, s1), HandleError::Panic(_, s2)) => s1 == s2,
             _ => false,
         }
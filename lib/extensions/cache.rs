@@ -0,0 +1,539 @@
+//! Cache responses in memory, so expensive GET routes don't have to run for every request.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    header::Headers,
+    internal::common::ForceLock,
+    middleware::{MiddleResult, Middleware},
+    path::Path,
+    response::ResponseBody,
+    HeaderType, Method, Request, Response, Status,
+};
+
+/// Middleware that keeps responses to GET requests in memory, and serves later requests for the same resource from the cache without calling the route handler.
+///
+/// Responses are cached by their path, query and the request headers named in their `Vary` header.
+/// Entries expire after the time to live, and when the cache is larger than its max size the least recently used entries are removed.
+/// Cached responses get an `Age` header with how long ago they were cached, and every response that could be cached gets an `X-Cache` header of `HIT` or `MISS`.
+///
+/// Only `200 OK` responses with a buffered body are cached, and responses with a `Set-Cookie` header, `Vary: *` or a `Cache-Control` of `private` or `no-store` are skipped.
+/// Responses to requests with an `Authorization` or `Cookie` header are likely made for that user, so they are only cached if they have a `Cache-Control` of `public` or `s-maxage`.
+/// Requests with `Cache-Control: no-cache` skip the cache lookup, but their responses are still cached.
+///
+/// Like other middleware, the cache runs after the middleware attached after it, so attach it before middleware that must see every request (like [`crate::extension::Auth`]).
+/// Post hooks of those middleware also run on cached responses.
+///
+/// ## Example
+/// ```rust
+/// # use std::time::Duration;
+/// # use afire::{Server, Method, Middleware, Response, extension::Cache};
+/// # let mut server = Server::<()>::new("localhost", 8080);
+/// let cache = Cache::new(Duration::from_secs(60)).route("/reports/{id}");
+/// let store = cache.store();
+/// cache.attach(&mut server);
+///
+/// server.route(Method::GET, "/reports/{id}", |req| {
+///     // Something slow
+///     Response::new().text(format!("Report {}", req.param("id").unwrap()))
+/// });
+///
+/// server.route(Method::POST, "/reports/{id}", move |req| {
+///     store.purge(format!("/reports/{}", req.param("id").unwrap()));
+///     Response::new().text("Updated")
+/// });
+/// ```
+pub struct Cache {
+    ttl: Duration,
+    store: CacheStore,
+    routes: Vec<Path>,
+}
+
+/// A handle to the responses cached by a [`Cache`], for removing entries that are out of date.
+/// Cloning this gives another handle to the same cache.
+#[derive(Clone)]
+pub struct CacheStore(Arc<CacheInner>);
+
+struct CacheInner {
+    /// The max total size of the cached bodies.
+    /// This is atomic so it can be changed after handles to the store have been given out.
+    max_size: AtomicUsize,
+    entries: Mutex<Entries>,
+}
+
+#[derive(Default)]
+struct Entries {
+    /// Cached responses by path and query.
+    /// There can be more than one for a key if the response varies on request headers.
+    map: HashMap<(String, String), Vec<Entry>>,
+    /// The total size of the cached bodies.
+    size: usize,
+}
+
+struct Entry {
+    /// The request headers the response varies on, and their values.
+    vary: Vec<(HeaderType, Option<String>)>,
+    status: Status,
+    reason: Option<String>,
+    headers: Headers,
+    body: Vec<u8>,
+    ttl: Duration,
+    stored: Instant,
+    used: Instant,
+}
+
+/// Added to the extensions of requests that were served from the cache, so they aren't cached again.
+struct CacheHit;
+
+impl Cache {
+    /// Create a new cache, where responses are kept for `ttl`.
+    /// The cache is limited to 16 MiB of response bodies by default.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            store: CacheStore::default(),
+            routes: Vec::new(),
+        }
+    }
+
+    /// Set the max total size of the cached response bodies, in bytes.
+    /// This also applies to handles from [`Cache::store`] that were made before.
+    pub fn max_size(self, max_size: usize) -> Self {
+        self.store.0.max_size.store(max_size, Ordering::Relaxed);
+        self
+    }
+
+    /// Only cache requests with a path that matches this route path, which can have parameters and wildcards like [`crate::Server::route`].
+    /// Can be called more than once to cache multiple routes.
+    /// If no routes are added, all GET requests can be cached.
+    pub fn route(mut self, path: impl AsRef<str>) -> Self {
+        self.routes.push(Path::new(path.as_ref().to_owned()));
+        self
+    }
+
+    /// Gets a handle to the cached responses, to purge them from route handlers or other threads.
+    pub fn store(&self) -> CacheStore {
+        self.store.clone()
+    }
+
+    /// Checks if a request's response can be cached.
+    fn cacheable(&self, req: &Request) -> bool {
+        req.method == Method::GET
+            && (self.routes.is_empty()
+                || self
+                    .routes
+                    .iter()
+                    .any(|x| x.match_path(req.path.clone()).is_some()))
+    }
+}
+
+impl CacheStore {
+    /// Removes all the cached responses for a path, with any query.
+    pub fn purge(&self, path: impl AsRef<str>) {
+        let path = path.as_ref();
+        let mut entries = self.0.entries.force_lock();
+        let mut removed = 0;
+        entries.map.retain(|(key, _), x| {
+            let keep = key != path;
+            if !keep {
+                removed += x.iter().map(|x| x.body.len()).sum::<usize>();
+            }
+            keep
+        });
+        entries.size -= removed;
+    }
+
+    /// Removes all the cached responses.
+    pub fn clear(&self) {
+        let mut entries = self.0.entries.force_lock();
+        entries.map.clear();
+        entries.size = 0;
+    }
+
+    /// The number of cached responses.
+    pub fn len(&self) -> usize {
+        self.0.entries.force_lock().map.values().map(Vec::len).sum()
+    }
+
+    /// Checks if there are no cached responses.
+    pub fn is_empty(&self) -> bool {
+        self.0.entries.force_lock().map.is_empty()
+    }
+
+    /// The total size of the cached response bodies, in bytes.
+    pub fn size(&self) -> usize {
+        self.0.entries.force_lock().size
+    }
+
+    /// Gets a cached response for a request, if there is a fresh one.
+    fn get(&self, req: &Request) -> Option<Response> {
+        let key = (req.path.clone(), req.query.to_string());
+        let mut entries = self.0.entries.force_lock();
+        let entries = &mut *entries;
+        let variants = entries.map.get_mut(&key)?;
+
+        let mut removed = 0;
+        variants.retain(|x| {
+            let fresh = x.stored.elapsed() < x.ttl;
+            if !fresh {
+                removed += x.body.len();
+            }
+            fresh
+        });
+        entries.size -= removed;
+
+        let entry = variants.iter_mut().find(|x| {
+            x.vary
+                .iter()
+                .all(|(name, value)| req.headers.get(name).map(str::trim) == value.as_deref())
+        });
+        let res = entry.map(|entry| {
+            entry.used = Instant::now();
+            let mut res = Response::new().status(entry.status).bytes(&entry.body);
+            res.reason = entry.reason.clone();
+            res.headers = entry.headers.clone();
+            res.headers
+                .add("Age", entry.stored.elapsed().as_secs().to_string());
+            res
+        });
+
+        if variants.is_empty() {
+            entries.map.remove(&key);
+        }
+        res
+    }
+
+    /// Caches a response for `ttl`, removing the least recently used entries if the cache is too big.
+    fn insert(&self, req: &Request, res: &Response, vary: Vec<HeaderType>, ttl: Duration) {
+        let max_size = self.0.max_size.load(Ordering::Relaxed);
+        let body = match &res.data {
+            ResponseBody::Static(i) if i.len() <= max_size => i.clone(),
+            _ => return,
+        };
+
+        let vary = vary
+            .into_iter()
+            .map(|x| {
+                let value = req.headers.get(&x).map(|x| x.trim().to_owned());
+                (x, value)
+            })
+            .collect::<Vec<_>>();
+
+        let mut entries = self.0.entries.force_lock();
+        let entries = &mut *entries;
+        while entries.size + body.len() > max_size {
+            let oldest = entries
+                .map
+                .iter()
+                .flat_map(|(k, v)| v.iter().enumerate().map(move |(i, x)| (k, i, x.used)))
+                .min_by_key(|x| x.2)
+                .map(|(k, i, _)| (k.clone(), i));
+            let (key, index) = match oldest {
+                Some(i) => i,
+                None => break,
+            };
+
+            let variants = entries.map.get_mut(&key).unwrap();
+            entries.size -= variants.remove(index).body.len();
+            if variants.is_empty() {
+                entries.map.remove(&key);
+            }
+        }
+
+        let variants = entries
+            .map
+            .entry((req.path.clone(), req.query.to_string()))
+            .or_default();
+        if let Some(i) = variants.iter().position(|x| x.vary == vary) {
+            entries.size -= variants.remove(i).body.len();
+        }
+
+        entries.size += body.len();
+        let now = Instant::now();
+        variants.push(Entry {
+            vary,
+            status: res.status,
+            reason: res.reason.clone(),
+            headers: res.headers.clone(),
+            body,
+            ttl,
+            stored: now,
+            used: now,
+        });
+    }
+}
+
+impl Default for CacheStore {
+    /// Creates an empty cache, limited to 16 MiB of response bodies.
+    fn default() -> Self {
+        Self(Arc::new(CacheInner {
+            max_size: AtomicUsize::new(16 * 1024 * 1024),
+            entries: Mutex::new(Entries::default()),
+        }))
+    }
+}
+
+/// Wraps a route handler so its responses are kept in the store for `ttl`.
+/// Used by [`crate::Server::cached_route`].
+pub(crate) fn cached_handler(
+    store: CacheStore,
+    ttl: Duration,
+    handler: impl Fn(&Request) -> Response + Send + Sync + 'static,
+) -> impl Fn(&Request) -> Response + Send + Sync + 'static {
+    move |req| {
+        let no_cache = cache_control(&req.headers).any(|x| x.eq_ignore_ascii_case("no-cache"));
+        if let Some(res) = (!no_cache).then(|| store.get(req)).flatten() {
+            return res.header("X-Cache", "HIT");
+        }
+
+        let mut res = handler(req);
+        if let Some(vary) = cache_vary(req, &res) {
+            store.insert(req, &res, vary, ttl);
+            res.headers.add("X-Cache", "MISS");
+        }
+        res
+    }
+}
+
+/// Gets the request headers a response varies on, or None if it can't be cached.
+fn cache_vary(req: &Request, res: &Response) -> Option<Vec<HeaderType>> {
+    if res.status != Status::Ok || res.headers.has(HeaderType::SetCookie) {
+        return None;
+    }
+
+    let no_store = cache_control(&res.headers)
+        .any(|x| x.eq_ignore_ascii_case("private") || x.eq_ignore_ascii_case("no-store"));
+    if no_store {
+        return None;
+    }
+
+    // Responses to requests with credentials can only be shared if they say so (RFC 9111 §3.5)
+    let credentials = req.headers.has("Authorization") || !req.cookies.is_empty();
+    let shared = cache_control(&res.headers)
+        .any(|x| x.eq_ignore_ascii_case("public") || x.eq_ignore_ascii_case("s-maxage"));
+    if credentials && !shared {
+        return None;
+    }
+
+    let mut vary = Vec::new();
+    for name in res
+        .headers
+        .iter()
+        .filter(|x| x.name == HeaderType::Vary)
+        .flat_map(|x| x.value.split(','))
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+    {
+        if name == "*" {
+            return None;
+        }
+
+        let name = HeaderType::from(name);
+        if !vary.contains(&name) {
+            vary.push(name);
+        }
+    }
+
+    Some(vary)
+}
+
+/// Gets the names of the directives in the `Cache-Control` headers.
+fn cache_control(headers: &Headers) -> impl Iterator<Item = &str> {
+    headers
+        .iter()
+        .filter(|x| x.name.to_string().eq_ignore_ascii_case("Cache-Control"))
+        .flat_map(|x| x.list())
+        .map(|x| x.name)
+}
+
+impl Middleware for Cache {
+    fn pre(&self, req: &mut Request) -> MiddleResult {
+        let no_cache = cache_control(&req.headers).any(|x| x.eq_ignore_ascii_case("no-cache"));
+        if !self.cacheable(req) || no_cache {
+            return MiddleResult::Continue;
+        }
+
+        match self.store.get(req) {
+            Some(res) => {
+                req.extensions.insert(CacheHit);
+                MiddleResult::Send(res.header("X-Cache", "HIT"))
+            }
+            None => MiddleResult::Continue,
+        }
+    }
+
+    fn post(&self, req: &Request, res: &mut Response) -> MiddleResult {
+        if req.extensions.contains::<CacheHit>() || !self.cacheable(req) {
+            return MiddleResult::Continue;
+        }
+
+        if let Some(vary) = cache_vary(req, res) {
+            self.store.insert(req, res, vary, self.ttl);
+            res.headers.add("X-Cache", "MISS");
+        }
+
+        MiddleResult::Continue
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+        time::Duration,
+    };
+
+    use super::Cache;
+    use crate::{
+        testing::{TestRequest, TestServer},
+        Method, Middleware, Response, Server,
+    };
+
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    fn server(cache: Cache) -> TestServer {
+        let mut server = Server::<()>::new("localhost", 8080);
+        cache.attach(&mut server);
+        server.route(Method::GET, "/page", |req| {
+            let calls = CALLS.fetch_add(1, Ordering::Relaxed);
+            let lang = req.headers.get("Accept-Language").unwrap_or("en");
+            Response::new()
+                .text(format!("{lang} {calls}"))
+                .header("Vary", "Accept-Language")
+        });
+        server.route(Method::GET, "/private", |_| {
+            Response::new().header("Cache-Control", "private")
+        });
+        server.route(Method::GET, "/user", |req| {
+            let user = req.cookies.get("user").unwrap_or("guest");
+            Response::new().text(format!("Hello, {user}"))
+        });
+        server.route(Method::GET, "/public", |req| {
+            let user = req.cookies.get("user").unwrap_or("guest");
+            Response::new()
+                .text(format!("Hello, {user}"))
+                .header("Cache-Control", "public, max-age=60")
+        });
+        server.into()
+    }
+
+    #[test]
+    fn test_cache() {
+        let cache = Cache::new(Duration::from_millis(200));
+        let store = cache.store();
+        let server = server(cache);
+
+        let first = TestRequest::get("/page").send(&server);
+        assert_eq!(first.header("X-Cache"), Some("MISS"));
+
+        let res = TestRequest::get("/page").send(&server);
+        assert_eq!(res.header("X-Cache"), Some("HIT"));
+        assert_eq!(res.header("Age"), Some("0"));
+        assert_eq!(res.text(), first.text());
+
+        // Responses vary on the Accept-Language header
+        let res = TestRequest::get("/page")
+            .header("Accept-Language", "fr")
+            .send(&server);
+        assert_eq!(res.header("X-Cache"), Some("MISS"));
+        assert!(res.text().starts_with("fr "));
+        assert_eq!(store.len(), 2);
+
+        // The query is part of the key
+        let res = TestRequest::get("/page?a=b").send(&server);
+        assert_eq!(res.header("X-Cache"), Some("MISS"));
+
+        let res = TestRequest::get("/private").send(&server);
+        assert_eq!(res.header("X-Cache"), None);
+
+        store.purge("/page");
+        assert!(store.is_empty());
+        assert_eq!(store.size(), 0);
+        let res = TestRequest::get("/page").send(&server);
+        assert_eq!(res.header("X-Cache"), Some("MISS"));
+
+        thread::sleep(Duration::from_millis(250));
+        let res = TestRequest::get("/page").send(&server);
+        assert_eq!(res.header("X-Cache"), Some("MISS"));
+    }
+
+    #[test]
+    fn test_cached_route() {
+        let mut server = Server::<()>::new("localhost", 8080);
+        let calls = AtomicUsize::new(0);
+        server.cached_route(
+            Method::GET,
+            "/expensive",
+            Duration::from_secs(60),
+            move |_| Response::new().text(calls.fetch_add(1, Ordering::Relaxed)),
+        );
+        let cache = server.cache();
+        let server = TestServer::from(server);
+
+        let res = TestRequest::get("/expensive").send(&server);
+        assert_eq!(res.header("X-Cache"), Some("MISS"));
+        let res = TestRequest::get("/expensive").send(&server);
+        assert_eq!(res.header("X-Cache"), Some("HIT"));
+        assert_eq!(res.text(), "0");
+
+        cache.purge("/expensive");
+        let res = TestRequest::get("/expensive").send(&server);
+        assert_eq!(res.header("X-Cache"), Some("MISS"));
+        assert_eq!(res.text(), "1");
+    }
+
+    #[test]
+    fn test_eviction() {
+        // The size can be set after getting a handle to the store
+        let cache = Cache::new(Duration::from_secs(60));
+        let store = cache.store();
+        let server = server(cache.max_size(6));
+
+        TestRequest::get("/page?1").send(&server);
+        TestRequest::get("/page?2").send(&server);
+        TestRequest::get("/page?1").send(&server);
+        assert_eq!(store.len(), 1);
+        assert!(store.size() <= 6);
+    }
+
+    #[test]
+    fn test_credentials() {
+        let cache = Cache::new(Duration::from_secs(60));
+        let store = cache.store();
+        let server = server(cache);
+
+        // Each user gets their own page
+        for user in ["alice", "bob"] {
+            let res = TestRequest::get("/user")
+                .header("Cookie", format!("user={user}"))
+                .send(&server);
+            assert_eq!(res.text(), format!("Hello, {user}"));
+            assert_eq!(res.header("X-Cache"), None);
+        }
+
+        let res = TestRequest::get("/user")
+            .header("Authorization", "Bearer token")
+            .send(&server);
+        assert_eq!(res.header("X-Cache"), None);
+        assert!(store.is_empty());
+
+        // Unless the response says it can be shared
+        let res = TestRequest::get("/public")
+            .header("Cookie", "user=alice")
+            .send(&server);
+        assert_eq!(res.header("X-Cache"), Some("MISS"));
+        let res = TestRequest::get("/public")
+            .header("Cookie", "user=bob")
+            .send(&server);
+        assert_eq!(res.header("X-Cache"), Some("HIT"));
+        assert_eq!(res.text(), "Hello, alice");
+    }
+}
@@ -0,0 +1,295 @@
+//! Middleware to compress response bodies with gzip, deflate or brotli, based on the client's `Accept-Encoding` header.
+//! Enabled with the `compression` feature.
+
+use std::{
+    cell::RefCell,
+    fmt::{self, Display},
+    io::{self, Read},
+    mem,
+};
+
+use brotli::CompressorReader;
+use flate2::read::{GzEncoder, ZlibEncoder};
+
+use crate::{
+    accept::quality_list,
+    internal::handle::Writeable,
+    middleware::{MiddleResult, Middleware},
+    response::ResponseBody,
+    HeaderType, Request, Response, Status,
+};
+
+/// A content coding that [`Compression`] can apply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// `br`, [RFC 7932](https://www.rfc-editor.org/rfc/rfc7932).
+    /// Usually gives the smallest bodies, but is the slowest.
+    Brotli,
+    /// `gzip`, [RFC 1952](https://www.rfc-editor.org/rfc/rfc1952).
+    Gzip,
+    /// `deflate`, which in HTTP means the zlib format from [RFC 1950](https://www.rfc-editor.org/rfc/rfc1950).
+    Deflate,
+}
+
+/// Middleware to compress response bodies.
+///
+/// The encoding is picked from the ones the client accepts in its `Accept-Encoding` header, preferring the one with the highest quality value, then the order they were given to [`Compression::encodings`].
+/// Static bodies are compressed in memory and get an updated `Content-Length`, streamed bodies are compressed as they are sent.
+///
+/// Responses are left alone if they:
+/// - already have a `Content-Encoding`
+/// - can't have a body (1xx, 204 and 304) or are partial (206)
+/// - have a static body smaller than [`Compression::min_size`]
+/// - have a `Content-Type` that is usually already compressed, like images, video, audio and archives
+///
+/// Compressed responses get `Accept-Encoding` added to their `Vary` header, and strong `ETag`s are made weak, as the compressed body is a different representation.
+///
+/// ## Example
+/// ```rust
+/// # use afire::{Middleware, Server, extension::{Compression, compression::Encoding}};
+/// # fn test(server: &mut Server) {
+/// Compression::new()
+///     // Don't use brotli
+///     .encodings([Encoding::Gzip, Encoding::Deflate])
+///     .attach(server);
+/// # }
+/// ```
+pub struct Compression {
+    encodings: Vec<Encoding>,
+    min_size: usize,
+    level: u32,
+}
+
+impl Compression {
+    /// Create a new Compression middleware.
+    /// By default brotli, gzip and deflate are supported (preferred in that order), bodies under 1 KiB aren't compressed and the level is 6.
+    pub fn new() -> Self {
+        Self {
+            encodings: vec![Encoding::Brotli, Encoding::Gzip, Encoding::Deflate],
+            min_size: 1024,
+            level: 6,
+        }
+    }
+
+    /// Set the encodings to use, in order of preference.
+    pub fn encodings(self, encodings: impl IntoIterator<Item = Encoding>) -> Self {
+        Self {
+            encodings: encodings.into_iter().collect(),
+            ..self
+        }
+    }
+
+    /// Set the smallest static body that will be compressed, in bytes.
+    /// Small bodies often get bigger when compressed, once the encoding's headers are added.
+    /// Streamed bodies are always compressed, as their size isn't known.
+    pub fn min_size(self, min_size: usize) -> Self {
+        Self { min_size, ..self }
+    }
+
+    /// Set the compression level, from 0 (fastest) to 9 for gzip and deflate, or 11 for brotli (smallest).
+    /// Higher values are capped to the encoding's max.
+    pub fn level(self, level: u32) -> Self {
+        Self { level, ..self }
+    }
+
+    /// Picks the encoding to use for an `Accept-Encoding` header.
+    fn pick(&self, accept: &str) -> Option<Encoding> {
+        let accepted = quality_list(accept).collect::<Vec<_>>();
+        let quality = |name: &str| {
+            let find = |name: &str| {
+                accepted
+                    .iter()
+                    .find(|x| x.0.eq_ignore_ascii_case(name))
+                    .map(|x| x.1)
+            };
+            find(name).or_else(|| find("*")).unwrap_or(0.0)
+        };
+
+        let mut best = None;
+        for &encoding in &self.encodings {
+            let q = quality(&encoding.to_string());
+            if q > 0.0 && best.is_none_or(|(_, best)| q > best) {
+                best = Some((encoding, q));
+            }
+        }
+
+        best.map(|x| x.0)
+    }
+
+    /// Wraps a body in an encoder.
+    fn encoder<'a>(
+        &self,
+        encoding: Encoding,
+        body: impl Read + Send + 'a,
+    ) -> Box<dyn Read + Send + 'a> {
+        let level = flate2::Compression::new(self.level.min(9));
+        match encoding {
+            Encoding::Brotli => Box::new(CompressorReader::new(body, 4096, self.level.min(11), 22)),
+            Encoding::Gzip => Box::new(GzEncoder::new(body, level)),
+            Encoding::Deflate => Box::new(ZlibEncoder::new(body, level)),
+        }
+    }
+}
+
+impl Middleware for Compression {
+    fn post(&self, req: &Request, res: &mut Response) -> MiddleResult {
+        let code = res.status.code();
+        if (100..200).contains(&code)
+            || matches!(
+                res.status,
+                Status::NoContent | Status::NotModified | Status::PartialContent
+            )
+            || res.headers.has(HeaderType::ContentEncoding)
+            || !compressible(res.headers.get(HeaderType::ContentType))
+        {
+            return MiddleResult::Continue;
+        }
+
+        if let ResponseBody::Static(data) = &res.data {
+            if data.len() < self.min_size {
+                return MiddleResult::Continue;
+            }
+        }
+
+        // The response depends on Accept-Encoding, even if this client doesn't get it compressed
+        res.headers.append_vary(HeaderType::AcceptEncoding);
+        let encoding = match req
+            .headers
+            .get(HeaderType::AcceptEncoding)
+            .and_then(|x| self.pick(x))
+        {
+            Some(i) => i,
+            None => return MiddleResult::Continue,
+        };
+
+        let body = match mem::replace(&mut res.data, ResponseBody::empty()) {
+            ResponseBody::Static(data) => {
+                let mut out = Vec::new();
+                let result = self
+                    .encoder(encoding, data.as_slice())
+                    .read_to_end(&mut out);
+                if let Err(e) = result {
+                    trace!(Level::Error, "Error compressing response: {}", e);
+                    res.data = ResponseBody::Static(data);
+                    return MiddleResult::Continue;
+                }
+                ResponseBody::Static(out)
+            }
+            ResponseBody::Stream(data) => {
+                let encoder = self.encoder(encoding, StreamBody(data));
+                ResponseBody::Stream(Box::new(RefCell::new(encoder)))
+            }
+            ResponseBody::RangedStream(data) => {
+                let encoder = self.encoder(encoding, data);
+                ResponseBody::Stream(Box::new(RefCell::new(encoder)))
+            }
+            ResponseBody::File(file) => {
+                let encoder = self.encoder(encoding, file);
+                ResponseBody::Stream(Box::new(RefCell::new(encoder)))
+            }
+        };
+
+        res.data = body;
+        res.headers.retain(|x| x.name != HeaderType::ContentLength);
+        res.headers
+            .add(HeaderType::ContentEncoding, encoding.to_string());
+        if let Some(etag) = res.headers.get_mut("ETag") {
+            if !etag.starts_with("W/") {
+                etag.insert_str(0, "W/");
+            }
+        }
+
+        MiddleResult::Continue
+    }
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        })
+    }
+}
+
+/// Checks if a content type is worth compressing.
+/// Types without a content type are assumed to be compressible.
+fn compressible(content_type: Option<&str>) -> bool {
+    let content_type = match content_type {
+        Some(i) => i.split(';').next().unwrap_or_default().trim(),
+        None => return true,
+    };
+
+    let (kind, sub) = content_type.split_once('/').unwrap_or((content_type, ""));
+    match kind.to_ascii_lowercase().as_str() {
+        "image" => sub.eq_ignore_ascii_case("svg+xml"),
+        "audio" | "video" | "font" => false,
+        "application" => !matches!(
+            sub.to_ascii_lowercase().as_str(),
+            "zip"
+                | "gzip"
+                | "x-gzip"
+                | "x-bzip2"
+                | "x-7z-compressed"
+                | "zstd"
+                | "pdf"
+                | "wasm"
+                | "octet-stream"
+        ),
+        _ => true,
+    }
+}
+
+/// A streamed response body, so it can be passed to an encoder.
+struct StreamBody(Writeable);
+
+impl Read for StreamBody {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.get_mut().read(buf)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{compressible, Compression, Encoding};
+
+    #[test]
+    fn test_pick() {
+        let compression = Compression::new();
+        assert_eq!(
+            compression.pick("gzip, deflate, br"),
+            Some(Encoding::Brotli)
+        );
+        assert_eq!(compression.pick("gzip, deflate"), Some(Encoding::Gzip));
+        assert_eq!(compression.pick("br;q=0.5, gzip"), Some(Encoding::Gzip));
+        assert_eq!(compression.pick("BR, GZIP"), Some(Encoding::Brotli));
+        assert_eq!(compression.pick("*;q=0.1, br;q=0"), Some(Encoding::Gzip));
+        assert_eq!(compression.pick("identity"), None);
+        assert_eq!(compression.pick("gzip;q=0"), None);
+        assert_eq!(compression.pick(""), None);
+
+        let compression = compression.encodings([Encoding::Deflate]);
+        assert_eq!(
+            compression.pick("gzip, deflate, br"),
+            Some(Encoding::Deflate)
+        );
+    }
+
+    #[test]
+    fn test_compressible() {
+        assert!(compressible(None));
+        assert!(compressible(Some("text/html; charset=utf-8")));
+        assert!(compressible(Some("application/json")));
+        assert!(compressible(Some("image/svg+xml")));
+        assert!(!compressible(Some("image/png")));
+        assert!(!compressible(Some("video/mp4")));
+        assert!(!compressible(Some("application/zip")));
+    }
+}
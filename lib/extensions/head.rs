@@ -1,3 +1,5 @@
+use std::io::{Seek, SeekFrom};
+
 use crate::{
     middleware::{MiddleResult, Middleware},
     response::ResponseBody,
@@ -9,6 +11,9 @@ use crate::{
 /// Once the response is processed by the normal route handler, the middleware will check if the header is present.
 /// If it is, any body data will be discarded and the [Content-Length] header will be added, if it is not already present.
 /// On static responses, the length is already known, but with streaming responses, the stream will be read to the end to get the length (by default).
+/// File and seekable stream responses use the length of the rest of the body, without reading it.
+///
+/// Consider [`Server::auto_head`](crate::Server::auto_head) instead, which answers HEAD requests in the router without reading streams.
 pub struct Head {
     /// Whether to add the Content-Length header to streaming responses.
     /// This is important because to get the length of a stream, it must be read to the end, which could be slow or impossible in some cases.
@@ -53,6 +58,16 @@ impl Middleware for Head {
         let len = match &mut res.data {
             _ if res.headers.has(HeaderType::ContentLength) => None,
             ResponseBody::Static(d) => Some(d.len()),
+            ResponseBody::File(f) => {
+                let end = f.metadata().map(|x| x.len()).unwrap_or_default();
+                let pos = f.stream_position().unwrap_or_default();
+                Some(end.saturating_sub(pos) as usize)
+            }
+            ResponseBody::RangedStream(s) => {
+                let pos = s.stream_position().unwrap_or_default();
+                let end = s.seek(SeekFrom::End(0)).unwrap_or_default();
+                Some(end.saturating_sub(pos) as usize)
+            }
             ResponseBody::Stream(s) if self.streaming => {
                 let mut buf = Vec::new();
                 s.get_mut().read_to_end(&mut buf).unwrap();
@@ -1,19 +1,34 @@
 //! Log requests to the console or a file.
 
 // If file logging is enabled
-use std::fs::{File, OpenOptions};
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
 use std::io::{self, prelude::*};
-use std::path::Path;
-use std::sync::Mutex;
+use std::path::{Path, PathBuf};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    mpsc::{self, Receiver, SyncSender, TrySendError},
+    Arc, Mutex,
+};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use crate::{extension::RealIp, HeaderType, Middleware, Request, Response};
+use crate::{
+    extension::RealIp, internal::common::epoch, middleware::ResponseInfo, HeaderType, Middleware,
+    Request, Response,
+};
+
+use super::{date::clf_date, version::json_string};
 
 /// Define Log Levels
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
 pub enum Level {
     /// Give lots of information on what's going on.
     ///
-    /// Adds Request Headers and Body
+    /// Adds Request Headers and Body, and a curl command to reproduce the request (see [`Request::to_curl`])
     Debug,
 
     /// Give a reasonable amount of information on what's going on.
@@ -22,20 +37,152 @@ pub enum Level {
     Info,
 }
 
+/// The format of each log line.
+/// See [`Logger::format`].
+///
+/// When loaded from a config, this is written as `"default"`, `"common"`, `"json"` or `{ custom = "FORMAT" }`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Format {
+    /// A human readable format, which depends on the log [`Level`].
+    /// Ex: `[127.0.0.1] GET /hello?name=Darren 200 (0.42ms)`
+    Default,
+    /// The Common Log Format, as used by Apache and nginx.
+    /// Ex: `127.0.0.1 - - [08/Feb/2023:23:39:57 +0000] "GET /hello?name=Darren HTTP/1.1" 200 13`
+    Common,
+    /// One JSON object per line, with `time` (unix seconds), `ip`, `method`, `path`, `query`, `version`, `status`, `bytes` and `ms` fields.
+    /// `bytes` is the number of body bytes sent, see [`ResponseInfo::bytes_written`](crate::middleware::ResponseInfo::bytes_written).
+    Json,
+    /// A format string with placeholders.
+    /// The placeholders are `{ip}`, `{method}`, `{path}`, `{query}`, `{version}`, `{status}`, `{bytes}`, `{ms}` and `{time}`.
+    /// Unknown placeholders are left as is.
+    /// Ex: `{method} {path} {status} {ms}ms`
+    Custom(String),
+}
+
+/// Defines when the log file is rotated.
+/// When a file is rotated, it is renamed to `{file}.1` (pushing older files to `{file}.2`, `{file}.3`, etc.) and a new file is started.
+/// See [`Logger::rotate`] and [`Logger::retain`].
+///
+/// When loaded from a config, this is written as `"never"`, `{ size = BYTES }` or `{ interval = SECONDS }`.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Rotation {
+    /// Never rotate the log file.
+    Never,
+    /// Rotate the log file when writing to it would make it bigger than this many bytes.
+    Size(u64),
+    /// Rotate the log file after this much time has passed since it was opened.
+    Interval(#[cfg_attr(feature = "serde", serde(deserialize_with = "duration_secs"))] Duration),
+}
+
+/// Configuration for a [`Logger`], for loading from a config file.
+/// Missing fields use the same defaults as [`Logger::new`].
+/// ## Example
+/// ```toml
+/// level = "debug"
+/// real_ip = "X-Forwarded-For"
+/// file = "log.txt"
+/// rotation = { interval = 86400 }
+/// retain = 7
+/// ```
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(default)]
+pub struct LoggerConfig {
+    /// What level of logs to show.
+    pub level: Level,
+    /// The format of each log line.
+    pub format: Format,
+    /// What header to use to get the clients actual IP, see [`Logger::real_ip`].
+    pub real_ip: Option<String>,
+    /// Optional file to write logs to.
+    pub file: Option<PathBuf>,
+    /// If logs should also be printed to stdout.
+    pub console: bool,
+    /// When to rotate the log file.
+    pub rotation: Rotation,
+    /// How many rotated log files to keep.
+    pub retain: usize,
+    /// How many log messages can be waiting to be written to the file before new ones are dropped.
+    pub queue_size: usize,
+}
+
 /// Log requests to the console or a file.
+/// Requests are logged after all other middleware has run, as the logger has the lowest [`Middleware::priority`].
 #[derive(Debug)]
 pub struct Logger {
     /// What level of logs to show
     level: Level,
 
+    /// The format of each log line
+    format: Format,
+
     /// What header to use to get the clients actual IP
     real_ip: Option<HeaderType>,
 
     /// Optional file to write logs to
-    file: Option<Mutex<File>>,
+    file: Option<FileLog>,
 
     /// If logs should also be printed to stdout
     console: bool,
+
+    /// When to rotate the log file
+    rotation: Rotation,
+
+    /// How many rotated log files to keep
+    retain: usize,
+
+    /// How many log messages can be waiting to be written to the file before new ones are dropped
+    queue_size: usize,
+
+    /// The number of log messages that have been dropped because the queue was full
+    dropped: Arc<AtomicU64>,
+
+    /// Only requests that match the filter are logged
+    filter: Option<Filter>,
+}
+
+/// The values that can be put in a log line.
+struct LogLine<'a> {
+    ip: String,
+    req: &'a Request,
+    path: &'a str,
+    status: u16,
+    bytes: Option<u64>,
+    ms: f64,
+    time: u64,
+}
+
+/// A filter set with [`Logger::filter`].
+struct Filter(Box<dyn Fn(&Request) -> bool + Send + Sync>);
+
+/// The log file, which is written to by a background thread.
+/// The thread is started when the first message is logged.
+#[derive(Debug)]
+struct FileLog {
+    path: PathBuf,
+    /// The opened log file, taken by the writer thread when it starts
+    file: Mutex<Option<File>>,
+    /// Channel to the writer thread, once it has been started
+    sender: Mutex<Option<SyncSender<String>>>,
+}
+
+/// Owned by the background thread, writes log messages to the file and rotates it.
+struct FileWriter {
+    path: PathBuf,
+    file: File,
+    rotation: Rotation,
+    retain: usize,
+    /// Bytes written to the current file
+    written: u64,
+    /// When the current file was opened
+    opened: Instant,
+    dropped: Arc<AtomicU64>,
+    /// The dropped message count when it was last reported in the log
+    last_dropped: u64,
 }
 
 impl Logger {
@@ -45,9 +192,17 @@ impl Logger {
     ///
     /// - Log Level: `Level::Info`
     ///
+    /// - Format: `Format::Default`
+    ///
     /// - File: `None`
     ///
     /// - Console: `true`
+    ///
+    /// - Rotation: `Rotation::Never`
+    ///
+    /// - Retain: `5`
+    ///
+    /// - Queue Size: `1024`
     /// ## Example
     /// ```rust
     /// // Import Lib
@@ -59,10 +214,46 @@ impl Logger {
     pub fn new() -> Logger {
         Logger {
             level: Level::Info,
+            format: Format::Default,
             real_ip: None,
             file: None,
             console: true,
+            rotation: Rotation::Never,
+            retain: 5,
+            queue_size: 1024,
+            dropped: Arc::new(AtomicU64::new(0)),
+            filter: None,
+        }
+    }
+
+    /// Make a new logger from a [`LoggerConfig`].
+    /// Will return an error if the log file can't be opened.
+    /// ## Example
+    /// ```rust
+    /// use afire::extension::logger::{Logger, LoggerConfig};
+    ///
+    /// let config: LoggerConfig = serde_json::from_str(r#"{"level": "debug"}"#).unwrap();
+    /// let logger = Logger::from_config(&config).unwrap();
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn from_config(config: &LoggerConfig) -> io::Result<Logger> {
+        let mut logger = Logger::new()
+            .level(config.level)
+            .format(config.format.clone())
+            .console(config.console)
+            .rotate(config.rotation)
+            .retain(config.retain)
+            .queue_size(config.queue_size);
+
+        if let Some(i) = &config.real_ip {
+            logger = logger.real_ip(HeaderType::from(i));
         }
+
+        if let Some(i) = &config.file {
+            logger = logger.file(i)?;
+        }
+
+        Ok(logger)
     }
 
     /// Set the log Level of a logger
@@ -79,6 +270,22 @@ impl Logger {
         Self { level, ..self }
     }
 
+    /// Set the format of each log line.
+    /// The [`Level`] only changes the [`Format::Default`] format.
+    /// ## Example
+    /// ```rust
+    /// use afire::extension::logger::{Logger, Format};
+    ///
+    /// // Log in the Common Log Format
+    /// let logger = Logger::new().format(Format::Common);
+    ///
+    /// // Or with a custom format
+    /// let logger = Logger::new().format(Format::Custom("{method} {path} {status} {ms}ms".to_owned()));
+    /// ```
+    pub fn format(self, format: Format) -> Self {
+        Self { format, ..self }
+    }
+
     /// Uses the [`crate::extension::RealIp`] extension for log IPs.
     /// You will need to supply the header that will contain the IP address, for example the [X-Forwarded-For header](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/X-Forwarded-For) ([`HeaderType::XForwardedFor`])
     ///
@@ -90,7 +297,8 @@ impl Logger {
         }
     }
 
-    /// Set the log file of a logger
+    /// Set the log file of a logger.
+    /// Logs are written to the file from a background thread, so file IO never blocks handling requests.
     /// ## Example
     /// ```rust
     /// // Import Lib
@@ -103,18 +311,77 @@ impl Logger {
     /// # }
     /// ```
     pub fn file(self, file: impl AsRef<Path>) -> io::Result<Self> {
+        let path = file.as_ref().to_path_buf();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+
         Ok(Self {
-            file: Some(Mutex::new(
-                OpenOptions::new()
-                    .create(true)
-                    .write(true)
-                    .append(true)
-                    .open(file)?,
-            )),
+            file: Some(FileLog {
+                path,
+                file: Mutex::new(Some(file)),
+                sender: Mutex::new(None),
+            }),
             ..self
         })
     }
 
+    /// Set when the log file should be rotated.
+    /// By default the log file is never rotated.
+    /// ## Example
+    /// ```rust
+    /// # use std::time::Duration;
+    /// use afire::extension::logger::{Logger, Rotation};
+    ///
+    /// // Rotate the log file every day, keeping the last week of logs
+    /// # fn run() {
+    /// let logger = Logger::new()
+    ///     .file("log.txt")
+    ///     .unwrap()
+    ///     .rotate(Rotation::Interval(Duration::from_secs(60 * 60 * 24)))
+    ///     .retain(7);
+    /// # }
+    /// ```
+    pub fn rotate(self, rotation: Rotation) -> Self {
+        Self { rotation, ..self }
+    }
+
+    /// Set how many rotated log files to keep.
+    /// Once there are more than this many, the oldest is deleted.
+    /// Setting this to 0 will just truncate the log file when it is rotated.
+    /// By default this is 5.
+    pub fn retain(self, retain: usize) -> Self {
+        Self { retain, ..self }
+    }
+
+    /// Set how many log messages can be queued up, waiting to be written to the log file.
+    /// If the queue is full, new messages will be dropped (and counted, see [`Logger::dropped`]) instead of blocking the request.
+    /// A size of 0 is treated as 1, as nothing would ever be written otherwise.
+    /// By default this is 1024.
+    pub fn queue_size(self, queue_size: usize) -> Self {
+        Self {
+            queue_size: queue_size.max(1),
+            ..self
+        }
+    }
+
+    /// Gets a counter of how many log messages have been dropped because the write queue was full.
+    /// The count is also written to the log file once there is room in the queue again.
+    /// ## Example
+    /// ```rust
+    /// # use afire::{Server, Middleware, extension::Logger};
+    /// # use std::sync::atomic::Ordering;
+    /// # fn run(server: &mut Server) {
+    /// let logger = Logger::new().file("log.txt").unwrap();
+    /// let dropped = logger.dropped();
+    /// logger.attach(server);
+    ///
+    /// // Later...
+    /// println!("Dropped {} log messages", dropped.load(Ordering::Relaxed));
+    /// # }
+    /// ```
+    pub fn dropped(&self) -> Arc<AtomicU64> {
+        self.dropped.clone()
+    }
+
     /// Enable writing events to stdout
     /// ## Example
     /// ```rust
@@ -129,13 +396,52 @@ impl Logger {
         Self { console, ..self }
     }
 
-    /// Take a request and log it
-    fn log(&self, req: &Request) {
+    /// Only log requests that match the filter.
+    /// ## Example
+    /// ```rust
+    /// use afire::extension::logger::Logger;
+    ///
+    /// // Don't log health checks
+    /// let logger = Logger::new()
+    ///     .filter(|req| req.path != "/health");
+    /// ```
+    pub fn filter(self, filter: impl Fn(&Request) -> bool + Send + Sync + 'static) -> Self {
+        Self {
+            filter: Some(Filter(Box::new(filter))),
+            ..self
+        }
+    }
+
+    /// Take a request and its response and log it
+    fn log(&self, req: &Request, res: &Response, info: &ResponseInfo) {
         let ip = match &self.real_ip {
             Some(i) => req.real_ip_header(i),
             None => req.address.ip(),
         };
 
+        let line = LogLine {
+            ip: ip.to_string(),
+            req,
+            path: if req.path.is_empty() { "/" } else { &req.path },
+            status: res.status.code(),
+            bytes: Some(info.bytes_written),
+            ms: info.duration.as_secs_f64() * 1000.0,
+            time: epoch().as_secs(),
+        };
+
+        self.send_log(match &self.format {
+            Format::Default => self.default_line(&line),
+            Format::Common => line.common(),
+            Format::Json => line.json(),
+            Format::Custom(format) => line.custom(format),
+        })
+    }
+
+    /// Formats a log line with [`Format::Default`].
+    fn default_line(&self, line: &LogLine) -> String {
+        let (req, ip, path) = (line.req, &line.ip, line.path);
+        let status = format!("{} ({:.2}ms)", line.status, line.ms);
+
         match self.level {
             // Add Headers and Body to this one
             Level::Debug => {
@@ -157,29 +463,18 @@ impl Logger {
                     query = query[0..query.len() - 2].to_string()
                 }
 
-                let mut new_path = req.path.to_owned();
-                if new_path.is_empty() {
-                    new_path = "/".to_string();
-                }
-
-                self.send_log(format!(
-                    "[{ip}] {} {} [{}] ({}) {{{}}}",
+                format!(
+                    "[{ip}] {} {} [{}] ({}) {{{}}} {status}\n  {}",
                     req.method,
-                    new_path,
+                    path,
                     query,
                     headers,
-                    String::from_utf8_lossy(&req.body).replace('\n', "\\n")
-                ))
+                    String::from_utf8_lossy(&req.body).replace('\n', "\\n"),
+                    req.to_curl()
+                )
             }
 
-            Level::Info => {
-                let mut new_path = req.path.clone();
-                if new_path.is_empty() {
-                    new_path = "/".to_string();
-                }
-
-                self.send_log(format!("[{ip}] {} {}{}", req.method, new_path, req.query))
-            }
+            Level::Info => format!("[{ip}] {} {}{} {status}", req.method, path, req.query),
         }
     }
 
@@ -190,22 +485,389 @@ impl Logger {
         }
 
         if let Some(i) = &self.file {
-            if let Err(e) = writeln!(i.lock().unwrap(), "{data}") {
-                eprintln!("[-] Erm... Error writhing to log file: {e}")
+            let mut sender = i.sender.lock().unwrap();
+            let sender = sender.get_or_insert_with(|| self.start_writer(i));
+
+            match sender.try_send(data) {
+                Ok(()) => {}
+                Err(TrySendError::Full(_)) => {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(TrySendError::Disconnected(_)) => {
+                    eprintln!("[-] Erm... Log file writer thread has stopped")
+                }
             }
         }
     }
+
+    /// Starts the background thread that writes to the log file.
+    fn start_writer(&self, log: &FileLog) -> SyncSender<String> {
+        let (tx, rx) = mpsc::sync_channel(self.queue_size);
+        let file = log
+            .file
+            .lock()
+            .unwrap()
+            .take()
+            .expect("Log file already taken");
+        let written = file.metadata().map(|x| x.len()).unwrap_or(0);
+
+        let writer = FileWriter {
+            path: log.path.clone(),
+            file,
+            rotation: self.rotation,
+            retain: self.retain,
+            written,
+            opened: Instant::now(),
+            dropped: self.dropped.clone(),
+            last_dropped: 0,
+        };
+
+        thread::Builder::new()
+            .name("Logger writer".to_owned())
+            .spawn(move || writer.run(rx))
+            .expect("Error creating log writer thread");
+
+        tx
+    }
+}
+
+impl FileWriter {
+    /// Writes messages to the file until the logger is dropped.
+    fn run(mut self, rx: Receiver<String>) {
+        for data in rx {
+            let dropped = self.dropped.load(Ordering::Relaxed);
+            if dropped > self.last_dropped {
+                let msg = format!("[-] Dropped {} log messages", dropped - self.last_dropped);
+                self.last_dropped = dropped;
+                self.write(msg);
+            }
+
+            self.write(data);
+        }
+    }
+
+    /// Writes a line to the log file, rotating it first if needed.
+    fn write(&mut self, data: String) {
+        let len = data.len() as u64 + 1;
+        let rotate = match self.rotation {
+            Rotation::Never => false,
+            Rotation::Size(size) => self.written > 0 && self.written + len > size,
+            Rotation::Interval(interval) => self.opened.elapsed() >= interval,
+        };
+
+        if rotate {
+            if let Err(e) = self.rotate() {
+                eprintln!("[-] Erm... Error rotating log file: {e}")
+            }
+        }
+
+        if let Err(e) = writeln!(self.file, "{data}") {
+            eprintln!("[-] Erm... Error writhing to log file: {e}")
+        }
+        self.written += len;
+    }
+
+    /// Renames the log file to `{path}.1`, moving older files up by one and deleting the oldest.
+    /// Then starts a new log file.
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.retain > 0 {
+            let _ = fs::remove_file(rotated_path(&self.path, self.retain));
+            for i in (1..self.retain).rev() {
+                let _ = fs::rename(rotated_path(&self.path, i), rotated_path(&self.path, i + 1));
+            }
+            fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        }
+
+        self.file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&self.path)?;
+        self.written = 0;
+        self.opened = Instant::now();
+        Ok(())
+    }
+}
+
+/// Gets the path of a rotated log file.
+/// Ex: `log.txt` => `log.txt.1`
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(format!(".{index}"));
+    PathBuf::from(name)
 }
 
 impl Middleware for Logger {
-    fn end(&self, req: &Request, _res: &Response) {
-        self.log(req);
+    /// Loggers run after all other middleware, so they log the final state of the request.
+    fn priority(&self) -> i32 {
+        i32::MIN
+    }
+
+    fn end_with_info(&self, req: &Request, res: &Response, info: &ResponseInfo) {
+        if self.filter.as_ref().is_some_and(|x| !(x.0)(req)) {
+            return;
+        }
+
+        self.log(req, res, info);
+    }
+}
+
+impl LogLine<'_> {
+    /// Formats the line with [`Format::Common`].
+    fn common(&self) -> String {
+        let bytes = self.bytes.map(|x| x.to_string());
+        format!(
+            "{} - - [{}] \"{} {}{} {}\" {} {}",
+            self.ip,
+            clf_date(self.time),
+            self.req.method,
+            self.path,
+            self.req.query,
+            self.req.version,
+            self.status,
+            bytes.as_deref().unwrap_or("-")
+        )
+    }
+
+    /// Formats the line with [`Format::Json`].
+    fn json(&self) -> String {
+        let query = self.req.query.to_string();
+        let bytes = self.bytes.map(|x| x.to_string());
+        format!(
+            r#"{{"time":{},"ip":{},"method":{},"path":{},"query":{},"version":{},"status":{},"bytes":{},"ms":{:.3}}}"#,
+            self.time,
+            json_string(Some(&self.ip)),
+            json_string(Some(&self.req.method.to_string())),
+            json_string(Some(self.path)),
+            json_string(Some(query.trim_start_matches('?'))),
+            json_string(Some(&self.req.version.to_string())),
+            self.status,
+            bytes.as_deref().unwrap_or("null"),
+            self.ms
+        )
+    }
+
+    /// Formats the line with a [`Format::Custom`] format string.
+    fn custom(&self, format: &str) -> String {
+        let mut out = String::with_capacity(format.len());
+        let mut rest = format;
+
+        while let Some(start) = rest.find('{') {
+            out.push_str(&rest[..start]);
+            rest = &rest[start..];
+
+            let Some(end) = rest.find('}') else {
+                break;
+            };
+
+            let value = match &rest[1..end] {
+                "ip" => self.ip.clone(),
+                "method" => self.req.method.to_string(),
+                "path" => self.path.to_owned(),
+                "query" => self.req.query.to_string(),
+                "version" => self.req.version.to_string(),
+                "status" => self.status.to_string(),
+                "bytes" => self.bytes.map_or("-".to_owned(), |x| x.to_string()),
+                "ms" => format!("{:.2}", self.ms),
+                "time" => self.time.to_string(),
+                _ => rest[..=end].to_owned(),
+            };
+            out.push_str(&value);
+            rest = &rest[end + 1..];
+        }
+
+        out.push_str(rest);
+        out
+    }
+}
+
+impl fmt::Debug for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Filter")
     }
 }
 
 // Impl Default for Response
+#[cfg(feature = "serde")]
+impl Default for LoggerConfig {
+    fn default() -> Self {
+        Self {
+            level: Level::Info,
+            format: Format::Default,
+            real_ip: None,
+            file: None,
+            console: true,
+            rotation: Rotation::Never,
+            retain: 5,
+            queue_size: 1024,
+        }
+    }
+}
+
+/// Deserializes a [`Duration`] from a number of seconds.
+#[cfg(feature = "serde")]
+fn duration_secs<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    <u64 as serde::Deserialize>::deserialize(deserializer).map(Duration::from_secs)
+}
+
 impl Default for Logger {
     fn default() -> Logger {
         Logger::new()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::{
+        fs::{self, File},
+        path::{Path, PathBuf},
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            mpsc, Arc,
+        },
+        time::Instant,
+    };
+
+    use super::{rotated_path, FileWriter, LogLine, Logger, Rotation};
+    use crate::{
+        testing::{TestRequest, TestServer},
+        Method, Response, Server,
+    };
+
+    /// Makes a writer for a new log file in a temp directory.
+    fn file_writer(name: &str, rotation: Rotation, retain: usize) -> (PathBuf, FileWriter) {
+        let dir = std::env::temp_dir().join(format!("afire-logger-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join("log.txt");
+        let writer = FileWriter {
+            path: path.clone(),
+            file: File::create(&path).unwrap(),
+            rotation,
+            retain,
+            written: 0,
+            opened: Instant::now(),
+            dropped: Arc::new(AtomicU64::new(0)),
+            last_dropped: 0,
+        };
+        (path, writer)
+    }
+
+    #[test]
+    fn test_rotation() {
+        let (path, mut writer) = file_writer("rotation", Rotation::Size(11), 2);
+        for i in 1..=4 {
+            writer.write(format!("message {i}!"));
+        }
+
+        let read = |i| fs::read_to_string(rotated_path(&path, i)).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "message 4!\n");
+        assert_eq!(read(1), "message 3!\n");
+        assert_eq!(read(2), "message 2!\n");
+        assert!(!rotated_path(&path, 3).exists());
+
+        // With no retained files, the log is just truncated
+        let (path, mut writer) = file_writer("truncate", Rotation::Size(11), 0);
+        writer.write("message 1!".to_owned());
+        writer.write("message 2!".to_owned());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "message 2!\n");
+        assert!(!rotated_path(&path, 1).exists());
+    }
+
+    #[test]
+    fn test_dropped() {
+        let (path, writer) = file_writer("dropped", Rotation::Never, 0);
+        let dropped = writer.dropped.clone();
+        dropped.store(3, Ordering::Relaxed);
+
+        let (tx, rx) = mpsc::sync_channel(2);
+        tx.send("first".to_owned()).unwrap();
+        tx.send("second".to_owned()).unwrap();
+        drop(tx);
+        writer.run(rx);
+
+        // The drop count is reported once, before the next message that is written
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "[-] Dropped 3 log messages\nfirst\nsecond\n"
+        );
+    }
+
+    #[test]
+    fn test_queue_size() {
+        assert_eq!(Logger::new().queue_size(0).queue_size, 1);
+        assert_eq!(Logger::new().queue_size(16).queue_size, 16);
+    }
+
+    #[test]
+    fn test_rotated_path() {
+        assert_eq!(
+            rotated_path(Path::new("logs/log.txt"), 1),
+            Path::new("logs/log.txt.1")
+        );
+        assert_eq!(rotated_path(Path::new("log"), 12), Path::new("log.12"));
+    }
+
+    #[test]
+    fn test_formats() {
+        let mut server = Server::<()>::new("localhost", 8080);
+        server.route(Method::GET, "/hello", |req| {
+            let line = LogLine {
+                ip: req.address.ip().to_string(),
+                req,
+                path: &req.path,
+                status: 200,
+                bytes: None,
+                ms: 1.5,
+                time: 1675899597,
+            };
+
+            Response::new().text(format!(
+                "{}\n{}\n{}",
+                line.common(),
+                line.json(),
+                line.custom("{method} {path}{query} {status} {ms}ms {bytes} {other} {")
+            ))
+        });
+
+        let res = TestRequest::get("/hello?name=Darren").send(&TestServer::from(server));
+        let text = res.text();
+        let lines = text.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines[0],
+            r#"127.0.0.1 - - [08/Feb/2023:23:39:57 +0000] "GET /hello?name=Darren HTTP/1.1" 200 -"#
+        );
+        assert_eq!(
+            lines[1],
+            r#"{"time":1675899597,"ip":"127.0.0.1","method":"GET","path":"/hello","query":"name=Darren","version":"HTTP/1.1","status":200,"bytes":null,"ms":1.500}"#
+        );
+        assert_eq!(lines[2], "GET /hello?name=Darren 200 1.50ms - {other} {");
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_logger_config() {
+        use std::time::Duration;
+
+        use super::{Format, Level, LoggerConfig, Rotation};
+
+        let config = serde_json::from_str::<LoggerConfig>(
+            r#"{"level": "debug", "rotation": {"interval": 60}, "retain": 2}"#,
+        )
+        .unwrap();
+        assert!(matches!(config.level, Level::Debug));
+        assert!(matches!(config.rotation, Rotation::Interval(i) if i == Duration::from_secs(60)));
+        assert_eq!(config.retain, 2);
+        assert!(config.console);
+        assert_eq!(config.queue_size, 1024);
+
+        let config = serde_json::from_str::<LoggerConfig>(r#"{"rotation": "never"}"#).unwrap();
+        assert!(matches!(config.rotation, Rotation::Never));
+        assert!(matches!(config.format, Format::Default));
+
+        let config =
+            serde_json::from_str::<LoggerConfig>(r#"{"format": {"custom": "{path}"}}"#).unwrap();
+        assert!(matches!(config.format, Format::Custom(i) if i == "{path}"));
+    }
+}
@@ -0,0 +1,234 @@
+//! Let clients override the request method with a header or form field.
+//! HTML forms can only send GET and POST requests, so this lets them use routes with other methods (like PUT or DELETE).
+
+use std::str::FromStr;
+
+use crate::{
+    middleware::{MiddleResult, Middleware},
+    HeaderType, Method, Query, Request,
+};
+
+/// Middleware to let POST requests override their method with the `X-HTTP-Method-Override` header or a `_method` query / form field.
+/// This runs before routing, so a form can send a POST with `_method=DELETE` and it will be handled by a DELETE route.
+///
+/// Only methods in the allowlist (PUT, PATCH and DELETE by default) can be used as overrides.
+/// The header is checked before the field.
+///
+/// The form field is only read from buffered bodies.
+/// Bodies too large to buffer (see [`crate::Server::body_buffer_limit`]) are left for the handler to read, so those requests have to use the header or the query instead.
+///
+/// ## Example
+/// ```rust
+/// # use afire::{Method, Middleware, Response, Server, extension::MethodOverride};
+/// # fn test(server: &mut Server) {
+/// MethodOverride::new().attach(server);
+///
+/// // Can be called from a form like this:
+/// // <form method="post" action="/post/1">
+/// //     <input type="hidden" name="_method" value="DELETE">
+/// //     <input type="submit" value="Delete">
+/// // </form>
+/// server.route(Method::DELETE, "/post/{id}", |req| {
+///     Response::new().text(format!("Deleted post {}", req.param("id").unwrap()))
+/// });
+/// # }
+/// ```
+pub struct MethodOverride {
+    /// Methods that a request is allowed to be overridden to.
+    allowed: Vec<Method>,
+    /// The header to get the method from.
+    header: Option<HeaderType>,
+    /// The query / form field to get the method from.
+    field: Option<String>,
+}
+
+impl MethodOverride {
+    /// Create a new instance of the middleware.
+    /// By default it will allow overriding to PUT, PATCH and DELETE using the `X-HTTP-Method-Override` header or the `_method` field.
+    pub fn new() -> Self {
+        Self {
+            allowed: vec![Method::PUT, Method::PATCH, Method::DELETE],
+            header: Some(HeaderType::Custom("X-HTTP-Method-Override".to_owned())),
+            field: Some("_method".to_owned()),
+        }
+    }
+
+    /// Set the methods that requests can be overridden to.
+    /// This replaces the default allowlist.
+    /// ## Example
+    /// ```rust
+    /// # use afire::{Method, extension::MethodOverride};
+    /// let method_override = MethodOverride::new().allow(&[Method::PUT, Method::DELETE]);
+    /// ```
+    pub fn allow(self, methods: &[Method]) -> Self {
+        Self {
+            allowed: methods.to_vec(),
+            ..self
+        }
+    }
+
+    /// Set the header to read the override method from.
+    /// Passing `None` will disable overriding with a header.
+    pub fn header(self, header: Option<impl Into<HeaderType>>) -> Self {
+        Self {
+            header: header.map(Into::into),
+            ..self
+        }
+    }
+
+    /// Set the query / form field to read the override method from.
+    /// Passing `None` will disable overriding with a field.
+    pub fn field(self, field: Option<impl AsRef<str>>) -> Self {
+        Self {
+            field: field.map(|x| x.as_ref().to_owned()),
+            ..self
+        }
+    }
+
+    /// Gets the override method of a request, if it has one.
+    fn get_method(&self, req: &Request) -> Option<String> {
+        if let Some(method) = self.header.as_ref().and_then(|x| req.headers.get(x)) {
+            return Some(method.to_owned());
+        }
+
+        let field = self.field.as_ref()?;
+        if let Some(method) = req.query.get(field) {
+            return Some(method.to_owned());
+        }
+
+        let is_form = req
+            .headers
+            .get_header(HeaderType::ContentType)
+            .map(|x| x.params().value == "application/x-www-form-urlencoded")
+            .unwrap_or(false);
+
+        // Reading a streamed body here would take it from the handler
+        if !is_form || req.body_streamed() {
+            return None;
+        }
+
+        Query::from_body(&req.body_str())
+            .get(field)
+            .map(|x| x.to_owned())
+    }
+}
+
+impl Middleware for MethodOverride {
+    fn pre(&self, req: &mut Request) -> MiddleResult {
+        if req.method != Method::POST {
+            return MiddleResult::Continue;
+        }
+
+        let method = match self.get_method(req).and_then(|x| Method::from_str(&x).ok()) {
+            Some(i) => i,
+            None => return MiddleResult::Continue,
+        };
+
+        if self.allowed.contains(&method) {
+            trace!(Level::Debug, "Overriding method POST => {}", method);
+            req.method = method;
+        }
+
+        MiddleResult::Continue
+    }
+}
+
+impl Default for MethodOverride {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Read;
+
+    use super::MethodOverride;
+    use crate::{
+        testing::{TestRequest, TestServer},
+        Method, Middleware, Response, Server,
+    };
+
+    fn test_server(method_override: MethodOverride) -> TestServer {
+        let mut server = Server::<()>::new("localhost", 8080).body_buffer_limit(32);
+        method_override.attach(&mut server);
+        server.route(Method::ANY, "/", |req| Response::new().text(req.method));
+        server.route(Method::ANY, "/stream", |req| {
+            let mut body = String::new();
+            req.body_reader().read_to_string(&mut body).unwrap();
+            Response::new().text(format!("{} {}", req.method, body.len()))
+        });
+        TestServer::from(server)
+    }
+
+    fn form(body: &str) -> TestRequest {
+        TestRequest::post("/")
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body)
+    }
+
+    #[test]
+    fn test_header_override() {
+        let server = test_server(MethodOverride::new());
+        let res = TestRequest::post("/")
+            .header("X-HTTP-Method-Override", "DELETE")
+            .send(&server);
+        assert_eq!(res.text(), "DELETE");
+
+        // The header is checked before the field
+        let res = form("_method=PATCH")
+            .header("X-HTTP-Method-Override", "PUT")
+            .send(&server);
+        assert_eq!(res.text(), "PUT");
+
+        // Only POST requests can be overridden
+        let res = TestRequest::get("/")
+            .header("X-HTTP-Method-Override", "DELETE")
+            .send(&server);
+        assert_eq!(res.text(), "GET");
+    }
+
+    #[test]
+    fn test_field_override() {
+        let server = test_server(MethodOverride::new());
+        assert_eq!(form("_method=PUT").send(&server).text(), "PUT");
+        assert_eq!(
+            TestRequest::post("/?_method=PATCH").send(&server).text(),
+            "PATCH"
+        );
+
+        // Fields are only read from form bodies
+        let res = TestRequest::post("/").body("_method=PUT").send(&server);
+        assert_eq!(res.text(), "POST");
+
+        // Streamed bodies are left for the handler
+        let body = format!("_method=PUT&padding={}", "a".repeat(32));
+        let res = TestRequest::post("/stream")
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(&body)
+            .send(&server);
+        assert_eq!(res.text(), format!("POST {}", body.len()));
+    }
+
+    #[test]
+    fn test_disallowed_methods() {
+        let server = test_server(MethodOverride::new());
+        assert_eq!(form("_method=GET").send(&server).text(), "POST");
+        assert_eq!(form("_method=CONNECT").send(&server).text(), "POST");
+        assert_eq!(form("_method=NOPE").send(&server).text(), "POST");
+
+        let server = test_server(MethodOverride::new().allow(&[Method::PUT]));
+        assert_eq!(form("_method=DELETE").send(&server).text(), "POST");
+        assert_eq!(form("_method=PUT").send(&server).text(), "PUT");
+
+        let server = test_server(
+            MethodOverride::new()
+                .header(None::<&str>)
+                .field(None::<&str>),
+        );
+        let res = form("_method=PUT")
+            .header("X-HTTP-Method-Override", "PUT")
+            .send(&server);
+        assert_eq!(res.text(), "POST");
+    }
+}
@@ -0,0 +1,278 @@
+//! Record request counts and latencies, and serve them in the Prometheus text format.
+//! See the [Prometheus docs](https://prometheus.io/docs/instrumenting/exposition_formats/) for the format.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    internal::common::ForceLock,
+    middleware::{MiddleResult, Middleware, ResponseInfo},
+    path::normalize_path,
+    Method, Request, Response,
+};
+
+/// The default histogram buckets in seconds, the same as the official Prometheus clients.
+const DEFAULT_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Records request metrics by method and route, and serves them for Prometheus to scrape.
+///
+/// The metrics are:
+/// - `afire_requests_total`: A counter of requests, with `method`, `route` and `status` labels.
+///   The status label is the status class, like `2xx`.
+/// - `afire_request_duration_seconds`: A histogram of how long requests took, from when the request was received to when the response was sent, with `method` and `route` labels.
+///
+/// The route label is the path the route was defined with (See [`Request::route`]), so `/users/1` and `/users/2` are both counted under `/users/{id}`.
+/// Requests that didn't match a route, like 404s or responses from other middleware, have an empty route label.
+///
+/// ## Example
+/// ```rust
+/// # use afire::{Server, Middleware, extension::Metrics};
+/// # let mut server = Server::<()>::new("localhost", 8080);
+/// // Serves the metrics on /metrics
+/// Metrics::new().attach(&mut server);
+/// ```
+pub struct Metrics {
+    registry: Registry,
+    path: String,
+}
+
+/// A handle to the metrics recorded by a [`Metrics`].
+/// Cloning this gives another handle to the same metrics.
+#[derive(Clone)]
+pub struct Registry(Arc<RegistryInner>);
+
+struct RegistryInner {
+    buckets: Vec<f64>,
+    routes: Mutex<BTreeMap<(String, String), RouteMetrics>>,
+}
+
+/// The metrics for one method and route.
+struct RouteMetrics {
+    /// Requests by status class, from 1xx to 5xx
+    statuses: [u64; 5],
+    /// Requests that took at most each bucket's time (not cumulative)
+    buckets: Vec<u64>,
+    /// The total time taken by all requests, in seconds
+    sum: f64,
+    count: u64,
+}
+
+impl Metrics {
+    /// Create a new Metrics extension, served on `/metrics` with the default histogram buckets.
+    pub fn new() -> Self {
+        Self {
+            registry: Registry(Arc::new(RegistryInner {
+                buckets: DEFAULT_BUCKETS.to_vec(),
+                routes: Mutex::new(BTreeMap::new()),
+            })),
+            path: "metrics".to_owned(),
+        }
+    }
+
+    /// Set the path the metrics are served on.
+    /// Defaults to `/metrics`.
+    ///
+    /// Requests for this path are not recorded.
+    pub fn path(self, path: impl AsRef<str>) -> Self {
+        Self {
+            path: normalize_path(path.as_ref().to_owned()),
+            ..self
+        }
+    }
+
+    /// Set the upper bounds of the latency histogram buckets, in seconds.
+    /// A `+Inf` bucket is always added.
+    /// ## Example
+    /// ```rust
+    /// # use afire::extension::Metrics;
+    /// let metrics = Metrics::new().buckets([0.001, 0.01, 0.1, 1.0]);
+    /// ```
+    pub fn buckets(self, buckets: impl IntoIterator<Item = f64>) -> Self {
+        let mut buckets = buckets.into_iter().collect::<Vec<_>>();
+        buckets.sort_by(f64::total_cmp);
+        Self {
+            registry: Registry(Arc::new(RegistryInner {
+                buckets,
+                routes: Mutex::new(BTreeMap::new()),
+            })),
+            ..self
+        }
+    }
+
+    /// Gets a handle to the metrics, to render or reset them from elsewhere.
+    pub fn registry(&self) -> Registry {
+        self.registry.clone()
+    }
+}
+
+impl Registry {
+    /// Records a request.
+    fn record(&self, method: Method, route: &str, status: u16, seconds: f64) {
+        let mut routes = self.0.routes.force_lock();
+        let metrics = routes
+            .entry((method.to_string(), route.to_owned()))
+            .or_insert_with(|| RouteMetrics {
+                statuses: [0; 5],
+                buckets: vec![0; self.0.buckets.len()],
+                sum: 0.0,
+                count: 0,
+            });
+
+        let class = (status / 100).clamp(1, 5) as usize - 1;
+        metrics.statuses[class] += 1;
+        if let Some(i) = self.0.buckets.iter().position(|&x| seconds <= x) {
+            metrics.buckets[i] += 1;
+        }
+        metrics.sum += seconds;
+        metrics.count += 1;
+    }
+
+    /// Renders the metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let routes = self.0.routes.force_lock();
+        let mut out = String::new();
+
+        out.push_str("# HELP afire_requests_total Total number of HTTP requests handled.\n");
+        out.push_str("# TYPE afire_requests_total counter\n");
+        for ((method, route), metrics) in routes.iter() {
+            for (i, count) in metrics.statuses.iter().enumerate() {
+                if *count > 0 {
+                    let _ = writeln!(
+                        out,
+                        "afire_requests_total{{method=\"{}\",route=\"{}\",status=\"{}xx\"}} {count}",
+                        escape_label(method),
+                        escape_label(route),
+                        i + 1
+                    );
+                }
+            }
+        }
+
+        out.push_str("# HELP afire_request_duration_seconds How long HTTP requests took.\n");
+        out.push_str("# TYPE afire_request_duration_seconds histogram\n");
+        for ((method, route), metrics) in routes.iter() {
+            let labels = format!(
+                "method=\"{}\",route=\"{}\"",
+                escape_label(method),
+                escape_label(route)
+            );
+
+            let mut cumulative = 0;
+            for (bound, count) in self.0.buckets.iter().zip(&metrics.buckets) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "afire_request_duration_seconds_bucket{{{labels},le=\"{bound}\"}} {cumulative}"
+                );
+            }
+
+            let count = metrics.count;
+            let _ = writeln!(
+                out,
+                "afire_request_duration_seconds_bucket{{{labels},le=\"+Inf\"}} {count}"
+            );
+            let _ = writeln!(
+                out,
+                "afire_request_duration_seconds_sum{{{labels}}} {}",
+                metrics.sum
+            );
+            let _ = writeln!(
+                out,
+                "afire_request_duration_seconds_count{{{labels}}} {count}"
+            );
+        }
+
+        out
+    }
+
+    /// Removes all the recorded metrics.
+    pub fn clear(&self) {
+        self.0.routes.force_lock().clear();
+    }
+}
+
+/// Escapes a label value, as described in the text format.
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+impl Middleware for Metrics {
+    fn pre(&self, req: &mut Request) -> MiddleResult {
+        if req.method == Method::GET && req.path.trim_matches('/') == self.path {
+            return MiddleResult::Send(
+                Response::new()
+                    .text(self.registry.render())
+                    .header("Content-Type", "text/plain; version=0.0.4; charset=utf-8"),
+            );
+        }
+
+        MiddleResult::Continue
+    }
+
+    fn end_with_info(&self, req: &Request, _res: &Response, info: &ResponseInfo) {
+        if req.path.trim_matches('/') == self.path {
+            return;
+        }
+
+        self.registry.record(
+            req.method,
+            req.route().unwrap_or_default(),
+            info.status.code(),
+            info.duration.as_secs_f64(),
+        );
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Metrics;
+    use crate::{
+        testing::{TestRequest, TestServer},
+        Method, Middleware, Response, Server, Status,
+    };
+
+    #[test]
+    fn test_metrics() {
+        let mut server = Server::<()>::new("localhost", 8080);
+        Metrics::new().buckets([0.5, 60.0]).attach(&mut server);
+        server.route(Method::GET, "/users/{id}", |_| Response::new());
+        let server = TestServer::from(server);
+
+        TestRequest::get("/users/1").send(&server);
+        TestRequest::get("/users/2").send(&server);
+        TestRequest::get("/missing").send(&server);
+
+        let res = TestRequest::get("/metrics").send(&server);
+        assert_eq!(res.status, Status::Ok);
+        let text = res.text();
+        let lines = text.lines().collect::<Vec<_>>();
+
+        assert!(lines
+            .contains(&r#"afire_requests_total{method="GET",route="/users/{id}",status="2xx"} 2"#));
+        assert!(lines.contains(&r#"afire_requests_total{method="GET",route="",status="4xx"} 1"#));
+        assert!(lines.contains(
+            &r#"afire_request_duration_seconds_bucket{method="GET",route="/users/{id}",le="60"} 2"#
+        ));
+        assert!(lines.contains(
+            &r#"afire_request_duration_seconds_bucket{method="GET",route="/users/{id}",le="+Inf"} 2"#
+        ));
+        assert!(lines.contains(
+            &r#"afire_request_duration_seconds_count{method="GET",route="/users/{id}"} 2"#
+        ));
+        assert!(!text.contains("route=\"/metrics\""));
+    }
+}
@@ -1,8 +1,28 @@
+pub mod auth;
+pub mod cache;
+#[cfg(feature = "compression")]
+pub mod compression;
 pub mod date;
 pub mod head;
 pub mod logger;
+pub mod method_override;
+pub mod metrics;
+#[cfg(feature = "oauth")]
+pub mod oauth;
+pub mod path_normalizer;
+pub mod range;
 pub mod ratelimit;
 pub mod real_ip;
+pub mod redirect;
+pub mod request_history;
 pub mod request_id;
+pub mod rewrite;
+pub mod serve_embedded;
 pub mod serve_static;
+pub mod session;
+#[cfg(feature = "serde")]
+pub mod templates;
+pub mod throttle;
 pub mod trace;
+pub mod version;
+pub mod well_known;
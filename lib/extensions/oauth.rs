@@ -0,0 +1,810 @@
+//! OAuth 2.0 and OpenID Connect login, with the authorization code flow and PKCE.
+//! Enabled with the `oauth` feature.
+//!
+//! The [`OAuth`] middleware redirects users to the provider to log in, exchanges the code it sends back for tokens, and keeps the user's [`Identity`] in an in-memory session.
+//! Handlers can get the identity of the current user with [`Sessions::identity`].
+//!
+//! ## Example
+//! ```rust,no_run
+//! # use afire::{Middleware, Method, Response, Server, Status, HeaderType, extension::oauth::{OAuth, Provider}};
+//! let mut server = Server::<()>::new("localhost", 8080);
+//!
+//! let oauth = OAuth::new(Provider::github(), "client-id", "http://localhost:8080/auth/callback")
+//!     .client_secret("client-secret");
+//! let sessions = oauth.sessions();
+//! oauth.attach(&mut server);
+//!
+//! server.route(Method::GET, "/", move |req| match sessions.identity(req) {
+//!     Some(user) => Response::new().text(format!("Hello, {}", user.claim("login").unwrap_or("user"))),
+//!     None => Response::new()
+//!         .status(Status::Found)
+//!         .header(HeaderType::Location, "/auth/login?redirect=/"),
+//! });
+//! ```
+
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    io,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use rustls::{crypto::ring, ClientConfig};
+use serde_json::Value;
+
+use crate::{
+    cookie::SameSite,
+    internal::{
+        client::{self, ClientResponse},
+        common::ForceLock,
+        encoding::{base64, sha256, url},
+    },
+    middleware::{MiddleResult, Middleware},
+    Content, Header, HeaderType, Method, Query, Request, Response, SetCookie, Status,
+};
+
+/// The cookie that holds the state of a login that is in progress.
+const STATE_COOKIE: &str = "afire_oauth_state";
+
+/// How long a user has to log in with the provider.
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// The most logins that can be in progress at once.
+/// Once reached, the oldest is dropped to make room for new ones, so clients can't fill up memory by starting logins.
+const MAX_PENDING: usize = 10_000;
+
+/// How long to wait for the provider's token and userinfo endpoints.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The endpoints and scopes of an OAuth provider.
+#[derive(Debug, Clone)]
+pub struct Provider {
+    /// Where users are sent to log in.
+    pub authorization_endpoint: String,
+    /// Where the code is exchanged for tokens.
+    pub token_endpoint: String,
+    /// Where to get information about the user, if the provider doesn't send an OpenID Connect `id_token`.
+    pub userinfo_endpoint: Option<String>,
+    /// The OpenID Connect issuer, which has to match the `iss` claim of `id_token`s.
+    /// If this is `None`, the issuer of tokens isn't checked.
+    pub issuer: Option<String>,
+    /// The scopes to ask for.
+    pub scopes: Vec<String>,
+}
+
+/// Middleware that adds OAuth 2.0 login, see the [module docs](self).
+///
+/// It handles three paths:
+/// - The login path (`/auth/login` by default) redirects to the provider.
+///   A `redirect` query parameter can be given, to send the user back to a page on this site after logging in.
+/// - The callback path, from the redirect uri, finishes the login and starts a session.
+/// - The logout path (`/auth/logout` by default) ends the session.
+///
+/// Logins are protected against CSRF with the `state` parameter, which is tied to the browser with a cookie, and against code interception with PKCE.
+/// OpenID Connect `id_tokens` also have to be for this client, from the provider's issuer, unexpired, and carry the `nonce` sent with the login.
+/// Sessions are kept in memory, so they are lost when the server restarts.
+pub struct OAuth {
+    provider: Provider,
+    client_id: String,
+    client_secret: Option<String>,
+    redirect_uri: String,
+    callback_path: String,
+    login_path: String,
+    logout_path: String,
+    tls: Arc<ClientConfig>,
+    sessions: Sessions,
+    /// Logins in progress, by their state.
+    pending: Mutex<HashMap<String, Pending>>,
+}
+
+/// A login in progress.
+struct Pending {
+    verifier: String,
+    nonce: String,
+    redirect: String,
+    started: Instant,
+}
+
+/// A logged in user.
+#[derive(Debug, Clone)]
+pub struct Identity {
+    /// The provider's unique ID for the user.
+    /// This is the `sub` claim for OpenID Connect providers, or the `id` from the userinfo endpoint for others (like GitHub).
+    pub subject: String,
+    /// All the claims about the user, from the `id_token` and the userinfo endpoint.
+    pub claims: Value,
+    /// The access token, for calling the provider's APIs for the user.
+    pub access_token: String,
+    /// The refresh token, if the provider sent one.
+    pub refresh_token: Option<String>,
+    /// When the access token expires, if the provider said.
+    pub expires_at: Option<SystemTime>,
+}
+
+/// The sessions of logged in users.
+/// Cloning this gives another handle to the same sessions.
+#[derive(Clone)]
+pub struct Sessions(Arc<SessionsInner>);
+
+struct SessionsInner {
+    cookie: String,
+    /// How long sessions last, in milliseconds.
+    /// This is atomic so it can be changed after handles to the sessions have been given out.
+    ttl: AtomicU64,
+    secure: bool,
+    sessions: Mutex<HashMap<String, (Arc<Identity>, Instant)>>,
+}
+
+impl Provider {
+    /// Create a provider from its authorization and token endpoints, with no scopes.
+    pub fn new(authorization_endpoint: impl AsRef<str>, token_endpoint: impl AsRef<str>) -> Self {
+        Self {
+            authorization_endpoint: authorization_endpoint.as_ref().to_owned(),
+            token_endpoint: token_endpoint.as_ref().to_owned(),
+            userinfo_endpoint: None,
+            issuer: None,
+            scopes: Vec::new(),
+        }
+    }
+
+    /// Sets the userinfo endpoint, which is called with the access token to get the user's claims.
+    pub fn userinfo(self, endpoint: impl AsRef<str>) -> Self {
+        Self {
+            userinfo_endpoint: Some(endpoint.as_ref().to_owned()),
+            ..self
+        }
+    }
+
+    /// Sets the OpenID Connect issuer, so `id_tokens` from anyone else are rejected.
+    pub fn issuer(self, issuer: impl AsRef<str>) -> Self {
+        Self {
+            issuer: Some(issuer.as_ref().to_owned()),
+            ..self
+        }
+    }
+
+    /// Sets the scopes to ask for.
+    pub fn scopes(self, scopes: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        Self {
+            scopes: scopes.into_iter().map(|x| x.as_ref().to_owned()).collect(),
+            ..self
+        }
+    }
+
+    /// Gets the endpoints and issuer of an OpenID Connect provider from its discovery document, at `{issuer}/.well-known/openid-configuration`.
+    /// The scopes are set to `openid email profile`.
+    pub fn discover(issuer: impl AsRef<str>) -> io::Result<Self> {
+        let url = format!(
+            "{}/.well-known/openid-configuration",
+            issuer.as_ref().trim_end_matches('/')
+        );
+        let res = client::request(
+            Method::GET,
+            &url,
+            &[Header::new("Accept", "application/json")],
+            &[],
+            &client::default_tls_config(),
+            REQUEST_TIMEOUT,
+        )?;
+        let config = json_response(res).map_err(io::Error::other)?;
+
+        let endpoint = |name: &str| config[name].as_str().map(str::to_owned);
+        let missing = || io::Error::new(io::ErrorKind::InvalidData, "missing endpoint");
+        Ok(Self {
+            authorization_endpoint: endpoint("authorization_endpoint").ok_or_else(missing)?,
+            token_endpoint: endpoint("token_endpoint").ok_or_else(missing)?,
+            userinfo_endpoint: endpoint("userinfo_endpoint"),
+            issuer: Some(endpoint("issuer").ok_or_else(missing)?),
+            scopes: vec!["openid".into(), "email".into(), "profile".into()],
+        })
+    }
+
+    /// Google, with the `openid email profile` scopes.
+    pub fn google() -> Self {
+        Self::new(
+            "https://accounts.google.com/o/oauth2/v2/auth",
+            "https://oauth2.googleapis.com/token",
+        )
+        .issuer("https://accounts.google.com")
+        .scopes(["openid", "email", "profile"])
+    }
+
+    /// GitHub, with the `read:user` scope.
+    /// GitHub doesn't support OpenID Connect, so the user's claims are from its `/user` API.
+    pub fn github() -> Self {
+        Self::new(
+            "https://github.com/login/oauth/authorize",
+            "https://github.com/login/oauth/access_token",
+        )
+        .userinfo("https://api.github.com/user")
+        .scopes(["read:user"])
+    }
+}
+
+impl OAuth {
+    /// Create a new OAuth middleware.
+    /// The redirect uri has to be registered with the provider, and its path is where the provider sends users back to.
+    /// Sessions last a day by default.
+    pub fn new(
+        provider: Provider,
+        client_id: impl AsRef<str>,
+        redirect_uri: impl AsRef<str>,
+    ) -> Self {
+        let redirect_uri = redirect_uri.as_ref().to_owned();
+        let callback_path = redirect_uri
+            .split_once("://")
+            .and_then(|(_, x)| x.find('/').map(|i| &x[i..]))
+            .unwrap_or("/")
+            .split('?')
+            .next()
+            .unwrap_or_default()
+            .to_owned();
+
+        Self {
+            provider,
+            client_id: client_id.as_ref().to_owned(),
+            client_secret: None,
+            callback_path,
+            login_path: "/auth/login".to_owned(),
+            logout_path: "/auth/logout".to_owned(),
+            tls: client::default_tls_config(),
+            sessions: Sessions(Arc::new(SessionsInner {
+                cookie: "afire_session".to_owned(),
+                ttl: AtomicU64::new(60 * 60 * 24 * 1000),
+                secure: redirect_uri.starts_with("https://"),
+                sessions: Mutex::new(HashMap::new()),
+            })),
+            redirect_uri,
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sets the client secret.
+    /// Public clients (like apps that can't keep a secret) don't have one, and rely on PKCE instead.
+    pub fn client_secret(self, client_secret: impl AsRef<str>) -> Self {
+        Self {
+            client_secret: Some(client_secret.as_ref().to_owned()),
+            ..self
+        }
+    }
+
+    /// Sets the path that starts a login.
+    pub fn login_path(self, path: impl AsRef<str>) -> Self {
+        Self {
+            login_path: path.as_ref().to_owned(),
+            ..self
+        }
+    }
+
+    /// Sets the path that ends a session.
+    pub fn logout_path(self, path: impl AsRef<str>) -> Self {
+        Self {
+            logout_path: path.as_ref().to_owned(),
+            ..self
+        }
+    }
+
+    /// Sets how long a session lasts.
+    /// This also applies to handles from [`OAuth::sessions`] that were made before.
+    pub fn session_ttl(self, ttl: Duration) -> Self {
+        let ttl = ttl.as_millis().min(u64::MAX as u128) as u64;
+        self.sessions.0.ttl.store(ttl, Ordering::Relaxed);
+        self
+    }
+
+    /// Sets the TLS config used to connect to the provider.
+    /// By default the Mozilla root certificates are trusted.
+    pub fn tls_config(self, tls: Arc<ClientConfig>) -> Self {
+        Self { tls, ..self }
+    }
+
+    /// Gets a handle to the sessions, to look up the identity of users in route handlers.
+    pub fn sessions(&self) -> Sessions {
+        self.sessions.clone()
+    }
+
+    /// Redirects the user to the provider.
+    fn login(&self, req: &Request) -> Response {
+        let redirect = req
+            .query
+            .get("redirect")
+            .filter(|x| local_path(x))
+            .unwrap_or("/")
+            .to_owned();
+
+        let state = random_token();
+        let verifier = random_token();
+        let nonce = random_token();
+        let challenge = base64::encode_url(&sha256::hash(verifier.as_bytes()));
+
+        let mut pending = self.pending.force_lock();
+        pending.retain(|_, x| x.started.elapsed() < LOGIN_TIMEOUT);
+        if pending.len() >= MAX_PENDING {
+            let oldest = pending
+                .iter()
+                .min_by_key(|x| x.1.started)
+                .map(|x| x.0.to_owned());
+            if let Some(oldest) = oldest {
+                pending.remove(&oldest);
+            }
+        }
+        pending.insert(
+            state.clone(),
+            Pending {
+                verifier,
+                nonce: nonce.clone(),
+                redirect,
+                started: Instant::now(),
+            },
+        );
+
+        let mut location = format!(
+            "{}{}response_type=code&client_id={}&redirect_uri={}&state={}&code_challenge={}&code_challenge_method=S256&nonce={}",
+            self.provider.authorization_endpoint,
+            if self.provider.authorization_endpoint.contains('?') { '&' } else { '?' },
+            url::encode(&self.client_id),
+            url::encode(&self.redirect_uri),
+            state,
+            challenge,
+            nonce
+        );
+        if !self.provider.scopes.is_empty() {
+            let _ = write!(
+                location,
+                "&scope={}",
+                url::encode(&self.provider.scopes.join(" "))
+            );
+        }
+
+        Response::new()
+            .status(Status::Found)
+            .header(HeaderType::Location, location)
+            .header(
+                HeaderType::SetCookie,
+                self.sessions.cookie(STATE_COOKIE, &state, LOGIN_TIMEOUT),
+            )
+    }
+
+    /// Finishes a login, when the provider sends the user back.
+    fn callback(&self, req: &Request) -> Response {
+        let error = |status, msg: &str| {
+            Response::new()
+                .status(status)
+                .text(msg)
+                .content(Content::TXT)
+        };
+        if let Some(err) = req.query.get("error") {
+            return error(Status::BadRequest, &format!("Login failed: {err}"));
+        }
+
+        let (code, state) = match (req.query.get("code"), req.query.get("state")) {
+            (Some(code), Some(state)) => (code, state),
+            _ => return error(Status::BadRequest, "Missing code or state"),
+        };
+
+        // The state has to be from this browser, so logins can't be forced on other users
+        let pending = match req.cookies.get(STATE_COOKIE) {
+            Some(cookie) if cookie == state => self.pending.force_lock().remove(state),
+            _ => None,
+        };
+        let pending = match pending {
+            Some(i) if i.started.elapsed() < LOGIN_TIMEOUT => i,
+            _ => return error(Status::BadRequest, "Invalid or expired login"),
+        };
+
+        let identity = match self.exchange(code, &pending) {
+            Ok(i) => i,
+            Err(e) => {
+                trace!(Level::Error, "OAuth token exchange failed: {}", e);
+                return error(Status::BadGateway, "Login failed");
+            }
+        };
+
+        let id = self.sessions.insert(identity);
+        Response::new()
+            .status(Status::Found)
+            .header(HeaderType::Location, pending.redirect)
+            .header(
+                HeaderType::SetCookie,
+                self.sessions
+                    .cookie(&self.sessions.0.cookie, &id, self.sessions.ttl()),
+            )
+            .header(
+                HeaderType::SetCookie,
+                self.sessions.cookie(STATE_COOKIE, "", Duration::ZERO),
+            )
+    }
+
+    /// Exchanges a code for tokens, and gets the user's identity.
+    fn exchange(&self, code: &str, pending: &Pending) -> Result<Identity, String> {
+        let mut form = format!(
+            "grant_type=authorization_code&code={}&redirect_uri={}&client_id={}&code_verifier={}",
+            url::encode(code),
+            url::encode(&self.redirect_uri),
+            url::encode(&self.client_id),
+            pending.verifier
+        );
+        if let Some(secret) = &self.client_secret {
+            let _ = write!(form, "&client_secret={}", url::encode(secret));
+        }
+
+        let res = client::request(
+            Method::POST,
+            &self.provider.token_endpoint,
+            &[
+                Header::new("Content-Type", "application/x-www-form-urlencoded"),
+                Header::new("Accept", "application/json"),
+            ],
+            form.as_bytes(),
+            &self.tls,
+            REQUEST_TIMEOUT,
+        )
+        .map_err(|e| e.to_string())?;
+        let tokens = json_response(res)?;
+
+        let access_token = tokens["access_token"]
+            .as_str()
+            .ok_or("no access token")?
+            .to_owned();
+        let mut claims = serde_json::Map::new();
+
+        // The id token came straight from the provider over TLS, so its signature doesn't need to be checked (OpenID Connect Core 3.1.3.7)
+        if let Some(id_token) = tokens["id_token"].as_str() {
+            let payload = id_token
+                .split('.')
+                .nth(1)
+                .and_then(base64::decode_url)
+                .ok_or("invalid id token")?;
+            let payload: Value = serde_json::from_slice(&payload).map_err(|e| e.to_string())?;
+            let audience_ok = match &payload["aud"] {
+                Value::String(aud) => *aud == self.client_id,
+                Value::Array(aud) => aud.iter().any(|x| *x == *self.client_id),
+                _ => false,
+            };
+            if !audience_ok {
+                return Err("id token is for another client".to_owned());
+            }
+            if let Some(issuer) = &self.provider.issuer {
+                if payload["iss"].as_str() != Some(issuer) {
+                    return Err("id token is from another issuer".to_owned());
+                }
+            }
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            match payload["exp"].as_u64() {
+                Some(exp) if exp > now => {}
+                _ => return Err("id token has expired".to_owned()),
+            }
+            // The nonce ties the token to this login, so a token from another login can't be replayed (OpenID Connect Core 3.1.3.7)
+            if payload["nonce"].as_str() != Some(&pending.nonce) {
+                return Err("id token has the wrong nonce".to_owned());
+            }
+            if let Value::Object(payload) = payload {
+                claims.extend(payload);
+            }
+        }
+
+        if let Some(userinfo) = &self.provider.userinfo_endpoint {
+            let res = client::request(
+                Method::GET,
+                userinfo,
+                &[
+                    Header::new("Authorization", format!("Bearer {access_token}")),
+                    Header::new("Accept", "application/json"),
+                ],
+                &[],
+                &self.tls,
+                REQUEST_TIMEOUT,
+            )
+            .map_err(|e| e.to_string())?;
+            if let Value::Object(info) = json_response(res)? {
+                claims.extend(info);
+            }
+        }
+
+        let subject = match claims.get("sub").or_else(|| claims.get("id")) {
+            Some(Value::String(i)) => i.to_owned(),
+            Some(Value::Number(i)) => i.to_string(),
+            _ => return Err("no subject in the user's claims".to_owned()),
+        };
+
+        Ok(Identity {
+            subject,
+            claims: Value::Object(claims),
+            access_token,
+            refresh_token: tokens["refresh_token"].as_str().map(str::to_owned),
+            expires_at: tokens["expires_in"]
+                .as_u64()
+                .map(|x| SystemTime::now() + Duration::from_secs(x)),
+        })
+    }
+
+    /// Ends the session and sends the user home.
+    fn logout(&self, req: &Request) -> Response {
+        self.sessions.remove(req);
+        Response::new()
+            .status(Status::Found)
+            .header(HeaderType::Location, "/")
+            .header(
+                HeaderType::SetCookie,
+                self.sessions
+                    .cookie(&self.sessions.0.cookie, "", Duration::ZERO),
+            )
+    }
+}
+
+impl Middleware for OAuth {
+    fn pre(&self, req: &mut Request) -> MiddleResult {
+        let res = match (&req.method, req.path.as_str()) {
+            (Method::GET, path) if path == self.login_path => self.login(req),
+            (Method::GET, path) if path == self.callback_path => self.callback(req),
+            (Method::GET | Method::POST, path) if path == self.logout_path => self.logout(req),
+            _ => return MiddleResult::Continue,
+        };
+
+        MiddleResult::Send(res)
+    }
+}
+
+impl Identity {
+    /// Gets a claim as a string, like `email` or `name`.
+    pub fn claim(&self, name: &str) -> Option<&str> {
+        self.claims.get(name)?.as_str()
+    }
+}
+
+impl Sessions {
+    /// Gets the identity of the user that made a request, if they are logged in.
+    pub fn identity(&self, req: &Request) -> Option<Arc<Identity>> {
+        let id = req.cookies.get(&self.0.cookie)?;
+        let mut sessions = self.0.sessions.force_lock();
+        match sessions.get(id) {
+            Some((identity, created)) if created.elapsed() < self.ttl() => Some(identity.clone()),
+            Some(_) => {
+                sessions.remove(id);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Ends the session of the user that made a request, returning their identity if they were logged in.
+    pub fn remove(&self, req: &Request) -> Option<Arc<Identity>> {
+        let id = req.cookies.get(&self.0.cookie)?;
+        self.0.sessions.force_lock().remove(id).map(|x| x.0)
+    }
+
+    /// Starts a new session, returning its ID.
+    fn insert(&self, identity: Identity) -> String {
+        let id = random_token();
+        let mut sessions = self.0.sessions.force_lock();
+        let ttl = self.ttl();
+        sessions.retain(|_, x| x.1.elapsed() < ttl);
+        sessions.insert(id.clone(), (Arc::new(identity), Instant::now()));
+        id
+    }
+
+    /// How long sessions last.
+    fn ttl(&self) -> Duration {
+        Duration::from_millis(self.0.ttl.load(Ordering::Relaxed))
+    }
+
+    /// Makes a `Set-Cookie` value that can't be read by scripts.
+    fn cookie(&self, name: &str, value: &str, max_age: Duration) -> String {
+        SetCookie::new(name, value)
+            .max_age(max_age.as_secs())
+            .path("/")
+            .secure(self.0.secure)
+            .http_only(true)
+            .same_site(SameSite::Lax)
+            .to_string()
+    }
+}
+
+/// Parses a JSON response, or returns an error if the request failed.
+/// Some providers send form encoded responses from their token endpoint, which are converted to a JSON object.
+fn json_response(res: ClientResponse) -> Result<Value, String> {
+    if res.status != 200 {
+        return Err(format!(
+            "provider returned {}: {}",
+            res.status,
+            String::from_utf8_lossy(&res.body)
+        ));
+    }
+
+    let form = res
+        .headers
+        .get(HeaderType::ContentType)
+        .is_some_and(|x| x.starts_with("application/x-www-form-urlencoded"));
+    let value = match form {
+        true => Value::Object(
+            Query::from_body(&String::from_utf8_lossy(&res.body))
+                .iter()
+                .map(|[key, value]| (key.to_owned(), Value::String(value.to_owned())))
+                .collect(),
+        ),
+        false => serde_json::from_slice(&res.body).map_err(|e| e.to_string())?,
+    };
+
+    // GitHub sends errors with a 200 status
+    match value["error"].as_str() {
+        Some(error) => Err(format!("provider returned an error: {error}")),
+        None => Ok(value),
+    }
+}
+
+/// Checks if a path is safe to redirect to after a login, so users can only be sent to pages on this site.
+/// Browsers treat `\` like `/`, so `/\evil.com` would go to another site, and control characters (like a CRLF) could end the `Location` header early.
+fn local_path(path: &str) -> bool {
+    path.starts_with('/')
+        && !path.starts_with("//")
+        && !path.contains('\\')
+        && !path.chars().any(char::is_control)
+}
+
+/// Makes a random url safe token, with 256 bits of entropy.
+fn random_token() -> String {
+    let mut buf = [0; 32];
+    ring::default_provider()
+        .secure_random
+        .fill(&mut buf)
+        .expect("Failed to get random bytes");
+    base64::encode_url(&buf)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::Arc,
+        time::{Duration, Instant},
+    };
+
+    use crate::{
+        internal::common::ForceLock,
+        testing::{TestRequest, TestServer},
+        HeaderType, Method, Server, Status,
+    };
+
+    use super::{local_path, random_token, OAuth, Pending, Provider, MAX_PENDING};
+
+    /// A server that runs the login and callback of an OAuth middleware, so its pending logins can be checked.
+    /// The provider's token endpoint can't be reached, so code exchanges fail.
+    fn login_server() -> (Arc<OAuth>, TestServer) {
+        let oauth = Arc::new(OAuth::new(
+            Provider::new("https://example.com/authorize", "http://127.0.0.1:1/token"),
+            "client",
+            "http://localhost:8080/auth/callback",
+        ));
+
+        let mut server = Server::<()>::new("localhost", 8080);
+        let login = oauth.clone();
+        server.route(Method::GET, "/auth/login", move |req| login.login(req));
+        let callback = oauth.clone();
+        server.route(Method::GET, "/auth/callback", move |req| {
+            callback.callback(req)
+        });
+
+        (oauth, TestServer::from(server))
+    }
+
+    #[test]
+    fn test_callback_path() {
+        let oauth = OAuth::new(
+            Provider::github(),
+            "id",
+            "https://example.com/auth/github/callback?x=y",
+        );
+        assert_eq!(oauth.callback_path, "/auth/github/callback");
+        assert!(oauth.sessions.0.secure);
+
+        let oauth = OAuth::new(Provider::github(), "id", "http://localhost:8080");
+        assert_eq!(oauth.callback_path, "/");
+        assert!(!oauth.sessions.0.secure);
+    }
+
+    #[test]
+    fn test_session_ttl() {
+        let oauth = OAuth::new(Provider::github(), "id", "http://localhost:8080");
+        let sessions = oauth.sessions();
+        let _oauth = oauth.session_ttl(Duration::from_secs(60));
+        assert_eq!(sessions.ttl(), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_random_token() {
+        let token = random_token();
+        assert_eq!(token.len(), 43);
+        assert_ne!(token, random_token());
+    }
+
+    #[test]
+    fn test_login_callback() {
+        let (oauth, server) = login_server();
+        let res = TestRequest::get("/auth/login?redirect=/me").send(&server);
+        assert_eq!(res.status, Status::Found);
+        let location = res.header(HeaderType::Location).unwrap();
+        assert!(location
+            .starts_with("https://example.com/authorize?response_type=code&client_id=client&"));
+
+        let state = location
+            .split('&')
+            .find_map(|x| x.strip_prefix("state="))
+            .unwrap()
+            .to_owned();
+        assert_eq!(oauth.pending.force_lock()[&state].redirect, "/me");
+
+        // The state has to come from the same browser
+        let callback = format!("/auth/callback?code=code&state={state}");
+        let res = TestRequest::get(&callback)
+            .header(HeaderType::Cookie, "afire_oauth_state=other")
+            .send(&server);
+        assert_eq!(res.status, Status::BadRequest);
+
+        // The login is used up even though the exchange fails
+        let cookie = format!("afire_oauth_state={state}");
+        let res = TestRequest::get(&callback)
+            .header(HeaderType::Cookie, &cookie)
+            .send(&server);
+        assert_eq!(res.status, Status::BadGateway);
+        assert!(oauth.pending.force_lock().is_empty());
+
+        let res = TestRequest::get(&callback)
+            .header(HeaderType::Cookie, &cookie)
+            .send(&server);
+        assert_eq!(res.status, Status::BadRequest);
+    }
+
+    #[test]
+    fn test_login_redirect() {
+        let (oauth, server) = login_server();
+        for (redirect, expected) in [
+            ("/me", "/me"),
+            ("/a/b%3Fc", "/a/b?c"),
+            ("https://evil.com", "/"),
+            ("//evil.com", "/"),
+            ("/%5Cevil.com", "/"),
+            ("/%5C%5Cevil.com", "/"),
+            ("/a%0D%0ASet-Cookie:%20x=y", "/"),
+            ("/a%00", "/"),
+            ("me", "/"),
+        ] {
+            oauth.pending.force_lock().clear();
+            TestRequest::get(format!("/auth/login?redirect={redirect}")).send(&server);
+            let pending = oauth.pending.force_lock();
+            let login = pending.values().next().unwrap();
+            assert_eq!(login.redirect, expected, "{redirect}");
+        }
+
+        assert!(local_path("/"));
+        assert!(!local_path("/\\evil.com"));
+    }
+
+    #[test]
+    fn test_pending_limit() {
+        let (oauth, server) = login_server();
+        let start = Instant::now();
+        oauth.pending.force_lock().extend((0..MAX_PENDING).map(|i| {
+            let pending = Pending {
+                verifier: String::new(),
+                nonce: String::new(),
+                redirect: "/".to_owned(),
+                started: start,
+            };
+            (i.to_string(), pending)
+        }));
+
+        // The oldest logins are dropped to make room
+        for _ in 0..10 {
+            TestRequest::get("/auth/login").send(&server);
+        }
+        let pending = oauth.pending.force_lock();
+        assert_eq!(pending.len(), MAX_PENDING);
+        assert_eq!(pending.values().filter(|x| x.started > start).count(), 10);
+    }
+}
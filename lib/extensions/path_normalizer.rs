@@ -0,0 +1,189 @@
+//! Normalize request paths, so the same resource can't be requested through different paths.
+
+use crate::{
+    middleware::{MiddleResult, Middleware},
+    HeaderType, Request, Response, Status,
+};
+
+/// Middleware that normalizes request paths before routing.
+///
+/// Repeated slashes and backslashes are always collapsed into single slashes, and by default [dot-segments](https://www.rfc-editor.org/rfc/rfc3986#section-5.2.4) are removed, so `/a/./b/../c` becomes `/a/c`.
+/// Dot-segments that are url encoded (`%2E`) are removed too.
+///
+/// By default the path is rewritten silently (See [`Request::rewrite_path`]), but the normalizer can also redirect clients to the normalized path with [`PathNormalizer::redirect`], so they (and search engines) use the canonical URL.
+///
+/// ## Example
+/// ```rust
+/// # use afire::{Method, Middleware, Response, Server, Status, extension::PathNormalizer};
+/// # fn test(server: &mut Server) {
+/// // `/docs/../about` gets a 308 redirect to `/about`
+/// PathNormalizer::new()
+///     .redirect(Status::PermanentRedirect)
+///     .attach(server);
+///
+/// server.route(Method::GET, "/about", |_req| Response::new().text("About"));
+/// # }
+/// ```
+pub struct PathNormalizer {
+    /// The redirect status, or None to rewrite the path.
+    redirect: Option<Status>,
+    /// If dot-segments should be removed.
+    dot_segments: bool,
+}
+
+impl PathNormalizer {
+    /// Create a new PathNormalizer that removes dot-segments and rewrites the path.
+    pub fn new() -> Self {
+        Self {
+            redirect: None,
+            dot_segments: true,
+        }
+    }
+
+    /// Rewrite the request path to the normalized path, without the client knowing.
+    /// This is the default.
+    pub fn rewrite(mut self) -> Self {
+        self.redirect = None;
+        self
+    }
+
+    /// Redirect requests for paths that aren't normalized to the normalized path, keeping the query.
+    /// The status should be `301 Moved Permanently` or `308 Permanent Redirect`.
+    /// Clients may change the method of a request to GET when following a 301, but not a 308.
+    pub fn redirect(mut self, status: Status) -> Self {
+        self.redirect = Some(status);
+        self
+    }
+
+    /// Sets if dot-segments (`.` and `..`) should be removed from paths.
+    /// Enabled by default.
+    pub fn dot_segments(mut self, dot_segments: bool) -> Self {
+        self.dot_segments = dot_segments;
+        self
+    }
+
+    /// Normalizes a path that already has its slashes collapsed.
+    fn normalize(&self, path: &str) -> String {
+        match self.dot_segments {
+            true => remove_dot_segments(path),
+            false => path.to_owned(),
+        }
+    }
+}
+
+impl Middleware for PathNormalizer {
+    fn pre(&self, req: &mut Request) -> MiddleResult {
+        let status = match self.redirect {
+            Some(status) => status,
+            None => {
+                let path = self.normalize(&req.path);
+                if path != req.path {
+                    req.rewrite_path(path);
+                }
+                return MiddleResult::Continue;
+            }
+        };
+
+        // Compare to the path as the client sent it, as the parsed path already has its slashes collapsed
+        let target = String::from_utf8_lossy(req.raw_head());
+        let target = target.split_whitespace().nth(1).unwrap_or_default();
+        let (raw_path, query) = match target.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (target, None),
+        };
+        let raw_path = match req.authority {
+            Some(_) => req.original_path(),
+            None => raw_path,
+        };
+
+        let path = self.normalize(req.original_path());
+        if path == raw_path {
+            return MiddleResult::Continue;
+        }
+
+        let location = match query {
+            Some(query) => format!("{path}?{query}"),
+            None => path,
+        };
+        MiddleResult::Send(
+            Response::new()
+                .status(status)
+                .header(HeaderType::Location, location),
+        )
+    }
+}
+
+impl Default for PathNormalizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Removes `.` and `..` segments from a path, following [RFC 3986](https://www.rfc-editor.org/rfc/rfc3986#section-5.2.4).
+/// A `..` at the root is dropped, and a path ending in a dot-segment keeps its trailing slash.
+/// Paths that don't start with a slash (like `*`) are not changed.
+pub(crate) fn remove_dot_segments(path: &str) -> String {
+    if !path.starts_with('/') {
+        return path.to_owned();
+    }
+
+    let mut out = Vec::new();
+    let mut trailing_slash = false;
+    let mut segments = path.split('/').skip(1).peekable();
+    while let Some(segment) = segments.next() {
+        let last = segments.peek().is_none();
+        match dot_segment(segment) {
+            Some(1) => trailing_slash = last,
+            Some(_) => {
+                out.pop();
+                trailing_slash = last;
+            }
+            None => {
+                out.push(segment);
+                trailing_slash = false;
+            }
+        }
+    }
+
+    let mut path = format!("/{}", out.join("/"));
+    if trailing_slash && !path.ends_with('/') {
+        path.push('/');
+    }
+    path
+}
+
+/// Gets the number of dots in a `.` or `..` segment, which can be url encoded as `%2E`.
+fn dot_segment(segment: &str) -> Option<usize> {
+    if segment.len() > 6 {
+        return None;
+    }
+
+    match segment.to_ascii_lowercase().replace("%2e", ".").as_str() {
+        "." => Some(1),
+        ".." => Some(2),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::remove_dot_segments;
+
+    #[test]
+    fn test_remove_dot_segments() {
+        for (path, expected) in [
+            ("/", "/"),
+            ("/a/b/c", "/a/b/c"),
+            ("/a/./b/../c", "/a/c"),
+            ("/a/b/..", "/a/"),
+            ("/a/b/.", "/a/b/"),
+            ("/../../a", "/a"),
+            ("/..", "/"),
+            ("/a/%2E%2e/b", "/b"),
+            ("/a/..b/.c", "/a/..b/.c"),
+            ("*", "*"),
+        ] {
+            assert_eq!(remove_dot_segments(path), expected, "{path}");
+        }
+    }
+}
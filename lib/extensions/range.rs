@@ -0,0 +1,267 @@
+//! Middleware to serve parts of response bodies for HTTP range requests (as defined in [RFC 9110, Section 14](https://www.rfc-editor.org/rfc/rfc9110.html#section-14)).
+//! This lets clients resume downloads and seek in media without getting the whole body.
+
+use std::{
+    cell::RefCell,
+    io::{Read, Seek, SeekFrom},
+    mem,
+};
+
+use crate::{
+    middleware::{MiddleResult, Middleware},
+    response::{ReadSeek, ResponseBody},
+    HeaderType, Method, Request, Response, Status,
+};
+
+/// Middleware to serve byte ranges of response bodies, for requests with a `Range` header.
+/// Works on any body that can seek, which are files and seekable streams (see [`Response::ranged_stream`]).
+/// Other bodies are always sent in full.
+///
+/// Successful responses with a seekable body get an `Accept-Ranges: bytes` header, so clients know they can ask for ranges.
+/// If the request has a single byte range, like `bytes=100-199`, `bytes=100-` or `bytes=-100`, only that part of the body is sent with a `206 Partial Content` status.
+/// Ranges starting past the end of the body get a `416 Range Not Satisfiable` response.
+/// Requests with more than one range are sent the whole body, as multipart ranges are not supported.
+///
+/// The `If-Range` header is also supported, so ranges are only sent if the body still has the same `ETag` or `Last-Modified` date.
+/// Middleware run in the reverse order they were attached, so attach this before middleware that makes responses, like [`crate::extension::ServeStatic`].
+/// ## Example
+/// ```rust
+/// # use afire::{Response, Method, Middleware, Server, extension::Range};
+/// # use std::io::Cursor;
+/// let mut server = Server::<()>::new("localhost", 8080);
+/// Range.attach(&mut server);
+///
+/// server.route(Method::GET, "/data", |_| {
+///     let data = Cursor::new(b"Hello World".to_vec());
+///     Response::new().ranged_stream(data)
+/// });
+/// ```
+pub struct Range;
+
+impl Middleware for Range {
+    fn post(&self, req: &Request, res: &mut Response) -> MiddleResult {
+        if !matches!(req.method, Method::GET | Method::HEAD) || res.status != Status::Ok {
+            return MiddleResult::Continue;
+        }
+
+        // The length of the body from the stream's current position, which is where the body starts
+        let (start, len) = match &mut res.data {
+            ResponseBody::File(file) => match body_len(file) {
+                Some(i) => i,
+                None => return MiddleResult::Continue,
+            },
+            ResponseBody::RangedStream(stream) => match body_len(stream) {
+                Some(i) => i,
+                None => return MiddleResult::Continue,
+            },
+            _ => return MiddleResult::Continue,
+        };
+
+        if !res.headers.has("Accept-Ranges") {
+            res.headers.add(HeaderType::AcceptRanges, "bytes");
+        }
+
+        let range = match req.headers.get(HeaderType::Range) {
+            Some(range) if if_range_matches(req, res) => range,
+            _ => return MiddleResult::Continue,
+        };
+
+        let (first, last) = match parse_range(range, len) {
+            Some(Ok(range)) => range,
+            Some(Err(())) => {
+                res.status = Status::RangeNotSatisfiable;
+                res.data = ResponseBody::empty();
+                res.headers.retain(|x| x.name != HeaderType::ContentLength);
+                res.headers
+                    .add(HeaderType::ContentRange, format!("bytes */{len}"));
+                return MiddleResult::Continue;
+            }
+            None => return MiddleResult::Continue,
+        };
+
+        let body = match mem::replace(&mut res.data, ResponseBody::empty()) {
+            ResponseBody::File(file) => part(file, start + first, last - first + 1),
+            ResponseBody::RangedStream(stream) => part(stream, start + first, last - first + 1),
+            _ => unreachable!(),
+        };
+
+        res.data = match body {
+            Some(body) => body,
+            None => {
+                trace!(
+                    Level::Error,
+                    "Error seeking response body for range request"
+                );
+                *res = Response::empty(Status::InternalServerError);
+                return MiddleResult::Continue;
+            }
+        };
+
+        res.status = Status::PartialContent;
+        res.headers.retain(|x| x.name != HeaderType::ContentLength);
+        res.headers.add(
+            HeaderType::ContentRange,
+            format!("bytes {first}-{last}/{len}"),
+        );
+        MiddleResult::Continue
+    }
+}
+
+/// Gets the current position of a stream and the number of bytes from there to the end.
+/// The stream is left at the same position.
+fn body_len(stream: &mut dyn ReadSeek) -> Option<(u64, u64)> {
+    let start = stream.stream_position().ok()?;
+    let end = stream.seek(SeekFrom::End(0)).ok()?;
+    stream.seek(SeekFrom::Start(start)).ok()?;
+    Some((start, end.saturating_sub(start)))
+}
+
+/// Makes a body of `len` bytes of a stream, starting at `offset`.
+fn part(
+    mut stream: impl Read + Seek + Send + 'static,
+    offset: u64,
+    len: u64,
+) -> Option<ResponseBody> {
+    stream.seek(SeekFrom::Start(offset)).ok()?;
+    Some(ResponseBody::Stream(Box::new(RefCell::new(
+        stream.take(len),
+    ))))
+}
+
+/// Checks if the range should be used, based on the request's `If-Range` header.
+/// It can have an entity tag, which must be a strong match for the response's `ETag`, or a date, which must be the same as its `Last-Modified` date.
+/// If there is no `If-Range` header the range is always used.
+fn if_range_matches(req: &Request, res: &Response) -> bool {
+    let condition = match req.headers.get("If-Range") {
+        Some(condition) => condition.trim(),
+        None => return true,
+    };
+
+    if condition.starts_with('"') || condition.starts_with("W/") {
+        return !condition.starts_with("W/")
+            && res.headers.get(HeaderType::ETag) == Some(condition);
+    }
+
+    res.headers.get("Last-Modified") == Some(condition)
+}
+
+/// Parses a `Range` header for a body of `len` bytes, into the first and last byte of the range (inclusive).
+/// Returns None if the header should be ignored, because it is invalid, is not in bytes or has more than one range.
+/// Returns `Some(Err(()))` if the range starts past the end of the body.
+fn parse_range(header: &str, len: u64) -> Option<Result<(u64, u64), ()>> {
+    let (unit, ranges) = header.split_once('=')?;
+    if !unit.trim().eq_ignore_ascii_case("bytes") {
+        return None;
+    }
+
+    let mut ranges = ranges.split(',').map(str::trim).filter(|x| !x.is_empty());
+    let (first, last) = ranges.next()?.split_once('-')?;
+    if ranges.next().is_some() {
+        return None;
+    }
+
+    let range = match (first.trim(), last.trim()) {
+        ("", "") => return None,
+        // A suffix range, for the last `n` bytes
+        ("", suffix) => {
+            let suffix = suffix.parse::<u64>().ok()?;
+            if suffix == 0 || len == 0 {
+                return Some(Err(()));
+            }
+            (len.saturating_sub(suffix), len - 1)
+        }
+        (first, last) => {
+            let first = first.parse::<u64>().ok()?;
+            let last = match last {
+                "" => u64::MAX,
+                last => last.parse::<u64>().ok()?,
+            };
+
+            if last < first {
+                return None;
+            }
+
+            if first >= len {
+                return Some(Err(()));
+            }
+            (first, last.min(len - 1))
+        }
+    };
+
+    Some(Ok(range))
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::{parse_range, Range};
+    use crate::{
+        testing::{TestRequest, TestServer},
+        HeaderType, Method, Middleware, Response, Server, Status,
+    };
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-4", 10), Some(Ok((0, 4))));
+        assert_eq!(parse_range("bytes=5-", 10), Some(Ok((5, 9))));
+        assert_eq!(parse_range("bytes=5-100", 10), Some(Ok((5, 9))));
+        assert_eq!(parse_range("bytes=-3", 10), Some(Ok((7, 9))));
+        assert_eq!(parse_range("bytes=-30", 10), Some(Ok((0, 9))));
+        assert_eq!(parse_range("bytes=10-", 10), Some(Err(())));
+        assert_eq!(parse_range("bytes=-0", 10), Some(Err(())));
+        assert_eq!(parse_range("bytes=0-", 0), Some(Err(())));
+        assert_eq!(parse_range("bytes=5-4", 10), None);
+        assert_eq!(parse_range("bytes=0-1, 4-5", 10), None);
+        assert_eq!(parse_range("items=0-1", 10), None);
+        assert_eq!(parse_range("bytes=a-b", 10), None);
+    }
+
+    #[test]
+    fn test_range_stream() {
+        let mut server = Server::<()>::new("localhost", 8080);
+        Range.attach(&mut server);
+        server.route(Method::GET, "/", |_| {
+            let mut data = Cursor::new(b"##Hello World".to_vec());
+            data.set_position(2);
+            Response::new()
+                .ranged_stream(data)
+                .header(HeaderType::ETag, "\"1\"")
+        });
+        server.route(Method::GET, "/stream", |_| {
+            Response::new().stream(Cursor::new(b"Hello World".to_vec()))
+        });
+        let server = TestServer::from(server);
+
+        let res = TestRequest::get("/").send(&server);
+        assert_eq!(res.status, Status::Ok);
+        assert_eq!(res.header(HeaderType::AcceptRanges), Some("bytes"));
+        assert_eq!(res.text(), "Hello World");
+
+        let res = TestRequest::get("/")
+            .header(HeaderType::Range, "bytes=6-")
+            .send(&server);
+        assert_eq!(res.status, Status::PartialContent);
+        assert_eq!(res.header(HeaderType::ContentRange), Some("bytes 6-10/11"));
+        assert_eq!(res.text(), "World");
+
+        let res = TestRequest::get("/")
+            .header(HeaderType::Range, "bytes=11-")
+            .send(&server);
+        assert_eq!(res.status, Status::RangeNotSatisfiable);
+        assert_eq!(res.header(HeaderType::ContentRange), Some("bytes */11"));
+
+        let res = TestRequest::get("/")
+            .header(HeaderType::Range, "bytes=0-4")
+            .header("If-Range", "\"2\"")
+            .send(&server);
+        assert_eq!(res.status, Status::Ok);
+        assert_eq!(res.text(), "Hello World");
+
+        let res = TestRequest::get("/stream")
+            .header(HeaderType::Range, "bytes=0-4")
+            .send(&server);
+        assert_eq!(res.status, Status::Ok);
+        assert_eq!(res.header(HeaderType::AcceptRanges), None);
+    }
+}
@@ -1,14 +1,14 @@
-//! An extension to limit the amount of requests sent from a single IP that will be handled by the server.
+//! An extension to limit the amount of requests sent from a single IP (or other key) that will be handled by the server.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::fmt;
-use std::net::IpAddr;
 use std::sync::{
     atomic::{AtomicU64, Ordering},
     RwLock,
 };
 
 use crate::internal::common::epoch;
+use crate::path::Path;
 use crate::Status;
 use crate::{
     middleware::{MiddleResult, Middleware},
@@ -17,27 +17,116 @@ use crate::{
 
 // Handler Type
 type Handler = Box<dyn Fn(&Request) -> Option<Response> + Send + Sync>;
+type KeyFn = Box<dyn Fn(&Request) -> String + Send + Sync>;
+
+/// Configuration for a [`RateLimiter`], for loading from a config file.
+/// Missing fields use the same defaults as [`RateLimiter::new`].
+/// ## Example
+/// ```toml
+/// limit = 100
+/// timeout = 60
+/// ```
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(default)]
+pub struct RateLimiterConfig {
+    /// Requests allowed per timeout period.
+    pub limit: u64,
+    /// How often to reset the counters, in seconds.
+    pub timeout: u64,
+    /// The algorithm to use, one of `fixed_window`, `sliding_window` or `token_bucket`.
+    pub algorithm: Algorithm,
+}
+
+/// The algorithm a [`RateLimiter`] uses to decide if a request is over the limit.
+/// They all allow about `limit` requests every `timeout` seconds, but handle bursts differently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Algorithm {
+    /// Counts requests in fixed periods, resetting every counter each `timeout` seconds.
+    /// This is the cheapest, but a client can send up to twice the limit around the end of a period.
+    #[default]
+    FixedWindow,
+    /// Keeps the time of each request, allowing `limit` requests in any `timeout` second period.
+    /// Uses memory proportional to the limit for each client.
+    SlidingWindow,
+    /// Each client has a bucket of `limit` tokens that refills evenly over `timeout` seconds, and each request takes one.
+    /// Allows bursts of up to `limit` requests, followed by a steady rate.
+    TokenBucket,
+}
 
 /// Limit the amount of requests handled by the server.
+///
+/// Requests are counted by a key, which is the client's IP address by default (See [`RateLimiter::key`]).
+/// Responses get `RateLimit-Limit`, `RateLimit-Remaining` and `RateLimit-Reset` headers, and responses to requests over the limit also get a `Retry-After` header.
+///
+/// Different paths can have different limits, by attaching more than one RateLimiter with [`RateLimiter::path`], or attaching one to a route with [`crate::Route::with`].
+/// Each RateLimiter counts requests separately.
+/// ## Example
+/// ```rust
+/// # use afire::{Server, Middleware, extension::{RateLimiter, RealIp}};
+/// # let mut server = Server::<()>::new("localhost", 8080);
+/// // Limit API clients by their key
+/// RateLimiter::new()
+///     .limit(100)
+///     .path("/api")
+///     .key(|req| req.headers.get("X-Api-Key").unwrap_or_default().to_owned())
+///     .attach(&mut server);
+///
+/// // And everything else by IP, through a reverse proxy
+/// RateLimiter::new()
+///     .limit(1000)
+///     .key(|req| req.real_ip().to_string())
+///     .attach(&mut server);
+/// ```
 pub struct RateLimiter {
     /// Requests Per Req_Timeout
     req_limit: u64,
 
-    /// Time of last reset
+    /// Time of last reset (ms)
     last_reset: AtomicU64,
 
     /// How often to reset the counters (sec)
     req_timeout: u64,
 
-    /// Table that maps an IP to a list of request timestamps
-    // requests: RwLock<HashMap<IpAddr, Vec<u64>>>,
-    requests: RwLock<HashMap<IpAddr, u64>>,
+    /// How requests are counted
+    algorithm: Algorithm,
+
+    /// Table that maps a key to its recent requests
+    requests: RwLock<HashMap<String, KeyState>>,
+
+    /// Gets the key to count a request under.
+    key: KeyFn,
+
+    /// Only requests under these paths are limited, or all requests if empty.
+    paths: Vec<Path>,
+
+    /// If the `RateLimit-*` and `Retry-After` headers are added.
+    headers: bool,
 
     /// Handler for when the limit is reached.
     /// If the handler returns None, the request will be processed normally.
     handler: Handler,
 }
 
+/// The requests made by one key, depending on the [`Algorithm`].
+/// All times are in milliseconds.
+#[derive(Debug)]
+enum KeyState {
+    Count(u64),
+    Log(VecDeque<u64>),
+    Bucket { tokens: f64, last: u64 },
+}
+
+/// The rate limit state of a request, stored in its extensions to add the headers to the response.
+#[derive(Debug, Clone, Copy)]
+struct RateLimitInfo {
+    limit: u64,
+    remaining: u64,
+    reset: u64,
+}
+
 impl RateLimiter {
     /// Make a new RateLimiter.
     ///
@@ -47,7 +136,11 @@ impl RateLimiter {
             last_reset: AtomicU64::new(0),
             req_limit: 10,
             req_timeout: 60,
+            algorithm: Algorithm::FixedWindow,
             requests: RwLock::new(HashMap::new()),
+            key: Box::new(|req| req.address.ip().to_string()),
+            paths: Vec::new(),
+            headers: true,
             handler: Box::new(|_| {
                 Some(
                     Response::new()
@@ -59,6 +152,23 @@ impl RateLimiter {
         }
     }
 
+    /// Make a new RateLimiter from a [`RateLimiterConfig`].
+    /// The handler is the default one, it can still be changed with [`RateLimiter::handler`].
+    /// ## Example
+    /// ```rust,no_run
+    /// # use afire::{Server, Middleware, extension::{RateLimiter, ratelimit::RateLimiterConfig}};
+    /// # let mut server = Server::<()>::new("localhost", 1234);
+    /// let config: RateLimiterConfig = serde_json::from_str(r#"{"limit": 100}"#).unwrap();
+    /// RateLimiter::from_config(&config).attach(&mut server);
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn from_config(config: &RateLimiterConfig) -> RateLimiter {
+        RateLimiter::new()
+            .limit(config.limit)
+            .timeout(config.timeout)
+            .algorithm(config.algorithm)
+    }
+
     /// Set the request limit per timeout
     /// Attach the rate limiter to a server.
     /// ## Example
@@ -114,6 +224,23 @@ impl RateLimiter {
         }
     }
 
+    /// Set the algorithm used to count requests.
+    /// Defaults to [`Algorithm::FixedWindow`].
+    /// ## Example
+    /// ```rust,no_run
+    /// # use afire::{Server, Middleware, extension::{RateLimiter, ratelimit::Algorithm}};
+    /// # let mut server = Server::<()>::new("localhost", 1234);
+    /// // Allow bursts of 20 requests, refilling at one request every 3 seconds
+    /// RateLimiter::new()
+    ///     .limit(20)
+    ///     .timeout(60)
+    ///     .algorithm(Algorithm::TokenBucket)
+    ///     .attach(&mut server);
+    /// ```
+    pub fn algorithm(self, algorithm: Algorithm) -> RateLimiter {
+        RateLimiter { algorithm, ..self }
+    }
+
     /// Define a Custom Handler for when a client has exceeded the ratelimit.
     /// If the handler returns None, the request will be processed normally.
     /// ## Example
@@ -139,42 +266,179 @@ impl RateLimiter {
         RateLimiter { handler, ..self }
     }
 
-    /// Count a request.
-    fn add_request(&self, ip: IpAddr) {
-        let mut req = self.requests.write().unwrap();
-        let count = req.get(&ip).unwrap_or(&0) + 1;
-        req.insert(ip, count);
+    /// Set how requests are grouped, by the key this returns.
+    /// Defaults to the client's IP address, which will be the address of the proxy if you are using a reverse proxy (See [`crate::extension::RealIp`]).
+    pub fn key(self, key: impl Fn(&Request) -> String + Send + Sync + 'static) -> RateLimiter {
+        RateLimiter {
+            key: Box::new(key),
+            ..self
+        }
+    }
+
+    /// Only limit requests with a path under this prefix.
+    /// Can be called more than once to limit multiple paths.
+    pub fn path(mut self, prefix: impl AsRef<str>) -> RateLimiter {
+        self.paths.push(Path::new(prefix.as_ref().to_owned()));
+        self
+    }
+
+    /// Set if the `RateLimit-Limit`, `RateLimit-Remaining`, `RateLimit-Reset` and `Retry-After` headers are added to responses.
+    /// Defaults to true.
+    pub fn headers(self, headers: bool) -> RateLimiter {
+        RateLimiter { headers, ..self }
+    }
+
+    /// Count a request made at `now` (ms), returning if it is over the limit and the state for the headers.
+    fn hit(&self, key: String, now: u64) -> (bool, RateLimitInfo) {
+        let (limit, window) = (self.req_limit, self.req_timeout * 1000);
+        let mut requests = self.requests.write().unwrap();
+        let state = requests.entry(key).or_insert_with(|| match self.algorithm {
+            Algorithm::FixedWindow => KeyState::Count(0),
+            Algorithm::SlidingWindow => KeyState::Log(VecDeque::new()),
+            Algorithm::TokenBucket => KeyState::Bucket {
+                tokens: limit as f64,
+                last: now,
+            },
+        });
+
+        let (over, remaining, reset) = match state {
+            KeyState::Count(count) => {
+                *count += 1;
+                let reset = self.last_reset.load(Ordering::Acquire) + window;
+                (
+                    *count > limit,
+                    limit.saturating_sub(*count),
+                    reset.saturating_sub(now),
+                )
+            }
+            KeyState::Log(log) => {
+                while log.front().is_some_and(|x| x + window <= now) {
+                    log.pop_front();
+                }
+
+                // Requests over the limit are not logged, so they don't keep a client limited forever
+                let over = log.len() as u64 >= limit;
+                if !over {
+                    log.push_back(now);
+                }
+
+                let reset = log.front().map_or(0, |x| (x + window).saturating_sub(now));
+                (over, limit.saturating_sub(log.len() as u64), reset)
+            }
+            KeyState::Bucket { tokens, last } => {
+                let rate = limit as f64 / window as f64;
+                *tokens = (*tokens + now.saturating_sub(*last) as f64 * rate).min(limit as f64);
+                *last = now;
+
+                let over = *tokens < 1.0;
+                if !over {
+                    *tokens -= 1.0;
+                }
+
+                // The time until the next token if over the limit, or until the bucket is full otherwise
+                let missing = if over {
+                    1.0 - *tokens
+                } else {
+                    limit as f64 - *tokens
+                };
+                let reset = if rate > 0.0 { missing / rate } else { 0.0 };
+                (over, *tokens as u64, reset.ceil() as u64)
+            }
+        };
+
+        let info = RateLimitInfo {
+            limit,
+            remaining,
+            reset: reset.div_ceil(1000),
+        };
+        (over, info)
+    }
+
+    /// Every timeout period, remove the keys that have no recent requests.
+    /// For fixed windows this resets every counter.
+    fn check_reset(&self, now: u64) {
+        let window = self.req_timeout * 1000;
+        if self.last_reset.load(Ordering::Acquire) + window <= now {
+            self.requests
+                .write()
+                .unwrap()
+                .retain(|_, x| !x.is_idle(now, window));
+            self.last_reset.store(now, Ordering::Release);
+        }
+    }
+
+    /// Checks if a request's path is limited.
+    fn in_scope(&self, req: &Request) -> bool {
+        self.paths.is_empty() || self.paths.iter().any(|x| x.match_prefix(req.path.clone()))
     }
+}
 
-    /// Check if request table needs to be cleared.
-    fn check_reset(&self) {
-        let time = epoch().as_secs();
-        if self.last_reset.load(Ordering::Acquire) + self.req_timeout <= time {
-            self.requests.write().unwrap().clear();
-            self.last_reset.store(time, Ordering::Release);
+impl KeyState {
+    /// Checks if a key's state is the same as if it had made no requests.
+    fn is_idle(&self, now: u64, window: u64) -> bool {
+        match self {
+            KeyState::Count(_) => true,
+            KeyState::Log(log) => log.back().is_none_or(|x| x + window <= now),
+            // An empty bucket refills in one window
+            KeyState::Bucket { last, .. } => last + window <= now,
         }
     }
+}
 
-    /// Check if the request limit has been reached for an ip.
-    fn is_over_limit(&self, ip: IpAddr) -> bool {
-        self.requests.read().unwrap().get(&ip).unwrap_or(&0) >= &self.req_limit
+#[cfg(feature = "serde")]
+impl Default for RateLimiterConfig {
+    fn default() -> Self {
+        Self {
+            limit: 10,
+            timeout: 60,
+            algorithm: Algorithm::FixedWindow,
+        }
     }
 }
 
 impl Middleware for RateLimiter {
     fn pre(&self, req: &mut Request) -> MiddleResult {
-        if self.is_over_limit(req.address.ip()) {
-            if let Some(i) = (self.handler)(req) {
-                return MiddleResult::Send(i);
+        if !self.in_scope(req) {
+            return MiddleResult::Continue;
+        }
+
+        let now = epoch().as_millis() as u64;
+        self.check_reset(now);
+        let (over, info) = self.hit((self.key)(req), now);
+
+        // With more than one limiter, the headers are for the one with the least requests left
+        if self.headers
+            && req
+                .extensions
+                .get::<RateLimitInfo>()
+                .is_none_or(|x| info.remaining < x.remaining)
+        {
+            req.extensions.insert(info);
+        }
+
+        if over {
+            if let Some(mut res) = (self.handler)(req) {
+                if self.headers {
+                    res.headers.add("Retry-After", info.reset.to_string());
+                }
+                return MiddleResult::Send(res);
             }
         }
 
         MiddleResult::Continue
     }
 
-    fn end(&self, req: &Request, _res: &Response) {
-        self.check_reset();
-        self.add_request(req.address.ip());
+    fn post(&self, req: &Request, res: &mut Response) -> MiddleResult {
+        if let Some(info) = req.extensions.get::<RateLimitInfo>() {
+            if !res.headers.has("RateLimit-Limit") {
+                res.headers.add("RateLimit-Limit", info.limit.to_string());
+                res.headers
+                    .add("RateLimit-Remaining", info.remaining.to_string());
+                res.headers.add("RateLimit-Reset", info.reset.to_string());
+            }
+        }
+
+        MiddleResult::Continue
     }
 }
 
@@ -191,7 +455,95 @@ impl fmt::Debug for RateLimiter {
             .field("req_limit", &self.req_limit)
             .field("req_timeout", &self.req_timeout)
             .field("last_reset", &self.last_reset)
+            .field("algorithm", &self.algorithm)
             .field("requests", &self.requests)
+            .field("paths", &self.paths)
+            .field("headers", &self.headers)
             .finish()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{Algorithm, RateLimiter};
+    use crate::{
+        testing::{TestRequest, TestServer},
+        Method, Middleware, Response, Server, Status,
+    };
+
+    #[test]
+    fn test_ratelimit() {
+        let mut server = Server::<()>::new("localhost", 8080);
+        RateLimiter::new()
+            .limit(2)
+            .path("/api")
+            .key(|req| req.headers.get("X-Key").unwrap_or_default().to_owned())
+            .attach(&mut server);
+        server.route(Method::GET, "**", |_| Response::new());
+        let server = TestServer::from(server);
+
+        let send = |path, key| TestRequest::get(path).header("X-Key", key).send(&server);
+
+        let res = send("/api", "a");
+        assert_eq!(res.status, Status::Ok);
+        assert_eq!(res.header("RateLimit-Limit"), Some("2"));
+        assert_eq!(res.header("RateLimit-Remaining"), Some("1"));
+        assert!(res.header("RateLimit-Reset").is_some());
+
+        send("/api", "a");
+        let res = send("/api/users", "a");
+        assert_eq!(res.status, Status::TooManyRequests);
+        assert_eq!(res.header("RateLimit-Remaining"), Some("0"));
+        assert!(res.header("Retry-After").is_some());
+
+        // Other keys and paths are counted separately
+        assert_eq!(send("/api", "b").status, Status::Ok);
+        let res = send("/", "a");
+        assert_eq!(res.status, Status::Ok);
+        assert_eq!(res.header("RateLimit-Limit"), None);
+    }
+
+    #[test]
+    fn test_sliding_window() {
+        let limiter = RateLimiter::new()
+            .limit(2)
+            .timeout(10)
+            .algorithm(Algorithm::SlidingWindow);
+        let hit = |now| limiter.hit("a".to_owned(), now);
+
+        assert!(!hit(0).0);
+        assert!(!hit(9_000).0);
+        let (over, info) = hit(9_500);
+        assert!(over);
+        assert_eq!((info.remaining, info.reset), (0, 1));
+
+        // Unlike a fixed window, the second request still counts after 10 seconds
+        assert!(!hit(10_000).0);
+        assert!(hit(10_500).0);
+        assert!(!hit(19_000).0);
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let limiter = RateLimiter::new()
+            .limit(2)
+            .timeout(10)
+            .algorithm(Algorithm::TokenBucket);
+        let hit = |now| limiter.hit("a".to_owned(), now);
+
+        assert!(!hit(0).0);
+        let (over, info) = hit(0);
+        assert!(!over);
+        assert_eq!((info.remaining, info.reset), (0, 10));
+
+        // One token refills every 5 seconds
+        let (over, info) = hit(1_000);
+        assert!(over);
+        assert_eq!(info.reset, 4);
+        assert!(!hit(5_000).0);
+        assert!(hit(5_000).0);
+        assert!(!hit(20_000).0);
+        assert!(!hit(20_000).0);
+        assert!(hit(20_000).0);
+    }
+}
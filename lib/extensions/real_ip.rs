@@ -1,13 +1,19 @@
 //! Methods for getting the real IP of a client through a reverse proxy.
 //!
 //! **Warning**: Make sure your reverse proxy is overwriting the specified header on the incoming requests so clients cant spoof their original Ips.
+//! Or use [`RealIp::real_ip_with`] with the addresses of your proxies, so only the hops they added are trusted.
 
-use std::net::IpAddr;
+use std::{
+    fmt::{self, Display},
+    net::IpAddr,
+    str::FromStr,
+};
 
 use crate::{HeaderType, Request};
 
 /// Trait that adds methods for getting the real IP of a client through a reverse proxy.
 /// If you are using the "X-Forwarded-For" header you can use `req.real_ip()` but if you are using a different header you will have to use `req.real_ip_header(...)`.
+/// For proxies that aren't on localhost, or to pick a different hop, use `req.real_ip_with(...)`.
 pub trait RealIp {
     /// Uses [`RealIp::real_ip_header`] with the ["X-Forwarded-For"](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/X-Forwarded-For) header.
     /// ## Example
@@ -28,25 +34,339 @@ pub trait RealIp {
 
     /// Gets the 'real IP' of a client by parsing the value of `header` into an IpAddr.
     /// If the connection is not coming from localhost, the header isn't found or the header contains an invalid IP address, the raw socket address will be returned.
+    /// This is the same as [`RealIp::real_ip_with`] with the default [`ProxyConfig`] using this header.
     ///
     /// **Warning**: Make sure your reverse proxy is overwriting the specified header on the incoming requests so clients cant spoof their original Ips.
-    fn real_ip_header(&self, header: impl Into<HeaderType>) -> IpAddr;
+    fn real_ip_header(&self, header: impl Into<HeaderType>) -> IpAddr {
+        self.real_ip_with(&ProxyConfig::new().header(header))
+    }
+
+    /// Gets the 'real IP' of a client, only trusting the forwarding headers added by the proxies in the config.
+    /// ## Example
+    /// ```rust
+    /// use afire::extension::{RealIp, real_ip::{Hop, ProxyConfig}};
+    /// # use afire::{Server, Method, Response, HeaderType};
+    ///
+    /// # fn test(server: &mut Server) {
+    /// let proxies = ProxyConfig::new()
+    ///     .trust("10.0.0.0/8")
+    ///     .header(HeaderType::Forwarded);
+    ///
+    /// server.route(Method::GET, "/", move |req| {
+    ///     let ip = req.real_ip_with(&proxies);
+    ///     Response::new().text(format!("Hello, {ip}"))
+    /// });
+    /// # }
+    /// ```
+    fn real_ip_with(&self, config: &ProxyConfig) -> IpAddr;
 }
 
-impl RealIp for Request {
-    fn real_ip_header(&self, header: impl Into<HeaderType>) -> IpAddr {
-        let ip = self.address.ip();
+/// Which proxies to trust, and how to get a client's IP from the headers they add, for [`RealIp::real_ip_with`].
+///
+/// The headers are only used if the socket address is a trusted proxy, otherwise the socket address is the client.
+/// Each proxy adds the address it got the request from to the end of the header, so the addresses are read from right to left, see [`Hop`].
+#[derive(Debug, Clone)]
+pub struct ProxyConfig {
+    trusted: Vec<Cidr>,
+    header: HeaderType,
+    hop: Hop,
+}
+
+/// Which address in a forwarding header is the client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hop {
+    /// The closest address that isn't a trusted proxy, found by walking back from the last address.
+    /// This is the default, and can't be spoofed by the client as long as all your proxies are trusted.
+    FirstUntrusted,
+    /// The address added by the proxy this many hops away, where 0 is the address added by the proxy that connected to the server.
+    /// Use this if there is always the same number of proxies in front of the server.
+    FromRight(usize),
+    /// The first address in the header.
+    /// This is the address the client says it is, so it can be spoofed.
+    Leftmost,
+}
+
+/// A range of IP addresses, like `10.0.0.0/8` or `fd00::/8`.
+/// A single address (like `192.168.1.1`) is a range with just that address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+/// Error returned when parsing an invalid [`Cidr`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidCidr(String);
+
+impl Default for ProxyConfig {
+    fn default() -> Self {
+        Self {
+            trusted: vec![
+                Cidr::new(IpAddr::from([127, 0, 0, 0]), 8),
+                Cidr::new(IpAddr::from([0, 0, 0, 0, 0, 0, 0, 1]), 128),
+            ],
+            header: HeaderType::XForwardedFor,
+            hop: Hop::FirstUntrusted,
+        }
+    }
+}
+
+impl ProxyConfig {
+    /// Creates a new config that trusts proxies on localhost and reads the `X-Forwarded-For` header.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trusts proxies in a range of addresses, like `10.0.0.0/8` or a single address like `192.168.1.5`.
+    /// Can be called more than once to trust multiple ranges.
+    /// Panics if the range is invalid.
+    pub fn trust(mut self, cidr: impl AsRef<str>) -> Self {
+        match cidr.as_ref().parse() {
+            Ok(cidr) => self.trusted.push(cidr),
+            Err(e) => panic!("{}", e),
+        }
+        self
+    }
+
+    /// Removes all the trusted proxies, including localhost.
+    pub fn trust_none(mut self) -> Self {
+        self.trusted.clear();
+        self
+    }
+
+    /// Sets the header to read addresses from.
+    /// The `Forwarded` header is parsed for its `for` parameters, other headers are read as a comma separated list of addresses (like `X-Forwarded-For`).
+    /// Defaults to `X-Forwarded-For`.
+    pub fn header(mut self, header: impl Into<HeaderType>) -> Self {
+        self.header = header.into();
+        self
+    }
+
+    /// Sets which address in the header is the client, see [`Hop`].
+    /// Defaults to [`Hop::FirstUntrusted`].
+    pub fn hop(mut self, hop: Hop) -> Self {
+        self.hop = hop;
+        self
+    }
+
+    /// Checks if an address is one of the trusted proxies.
+    pub fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.trusted.iter().any(|x| x.contains(ip))
+    }
+
+    /// Gets the client's IP from the socket address and the addresses in the forwarding headers.
+    /// Addresses that couldn't be parsed are None.
+    fn client_ip(&self, peer: IpAddr, hops: &[Option<IpAddr>]) -> IpAddr {
+        if !self.is_trusted(peer) {
+            return peer;
+        }
+
+        match self.hop {
+            Hop::FirstUntrusted => {
+                let mut ip = peer;
+                for hop in hops.iter().rev() {
+                    match hop {
+                        Some(hop) if self.is_trusted(*hop) => ip = *hop,
+                        Some(hop) => return *hop,
+                        None => break,
+                    }
+                }
+                ip
+            }
+            Hop::FromRight(n) => hops
+                .len()
+                .checked_sub(n + 1)
+                .and_then(|i| hops[i])
+                .unwrap_or(peer),
+            Hop::Leftmost => hops.first().copied().flatten().unwrap_or(peer),
+        }
+    }
+}
+
+impl Cidr {
+    /// Creates a range from an address and the number of bits in its prefix.
+    /// The prefix is clamped to the length of the address.
+    pub fn new(addr: IpAddr, prefix: u8) -> Self {
+        let max = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        Self {
+            addr,
+            prefix: prefix.min(max),
+        }
+    }
 
-        // If the connection is not coming from localhost (likely from reverse proxy) return the raw IP
-        if !ip.is_loopback() {
-            return ip;
+    /// Checks if an address is in the range.
+    /// IPv4 addresses mapped to IPv6 (`::ffff:a.b.c.d`) are checked as IPv4 addresses.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(a), IpAddr::V4(b)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(a) & mask == u32::from(b) & mask
+            }
+            (IpAddr::V6(a), IpAddr::V6(b)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(a) & mask == u128::from(b) & mask
+            }
+            _ => false,
         }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = InvalidCidr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || InvalidCidr(s.to_owned());
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+
+        let addr = addr.trim().parse::<IpAddr>().map_err(|_| err())?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(i) => i
+                .trim()
+                .parse::<u8>()
+                .ok()
+                .filter(|x| *x <= max)
+                .ok_or_else(err)?,
+            None => max,
+        };
+
+        Ok(Self::new(addr, prefix))
+    }
+}
+
+impl Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+impl Display for InvalidCidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid CIDR range `{}`", self.0)
+    }
+}
+
+impl std::error::Error for InvalidCidr {}
+
+impl RealIp for Request {
+    fn real_ip_with(&self, config: &ProxyConfig) -> IpAddr {
+        let values = self
+            .headers
+            .iter()
+            .filter(|x| x.name == config.header)
+            .flat_map(|x| x.value.split(','));
+
+        let hops = match config.header {
+            HeaderType::Forwarded => values.map(parse_forwarded).collect::<Vec<_>>(),
+            _ => values.map(|x| x.trim().parse().ok()).collect(),
+        };
+
+        config.client_ip(self.address.ip(), &hops)
+    }
+}
+
+/// Gets the address from the `for` parameter of one element of a `Forwarded` header, like `for=192.0.2.60;proto=http` or `for="[2001:db8::1]:4711"`.
+/// Returns None for obfuscated identifiers like `unknown` or `_hidden`.
+fn parse_forwarded(element: &str) -> Option<IpAddr> {
+    let value = element.split(';').find_map(|x| {
+        let (name, value) = x.split_once('=')?;
+        name.trim()
+            .eq_ignore_ascii_case("for")
+            .then(|| value.trim().trim_matches('"'))
+    })?;
+
+    // IPv6 addresses are in brackets, and either kind can have a port
+    if let Some(v6) = value.strip_prefix('[') {
+        return v6.split(']').next()?.parse().ok();
+    }
+
+    let addr = value.split(':').next()?;
+    addr.parse().ok()
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::IpAddr;
+
+    use super::{parse_forwarded, Cidr, Hop, ProxyConfig};
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_cidr() {
+        let cidr = "10.0.0.0/8".parse::<Cidr>().unwrap();
+        assert!(cidr.contains(ip("10.1.2.3")));
+        assert!(cidr.contains(ip("::ffff:10.1.2.3")));
+        assert!(!cidr.contains(ip("11.0.0.1")));
+
+        let cidr = "fd00::/8".parse::<Cidr>().unwrap();
+        assert!(cidr.contains(ip("fd12::1")));
+        assert!(!cidr.contains(ip("fe80::1")));
+
+        assert!("0.0.0.0/0".parse::<Cidr>().unwrap().contains(ip("1.2.3.4")));
+        assert!("1.2.3.4".parse::<Cidr>().unwrap().contains(ip("1.2.3.4")));
+        assert!("1.2.3.4/33".parse::<Cidr>().is_err());
+        assert!("nope/8".parse::<Cidr>().is_err());
+    }
+
+    #[test]
+    fn test_forwarded() {
+        assert_eq!(
+            parse_forwarded("for=192.0.2.60;proto=http"),
+            Some(ip("192.0.2.60"))
+        );
+        assert_eq!(
+            parse_forwarded(" For=\"[2001:db8::1]:4711\""),
+            Some(ip("2001:db8::1"))
+        );
+        assert_eq!(
+            parse_forwarded("by=1.1.1.1;for=1.2.3.4:80"),
+            Some(ip("1.2.3.4"))
+        );
+        assert_eq!(parse_forwarded("for=unknown"), None);
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let hops = [
+            Some(ip("1.1.1.1")),
+            Some(ip("2.2.2.2")),
+            Some(ip("10.0.0.2")),
+        ];
+        let peer = ip("10.0.0.1");
+        let config = ProxyConfig::new().trust("10.0.0.0/8");
+
+        assert_eq!(config.client_ip(peer, &hops), ip("2.2.2.2"));
+        assert_eq!(config.client_ip(ip("3.3.3.3"), &hops), ip("3.3.3.3"));
+        assert_eq!(
+            config.clone().hop(Hop::Leftmost).client_ip(peer, &hops),
+            ip("1.1.1.1")
+        );
+        assert_eq!(
+            config.clone().hop(Hop::FromRight(1)).client_ip(peer, &hops),
+            ip("2.2.2.2")
+        );
+        assert_eq!(
+            config.clone().hop(Hop::FromRight(5)).client_ip(peer, &hops),
+            peer
+        );
 
-        // If the 'X-Forwarded-For' Header is present and its value is a valid IP, return it
-        // Otherwise return the socket address
-        self.headers
-            .get(header.into())
-            .and_then(|x| x.parse().ok())
-            .unwrap_or(ip)
+        // Invalid hops stop the walk at the last trusted proxy
+        let hops = [Some(ip("1.1.1.1")), None, Some(ip("10.0.0.2"))];
+        assert_eq!(config.client_ip(peer, &hops), ip("10.0.0.2"));
+        assert_eq!(
+            ProxyConfig::new()
+                .trust_none()
+                .client_ip(ip("127.0.0.1"), &hops),
+            ip("127.0.0.1")
+        );
     }
 }
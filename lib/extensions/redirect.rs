@@ -0,0 +1,213 @@
+//! Redirect clients to another URL, with the right status code for the kind of redirect.
+
+use crate::{
+    extensions::path_normalizer::remove_dot_segments, HeaderType, Request, Response, Status,
+};
+
+/// The kind of a redirect, which picks its status code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedirectType {
+    /// `301 Moved Permanently`.
+    /// Clients may change the method to GET when following it, so use [`RedirectType::Permanent`] for requests with a body.
+    MovedPermanently,
+    /// `302 Found`, a temporary redirect.
+    /// Clients may change the method to GET when following it, so use [`RedirectType::Temporary`] for requests with a body.
+    Found,
+    /// `303 See Other`.
+    /// The client always follows it with a GET request, so this is used to send users to a result page after submitting a form.
+    SeeOther,
+    /// `307 Temporary Redirect`.
+    /// The client keeps the method and body when following it.
+    Temporary,
+    /// `308 Permanent Redirect`.
+    /// The client keeps the method and body when following it.
+    Permanent,
+}
+
+/// Trait that adds a method for redirecting to a location to [`Response`].
+pub trait RedirectResponse {
+    /// Turns the response into a redirect to the location, with the status for the kind of redirect.
+    /// The location is sent as is, use [`Redirect`] to resolve relative paths or keep the request's query.
+    /// ## Example
+    /// ```rust
+    /// # use afire::{Server, Method, Response, extension::redirect::{RedirectResponse, RedirectType}};
+    /// # fn test(server: &mut Server) {
+    /// server.route(Method::GET, "/old", |_req| {
+    ///     Response::new().redirect(RedirectType::Permanent, "/new")
+    /// });
+    /// # }
+    /// ```
+    fn redirect(self, redirect_type: RedirectType, location: impl AsRef<str>) -> Self;
+}
+
+/// A redirect to a target that can be relative to the request's path, and can keep the request's query string.
+///
+/// Targets with a scheme (`https://example.com`) or starting with `/` are used as is.
+/// Other targets are resolved against the request's path like a link on the page would be, so `../list` from `/users/5/edit` redirects to `/users/list`.
+/// ## Example
+/// ```rust
+/// # use afire::{Server, Method, Response, extension::redirect::Redirect};
+/// # fn test(server: &mut Server) {
+/// // `/docs/intro?lang=en` is redirected to `/docs/getting-started?lang=en`
+/// server.route(Method::GET, "/docs/intro", |req| {
+///     Redirect::to("getting-started").permanent().keep_query().response(req)
+/// });
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Redirect {
+    target: String,
+    redirect_type: RedirectType,
+    keep_query: bool,
+}
+
+impl RedirectType {
+    /// Gets the status code of this kind of redirect.
+    pub fn status(&self) -> Status {
+        match self {
+            RedirectType::MovedPermanently => Status::MovedPermanently,
+            RedirectType::Found => Status::Found,
+            RedirectType::SeeOther => Status::SeeOther,
+            RedirectType::Temporary => Status::TemporaryRedirect,
+            RedirectType::Permanent => Status::PermanentRedirect,
+        }
+    }
+}
+
+impl RedirectResponse for Response {
+    fn redirect(self, redirect_type: RedirectType, location: impl AsRef<str>) -> Self {
+        self.status(redirect_type.status())
+            .header(HeaderType::Location, location.as_ref())
+    }
+}
+
+impl Redirect {
+    /// Creates a `302 Found` redirect to the target.
+    pub fn to(target: impl AsRef<str>) -> Self {
+        Self {
+            target: target.as_ref().to_owned(),
+            redirect_type: RedirectType::Found,
+            keep_query: false,
+        }
+    }
+
+    /// Sets the kind of redirect, see [`RedirectType`].
+    pub fn redirect_type(mut self, redirect_type: RedirectType) -> Self {
+        self.redirect_type = redirect_type;
+        self
+    }
+
+    /// Makes this a `308 Permanent Redirect`, which keeps the method.
+    pub fn permanent(self) -> Self {
+        self.redirect_type(RedirectType::Permanent)
+    }
+
+    /// Makes this a `307 Temporary Redirect`, which keeps the method.
+    pub fn temporary(self) -> Self {
+        self.redirect_type(RedirectType::Temporary)
+    }
+
+    /// Makes this a `303 See Other` redirect, which the client follows with a GET.
+    pub fn see_other(self) -> Self {
+        self.redirect_type(RedirectType::SeeOther)
+    }
+
+    /// Adds the request's query string to the target, exactly as the client sent it.
+    /// If the target has its own query, the request's parameters are added after it.
+    pub fn keep_query(mut self) -> Self {
+        self.keep_query = true;
+        self
+    }
+
+    /// Gets the location to redirect the request to.
+    pub fn location(&self, req: &Request) -> String {
+        let (rest, fragment) = match self.target.split_once('#') {
+            Some((rest, fragment)) => (rest, Some(fragment)),
+            None => (self.target.as_str(), None),
+        };
+        let (path, query) = match rest.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (rest, None),
+        };
+
+        let mut location = if path.contains("://") || path.starts_with('/') {
+            path.to_owned()
+        } else if path.is_empty() {
+            req.path.to_owned()
+        } else {
+            let base = req.path.rsplit_once('/').map_or("", |x| x.0);
+            remove_dot_segments(&format!("{base}/{path}"))
+        };
+
+        let request_query = req.raw_query().filter(|_| self.keep_query);
+        let query = query
+            .into_iter()
+            .chain(request_query)
+            .filter(|x| !x.is_empty())
+            .collect::<Vec<_>>();
+        if !query.is_empty() {
+            location.push('?');
+            location.push_str(&query.join("&"));
+        }
+
+        if let Some(fragment) = fragment {
+            location.push('#');
+            location.push_str(fragment);
+        }
+
+        location
+    }
+
+    /// Creates the redirect response for a request.
+    pub fn response(&self, req: &Request) -> Response {
+        Response::new().redirect(self.redirect_type, self.location(req))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Redirect, RedirectResponse, RedirectType};
+    use crate::{
+        testing::{TestRequest, TestServer},
+        Method, Response, Server, Status,
+    };
+
+    #[test]
+    fn test_redirect() {
+        let mut server = Server::<()>::new("localhost", 8080);
+        server.route(Method::GET, "/old", |_| {
+            Response::new().redirect(RedirectType::SeeOther, "/new")
+        });
+        server.route(Method::GET, "/users/{id}/edit", |req| {
+            let target = req.query.get("to").unwrap_or_default();
+            Redirect::to(target).permanent().keep_query().response(req)
+        });
+        let server = TestServer::from(server);
+
+        let res = TestRequest::get("/old").send(&server);
+        assert_eq!(res.status, Status::SeeOther);
+        assert_eq!(res.header("Location"), Some("/new"));
+
+        for (to, expected) in [
+            ("..%2Flist", "/users/list?to=..%2Flist"),
+            (
+                "save%3Fa%3Db%23top",
+                "/users/5/save?a=b&to=save%3Fa%3Db%23top#top",
+            ),
+            ("%2Fhome", "/home?to=%2Fhome"),
+            // The query is kept exactly as the client sent it
+            (
+                "%2Fhome&name=a+b%20c&flag",
+                "/home?to=%2Fhome&name=a+b%20c&flag",
+            ),
+            (
+                "https%3A%2F%2Fexample.com",
+                "https://example.com?to=https%3A%2F%2Fexample.com",
+            ),
+        ] {
+            let res = TestRequest::get(format!("/users/5/edit?to={to}")).send(&server);
+            assert_eq!(res.status, Status::PermanentRedirect);
+            assert_eq!(res.header("Location"), Some(expected), "{to}");
+        }
+    }
+}
@@ -0,0 +1,230 @@
+//! Keep the last few requests in memory, for answering "what just happened" without any log infrastructure.
+//! The history can be read from route handlers, or served as JSON on a debug path.
+
+use std::{
+    collections::VecDeque,
+    fmt::Write,
+    net::SocketAddr,
+    rc::Rc,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    error::Result,
+    extensions::version::json_string,
+    internal::common::ForceLock,
+    middleware::{MiddleResult, Middleware},
+    path::normalize_path,
+    Content, HeaderType, Method, Request, Response,
+};
+
+/// Keeps a ring buffer of the last requests the server handled.
+///
+/// Each entry has the method, path, status, how long the request took, a summary of the error if there was one, and the request id.
+/// The history can be read with a [`History`] handle, or served as JSON (newest first) by setting a [`RequestHistory::path`].
+///
+/// Attach it after other middleware so its timing covers them, and so requests that other middleware respond to are still recorded.
+///
+/// ## Example
+/// ```rust
+/// # use afire::{Server, Middleware, Method, Response, extension::{RequestHistory, RequestId}};
+/// let mut server = Server::<()>::new("localhost", 8080);
+/// RequestId::new("X-Request-Id").attach(&mut server);
+///
+/// let history = RequestHistory::new(100).path("/debug/requests");
+/// let records = history.history();
+/// history.attach(&mut server);
+///
+/// server.route(Method::GET, "/errors", move |_req| {
+///     let errors = records.entries().into_iter().filter(|x| x.status >= 500).count();
+///     Response::new().text(format!("{errors} recent errors"))
+/// });
+/// ```
+pub struct RequestHistory {
+    history: History,
+    path: Option<String>,
+    request_id: HeaderType,
+}
+
+/// A handle to the requests recorded by a [`RequestHistory`].
+/// Cloning this gives another handle to the same history.
+#[derive(Clone)]
+pub struct History(Arc<HistoryInner>);
+
+struct HistoryInner {
+    capacity: usize,
+    entries: Mutex<VecDeque<RequestRecord>>,
+}
+
+/// A request recorded by a [`RequestHistory`].
+#[derive(Debug, Clone)]
+pub struct RequestRecord {
+    /// When the response was sent.
+    pub time: SystemTime,
+    /// The request method.
+    pub method: Method,
+    /// The request path, after any rewrites.
+    pub path: String,
+    /// The response status code.
+    pub status: u16,
+    /// How long it took from when the [`RequestHistory`] middleware first saw the request to when the response was sent.
+    /// None if an earlier middleware responded before it ran.
+    pub duration: Option<Duration>,
+    /// The error that caused an error response, like a handler panic or a missing route.
+    pub error: Option<String>,
+    /// The value of the request id header, see [`RequestHistory::request_id`].
+    pub request_id: Option<String>,
+    /// The address of the client.
+    pub address: SocketAddr,
+}
+
+/// When the current request on a connection started, stored in the socket storage.
+struct Started(Instant);
+
+/// The error from the current request on a connection, stored in the socket storage.
+struct Failed(String);
+
+impl RequestHistory {
+    /// Create a new RequestHistory, keeping the last `capacity` requests.
+    /// Panics if `capacity` is 0.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "Capacity must be greater than 0");
+        Self {
+            history: History(Arc::new(HistoryInner {
+                capacity,
+                entries: Mutex::new(VecDeque::with_capacity(capacity)),
+            })),
+            path: None,
+            request_id: HeaderType::Custom("X-Request-Id".to_owned()),
+        }
+    }
+
+    /// Serve the history as JSON on this path, newest first.
+    /// By default it is not served.
+    ///
+    /// The history includes paths and errors from every client, so make sure this path is not public.
+    pub fn path(self, path: impl AsRef<str>) -> Self {
+        Self {
+            path: Some(normalize_path(path.as_ref().to_owned())),
+            ..self
+        }
+    }
+
+    /// Set the header to get the request id from.
+    /// Defaults to `X-Request-Id`, which can be added with the [`crate::extension::RequestId`] extension.
+    pub fn request_id(self, header: impl Into<HeaderType>) -> Self {
+        Self {
+            request_id: header.into(),
+            ..self
+        }
+    }
+
+    /// Gets a handle to the history, to read it from route handlers or other threads.
+    pub fn history(&self) -> History {
+        self.history.clone()
+    }
+}
+
+impl History {
+    /// Gets the recorded requests, oldest first.
+    pub fn entries(&self) -> Vec<RequestRecord> {
+        self.0.entries.force_lock().iter().cloned().collect()
+    }
+
+    /// Gets the most recent recorded request.
+    pub fn last(&self) -> Option<RequestRecord> {
+        self.0.entries.force_lock().back().cloned()
+    }
+
+    /// The max number of requests that are kept.
+    pub fn capacity(&self) -> usize {
+        self.0.capacity
+    }
+
+    /// Removes all the recorded requests.
+    pub fn clear(&self) {
+        self.0.entries.force_lock().clear();
+    }
+
+    fn push(&self, record: RequestRecord) {
+        let mut entries = self.0.entries.force_lock();
+        if entries.len() >= self.0.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(record);
+    }
+
+    /// Encodes the history as a JSON array, newest first.
+    fn json(&self) -> String {
+        let mut out = String::from("[");
+        for (i, record) in self.0.entries.force_lock().iter().rev().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+
+            let time = record
+                .time
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis();
+            let duration = record.duration.map_or_else(
+                || "null".to_owned(),
+                |x| format!("{:.3}", x.as_secs_f64() * 1000.0),
+            );
+            let _ = write!(
+                out,
+                r#"{{"time":{time},"method":{},"path":{},"status":{},"duration_ms":{duration},"error":{},"request_id":{},"address":{}}}"#,
+                json_string(Some(&record.method.to_string())),
+                json_string(Some(&record.path)),
+                record.status,
+                json_string(record.error.as_deref()),
+                json_string(record.request_id.as_deref()),
+                json_string(Some(&record.address.to_string())),
+            );
+        }
+        out.push(']');
+        out
+    }
+}
+
+impl Middleware for RequestHistory {
+    fn pre(&self, req: &mut Request) -> MiddleResult {
+        req.socket.insert_data(Started(Instant::now()));
+        req.socket.remove_data::<Failed>();
+
+        match &self.path {
+            Some(path) if req.method == Method::GET && req.path.trim_matches('/') == path => {
+                MiddleResult::Send(
+                    Response::new()
+                        .text(self.history.json())
+                        .content(Content::JSON),
+                )
+            }
+            _ => MiddleResult::Continue,
+        }
+    }
+
+    fn post_raw(&self, req: Result<Rc<Request>>, res: &mut Result<Response>) -> MiddleResult {
+        if let (Ok(req), Err(e)) = (req, res) {
+            req.socket.insert_data(Failed(e.to_string()));
+        }
+
+        MiddleResult::Continue
+    }
+
+    fn end(&self, req: &Request, res: &Response) {
+        let started = req.socket.remove_data::<Started>();
+        let error = req.socket.remove_data::<Failed>();
+        self.history.push(RequestRecord {
+            time: SystemTime::now(),
+            method: req.method,
+            path: req.path.clone(),
+            status: res.status.code(),
+            duration: started.map(|x| x.0.elapsed()),
+            error: error.map(|x| x.0.clone()),
+            request_id: req.headers.get(&self.request_id).map(ToOwned::to_owned),
+            address: req.address,
+        });
+    }
+}
@@ -10,12 +10,17 @@ use crate::{
 
 /// Add an id to every incoming Request
 ///
-/// The ID is just incremented on each request to not have to worry about collisions
+/// The ID is just incremented on each request to not have to worry about collisions.
+/// It is also stored in the request's [extensions](Request::extensions) as an [`Id`], which is included in the server's error logs.
 pub struct RequestId {
     id_header: HeaderType,
     id: AtomicUsize,
 }
 
+/// The ID given to a request by [`RequestId`], stored in the request's extensions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Id(pub usize);
+
 impl RequestId {
     /// Create a new RequestId Middleware
     /// ## Example
@@ -40,10 +45,9 @@ impl RequestId {
 
 impl Middleware for RequestId {
     fn pre(&self, req: &mut Request) -> MiddleResult {
-        req.headers.add(
-            &self.id_header,
-            self.id.fetch_add(1, Ordering::Relaxed).to_string(),
-        );
+        let id = self.id.fetch_add(1, Ordering::Relaxed);
+        req.headers.add(&self.id_header, id.to_string());
+        req.extensions.insert(Id(id));
 
         MiddleResult::Continue
     }
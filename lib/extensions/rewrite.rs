@@ -0,0 +1,123 @@
+//! Rewrite request paths before they are routed.
+//! Useful for stripping a deployment prefix or mapping legacy URLs to new routes.
+
+use crate::{
+    middleware::{MiddleResult, Middleware},
+    Request,
+};
+
+type RewriteFn = Box<dyn Fn(&mut Request) + Send + Sync>;
+
+/// Middleware to rewrite request paths before routing.
+/// The rules are applied in the order they were added, and the router sees the final path.
+/// The path the client requested is still available with [`Request::original_path`].
+///
+/// Middleware run in the reverse order they were attached, so attach this after any middleware that should see the rewritten path.
+///
+/// ## Example
+/// ```rust
+/// # use afire::{Method, Middleware, Response, Server, extension::Rewrite};
+/// # fn test(server: &mut Server) {
+/// Rewrite::new()
+///     // `/app/users` is routed as `/users`
+///     .strip_prefix("/app")
+///     // `/old-about.html` is routed as `/about`
+///     .path("/old-about.html", "/about")
+///     .attach(server);
+///
+/// server.route(Method::GET, "/about", |_req| Response::new().text("About"));
+/// # }
+/// ```
+pub struct Rewrite {
+    rules: Vec<Rule>,
+}
+
+enum Rule {
+    /// Remove a leading path prefix.
+    StripPrefix(String),
+    /// Replace one exact path with another.
+    Path(String, String),
+    /// Run a custom function on the request.
+    Custom(RewriteFn),
+}
+
+impl Rewrite {
+    /// Create a new Rewrite middleware with no rules.
+    pub fn new() -> Self {
+        Self { rules: Vec::new() }
+    }
+
+    /// Remove a prefix from the start of request paths.
+    /// The prefix only matches whole segments, so stripping `/app` changes `/app/users` to `/users` and `/app` to `/`, but leaves `/apple` alone.
+    pub fn strip_prefix(mut self, prefix: impl AsRef<str>) -> Self {
+        let prefix = prefix.as_ref().trim_end_matches('/');
+        self.rules.push(Rule::StripPrefix(format!(
+            "/{}",
+            prefix.trim_start_matches('/')
+        )));
+        self
+    }
+
+    /// Rewrite requests for the exact path `from` to `to`.
+    /// `to` can include a query string, which will replace the request's query.
+    pub fn path(mut self, from: impl AsRef<str>, to: impl AsRef<str>) -> Self {
+        self.rules
+            .push(Rule::Path(from.as_ref().to_owned(), to.as_ref().to_owned()));
+        self
+    }
+
+    /// Add a custom rewrite rule.
+    /// Use [`Request::rewrite_path`] to change the path, or set the method and headers directly.
+    /// ## Example
+    /// ```rust
+    /// # use afire::{Method, extension::Rewrite};
+    /// // Route HEAD requests for the legacy API as GET requests
+    /// let rewrite = Rewrite::new().with(|req| {
+    ///     if req.method == Method::HEAD && req.path.starts_with("/api/v1") {
+    ///         req.method = Method::GET;
+    ///     }
+    /// });
+    /// ```
+    pub fn with(mut self, rule: impl Fn(&mut Request) + Send + Sync + 'static) -> Self {
+        self.rules.push(Rule::Custom(Box::new(rule)));
+        self
+    }
+}
+
+impl Rule {
+    fn apply(&self, req: &mut Request) {
+        match self {
+            Rule::StripPrefix(prefix) => {
+                let rest = match req.path.strip_prefix(prefix.as_str()) {
+                    Some(rest) if rest.is_empty() || rest.starts_with('/') => rest,
+                    _ => return,
+                };
+
+                let path = format!("/{}", rest.trim_start_matches('/'));
+                req.rewrite_path(path);
+            }
+            Rule::Path(from, to) => {
+                if req.path == *from {
+                    req.rewrite_path(to);
+                }
+            }
+            Rule::Custom(rule) => rule(req),
+        }
+    }
+}
+
+impl Middleware for Rewrite {
+    fn pre(&self, req: &mut Request) -> MiddleResult {
+        for rule in &self.rules {
+            rule.apply(req);
+        }
+
+        MiddleResult::Continue
+    }
+}
+
+impl Default for Rewrite {
+    fn default() -> Self {
+        Self::new()
+    }
+}
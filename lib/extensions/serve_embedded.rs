@@ -0,0 +1,219 @@
+//! Serve static files that are embedded in the binary, for single-binary deployments without a data directory.
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    rc::Rc,
+};
+
+use crate::{
+    error::{HandleError, Result},
+    extensions::serve_static::etag_matches,
+    middleware::{MiddleResult, Middleware},
+    mime,
+    path::normalize_path,
+    Error, HeaderType, Method, Request, Response, Status,
+};
+
+/// Serve files that were compiled into the binary, with something like [`include_bytes!`] or the `include_dir` crate.
+///
+/// Files are looked up by their path relative to [`ServeEmbedded::path`], with `index.html` served for directories like [`crate::extension::ServeStatic`].
+/// Content types come from the file extension, using the shared [`crate::mime`] registry and any types added with [`ServeEmbedded::mime_type`].
+/// Each file gets an `ETag` from a hash of its contents, so `If-None-Match` requests get a `304 Not Modified`.
+///
+/// Routes take priority, so only `GET` and `HEAD` requests that don't match a route are served.
+///
+/// ## Example
+/// ```rust
+/// # use afire::{Server, Middleware, extension::ServeEmbedded};
+/// # let mut server = Server::<()>::new("localhost", 8080);
+/// ServeEmbedded::new([
+///     // Usually `include_bytes!("../web/index.html")`
+///     ("index.html", &b"<script src=/app.js></script>"[..]),
+///     ("app.js", &b"console.log('Hello')"[..]),
+/// ])
+/// .spa_fallback("index.html")
+/// .attach(&mut server);
+/// ```
+pub struct ServeEmbedded {
+    serve_path: String,
+    files: HashMap<String, Asset>,
+    types: Vec<(String, String)>,
+    spa_fallback: Option<String>,
+}
+
+struct Asset {
+    data: &'static [u8],
+    etag: String,
+}
+
+impl ServeEmbedded {
+    /// Create a new embedded file server from pairs of paths and file contents.
+    /// Paths are relative to the served path, and a leading `/` or `./` is ignored.
+    pub fn new<T: AsRef<str>>(files: impl IntoIterator<Item = (T, &'static [u8])>) -> Self {
+        files.into_iter().fold(
+            Self {
+                serve_path: String::new(),
+                files: HashMap::new(),
+                types: Vec::new(),
+                spa_fallback: None,
+            },
+            |this, (path, data)| this.file(path, data),
+        )
+    }
+
+    /// Add a file.
+    /// Replaces any file already at the same path.
+    pub fn file(mut self, path: impl AsRef<str>, data: &'static [u8]) -> Self {
+        let mut hasher = DefaultHasher::new();
+        data.hash(&mut hasher);
+        let etag = format!("\"{:x}-{:x}\"", data.len(), hasher.finish());
+
+        self.files
+            .insert(asset_key(path.as_ref()), Asset { data, etag });
+        self
+    }
+
+    /// Set the path to serve the files on.
+    /// Defaults to `/` (root).
+    pub fn path(self, path: impl AsRef<str>) -> Self {
+        Self {
+            serve_path: normalize_path(path.as_ref().to_owned()),
+            ..self
+        }
+    }
+
+    /// Add a MIME type for a file extension, with or without a leading dot.
+    /// Types added here only apply to this server, to add a type for the whole process use [`crate::mime::register`].
+    pub fn mime_type(mut self, extension: impl AsRef<str>, mime: impl AsRef<str>) -> Self {
+        self.types.push((
+            extension.as_ref().trim_start_matches('.').to_owned(),
+            mime.as_ref().to_owned(),
+        ));
+        self
+    }
+
+    /// Serve a file for client-side routes in single-page apps, like [`crate::extension::ServeStatic::spa_fallback`].
+    /// Requests for paths that aren't embedded and don't have a file extension are sent this file instead of a 404.
+    pub fn spa_fallback(self, path: impl AsRef<str>) -> Self {
+        Self {
+            spa_fallback: Some(asset_key(path.as_ref())),
+            ..self
+        }
+    }
+
+    /// Finds the file for a request path, or None if the path is outside of the served path or there is no file for it.
+    fn find(&self, path: &str) -> Option<(&str, &Asset)> {
+        let path = path.trim_start_matches('/');
+        let path = match path.strip_prefix(self.serve_path.as_str()) {
+            Some(i) if self.serve_path.is_empty() || i.is_empty() || i.starts_with('/') => i,
+            _ => return None,
+        };
+
+        let key = asset_key(path);
+        let name = key.rsplit('/').next().unwrap_or_default();
+        let index = match key.as_str() {
+            "" => "index.html".to_owned(),
+            _ => format!("{key}/index.html"),
+        };
+
+        let fallback = self.spa_fallback.as_deref().filter(|_| !name.contains('.'));
+        [key.as_str(), index.as_str()]
+            .iter()
+            .copied()
+            .chain(fallback)
+            .find_map(|x| self.files.get_key_value(x))
+            .map(|(k, v)| (k.as_str(), v))
+    }
+
+    /// Gets the content type of a file from its extension.
+    fn content_type(&self, path: &str) -> String {
+        let ext = match path.rsplit_once('.') {
+            Some((_, ext)) if !ext.contains('/') => ext,
+            _ => "",
+        };
+
+        self.types
+            .iter()
+            .find(|x| x.0.eq_ignore_ascii_case(ext))
+            .map(|x| x.1.as_str())
+            .or_else(|| mime::from_extension(ext))
+            .map_or_else(|| "application/octet-stream".to_owned(), mime::content_type)
+    }
+}
+
+/// Normalizes a file path for looking up files.
+/// Ex: `./assets\app.js` => `assets/app.js`
+fn asset_key(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let path = path.trim_start_matches("./");
+    normalize_path(path.to_owned())
+}
+
+impl Middleware for ServeEmbedded {
+    fn post_raw(&self, req: Result<Rc<Request>>, res: &mut Result<Response>) -> MiddleResult {
+        let req = match req {
+            Ok(req) if matches!(req.method, Method::GET | Method::HEAD) => req,
+            _ => return MiddleResult::Continue,
+        };
+
+        let path = match res {
+            Err(Error::Handle(e)) => match &**e {
+                HandleError::NotFound(_, i) | HandleError::MethodNotAllowed(_, i, _) => i,
+                _ => return MiddleResult::Continue,
+            },
+            _ => return MiddleResult::Continue,
+        };
+
+        let (name, asset) = match self.find(path) {
+            Some(i) => i,
+            None => return MiddleResult::Continue,
+        };
+
+        if let Some(i) = req.headers.get("If-None-Match") {
+            if etag_matches(i, &asset.etag) {
+                *res = Ok(Response::empty(Status::NotModified).header("ETag", &asset.etag));
+                return MiddleResult::Continue;
+            }
+        }
+
+        let new_res = Response::new()
+            .header(HeaderType::ContentType, self.content_type(name))
+            .header("ETag", &asset.etag);
+        *res = Ok(match req.method {
+            Method::HEAD => new_res
+                .bytes(&[])
+                .header(HeaderType::ContentLength, asset.data.len().to_string()),
+            _ => new_res.bytes(asset.data),
+        });
+
+        MiddleResult::Continue
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ServeEmbedded;
+
+    #[test]
+    fn test_find() {
+        let serve = ServeEmbedded::new([
+            ("/index.html", &b"root"[..]),
+            ("./docs/index.html", &b"docs"[..]),
+            ("docs\\guide.md", &b"guide"[..]),
+        ])
+        .path("/static");
+
+        let find = |path| serve.find(path).map(|x| x.1.data);
+        assert_eq!(find("/static"), Some(&b"root"[..]));
+        assert_eq!(find("/static/"), Some(&b"root"[..]));
+        assert_eq!(find("/static/docs"), Some(&b"docs"[..]));
+        assert_eq!(find("/static/docs/guide.md"), Some(&b"guide"[..]));
+        assert_eq!(find("/staticx/docs"), None);
+        assert_eq!(find("/static/app"), None);
+
+        let serve = serve.spa_fallback("index.html");
+        assert_eq!(serve.find("/static/app").map(|x| x.0), Some("index.html"));
+        assert_eq!(serve.find("/static/app.js").map(|x| x.0), None);
+    }
+}
@@ -1,17 +1,59 @@
 //! Serve Static Content from the file system.
 
-use std::{borrow::Cow, fs::File, rc::Rc};
+#[cfg(feature = "serde")]
+use std::collections::BTreeMap;
+use std::{
+    borrow::Cow,
+    fs::{File, Metadata},
+    rc::Rc,
+    time::UNIX_EPOCH,
+};
 
 use crate::{
     error::{HandleError, Result},
     middleware::{MiddleResult, Middleware},
+    mime,
     path::normalize_path,
-    Error, HeaderType, Request, Response, Status,
+    Error, HeaderType, Method, Request, Response, Status,
 };
 
+pub use crate::mime::{get_type, MIME, TYPES};
+
 type SSMiddleware = Box<dyn Fn(Rc<Request>, &mut Response, &mut bool) + Send + Sync>;
 
+/// Configuration for [`ServeStatic`], for loading from a config file.
+/// Only `data_dir` is required, the path defaults to `/`.
+/// ## Example
+/// ```toml
+/// data_dir = "web/static"
+/// path = "/static"
+/// disabled_files = ["index.scss"]
+/// mime_types = { "3gp" = "video/3gpp" }
+/// spa_fallback = "/index.html"
+/// ```
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct ServeStaticConfig {
+    /// Directory to serve files from.
+    pub data_dir: String,
+    /// Path to serve the files on.
+    #[serde(default = "default_serve_path")]
+    pub path: String,
+    /// Files that will not be served (relative from the data dir).
+    #[serde(default)]
+    pub disabled_files: Vec<String>,
+    /// Extra MIME types, mapping file extensions to MIME types.
+    #[serde(default)]
+    pub mime_types: BTreeMap<String, String>,
+    /// File to serve for client-side routes, see [`ServeStatic::spa_fallback`].
+    #[serde(default)]
+    pub spa_fallback: Option<String>,
+}
+
 /// Serve Static Content
+///
+/// Files are served with an `ETag` header, built from the file's size and modification time.
+/// If a request's `If-None-Match` header matches the current ETag, a `304 Not Modified` response is sent instead of the file.
 pub struct ServeStatic {
     /// Path to serve static content on
     ///
@@ -34,6 +76,10 @@ pub struct ServeStatic {
 
     /// MIME Types
     pub types: Vec<(String, String)>,
+
+    /// File served for unmatched GET requests that aren't for a static asset (relative from data dir).
+    /// See [`ServeStatic::spa_fallback`].
+    pub spa_fallback: Option<String>,
 }
 
 impl Middleware for ServeStatic {
@@ -49,7 +95,7 @@ impl Middleware for ServeStatic {
 
         let path = match res {
             Err(Error::Handle(e)) => match &**e {
-                HandleError::NotFound(_, i) => i,
+                HandleError::NotFound(_, i) | HandleError::MethodNotAllowed(_, i, _) => i,
                 _ => return MiddleResult::Continue,
             },
             _ => return MiddleResult::Continue,
@@ -97,6 +143,32 @@ impl ServeStatic {
                     .header(HeaderType::ContentType, "text/plain")
             },
             types: Vec::new(),
+            spa_fallback: None,
+        }
+    }
+
+    /// Make a new Static File Server from a [`ServeStaticConfig`].
+    /// ## Example
+    /// ```rust,no_run
+    /// # use afire::{Server, Middleware, extension::{ServeStatic, serve_static::ServeStaticConfig}};
+    /// # let mut server = Server::<()>::new("localhost", 8080);
+    /// let config: ServeStaticConfig =
+    ///     serde_json::from_str(r#"{"data_dir": "data/static", "path": "/static"}"#).unwrap();
+    /// ServeStatic::from_config(&config).attach(&mut server);
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn from_config(config: &ServeStaticConfig) -> Self {
+        let types = config
+            .mime_types
+            .iter()
+            .map(|(k, v)| (k.trim_start_matches('.').to_owned(), v.to_owned()))
+            .collect();
+
+        Self {
+            disabled_files: config.disabled_files.clone(),
+            types,
+            spa_fallback: config.spa_fallback.clone(),
+            ..Self::new(&config.data_dir).path(&config.path)
         }
     }
 
@@ -188,12 +260,54 @@ impl ServeStatic {
         }
     }
 
+    /// Serve a file for client-side routes in single-page apps.
+    /// GET requests for paths that don't exist and don't look like a static asset (their last segment has no file extension) are sent this file with a `200 OK`, instead of the not found page.
+    /// So with a router in the browser, `/dashboard/settings` loads the app, while a missing `/app.js` is still a 404.
+    ///
+    /// The path is relative to the dir being served.
+    /// ## Example
+    /// ```rust,no_run
+    /// // Import Library
+    /// use afire::{Server, extension::ServeStatic, Middleware};
+    ///
+    /// // Create a server for localhost on port 8080
+    /// let mut server = Server::<()>::new("localhost", 8080);
+    ///
+    /// // Make a new static server
+    /// ServeStatic::new("web/dist")
+    ///     // Let the app handle its own routes
+    ///     .spa_fallback("/index.html")
+    ///     // Attach it to the afire server
+    ///     .attach(&mut server);
+    ///
+    /// server.start().unwrap();
+    /// ```
+    pub fn spa_fallback(self, file_path: impl AsRef<str>) -> Self {
+        Self {
+            spa_fallback: Some(file_path.as_ref().to_owned()),
+            ..self
+        }
+    }
+
+    /// Opens the SPA fallback file, if one is set and the request looks like a client-side route.
+    fn open_fallback(&self, req: &Request) -> Option<(String, File)> {
+        let fallback = self.spa_fallback.as_ref()?;
+        let name = req.path.rsplit('/').next().unwrap_or_default();
+        if req.method != Method::GET || name.contains('.') {
+            return None;
+        }
+
+        let path = format!("{}/{}", self.data_dir, fallback.trim_start_matches('/'));
+        File::open(&path).ok().map(|x| (path, x))
+    }
+
     /// Add a MIME type to the Static file Server
     ///
     /// This extension comes with a lot of builtin MIME types
-    /// but if you need to add more thats what this is for
+    /// but if you need to add more thats what this is for.
+    /// Types added here only apply to this static server, to add a type for the whole process use [`crate::mime::register`].
     ///
-    /// The key is the file extension
+    /// The key is the file extension, with or without a leading dot
     ///
     /// The value is the MIME type
     /// ## Example
@@ -215,7 +329,10 @@ impl ServeStatic {
     /// ```
     pub fn mime_type(self, key: impl AsRef<str>, value: impl AsRef<str>) -> Self {
         let mut types = self.types;
-        types.push((key.as_ref().to_owned(), value.as_ref().to_owned()));
+        types.push((
+            key.as_ref().trim_start_matches('.').to_owned(),
+            value.as_ref().to_owned(),
+        ));
 
         Self { types, ..self }
     }
@@ -247,7 +364,12 @@ impl ServeStatic {
     pub fn mime_types(self, new_types: &[(impl AsRef<str>, impl AsRef<str>)]) -> Self {
         let mut new_types = new_types
             .iter()
-            .map(|x| (x.0.as_ref().to_owned(), x.1.as_ref().to_owned()))
+            .map(|x| {
+                (
+                    x.0.as_ref().trim_start_matches('.').to_owned(),
+                    x.1.as_ref().to_owned(),
+                )
+            })
             .collect();
         let mut types = self.types;
 
@@ -324,25 +446,68 @@ fn process_req(req: Rc<Request>, this: &ServeStatic) -> (Response, bool) {
         return ((this.not_found)(req, true), false);
     }
 
-    // Try to read File
-    let ext = path.rsplit('.').next().unwrap_or_default();
+    // Try to read File, falling back to the app's page for client-side routes
     let file = match File::open(&path) {
         Ok(i) => i,
-        Err(_) => return ((this.not_found)(req, false), false),
+        Err(_) => match this.open_fallback(&req) {
+            Some((fallback, file)) => {
+                path = fallback;
+                file
+            }
+            None => return ((this.not_found)(req, false), false),
+        },
     };
+    let ext = path.rsplit('.').next().unwrap_or_default();
 
-    let content_type = get_type(ext, &TYPES)
-        .or_else(|| this.types.iter().find(|x| x.0 == ext).map(|x| x.1.as_str()))
-        .unwrap_or("application/octet-stream");
+    // Types added to this server take priority over the shared registry
+    let content_type = this
+        .types
+        .iter()
+        .find(|x| x.0.eq_ignore_ascii_case(ext))
+        .map(|x| x.1.as_str())
+        .or_else(|| mime::from_extension(ext))
+        .map_or_else(|| "application/octet-stream".to_owned(), mime::content_type);
 
     let mut res = Response::new();
     if let Ok(i) = file.metadata() {
+        if let Some(etag) = etag(&i) {
+            if let Some(j) = req.headers.get("If-None-Match") {
+                if etag_matches(j, &etag) {
+                    let res = Response::empty(Status::NotModified).header("ETag", etag);
+                    return (res, true);
+                }
+            }
+
+            res.headers.add("ETag", etag);
+        }
+
         res.headers.add("Content-Length", i.len().to_string());
     }
 
     (res.stream(file).header("Content-Type", content_type), true)
 }
 
+/// Builds an ETag for a file from its size and last modification time.
+/// Returns None if the platform dose not support getting the modification time.
+fn etag(meta: &Metadata) -> Option<String> {
+    let modified = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some(format!("\"{:x}-{:x}\"", meta.len(), modified.as_nanos()))
+}
+
+/// Checks if an `If-None-Match` header matches an ETag.
+/// Uses weak comparison, as defined in [RFC 9110](https://www.rfc-editor.org/rfc/rfc9110#section-13.1.2).
+pub(crate) fn etag_matches(header: &str, etag: &str) -> bool {
+    header
+        .split(',')
+        .map(str::trim)
+        .any(|x| x == "*" || x.trim_start_matches("W/") == etag)
+}
+
+#[cfg(feature = "serde")]
+fn default_serve_path() -> String {
+    "/".to_owned()
+}
+
 /// Prevents path traversals.
 /// Ex: '/hello/../../../data.db' => '/data.db'
 #[inline]
@@ -364,90 +529,35 @@ pub fn safe_path(path: &str) -> Cow<'_, str> {
     Cow::Owned(out.join("/"))
 }
 
-/// Common MIME Types (sorted in alphabetical order by extension)
-///
-/// Used by ServeStatic extension
-pub const TYPES: [MIME; 56] = [
-    MIME::new("7z", "application/x-7z-compressed"),
-    MIME::new("aac", "audio/aac"),
-    MIME::new("avi", "video/x-msvideo"),
-    MIME::new("bin", "application/octet-stream"),
-    MIME::new("bmp", "image/bmp"),
-    MIME::new("bz", "application/x-bzip"),
-    MIME::new("bz2", "application/x-bzip2"),
-    MIME::new("cda", "application/x-cdf"),
-    MIME::new("css", "text/css"),
-    MIME::new("csv", "text/csv"),
-    MIME::new("epub", "application/epub+zip"),
-    MIME::new("gif", "image/gif"),
-    MIME::new("gz", "application/gzip"),
-    MIME::new("htm", "text/html"),
-    MIME::new("html", "text/html"),
-    MIME::new("ico", "image/x-icon"),
-    MIME::new("ics", "text/calendar"),
-    MIME::new("jar", "application/java-archive"),
-    MIME::new("jpeg", "image/jpeg"),
-    MIME::new("jpg", "image/jpeg"),
-    MIME::new("js", "application/javascript"),
-    MIME::new("json", "application/json"),
-    MIME::new("jsonld", "application/ld+json"),
-    MIME::new("mid", "audio/midi audio/x-midi"),
-    MIME::new("midi", "audio/midi audio/x-midi"),
-    MIME::new("mjs", "text/javascript"),
-    MIME::new("mp3", "audio/mpeg"),
-    MIME::new("mp4", "video/mp4"),
-    MIME::new("mpeg", "video/mpeg"),
-    MIME::new("oga", "audio/ogg"),
-    MIME::new("ogv", "video/ogg"),
-    MIME::new("ogx", "application/ogg"),
-    MIME::new("opus", "audio/opus"),
-    MIME::new("otf", "font/otf"),
-    MIME::new("pdf", "application/pdf"),
-    MIME::new("png", "image/png"),
-    MIME::new("rar", "application/vnd.rar"),
-    MIME::new("rtf", "application/rtf"),
-    MIME::new("sh", "application/x-sh"),
-    MIME::new("svg", "image/svg+xml"),
-    MIME::new("swf", "application/x-shockwave-flash"),
-    MIME::new("tar", "application/x-tar"),
-    MIME::new("tif", "image/tiff"),
-    MIME::new("tiff", "image/tiff"),
-    MIME::new("ts", "text/x-typescript"),
-    MIME::new("ttf", "font/ttf"),
-    MIME::new("txt", "text/plain"),
-    MIME::new("wav", "audio/wav"),
-    MIME::new("weba", "audio/webm"),
-    MIME::new("webm", "video/webm"),
-    MIME::new("webp", "image/webp"),
-    MIME::new("woff", "font/woff"),
-    MIME::new("woff2", "font/woff2"),
-    MIME::new("xhtml", "application/xhtml+xml"),
-    MIME::new("xml", "application/xml"),
-    MIME::new("zip", "application/zip"),
-];
-
-/// Struct to hold a file extension and its matching MIME type
-#[derive(Debug, Clone)]
-pub struct MIME {
-    extension: &'static str,
-    mime_type: &'static str,
-}
-
-impl MIME {
-    /// Create a new MIME type
-    pub const fn new(extension: &'static str, mime_type: &'static str) -> Self {
-        Self {
-            extension,
-            mime_type,
-        }
+#[cfg(test)]
+mod test {
+    use super::etag_matches;
+
+    #[test]
+    fn test_etag_matches() {
+        assert!(etag_matches("\"a-b\"", "\"a-b\""));
+        assert!(etag_matches("W/\"a-b\"", "\"a-b\""));
+        assert!(etag_matches("\"x\", \"a-b\"", "\"a-b\""));
+        assert!(etag_matches("*", "\"a-b\""));
+        assert!(!etag_matches("\"a-c\"", "\"a-b\""));
     }
-}
 
-/// Gets the MIME type from the specified file extension using a slice of MIME types.
-/// If no type is found, None is returned.
-pub fn get_type(ext: &str, extensions: &[MIME]) -> Option<&'static str> {
-    extensions
-        .binary_search_by(|x| x.extension.cmp(ext))
-        .map(|x| TYPES[x].mime_type)
-        .ok()
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serve_static_config() {
+        use super::{ServeStatic, ServeStaticConfig};
+
+        let config = serde_json::from_str::<ServeStaticConfig>(
+            r#"{"data_dir": "static", "path": "/files/", "mime_types": {"3gp": "video/3gpp"}}"#,
+        )
+        .unwrap();
+        let serve = ServeStatic::from_config(&config);
+        assert_eq!(serve.data_dir, "static");
+        assert_eq!(serve.serve_path, "files");
+        assert_eq!(serve.types, [("3gp".to_owned(), "video/3gpp".to_owned())]);
+
+        let config =
+            serde_json::from_str::<ServeStaticConfig>(r#"{"data_dir": "static"}"#).unwrap();
+        assert_eq!(ServeStatic::from_config(&config).serve_path, "");
+    }
 }
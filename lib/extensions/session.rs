@@ -0,0 +1,425 @@
+//! Cookie based sessions, with pluggable storage.
+//!
+//! The [`Sessions`] middleware loads the session of each request from its [`SessionStore`] (in memory by default), using an id from a cookie.
+//! Handlers read and change it with `req.session()` (See [`RequestSession`]), and changed sessions are saved and their cookie sent after the route runs.
+//!
+//! ## Example
+//! ```rust
+//! # use afire::{Server, Method, Middleware, Response, extension::{Sessions, session::RequestSession}};
+//! let mut server = Server::<()>::new("localhost", 8080);
+//! Sessions::new().attach(&mut server);
+//!
+//! server.route(Method::GET, "/visits", |req| {
+//!     let session = req.session();
+//!     let visits = session.get("visits").and_then(|x| x.parse().ok()).unwrap_or(0) + 1;
+//!     session.set("visits", visits.to_string());
+//!     Response::new().text(format!("You have visited this page {visits} times"))
+//! });
+//! ```
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    cookie::SameSite,
+    internal::{common::ForceLock, encoding::base64},
+    middleware::{MiddleResult, Middleware},
+    Request, Response, SetCookie,
+};
+
+/// The values in a session.
+pub type SessionData = HashMap<String, String>;
+
+/// Where sessions are kept between requests.
+/// Implement this to keep sessions in a database or cache, so they are shared between servers and survive restarts.
+pub trait SessionStore: Send + Sync {
+    /// Loads a session by its id.
+    /// Returns None if there is no session with this id, or if it has expired.
+    fn load(&self, id: &str) -> Option<SessionData>;
+
+    /// Saves a session, which should expire after `ttl`.
+    fn save(&self, id: &str, data: &SessionData, ttl: Duration);
+
+    /// Removes a session.
+    fn remove(&self, id: &str);
+}
+
+/// A [`SessionStore`] that keeps sessions in memory.
+/// Sessions are lost when the server restarts.
+#[derive(Default)]
+pub struct MemoryStore {
+    sessions: Mutex<HashMap<String, (SessionData, Instant)>>,
+}
+
+/// Middleware that gives each client a session, identified by a cookie.
+/// See the [module level docs](crate::extension::session) for an example.
+///
+/// Sessions are only saved (and their cookie sent) once something is set in them, so clients that never use their session don't fill up the store.
+/// A session expires when it hasn't been changed for the [`Sessions::ttl`], or when it hasn't been used at all if [`Sessions::rolling`] is enabled.
+///
+/// The cookie is `HttpOnly` and `SameSite=Lax`, so it can't be read by scripts or sent with cross-site form posts.
+pub struct Sessions {
+    store: Arc<dyn SessionStore>,
+    cookie: String,
+    ttl: Duration,
+    rolling: bool,
+    path: String,
+    domain: Option<String>,
+    secure: bool,
+}
+
+/// The session of a request, from [`RequestSession::session`].
+/// Changes are saved after the route handler runs.
+pub struct Session {
+    state: Mutex<SessionState>,
+}
+
+struct SessionState {
+    /// The id of the session, None if it is new.
+    id: Option<String>,
+    data: SessionData,
+    changed: bool,
+    /// The session was destroyed, its old id is kept so it can be removed from the store.
+    destroyed: bool,
+    /// The old id of a session that was given a new id, to remove it from the store.
+    old_id: Option<String>,
+}
+
+/// Trait that adds the [`RequestSession::session`] method to requests.
+pub trait RequestSession {
+    /// Gets the session of the request.
+    /// Panics if the [`Sessions`] middleware is not attached, or if the request was answered by a middleware before sessions were loaded.
+    fn session(&self) -> &Session;
+}
+
+impl Sessions {
+    /// Create a new Sessions middleware, with an in memory store.
+    /// The cookie is called `session`, and sessions expire after a day without changes.
+    pub fn new() -> Self {
+        Self {
+            store: Arc::new(MemoryStore::default()),
+            cookie: "session".to_owned(),
+            ttl: Duration::from_secs(60 * 60 * 24),
+            rolling: false,
+            path: "/".to_owned(),
+            domain: None,
+            secure: false,
+        }
+    }
+
+    /// Sets where sessions are stored.
+    /// Defaults to a [`MemoryStore`].
+    pub fn store(self, store: impl SessionStore + 'static) -> Self {
+        Self {
+            store: Arc::new(store),
+            ..self
+        }
+    }
+
+    /// Sets the name of the session cookie.
+    /// Defaults to `session`.
+    pub fn cookie_name(self, name: impl AsRef<str>) -> Self {
+        Self {
+            cookie: name.as_ref().to_owned(),
+            ..self
+        }
+    }
+
+    /// Sets how long sessions last, which is also the Max-Age of the cookie.
+    /// Defaults to a day.
+    pub fn ttl(self, ttl: Duration) -> Self {
+        Self { ttl, ..self }
+    }
+
+    /// If enabled, sessions are saved and their cookie sent again on every request, so they only expire when they haven't been used for the [`Sessions::ttl`].
+    /// Defaults to false.
+    pub fn rolling(self, rolling: bool) -> Self {
+        Self { rolling, ..self }
+    }
+
+    /// Sets the path of the session cookie.
+    /// Defaults to `/`.
+    pub fn path(self, path: impl AsRef<str>) -> Self {
+        Self {
+            path: path.as_ref().to_owned(),
+            ..self
+        }
+    }
+
+    /// Sets the domain of the session cookie.
+    /// By default it is not set, so the cookie is only sent to the host that set it.
+    pub fn domain(self, domain: impl AsRef<str>) -> Self {
+        Self {
+            domain: Some(domain.as_ref().to_owned()),
+            ..self
+        }
+    }
+
+    /// Sets if the session cookie should only be sent over HTTPS.
+    /// Defaults to false, but should be enabled if the site is served over HTTPS.
+    pub fn secure(self, secure: bool) -> Self {
+        Self { secure, ..self }
+    }
+
+    /// Makes a `Set-Cookie` value for the session cookie.
+    fn cookie(&self, value: &str, max_age: u64) -> String {
+        let mut cookie = SetCookie::new(&self.cookie, value)
+            .max_age(max_age)
+            .path(&self.path)
+            .secure(self.secure)
+            .http_only(true)
+            .same_site(SameSite::Lax);
+        cookie.domain = self.domain.clone();
+        cookie.to_string()
+    }
+}
+
+impl Session {
+    /// Gets a value from the session.
+    pub fn get(&self, key: impl AsRef<str>) -> Option<String> {
+        self.state.force_lock().data.get(key.as_ref()).cloned()
+    }
+
+    /// Sets a value in the session.
+    pub fn set(&self, key: impl AsRef<str>, value: impl AsRef<str>) {
+        let mut state = self.state.force_lock();
+        state
+            .data
+            .insert(key.as_ref().to_owned(), value.as_ref().to_owned());
+        state.changed = true;
+    }
+
+    /// Removes a value from the session, returning it.
+    pub fn remove(&self, key: impl AsRef<str>) -> Option<String> {
+        let mut state = self.state.force_lock();
+        let old = state.data.remove(key.as_ref());
+        state.changed |= old.is_some();
+        old
+    }
+
+    /// Gets a copy of all the values in the session.
+    pub fn data(&self) -> SessionData {
+        self.state.force_lock().data.clone()
+    }
+
+    /// Gets the id of the session.
+    /// Returns None if the session is new, it will get an id once it is saved.
+    pub fn id(&self) -> Option<String> {
+        self.state.force_lock().id.clone()
+    }
+
+    /// Checks if the session is new, meaning the client did not send a valid session cookie.
+    pub fn is_new(&self) -> bool {
+        self.state.force_lock().id.is_none()
+    }
+
+    /// Removes all values and ends the session.
+    /// It is removed from the store, and the client is told to delete the cookie.
+    pub fn destroy(&self) {
+        let mut state = self.state.force_lock();
+        state.data.clear();
+        state.destroyed = true;
+    }
+
+    /// Gives the session a new id, keeping its values.
+    /// This should be done when a user logs in, so an id that was set by an attacker before the login can't be used to get into their session.
+    pub fn regenerate(&self) {
+        let mut state = self.state.force_lock();
+        if let Some(id) = state.id.take() {
+            state.old_id = Some(id);
+        }
+        state.changed = true;
+    }
+}
+
+impl RequestSession for Request {
+    fn session(&self) -> &Session {
+        self.extensions
+            .get::<Session>()
+            .expect("Sessions middleware not attached")
+    }
+}
+
+impl SessionStore for MemoryStore {
+    fn load(&self, id: &str) -> Option<SessionData> {
+        let mut sessions = self.sessions.force_lock();
+        match sessions.get(id) {
+            Some((data, expires)) if *expires > Instant::now() => Some(data.clone()),
+            Some(_) => {
+                sessions.remove(id);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn save(&self, id: &str, data: &SessionData, ttl: Duration) {
+        let now = Instant::now();
+        let mut sessions = self.sessions.force_lock();
+        sessions.retain(|_, x| x.1 > now);
+        sessions.insert(id.to_owned(), (data.clone(), now + ttl));
+    }
+
+    fn remove(&self, id: &str) {
+        self.sessions.force_lock().remove(id);
+    }
+}
+
+impl Middleware for Sessions {
+    fn pre(&self, req: &mut Request) -> MiddleResult {
+        let loaded = req
+            .cookies
+            .get(&self.cookie)
+            .and_then(|id| Some((id.to_owned(), self.store.load(id)?)));
+        let (id, data) = match loaded {
+            Some((id, data)) => (Some(id), data),
+            None => (None, SessionData::new()),
+        };
+
+        req.extensions.insert(Session {
+            state: Mutex::new(SessionState {
+                id,
+                data,
+                changed: false,
+                destroyed: false,
+                old_id: None,
+            }),
+        });
+        MiddleResult::Continue
+    }
+
+    fn post(&self, req: &Request, res: &mut Response) -> MiddleResult {
+        let session = match req.extensions.get::<Session>() {
+            Some(i) => i,
+            None => return MiddleResult::Continue,
+        };
+        let mut state = session.state.force_lock();
+
+        if let Some(id) = state.old_id.take() {
+            self.store.remove(&id);
+        }
+
+        if state.destroyed {
+            if let Some(id) = state.id.take() {
+                self.store.remove(&id);
+                res.headers.add("Set-Cookie", self.cookie("", 0));
+            }
+            return MiddleResult::Continue;
+        }
+
+        let save = match state.id {
+            Some(_) => state.changed || self.rolling,
+            None => state.changed && !state.data.is_empty(),
+        };
+        if !save {
+            return MiddleResult::Continue;
+        }
+
+        let id = state.id.get_or_insert_with(random_id).clone();
+        self.store.save(&id, &state.data, self.ttl);
+        res.headers
+            .add("Set-Cookie", self.cookie(&id, self.ttl.as_secs()));
+
+        MiddleResult::Continue
+    }
+}
+
+impl Default for Sessions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Session {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.state.force_lock();
+        f.debug_struct("Session")
+            .field("id", &state.id)
+            .field("data", &state.data)
+            .finish()
+    }
+}
+
+/// Makes a random session id, with 256 bits from the operating system's secure random number generator.
+fn random_id() -> String {
+    let mut buf = [0; 32];
+    getrandom::getrandom(&mut buf).expect("Failed to get random bytes");
+    base64::encode_url(&buf)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{random_id, RequestSession, Sessions};
+    use crate::{
+        testing::{TestRequest, TestServer},
+        Method, Middleware, Response, Server,
+    };
+
+    fn server() -> TestServer {
+        let mut server = Server::<()>::new("localhost", 8080);
+        Sessions::new().attach(&mut server);
+        server.route(Method::GET, "/", |req| {
+            Response::new().text(req.session().get("user").unwrap_or_default())
+        });
+        server.route(Method::POST, "/login", |req| {
+            req.session().regenerate();
+            req.session().set("user", "darren");
+            Response::new()
+        });
+        server.route(Method::POST, "/logout", |req| {
+            req.session().destroy();
+            Response::new()
+        });
+        server.into()
+    }
+
+    #[test]
+    fn test_sessions() {
+        let server = server();
+
+        let res = TestRequest::get("/").send(&server);
+        assert_eq!(res.header("Set-Cookie"), None);
+
+        let res = TestRequest::post("/login").send(&server);
+        let cookie = res.header("Set-Cookie").unwrap();
+        assert!(cookie.ends_with("; Max-Age=86400; Path=/; HttpOnly; SameSite=Lax"));
+        let session = cookie.split(';').next().unwrap().to_owned();
+
+        let res = TestRequest::get("/")
+            .header("Cookie", &session)
+            .send(&server);
+        assert_eq!(res.text(), "darren");
+        assert_eq!(res.header("Set-Cookie"), None);
+
+        // Logging in again gives a new id, and the old one stops working
+        let res = TestRequest::post("/login")
+            .header("Cookie", &session)
+            .send(&server);
+        let new_session = res.header("Set-Cookie").unwrap().split(';').next().unwrap();
+        assert_ne!(new_session, session);
+        let res = TestRequest::get("/")
+            .header("Cookie", &session)
+            .send(&server);
+        assert_eq!(res.text(), "");
+
+        let res = TestRequest::post("/logout")
+            .header("Cookie", new_session)
+            .send(&server);
+        assert!(res.header("Set-Cookie").unwrap().contains("Max-Age=0;"));
+        let res = TestRequest::get("/")
+            .header("Cookie", new_session)
+            .send(&server);
+        assert_eq!(res.text(), "");
+    }
+
+    #[test]
+    fn test_random_id() {
+        let id = random_id();
+        assert_eq!(id.len(), 43);
+        assert_ne!(id, random_id());
+    }
+}
@@ -0,0 +1,284 @@
+//! Render HTML templates from handlers, with any template engine.
+//! Only available with the `serde` feature, as template data is passed as JSON values.
+
+use std::{collections::HashMap, error::Error, fmt::Write, fs, io, path::Path, sync::Arc};
+
+use serde_json::Value;
+
+use crate::{
+    middleware::{MiddleResult, Middleware},
+    Content, Request, Response, Status,
+};
+
+/// The error type returned by template engines.
+pub type TemplateError = Box<dyn Error + Send + Sync>;
+
+/// A template engine that renders named templates with some data.
+/// Implement this for a wrapper around a third party engine (like Tera, Handlebars or MiniJinja) to use it with [`Templates`].
+///
+/// ## Example
+/// ```rust
+/// # use afire::extension::templates::{TemplateEngine, TemplateError};
+/// # use serde_json::Value;
+/// /// An engine that just shows the data.
+/// struct Debug;
+///
+/// impl TemplateEngine for Debug {
+///     fn render(&self, name: &str, data: &Value) -> Result<String, TemplateError> {
+///         Ok(format!("<pre>{name}: {data:#}</pre>"))
+///     }
+/// }
+/// ```
+pub trait TemplateEngine: Send + Sync {
+    /// Renders the template with this name, using the data (which is usually an object).
+    fn render(&self, name: &str, data: &Value) -> Result<String, TemplateError>;
+}
+
+/// Middleware that makes a [`TemplateEngine`] available to handlers, through [`RenderExt::render`].
+///
+/// ## Example
+/// ```rust
+/// # use afire::{Server, Method, Middleware, Response, extension::{Templates, templates::{RenderExt, SimpleEngine}}};
+/// # use serde_json::json;
+/// # let mut server = Server::<()>::new("localhost", 8080);
+/// let engine = SimpleEngine::new().template("hello", "<h1>Hello, {{ name }}!</h1>");
+/// Templates::new(engine).attach(&mut server);
+///
+/// server.route(Method::GET, "/hello/{name}", |req| {
+///     req.render("hello", &json!({ "name": req.param("name") }))
+/// });
+/// ```
+pub struct Templates {
+    engine: Arc<dyn TemplateEngine>,
+}
+
+/// The engine, stored in the extensions of each request.
+struct Engine(Arc<dyn TemplateEngine>);
+
+/// A simple built-in template engine, that replaces `{{ key }}` with values from the data.
+///
+/// - `{{ key }}` is replaced with the HTML escaped value.
+/// - `{{& key }}` is replaced with the raw value, for data that is already HTML.
+/// - Keys can be paths into nested objects and arrays, like `{{ user.name }}` or `{{ items.0 }}`.
+/// - Missing and null values are replaced with nothing, strings are inserted as is and other values as JSON.
+///
+/// There are no loops or conditionals, for those implement [`TemplateEngine`] for a full engine.
+#[derive(Debug, Default, Clone)]
+pub struct SimpleEngine {
+    templates: HashMap<String, String>,
+}
+
+/// Renders templates from a request.
+pub trait RenderExt {
+    /// Renders a template with the [`Templates`] middleware's engine, into an HTML response.
+    /// The data can be anything that implements [`serde::Serialize`].
+    ///
+    /// If the template can't be rendered, or the middleware isn't attached, the error is logged and a `500 Internal Server Error` is returned.
+    fn render(&self, name: &str, data: &impl serde::Serialize) -> Response;
+}
+
+impl Templates {
+    /// Create a new Templates middleware, using the given engine.
+    pub fn new(engine: impl TemplateEngine + 'static) -> Self {
+        Self {
+            engine: Arc::new(engine),
+        }
+    }
+
+    /// Renders a template outside of a request, like for an email.
+    pub fn render(
+        &self,
+        name: &str,
+        data: &impl serde::Serialize,
+    ) -> Result<String, TemplateError> {
+        render(&*self.engine, name, data)
+    }
+}
+
+impl Middleware for Templates {
+    fn pre(&self, req: &mut Request) -> MiddleResult {
+        req.extensions.insert(Engine(self.engine.clone()));
+        MiddleResult::Continue
+    }
+}
+
+impl RenderExt for Request {
+    fn render(&self, name: &str, data: &impl serde::Serialize) -> Response {
+        let result = match self.extensions.get::<Engine>() {
+            Some(engine) => render(&*engine.0, name, data),
+            None => Err("the Templates middleware is not attached".into()),
+        };
+
+        match result {
+            Ok(html) => Response::new().html(html),
+            Err(e) => {
+                trace!(Level::Error, "Error rendering template `{}`: {}", name, e);
+                Response::new()
+                    .status(Status::InternalServerError)
+                    .text("Internal Server Error")
+                    .content(Content::TXT)
+            }
+        }
+    }
+}
+
+/// Converts the data to JSON and renders a template with it.
+fn render(
+    engine: &dyn TemplateEngine,
+    name: &str,
+    data: &impl serde::Serialize,
+) -> Result<String, TemplateError> {
+    let data = serde_json::to_value(data)?;
+    engine.render(name, &data)
+}
+
+impl SimpleEngine {
+    /// Create a new engine with no templates.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a template with the given name.
+    pub fn template(mut self, name: impl AsRef<str>, source: impl AsRef<str>) -> Self {
+        self.templates
+            .insert(name.as_ref().to_owned(), source.as_ref().to_owned());
+        self
+    }
+
+    /// Loads every file in a directory (and its subdirectories) as a template.
+    /// Templates are named by their path relative to the directory, with `/` separators and without their extension, so `dir/users/list.html` is `users/list`.
+    pub fn from_dir(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let mut engine = Self::new();
+        let mut dirs = vec![dir.as_ref().to_path_buf()];
+        while let Some(path) = dirs.pop() {
+            for entry in fs::read_dir(path)? {
+                let path = entry?.path();
+                if path.is_dir() {
+                    dirs.push(path);
+                    continue;
+                }
+
+                let name = path
+                    .strip_prefix(&dir)
+                    .unwrap_or(&path)
+                    .with_extension("")
+                    .components()
+                    .map(|x| x.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                engine.templates.insert(name, fs::read_to_string(&path)?);
+            }
+        }
+
+        Ok(engine)
+    }
+}
+
+impl TemplateEngine for SimpleEngine {
+    fn render(&self, name: &str, data: &Value) -> Result<String, TemplateError> {
+        let template = self
+            .templates
+            .get(name)
+            .ok_or_else(|| format!("no template named `{name}`"))?;
+
+        let mut out = String::with_capacity(template.len());
+        let mut rest = template.as_str();
+        while let Some(start) = rest.find("{{") {
+            out.push_str(&rest[..start]);
+            let end = rest[start..]
+                .find("}}")
+                .ok_or_else(|| format!("unclosed tag in template `{name}`"))?;
+            let tag = rest[start + 2..start + end].trim();
+            rest = &rest[start + end + 2..];
+
+            let (raw, key) = match tag.strip_prefix('&') {
+                Some(key) => (true, key.trim()),
+                None => (false, tag),
+            };
+            let value = key.split('.').try_fold(data, |value, key| match value {
+                Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+                _ => value.get(key),
+            });
+
+            let text = match value {
+                None | Some(Value::Null) => continue,
+                Some(Value::String(x)) => x.to_owned(),
+                Some(x) => x.to_string(),
+            };
+            match raw {
+                true => out.push_str(&text),
+                false => escape_html(&mut out, &text),
+            }
+        }
+
+        out.push_str(rest);
+        Ok(out)
+    }
+}
+
+/// Writes text to a string, escaping the characters that are special in HTML.
+fn escape_html(out: &mut String, text: &str) {
+    for c in text.chars() {
+        let _ = match c {
+            '&' => out.write_str("&amp;"),
+            '<' => out.write_str("&lt;"),
+            '>' => out.write_str("&gt;"),
+            '"' => out.write_str("&quot;"),
+            '\'' => out.write_str("&#39;"),
+            c => out.write_char(c),
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::{RenderExt, SimpleEngine, TemplateEngine, Templates};
+    use crate::{
+        testing::{TestRequest, TestServer},
+        Method, Middleware, Server, Status,
+    };
+
+    #[test]
+    fn test_simple_engine() {
+        let engine = SimpleEngine::new()
+            .template(
+                "page",
+                "<h1>{{ title }}</h1>{{& body }}<p>{{user.name}} {{ tags.1 }}{{ missing }}</p>",
+            )
+            .template("broken", "{{ title");
+        let data = json!({
+            "title": "Tom & Jerry",
+            "body": "<b>Hi</b>",
+            "user": { "name": "<Darren>" },
+            "tags": ["a", 2],
+        });
+
+        assert_eq!(
+            engine.render("page", &data).unwrap(),
+            "<h1>Tom &amp; Jerry</h1><b>Hi</b><p>&lt;Darren&gt; 2</p>"
+        );
+        assert!(engine.render("broken", &data).is_err());
+        assert!(engine.render("missing", &data).is_err());
+    }
+
+    #[test]
+    fn test_render() {
+        let mut server = Server::<()>::new("localhost", 8080);
+        Templates::new(SimpleEngine::new().template("hello", "Hello, {{ name }}!"))
+            .attach(&mut server);
+        server.route(Method::GET, "/{name}", |req| {
+            req.render("hello", &json!({ "name": req.param("name") }))
+        });
+        server.route(Method::GET, "/", |req| req.render("other", &()));
+        let server = TestServer::from(server);
+
+        let res = TestRequest::get("/Darren").send(&server);
+        assert_eq!(res.text(), "Hello, Darren!");
+        assert_eq!(res.header("Content-Type"), Some("text/html; charset=utf-8"));
+
+        let res = TestRequest::get("/").send(&server);
+        assert_eq!(res.status, Status::InternalServerError);
+    }
+}
@@ -0,0 +1,267 @@
+//! Middleware to limit how fast response bodies are sent.
+//! Useful for fair-sharing bandwidth between large downloads, or simulating slow networks in staging.
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    io::{self, Cursor, Read},
+    mem,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    internal::{common::ForceLock, handle::Writeable},
+    middleware::{MiddleResult, Middleware},
+    response::ResponseBody,
+    HeaderType, Request, Response,
+};
+
+type Filter = Box<dyn Fn(&Request) -> bool + Send + Sync>;
+
+/// The ID of the next [`Throttle`], used to keep the buckets of different throttles on the same connection apart.
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Which responses share a byte budget.
+/// See [`Throttle::scope`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ThrottleScope {
+    /// Each connection gets its own budget, shared by all the responses sent over it.
+    /// This is the default.
+    #[default]
+    Connection,
+    /// All the throttled responses share one budget, so the total bandwidth is limited.
+    Shared,
+}
+
+/// Middleware to limit the throughput of response bodies, using a token bucket on bytes.
+/// Bodies are sent in chunks as tokens become available, so throttled responses always use chunked transfer encoding.
+///
+/// The throttling happens while the response is being written, so a slow response holds up its worker thread (or io thread with [`crate::Server::start_split`]) for as long as it takes to send.
+///
+/// ## Example
+/// ```rust
+/// # use afire::{Middleware, Server, extension::{Throttle, ThrottleScope}};
+/// # fn test(server: &mut Server) {
+/// // Limit each connection to 64 KiB/s
+/// Throttle::new(64 * 1024).attach(server);
+///
+/// // Share 10 MiB/s between all downloads
+/// Throttle::new(10 * 1024 * 1024)
+///     .scope(ThrottleScope::Shared)
+///     .filter(|req| req.path.starts_with("/downloads"))
+///     .attach(server);
+/// # }
+/// ```
+pub struct Throttle {
+    id: u64,
+    /// Bytes added to the bucket per second.
+    rate: u64,
+    /// Max bytes in the bucket.
+    burst: u64,
+    scope: ThrottleScope,
+    /// The bucket used for [`ThrottleScope::Shared`].
+    shared: Arc<Mutex<Bucket>>,
+    filter: Option<Filter>,
+}
+
+impl Throttle {
+    /// Create a new Throttle that limits responses to `bytes_per_sec`.
+    /// The burst size defaults to a tenth of a second worth of bytes, see [`Throttle::burst`].
+    pub fn new(bytes_per_sec: u64) -> Self {
+        let rate = bytes_per_sec.max(1);
+        let burst = (rate / 10).max(1);
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            rate,
+            burst,
+            scope: ThrottleScope::default(),
+            shared: Arc::new(Mutex::new(Bucket::new(burst))),
+            filter: None,
+        }
+    }
+
+    /// Set the max number of bytes that can be sent at once, after the bucket has had time to fill up.
+    /// This is also the largest chunk the body will be sent in.
+    pub fn burst(mut self, bytes: u64) -> Self {
+        self.burst = bytes.max(1);
+        self.shared = Arc::new(Mutex::new(Bucket::new(self.burst)));
+        self
+    }
+
+    /// Set which responses share a byte budget.
+    /// Defaults to [`ThrottleScope::Connection`].
+    pub fn scope(self, scope: ThrottleScope) -> Self {
+        Self { scope, ..self }
+    }
+
+    /// Only throttle responses to requests that match the filter.
+    /// Can be used to throttle specific routes.
+    pub fn filter(self, filter: impl Fn(&Request) -> bool + Send + Sync + 'static) -> Self {
+        Self {
+            filter: Some(Box::new(filter)),
+            ..self
+        }
+    }
+
+    /// Gets the bucket to use for a request.
+    fn bucket(&self, req: &Request) -> Arc<Mutex<Bucket>> {
+        match self.scope {
+            ThrottleScope::Shared => self.shared.clone(),
+            ThrottleScope::Connection => {
+                let buckets = req.socket.get_or_insert_data(ConnectionBuckets::default);
+                let mut buckets = buckets.0.force_lock();
+                buckets
+                    .entry(self.id)
+                    .or_insert_with(|| Arc::new(Mutex::new(Bucket::new(self.burst))))
+                    .clone()
+            }
+        }
+    }
+}
+
+impl Middleware for Throttle {
+    fn post(&self, req: &Request, res: &mut Response) -> MiddleResult {
+        if self.filter.as_ref().is_some_and(|x| !x(req)) {
+            return MiddleResult::Continue;
+        }
+
+        let body = match mem::replace(&mut res.data, ResponseBody::empty()) {
+            ResponseBody::Static(data) if data.is_empty() => {
+                res.data = ResponseBody::Static(data);
+                return MiddleResult::Continue;
+            }
+            ResponseBody::Static(data) => Body::Static(Cursor::new(data)),
+            ResponseBody::Stream(data) => Body::Stream(data),
+            ResponseBody::RangedStream(data) => Body::Stream(Box::new(RefCell::new(data))),
+            ResponseBody::File(file) => Body::Stream(Box::new(RefCell::new(file))),
+        };
+
+        let throttled = Throttled {
+            body,
+            bucket: self.bucket(req),
+            rate: self.rate,
+            burst: self.burst,
+        };
+        res.headers.retain(|x| x.name != HeaderType::ContentLength);
+        res.data = ResponseBody::Stream(Box::new(RefCell::new(throttled)));
+        MiddleResult::Continue
+    }
+}
+
+/// The buckets of each [`Throttle`] on a connection, kept in the socket's storage.
+#[derive(Default)]
+struct ConnectionBuckets(Mutex<HashMap<u64, Arc<Mutex<Bucket>>>>);
+
+/// A token bucket, where each token is a byte.
+struct Bucket {
+    tokens: u64,
+    last: Instant,
+}
+
+impl Bucket {
+    fn new(burst: u64) -> Self {
+        Self {
+            tokens: burst,
+            last: Instant::now(),
+        }
+    }
+
+    /// Takes `want` tokens, or returns how long to wait until there are enough.
+    /// `want` must be no more than `burst`.
+    fn take(&mut self, want: u64, rate: u64, burst: u64) -> Result<(), Duration> {
+        let now = Instant::now();
+        let new = (now.duration_since(self.last).as_nanos() * rate as u128 / 1_000_000_000) as u64;
+        if new > 0 {
+            self.tokens = (self.tokens + new).min(burst);
+            // Only move forward by the time used to make the new tokens, so partial tokens aren't lost
+            self.last += Duration::from_nanos((new as u128 * 1_000_000_000 / rate as u128) as u64);
+            if self.tokens == burst {
+                self.last = now;
+            }
+        }
+
+        if self.tokens < want {
+            let wait = (want - self.tokens) as u128 * 1_000_000_000 / rate as u128 + 1;
+            return Err(Duration::from_nanos(wait as u64));
+        }
+
+        self.tokens -= want;
+        Ok(())
+    }
+
+    /// Returns tokens that were taken but not used.
+    fn refund(&mut self, tokens: u64, burst: u64) {
+        self.tokens = (self.tokens + tokens).min(burst);
+    }
+}
+
+/// A response body before it was throttled.
+enum Body {
+    Static(Cursor<Vec<u8>>),
+    Stream(Writeable),
+}
+
+/// A response body that reads no faster than its bucket allows.
+struct Throttled {
+    body: Body,
+    bucket: Arc<Mutex<Bucket>>,
+    rate: u64,
+    burst: u64,
+}
+
+impl Read for Throttled {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let want = (buf.len() as u64).min(self.burst);
+        let allowed = loop {
+            let taken = self.bucket.force_lock().take(want, self.rate, self.burst);
+            match taken {
+                Ok(()) => break want as usize,
+                Err(wait) => thread::sleep(wait),
+            }
+        };
+
+        let read = match &mut self.body {
+            Body::Static(data) => data.read(&mut buf[..allowed]),
+            Body::Stream(data) => data.get_mut().read(&mut buf[..allowed]),
+        };
+
+        let unused = allowed - *read.as_ref().unwrap_or(&0);
+        if unused > 0 {
+            self.bucket.force_lock().refund(unused as u64, self.burst);
+        }
+        read
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{thread, time::Duration};
+
+    use super::Bucket;
+
+    #[test]
+    fn test_bucket() {
+        let mut bucket = Bucket::new(100);
+        assert_eq!(bucket.take(60, 1000, 100), Ok(()));
+        assert_eq!(bucket.take(40, 1000, 100), Ok(()));
+
+        // 10 more tokens take 10ms at 1000 bytes/s
+        let wait = bucket.take(10, 1000, 100).unwrap_err();
+        assert!(wait > Duration::from_millis(9) && wait <= Duration::from_millis(11));
+
+        bucket.refund(10, 100);
+        assert_eq!(bucket.take(10, 1000, 100), Ok(()));
+
+        thread::sleep(Duration::from_millis(60));
+        assert_eq!(bucket.take(50, 1000, 100), Ok(()));
+    }
+}
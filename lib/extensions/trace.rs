@@ -1,70 +1,112 @@
 use crate::{
     middleware::{MiddleResult, Middleware},
-    Content, Header, HeaderType, Method, Request, Response,
+    Content, HeaderType, Method, Request, Response,
 };
 
 /// Adds support for the [HTTP TRACE](https://developer.mozilla.org/en-US/docs/Web/HTTP/Methods/TRACE) method.
-/// It echos the request (Status line + Headers) back to the client as the response body.
-/// The `Cookie` header is excluded by default because it could contain sensitive information.
+/// It echos the request head (Request line + Headers) back to the client exactly as it was received, as a `message/http` response body.
 /// Read more about it in [RFC-9110](https://www.rfc-editor.org/rfc/rfc9110#TRACE).
+///
+/// Reflecting requests can leak credentials, so this is disabled until [`Trace::enabled`] is called, and the values of the `Authorization`, `Cookie` and `Proxy-Authorization` headers are redacted by default.
+/// ## Example
+/// ```rust
+/// # use afire::{Server, Middleware, extension::Trace};
+/// # fn add(mut server: Server) {
+/// Trace::new().enabled(true).attach(&mut server);
+/// # }
+/// ```
 pub struct Trace {
+    enabled: bool,
     exclude_headers: Vec<HeaderType>,
 }
 
+/// The value that redacted headers are replaced with.
+const REDACTED: &[u8] = b"[redacted]";
+
 impl Trace {
     /// Create a new instance of the middleware.
-    /// Note: The `Cookie` header is excluded by default because it could contain sensitive information.
-    /// If you want to include it, use the [`include`] method.
+    /// Note: It is disabled by default, use [`Trace::enabled`] to enable it.
+    /// The `Authorization`, `Cookie` and `Proxy-Authorization` headers are redacted by default because they could contain sensitive information.
+    /// If you want to include them, use the [`Trace::include`] method.
     pub fn new() -> Self {
         Self {
-            exclude_headers: vec![HeaderType::Cookie],
+            enabled: false,
+            exclude_headers: vec![
+                HeaderType::Cookie,
+                HeaderType::Custom("Authorization".to_owned()),
+                HeaderType::Custom("Proxy-Authorization".to_owned()),
+            ],
         }
     }
 
-    /// Adds a header to the list of headers to exclude from the response.
-    pub fn exclude(mut self, header: HeaderType) -> Self {
-        self.exclude_headers.push(header);
+    /// Sets if TRACE requests should be answered.
+    /// When disabled, TRACE requests are handled like any other request, so they will usually get a 404.
+    pub fn enabled(self, enabled: bool) -> Self {
+        Self { enabled, ..self }
+    }
+
+    /// Adds a header to the list of headers to redact from the response.
+    /// The header line is still sent, but with its value replaced by `[redacted]`.
+    pub fn exclude(mut self, header: impl Into<HeaderType>) -> Self {
+        self.exclude_headers.push(header.into());
         self
     }
 
-    /// Adds a list of headers to the list of headers to exclude from the response.
+    /// Adds a list of headers to the list of headers to redact from the response.
     pub fn exclude_all(mut self, headers: &[HeaderType]) -> Self {
         self.exclude_headers.extend_from_slice(headers);
         self
     }
 
-    /// Removes a header from the list of headers to exclude from the response.
-    /// Likely to be used with for allowing the `Cookie` header to be sent, as it is excluded by default.
-    pub fn include(mut self, header: HeaderType) -> Self {
-        self.exclude_headers.retain(|h| *h != header);
+    /// Removes a header from the list of headers to redact from the response.
+    /// Likely to be used with for allowing the `Cookie` header to be sent, as it is redacted by default.
+    pub fn include(mut self, header: impl Into<HeaderType>) -> Self {
+        let header = header.into().to_string();
+        self.exclude_headers
+            .retain(|h| !h.to_string().eq_ignore_ascii_case(&header));
         self
     }
+
+    /// Copies the raw request head, replacing the values of excluded headers.
+    fn reflect(&self, head: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(head.len());
+        for (i, line) in head.split_inclusive(|x| *x == b'\n').enumerate() {
+            let colon = line.iter().position(|x| *x == b':');
+            let redact = match colon {
+                Some(colon) if i > 0 => {
+                    // Compared as strings, as custom header names are case sensitive in HeaderType
+                    let name = String::from_utf8_lossy(&line[..colon]);
+                    self.exclude_headers
+                        .iter()
+                        .any(|x| x.to_string().eq_ignore_ascii_case(name.trim()))
+                }
+                _ => false,
+            };
+
+            match colon {
+                Some(colon) if redact => {
+                    out.extend_from_slice(&line[..=colon]);
+                    out.push(b' ');
+                    out.extend_from_slice(REDACTED);
+                    out.extend_from_slice(b"\r\n");
+                }
+                _ => out.extend_from_slice(line),
+            }
+        }
+
+        out
+    }
 }
 
 impl Middleware for Trace {
     fn pre(&self, req: &mut Request) -> MiddleResult {
-        if req.method != Method::TRACE {
+        if !self.enabled || req.method != Method::TRACE {
             return MiddleResult::Continue;
         }
 
-        let headers = req
-            .headers
-            .iter()
-            .filter(|h| !self.exclude_headers.contains(&h.name))
-            .map(Header::to_string)
-            .fold(String::new(), |acc, i| acc + &i + "\r\n");
-
-        let out = format!(
-            "{} {} {}\r\n{}\r\n\r\n",
-            req.method,
-            req.path,
-            req.version,
-            &headers[..headers.len() - 2]
-        );
-
         MiddleResult::Send(
             Response::new()
-                .text(out)
+                .bytes(&self.reflect(req.raw_head()))
                 .content(Content::Custom("message/http")),
         )
     }
@@ -75,3 +117,27 @@ impl Default for Trace {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::Trace;
+
+    #[test]
+    fn test_reflect() {
+        let head = b"TRACE /a//b?c=d HTTP/1.1\r\nhost: localhost\r\nCookie: a=b\r\nauthorization:Basic abc\r\nX-Test:  1 \r\n\r\n";
+        let out = Trace::new().reflect(head);
+        assert_eq!(
+            out,
+            b"TRACE /a//b?c=d HTTP/1.1\r\nhost: localhost\r\nCookie: [redacted]\r\nauthorization: [redacted]\r\nX-Test:  1 \r\n\r\n"
+        );
+
+        let out = Trace::new()
+            .include("Cookie")
+            .exclude("X-Test")
+            .reflect(head);
+        assert_eq!(
+            out,
+            b"TRACE /a//b?c=d HTTP/1.1\r\nhost: localhost\r\nCookie: a=b\r\nauthorization: [redacted]\r\nX-Test: [redacted]\r\n\r\n"
+        );
+    }
+}
@@ -0,0 +1,167 @@
+//! Serve build information about your app as JSON.
+//! Responds to `GET /version` (by default) with the app version, afire version, git hash and uptime.
+
+use std::{any::type_name, fmt::Write};
+
+use crate::{
+    middleware::{MiddleResult, Middleware},
+    path::normalize_path,
+    server::StartTime,
+    trace::emoji,
+    Content, Method, Request, Response, Server, VERSION,
+};
+
+/// Serve build information about your app as JSON.
+///
+/// The response looks like this, with `null` used for any values that were not supplied:
+/// ```json
+/// {"version":"1.0.0","afire":"2.2.1","git_hash":"4df20a1","uptime":3600}
+/// ```
+/// The uptime is in seconds.
+///
+/// ## Example
+/// ```rust,no_run
+/// # use afire::{Server, Middleware, extension::Version};
+/// let mut server = Server::<()>::new("localhost", 8080);
+///
+/// Version::new()
+///     .version(env!("CARGO_PKG_VERSION"))
+///     .git_hash(option_env!("GIT_HASH").unwrap_or("unknown"))
+///     .attach(&mut server);
+///
+/// server.start().unwrap();
+/// ```
+pub struct Version {
+    path: String,
+    version: Option<String>,
+    git_hash: Option<String>,
+    start_time: StartTime,
+}
+
+impl Version {
+    /// Create a new Version extension, serving on `/version`.
+    pub fn new() -> Self {
+        Self {
+            path: "version".to_owned(),
+            version: None,
+            git_hash: None,
+            start_time: StartTime::default(),
+        }
+    }
+
+    /// Set the path to serve the build information on.
+    /// Defaults to `/version`.
+    pub fn path(self, path: impl AsRef<str>) -> Self {
+        Self {
+            path: normalize_path(path.as_ref().to_owned()),
+            ..self
+        }
+    }
+
+    /// Set the version of your app.
+    /// You will usually want to use `env!("CARGO_PKG_VERSION")`.
+    pub fn version(self, version: impl AsRef<str>) -> Self {
+        Self {
+            version: Some(version.as_ref().to_owned()),
+            ..self
+        }
+    }
+
+    /// Set the git commit hash your app was built from.
+    /// This can be passed in from a build script or an environment variable at compile time.
+    pub fn git_hash(self, git_hash: impl AsRef<str>) -> Self {
+        Self {
+            git_hash: Some(git_hash.as_ref().to_owned()),
+            ..self
+        }
+    }
+
+    fn json(&self) -> String {
+        format!(
+            r#"{{"version":{},"afire":{},"git_hash":{},"uptime":{}}}"#,
+            json_string(self.version.as_deref()),
+            json_string(Some(VERSION)),
+            json_string(self.git_hash.as_deref()),
+            self.start_time
+                .uptime()
+                .map(|x| x.as_secs().to_string())
+                .unwrap_or_else(|| "null".to_owned())
+        )
+    }
+}
+
+impl Middleware for Version {
+    fn pre(&self, req: &mut Request) -> MiddleResult {
+        if req.method != Method::GET || req.path.trim_matches('/') != self.path {
+            return MiddleResult::Continue;
+        }
+
+        MiddleResult::Send(Response::new().text(self.json()).content(Content::JSON))
+    }
+
+    fn attach<State>(mut self, server: &mut Server<State>)
+    where
+        Self: 'static + Send + Sync + Sized,
+        State: 'static + Send + Sync,
+    {
+        trace!("{}Adding Middleware {}", emoji("📦"), type_name::<Self>());
+
+        self.start_time = server.start_time.clone();
+        server.insert_middleware(Box::new(self));
+    }
+}
+
+impl Default for Version {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Encodes an optional string as a JSON string, or `null`.
+pub(crate) fn json_string(value: Option<&str>) -> String {
+    let value = match value {
+        Some(i) => i,
+        None => return "null".to_owned(),
+    };
+
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for i in value.chars() {
+        match i {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            i if (i as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", i as u32);
+            }
+            i => out.push(i),
+        }
+    }
+    out.push('"');
+
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::{json_string, Version};
+    use crate::VERSION;
+
+    #[test]
+    fn test_json_string() {
+        assert_eq!(json_string(None), "null");
+        assert_eq!(json_string(Some("1.0")), "\"1.0\"");
+        assert_eq!(json_string(Some("a\"b\\c\n\u{1}")), r#""a\"b\\c\n\u0001""#);
+    }
+
+    #[test]
+    fn test_version_json() {
+        let version = Version::new().version("1.0.0");
+        assert_eq!(
+            version.json(),
+            format!(r#"{{"version":"1.0.0","afire":"{VERSION}","git_hash":null,"uptime":null}}"#)
+        );
+    }
+}
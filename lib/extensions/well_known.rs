@@ -0,0 +1,162 @@
+//! Serve `robots.txt`, the favicon, `security.txt` and other `/.well-known/` files from memory.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::Path,
+    time::Duration,
+};
+
+use crate::{
+    middleware::{MiddleResult, Middleware},
+    HeaderType, Method, Request, Response,
+};
+
+/// Middleware to serve the small files every site needs, like `robots.txt` and `/favicon.ico`.
+/// Each file is kept in memory and sent with its content type and a `Cache-Control` header.
+///
+/// Only `GET` and `HEAD` requests are answered, anything else is passed on to the routes.
+/// These files are requested constantly by browsers and crawlers, so [`WellKnown::log_filter`] can be used to keep them out of the [`crate::extension::Logger`].
+///
+/// ## Example
+/// ```rust
+/// # use afire::{Middleware, Server, extension::{Logger, WellKnown}};
+/// # fn test(server: &mut Server) {
+/// let well_known = WellKnown::new()
+///     .robots("User-agent: *\nDisallow: /admin\n")
+///     .security_txt("Contact: mailto:security@example.com\nExpires: 2030-01-01T00:00:00.000Z\n")
+///     .well_known("assetlinks.json", "application/json", "[]");
+///
+/// Logger::new().filter(well_known.log_filter()).attach(server);
+/// well_known.attach(server);
+/// # }
+/// ```
+pub struct WellKnown {
+    files: HashMap<String, File>,
+    max_age: Duration,
+}
+
+struct File {
+    content_type: String,
+    body: Vec<u8>,
+}
+
+impl WellKnown {
+    /// Create a new WellKnown middleware with no files.
+    /// Files are cached for a day by default, see [`WellKnown::max_age`].
+    pub fn new() -> Self {
+        Self {
+            files: HashMap::new(),
+            max_age: Duration::from_secs(60 * 60 * 24),
+        }
+    }
+
+    /// Serve `/robots.txt`.
+    pub fn robots(self, robots: impl AsRef<str>) -> Self {
+        self.path("/robots.txt", "text/plain; charset=utf-8", robots.as_ref())
+    }
+
+    /// Serve `/favicon.ico`.
+    /// The content type is worked out from the image, so PNG and SVG icons can be used as well as ICO files.
+    pub fn favicon(self, icon: impl Into<Vec<u8>>) -> Self {
+        let icon = icon.into();
+        let content_type = if icon.starts_with(b"\x89PNG") {
+            "image/png"
+        } else if icon.starts_with(b"<svg") || icon.starts_with(b"<?xml") {
+            "image/svg+xml"
+        } else {
+            "image/x-icon"
+        };
+
+        self.path("/favicon.ico", content_type, icon)
+    }
+
+    /// Serve `/favicon.ico` from a file, see [`WellKnown::favicon`].
+    /// The file is read once, when this is called.
+    pub fn favicon_file(self, path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(self.favicon(fs::read(path)?))
+    }
+
+    /// Serve `/.well-known/security.txt`, as defined in [RFC 9116](https://www.rfc-editor.org/rfc/rfc9116).
+    pub fn security_txt(self, security: impl AsRef<str>) -> Self {
+        self.well_known(
+            "security.txt",
+            "text/plain; charset=utf-8",
+            security.as_ref(),
+        )
+    }
+
+    /// Serve a file at `/.well-known/{name}`.
+    pub fn well_known(
+        self,
+        name: impl AsRef<str>,
+        content_type: impl AsRef<str>,
+        body: impl Into<Vec<u8>>,
+    ) -> Self {
+        let path = format!("/.well-known/{}", name.as_ref().trim_start_matches('/'));
+        self.path(path, content_type, body)
+    }
+
+    /// Serve a file at any path.
+    pub fn path(
+        mut self,
+        path: impl AsRef<str>,
+        content_type: impl AsRef<str>,
+        body: impl Into<Vec<u8>>,
+    ) -> Self {
+        self.files.insert(
+            path.as_ref().to_owned(),
+            File {
+                content_type: content_type.as_ref().to_owned(),
+                body: body.into(),
+            },
+        );
+        self
+    }
+
+    /// Set how long clients and caches can keep the files, for the `Cache-Control` header.
+    pub fn max_age(self, max_age: Duration) -> Self {
+        Self { max_age, ..self }
+    }
+
+    /// Gets a filter for [`crate::extension::Logger::filter`] that skips requests for the files served by this middleware.
+    /// Files added after calling this are not included.
+    pub fn log_filter(&self) -> impl Fn(&Request) -> bool + Send + Sync + 'static {
+        let paths = self.files.keys().cloned().collect::<HashSet<_>>();
+        move |req| !(matches!(req.method, Method::GET | Method::HEAD) && paths.contains(&req.path))
+    }
+}
+
+impl Middleware for WellKnown {
+    fn pre(&self, req: &mut Request) -> MiddleResult {
+        if !matches!(req.method, Method::GET | Method::HEAD) {
+            return MiddleResult::Continue;
+        }
+
+        let file = match self.files.get(&req.path) {
+            Some(i) => i,
+            None => return MiddleResult::Continue,
+        };
+
+        let res = Response::new()
+            .header(HeaderType::ContentType, &file.content_type)
+            .header(
+                "Cache-Control",
+                format!("public, max-age={}", self.max_age.as_secs()),
+            );
+        let res = match req.method {
+            Method::HEAD => res
+                .bytes(&[])
+                .header(HeaderType::ContentLength, file.body.len().to_string()),
+            _ => res.bytes(&file.body),
+        };
+
+        MiddleResult::Send(res)
+    }
+}
+
+impl Default for WellKnown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,302 @@
+//! Extractors, for writing handlers that take the parts of the request they need as arguments.
+//!
+//! A handler for [`Server::extract_route`](crate::Server::extract_route) can take any number of arguments (up to 8) that implement [`FromRequest`].
+//! Each one is pulled out of the request before the handler runs, and if one fails, its error response is sent instead.
+//! The handler can return anything that converts into a [`Response`].
+//!
+//! The extractors are:
+//! - [`Path`]: The path parameters, parsed into a tuple in the order they appear in the route.
+//! - [`Query`]: The query string, and [`QueryAs`] to deserialize it into a struct (with the `serde` feature).
+//! - [`Json`]: The body deserialized from JSON (with the `serde` feature).
+//! - [`Form`]: The body deserialized from a url encoded form (with the `serde` feature).
+//! - [`Headers`]: The request headers.
+//! - [`State`]: The server's state (See [`Server::state`](crate::Server::state)).
+//! - [`Option<T>`]: Any extractor, or `None` if it failed.
+//!
+//! ## Example
+//! ```rust
+//! # use afire::{Server, Method, Response, extract::{Path, State}};
+//! # use std::sync::atomic::{AtomicU64, Ordering};
+//! let mut server = Server::<AtomicU64>::new("localhost", 8080).state(AtomicU64::new(0));
+//!
+//! server.extract_route(
+//!     Method::GET,
+//!     "/users/{id}/posts/{post}",
+//!     |Path((id, post)): Path<(u32, u32)>, State(views): State<AtomicU64>| {
+//!         views.fetch_add(1, Ordering::Relaxed);
+//!         Response::new().text(format!("Post {post} by user {id}"))
+//!     },
+//! );
+//! ```
+
+use std::{fmt::Display, str::FromStr, sync::Arc};
+
+use crate::{error::RouteError, Request, Response};
+pub use crate::{header::Headers, Query};
+
+/// Something that can be taken from a request, to be passed to a handler.
+/// See the [module docs](self) for the extractors afire provides.
+///
+/// ## Example
+/// ```rust
+/// # use std::sync::Arc;
+/// # use afire::{Request, Response, Status, extract::FromRequest};
+/// /// The value of the `X-Api-Key` header.
+/// struct ApiKey(String);
+///
+/// impl<S> FromRequest<S> for ApiKey {
+///     fn from_request(req: &Request, _state: Option<&Arc<S>>) -> Result<Self, Response> {
+///         match req.headers.get("X-Api-Key") {
+///             Some(key) => Ok(ApiKey(key.to_owned())),
+///             None => Err(Response::new().status(Status::Unauthorized)),
+///         }
+///     }
+/// }
+/// ```
+pub trait FromRequest<S>: Sized {
+    /// If this extractor needs the server's state.
+    /// Routes using one are stateful, so starting the server without a state returns an error.
+    const STATEFUL: bool = false;
+
+    /// Takes the value from the request, or returns the response to send instead.
+    /// `state` is the server's state, which is only passed to routes using a [`FromRequest::STATEFUL`] extractor.
+    fn from_request(req: &Request, state: Option<&Arc<S>>) -> Result<Self, Response>;
+}
+
+/// A function that can handle requests with extractors, see [`Server::extract_route`](crate::Server::extract_route).
+/// This is implemented for functions taking up to 8 [`FromRequest`] arguments and returning anything that converts into a [`Response`].
+pub trait Handler<S, Args>: Send + Sync + 'static {
+    /// If any of the handler's arguments need the server's state.
+    const STATEFUL: bool;
+
+    /// Extracts the arguments from the request and runs the handler.
+    fn call(&self, req: &Request, state: Option<&Arc<S>>) -> Response;
+}
+
+/// The path parameters of a request, parsed into a tuple in the order they are defined in the route.
+/// Each value is url decoded and parsed with [`FromStr`], and a `400 Bad Request` is sent if one is missing or invalid.
+/// ## Example
+/// ```rust
+/// # use afire::{Server, Method, Response, extract::Path};
+/// # let mut server = Server::<()>::new("localhost", 8080);
+/// server.extract_route(Method::GET, "/users/{id}", |Path((id,)): Path<(u64,)>| {
+///     Response::new().text(format!("User #{id}"))
+/// });
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Path<T>(pub T);
+
+/// The query string of a request, deserialized into a struct.
+/// Values are parsed from strings like [`Request::form_as`].
+/// Only available with the `serde` feature.
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryAs<T>(pub T);
+
+/// The body of a request, deserialized from JSON with [`Request::json`].
+/// Only available with the `serde` feature.
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Json<T>(pub T);
+
+/// The body of a request, deserialized from a url encoded form with [`Request::form_as`].
+/// Only available with the `serde` feature.
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Form<T>(pub T);
+
+/// The server's state, see [`Server::state`](crate::Server::state).
+#[derive(Debug)]
+pub struct State<S>(pub Arc<S>);
+
+/// Tuples of path parameters, see [`Path`].
+pub trait PathParams: Sized {
+    /// Parses the path parameters, given the names of the route's parameters in the order they are defined.
+    fn from_params(req: &Request, names: &[&str]) -> Result<Self, RouteError>;
+}
+
+impl<S, T: PathParams> FromRequest<S> for Path<T> {
+    fn from_request(req: &Request, _state: Option<&Arc<S>>) -> Result<Self, Response> {
+        let params = req.path_params.get().map(Vec::as_slice).unwrap_or_default();
+        let names = params.iter().map(|x| x.0.as_str()).collect::<Vec<_>>();
+        Ok(Path(T::from_params(req, &names)?))
+    }
+}
+
+impl<S> FromRequest<S> for Query {
+    fn from_request(req: &Request, _state: Option<&Arc<S>>) -> Result<Self, Response> {
+        Ok(req.query.clone())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<S, T: serde::de::DeserializeOwned> FromRequest<S> for QueryAs<T> {
+    fn from_request(req: &Request, _state: Option<&Arc<S>>) -> Result<Self, Response> {
+        crate::internal::encoding::form::from_query(&req.query)
+            .map(QueryAs)
+            .map_err(|e| {
+                let query = req.query.to_string();
+                RouteError::Invalid("query".to_owned(), query, e.to_string()).into()
+            })
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<S, T: serde::de::DeserializeOwned> FromRequest<S> for Json<T> {
+    fn from_request(req: &Request, _state: Option<&Arc<S>>) -> Result<Self, Response> {
+        Ok(Json(req.json()?))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<S, T: serde::de::DeserializeOwned> FromRequest<S> for Form<T> {
+    fn from_request(req: &Request, _state: Option<&Arc<S>>) -> Result<Self, Response> {
+        Ok(Form(req.form_as()?))
+    }
+}
+
+impl<S> FromRequest<S> for Headers {
+    fn from_request(req: &Request, _state: Option<&Arc<S>>) -> Result<Self, Response> {
+        Ok(req.headers.clone())
+    }
+}
+
+impl<S> FromRequest<S> for State<S> {
+    const STATEFUL: bool = true;
+
+    fn from_request(_req: &Request, state: Option<&Arc<S>>) -> Result<Self, Response> {
+        Ok(State(state.expect("State not initialized").clone()))
+    }
+}
+
+impl<S, T: FromRequest<S>> FromRequest<S> for Option<T> {
+    const STATEFUL: bool = T::STATEFUL;
+
+    fn from_request(req: &Request, state: Option<&Arc<S>>) -> Result<Self, Response> {
+        Ok(T::from_request(req, state).ok())
+    }
+}
+
+/// Parses the path parameter at an index, see [`Path`].
+fn path_param<T>(req: &Request, names: &[&str], i: usize) -> Result<T, RouteError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    match names.get(i) {
+        Some(name) => req.param_parsed(name),
+        None => RouteError::parse(|| format!("path parameter {i}"), None),
+    }
+}
+
+/// Implements [`PathParams`] for a tuple.
+macro_rules! path_params {
+    ($($i:tt $T:ident),*) => {
+        impl<$($T),*> PathParams for ($($T,)*)
+        where
+            $($T: FromStr, $T::Err: Display,)*
+        {
+            fn from_params(req: &Request, names: &[&str]) -> Result<Self, RouteError> {
+                Ok(($(path_param(req, names, $i)?,)*))
+            }
+        }
+    };
+}
+
+/// Implements [`Handler`] for functions with some number of arguments.
+macro_rules! handler {
+    ($($T:ident),*) => {
+        impl<S, F, R, $($T),*> Handler<S, ($($T,)*)> for F
+        where
+            F: Fn($($T),*) -> R + Send + Sync + 'static,
+            R: Into<Response>,
+            $($T: FromRequest<S>,)*
+        {
+            const STATEFUL: bool = false $(|| $T::STATEFUL)*;
+
+            #[allow(non_snake_case, unused_variables)]
+            fn call(&self, req: &Request, state: Option<&Arc<S>>) -> Response {
+                $(
+                    let $T = match $T::from_request(req, state) {
+                        Ok(i) => i,
+                        Err(res) => return res,
+                    };
+                )*
+                (self)($($T),*).into()
+            }
+        }
+    };
+}
+
+path_params!(0 A);
+path_params!(0 A, 1 B);
+path_params!(0 A, 1 B, 2 C);
+path_params!(0 A, 1 B, 2 C, 3 D);
+path_params!(0 A, 1 B, 2 C, 3 D, 4 E);
+path_params!(0 A, 1 B, 2 C, 3 D, 4 E, 5 G);
+
+handler!();
+handler!(A);
+handler!(A, B);
+handler!(A, B, C);
+handler!(A, B, C, D);
+handler!(A, B, C, D, E);
+handler!(A, B, C, D, E, G);
+handler!(A, B, C, D, E, G, H);
+handler!(A, B, C, D, E, G, H, I);
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::{Headers, Path, Query, State};
+    use crate::{
+        testing::{TestRequest, TestServer},
+        Method, Response, Server, Status,
+    };
+
+    #[test]
+    fn test_extractors() {
+        let mut server = Server::<AtomicU32>::new("localhost", 8080).state(AtomicU32::new(0));
+        server.extract_route(
+            Method::GET,
+            "/add/{a}/{b}",
+            |Path((a, b)): Path<(u32, u32)>, query: Query, State(count): State<AtomicU32>| {
+                count.fetch_add(1, Ordering::Relaxed);
+                let c = query.get_parsed::<u32>("c").unwrap_or(0);
+                Response::new().text(a + b + c)
+            },
+        );
+        server.extract_route(Method::GET, "/agent", |headers: Headers| {
+            Response::new().text(headers.get("User-Agent").unwrap_or("none"))
+        });
+        let state = server.state.clone().unwrap();
+        let server = TestServer::from(server);
+
+        let res = TestRequest::get("/add/1/2?c=3").send(&server);
+        assert_eq!(res.text(), "6");
+        assert_eq!(state.load(Ordering::Relaxed), 1);
+
+        let res = TestRequest::get("/add/1/two").send(&server);
+        assert_eq!(res.status, Status::BadRequest);
+        assert_eq!(state.load(Ordering::Relaxed), 1);
+
+        let res = TestRequest::get("/agent")
+            .header("User-Agent", "afire")
+            .send(&server);
+        assert_eq!(res.text(), "afire");
+    }
+
+    #[test]
+    fn test_stateless_extractors() {
+        let mut server = Server::<()>::new("localhost", 8080);
+        server.extract_route(Method::GET, "/{id}", |id: Option<Path<(u8,)>>| {
+            Response::new().text(id.map_or(0, |Path((id,))| id))
+        });
+        assert!(!server.routes[0].is_stateful());
+        let server = TestServer::from(server);
+
+        assert_eq!(TestRequest::get("/5").send(&server).text(), "5");
+        assert_eq!(TestRequest::get("/500").send(&server).text(), "0");
+    }
+}
@@ -0,0 +1,118 @@
+//! Guards, for routes that should only match requests with something extra, like a certain host or header.
+//!
+//! Add them to a route with [`Route::guard`](crate::Route::guard).
+//! If any of a route's guards don't pass, the route is skipped and the request can match other routes, as if the path had not matched.
+//! Any `Fn(&Request) -> bool` can be used as a guard, along with the ones in this module.
+//! ## Example
+//! ```rust
+//! # use afire::{Server, Response, Method, guard::{Host, HeaderEquals}};
+//! # let mut server = Server::<()>::new("localhost", 8080);
+//! server.route(Method::GET, "/", |_| Response::new().text("Home"));
+//! server
+//!     .route(Method::GET, "/", |_| Response::new().text("Admin"))
+//!     .guard(Host("admin.example.com"))
+//!     .guard(HeaderEquals("X-Key", "secret"));
+//! ```
+
+use std::{
+    any::type_name,
+    fmt::{self, Debug},
+};
+
+use crate::{HeaderType, Request};
+
+/// A check a request has to pass for a route to match it.
+/// See the [module level docs](self) for more.
+pub trait Guard: Send + Sync {
+    /// Checks if the route should match the request.
+    fn check(&self, req: &Request) -> bool;
+
+    /// The name of this guard, used when debug printing routes.
+    /// Defaults to the type name.
+    fn name(&self) -> &str {
+        type_name::<Self>()
+    }
+}
+
+/// Matches requests for a host, from the `Host` header.
+/// The port and case of the host are ignored.
+pub struct Host<T>(pub T);
+
+/// Matches requests that have a header, with any value.
+pub struct HasHeader<N>(pub N);
+
+/// Matches requests where a header has a value.
+/// If the header is sent more than once, the first one is checked.
+pub struct HeaderEquals<N, V>(pub N, pub V);
+
+/// Matches requests that have a query parameter, with any value.
+pub struct HasQuery<N>(pub N);
+
+/// Matches requests where a query parameter has a value.
+pub struct QueryEquals<N, V>(pub N, pub V);
+
+impl<F: Fn(&Request) -> bool + Send + Sync> Guard for F {
+    fn check(&self, req: &Request) -> bool {
+        self(req)
+    }
+}
+
+impl<T: AsRef<str> + Send + Sync> Guard for Host<T> {
+    fn check(&self, req: &Request) -> bool {
+        req.headers
+            .get(HeaderType::Host)
+            .is_some_and(|x| strip_port(x).eq_ignore_ascii_case(self.0.as_ref()))
+    }
+}
+
+impl<N: AsRef<str> + Send + Sync> Guard for HasHeader<N> {
+    fn check(&self, req: &Request) -> bool {
+        req.headers.has(self.0.as_ref())
+    }
+}
+
+impl<N: AsRef<str> + Send + Sync, V: AsRef<str> + Send + Sync> Guard for HeaderEquals<N, V> {
+    fn check(&self, req: &Request) -> bool {
+        req.headers.get(self.0.as_ref()) == Some(self.1.as_ref())
+    }
+}
+
+impl<N: AsRef<str> + Send + Sync> Guard for HasQuery<N> {
+    fn check(&self, req: &Request) -> bool {
+        req.query.has(self.0.as_ref())
+    }
+}
+
+impl<N: AsRef<str> + Send + Sync, V: AsRef<str> + Send + Sync> Guard for QueryEquals<N, V> {
+    fn check(&self, req: &Request) -> bool {
+        req.query.get(self.0.as_ref()) == Some(self.1.as_ref())
+    }
+}
+
+impl Debug for dyn Guard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Removes the port from a host, like `example.com:8080` or `[::1]:8080`.
+fn strip_port(host: &str) -> &str {
+    let host = host.trim();
+    match host.rfind(':') {
+        Some(i) if !host[i..].contains(']') => &host[..i],
+        _ => host,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::strip_port;
+
+    #[test]
+    fn test_strip_port() {
+        assert_eq!(strip_port("example.com"), "example.com");
+        assert_eq!(strip_port("example.com:8080"), "example.com");
+        assert_eq!(strip_port("[::1]:8080"), "[::1]");
+        assert_eq!(strip_port("[::1]"), "[::1]");
+    }
+}
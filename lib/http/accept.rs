@@ -0,0 +1,181 @@
+//! Content negotiation with the `Accept` and `Accept-Language` headers.
+//! See [`Request::negotiate`](crate::Request::negotiate) and [`Request::negotiate_language`](crate::Request::negotiate_language) for the easy way to use these.
+
+use crate::{header::parse_list, Content};
+
+/// A parsed `Accept` header, listing the media types the client wants and how much it wants them.
+/// ## Example
+/// ```rust
+/// # use afire::{Content, accept::Accept};
+/// let accept = Accept::parse("text/html, application/json;q=0.9, */*;q=0.1");
+/// assert_eq!(accept.negotiate(&[Content::JSON, Content::HTML]), Some(Content::HTML));
+/// assert_eq!(accept.negotiate(&[Content::CSV]), Some(Content::CSV));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Accept {
+    /// The media ranges, in the order they were given.
+    pub ranges: Vec<MediaRange>,
+}
+
+/// One media range of an `Accept` header, like `text/*;q=0.5`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaRange {
+    /// The type, like `text`, or `*`.
+    pub kind: String,
+    /// The subtype, like `html`, or `*`.
+    pub subtype: String,
+    /// The quality value, from 0 to 1.
+    /// A quality of 0 means the type is not acceptable.
+    pub quality: f32,
+}
+
+/// A parsed `Accept-Language` header, listing the languages the client wants and how much it wants them.
+/// ## Example
+/// ```rust
+/// # use afire::accept::AcceptLanguage;
+/// let accept = AcceptLanguage::parse("fr-CH, fr;q=0.9, en;q=0.8");
+/// assert_eq!(accept.negotiate(&["en", "fr-FR"]), Some("fr-FR"));
+/// assert_eq!(accept.negotiate(&["de"]), None);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct AcceptLanguage {
+    /// The language ranges and their quality values, in the order they were given.
+    /// A range is a language tag like `en-US`, or `*`.
+    pub ranges: Vec<(String, f32)>,
+}
+
+impl Accept {
+    /// Parses an `Accept` header.
+    /// Invalid media ranges are skipped, and parameters other than `q` are ignored.
+    pub fn parse(header: &str) -> Self {
+        let ranges = quality_list(header)
+            .filter_map(|(range, quality)| {
+                let (kind, subtype) = range.split_once('/')?;
+                Some(MediaRange {
+                    kind: kind.trim().to_ascii_lowercase(),
+                    subtype: subtype.trim().to_ascii_lowercase(),
+                    quality,
+                })
+            })
+            .collect();
+
+        Self { ranges }
+    }
+
+    /// Gets the quality of a media type, from the most specific range that matches it.
+    /// Types that no range matches have a quality of 0.
+    pub fn quality(&self, mime: &str) -> f32 {
+        let mime = mime.split(';').next().unwrap_or_default().trim();
+        let (kind, subtype) = mime.split_once('/').unwrap_or((mime, ""));
+
+        self.ranges
+            .iter()
+            .filter_map(|range| {
+                let specificity = match (range.kind.as_str(), range.subtype.as_str()) {
+                    ("*", "*") => 0,
+                    (k, "*") if k.eq_ignore_ascii_case(kind) => 1,
+                    (k, s) if k.eq_ignore_ascii_case(kind) && s.eq_ignore_ascii_case(subtype) => 2,
+                    _ => return None,
+                };
+                Some((specificity, range.quality))
+            })
+            .max_by_key(|x| x.0)
+            .map_or(0.0, |x| x.1)
+    }
+
+    /// Picks the content type the client would most like from the ones the server can send.
+    /// Ties are broken by the order of `offered`, so put the one you prefer first.
+    /// Returns `None` if the client accepts none of them, in which case you may want to send a `406 Not Acceptable`.
+    pub fn negotiate<'a>(&self, offered: &[Content<'a>]) -> Option<Content<'a>> {
+        best(offered.iter().copied(), |x| self.quality(x.as_type()))
+    }
+}
+
+impl AcceptLanguage {
+    /// Parses an `Accept-Language` header.
+    pub fn parse(header: &str) -> Self {
+        let ranges = quality_list(header)
+            .map(|(range, quality)| (range.to_owned(), quality))
+            .collect();
+        Self { ranges }
+    }
+
+    /// Gets the quality of a language tag, from the longest range that matches it.
+    /// A range matches a tag if it is the same or a prefix of it ending at a `-`, so `en` matches `en-US` (RFC 4647 basic filtering).
+    /// Tags that no range matches have a quality of 0.
+    pub fn quality(&self, tag: &str) -> f32 {
+        self.ranges
+            .iter()
+            .filter(|(range, _)| {
+                range == "*"
+                    || range.eq_ignore_ascii_case(tag)
+                    || tag.get(..range.len()).is_some_and(|x| {
+                        x.eq_ignore_ascii_case(range) && tag.as_bytes()[range.len()] == b'-'
+                    })
+            })
+            .max_by_key(|(range, _)| if range == "*" { 0 } else { range.len() })
+            .map_or(0.0, |x| x.1)
+    }
+
+    /// Picks the language the client would most like from the ones the server has.
+    /// Ties are broken by the order of `available`, so put the default first.
+    pub fn negotiate<'a>(&self, available: &[&'a str]) -> Option<&'a str> {
+        best(available.iter().copied(), |x| self.quality(x))
+    }
+}
+
+/// Finds the item with the highest quality above 0, preferring earlier items on ties.
+fn best<T>(items: impl Iterator<Item = T>, quality: impl Fn(&T) -> f32) -> Option<T> {
+    let mut best = None;
+    for item in items {
+        let q = quality(&item);
+        if q > 0.0 && best.as_ref().is_none_or(|(_, best)| q > *best) {
+            best = Some((item, q));
+        }
+    }
+
+    best.map(|x| x.0)
+}
+
+/// Splits a header like `Accept` into its values and their quality (`q`) parameters, which default to 1.
+/// Values with an invalid quality are skipped.
+pub(crate) fn quality_list(header: &str) -> impl Iterator<Item = (&str, f32)> {
+    parse_list(header).filter_map(|x| Some((x.name, x.quality()?)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Accept, AcceptLanguage};
+    use crate::Content;
+
+    #[test]
+    fn test_accept() {
+        let accept = Accept::parse("text/*;q=0.5, text/html, application/json;q=0.8, */*;q=0.1");
+        assert_eq!(accept.quality("text/html; charset=utf-8"), 1.0);
+        assert_eq!(accept.quality("text/csv"), 0.5);
+        assert_eq!(accept.quality("image/png"), 0.1);
+
+        let offered = [Content::JSON, Content::CSV, Content::HTML];
+        assert_eq!(accept.negotiate(&offered), Some(Content::HTML));
+        assert_eq!(accept.negotiate(&offered[..2]), Some(Content::JSON));
+
+        let accept = Accept::parse("application/json;q=0, text/plain");
+        assert_eq!(accept.negotiate(&[Content::JSON]), None);
+        assert_eq!(
+            accept.negotiate(&[Content::JSON, Content::TXT]),
+            Some(Content::TXT)
+        );
+    }
+
+    #[test]
+    fn test_accept_language() {
+        let accept = AcceptLanguage::parse("en-US, en;q=0.7, *;q=0.1, de;q=0");
+        assert_eq!(accept.quality("en-us"), 1.0);
+        assert_eq!(accept.quality("en-GB"), 0.7);
+        assert_eq!(accept.quality("eng"), 0.1);
+        assert_eq!(accept.quality("de-AT"), 0.0);
+
+        assert_eq!(accept.negotiate(&["fr", "en-GB"]), Some("en-GB"));
+        assert_eq!(accept.negotiate(&["de"]), None);
+    }
+}
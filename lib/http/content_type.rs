@@ -1,7 +1,7 @@
-use crate::Header;
+use crate::{mime, Header};
 
 /// Common MIME types.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Content<'a> {
     /// HTML - `text/html`
     HTML,
@@ -29,11 +29,29 @@ impl Content<'_> {
             Content::Custom(i) => i,
         }
     }
+
+    /// Gets the Content for a file extension, from the [`mime`] registry.
+    /// ## Example
+    /// ```rust
+    /// # use afire::Content;
+    /// assert_eq!(Content::from_extension("html"), Some(Content::HTML));
+    /// assert_eq!(Content::from_extension("png"), Some(Content::Custom("image/png")));
+    /// ```
+    pub fn from_extension(extension: &str) -> Option<Content<'static>> {
+        Some(match mime::from_extension(extension)? {
+            "text/html" => Content::HTML,
+            "text/plain" => Content::TXT,
+            "text/csv" => Content::CSV,
+            "application/json" => Content::JSON,
+            "application/xml" => Content::XML,
+            i => Content::Custom(i),
+        })
+    }
 }
 
 impl From<Content<'_>> for Header {
-    // Convert Content to a Content-Type Header
+    // Convert Content to a Content-Type Header, with the charset from the mime registry
     fn from(x: Content<'_>) -> Self {
-        Header::new("Content-Type", format!("{}; charset=utf-8", x.as_type()))
+        Header::new("Content-Type", mime::content_type(x.as_type()))
     }
 }
@@ -3,11 +3,12 @@
 //! This module provides a simple interface for setting and receiving cookies.
 
 use std::{
-    fmt,
+    error, fmt,
     ops::{Deref, DerefMut},
+    time::SystemTime,
 };
 
-use crate::encoding::url;
+use crate::{date::http_date, encoding::url};
 
 /// Represents a Cookie
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
@@ -21,6 +22,8 @@ pub struct Cookie {
 
 /// Represents a Set-Cookie header.
 /// Has more information than a normal Cookie (e.g. max-age, domain, path, secure).
+///
+/// Cookies with a `__Secure-` or `__Host-` prefix are only accepted by browsers if they have the right attributes, which can be checked with [`SetCookie::validate`].
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
 pub struct SetCookie {
     /// Base Cookie
@@ -38,6 +41,41 @@ pub struct SetCookie {
 
     /// Cookie is secure
     pub secure: bool,
+
+    /// Cookie can't be read by scripts
+    pub http_only: bool,
+
+    /// Cookie SameSite policy, which controls if it is sent with cross-site requests
+    pub same_site: Option<SameSite>,
+
+    /// Cookie Expires.
+    /// The time the cookie expires, if [`SetCookie::max_age`] isn't set.
+    pub expires: Option<SystemTime>,
+}
+
+/// The SameSite attribute of a [`SetCookie`].
+/// See [MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Set-Cookie#samesitesamesite-value) for more information.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum SameSite {
+    /// Only send the cookie with requests from the same site.
+    Strict,
+    /// Also send the cookie when navigating to the site from another one.
+    /// This is the default in most browsers.
+    Lax,
+    /// Send the cookie with all requests, including cross-site ones.
+    /// The cookie must also be [`SetCookie::secure`].
+    None,
+}
+
+/// Why a [`SetCookie`] would be rejected by browsers, from [`SetCookie::validate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CookieError {
+    /// Cookies with a `__Secure-` prefix must be secure.
+    SecurePrefix,
+    /// Cookies with a `__Host-` prefix must be secure, have a path of `/` and no domain.
+    HostPrefix,
+    /// Cookies with `SameSite=None` must be secure.
+    SameSiteNone,
 }
 
 /// A collection of Cookies.
@@ -91,6 +129,9 @@ impl SetCookie {
             domain: None,
             path: None,
             secure: false,
+            http_only: false,
+            same_site: None,
+            expires: None,
         }
     }
 
@@ -157,8 +198,114 @@ impl SetCookie {
         new.secure = secure;
         new
     }
+
+    /// Set the HttpOnly field of a SetCookie.
+    /// HttpOnly cookies can't be read by scripts, which helps protect them from cross-site scripting.
+    /// ## Example
+    /// ```
+    /// # use afire::SetCookie;
+    /// let mut cookie = SetCookie::new("name", "value")
+    ///     .http_only(true);
+    ///
+    /// assert_eq!(cookie.http_only, true);
+    /// ```
+    pub fn http_only(self, http_only: bool) -> SetCookie {
+        SetCookie { http_only, ..self }
+    }
+
+    /// Set the SameSite field of a SetCookie.
+    /// ## Example
+    /// ```
+    /// # use afire::{SetCookie, cookie::SameSite};
+    /// let mut cookie = SetCookie::new("name", "value")
+    ///     .same_site(SameSite::Strict);
+    ///
+    /// assert_eq!(cookie.to_string(), "name=value; SameSite=Strict");
+    /// ```
+    pub fn same_site(self, same_site: SameSite) -> SetCookie {
+        SetCookie {
+            same_site: Some(same_site),
+            ..self
+        }
+    }
+
+    /// Set the Expires field of a SetCookie.
+    /// This is the time the cookie stops being valid, and is sent as an HTTP-date.
+    /// If [`SetCookie::max_age`] is also set, browsers will use that instead.
+    /// ## Example
+    /// ```
+    /// # use afire::SetCookie;
+    /// # use std::time::{Duration, UNIX_EPOCH};
+    /// let mut cookie = SetCookie::new("name", "value")
+    ///     .expires(UNIX_EPOCH + Duration::from_secs(1675899597));
+    ///
+    /// assert_eq!(cookie.to_string(), "name=value; Expires=Wed, 08 Feb 2023 23:39:57 GMT");
+    /// ```
+    pub fn expires(self, expires: SystemTime) -> SetCookie {
+        SetCookie {
+            expires: Some(expires),
+            ..self
+        }
+    }
+
+    /// Checks that the cookie has the attributes browsers require for its name prefix and SameSite policy.
+    /// - `__Secure-` cookies must be secure.
+    /// - `__Host-` cookies must be secure, have a path of `/` and no domain.
+    /// - `SameSite=None` cookies must be secure.
+    ///
+    /// Browsers silently ignore cookies that break these rules.
+    /// [`crate::Response::cookie`] logs an error for them.
+    /// ## Example
+    /// ```
+    /// # use afire::{SetCookie, cookie::CookieError};
+    /// let cookie = SetCookie::new("__Host-id", "1234").secure(true);
+    /// assert_eq!(cookie.validate(), Err(CookieError::HostPrefix));
+    /// assert_eq!(cookie.path("/").validate(), Ok(()));
+    /// ```
+    pub fn validate(&self) -> Result<(), CookieError> {
+        let name = &self.cookie.name;
+        if name.starts_with("__Secure-") && !self.secure {
+            return Err(CookieError::SecurePrefix);
+        }
+
+        if name.starts_with("__Host-")
+            && (!self.secure || self.path.as_deref() != Some("/") || self.domain.is_some())
+        {
+            return Err(CookieError::HostPrefix);
+        }
+
+        if self.same_site == Some(SameSite::None) && !self.secure {
+            return Err(CookieError::SameSiteNone);
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for SameSite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            SameSite::Strict => "Strict",
+            SameSite::Lax => "Lax",
+            SameSite::None => "None",
+        })
+    }
 }
 
+impl fmt::Display for CookieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            CookieError::SecurePrefix => "cookies with a `__Secure-` prefix must be secure",
+            CookieError::HostPrefix => {
+                "cookies with a `__Host-` prefix must be secure, have a path of `/` and no domain"
+            }
+            CookieError::SameSiteNone => "cookies with `SameSite=None` must be secure",
+        })
+    }
+}
+
+impl error::Error for CookieError {}
+
 impl CookieJar {
     /// Create a new empty CookieJar.
     pub fn new() -> CookieJar {
@@ -302,35 +449,43 @@ impl fmt::Display for Cookie {
 // Impl Display for SetCookie
 impl fmt::Display for SetCookie {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let mut cookie_string = format!("{}={}; ", self.cookie.name, self.cookie.value);
+        write!(f, "{}={}", self.cookie.name, self.cookie.value)?;
 
-        // Add max_age
-        if self.max_age.is_some() {
-            cookie_string.push_str(&format!("Max-Age={}; ", self.max_age.unwrap()));
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={max_age}")?;
         }
 
-        // Add domain
-        if self.domain.is_some() {
-            cookie_string.push_str(&format!("Domain={}; ", self.domain.as_ref().unwrap()));
+        if let Some(expires) = self.expires {
+            write!(f, "; Expires={}", http_date(expires))?;
         }
 
-        // Add path
-        if self.path.is_some() {
-            cookie_string.push_str(&format!("Path={}; ", self.path.as_ref().unwrap()));
+        if let Some(domain) = &self.domain {
+            write!(f, "; Domain={domain}")?;
+        }
+
+        if let Some(path) = &self.path {
+            write!(f, "; Path={path}")?;
         }
 
-        // Add secure
         if self.secure {
-            cookie_string.push_str("Secure; ");
+            f.write_str("; Secure")?;
+        }
+
+        if self.http_only {
+            f.write_str("; HttpOnly")?;
         }
 
-        f.write_str(cookie_string.trim_end())
+        if let Some(same_site) = self.same_site {
+            write!(f, "; SameSite={same_site}")?;
+        }
+
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::Cookie;
+    use super::{Cookie, CookieError, SameSite, SetCookie};
 
     #[test]
     fn test_cookie_parse() {
@@ -355,4 +510,35 @@ mod test {
         assert_eq!(cookies[1].name, "name3");
         assert_eq!(cookies[1].value, "value3");
     }
+
+    #[test]
+    fn test_set_cookie_string() {
+        let cookie = SetCookie::new("name", "value")
+            .max_age(60)
+            .domain("example.com")
+            .path("/")
+            .secure(true)
+            .http_only(true)
+            .same_site(SameSite::Lax);
+        assert_eq!(
+            cookie.to_string(),
+            "name=value; Max-Age=60; Domain=example.com; Path=/; Secure; HttpOnly; SameSite=Lax"
+        );
+        assert_eq!(SetCookie::new("name", "value").to_string(), "name=value");
+    }
+
+    #[test]
+    fn test_cookie_prefix() {
+        let cookie = SetCookie::new("__Secure-id", "1");
+        assert_eq!(cookie.validate(), Err(CookieError::SecurePrefix));
+        assert_eq!(cookie.secure(true).validate(), Ok(()));
+
+        let cookie = SetCookie::new("__Host-id", "1").secure(true).path("/");
+        assert_eq!(cookie.validate(), Ok(()));
+        let cookie = cookie.domain("example.com");
+        assert_eq!(cookie.validate(), Err(CookieError::HostPrefix));
+
+        let cookie = SetCookie::new("id", "1").same_site(SameSite::None);
+        assert_eq!(cookie.validate(), Err(CookieError::SameSiteNone));
+    }
 }
@@ -0,0 +1,108 @@
+//! Formatting dates for HTTP headers, like `Date`, `Expires` and `Last-Modified`.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const DAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Returns the number of days in a month.
+/// Month is 1-indexed.
+fn days_in_month(month: u8, year: u16) -> u8 {
+    match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if year % 4 == 0 => 29,
+        2 => 28,
+        _ => unreachable!("Invalid month: {}", month),
+    }
+}
+
+/// Splits a unix timestamp into its year, month (1-indexed), day of the month (0-indexed), hours, minutes, seconds and weekday.
+fn split_epoch(epoch: u64) -> (u16, u8, u16, u64, u64, u64, u16) {
+    let seconds = epoch % 60;
+    let minutes = (epoch / 60) % 60;
+    let hours = (epoch / 3600) % 24;
+    let mut days = (epoch / 86400) as u16;
+    let weekday = (days + 4) % 7;
+
+    let mut year = 1970;
+    let mut month = 1;
+    while days >= days_in_month(month, year) as u16 {
+        days -= days_in_month(month, year) as u16;
+        month += 1;
+        if month > 12 {
+            month = 1;
+            year += 1;
+        }
+    }
+
+    (year, month, days, hours, minutes, seconds, weekday)
+}
+
+/// Formats a unix timestamp as an HTTP-date, in the IMF-fixdate format (as defined in [RFC 9110, Section 5.6.7](https://www.rfc-editor.org/rfc/rfc9110.html#section-5.6.7)).
+/// Example: `Sun, 06 Nov 1994 08:49:37 GMT`
+pub fn imp_date(epoch: u64) -> String {
+    let (year, month, days, hours, minutes, seconds, weekday) = split_epoch(epoch);
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        DAYS[weekday as usize],
+        days + 1,
+        MONTHS[month as usize - 1],
+        year,
+        hours,
+        minutes,
+        seconds
+    )
+}
+
+/// Returns the date in the format used by the Common Log Format.
+/// Example: `06/Nov/1994:08:49:37 +0000`
+#[cfg(feature = "extensions")]
+pub(crate) fn clf_date(epoch: u64) -> String {
+    let (year, month, days, hours, minutes, seconds, _) = split_epoch(epoch);
+    format!(
+        "{:02}/{}/{}:{:02}:{:02}:{:02} +0000",
+        days + 1,
+        MONTHS[month as usize - 1],
+        year,
+        hours,
+        minutes,
+        seconds
+    )
+}
+
+/// Formats a [`SystemTime`] as an HTTP-date, see [`imp_date`].
+/// Times before the unix epoch are clamped to it.
+pub fn http_date(time: SystemTime) -> String {
+    let epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    imp_date(epoch.as_secs())
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[cfg(feature = "extensions")]
+    use super::clf_date;
+    use super::{http_date, imp_date};
+
+    #[test]
+    fn test_epoch() {
+        assert_eq!(imp_date(0), "Thu, 01 Jan 1970 00:00:00 GMT");
+        assert_eq!(imp_date(123456), "Fri, 02 Jan 1970 10:17:36 GMT");
+        assert_eq!(imp_date(1675899597), "Wed, 08 Feb 2023 23:39:57 GMT");
+        assert_eq!(
+            http_date(UNIX_EPOCH + Duration::from_secs(1675899597)),
+            "Wed, 08 Feb 2023 23:39:57 GMT"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "extensions")]
+    fn test_clf_date() {
+        assert_eq!(clf_date(0), "01/Jan/1970:00:00:00 +0000");
+        assert_eq!(clf_date(1675899597), "08/Feb/2023:23:39:57 +0000");
+    }
+}
@@ -1,8 +1,11 @@
 //! HTTP headers.
 
 use std::{
+    borrow::Cow,
     fmt::{self, Display},
+    iter,
     ops::{Deref, DerefMut},
+    str,
 };
 
 use crate::error::{ParseError, Result};
@@ -38,6 +41,29 @@ pub struct HeaderParams<'a> {
     params: Vec<[&'a str; 2]>,
 }
 
+/// One item of a comma-separated list header, like `text/html;q=0.9` in `Accept` or `max-age=60` in `Cache-Control`.
+/// Use [`Header::list`], [`Headers::list`] or [`parse_list`] to get them.
+/// Values in quoted strings have their quotes and escapes removed.
+/// ## Example
+/// ```rust
+/// # use afire::header::parse_list;
+/// let items = parse_list(r#"gzip;q=0.8, br, private="Set-Cookie, Vary""#).collect::<Vec<_>>();
+/// assert_eq!(items.len(), 3);
+/// assert_eq!(items[0].name, "gzip");
+/// assert_eq!(items[0].quality(), Some(0.8));
+/// assert_eq!(items[1].quality(), Some(1.0));
+/// assert_eq!(items[2].value.as_deref(), Some("Set-Cookie, Vary"));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListItem<'a> {
+    /// The item itself, like `text/html` or `max-age`.
+    pub name: &'a str,
+    /// The value after an `=` in the item, like `60` in `max-age=60`.
+    pub value: Option<Cow<'a, str>>,
+    /// The parameters after the item, which are separated by semicolons.
+    pub params: Vec<(&'a str, Cow<'a, str>)>,
+}
+
 /// Collection of headers.
 /// Used within [`Request`](crate::Request) and [`Response`](crate::Response).
 #[derive(Debug, Hash, Clone, PartialEq, Eq, Default)]
@@ -92,9 +118,15 @@ impl Header {
     }
 
     /// Get the parameters of the header.
-    pub fn params(&self) -> HeaderParams {
+    pub fn params(&self) -> HeaderParams<'_> {
         HeaderParams::new(self.value.as_str())
     }
+
+    /// Parses the value as a comma-separated list, like the `Accept` or `Cache-Control` headers.
+    /// See [`ListItem`].
+    pub fn list(&self) -> impl Iterator<Item = ListItem<'_>> {
+        parse_list(&self.value)
+    }
 }
 
 impl<'a> HeaderParams<'a> {
@@ -133,7 +165,7 @@ impl<'a> HeaderParams<'a> {
 
     /// Gets the value of the specified parameter, returning `None` if it is not present.
     /// A parameter is a key-value pair that is separated by a semicolon and a space.
-    pub fn get(&self, name: impl AsRef<str>) -> Option<&str> {
+    pub fn get(&self, name: impl AsRef<str>) -> Option<&'a str> {
         let name = name.as_ref();
         self.params
             .iter()
@@ -142,6 +174,109 @@ impl<'a> HeaderParams<'a> {
     }
 }
 
+impl<'a> ListItem<'a> {
+    fn parse(item: &'a str) -> Option<Self> {
+        let mut parts = split_quoted(item, b';');
+        let first = parts.next()?.trim();
+        let (name, value) = match first.split_once('=') {
+            Some((name, value)) => (name.trim(), Some(unquote(value))),
+            None => (first, None),
+        };
+
+        if name.is_empty() {
+            return None;
+        }
+
+        let params = parts
+            .filter_map(|x| x.split_once('='))
+            .map(|(key, value)| (key.trim(), unquote(value)))
+            .collect();
+
+        Some(Self {
+            name,
+            value,
+            params,
+        })
+    }
+
+    /// Gets the value of a parameter, ignoring the case of its name.
+    pub fn param(&self, name: impl AsRef<str>) -> Option<&str> {
+        let name = name.as_ref();
+        self.params
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_ref())
+    }
+
+    /// Gets the quality value (the `q` parameter) of the item, from 0 to 1.
+    /// Defaults to 1 if the item has no quality, and returns None if it is not a valid number.
+    pub fn quality(&self) -> Option<f32> {
+        match self.param("q") {
+            Some(q) => q
+                .parse::<f32>()
+                .ok()
+                .filter(|x| !x.is_nan())
+                .map(|x| x.clamp(0.0, 1.0)),
+            None => Some(1.0),
+        }
+    }
+}
+
+/// Parses a comma-separated list header value into its items.
+/// Empty items are skipped, and commas and semicolons in quoted strings are not treated as separators.
+/// See [`ListItem`].
+pub fn parse_list(value: &str) -> impl Iterator<Item = ListItem<'_>> {
+    split_quoted(value, b',').filter_map(ListItem::parse)
+}
+
+/// Splits a string on an ASCII separator, ignoring separators in quoted strings.
+fn split_quoted(value: &str, separator: u8) -> impl Iterator<Item = &str> {
+    let mut rest = Some(value);
+    iter::from_fn(move || {
+        let value = rest?;
+        let (mut quoted, mut escaped) = (false, false);
+        for (i, byte) in value.bytes().enumerate() {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' if quoted => escaped = true,
+                b'"' => quoted = !quoted,
+                _ if byte == separator && !quoted => {
+                    rest = Some(&value[i + 1..]);
+                    return Some(&value[..i]);
+                }
+                _ => {}
+            }
+        }
+
+        rest = None;
+        Some(value)
+    })
+}
+
+/// Removes the quotes and escapes from a quoted string, or just trims it if it is not quoted.
+fn unquote(value: &str) -> Cow<'_, str> {
+    let value = value.trim();
+    let inner = match value.strip_prefix('"').and_then(|x| x.strip_suffix('"')) {
+        Some(inner) => inner,
+        None => return Cow::Borrowed(value),
+    };
+
+    if !inner.contains('\\') {
+        return Cow::Borrowed(inner);
+    }
+
+    let mut out = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(chr) = chars.next() {
+        match chr {
+            '\\' => out.extend(chars.next()),
+            _ => out.push(chr),
+        }
+    }
+
+    Cow::Owned(out)
+}
+
 impl Deref for Headers {
     type Target = Vec<Header>;
 
@@ -226,6 +361,24 @@ impl Headers {
             .map(|x| &mut x.value)
     }
 
+    /// Parses all headers with the specified name as one comma-separated list.
+    /// Sending a list header multiple times is the same as sending it once with the values joined by commas.
+    /// ## Example
+    /// ```rust
+    /// # use afire::header::{Headers, HeaderType};
+    /// # fn test(headers: &Headers) {
+    /// let gzip = headers
+    ///     .list(HeaderType::AcceptEncoding)
+    ///     .any(|x| x.name == "gzip" && x.quality() != Some(0.0));
+    /// # }
+    /// ```
+    pub fn list(&self, name: impl Into<HeaderType>) -> impl Iterator<Item = ListItem<'_>> {
+        let name = name.into();
+        self.iter()
+            .filter(move |x| x.name == name)
+            .flat_map(|x| x.list())
+    }
+
     /// Adds a header to the collection.
     /// See [`Headers::add`] for a version that takes a name and value.
     /// ## Example
@@ -252,6 +405,53 @@ impl Headers {
         let name = name.into();
         self.iter_mut().find(|x| x.name == name)
     }
+
+    /// Adds a header name to the `Vary` header.
+    /// Any existing `Vary` headers are merged into one comma-separated header, and names that are already present (case-insensitively) are not duplicated.
+    /// If the header is `*`, all other values are removed, as the response varies on everything.
+    /// Middleware that changes the response based on a request header should use this, so it does not clobber values added by others.
+    /// Of the built-in extensions, only `Compression` (for `Accept-Encoding`) does this, as the others don't pick a response based on request headers.
+    /// ## Example
+    /// ```rust
+    /// # use afire::header::{Headers, HeaderType};
+    /// let mut headers = Headers::default();
+    /// headers.append_vary(HeaderType::AcceptEncoding);
+    /// headers.append_vary("Origin");
+    /// headers.append_vary("accept-encoding");
+    /// assert_eq!(headers.get(HeaderType::Vary), Some("Accept-Encoding, Origin"));
+    /// ```
+    pub fn append_vary(&mut self, name: impl Into<HeaderType>) {
+        let name = name.into().to_string();
+        let mut values = Vec::new();
+        self.0.retain(|x| {
+            if x.name != HeaderType::Vary {
+                return true;
+            }
+
+            values.extend(
+                x.value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|x| !x.is_empty())
+                    .map(str::to_owned),
+            );
+            false
+        });
+
+        if name == "*" {
+            values.clear();
+        }
+
+        if !values
+            .iter()
+            .any(|x| x == "*" || x.eq_ignore_ascii_case(&name))
+        {
+            values.push(name);
+        }
+
+        self.0
+            .push(Header::new(HeaderType::Vary, values.join(", ")));
+    }
 }
 
 impl fmt::Display for Header {
@@ -268,17 +468,6 @@ impl fmt::Display for Header {
     }
 }
 
-/// Stringify a Vec of headers.
-/// Each header is in the format `name: value` amd separated by a carriage return and newline (`\r\n`).
-pub(crate) fn headers_to_string(headers: &[Header]) -> String {
-    let out = headers
-        .iter()
-        .map(Header::to_string)
-        .fold(String::new(), |acc, i| acc + &i + "\r\n");
-
-    out[..out.len() - 2].to_owned()
-}
-
 // https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers
 /// Common HTTP headers.
 /// Just the 'common' ones, which are ones that I use semi-frequently, or that are used internally.
@@ -296,9 +485,40 @@ pub enum HeaderType {
     /// Indicates what languages are acceptable for the client.
     /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Accept-Language))
     AcceptLanguage,
+    /// Sent by the server to say it supports range requests, usually with the value `bytes`.
+    /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Accept-Ranges))
+    AcceptRanges,
+    /// Tells the browser whether to expose the response to frontend code when the request's credentials mode is `include`.
+    /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Access-Control-Allow-Credentials))
+    AccessControlAllowCredentials,
+    /// Lists the headers that can be used in the actual request, in response to a preflight request.
+    /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Access-Control-Allow-Headers))
+    AccessControlAllowHeaders,
+    /// Lists the methods allowed when accessing the resource, in response to a preflight request.
+    /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Access-Control-Allow-Methods))
+    AccessControlAllowMethods,
+    /// Indicates whether the response can be shared with requesting code from the given origin.
+    /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Access-Control-Allow-Origin))
+    AccessControlAllowOrigin,
+    /// Lists the response headers that should be made available to scripts running in the browser.
+    /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Access-Control-Expose-Headers))
+    AccessControlExposeHeaders,
+    /// How long, in seconds, the results of a preflight request can be cached.
+    /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Access-Control-Max-Age))
+    AccessControlMaxAge,
+    /// Sent with a preflight request to say which headers will be used in the actual request.
+    /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Access-Control-Request-Headers))
+    AccessControlRequestHeaders,
+    /// Sent with a preflight request to say which method will be used in the actual request.
+    /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Access-Control-Request-Method))
+    AccessControlRequestMethod,
     /// Allows re-using a socket for multiple requests with `keep-alive`, or closing the sockets with `close`.
     /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Connection))
     Connection,
+    /// Indicates if the content should be displayed inline or downloaded as an attachment, along with its filename.
+    /// Also used for the parts of a `multipart/form-data` body.
+    /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Content-Disposition))
+    ContentDisposition,
     /// Lists the encodings that have been applied to the entity body.
     /// See [`HeaderType::AcceptEncoding`]
     /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Content-Encoding))
@@ -307,6 +527,9 @@ pub enum HeaderType {
     /// This is only required when the body is not chunked.
     /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Content-Length))
     ContentLength,
+    /// Indicates where in the full body a partial message belongs, used with `206 Partial Content` responses.
+    /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Content-Range))
+    ContentRange,
     /// Indicates the media type of the entity body.
     /// This can be set on a response with the [`crate::Response::content`] method.
     /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Content-Type))
@@ -317,13 +540,39 @@ pub enum HeaderType {
     /// The date and time at which the message was originated.
     /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Date))
     Date,
+    /// An identifier for a specific version of a resource, used for conditional requests and caching.
+    /// See [`HeaderType::IfNoneMatch`]
+    /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/ETag))
+    ETag,
+    /// Sent with requests to indicate expectations the server has to meet, like `100-continue` to wait for the server to accept the head before sending the body.
+    /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Expect))
+    Expect,
+    /// The standard header added by proxies with the addresses of the client and proxies a request went through.
+    /// See [`crate::extension::real_ip::ProxyConfig`] for getting the client's IP from it.
+    /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Forwarded))
+    Forwarded,
     /// Sent with requests to indicate the host and port of the server to which the request is being sent.
     /// This allows for reverse proxies to forward requests to the correct server.
     /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Host))
     Host,
+    /// Makes the request conditional, the server will respond with `304 Not Modified` if the resource has not been modified since the given date.
+    /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/If-Modified-Since))
+    IfModifiedSince,
+    /// Makes the request conditional, the server will respond with `304 Not Modified` if the resource's [`HeaderType::ETag`] matches one of the listed values.
+    /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/If-None-Match))
+    IfNoneMatch,
+    /// Links the resource to other resources, like the `<link>` element in HTML.
+    /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Link))
+    Link,
     /// Used with redirection status codes (301, 302, 303, 307, 308) to indicate the URL to redirect to.
     /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Location))
     Location,
+    /// The origin (scheme, host and port) that caused the request, sent with CORS and POST requests.
+    /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Origin))
+    Origin,
+    /// Asks the server to only send part of a resource, like `bytes=0-1023`.
+    /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Range))
+    Range,
     /// Contains the address of the webpage that linked to the resource being requested.
     /// Note the misspelling of referrer as 'referer' in the HTTP spec.
     /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Referer))
@@ -336,6 +585,9 @@ pub enum HeaderType {
     /// Its recommended to use the [`crate::SetCookie`] builder instead of this directly.
     /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Set-Cookie))
     SetCookie,
+    /// Tells browsers that the site should only be accessed over HTTPS.
+    /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Strict-Transport-Security))
+    StrictTransportSecurity,
     /// Specifies the transfer encoding of the message body.
     /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Transfer-Encoding))
     TransferEncoding,
@@ -346,6 +598,10 @@ pub enum HeaderType {
     /// Contains information about the client application, operating system, vendor, etc. that is making the request.
     /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/User-Agent))
     UserAgent,
+    /// Lists the request headers that were used to select the response, so caches know when a stored response can be reused.
+    /// Use [`Headers::append_vary`] to add to it without overwriting other values.
+    /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Vary))
+    Vary,
     /// A header added by proxies to track message forewords, avoid request loops, and identifying protocol capabilities.
     /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Via))
     Via,
@@ -353,6 +609,14 @@ pub enum HeaderType {
     /// This is not an official HTTP header, but is still widely used.
     /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/X-Forwarded-For))
     XForwardedFor,
+    /// A header often added by reverse proxies with the original `Host` requested by the client.
+    /// This is not an official HTTP header, but is still widely used.
+    /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/X-Forwarded-Host))
+    XForwardedHost,
+    /// A header often added by reverse proxies with the protocol (`http` or `https`) the client used to connect to the proxy.
+    /// This is not an official HTTP header, but is still widely used.
+    /// ([MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/X-Forwarded-Proto))
+    XForwardedProto,
     /// Any other header that is not in this enum.
     Custom(String),
 }
@@ -370,30 +634,68 @@ impl<T: AsRef<str>> From<T> for HeaderType {
 }
 
 impl HeaderType {
+    /// Length of the longest known header name, `Access-Control-Allow-Credentials`.
+    const MAX_KNOWN_LEN: usize = 32;
+
+    /// Gets the header type from its name, ignoring case.
+    /// Known names are lowercased into a buffer on the stack, so only custom headers allocate.
     #[rustfmt::skip]
     fn from_str(s: &str) -> Self {
-        match s.to_ascii_lowercase().as_str() {
-            "accept"            => HeaderType::Accept,
-            "accept-charset"    => HeaderType::AcceptCharset,
-            "accept-encoding"   => HeaderType::AcceptEncoding,
-            "accept-language"   => HeaderType::AcceptLanguage,
-            "connection"        => HeaderType::Connection,
-            "content-encoding"  => HeaderType::ContentEncoding,
-            "content-length"    => HeaderType::ContentLength,
-            "content-type"      => HeaderType::ContentType,
-            "cookie"            => HeaderType::Cookie,
-            "date"              => HeaderType::Date,
-            "host"              => HeaderType::Host,
-            "location"          => HeaderType::Location,
-            "referer"           => HeaderType::Referer,
-            "server"            => HeaderType::Server,
-            "set-cookie"        => HeaderType::SetCookie,
-            "transfer-encoding" => HeaderType::TransferEncoding,
-            "upgrade"           => HeaderType::Upgrade,
-            "user-agent"        => HeaderType::UserAgent,
-            "via"               => HeaderType::Via,
-            "x-forwarded-for"   => HeaderType::XForwardedFor,
-            _                   => HeaderType::Custom(s.to_string()),
+        if s.len() > Self::MAX_KNOWN_LEN {
+            return HeaderType::Custom(s.to_string());
+        }
+
+        let mut buf = [0; Self::MAX_KNOWN_LEN];
+        let buf = &mut buf[..s.len()];
+        buf.copy_from_slice(s.as_bytes());
+        buf.make_ascii_lowercase();
+
+        // Lowercasing only changes ASCII bytes, so the buffer is still valid UTF-8.
+        match str::from_utf8(buf).unwrap_or_default() {
+            "accept"                           => HeaderType::Accept,
+            "accept-charset"                   => HeaderType::AcceptCharset,
+            "accept-encoding"                  => HeaderType::AcceptEncoding,
+            "accept-language"                  => HeaderType::AcceptLanguage,
+            "accept-ranges"                    => HeaderType::AcceptRanges,
+            "access-control-allow-credentials" => HeaderType::AccessControlAllowCredentials,
+            "access-control-allow-headers"     => HeaderType::AccessControlAllowHeaders,
+            "access-control-allow-methods"     => HeaderType::AccessControlAllowMethods,
+            "access-control-allow-origin"      => HeaderType::AccessControlAllowOrigin,
+            "access-control-expose-headers"    => HeaderType::AccessControlExposeHeaders,
+            "access-control-max-age"           => HeaderType::AccessControlMaxAge,
+            "access-control-request-headers"   => HeaderType::AccessControlRequestHeaders,
+            "access-control-request-method"    => HeaderType::AccessControlRequestMethod,
+            "connection"                       => HeaderType::Connection,
+            "content-disposition"              => HeaderType::ContentDisposition,
+            "content-encoding"                 => HeaderType::ContentEncoding,
+            "content-length"                   => HeaderType::ContentLength,
+            "content-range"                    => HeaderType::ContentRange,
+            "content-type"                     => HeaderType::ContentType,
+            "cookie"                           => HeaderType::Cookie,
+            "date"                             => HeaderType::Date,
+            "etag"                             => HeaderType::ETag,
+            "expect"                           => HeaderType::Expect,
+            "forwarded"                        => HeaderType::Forwarded,
+            "host"                             => HeaderType::Host,
+            "if-modified-since"                => HeaderType::IfModifiedSince,
+            "if-none-match"                    => HeaderType::IfNoneMatch,
+            "link"                             => HeaderType::Link,
+            "location"                         => HeaderType::Location,
+            "origin"                           => HeaderType::Origin,
+            "range"                            => HeaderType::Range,
+            "referer"                          => HeaderType::Referer,
+            "server"                           => HeaderType::Server,
+            "set-cookie"                       => HeaderType::SetCookie,
+            "strict-transport-security"        => HeaderType::StrictTransportSecurity,
+            "transfer-encoding"                => HeaderType::TransferEncoding,
+            "upgrade"                          => HeaderType::Upgrade,
+            "user-agent"                       => HeaderType::UserAgent,
+            "vary"                             => HeaderType::Vary,
+            "via"                              => HeaderType::Via,
+            "x-forwarded-for"                  => HeaderType::XForwardedFor,
+            "x-forwarded-host"                 => HeaderType::XForwardedHost,
+            "x-forwarded-proto"                => HeaderType::XForwardedProto,
+            _                                  => HeaderType::Custom(s.to_string()),
         }
     }
 }
@@ -405,28 +707,138 @@ impl Display for HeaderType {
             f,
             "{}",
             match self {
-                HeaderType::Accept           => "Accept",
-                HeaderType::AcceptCharset    => "Accept-Charset",
-                HeaderType::AcceptEncoding   => "Accept-Encoding",
-                HeaderType::AcceptLanguage   => "Accept-Language",
-                HeaderType::Connection       => "Connection",
-                HeaderType::ContentEncoding  => "Content-Encoding",
-                HeaderType::ContentLength    => "Content-Length",
-                HeaderType::ContentType      => "Content-Type",
-                HeaderType::Cookie           => "Cookie",
-                HeaderType::Date             => "Date",
-                HeaderType::Host             => "Host",
-                HeaderType::Location         => "Location",
-                HeaderType::Referer          => "Referer",
-                HeaderType::Server           => "Server",
-                HeaderType::SetCookie        => "Set-Cookie",
-                HeaderType::TransferEncoding => "Transfer-Encoding",
-                HeaderType::Upgrade          => "Upgrade",
-                HeaderType::UserAgent        => "User-Agent",
-                HeaderType::Via              => "Via",
-                HeaderType::XForwardedFor    => "X-Forwarded-For",
-                HeaderType::Custom(s)        => s,
+                HeaderType::Accept                        => "Accept",
+                HeaderType::AcceptCharset                 => "Accept-Charset",
+                HeaderType::AcceptEncoding                => "Accept-Encoding",
+                HeaderType::AcceptLanguage                => "Accept-Language",
+                HeaderType::AcceptRanges                  => "Accept-Ranges",
+                HeaderType::AccessControlAllowCredentials => "Access-Control-Allow-Credentials",
+                HeaderType::AccessControlAllowHeaders     => "Access-Control-Allow-Headers",
+                HeaderType::AccessControlAllowMethods     => "Access-Control-Allow-Methods",
+                HeaderType::AccessControlAllowOrigin      => "Access-Control-Allow-Origin",
+                HeaderType::AccessControlExposeHeaders    => "Access-Control-Expose-Headers",
+                HeaderType::AccessControlMaxAge           => "Access-Control-Max-Age",
+                HeaderType::AccessControlRequestHeaders   => "Access-Control-Request-Headers",
+                HeaderType::AccessControlRequestMethod    => "Access-Control-Request-Method",
+                HeaderType::Connection                    => "Connection",
+                HeaderType::ContentDisposition            => "Content-Disposition",
+                HeaderType::ContentEncoding               => "Content-Encoding",
+                HeaderType::ContentLength                 => "Content-Length",
+                HeaderType::ContentRange                  => "Content-Range",
+                HeaderType::ContentType                   => "Content-Type",
+                HeaderType::Cookie                        => "Cookie",
+                HeaderType::Date                          => "Date",
+                HeaderType::ETag                          => "ETag",
+                HeaderType::Expect                        => "Expect",
+                HeaderType::Forwarded                     => "Forwarded",
+                HeaderType::Host                          => "Host",
+                HeaderType::IfModifiedSince               => "If-Modified-Since",
+                HeaderType::IfNoneMatch                   => "If-None-Match",
+                HeaderType::Link                          => "Link",
+                HeaderType::Location                      => "Location",
+                HeaderType::Origin                        => "Origin",
+                HeaderType::Range                         => "Range",
+                HeaderType::Referer                       => "Referer",
+                HeaderType::Server                        => "Server",
+                HeaderType::SetCookie                     => "Set-Cookie",
+                HeaderType::StrictTransportSecurity       => "Strict-Transport-Security",
+                HeaderType::TransferEncoding              => "Transfer-Encoding",
+                HeaderType::Upgrade                       => "Upgrade",
+                HeaderType::UserAgent                     => "User-Agent",
+                HeaderType::Vary                          => "Vary",
+                HeaderType::Via                           => "Via",
+                HeaderType::XForwardedFor                 => "X-Forwarded-For",
+                HeaderType::XForwardedHost                => "X-Forwarded-Host",
+                HeaderType::XForwardedProto               => "X-Forwarded-Proto",
+                HeaderType::Custom(s)                     => s,
             }
         )
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{parse_list, Header, HeaderType, Headers};
+
+    #[test]
+    fn test_append_vary_merge() {
+        let mut headers = Headers(vec![
+            Header::new("Vary", "Accept-Language,  origin"),
+            Header::new("Content-Type", "text/plain"),
+            Header::new("Vary", "Cookie"),
+        ]);
+
+        headers.append_vary("Origin");
+        assert_eq!(
+            headers.get(HeaderType::Vary),
+            Some("Accept-Language, origin, Cookie")
+        );
+        assert_eq!(
+            headers
+                .iter()
+                .filter(|x| x.name == HeaderType::Vary)
+                .count(),
+            1
+        );
+
+        headers.append_vary("*");
+        assert_eq!(headers.get(HeaderType::Vary), Some("*"));
+        headers.append_vary("Accept");
+        assert_eq!(headers.get(HeaderType::Vary), Some("*"));
+    }
+
+    #[test]
+    fn test_header_type_from_str() {
+        assert_eq!(HeaderType::from("etag"), HeaderType::ETag);
+        assert_eq!(HeaderType::from("If-None-Match"), HeaderType::IfNoneMatch);
+        assert_eq!(
+            HeaderType::from("ACCESS-CONTROL-ALLOW-CREDENTIALS"),
+            HeaderType::AccessControlAllowCredentials
+        );
+        assert_eq!(
+            HeaderType::from("x-forwarded-proto").to_string(),
+            "X-Forwarded-Proto"
+        );
+
+        assert_eq!(
+            HeaderType::from("X-Custom"),
+            HeaderType::Custom("X-Custom".to_owned())
+        );
+        let long = "X-A-Very-Long-Custom-Header-Name-Indeed";
+        assert_eq!(HeaderType::from(long), HeaderType::Custom(long.to_owned()));
+        assert_eq!(
+            HeaderType::from("Ünicode"),
+            HeaderType::Custom("Ünicode".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_parse_list() {
+        let items = parse_list(r#"text/html, , application/json;q=0.5;level="a;b", */*;Q=oops"#)
+            .collect::<Vec<_>>();
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0].name, "text/html");
+        assert_eq!(items[1].param("level"), Some("a;b"));
+        assert_eq!(items[1].quality(), Some(0.5));
+        assert_eq!(items[2].quality(), None);
+
+        let items = parse_list(r#"max-age=60, private="Set-Cookie, \"Vary\"", no-cache"#)
+            .collect::<Vec<_>>();
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0].value.as_deref(), Some("60"));
+        assert_eq!(items[1].name, "private");
+        assert_eq!(items[1].value.as_deref(), Some(r#"Set-Cookie, "Vary""#));
+        assert_eq!(items[2].name, "no-cache");
+        assert_eq!(items[2].value, None);
+
+        let headers = Headers(vec![
+            Header::new("Accept-Encoding", "gzip;q=0.8"),
+            Header::new("Accept-Encoding", "br"),
+        ]);
+        let names = headers
+            .list(HeaderType::AcceptEncoding)
+            .map(|x| x.name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["gzip", "br"]);
+    }
+}
@@ -54,6 +54,12 @@ pub enum Method {
     /// Used for tracing the route of a request
     TRACE,
 
+    /// HTTP CONNECT Method.
+    /// [MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Methods/CONNECT)
+    ///
+    /// Used for opening a tunnel through a proxy, the target is in [`crate::Request::authority`]
+    CONNECT,
+
     /// For routes that run on all methods
     ///
     /// Will not be use in a request
@@ -77,6 +83,7 @@ impl FromStr for Method {
     /// assert!(Method::from_str("HEAD").unwrap() == Method::HEAD);
     /// assert!(Method::from_str("PATCH").unwrap() == Method::PATCH);
     /// assert!(Method::from_str("TRACE").unwrap() == Method::TRACE);
+    /// assert!(Method::from_str("CONNECT").unwrap() == Method::CONNECT);
     /// assert!(Method::from_str("ANY") == Err(()));
     /// assert!(Method::from_str("foo") == Err(()));
     /// ```
@@ -90,6 +97,7 @@ impl FromStr for Method {
             "HEAD" => Method::HEAD,
             "PATCH" => Method::PATCH,
             "TRACE" => Method::TRACE,
+            "CONNECT" => Method::CONNECT,
             _ => return Err(()),
         })
     }
@@ -112,6 +120,7 @@ impl fmt::Display for Method {
             Method::HEAD => "HEAD",
             Method::PATCH => "PATCH",
             Method::TRACE => "TRACE",
+            Method::CONNECT => "CONNECT",
             Method::ANY => "ANY",
         })
     }
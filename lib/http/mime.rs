@@ -0,0 +1,228 @@
+//! A registry of MIME types, shared by [`crate::Content`] and the `ServeStatic` extension.
+//!
+//! It maps file extensions to MIME types, and MIME types to the charset they are sent with by default.
+//! The registry is process-wide, and new types can be added at runtime with [`register`] and [`set_charset`].
+//!
+//! ## Example
+//! ```rust
+//! # use afire::{mime, Content, Header};
+//! mime::register("gltf", "model/gltf+json");
+//! mime::set_charset("model/gltf+json", Some("utf-8"));
+//!
+//! assert_eq!(mime::from_extension("GLTF"), Some("model/gltf+json"));
+//! let header: Header = Content::Custom("model/gltf+json").into();
+//! assert_eq!(header.value, "model/gltf+json; charset=utf-8");
+//! ```
+
+use std::{
+    collections::HashMap,
+    sync::{OnceLock, RwLock},
+};
+
+/// Types added at runtime, and charsets that replace the defaults.
+#[derive(Default)]
+struct Registry {
+    types: HashMap<String, &'static str>,
+    charsets: HashMap<String, Option<&'static str>>,
+}
+
+fn registry() -> &'static RwLock<Registry> {
+    static REGISTRY: OnceLock<RwLock<Registry>> = OnceLock::new();
+    REGISTRY.get_or_init(Default::default)
+}
+
+/// Adds a file extension to the registry, or changes the type of an existing one.
+/// The extension is case insensitive, and can start with a dot.
+///
+/// The MIME type is leaked so lookups can return a `&'static str`, so this is meant to be called a few times at startup, not per request.
+pub fn register(extension: impl AsRef<str>, mime_type: impl AsRef<str>) {
+    let mime_type = Box::leak(mime_type.as_ref().to_owned().into_boxed_str());
+    registry()
+        .write()
+        .unwrap()
+        .types
+        .insert(normalize_extension(extension.as_ref()), mime_type);
+}
+
+/// Sets the charset a MIME type is sent with, or `None` to send it without one.
+/// By default `text/*` types and common text based `application/*` types (like JSON and XML) use `utf-8`.
+pub fn set_charset(mime_type: impl AsRef<str>, charset: Option<&str>) {
+    let charset = charset.map(|x| &*Box::leak(x.to_owned().into_boxed_str()));
+    registry()
+        .write()
+        .unwrap()
+        .charsets
+        .insert(mime_type.as_ref().to_ascii_lowercase(), charset);
+}
+
+/// Gets the MIME type of a file extension, checking types added with [`register`] before the builtin [`TYPES`].
+/// The extension is case insensitive, and can start with a dot.
+pub fn from_extension(extension: &str) -> Option<&'static str> {
+    let extension = normalize_extension(extension);
+    if let Some(i) = registry().read().unwrap().types.get(&extension) {
+        return Some(i);
+    }
+
+    get_type(&extension, &TYPES)
+}
+
+/// Gets the charset a MIME type is sent with by default, see [`set_charset`].
+/// Any parameters on the type are ignored.
+pub fn charset(mime_type: &str) -> Option<&'static str> {
+    let mime_type = mime_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    if let Some(i) = registry().read().unwrap().charsets.get(&mime_type) {
+        return *i;
+    }
+
+    let (kind, sub) = mime_type.split_once('/').unwrap_or((&mime_type, ""));
+    let text = kind == "text"
+        || (kind == "application"
+            && (matches!(sub, "json" | "javascript" | "xml")
+                || sub.ends_with("+json")
+                || sub.ends_with("+xml")))
+        || mime_type == "image/svg+xml";
+    text.then_some("utf-8")
+}
+
+/// Gets the value of a `Content-Type` header for a MIME type, adding its default charset if it has one and doesn't already specify one.
+pub fn content_type(mime_type: &str) -> String {
+    let has_charset = mime_type
+        .split(';')
+        .skip(1)
+        .any(|x| x.trim().to_ascii_lowercase().starts_with("charset="));
+    match charset(mime_type) {
+        Some(charset) if !has_charset => format!("{mime_type}; charset={charset}"),
+        _ => mime_type.to_owned(),
+    }
+}
+
+fn normalize_extension(extension: &str) -> String {
+    extension.trim_start_matches('.').to_ascii_lowercase()
+}
+
+/// Common MIME Types (sorted in alphabetical order by extension)
+///
+/// These are the defaults of the registry, see [`from_extension`].
+pub const TYPES: [MIME; 56] = [
+    MIME::new("7z", "application/x-7z-compressed"),
+    MIME::new("aac", "audio/aac"),
+    MIME::new("avi", "video/x-msvideo"),
+    MIME::new("bin", "application/octet-stream"),
+    MIME::new("bmp", "image/bmp"),
+    MIME::new("bz", "application/x-bzip"),
+    MIME::new("bz2", "application/x-bzip2"),
+    MIME::new("cda", "application/x-cdf"),
+    MIME::new("css", "text/css"),
+    MIME::new("csv", "text/csv"),
+    MIME::new("epub", "application/epub+zip"),
+    MIME::new("gif", "image/gif"),
+    MIME::new("gz", "application/gzip"),
+    MIME::new("htm", "text/html"),
+    MIME::new("html", "text/html"),
+    MIME::new("ico", "image/x-icon"),
+    MIME::new("ics", "text/calendar"),
+    MIME::new("jar", "application/java-archive"),
+    MIME::new("jpeg", "image/jpeg"),
+    MIME::new("jpg", "image/jpeg"),
+    MIME::new("js", "application/javascript"),
+    MIME::new("json", "application/json"),
+    MIME::new("jsonld", "application/ld+json"),
+    MIME::new("mid", "audio/midi"),
+    MIME::new("midi", "audio/midi"),
+    MIME::new("mjs", "text/javascript"),
+    MIME::new("mp3", "audio/mpeg"),
+    MIME::new("mp4", "video/mp4"),
+    MIME::new("mpeg", "video/mpeg"),
+    MIME::new("oga", "audio/ogg"),
+    MIME::new("ogv", "video/ogg"),
+    MIME::new("ogx", "application/ogg"),
+    MIME::new("opus", "audio/opus"),
+    MIME::new("otf", "font/otf"),
+    MIME::new("pdf", "application/pdf"),
+    MIME::new("png", "image/png"),
+    MIME::new("rar", "application/vnd.rar"),
+    MIME::new("rtf", "application/rtf"),
+    MIME::new("sh", "application/x-sh"),
+    MIME::new("svg", "image/svg+xml"),
+    MIME::new("swf", "application/x-shockwave-flash"),
+    MIME::new("tar", "application/x-tar"),
+    MIME::new("tif", "image/tiff"),
+    MIME::new("tiff", "image/tiff"),
+    MIME::new("ts", "text/x-typescript"),
+    MIME::new("ttf", "font/ttf"),
+    MIME::new("txt", "text/plain"),
+    MIME::new("wav", "audio/wav"),
+    MIME::new("weba", "audio/webm"),
+    MIME::new("webm", "video/webm"),
+    MIME::new("webp", "image/webp"),
+    MIME::new("woff", "font/woff"),
+    MIME::new("woff2", "font/woff2"),
+    MIME::new("xhtml", "application/xhtml+xml"),
+    MIME::new("xml", "application/xml"),
+    MIME::new("zip", "application/zip"),
+];
+
+/// Struct to hold a file extension and its matching MIME type
+#[derive(Debug, Clone)]
+pub struct MIME {
+    extension: &'static str,
+    mime_type: &'static str,
+}
+
+impl MIME {
+    /// Create a new MIME type
+    pub const fn new(extension: &'static str, mime_type: &'static str) -> Self {
+        Self {
+            extension,
+            mime_type,
+        }
+    }
+}
+
+/// Gets the MIME type from the specified file extension using a slice of MIME types, sorted by extension.
+/// If no type is found, None is returned.
+/// To also check types added at runtime, use [`from_extension`].
+pub fn get_type(ext: &str, extensions: &[MIME]) -> Option<&'static str> {
+    extensions
+        .binary_search_by(|x| x.extension.cmp(ext))
+        .map(|x| extensions[x].mime_type)
+        .ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{charset, content_type, from_extension, get_type, register, set_charset, TYPES};
+
+    #[test]
+    fn test_types_sorted() {
+        assert!(TYPES.windows(2).all(|x| x[0].extension < x[1].extension));
+        assert_eq!(get_type("zip", &TYPES), Some("application/zip"));
+        assert_eq!(get_type("nope", &TYPES), None);
+    }
+
+    #[test]
+    fn test_registry() {
+        assert_eq!(from_extension(".HTML"), Some("text/html"));
+        assert_eq!(from_extension("test-ext"), None);
+        register(".Test-Ext", "application/x-test");
+        assert_eq!(from_extension("test-ext"), Some("application/x-test"));
+
+        assert_eq!(charset("text/css"), Some("utf-8"));
+        assert_eq!(charset("application/ld+json"), Some("utf-8"));
+        assert_eq!(charset("image/png"), None);
+        set_charset("application/x-test", Some("utf-16"));
+        assert_eq!(charset("Application/X-Test; q=1"), Some("utf-16"));
+
+        assert_eq!(content_type("image/png"), "image/png");
+        assert_eq!(content_type("text/html"), "text/html; charset=utf-8");
+        assert_eq!(
+            content_type("text/html; charset=latin1"),
+            "text/html; charset=latin1"
+        );
+    }
+}
@@ -1,10 +1,18 @@
 //! Modules relating to HTTP requests and responses.
 
+pub mod accept;
 pub mod content_type;
 pub mod cookie;
+pub mod date;
 pub mod header;
 pub mod method;
+pub mod mime;
 pub mod multipart;
 pub mod query;
+pub mod reproduce;
+pub mod response_writer;
 pub mod server_sent_events;
 pub mod status;
+pub mod type_map;
+pub mod version;
+pub mod web_socket;
@@ -1,9 +1,16 @@
-//! Multipart request parsing.
+//! Multipart request parsing and body building.
 
 use std::{
+    cell::RefCell,
+    collections::hash_map::RandomState,
     convert::TryFrom,
-    io::BufRead,
+    fmt::Write,
+    hash::{BuildHasher, Hasher},
+    io::{self, BufRead, Read},
     ops::{Deref, DerefMut},
+    rc::Rc,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 use crate::{header::Headers, Header, Request};
@@ -39,16 +46,22 @@ pub enum MultipartError {
     InvalidData,
     /// An entry is invalid.
     InvalidEntry,
+    /// A part of a [`MultipartStream`] is larger than [`MultipartStream::max_part_size`].
+    PartTooLarge,
+    /// The headers of a part of a [`MultipartStream`] are larger than [`MultipartStream::max_header_size`].
+    HeadersTooLarge,
+    /// There was an error reading the body of a [`MultipartStream`].
+    Io(io::Error),
 }
 
 impl<'a> MultipartData<'a> {
     /// Get an entry by name, returns `None` if the entry does not exist.
-    pub fn get(&self, name: impl AsRef<str>) -> Option<&MultipartEntry> {
+    pub fn get(&self, name: impl AsRef<str>) -> Option<&MultipartEntry<'a>> {
         self.entries.iter().find(|x| x.name == name.as_ref())
     }
 
     /// Gets a mutable reference to an entry by name, returns `None` if the entry does not exist.
-    pub fn get_mut(&'a mut self, name: impl AsRef<str>) -> Option<&mut MultipartEntry> {
+    pub fn get_mut(&mut self, name: impl AsRef<str>) -> Option<&mut MultipartEntry<'a>> {
         self.entries.iter_mut().find(|x| x.name == name.as_ref())
     }
 }
@@ -71,21 +84,7 @@ impl<'a> TryFrom<&'a Request> for MultipartData<'a> {
     type Error = MultipartError;
 
     fn try_from(req: &'a Request) -> Result<Self, Self::Error> {
-        let content_type = req
-            .headers
-            .get_header("Content-Type")
-            .ok_or(MultipartError::InvalidContentType)?
-            .params();
-
-        let body_type = &content_type.value;
-        let boundary = content_type
-            .get("boundary")
-            .ok_or(MultipartError::InvalidBoundary)?;
-
-        if *body_type != "multipart/form-data" {
-            return Err(MultipartError::InvalidContentType);
-        }
-
+        let (body_type, boundary) = form_boundary(req)?;
         let boundary = [b"--", boundary.as_bytes()].concat();
         let data = split_boundary(&req.body, &boundary);
 
@@ -121,21 +120,7 @@ impl<'a> TryFrom<&'a [u8]> for MultipartEntry<'a> {
         }
 
         let (raw_headers, data) = value.split_at(index);
-
-        let mut headers = Vec::new();
-
-        for i in raw_headers
-            .lines()
-            .map(|x| x.unwrap())
-            .filter(|x| !x.is_empty())
-        {
-            let header = Header::from_string(i)
-                .ok()
-                .ok_or(MultipartError::InvalidEntry)?;
-            headers.push(header);
-        }
-
-        let headers = Headers(headers);
+        let headers = parse_headers(raw_headers)?;
         let content = headers
             .get_header("Content-Disposition")
             .ok_or(MultipartError::InvalidEntry)?;
@@ -156,6 +141,484 @@ impl<'a> TryFrom<&'a [u8]> for MultipartEntry<'a> {
     }
 }
 
+/// A `multipart/form-data` body that is parsed as it is read, see [`Request::multipart`].
+/// This is an iterator over the parts of the body, each of which can be read with [`Read`].
+///
+/// Only one part can be read at a time.
+/// Moving on to the next part skips anything left of the current one, after which the old part reads as empty.
+/// Once an error is returned the iterator ends.
+pub struct MultipartStream<'a> {
+    parser: Rc<RefCell<Parser<'a>>>,
+}
+
+/// A part of a [`MultipartStream`].
+/// Reading it reads the part's data straight from the request body.
+pub struct MultipartPart<'a> {
+    /// The name of the part.
+    pub name: String,
+    /// The name of the uploaded file, if applicable.
+    pub filename: Option<String>,
+    /// All the headers of the part.
+    pub headers: Headers,
+    index: usize,
+    parser: Rc<RefCell<Parser<'a>>>,
+}
+
+/// Where a [`Parser`] is in the body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Before the first boundary.
+    Preamble,
+    /// In the data of a part.
+    Data,
+    /// Just after a boundary.
+    Delimiter,
+    /// After the closing boundary.
+    Done,
+    /// After a part went over the size limit.
+    TooLarge,
+    /// After an error.
+    Failed,
+}
+
+struct Parser<'a> {
+    reader: Box<dyn Read + 'a>,
+    /// Read but not yet used bytes of the body, from `pos`.
+    buf: Vec<u8>,
+    pos: usize,
+    /// The delimiter between parts, `\r\n--{boundary}`.
+    delimiter: Vec<u8>,
+    state: State,
+    /// The index of the current part, starting at one.
+    part: usize,
+    /// The number of bytes of the current part that have been read.
+    part_read: usize,
+    max_part_size: Option<usize>,
+    max_header_size: usize,
+}
+
+impl<'a> MultipartStream<'a> {
+    /// Creates a new MultipartStream over a request body.
+    /// The boundary is the one from the request's `Content-Type` header.
+    pub fn new(boundary: &str, body: impl Read + 'a) -> Self {
+        Self {
+            parser: Rc::new(RefCell::new(Parser {
+                reader: Box::new(body),
+                // The first boundary doesn't have to follow a line break
+                buf: b"\r\n".to_vec(),
+                pos: 0,
+                delimiter: [b"\r\n--", boundary.as_bytes()].concat(),
+                state: State::Preamble,
+                part: 0,
+                part_read: 0,
+                max_part_size: None,
+                max_header_size: 8 * 1024,
+            })),
+        }
+    }
+
+    /// Sets the max size of the data of each part, in bytes.
+    /// Reading past this returns an error, and ends the stream with [`MultipartError::PartTooLarge`].
+    /// By default parts can be any size.
+    pub fn max_part_size(self, max_part_size: usize) -> Self {
+        self.parser.borrow_mut().max_part_size = Some(max_part_size);
+        self
+    }
+
+    /// Sets the max size of the headers of each part, in bytes.
+    /// Headers are held in memory while they are parsed, so this should be kept small.
+    /// Defaults to 8 KiB.
+    pub fn max_header_size(self, max_header_size: usize) -> Self {
+        self.parser.borrow_mut().max_header_size = max_header_size;
+        self
+    }
+}
+
+impl<'a> Iterator for MultipartStream<'a> {
+    type Item = Result<MultipartPart<'a>, MultipartError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut parser = self.parser.borrow_mut();
+        match parser.state {
+            State::Done | State::Failed => return None,
+            State::TooLarge => {
+                parser.state = State::Failed;
+                return Some(Err(MultipartError::PartTooLarge));
+            }
+            _ => {}
+        }
+
+        match parser.next_part() {
+            Ok(Some((name, filename, headers))) => Some(Ok(MultipartPart {
+                name,
+                filename,
+                headers,
+                index: parser.part,
+                parser: self.parser.clone(),
+            })),
+            Ok(None) => {
+                parser.state = State::Done;
+                None
+            }
+            Err(e) => {
+                parser.state = State::Failed;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl Read for MultipartPart<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut parser = self.parser.borrow_mut();
+        if parser.part != self.index {
+            return Ok(0);
+        }
+
+        parser.read_data(buf, true)
+    }
+}
+
+impl Parser<'_> {
+    /// Reads more of the body into the buffer.
+    /// Returns false if the body has ended.
+    fn fill(&mut self) -> io::Result<bool> {
+        self.buf.drain(..self.pos);
+        self.pos = 0;
+
+        let start = self.buf.len();
+        self.buf.resize(start + 8 * 1024, 0);
+        let read = self.reader.read(&mut self.buf[start..]);
+        self.buf.truncate(start + *read.as_ref().unwrap_or(&0));
+        Ok(read? > 0)
+    }
+
+    /// Reads data up to the next delimiter.
+    /// Returns zero once the delimiter is reached, which moves the state to [`State::Delimiter`].
+    fn read_data(&mut self, out: &mut [u8], limited: bool) -> io::Result<usize> {
+        if !matches!(self.state, State::Preamble | State::Data) || out.is_empty() {
+            return Ok(0);
+        }
+
+        let read = loop {
+            let available = &self.buf[self.pos..];
+            let safe = match find(available, &self.delimiter) {
+                Some(0) => {
+                    self.pos += self.delimiter.len();
+                    self.state = State::Delimiter;
+                    return Ok(0);
+                }
+                Some(i) => i,
+                // The end of the buffer could be the start of a delimiter
+                None => available.len().saturating_sub(self.delimiter.len() - 1),
+            };
+
+            if safe > 0 {
+                let read = safe.min(out.len());
+                out[..read].copy_from_slice(&available[..read]);
+                self.pos += read;
+                break read;
+            }
+
+            if !self.fill()? {
+                self.state = State::Failed;
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        };
+
+        if self.state == State::Data {
+            self.part_read += read;
+            if limited && self.max_part_size.is_some_and(|x| self.part_read > x) {
+                self.state = State::TooLarge;
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "multipart part too large",
+                ));
+            }
+        }
+
+        Ok(read)
+    }
+
+    /// Makes sure at least `len` bytes are buffered.
+    /// Returns false if the body ends first.
+    fn ensure(&mut self, len: usize) -> io::Result<bool> {
+        while self.buf.len() - self.pos < len {
+            if !self.fill()? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Skips the rest of the current part and parses the headers of the next one.
+    /// Returns `None` after the closing boundary.
+    #[allow(clippy::type_complexity)]
+    fn next_part(&mut self) -> Result<Option<(String, Option<String>, Headers)>, MultipartError> {
+        let mut skip = [0; 4096];
+        while self
+            .read_data(&mut skip, false)
+            .map_err(MultipartError::Io)?
+            > 0
+        {}
+
+        if !self.ensure(2).map_err(MultipartError::Io)? {
+            return Err(MultipartError::InvalidData);
+        }
+        if self.buf[self.pos..].starts_with(b"--") {
+            return Ok(None);
+        }
+
+        // Skip any padding after the boundary, up to the line break that starts the headers
+        let end = loop {
+            let available = &self.buf[self.pos..];
+            if let Some(i) = find(available, b"\r\n\r\n") {
+                break i;
+            }
+            if available.len() > self.max_header_size {
+                return Err(MultipartError::HeadersTooLarge);
+            }
+            if !self.fill().map_err(MultipartError::Io)? {
+                return Err(MultipartError::InvalidData);
+            }
+        };
+
+        let raw = &self.buf[self.pos..self.pos + end];
+        let start = find(raw, b"\r\n").ok_or(MultipartError::InvalidData)?;
+        if !raw[..start].iter().all(|x| matches!(x, b' ' | b'\t')) {
+            return Err(MultipartError::InvalidData);
+        }
+        if end - start > self.max_header_size {
+            return Err(MultipartError::HeadersTooLarge);
+        }
+
+        let headers = parse_headers(&raw[start..])?;
+        self.pos += end + 4;
+
+        let content = headers
+            .get_header("Content-Disposition")
+            .ok_or(MultipartError::InvalidEntry)?;
+        let params = content.params();
+        let name = params
+            .get("name")
+            .map(|x| unquote(x).to_owned())
+            .ok_or(MultipartError::InvalidEntry)?;
+        let filename = params.get("filename").map(|x| unquote(x).to_owned());
+
+        self.part += 1;
+        self.part_read = 0;
+        self.state = State::Data;
+        Ok(Some((name, filename, headers)))
+    }
+}
+
+/// Builds a `multipart/form-data` body.
+/// Each builder gets a new random boundary, which is used to separate the parts of the body.
+///
+/// ## Example
+/// ```rust
+/// # use afire::{Server, Method, Response, multipart::MultipartBuilder};
+/// # let mut server = Server::<()>::new("localhost", 8080);
+/// server.route(Method::GET, "/form", |_req| {
+///     let body = MultipartBuilder::new()
+///         .field("name", "afire")
+///         .file("logo", "logo.txt", "text/plain", "🔥");
+///     Response::new().multipart(body)
+/// });
+/// ```
+#[derive(Debug, Clone)]
+pub struct MultipartBuilder {
+    boundary: String,
+    body: Vec<u8>,
+}
+
+impl MultipartBuilder {
+    /// Creates a new builder with a random boundary.
+    pub fn new() -> Self {
+        Self {
+            boundary: random_boundary(),
+            body: Vec::new(),
+        }
+    }
+
+    /// Creates a new builder with a specific boundary.
+    /// Returns [`MultipartError::InvalidBoundary`] if the boundary is not allowed by [RFC 2046](https://www.rfc-editor.org/rfc/rfc2046#section-5.1.1).
+    /// It must be 1 to 70 characters long, and only contain letters, digits, spaces (but not at the end) and `'()+_,-./:=?`.
+    ///
+    /// Make sure the boundary does not appear in any of the parts, or the body will not be parsed correctly.
+    pub fn with_boundary(boundary: impl AsRef<str>) -> Result<Self, MultipartError> {
+        let boundary = boundary.as_ref();
+        if !valid_boundary(boundary) {
+            return Err(MultipartError::InvalidBoundary);
+        }
+
+        Ok(Self {
+            boundary: boundary.to_owned(),
+            body: Vec::new(),
+        })
+    }
+
+    /// Gets the boundary that separates the parts of the body.
+    pub fn boundary(&self) -> &str {
+        &self.boundary
+    }
+
+    /// Gets the value of the `Content-Type` header for this body.
+    /// Ex: `multipart/form-data; boundary=afire-1f0c1e6ab3a4c5d2e9b0a7c8d6e5f4a3`
+    pub fn content_type(&self) -> String {
+        format!("multipart/form-data; boundary={}", self.boundary)
+    }
+
+    /// Adds a form field.
+    pub fn field(self, name: impl AsRef<str>, value: impl AsRef<[u8]>) -> Self {
+        let disposition = format!("form-data; name=\"{}\"", escape_quoted(name.as_ref()));
+        self.part(&[Header::new("Content-Disposition", disposition)], value)
+    }
+
+    /// Adds a file, with the file name and MIME type that will be sent to the client.
+    pub fn file(
+        self,
+        name: impl AsRef<str>,
+        filename: impl AsRef<str>,
+        content_type: impl AsRef<str>,
+        data: impl AsRef<[u8]>,
+    ) -> Self {
+        let disposition = format!(
+            "form-data; name=\"{}\"; filename=\"{}\"",
+            escape_quoted(name.as_ref()),
+            escape_quoted(filename.as_ref())
+        );
+        self.part(
+            &[
+                Header::new("Content-Disposition", disposition),
+                Header::new("Content-Type", content_type),
+            ],
+            data,
+        )
+    }
+
+    /// Adds a part with custom headers.
+    /// The headers should include a `Content-Disposition` header for the body to be valid form data.
+    pub fn part(mut self, headers: &[Header], data: impl AsRef<[u8]>) -> Self {
+        let mut head = format!("--{}\r\n", self.boundary);
+        for i in headers {
+            let _ = write!(head, "{}\r\n", i);
+        }
+        head.push_str("\r\n");
+
+        self.body.extend_from_slice(head.as_bytes());
+        self.body.extend_from_slice(data.as_ref());
+        self.body.extend_from_slice(b"\r\n");
+        self
+    }
+
+    /// Finishes the body, adding the closing boundary.
+    /// Use [`MultipartBuilder::content_type`] first to get the `Content-Type` header, or use [`crate::Response::multipart`] to do both.
+    pub fn build(mut self) -> Vec<u8> {
+        self.body.extend_from_slice(b"--");
+        self.body.extend_from_slice(self.boundary.as_bytes());
+        self.body.extend_from_slice(b"--\r\n");
+        self.body
+    }
+}
+
+impl Default for MultipartBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Gets the content type and boundary of a `multipart/form-data` request.
+pub(crate) fn form_boundary(req: &Request) -> Result<(&str, &str), MultipartError> {
+    let content_type = req
+        .headers
+        .get_header("Content-Type")
+        .ok_or(MultipartError::InvalidContentType)?
+        .params();
+
+    let body_type = content_type.value;
+    let boundary = content_type
+        .get("boundary")
+        .map(unquote)
+        .ok_or(MultipartError::InvalidBoundary)?;
+
+    if body_type != "multipart/form-data" {
+        return Err(MultipartError::InvalidContentType);
+    }
+
+    Ok((body_type, boundary))
+}
+
+/// Parses the headers of a part.
+fn parse_headers(raw: &[u8]) -> Result<Headers, MultipartError> {
+    let mut headers = Vec::new();
+
+    for i in raw.lines() {
+        let i = i.map_err(|_| MultipartError::InvalidEntry)?;
+        if i.is_empty() {
+            continue;
+        }
+
+        let header = Header::from_string(i)
+            .ok()
+            .ok_or(MultipartError::InvalidEntry)?;
+        headers.push(header);
+    }
+
+    Ok(Headers(headers))
+}
+
+/// Removes the quotes around a header parameter, if it has them.
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|x| x.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+/// Finds the first position of `needle` in `data`.
+fn find(data: &[u8], needle: &[u8]) -> Option<usize> {
+    data.windows(needle.len()).position(|x| x == needle)
+}
+
+/// Generates a random boundary.
+/// This does not need to be cryptographically secure, it just has to be very unlikely to appear in the body.
+fn random_boundary() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_nanos())
+        .unwrap_or_default();
+
+    let mut out = String::from("afire-");
+    for _ in 0..2 {
+        // Each RandomState is seeded differently
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+        hasher.write_u128(time);
+        let _ = write!(out, "{:016x}", hasher.finish());
+    }
+
+    out
+}
+
+/// Checks if a boundary is allowed by RFC 2046.
+fn valid_boundary(boundary: &str) -> bool {
+    (1..=70).contains(&boundary.len())
+        && !boundary.ends_with(' ')
+        && boundary
+            .bytes()
+            .all(|x| x.is_ascii_alphanumeric() || b"'()+_,-./:=? ".contains(&x))
+}
+
+/// Escapes a value to be put in a quoted string.
+fn escape_quoted(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace(['\r', '\n'], " ")
+}
+
 fn split_boundary<'a>(data: &'a [u8], boundary: &[u8]) -> Vec<&'a [u8]> {
     let indexes = data
         .windows(boundary.len())
@@ -192,4 +655,150 @@ mod tests {
         assert_eq!(out[2], b"");
         assert_eq!(out[3], b"tomato");
     }
+
+    /// Reads one byte at a time, so delimiters are split between reads.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = self.0.len().min(buf.len()).min(1);
+            buf[..len].copy_from_slice(&self.0[..len]);
+            self.0 = &self.0[len..];
+            Ok(len)
+        }
+    }
+
+    fn stream_body() -> Vec<u8> {
+        let mut body = b"preamble\r\n".to_vec();
+        body.extend(
+            MultipartBuilder::with_boundary("xyz")
+                .unwrap()
+                .field("name", "afire")
+                .file("file", "a.txt", "text/plain", "line\r\n--xy not a boundary")
+                .field("empty", "")
+                .build(),
+        );
+        body
+    }
+
+    #[test]
+    fn test_stream() {
+        let body = stream_body();
+        let mut parts = Vec::new();
+        for part in MultipartStream::new("xyz", Trickle(&body)) {
+            let mut part = part.unwrap();
+            let mut data = String::new();
+            part.read_to_string(&mut data).unwrap();
+            parts.push((part.name, part.filename, data));
+        }
+
+        assert_eq!(
+            parts,
+            [
+                ("name".into(), None, "afire".into()),
+                (
+                    "file".into(),
+                    Some("a.txt".into()),
+                    "line\r\n--xy not a boundary".into()
+                ),
+                ("empty".into(), None, "".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stream_skip() {
+        let body = stream_body();
+        let mut stream = MultipartStream::new("xyz", body.as_slice());
+        let mut first = stream.next().unwrap().unwrap();
+        let second = stream.next().unwrap().unwrap();
+        assert_eq!(second.headers.get("Content-Type"), Some("text/plain"));
+
+        // The first part was skipped, so it reads as empty
+        let mut data = Vec::new();
+        first.read_to_end(&mut data).unwrap();
+        assert!(data.is_empty());
+        assert_eq!(stream.count(), 1);
+    }
+
+    #[test]
+    fn test_non_utf8_headers() {
+        let headers = b"Content-Disposition: form-data; name=\"\xff\"\r\n";
+        assert!(matches!(
+            parse_headers(headers),
+            Err(MultipartError::InvalidEntry)
+        ));
+
+        let body = b"--xyz\r\nContent-Type: \xfe\r\n\r\ndata\r\n--xyz--\r\n";
+        let mut stream = MultipartStream::new("xyz", &body[..]);
+        assert!(matches!(
+            stream.next(),
+            Some(Err(MultipartError::InvalidEntry))
+        ));
+    }
+
+    #[test]
+    fn test_stream_limits() {
+        let body = stream_body();
+        let mut stream = MultipartStream::new("xyz", body.as_slice()).max_part_size(10);
+        let mut data = Vec::new();
+        stream
+            .next()
+            .unwrap()
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+
+        let mut file = stream.next().unwrap().unwrap();
+        assert!(file.read_to_end(&mut data).is_err());
+        assert!(matches!(
+            stream.next(),
+            Some(Err(MultipartError::PartTooLarge))
+        ));
+        assert!(stream.next().is_none());
+
+        let mut stream = MultipartStream::new("xyz", body.as_slice()).max_header_size(16);
+        assert!(matches!(
+            stream.next(),
+            Some(Err(MultipartError::HeadersTooLarge))
+        ));
+
+        // Missing the closing boundary
+        let mut stream = MultipartStream::new("xyz", &body[..body.len() - 10]);
+        assert!(stream.by_ref().take(3).all(|x| x.is_ok()));
+        assert!(matches!(stream.next(), Some(Err(MultipartError::Io(_)))));
+    }
+
+    #[test]
+    fn test_random_boundary() {
+        let a = MultipartBuilder::new();
+        let b = MultipartBuilder::new();
+        assert_ne!(a.boundary(), b.boundary());
+        assert!(valid_boundary(a.boundary()));
+    }
+
+    #[test]
+    fn test_valid_boundary() {
+        assert!(MultipartBuilder::with_boundary("simple boundary").is_ok());
+        assert!(MultipartBuilder::with_boundary("").is_err());
+        assert!(MultipartBuilder::with_boundary("trailing ").is_err());
+        assert!(MultipartBuilder::with_boundary("semi;colon").is_err());
+        assert!(MultipartBuilder::with_boundary("a".repeat(71)).is_err());
+    }
+
+    #[test]
+    fn test_builder_body() {
+        let body = MultipartBuilder::with_boundary("xyz")
+            .unwrap()
+            .field("name", "afire")
+            .file("file", "a.txt", "text/plain", "hi")
+            .build();
+
+        assert_eq!(
+            String::from_utf8(body).unwrap(),
+            "--xyz\r\nContent-Disposition: form-data; name=\"name\"\r\n\r\nafire\r\n\
+             --xyz\r\nContent-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\
+             Content-Type: text/plain\r\n\r\nhi\r\n--xyz--\r\n"
+        );
+    }
 }
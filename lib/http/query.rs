@@ -1,13 +1,17 @@
 use std::{
     fmt,
     ops::{Deref, DerefMut},
+    str::FromStr,
 };
 
-use crate::internal::encoding::url;
+use crate::{error::RouteError, internal::encoding::url};
 
 /// Collection of query parameters.
 /// Can be made from the query string of a URL, or the body of a POST request.
 /// Similar to [`crate::header::Headers`].
+///
+/// Keys and values are url decoded (including `+` as a space), and keys can be repeated, see [`Query::get_all`].
+/// Keys without a value (`?debug`) have an empty value.
 #[derive(Debug, Hash, PartialEq, Eq, Clone)]
 pub struct Query(Vec<[String; 2]>);
 
@@ -72,6 +76,63 @@ impl Query {
             .map(|x| x.as_str())
     }
 
+    /// Gets the value of the specified key, parsed into any type that implements [`FromStr`].
+    /// If the key is missing or the value fails to parse, a [`RouteError`] is returned, which can be turned into a `400 Bad Request` response.
+    /// ## Example
+    /// ```
+    /// # use afire::Query;
+    /// let query = Query::from_body("page=2&count=many");
+    /// assert_eq!(query.get_parsed::<u32>("page"), Ok(2));
+    /// assert!(query.get_parsed::<u32>("count").is_err());
+    /// assert!(query.get_parsed::<u32>("missing").is_err());
+    /// ```
+    pub fn get_parsed<T>(&self, key: impl AsRef<str>) -> Result<T, RouteError>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        let key = key.as_ref();
+        RouteError::parse(|| format!("query parameter `{key}`"), self.get(key))
+    }
+
+    /// Gets all the values of the specified key, in the order they appear.
+    /// Useful for repeated keys, like `?tag=a&tag=b`.
+    /// ## Example
+    /// ```
+    /// # use afire::Query;
+    /// let query = Query::from_body("tag=a&tag=b+c&page=1");
+    /// assert_eq!(query.get_all("tag"), ["a", "b c"]);
+    /// assert!(query.get_all("missing").is_empty());
+    /// ```
+    pub fn get_all(&self, key: impl AsRef<str>) -> Vec<&str> {
+        let key = key.as_ref();
+        self.iter()
+            .filter(|i| i[0] == key)
+            .map(|i| i[1].as_str())
+            .collect()
+    }
+
+    /// Gets all the values of the specified key, parsed into any type that implements [`FromStr`].
+    /// If any value fails to parse, a [`RouteError`] is returned.
+    /// A missing key is not an error, it just gives an empty list.
+    /// ## Example
+    /// ```
+    /// # use afire::Query;
+    /// let query = Query::from_body("id=1&id=2");
+    /// assert_eq!(query.get_all_parsed::<u32>("id"), Ok(vec![1, 2]));
+    /// ```
+    pub fn get_all_parsed<T>(&self, key: impl AsRef<str>) -> Result<Vec<T>, RouteError>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        let key = key.as_ref();
+        self.get_all(key)
+            .into_iter()
+            .map(|x| RouteError::parse(|| format!("query parameter `{key}`"), Some(x)))
+            .collect()
+    }
+
     /// Gets a value of the specified key as a mutable reference.
     /// This will return None if the key does not exist.
     /// See [`Query::get`] for the non-mutable version.
@@ -113,24 +174,15 @@ impl Query {
     /// let query = Query::from_body("foo=bar&nose=dog");
     /// ```
     pub fn from_body(body: &str) -> Self {
-        let mut data = Vec::new();
-
-        let parts = body.split('&');
-        for i in parts {
-            let mut sub = i.splitn(2, '=');
-
-            let key = match sub.next() {
-                Some(i) => url::decode(i).unwrap_or_else(|| i.to_owned()),
-                None => continue,
-            };
-
-            let value = match sub.next() {
-                Some(i) => url::decode(i).unwrap_or_else(|| i.to_owned()),
-                None => continue,
-            };
-
-            data.push([key, value])
-        }
+        let decode = |x: &str| url::decode(x).unwrap_or_else(|| x.to_owned());
+        let data = body
+            .split('&')
+            .filter(|x| !x.is_empty())
+            .map(|i| {
+                let (key, value) = i.split_once('=').unwrap_or((i, ""));
+                [decode(key), decode(value)]
+            })
+            .collect();
 
         Query(data)
     }
@@ -145,7 +197,7 @@ impl fmt::Display for Query {
 
         let mut output = String::from("?");
         for i in &self.0 {
-            output.push_str(&format!("{}={}&", i[0], i[1]));
+            output.push_str(&format!("{}={}&", url::encode(&i[0]), url::encode(&i[1])));
         }
         output.pop();
         f.write_str(&output)
@@ -172,6 +224,17 @@ mod test {
         assert_eq!(query.get("bar"), None);
     }
 
+    #[test]
+    fn test_multi_value() {
+        let query = Query::from_body("tag=a&flag&tag=b%26c&&a+b=c%20d");
+        assert_eq!(query.get_all("tag"), ["a", "b&c"]);
+        assert_eq!(query.get("flag"), Some(""));
+        assert_eq!(query.get("a b"), Some("c d"));
+        assert_eq!(query.len(), 4);
+        assert_eq!(query.to_string(), "?tag=a&flag=&tag=b%26c&a%20b=c%20d");
+        assert_eq!(Query::from_body(&query.to_string()[1..]), query);
+    }
+
     #[test]
     fn test_get_mut() {
         let mut query = Query::from_body("foo=bar&nose=dog");
@@ -0,0 +1,230 @@
+//! Turn requests back into something that can be sent again, for bug reports and debugging.
+//! See [`Request::to_curl`] and [`Request::to_raw_http`].
+
+use std::{fmt::Write, fs, io, path::Path};
+
+use crate::{HeaderType, HttpVersion, Method, Request};
+
+type Redactor<'a> = Box<dyn Fn(&str, &str) -> Option<String> + 'a>;
+
+/// The value that redacted headers are replaced with.
+const REDACTED: &str = "[redacted]";
+
+/// Builds a reproduction of a request, as a curl command or raw HTTP.
+/// Made with [`Request::reproducer`].
+///
+/// The request line and headers are taken from [`Request::raw_head`], so they are exactly what the client sent, before any middleware changed them.
+/// The values of the `Authorization`, `Cookie` and `Proxy-Authorization` headers are redacted by default.
+///
+/// Streamed bodies (see [`crate::Server::body_buffer_limit`]) can't be included, as they are only read once.
+///
+/// ## Example
+/// ```rust
+/// # use afire::{Method, Response, Server};
+/// # let mut server = Server::<()>::new("localhost", 8080);
+/// server.route(Method::POST, "/api", |req| {
+///     let curl = req
+///         .reproducer()
+///         .include("Cookie")
+///         .exclude("X-Api-Key")
+///         .redact_with(|name, value| {
+///             name.eq_ignore_ascii_case("X-User").then(|| format!("<{} chars>", value.len()))
+///         })
+///         .curl();
+///     println!("{curl}");
+///     Response::new()
+/// });
+/// ```
+pub struct Reproducer<'a> {
+    req: &'a Request,
+    exclude_headers: Vec<HeaderType>,
+    redactor: Option<Redactor<'a>>,
+    body_file: Option<String>,
+}
+
+impl<'a> Reproducer<'a> {
+    pub(crate) fn new(req: &'a Request) -> Self {
+        Self {
+            req,
+            exclude_headers: vec![
+                HeaderType::Cookie,
+                HeaderType::Custom("Authorization".to_owned()),
+                HeaderType::Custom("Proxy-Authorization".to_owned()),
+            ],
+            redactor: None,
+            body_file: None,
+        }
+    }
+
+    /// Adds a header to redact.
+    /// The header is still included, but with its value replaced by `[redacted]`.
+    pub fn exclude(mut self, header: impl Into<HeaderType>) -> Self {
+        self.exclude_headers.push(header.into());
+        self
+    }
+
+    /// Removes a header from the list of headers to redact.
+    pub fn include(mut self, header: impl Into<HeaderType>) -> Self {
+        let header = header.into().to_string();
+        self.exclude_headers
+            .retain(|h| !h.to_string().eq_ignore_ascii_case(&header));
+        self
+    }
+
+    /// Sets a function that can replace header values, for redacting things that aren't a whole header.
+    /// It is called with the name and value of each header that isn't already excluded, and returns the new value or `None` to keep it.
+    pub fn redact_with(self, redactor: impl Fn(&str, &str) -> Option<String> + 'a) -> Self {
+        Self {
+            redactor: Some(Box::new(redactor)),
+            ..self
+        }
+    }
+
+    /// Writes the body to a file, and has the curl command read it from there instead of including it inline.
+    /// Useful for large or binary bodies.
+    pub fn body_file(self, path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        fs::write(path, &*self.req.body)?;
+        Ok(Self {
+            body_file: Some(path.to_string_lossy().into_owned()),
+            ..self
+        })
+    }
+
+    /// Gets the request line and the redacted headers.
+    fn head(&self) -> (&'a str, Vec<(&'a str, String)>) {
+        let head = std::str::from_utf8(self.req.raw_head()).unwrap_or_default();
+        let mut lines = head.lines();
+        let request_line = lines.next().unwrap_or_default();
+
+        let headers = lines
+            .filter_map(|x| x.split_once(':'))
+            .map(|(name, value)| {
+                let (name, value) = (name.trim(), value.trim());
+                let excluded = self
+                    .exclude_headers
+                    .iter()
+                    .any(|x| x.to_string().eq_ignore_ascii_case(name));
+                let value = match &self.redactor {
+                    _ if excluded => REDACTED.to_owned(),
+                    Some(redactor) => redactor(name, value).unwrap_or_else(|| value.to_owned()),
+                    None => value.to_owned(),
+                };
+                (name, value)
+            })
+            .collect();
+
+        (request_line, headers)
+    }
+
+    /// Gets the request as a curl command, on one line.
+    /// Bodies that aren't printable text are quoted with `$'...'`, which works in bash and zsh but not all shells, see [`Reproducer::body_file`].
+    pub fn curl(&self) -> String {
+        let (request_line, headers) = self.head();
+        let mut parts = request_line.split(' ');
+        let method = parts.next().unwrap_or_default();
+        let target = parts.next().unwrap_or("/");
+
+        let url = if target.starts_with("http://") || target.starts_with("https://") {
+            target.to_owned()
+        } else {
+            let host = headers
+                .iter()
+                .find(|x| x.0.eq_ignore_ascii_case("Host"))
+                .map_or("localhost", |x| x.1.as_str());
+            format!("http://{host}{target}")
+        };
+
+        let mut out = String::from("curl");
+        match self.req.method {
+            Method::GET => {}
+            Method::HEAD => out.push_str(" --head"),
+            _ => {
+                let _ = write!(out, " -X {}", quote(method));
+            }
+        }
+        if self.req.version == HttpVersion::Http10 {
+            out.push_str(" --http1.0");
+        }
+        let _ = write!(out, " {}", quote(&url));
+
+        // Curl adds these itself, from the url and body
+        for (name, value) in headers.iter().filter(|x| {
+            !x.0.eq_ignore_ascii_case("Host") && !x.0.eq_ignore_ascii_case("Content-Length")
+        }) {
+            let _ = write!(out, " -H {}", quote(&format!("{name}: {value}")));
+        }
+
+        match &self.body_file {
+            Some(path) => {
+                let _ = write!(out, " --data-binary {}", quote(&format!("@{path}")));
+            }
+            None if !self.req.body.is_empty() => {
+                let body = match std::str::from_utf8(&self.req.body) {
+                    Ok(i) if printable(i) => quote(i),
+                    _ => ansi_quote(&self.req.body),
+                };
+                let _ = write!(out, " --data-binary {body}");
+            }
+            None => {}
+        }
+
+        out
+    }
+
+    /// Gets the request as it would be sent over the wire.
+    /// The body is converted to a string with [`String::from_utf8_lossy`].
+    pub fn raw_http(&self) -> String {
+        let (request_line, headers) = self.head();
+        let mut out = format!("{request_line}\r\n");
+        for (name, value) in headers {
+            let _ = write!(out, "{name}: {value}\r\n");
+        }
+        out.push_str("\r\n");
+        out.push_str(&String::from_utf8_lossy(&self.req.body));
+        out
+    }
+}
+
+/// Checks if a string can be put in single quotes without hiding anything.
+fn printable(value: &str) -> bool {
+    !value
+        .chars()
+        .any(|x| x.is_control() && !matches!(x, '\n' | '\r' | '\t'))
+}
+
+/// Quotes a string for a POSIX shell.
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Quotes bytes with ANSI-C quoting (`$'...'`), escaping anything that isn't printable ASCII.
+fn ansi_quote(value: &[u8]) -> String {
+    let mut out = String::from("$'");
+    for &byte in value {
+        match byte {
+            b'\'' | b'\\' => {
+                out.push('\\');
+                out.push(byte as char);
+            }
+            b' '..=b'~' => out.push(byte as char),
+            _ => {
+                let _ = write!(out, "\\x{byte:02x}");
+            }
+        }
+    }
+    out.push('\'');
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ansi_quote, quote};
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("a b"), "'a b'");
+        assert_eq!(quote("it's"), "'it'\\''s'");
+        assert_eq!(ansi_quote(b"a'\\\x00\xff\n"), "$'a\\'\\\\\\x00\\xff\\x0a'");
+    }
+}
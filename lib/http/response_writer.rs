@@ -0,0 +1,172 @@
+//! Responses that are written from the route handler as the body is made, for things like progress updates and long-polling.
+//! See [`ResponseWriterExt::begin_stream`].
+
+use std::{
+    cell::RefCell,
+    io::{self, Write},
+    sync::Arc,
+};
+
+use crate::{
+    consts,
+    internal::common::ForceLock,
+    internal::socket::{chunk_header, write_all_deadline, write_vectored_deadline},
+    response::ResponseBody,
+    server::ResponseFinish,
+    transport::Socket,
+    HttpVersion, Method, Request, Response,
+};
+
+/// Writes the body of a response to the client as it is written, with chunked transfer encoding.
+/// Created with [`ResponseWriterExt::begin_stream`].
+///
+/// Writes are buffered and sent as a chunk once 16 KiB have built up, or when [`ResponseWriter::flush`] is called.
+/// The response is finished with [`ResponseWriter::finish`], or when the writer is dropped.
+///
+/// HTTP/1.0 clients don't support chunked encoding, so for them the body is sent as is and the connection is closed once the writer and request are dropped.
+/// For `HEAD` requests, only the head is sent and everything written is discarded.
+///
+/// Each write has to finish within the socket's write timeout, so a client that stops reading can't hold up the handler forever.
+pub struct ResponseWriter {
+    socket: Arc<Socket>,
+    buffer: Vec<u8>,
+    chunked: bool,
+    discard: bool,
+    finished: bool,
+}
+
+impl ResponseWriter {
+    /// Sends the head of `res` and creates a writer for its body.
+    /// This is called automatically if you use the [`ResponseWriterExt`] trait's .begin_stream() method.
+    pub fn from_request(req: &Request, mut res: Response) -> io::Result<Self> {
+        // The response doesn't go back through the server, so it is finalized here like any other
+        if let Some(finish) = req.socket.get_data::<ResponseFinish>() {
+            finish.apply_for(req, &mut res);
+        }
+
+        // The placeholder stream body gets the head the right framing headers, but is never read
+        res.data = ResponseBody::Stream(Box::new(RefCell::new(io::empty())));
+        res.write_as(req.socket.clone(), &[], req.version, true, &mut 0)
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        req.socket.insert_data(StreamStarted);
+
+        Ok(Self {
+            socket: req.socket.clone(),
+            buffer: Vec::with_capacity(consts::CHUNK_SIZE),
+            chunked: req.version == HttpVersion::Http11,
+            discard: req.method == Method::HEAD,
+            finished: false,
+        })
+    }
+
+    /// Sends the final chunk, ending the response.
+    /// Anything that is still buffered is sent first.
+    /// Calling this more than once does nothing.
+    pub fn finish(&mut self) -> io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+
+        self.flush()?;
+        self.finished = true;
+        if self.chunked && !self.discard {
+            write_all_deadline(&mut **self.socket.force_lock(), b"0\r\n\r\n")
+                .map_err(|e| io::Error::other(e.to_string()))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Write for ResponseWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.finished {
+            return Err(io::Error::other("Response stream already finished"));
+        }
+
+        if self.discard {
+            return Ok(buf.len());
+        }
+
+        self.buffer.extend_from_slice(buf);
+        if self.buffer.len() >= consts::CHUNK_SIZE {
+            self.flush()?;
+        }
+
+        Ok(buf.len())
+    }
+
+    /// Sends everything that has been written so far to the client as one chunk.
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+
+        let mut socket = self.socket.force_lock();
+        let mut size = [0; 20];
+        let written = match self.chunked {
+            true => write_vectored_deadline(
+                &mut **socket,
+                [
+                    chunk_header(self.buffer.len(), &mut size),
+                    &self.buffer,
+                    b"\r\n",
+                ],
+            ),
+            false => write_all_deadline(&mut **socket, &self.buffer),
+        };
+        written.map_err(|e| io::Error::other(e.to_string()))?;
+
+        socket.flush()?;
+        self.buffer.clear();
+        Ok(())
+    }
+}
+
+/// Stored in the socket's data once a [`ResponseWriter`] has sent a response head.
+/// The server checks for it, so it doesn't send a second response if the handler returns something other than [`Response::end`].
+pub(crate) struct StreamStarted;
+
+impl Drop for ResponseWriter {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+/// A trait for writing a response body from a route handler.
+pub trait ResponseWriterExt {
+    /// Sends the status and headers of `res`, then returns a [`ResponseWriter`] to write the body with.
+    /// The body of `res` is ignored.
+    /// The head is finished like any other response, with the server's reason phrase setting, default headers and header policies (including the route's [`crate::Route::header_policy`]).
+    ///
+    /// The route handler should return [`Response::end`] once it is done writing.
+    /// The response has already been sent, so anything else the handler returns is ignored (with an error logged).
+    /// Either way the connection is closed once the handler returns, and end middleware (like a request logger) is not run for the request, as the server never sees the response that was sent.
+    /// ## Example
+    /// ```rust,no_run
+    /// # use std::{io::Write, thread, time::Duration};
+    /// # use afire::{Server, Method, Response, Content, response_writer::ResponseWriterExt};
+    /// # let mut server = Server::<()>::new("localhost", 8080);
+    /// server.route(Method::GET, "/progress", |req| {
+    ///     let mut writer = req
+    ///         .begin_stream(Response::new().content(Content::TXT))
+    ///         .unwrap();
+    ///
+    ///     for i in 1..=10 {
+    ///         if write!(writer, "{}0%\n", i).and_then(|_| writer.flush()).is_err() {
+    ///             break;
+    ///         }
+    ///         thread::sleep(Duration::from_secs(1));
+    ///     }
+    ///
+    ///     Response::end()
+    /// });
+    /// ```
+    fn begin_stream(&self, res: Response) -> io::Result<ResponseWriter>;
+}
+
+impl ResponseWriterExt for Request {
+    fn begin_stream(&self, res: Response) -> io::Result<ResponseWriter> {
+        ResponseWriter::from_request(self, res)
+    }
+}
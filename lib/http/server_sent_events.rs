@@ -24,18 +24,65 @@
 //!   console.log(event.data);
 //! });
 //! ```
+//!
+//! ## Broadcasting
+//! To push the same events to every connected client, like for a dashboard, add each stream to a [`Broadcaster`].
+//! ```rust
+//! # use afire::{Server, Response, Method, server_sent_events::{Broadcaster, Event, ServerSentEventsExt}};
+//! # fn run(server: &mut Server) {
+//! let broadcaster = Broadcaster::new();
+//!
+//! let clients = broadcaster.clone();
+//! server.route(Method::GET, "/sse", move |req| {
+//!     clients.add(req.sse().unwrap());
+//!     Response::end()
+//! });
+//!
+//! // Later, from any thread
+//! broadcaster.broadcast(Event::new("update").data("Hello, everyone!"));
+//! # }
+//! ```
+//!
+//! ## Keep-alive
+//! Proxies often close connections that have been idle for a while, so when no events have been sent for 15 seconds a `: keep-alive` comment is sent.
+//! Browsers ignore comments, so this doesn't fire any events.
+//! The interval can be changed, or the pings turned off, with [`ServerSentEventStream::keep_alive`].
+//!
+//! ## Reconnecting
+//! When the connection drops, the browser will reconnect after the retry interval, sending the id of the last event it got in the `Last-Event-ID` header.
+//! If your events have ids, you can use [`ServerSentEventStream::backfill`] to replay the ones the client missed.
+//! ```rust
+//! # use afire::{Server, Response, Method, server_sent_events::{Event, ServerSentEventsExt}};
+//! # fn run(server: &mut Server) {
+//! server.route(Method::GET, "/sse", |req| {
+//!     let stream = req
+//!         .sse()
+//!         .unwrap()
+//!         .retry(5000)
+//!         .backfill(|last_id| (last_id + 1..10).map(|i| Event::new("update").id(i).data(i)));
+//!
+//!     // Send new events...
+//!     # drop(stream);
+//!     Response::end()
+//! });
+//! # }
+//! ```
 use std::{
-    fmt::Display,
+    fmt::{self, Display},
     io::{self, Write},
     sync::{
-        mpsc::{self, Sender},
-        Arc, Barrier,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError, Sender},
+        Arc, Barrier, Mutex,
     },
     thread,
+    time::Duration,
 };
 
 use crate::{internal::common::ForceLock, Request};
 
+type EventFilter = Box<dyn Fn(&Event) -> bool + Send>;
+
 /// A [server-sent event](https://developer.mozilla.org/en-US/docs/Web/API/Server-sent_events) stream.
 ///
 /// For more information and usage examples, visit the [module level documentation](index.html).
@@ -45,18 +92,39 @@ pub struct ServerSentEventStream {
     /// If the EventSource connection gets reset, the client will send the last received event id in the `Last-Event-ID` header.
     /// This will be available here, if applicable.
     pub last_index: Option<u32>,
+    /// Cleared by the worker thread when a write to the client fails.
+    open: Arc<AtomicBool>,
+}
+
+/// Sends events to many SSE streams at once, for pushing updates to every connected client.
+/// Streams are removed once their client disconnects, which is noticed the next time an event or keep-alive comment fails to send.
+/// Cloning a broadcaster gives another handle to the same set of streams.
+///
+/// See the [module level documentation](index.html#broadcasting) for an example.
+#[derive(Clone, Default)]
+pub struct Broadcaster {
+    clients: Arc<Mutex<Vec<Client>>>,
+}
+
+/// A stream added to a [`Broadcaster`], with the filter for the events it gets.
+struct Client {
+    stream: ServerSentEventStream,
+    filter: Option<EventFilter>,
 }
 
 /// An event that can be sent as a [server-sent event](https://developer.mozilla.org/en-US/docs/Web/API/Server-sent_events).
+#[derive(Debug, Clone)]
 pub struct Event {
     id: Option<u32>,
     event: String,
     data: String,
+    retry: Option<u32>,
 }
 
 enum EventType {
     Event(Event),
     SetRetry(u32),
+    KeepAlive(Option<Duration>),
     Close(Arc<Barrier>),
 }
 
@@ -84,6 +152,41 @@ impl ServerSentEventStream {
         let _ = self.stream.send(EventType::SetRetry(retry));
     }
 
+    /// Sets the retry interval in milliseconds, like [`ServerSentEventStream::set_retry`] but as a builder method.
+    /// This is how long the client will wait before reconnecting if the connection is lost.
+    pub fn retry(self, retry: u32) -> Self {
+        self.set_retry(retry);
+        self
+    }
+
+    /// Sets how long the stream can be idle before a keep-alive comment is sent, or None to never send them.
+    /// These stop proxies from closing the connection when no events are being sent.
+    /// Defaults to 15 seconds.
+    pub fn keep_alive(self, interval: Option<Duration>) -> Self {
+        let _ = self.stream.send(EventType::KeepAlive(interval));
+        self
+    }
+
+    /// Replays events the client missed while it was disconnected.
+    /// If the client is resuming a stream (sent a `Last-Event-ID` header), the callback is called with the last event id it received, and all the events it returns are sent.
+    /// On new connections the callback is not called.
+    ///
+    /// For the client to be able to resume, events need to be sent with ids, see [`ServerSentEventStream::send_id`] and [`Event::id`].
+    pub fn backfill<I: IntoIterator<Item = Event>>(self, backfill: impl FnOnce(u32) -> I) -> Self {
+        if let Some(last_index) = self.last_index {
+            for event in backfill(last_index) {
+                self.send_event(event);
+            }
+        }
+        self
+    }
+
+    /// Checks if the client is still connected.
+    /// This only changes once an event or keep-alive comment fails to send.
+    pub fn is_open(&self) -> bool {
+        self.open.load(Ordering::Relaxed)
+    }
+
     /// Closes the SSE stream.
     /// This will leave the socket open, so a new SSEStream could be created.
     /// Note: The client will likely try to reconnect automatically after a few seconds.
@@ -105,19 +208,40 @@ impl ServerSentEventStream {
         socket.force_lock().write_all(b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\n\r\n")?;
 
         let (tx, rx) = mpsc::channel::<EventType>();
+        let open = Arc::new(AtomicBool::new(true));
+        let worker_open = open.clone();
         thread::Builder::new()
             .name("SSE worker".to_owned())
             .spawn(move || {
-                for event in rx {
+                let write = |data: &[u8]| {
+                    if socket.force_lock().write_all(data).is_err() {
+                        worker_open.store(false, Ordering::Relaxed);
+                    }
+                };
+
+                let mut keep_alive = Some(Duration::from_secs(15));
+                loop {
+                    let event = match keep_alive {
+                        Some(interval) => match rx.recv_timeout(interval) {
+                            Ok(event) => event,
+                            Err(RecvTimeoutError::Timeout) => {
+                                write(b": keep-alive\n\n");
+                                continue;
+                            }
+                            Err(RecvTimeoutError::Disconnected) => break,
+                        },
+                        None => match rx.recv() {
+                            Ok(event) => event,
+                            Err(_) => break,
+                        },
+                    };
+
                     match event {
-                        EventType::Event(e) => {
-                            let _ = socket.force_lock().write_all(e.to_string().as_bytes());
-                        }
+                        EventType::Event(e) => write(e.to_string().as_bytes()),
                         EventType::SetRetry(retry) => {
-                            let _ = socket
-                                .force_lock()
-                                .write_all(format!("retry: {retry}\n\n").as_bytes());
+                            write(format!("retry: {retry}\n\n").as_bytes())
                         }
+                        EventType::KeepAlive(interval) => keep_alive = interval,
                         EventType::Close(b) => {
                             b.wait();
                             break;
@@ -130,10 +254,72 @@ impl ServerSentEventStream {
         Ok(Self {
             stream: tx,
             last_index,
+            open,
         })
     }
 }
 
+impl Broadcaster {
+    /// Creates a new broadcaster with no streams.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a stream that gets every broadcast event.
+    pub fn add(&self, stream: ServerSentEventStream) {
+        self.clients.force_lock().push(Client {
+            stream,
+            filter: None,
+        });
+    }
+
+    /// Adds a stream that only gets the broadcast events the filter returns true for.
+    /// ## Example
+    /// ```rust
+    /// # use afire::{Server, Response, Method, server_sent_events::{Broadcaster, ServerSentEventsExt}};
+    /// # fn run(server: &mut Server, broadcaster: Broadcaster) {
+    /// server.route(Method::GET, "/sse/{topic}", move |req| {
+    ///     let topic = req.param("topic").unwrap().into_owned();
+    ///     broadcaster.add_filtered(req.sse().unwrap(), move |event| event.event_type() == topic);
+    ///     Response::end()
+    /// });
+    /// # }
+    /// ```
+    pub fn add_filtered(
+        &self,
+        stream: ServerSentEventStream,
+        filter: impl Fn(&Event) -> bool + Send + 'static,
+    ) {
+        self.clients.force_lock().push(Client {
+            stream,
+            filter: Some(Box::new(filter)),
+        });
+    }
+
+    /// Sends an event to every stream whose filter accepts it, removing the streams of clients that have disconnected.
+    pub fn broadcast(&self, event: Event) {
+        let mut clients = self.clients.force_lock();
+        clients.retain(|x| x.stream.is_open());
+        for client in clients.iter() {
+            if client.filter.as_ref().is_none_or(|x| x(&event)) {
+                client.stream.send_event(event.clone());
+            }
+        }
+    }
+
+    /// The number of connected streams, after removing the ones of clients that have disconnected.
+    pub fn len(&self) -> usize {
+        let mut clients = self.clients.force_lock();
+        clients.retain(|x| x.stream.is_open());
+        clients.len()
+    }
+
+    /// Checks if there are no connected streams.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
 impl Event {
     /// Creates a new event with the given event type.
     pub fn new(event_type: impl AsRef<str>) -> Self {
@@ -141,9 +327,15 @@ impl Event {
             id: None,
             event: event_type.as_ref().to_owned(),
             data: String::new(),
+            retry: None,
         }
     }
 
+    /// Gets the event type.
+    pub fn event_type(&self) -> &str {
+        &self.event
+    }
+
     /// Adds an id to the event.
     pub fn id(mut self, id: u32) -> Self {
         self.id = Some(id);
@@ -151,29 +343,35 @@ impl Event {
     }
 
     /// Adds data to the event.
+    /// Data with multiple lines is sent as multiple `data` fields, which the client joins back together with newlines.
     pub fn data(mut self, data: impl Display) -> Self {
         self.data.push_str(&data.to_string());
         self
     }
-}
 
-impl ToString for Event {
-    fn to_string(&self) -> String {
-        let mut out = String::new();
+    /// Sets the client's retry interval in milliseconds along with the event, like [`ServerSentEventStream::set_retry`].
+    pub fn retry(mut self, retry: u32) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+}
 
+impl Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if let Some(id) = self.id {
-            out.push_str(&format!("id: {id}\n"));
+            writeln!(f, "id: {id}")?;
         }
 
-        let event = &self.event;
-        out.push_str(&format!("event: {event}\n"));
+        writeln!(f, "event: {}", self.event)?;
+        if let Some(retry) = self.retry {
+            writeln!(f, "retry: {retry}")?;
+        }
 
         for i in self.data.split('\n') {
-            out.push_str(&format!("data: {i}\n"));
+            writeln!(f, "data: {}", i.strip_suffix('\r').unwrap_or(i))?;
         }
 
-        out.push('\n');
-        out
+        writeln!(f)
     }
 }
 
@@ -206,5 +404,11 @@ mod test {
 
         let event = Event::new("update").id(1).data("Hello");
         assert_eq!(event.to_string(), "id: 1\nevent: update\ndata: Hello\n\n");
+
+        let event = Event::new("log").retry(500).data("a\r\nb\nc");
+        assert_eq!(
+            event.to_string(),
+            "event: log\nretry: 500\ndata: a\ndata: b\ndata: c\n\n"
+        );
     }
 }
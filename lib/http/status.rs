@@ -8,11 +8,13 @@
 /// Note: Methods that accept a [`Status`] will also accept any [`u16`] value, converting it to a Status::Custom if it is not a valid status code.
 ///
 ///  Supports Status:
-/// - 100-101
-/// - 200-206
-/// - 300-307
-/// - 400-417
-/// - 500-505
+/// - 100-103
+/// - 200-208, 226
+/// - 300-308
+/// - 400-418, 421, 425-426, 428-429, 431, 451
+/// - 500-506, 510-511
+///
+/// Use [`Status::class`] or the `is_*` methods to check what kind of status it is, which also works for custom codes.
 ///
 /// From <https://developer.mozilla.org/en-US/docs/Web/HTTP/Status>
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -24,6 +26,9 @@ pub enum Status {
     /// HTTP 101 Switching Protocols.
     /// [MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Status/101)
     SwitchingProtocols,
+    /// HTTP 102 Processing.
+    /// [MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Status/102)
+    Processing,
     /// HTTP 103 Early Hints.
     /// [MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Status/103)
     EarlyHints,
@@ -50,6 +55,15 @@ pub enum Status {
     /// HTTP 206 Partial Content.
     /// [MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Status/206)
     PartialContent,
+    /// HTTP 207 Multi-Status.
+    /// [MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Status/207)
+    MultiStatus,
+    /// HTTP 208 Already Reported.
+    /// [MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Status/208)
+    AlreadyReported,
+    /// HTTP 226 IM Used.
+    /// [MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Status/226)
+    IMUsed,
 
     // == Redirection ==
     /// HTTP 300 Multiple Choices.
@@ -70,6 +84,9 @@ pub enum Status {
     /// HTTP 305 Use Proxy.
     /// [MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Status/305)
     UseProxy,
+    /// HTTP 306 Switch Proxy.
+    /// No longer used, but reserved.
+    SwitchProxy,
     /// HTTP 307 Temporary Redirect.
     /// [MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Status/307)
     TemporaryRedirect,
@@ -191,12 +208,29 @@ pub enum Status {
     Custom(u16),
 }
 
+/// The class of a status code, from its first digit.
+/// See [`Status::class`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StatusClass {
+    /// 1xx, the request was received and is still being processed.
+    Informational,
+    /// 2xx, the request was successful.
+    Success,
+    /// 3xx, the client needs to do something else to complete the request, like follow a redirect.
+    Redirection,
+    /// 4xx, the request was invalid.
+    ClientError,
+    /// 5xx, the server failed to handle a valid request.
+    ServerError,
+}
+
 impl Status {
     /// Gets the actual HTTP status code for the status.
     pub fn code(&self) -> u16 {
         match self {
             Status::Continue => 100,
             Status::SwitchingProtocols => 101,
+            Status::Processing => 102,
             Status::EarlyHints => 103,
 
             Status::Ok => 200,
@@ -206,6 +240,9 @@ impl Status {
             Status::NoContent => 204,
             Status::ResetContent => 205,
             Status::PartialContent => 206,
+            Status::MultiStatus => 207,
+            Status::AlreadyReported => 208,
+            Status::IMUsed => 226,
 
             Status::MultipleChoices => 300,
             Status::MovedPermanently => 301,
@@ -213,6 +250,7 @@ impl Status {
             Status::SeeOther => 303,
             Status::NotModified => 304,
             Status::UseProxy => 305,
+            Status::SwitchProxy => 306,
             Status::TemporaryRedirect => 307,
             Status::PermanentRedirect => 308,
 
@@ -257,12 +295,63 @@ impl Status {
         }
     }
 
+    /// Gets the class of the status, from the first digit of its code.
+    /// Returns None for custom codes outside of 100-599.
+    /// ## Example
+    /// ```rust
+    /// # use afire::{Status, StatusClass};
+    /// assert_eq!(Status::NotFound.class(), Some(StatusClass::ClientError));
+    /// assert_eq!(Status::Custom(299).class(), Some(StatusClass::Success));
+    /// assert_eq!(Status::Custom(600).class(), None);
+    /// ```
+    pub fn class(&self) -> Option<StatusClass> {
+        Some(match self.code() {
+            100..=199 => StatusClass::Informational,
+            200..=299 => StatusClass::Success,
+            300..=399 => StatusClass::Redirection,
+            400..=499 => StatusClass::ClientError,
+            500..=599 => StatusClass::ServerError,
+            _ => return None,
+        })
+    }
+
+    /// Checks if this is an informational (1xx) status.
+    pub fn is_informational(&self) -> bool {
+        self.class() == Some(StatusClass::Informational)
+    }
+
+    /// Checks if this is a success (2xx) status.
+    pub fn is_success(&self) -> bool {
+        self.class() == Some(StatusClass::Success)
+    }
+
+    /// Checks if this is a redirection (3xx) status.
+    pub fn is_redirect(&self) -> bool {
+        self.class() == Some(StatusClass::Redirection)
+    }
+
+    /// Checks if this is a client error (4xx) status.
+    pub fn is_client_error(&self) -> bool {
+        self.class() == Some(StatusClass::ClientError)
+    }
+
+    /// Checks if this is a server error (5xx) status.
+    pub fn is_server_error(&self) -> bool {
+        self.class() == Some(StatusClass::ServerError)
+    }
+
+    /// Checks if this is a client or server error (4xx or 5xx) status.
+    pub fn is_error(&self) -> bool {
+        self.is_client_error() || self.is_server_error()
+    }
+
     /// Gets the default reason phrase for the status.
     /// For responses you can use the [`crate::Response::reason`] method to set a custom reason phrase.
     pub fn reason_phrase(&self) -> &str {
         match self.code() {
             100 => "Continue",
             101 => "Switching Protocols",
+            102 => "Processing",
             103 => "Early Hints",
 
             200 => "OK",
@@ -272,6 +361,9 @@ impl Status {
             204 => "No Content",
             205 => "Reset Content",
             206 => "Partial Content",
+            207 => "Multi-Status",
+            208 => "Already Reported",
+            226 => "IM Used",
 
             300 => "Multiple Choices",
             301 => "Moved Permanently",
@@ -279,6 +371,7 @@ impl Status {
             303 => "See Other",
             304 => "Not Modified",
             305 => "Use Proxy",
+            306 => "Switch Proxy",
             307 => "Temporary Redirect",
             308 => "Permanent Redirect",
 
@@ -328,6 +421,7 @@ impl From<u16> for Status {
         match code {
             100 => Status::Continue,
             101 => Status::SwitchingProtocols,
+            102 => Status::Processing,
             103 => Status::EarlyHints,
 
             200 => Status::Ok,
@@ -337,6 +431,9 @@ impl From<u16> for Status {
             204 => Status::NoContent,
             205 => Status::ResetContent,
             206 => Status::PartialContent,
+            207 => Status::MultiStatus,
+            208 => Status::AlreadyReported,
+            226 => Status::IMUsed,
 
             300 => Status::MultipleChoices,
             301 => Status::MovedPermanently,
@@ -344,6 +441,7 @@ impl From<u16> for Status {
             303 => Status::SeeOther,
             304 => Status::NotModified,
             305 => Status::UseProxy,
+            306 => Status::SwitchProxy,
             307 => Status::TemporaryRedirect,
             308 => Status::PermanentRedirect,
 
@@ -388,3 +486,31 @@ impl From<u16> for Status {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{Status, StatusClass};
+
+    #[test]
+    fn test_new_codes_round_trip() {
+        for code in [102, 207, 208, 226, 306] {
+            let status = Status::from(code);
+            assert!(!matches!(status, Status::Custom(_)));
+            assert_eq!(status.code(), code);
+        }
+        assert_eq!(Status::MultiStatus.reason_phrase(), "Multi-Status");
+    }
+
+    #[test]
+    fn test_status_class() {
+        assert_eq!(Status::Processing.class(), Some(StatusClass::Informational));
+        assert!(Status::IMUsed.is_success());
+        assert!(Status::SwitchProxy.is_redirect());
+        assert!(Status::Custom(499).is_client_error());
+        assert!(Status::InternalServerError.is_server_error());
+        assert!(Status::NotFound.is_error());
+        assert!(!Status::Ok.is_error());
+        assert_eq!(Status::Custom(99).class(), None);
+        assert!(!Status::Custom(600).is_server_error());
+    }
+}
@@ -0,0 +1,127 @@
+//! A map keyed by type, for attaching typed data to requests.
+//! See [`crate::Request::extensions`].
+
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    fmt::{self, Debug},
+    sync::Arc,
+};
+
+/// A map that holds at most one value of each type.
+///
+/// Used for [`crate::Request::extensions`], so pre middleware (like an auth layer) can attach typed data to a request for route handlers to use, instead of passing it through headers.
+/// Values are stored in an [`Arc`], so copies of a request share them.
+///
+/// ## Example
+/// ```rust
+/// # use afire::{Server, Method, Middleware, Request, Response, Status, middleware::MiddleResult};
+/// struct User(String);
+/// struct Auth;
+///
+/// impl Middleware for Auth {
+///     fn pre(&self, req: &mut Request) -> MiddleResult {
+///         match req.headers.get("X-User").map(ToOwned::to_owned) {
+///             Some(name) => {
+///                 req.extensions.insert(User(name));
+///                 MiddleResult::Continue
+///             }
+///             None => MiddleResult::Send(Response::new().status(Status::Unauthorized)),
+///         }
+///     }
+/// }
+///
+/// # let mut server = Server::<()>::new("localhost", 8080);
+/// Auth.attach(&mut server);
+/// server.route(Method::GET, "/", |req| {
+///     let user = req.extensions.get::<User>().unwrap();
+///     Response::new().text(format!("Hello, {}", user.0))
+/// });
+/// ```
+#[derive(Default, Clone)]
+pub struct TypeMap {
+    values: HashMap<TypeId, Arc<dyn Any + Send + Sync>>,
+}
+
+impl TypeMap {
+    /// Creates a new empty map.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gets the value of type `T`, if there is one.
+    pub fn get<T: Any + Send + Sync>(&self) -> Option<&T> {
+        self.values.get(&TypeId::of::<T>())?.downcast_ref()
+    }
+
+    /// Gets a mutable reference to the value of type `T`.
+    /// Returns None if there is no value, or if it is shared with a copy of the map.
+    pub fn get_mut<T: Any + Send + Sync>(&mut self) -> Option<&mut T> {
+        Arc::get_mut(self.values.get_mut(&TypeId::of::<T>())?)?.downcast_mut()
+    }
+
+    /// Inserts a value, returning the previous value of the same type.
+    pub fn insert<T: Any + Send + Sync>(&mut self, value: T) -> Option<Arc<T>> {
+        let old = self.values.insert(TypeId::of::<T>(), Arc::new(value))?;
+        Some(old.downcast().expect("TypeMap keyed by TypeId"))
+    }
+
+    /// Removes the value of type `T`, returning it.
+    pub fn remove<T: Any + Send + Sync>(&mut self) -> Option<Arc<T>> {
+        let old = self.values.remove(&TypeId::of::<T>())?;
+        Some(old.downcast().expect("TypeMap keyed by TypeId"))
+    }
+
+    /// Checks if there is a value of type `T`.
+    pub fn contains<T: Any + Send + Sync>(&self) -> bool {
+        self.values.contains_key(&TypeId::of::<T>())
+    }
+
+    /// The number of values in the map.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Checks if the map is empty.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+impl Debug for TypeMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Type names aren't kept, so only the count can be shown
+        f.debug_struct("TypeMap")
+            .field("len", &self.values.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TypeMap;
+
+    #[test]
+    fn test_type_map() {
+        let mut map = TypeMap::new();
+        assert!(map.is_empty());
+        assert_eq!(map.get::<u32>(), None);
+
+        assert_eq!(map.insert(5u32), None);
+        assert_eq!(map.insert("hi"), None);
+        assert_eq!(map.insert(6u32).as_deref(), Some(&5));
+        assert_eq!(map.get::<u32>(), Some(&6));
+        assert_eq!(map.len(), 2);
+
+        *map.get_mut::<u32>().unwrap() += 1;
+        assert_eq!(map.get::<u32>(), Some(&7));
+
+        let copy = map.clone();
+        assert_eq!(map.get_mut::<u32>(), None);
+        assert_eq!(copy.get::<&str>(), Some(&"hi"));
+
+        assert_eq!(map.remove::<u32>().as_deref(), Some(&7));
+        assert!(!map.contains::<u32>());
+        assert!(map.contains::<&str>());
+    }
+}
@@ -0,0 +1,57 @@
+use std::{fmt, str::FromStr};
+
+/// HTTP versions.
+///
+/// afire speaks HTTP/1.1, but also follows the HTTP/1.0 rules for older clients:
+/// connections are closed after each response unless the client asks for `Connection: keep-alive`, and streamed responses are sent without chunked encoding.
+#[derive(Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum HttpVersion {
+    /// HTTP/1.0.
+    Http10,
+
+    /// HTTP/1.1.
+    /// Requests with a later HTTP/1.x version are also treated as HTTP/1.1.
+    Http11,
+}
+
+impl FromStr for HttpVersion {
+    type Err = ();
+
+    /// Parses the version from a request line, like `HTTP/1.1`.
+    /// Minor versions past 1.1 are treated as HTTP/1.1, as they are backwards compatible.
+    /// ## Example
+    /// ```rust
+    /// # use std::str::FromStr;
+    /// # use afire::HttpVersion;
+    /// assert_eq!(HttpVersion::from_str("HTTP/1.0"), Ok(HttpVersion::Http10));
+    /// assert_eq!(HttpVersion::from_str("HTTP/1.1"), Ok(HttpVersion::Http11));
+    /// assert_eq!(HttpVersion::from_str("HTTP/1.2"), Ok(HttpVersion::Http11));
+    /// assert_eq!(HttpVersion::from_str("HTTP/2.0"), Err(()));
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let minor = s.strip_prefix("HTTP/1.").ok_or(())?;
+        if minor.is_empty() || !minor.bytes().all(|x| x.is_ascii_digit()) {
+            return Err(());
+        }
+
+        Ok(match minor.trim_start_matches('0') {
+            "" => HttpVersion::Http10,
+            _ => HttpVersion::Http11,
+        })
+    }
+}
+
+impl fmt::Display for HttpVersion {
+    /// Returns the version as it appears in a request line.
+    ///
+    /// ```rust
+    /// # use afire::HttpVersion;
+    /// assert_eq!("HTTP/1.0", HttpVersion::Http10.to_string());
+    /// ```
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            HttpVersion::Http10 => "HTTP/1.0",
+            HttpVersion::Http11 => "HTTP/1.1",
+        })
+    }
+}
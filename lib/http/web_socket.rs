@@ -1,9 +1,10 @@
+//! [WebSocket](https://developer.mozilla.org/en-US/docs/Web/API/WebSockets_API) support.
+
 use std::{
-    convert::TryInto,
     fmt::Display,
-    io::{self, Read, Write},
-    net::TcpStream,
+    io::{self, ErrorKind, Read},
     sync::{
+        atomic::{AtomicUsize, Ordering},
         mpsc::{self, Iter, Receiver, SyncSender},
         Arc,
     },
@@ -11,19 +12,62 @@ use std::{
 };
 
 use crate::{
+    consts,
+    error::{Result, WebSocketError},
     internal::{
         common::ForceLock,
         encoding::{base64, sha1},
+        socket::write_all_deadline,
     },
+    transport::Stream,
     HeaderType, Request, Response, Status,
 };
 
 const WS_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
 
+/// Close code for a frame that breaks the protocol, like a continuation frame with no message to continue.
+const CLOSE_PROTOCOL_ERROR: u16 = 1002;
+/// Close code for a message that is over the size or frame count limits.
+const CLOSE_MESSAGE_TOO_BIG: u16 = 1009;
+/// Close code for when the server can't go on, like a streamed message whose reader failed.
+const CLOSE_INTERNAL_ERROR: u16 = 1011;
+
+/// Errors that can occur when decoding a message with [`TxType::as_json`].
+#[cfg(feature = "serde")]
+#[derive(Debug)]
+pub enum JsonError {
+    /// The message is a close frame, so it has no data to decode.
+    Close,
+    /// The message data is not valid JSON, or doesn't match the type it is being decoded into.
+    Decode(serde_json::Error),
+}
+
 /// A WebSocket stream.
 pub struct WebSocketStream {
-    rx: Arc<Receiver<TxType>>,
-    tx: Arc<SyncSender<TxType>>,
+    rx: Receiver<TxType>,
+    tx: Arc<SyncSender<WriteMessage>>,
+    /// The max payload size of each frame sent with [`WebSocketStream::send_stream`].
+    fragment_size: usize,
+    /// Limits on received messages, shared with the reader thread.
+    limits: Arc<Limits>,
+}
+
+/// Limits on the messages received from the client.
+/// These are atomics so they can be changed after the reader thread is started.
+struct Limits {
+    /// The max total payload size of a message, across all its frames.
+    max_message_size: AtomicUsize,
+    /// The max number of frames a message can be split into.
+    max_frames: AtomicUsize,
+}
+
+/// Collects the frames of a fragmented message until its final frame.
+#[derive(Default)]
+struct FrameStack {
+    /// The opcode of the first frame, or None if no message is in progress.
+    opcode: Option<u8>,
+    payload: Vec<u8>,
+    frames: usize,
 }
 
 #[derive(Debug)]
@@ -38,6 +82,26 @@ struct Frame {
     payload: Vec<u8>,
 }
 
+/// Opcodes of WebSocket data frames.
+/// Used to define the type of the message sent with [`WebSocketStream::send_stream`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpCode {
+    /// A UTF-8 text message
+    Text,
+    /// A binary message
+    Binary,
+}
+
+/// Messages handled by the socket writer thread.
+enum WriteMessage {
+    /// Write a single frame
+    Frame(TxType),
+    /// Stream a message from a reader, fragmenting it into frames of the specified max size
+    Stream(Box<dyn Read + Send>, OpCode, usize),
+    /// Close the socket with a status code
+    Close(u16),
+}
+
 /// Types of WebSocket frames
 #[derive(Debug)]
 pub enum TxType {
@@ -51,9 +115,25 @@ pub enum TxType {
 
 impl WebSocketStream {
     /// Create a new WebSocket stream from a Request.
-    pub fn from_request(req: &Request) -> io::Result<Self> {
-        dbg!(&req);
-        let ws_key = req.headers.get("Sec-WebSocket-Key").unwrap().to_owned();
+    /// Returns a [`WebSocketError`] if the request isn't a valid WebSocket handshake, which can be turned into an error response.
+    pub fn from_request(req: &Request) -> Result<Self> {
+        let upgrade = req.headers.get(HeaderType::Upgrade);
+        if !upgrade.is_some_and(|x| x.eq_ignore_ascii_case("websocket")) {
+            return Err(WebSocketError::NotUpgrade.into());
+        }
+
+        match req.headers.get("Sec-WebSocket-Version") {
+            Some(version) if version.trim() != "13" => {
+                return Err(WebSocketError::UnsupportedVersion(version.to_owned()).into());
+            }
+            _ => {}
+        }
+
+        let ws_key = req
+            .headers
+            .get("Sec-WebSocket-Key")
+            .ok_or(WebSocketError::MissingKey)?
+            .to_owned();
         trace!(Level::Debug, "WS Key: {}", ws_key);
         let accept = base64::encode(&sha1::hash((ws_key + WS_GUID).as_bytes()));
         trace!(Level::Debug, "WS Accept: {}", accept);
@@ -64,39 +144,52 @@ impl WebSocketStream {
             .header(HeaderType::Connection, "Upgrade")
             .header("Sec-WebSocket-Accept", &accept)
             .header("Sec-WebSocket-Version", "13");
-        upgrade.write(req.socket.clone(), &[]).unwrap();
+        upgrade.write(req.socket.clone(), &[])?;
 
-        let (s2c, rx) = mpsc::sync_channel::<TxType>(10);
-        let (_tx, c2s) = mpsc::sync_channel::<TxType>(10);
-        let (s2c, c2s) = (Arc::new(s2c), Arc::new(c2s));
+        let (s2c, rx) = mpsc::sync_channel::<WriteMessage>(10);
+        let (c2s_tx, c2s) = mpsc::sync_channel::<TxType>(10);
+        let s2c = Arc::new(s2c);
         let this_s2c = s2c.clone();
 
         let socket = req.socket.force_lock();
-        let mut read_socket = socket.try_clone().unwrap();
-        let mut write_socket = socket.try_clone().unwrap();
+        let mut read_socket = socket.try_clone()?;
+        let mut write_socket = socket.try_clone()?;
         drop(socket);
+        let limits = Arc::new(Limits {
+            max_message_size: AtomicUsize::new(16 * 1024 * 1024),
+            max_frames: AtomicUsize::new(1024),
+        });
+        let reader_limits = limits.clone();
         thread::spawn(move || {
-            let mut buf = [0u8; 1024];
-            loop {
-                let len = read_socket.read(&mut buf).unwrap();
-                if len == 0 {
-                    break;
+            let mut stack = FrameStack::default();
+            while let Ok(mut frame) = Frame::read_header(&mut read_socket) {
+                if frame.rsv != 0 {
+                    trace!(Level::Trace, "WS: Received frame with non-zero RSV bits");
                 }
 
-                trace!(Level::Debug, "WS: Received: {:?}", &buf[..len]);
-                let frame = match Frame::from_slice(&buf[..len]) {
-                    Some(f) => f,
-                    None => continue,
+                // Control frames can't be fragmented, and can come between the frames of a message
+                let control = frame.opcode & 0b1000 != 0;
+                let close = if control && (!frame.fin || frame.payload_len > 125) {
+                    Some(CLOSE_PROTOCOL_ERROR)
+                } else if !control && !stack.fits(&frame, &reader_limits) {
+                    Some(CLOSE_MESSAGE_TOO_BIG)
+                } else {
+                    None
                 };
 
-                assert_eq!(&buf[..len], &frame.to_bytes()[..]);
+                if let Some(code) = close {
+                    trace!(Level::Debug, "WS: Closing with code {}", code);
+                    let _ = this_s2c.send(WriteMessage::Close(code));
+                    break;
+                }
 
-                if !frame.fin {
-                    todo!("Handle fragmented frames");
+                if frame.read_payload(&mut read_socket).is_err() {
+                    break;
                 }
 
-                if frame.rsv != 0 {
-                    trace!(Level::Trace, "WS: Received frame with non-zero RSV bits");
+                if frame.mask.is_none() {
+                    trace!(Level::Debug, "WS: No mask");
+                    continue;
                 }
 
                 // 0 = continuation
@@ -105,44 +198,175 @@ impl WebSocketStream {
                 // 8 = close
                 // 9 = ping
                 // 10 = pong
-                match frame.opcode {
-                    0 => {}
-                    1 => {}
-                    2 => {}
-                    8 => this_s2c.send(TxType::Close).unwrap(),
-                    9 => {}
-                    10 => {}
-                    _ => {}
-                }
-            }
-        });
+                let message = match frame.opcode {
+                    0..=2 => match stack.push(frame) {
+                        Ok(Some(message)) => message,
+                        Ok(None) => continue,
+                        Err(code) => {
+                            let _ = this_s2c.send(WriteMessage::Close(code));
+                            break;
+                        }
+                    },
+                    8 => {
+                        let _ = this_s2c.send(WriteMessage::Frame(TxType::Close));
+                        continue;
+                    }
+                    // Ping and pong frames are not handled yet
+                    _ => continue,
+                };
 
-        thread::spawn(move || {
-            //todo
-            for i in rx {
-                trace!(Level::Debug, "WS: Sending {:?}", i);
-                match i {
-                    TxType::Close => Frame::close(),
-                    TxType::Text(s) => Frame::text(s),
-                    TxType::Binary(b) => Frame::binary(b),
+                // The stream has been dropped, so no one is listening
+                if c2s_tx.send(message).is_err() {
+                    break;
                 }
-                .write(&mut write_socket)
-                .unwrap();
-                trace!(Level::Debug, "WS: Sent :p");
             }
         });
 
-        Ok(Self { rx: c2s, tx: s2c })
+        thread::spawn(move || write_messages(rx, &mut *write_socket));
+
+        Ok(Self {
+            rx: c2s,
+            tx: s2c,
+            fragment_size: consts::CHUNK_SIZE,
+            limits,
+        })
+    }
+
+    /// Sets the max total payload size of a received message, across all of its frames.
+    /// If a message goes over this, the connection is closed with a `1009 Message Too Big` close code.
+    /// By default this is 16 MiB.
+    pub fn max_message_size(self, size: usize) -> Self {
+        self.limits.max_message_size.store(size, Ordering::Relaxed);
+        self
+    }
+
+    /// Sets the max number of frames a received message can be split into.
+    /// If a message has more, the connection is closed with a `1009 Message Too Big` close code.
+    /// By default this is 1024.
+    /// Panics if `count` is 0.
+    pub fn max_frames(self, count: usize) -> Self {
+        assert!(count > 0, "Max frames must be greater than 0");
+        self.limits.max_frames.store(count, Ordering::Relaxed);
+        self
+    }
+
+    /// Sets the max payload size of the frames sent with [`WebSocketStream::send_stream`].
+    /// By default this is 16 KiB.
+    /// Panics if `size` is 0.
+    pub fn fragment_size(self, size: usize) -> Self {
+        assert!(size > 0, "Fragment size must be greater than 0");
+        Self {
+            fragment_size: size,
+            ..self
+        }
     }
 
     /// Sends 'text' data to the client.
+    /// If the connection has been closed, the message is dropped.
     pub fn send(&self, data: impl Display) {
-        self.tx.send(TxType::Text(data.to_string())).unwrap();
+        let _ = self
+            .tx
+            .send(WriteMessage::Frame(TxType::Text(data.to_string())));
     }
 
     /// Sends binary data to the client.
+    /// If the connection has been closed, the message is dropped.
     pub fn send_binary(&self, data: Vec<u8>) {
-        self.tx.send(TxType::Binary(data)).unwrap();
+        let _ = self.tx.send(WriteMessage::Frame(TxType::Binary(data)));
+    }
+
+    /// Serializes a value to JSON and sends it to the client as a text message.
+    /// If the value can't be serialized, the error is returned and nothing is sent.
+    /// Like [`WebSocketStream::send`], the message is dropped if the connection has been closed.
+    /// ## Example
+    /// ```rust,no_run
+    /// # use afire::{Method, Response, Server, web_socket::WebSocketExt};
+    /// # use serde::{Deserialize, Serialize};
+    /// # fn test(server: &mut Server) {
+    /// #[derive(Serialize, Deserialize)]
+    /// struct Message {
+    ///     user: String,
+    ///     text: String,
+    /// }
+    ///
+    /// server.route(Method::GET, "/chat", |req| {
+    ///     let ws = req.ws().unwrap();
+    ///     for i in &ws {
+    ///         match i.as_json::<Message>() {
+    ///             Ok(msg) => ws.send_json(&msg).unwrap(),
+    ///             Err(e) => ws.send(format!("Invalid message: {e}")),
+    ///         }
+    ///     }
+    ///
+    ///     Response::end()
+    /// });
+    /// # }
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn send_json(&self, data: &impl serde::Serialize) -> serde_json::Result<()> {
+        let data = serde_json::to_string(data)?;
+        let _ = self.tx.send(WriteMessage::Frame(TxType::Text(data)));
+        Ok(())
+    }
+
+    /// Sends a message to the client from a reader.
+    /// The data is read in pieces of [`WebSocketStream::fragment_size`] bytes, each being sent as its own frame.
+    /// This lets you send large messages (like files) without having to load the whole thing into memory first.
+    /// If the connection has been closed, the reader is dropped without being read.
+    /// If the reader fails partway through, the connection is closed with a `1011 Internal Error` close code, as the client can't make sense of the rest of the message.
+    /// ## Example
+    /// ```rust,no_run
+    /// # use afire::{Method, Response, Server, web_socket::{OpCode, WebSocketExt}};
+    /// # use std::fs::File;
+    /// # fn test(server: &mut Server) {
+    /// server.route(Method::GET, "/download", |req| {
+    ///     let ws = req.ws().unwrap();
+    ///     let file = File::open("data.bin").unwrap();
+    ///     ws.send_stream(file, OpCode::Binary);
+    ///
+    ///     Response::end()
+    /// });
+    /// # }
+    /// ```
+    pub fn send_stream(&self, data: impl Read + Send + 'static, opcode: OpCode) {
+        let _ = self.tx.send(WriteMessage::Stream(
+            Box::new(data),
+            opcode,
+            self.fragment_size,
+        ));
+    }
+}
+
+impl TxType {
+    /// Decodes the data of a text or binary message as JSON.
+    /// See [`WebSocketStream::send_json`] for an example.
+    #[cfg(feature = "serde")]
+    pub fn as_json<T: serde::de::DeserializeOwned>(&self) -> std::result::Result<T, JsonError> {
+        match self {
+            TxType::Close => Err(JsonError::Close),
+            TxType::Text(i) => serde_json::from_str(i).map_err(JsonError::Decode),
+            TxType::Binary(i) => serde_json::from_slice(i).map_err(JsonError::Decode),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Display for JsonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JsonError::Close => f.write_str("Close frames have no data"),
+            JsonError::Decode(e) => write!(f, "Invalid JSON: {e}"),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl std::error::Error for JsonError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            JsonError::Close => None,
+            JsonError::Decode(e) => Some(e),
+        }
     }
 }
 
@@ -156,60 +380,74 @@ impl<'a> IntoIterator for &'a WebSocketStream {
 }
 
 impl Frame {
-    fn from_slice(buf: &[u8]) -> Option<Self> {
-        let fin = buf[0] & 0b1000_0000 != 0;
-        let rsv = (buf[0] & 0b0111_0000) >> 4;
-
-        let mask = buf[1] & 0b1000_0000 != 0;
-        let opcode = buf[0] & 0b0000_1111;
-        let (payload_len, offset) = match buf[1] as u64 & 0b0111_1111 {
-            126 => (u16::from_be_bytes([buf[2], buf[3]]) as u64, 4),
-            127 => (
-                u64::from_be_bytes([
-                    buf[2], buf[3], buf[4], buf[5], buf[6], buf[7], buf[8], buf[9],
-                ]),
-                10,
-            ),
-            i => (i, 2),
+    /// Reads the header of a frame, up to the start of its payload.
+    /// The payload is left to be read with [`Frame::read_payload`], so its length can be checked first.
+    fn read_header(socket: &mut impl Read) -> io::Result<Self> {
+        let mut head = [0; 2];
+        socket.read_exact(&mut head)?;
+
+        let fin = head[0] & 0b1000_0000 != 0;
+        let rsv = (head[0] & 0b0111_0000) >> 4;
+        let opcode = head[0] & 0b0000_1111;
+        let mask = head[1] & 0b1000_0000 != 0;
+
+        let payload_len = match head[1] as u64 & 0b0111_1111 {
+            126 => {
+                let mut len = [0; 2];
+                socket.read_exact(&mut len)?;
+                u16::from_be_bytes(len) as u64
+            }
+            127 => {
+                let mut len = [0; 8];
+                socket.read_exact(&mut len)?;
+                u64::from_be_bytes(len)
+            }
+            i => i,
         };
-        trace!(
-            Level::Debug,
-            "WS: {{ fin: {fin}, rsv: {rsv}, opcode: {opcode}, payload_len: {payload_len}, mask: {mask} }}",
-        );
 
-        if payload_len == 0 {
-            trace!(Level::Debug, "WS: Empty payload");
-            return None;
-        }
-
-        if !mask {
-            trace!(Level::Debug, "WS: No mask");
-            return None;
-        }
-
-        let mut decoded = Vec::with_capacity(payload_len as usize);
-        let mask = &buf[offset..offset + 4];
-        for i in 0..payload_len as usize {
-            decoded.push(buf[i + offset + 4] ^ mask[i % 4]);
-        }
+        let mask = match mask {
+            true => {
+                let mut mask = [0; 4];
+                socket.read_exact(&mut mask)?;
+                Some(mask)
+            }
+            false => None,
+        };
 
-        trace!(Level::Debug, "WS: Decoded: {:?}", decoded);
         trace!(
             Level::Debug,
-            "WS: Decoded: {:?}",
-            String::from_utf8_lossy(&decoded)
+            "WS: {{ fin: {fin}, rsv: {rsv}, opcode: {opcode}, payload_len: {payload_len}, mask: {} }}",
+            mask.is_some()
         );
 
-        Some(Self {
+        Ok(Self {
             fin,
             rsv,
             opcode,
             payload_len,
-            mask: Some(mask.try_into().unwrap()),
-            payload: decoded,
+            mask,
+            payload: Vec::new(),
         })
     }
 
+    /// Reads and unmasks the payload of a frame, after its header.
+    fn read_payload(&mut self, socket: &mut impl Read) -> io::Result<()> {
+        let mut payload = vec![0; self.payload_len as usize];
+        socket.read_exact(&mut payload)?;
+
+        self.payload = match self.mask {
+            Some(mask) => xor_mask(&mask, &payload),
+            None => payload,
+        };
+
+        trace!(
+            Level::Debug,
+            "WS: Decoded: {:?}",
+            String::from_utf8_lossy(&self.payload)
+        );
+        Ok(())
+    }
+
     /*
       0                   1                   2                   3
       0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
@@ -238,7 +476,7 @@ impl Frame {
             ..=125 => buf.push((self.mask.is_some() as u8) << 7 | self.payload_len as u8),
             126..=65535 => {
                 buf.push((self.mask.is_some() as u8) << 7 | 126);
-                buf.extend_from_slice(&self.payload_len.to_be_bytes());
+                buf.extend_from_slice(&(self.payload_len as u16).to_be_bytes());
             }
             _ => {
                 buf.push((self.mask.is_some() as u8) << 7 | 127);
@@ -257,12 +495,22 @@ impl Frame {
         buf
     }
 
-    fn write(&self, socket: &mut TcpStream) -> io::Result<()> {
+    fn write(&self, socket: &mut dyn Stream) -> Result<()> {
         let buf = self.to_bytes();
         trace!(Level::Debug, "WS: Writing: {:?}", buf);
 
-        socket.write_all(&buf)?;
-        Ok(())
+        write_all_deadline(socket, &buf)
+    }
+
+    fn fragment(opcode: u8, fin: bool, data: Vec<u8>) -> Self {
+        Self {
+            fin,
+            rsv: 0,
+            opcode,
+            payload_len: data.len() as u64,
+            mask: None,
+            payload: data,
+        }
     }
 
     fn close() -> Self {
@@ -276,6 +524,17 @@ impl Frame {
         }
     }
 
+    fn close_code(code: u16) -> Self {
+        Self {
+            fin: true,
+            rsv: 0,
+            opcode: 8,
+            payload_len: 2,
+            mask: None,
+            payload: code.to_be_bytes().to_vec(),
+        }
+    }
+
     fn text(text: String) -> Self {
         Self {
             fin: true,
@@ -297,28 +556,77 @@ impl Frame {
             payload: binary,
         }
     }
+}
 
-    fn rsv1(&self) -> bool {
-        self.rsv & 0b100 != 0
+impl FrameStack {
+    /// Checks if a data frame can be added to the message without going over the limits.
+    fn fits(&self, frame: &Frame, limits: &Limits) -> bool {
+        let size = self.payload.len() as u64 + frame.payload_len;
+        size <= limits.max_message_size.load(Ordering::Relaxed) as u64
+            && self.frames < limits.max_frames.load(Ordering::Relaxed)
     }
 
-    fn rsv2(&self) -> bool {
-        self.rsv & 0b010 != 0
+    /// Adds a data frame to the message.
+    /// Returns the message once its final frame is added, or the close code if the frame doesn't fit in the message.
+    fn push(&mut self, frame: Frame) -> std::result::Result<Option<TxType>, u16> {
+        match (frame.opcode, self.opcode) {
+            (0, Some(_)) => {}
+            (0, None) | (_, Some(_)) => return Err(CLOSE_PROTOCOL_ERROR),
+            (opcode, None) => self.opcode = Some(opcode),
+        }
+
+        self.payload.extend_from_slice(&frame.payload);
+        self.frames += 1;
+        if !frame.fin {
+            return Ok(None);
+        }
+
+        let payload = std::mem::take(&mut self.payload);
+        let opcode = self.opcode.take();
+        self.frames = 0;
+        Ok(Some(match opcode {
+            Some(1) => TxType::Text(String::from_utf8_lossy(&payload).into_owned()),
+            _ => TxType::Binary(payload),
+        }))
     }
+}
 
-    fn rsv3(&self) -> bool {
-        self.rsv & 0b001 != 0
+impl OpCode {
+    /// Gets the raw opcode used in the frame header.
+    fn code(&self) -> u8 {
+        match self {
+            OpCode::Text => 1,
+            OpCode::Binary => 2,
+        }
     }
 }
 
 /// A trait for initiating a WebSocket connection on a request.
 pub trait WebSocketExt {
     /// Initiates a WebSocket connection on a request.
-    fn ws(&self) -> io::Result<WebSocketStream>;
+    /// Fails with [`crate::Error::WebSocket`] if the request isn't a valid WebSocket handshake.
+    /// ## Example
+    /// ```rust
+    /// # use afire::{Method, Response, Server, Error, web_socket::WebSocketExt};
+    /// # fn test(server: &mut Server) {
+    /// server.route(Method::GET, "/ws", |req| {
+    ///     let ws = match req.ws() {
+    ///         Ok(ws) => ws,
+    ///         // 400 Bad Request: Missing Sec-WebSocket-Key header
+    ///         Err(Error::WebSocket(e)) => return e.into(),
+    ///         Err(_) => return Response::end(),
+    ///     };
+    ///
+    ///     ws.send("Hello!");
+    ///     Response::end()
+    /// });
+    /// # }
+    /// ```
+    fn ws(&self) -> Result<WebSocketStream>;
 }
 
 impl WebSocketExt for Request {
-    fn ws(&self) -> io::Result<WebSocketStream> {
+    fn ws(&self) -> Result<WebSocketStream> {
         WebSocketStream::from_request(self)
     }
 }
@@ -333,3 +641,304 @@ fn xor_mask(mask: &[u8], data: &[u8]) -> Vec<u8> {
 
     decoded
 }
+
+/// Writes the messages sent to the writer thread to the socket.
+/// Once a write fails or a close frame is sent the connection is unusable, so this returns and later sends are dropped.
+fn write_messages(rx: Receiver<WriteMessage>, socket: &mut dyn Stream) {
+    for i in rx {
+        let (written, close) = match i {
+            WriteMessage::Frame(frame) => {
+                trace!(Level::Debug, "WS: Sending {:?}", frame);
+                let close = matches!(frame, TxType::Close);
+                let frame = match frame {
+                    TxType::Close => Frame::close(),
+                    TxType::Text(s) => Frame::text(s),
+                    TxType::Binary(b) => Frame::binary(b),
+                };
+                (frame.write(socket), close)
+            }
+            WriteMessage::Stream(data, opcode, size) => {
+                trace!(Level::Debug, "WS: Streaming {:?} message", opcode);
+                match write_stream(socket, data, opcode, size) {
+                    Ok(()) => (Ok(()), false),
+                    // The message was cut off partway, so nothing sent after it would make sense to the client
+                    Err(e) => {
+                        trace!(Level::Debug, "WS: Error streaming message: {:?}", e);
+                        (Frame::close_code(CLOSE_INTERNAL_ERROR).write(socket), true)
+                    }
+                }
+            }
+            WriteMessage::Close(code) => (Frame::close_code(code).write(socket), true),
+        };
+
+        if let Err(e) = written {
+            trace!(Level::Debug, "WS: Error sending message: {:?}", e);
+            break;
+        }
+
+        if close {
+            trace!(Level::Debug, "WS: Sent close frame");
+            break;
+        }
+    }
+}
+
+/// Writes a message from a reader to the socket as a series of fragmented frames.
+/// The first frame has the message's opcode, the following ones are continuation frames.
+/// One chunk is always read ahead, so the last frame can be marked with the FIN bit.
+fn write_stream(
+    socket: &mut dyn Stream,
+    mut data: Box<dyn Read + Send>,
+    opcode: OpCode,
+    size: usize,
+) -> Result<()> {
+    let mut opcode = opcode.code();
+    let mut chunk = read_chunk(&mut data, size)?;
+
+    loop {
+        let next = read_chunk(&mut data, size)?;
+        let fin = next.is_empty();
+        Frame::fragment(opcode, fin, chunk).write(socket)?;
+
+        if fin {
+            break;
+        }
+
+        opcode = 0;
+        chunk = next;
+    }
+
+    Ok(())
+}
+
+/// Reads up to `size` bytes from the reader.
+/// Only returns less than `size` bytes if the end of the reader is reached.
+fn read_chunk(data: &mut impl Read, size: usize) -> io::Result<Vec<u8>> {
+    let mut chunk = vec![0; size];
+    let mut read = 0;
+
+    while read < size {
+        match data.read(&mut chunk[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    chunk.truncate(read);
+    Ok(chunk)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{self, Cursor, Read, Write},
+        net::{Shutdown, SocketAddr},
+        sync::{atomic::AtomicUsize, mpsc},
+    };
+
+    use super::{
+        read_chunk, write_messages, Frame, FrameStack, Limits, OpCode, TxType, WebSocketExt,
+        WriteMessage,
+    };
+    use crate::{
+        error::WebSocketError,
+        testing::{TestRequest, TestServer},
+        transport::Stream,
+        Error, Method, Response, Server, Status,
+    };
+
+    /// A stream that records what is written to it, failing once more than `limit` bytes have been written.
+    struct Recorder {
+        written: Vec<u8>,
+        limit: usize,
+    }
+
+    impl Read for Recorder {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            Ok(0)
+        }
+    }
+
+    impl Write for Recorder {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.written.len() + buf.len() > self.limit {
+                return Err(io::ErrorKind::BrokenPipe.into());
+            }
+
+            self.written.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Stream for Recorder {
+        fn peer_addr(&self) -> io::Result<SocketAddr> {
+            Ok(SocketAddr::from(([0, 0, 0, 0], 0)))
+        }
+
+        fn try_clone(&self) -> io::Result<Box<dyn Stream>> {
+            Err(io::ErrorKind::Unsupported.into())
+        }
+
+        fn shutdown(&self, _how: Shutdown) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Runs the writer loop over `messages`, returning what was written to the socket.
+    fn write_all(messages: Vec<WriteMessage>, limit: usize) -> Vec<u8> {
+        let (tx, rx) = mpsc::sync_channel(messages.len());
+        for i in messages {
+            tx.send(i).unwrap();
+        }
+        drop(tx);
+
+        let mut socket = Recorder {
+            written: Vec::new(),
+            limit,
+        };
+        write_messages(rx, &mut socket);
+        socket.written
+    }
+
+    #[test]
+    fn test_frame_extended_len() {
+        let frame = Frame::fragment(2, true, vec![0; 300]);
+        let bytes = frame.to_bytes();
+
+        assert_eq!(bytes[0], 0b1000_0010);
+        assert_eq!(bytes[1], 126);
+        assert_eq!(&bytes[2..4], &300u16.to_be_bytes());
+        assert_eq!(bytes.len(), 4 + 300);
+    }
+
+    #[test]
+    fn test_read_frame() {
+        let mut frame = Frame::text("Hello".to_owned());
+        frame.mask = Some([1, 2, 3, 4]);
+        let mut bytes = Cursor::new(frame.to_bytes());
+
+        let mut read = Frame::read_header(&mut bytes).unwrap();
+        assert!(read.fin);
+        assert_eq!(read.opcode, 1);
+        assert_eq!(read.payload_len, 5);
+        read.read_payload(&mut bytes).unwrap();
+        assert_eq!(read.payload, b"Hello");
+    }
+
+    #[test]
+    fn test_frame_stack() {
+        let limits = Limits {
+            max_message_size: AtomicUsize::new(8),
+            max_frames: AtomicUsize::new(2),
+        };
+        let mut stack = FrameStack::default();
+
+        let first = Frame::fragment(2, false, vec![1; 4]);
+        assert!(stack.fits(&first, &limits));
+        assert!(stack.push(first).unwrap().is_none());
+        assert!(!stack.fits(&Frame::fragment(0, true, vec![2; 5]), &limits));
+
+        let message = stack.push(Frame::fragment(0, true, vec![2; 4])).unwrap();
+        assert!(matches!(message, Some(TxType::Binary(x)) if x.len() == 8));
+        assert!(stack.push(Frame::fragment(0, true, vec![])).is_err());
+
+        stack = FrameStack::default();
+        stack.push(Frame::fragment(1, false, vec![])).unwrap();
+        stack.push(Frame::fragment(0, false, vec![])).unwrap();
+        assert!(!stack.fits(&Frame::fragment(0, true, vec![]), &limits));
+    }
+
+    #[test]
+    fn test_read_chunk() {
+        let mut data = Cursor::new(vec![1; 10]);
+        assert_eq!(read_chunk(&mut data, 4).unwrap().len(), 4);
+        assert_eq!(read_chunk(&mut data, 4).unwrap().len(), 4);
+        assert_eq!(read_chunk(&mut data, 4).unwrap().len(), 2);
+        assert!(read_chunk(&mut data, 4).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_write_stream_error() {
+        let text = |x: &str| WriteMessage::Frame(TxType::Text(x.to_owned()));
+        let frames = |x: &[Frame]| x.iter().flat_map(Frame::to_bytes).collect::<Vec<_>>();
+
+        // A reader that fails partway through closes the connection with 1011, dropping later messages
+        let data = Cursor::new(vec![1; 8]).chain(FailingReader);
+        let written = write_all(
+            vec![
+                WriteMessage::Stream(Box::new(data), OpCode::Binary, 4),
+                text("after"),
+            ],
+            usize::MAX,
+        );
+        assert_eq!(
+            written,
+            frames(&[
+                Frame::fragment(2, false, vec![1; 4]),
+                Frame::close_code(1011)
+            ])
+        );
+
+        // Once the socket fails partway through a stream, nothing else is written
+        let data = Cursor::new(vec![1; 12]);
+        let written = write_all(
+            vec![
+                WriteMessage::Stream(Box::new(data), OpCode::Binary, 4),
+                text("after"),
+            ],
+            6,
+        );
+        assert_eq!(written, frames(&[Frame::fragment(2, false, vec![1; 4])]));
+
+        // Nothing is sent after a close frame
+        let written = write_all(
+            vec![text("a"), WriteMessage::Frame(TxType::Close), text("b")],
+            usize::MAX,
+        );
+        assert_eq!(
+            written,
+            frames(&[Frame::text("a".to_owned()), Frame::close()])
+        );
+    }
+
+    struct FailingReader;
+
+    impl Read for FailingReader {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::Other.into())
+        }
+    }
+
+    #[test]
+    fn test_handshake_errors() {
+        let mut server = Server::<()>::new("localhost", 8080);
+        server.route(Method::GET, "/ws", |req| match req.ws() {
+            Err(Error::WebSocket(e)) => e.into(),
+            _ => Response::end(),
+        });
+        let server = TestServer::from(server);
+
+        let res = TestRequest::get("/ws").send(&server);
+        assert_eq!(res.status, Status::BadRequest);
+        assert_eq!(res.text(), WebSocketError::NotUpgrade.to_string());
+
+        let res = TestRequest::get("/ws")
+            .header("Upgrade", "websocket")
+            .send(&server);
+        assert_eq!(res.text(), WebSocketError::MissingKey.to_string());
+
+        let res = TestRequest::get("/ws")
+            .header("Upgrade", "websocket")
+            .header("Sec-WebSocket-Version", "8")
+            .header("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ==")
+            .send(&server);
+        assert_eq!(res.status, Status::UpgradeRequired);
+        assert_eq!(res.header("Sec-WebSocket-Version"), Some("13"));
+    }
+}
@@ -0,0 +1,196 @@
+//! A minimal blocking HTTP/1.1 client, for extensions that need to talk to other servers (like [`crate::extension::OAuth`] exchanging tokens).
+//! Each request uses a new connection, and `https` urls are sent over rustls.
+
+use std::{
+    convert::TryFrom,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    sync::Arc,
+    time::Duration,
+};
+
+use rustls::{pki_types::ServerName, ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+
+use crate::{header::Headers, Header, Method, VERSION};
+
+/// The largest response body that will be read.
+const MAX_BODY: usize = 4 * 1024 * 1024;
+
+/// A response from [`request`].
+pub(crate) struct ClientResponse {
+    pub status: u16,
+    pub headers: Headers,
+    pub body: Vec<u8>,
+}
+
+/// Makes a TLS client config that trusts the Mozilla root certificates.
+pub(crate) fn default_tls_config() -> Arc<ClientConfig> {
+    let roots = RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    let config =
+        ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .expect("The ring provider supports the default protocol versions")
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+    Arc::new(config)
+}
+
+/// Sends a request and reads the whole response.
+/// `Host`, `Content-Length` and `Connection` headers are added automatically.
+pub(crate) fn request(
+    method: Method,
+    url: &str,
+    headers: &[Header],
+    body: &[u8],
+    tls: &Arc<ClientConfig>,
+    timeout: Duration,
+) -> io::Result<ClientResponse> {
+    let invalid = |msg| io::Error::new(io::ErrorKind::InvalidInput, msg);
+    let (https, rest) = match url.split_once("://") {
+        Some(("https", rest)) => (true, rest),
+        Some(("http", rest)) => (false, rest),
+        _ => return Err(invalid("url must be http or https")),
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(i) => rest.split_at(i),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        // The colon could be part of an IPv6 address
+        Some((host, port)) if !port.is_empty() && port.bytes().all(|x| x.is_ascii_digit()) => {
+            (host, port.parse().map_err(|_| invalid("invalid port"))?)
+        }
+        _ => (authority, if https { 443 } else { 80 }),
+    };
+    let host = host.trim_matches(['[', ']']);
+
+    let mut head = format!(
+        "{method} {path} HTTP/1.1\r\nHost: {authority}\r\nUser-Agent: afire/{VERSION}\r\nConnection: close\r\nContent-Length: {}\r\n",
+        body.len()
+    );
+    for i in headers {
+        head.push_str(&format!("{}: {}\r\n", i.name, i.value));
+    }
+    head.push_str("\r\n");
+
+    let addr = (host, port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| invalid("host not found"))?;
+    let tcp = TcpStream::connect_timeout(&addr, timeout)?;
+    tcp.set_read_timeout(Some(timeout))?;
+    tcp.set_write_timeout(Some(timeout))?;
+
+    let mut stream: Box<dyn ReadWrite> = match https {
+        true => {
+            let name =
+                ServerName::try_from(host.to_owned()).map_err(|_| invalid("invalid host"))?;
+            let conn = ClientConnection::new(tls.clone(), name).map_err(io::Error::other)?;
+            Box::new(StreamOwned::new(conn, tcp))
+        }
+        false => Box::new(tcp),
+    };
+
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(body)?;
+    stream.flush()?;
+    read_response(BufReader::new(stream))
+}
+
+/// A stream that can be read from and written to.
+trait ReadWrite: Read + Write {}
+impl<T: Read + Write> ReadWrite for T {}
+
+/// Reads a response, with a body of either a fixed length, chunked or until the connection closes.
+fn read_response(mut stream: impl BufRead) -> io::Result<ClientResponse> {
+    let invalid = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+    let mut line = String::new();
+    stream.read_line(&mut line)?;
+    let status = line
+        .split(' ')
+        .nth(1)
+        .and_then(|x| x.parse().ok())
+        .ok_or_else(|| invalid("invalid status line"))?;
+
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        stream.read_line(&mut line)?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        headers.push(Header::from_string(line).map_err(|_| invalid("invalid header"))?);
+    }
+    let headers = Headers(headers);
+
+    let mut body = Vec::new();
+    let chunked = headers
+        .get("Transfer-Encoding")
+        .is_some_and(|x| x.eq_ignore_ascii_case("chunked"));
+    if chunked {
+        loop {
+            let mut size = String::new();
+            stream.read_line(&mut size)?;
+            let size = usize::from_str_radix(size.split(';').next().unwrap_or_default().trim(), 16)
+                .map_err(|_| invalid("invalid chunk size"))?;
+            if body.len() + size > MAX_BODY {
+                return Err(invalid("response too large"));
+            }
+
+            let start = body.len();
+            body.resize(start + size, 0);
+            stream.read_exact(&mut body[start..])?;
+            stream.read_line(&mut String::new())?;
+            if size == 0 {
+                break;
+            }
+        }
+    } else if let Some(len) = headers.get("Content-Length") {
+        let len = len.parse().map_err(|_| invalid("invalid content length"))?;
+        if len > MAX_BODY {
+            return Err(invalid("response too large"));
+        }
+        body.resize(len, 0);
+        stream.read_exact(&mut body)?;
+    } else {
+        match stream.take(MAX_BODY as u64).read_to_end(&mut body) {
+            // Lots of servers close TLS connections without a close_notify
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {}
+            Err(e) => return Err(e),
+            Ok(_) => {}
+        }
+    }
+
+    Ok(ClientResponse {
+        status,
+        headers,
+        body,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::read_response;
+
+    #[test]
+    fn test_read_response() {
+        let res = read_response(
+            &b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n2;x=y\r\nde\r\n0\r\n\r\n"[..],
+        )
+        .unwrap();
+        assert_eq!(res.status, 200);
+        assert_eq!(res.body, b"abcde");
+
+        let res =
+            read_response(&b"HTTP/1.1 404 Not Found\r\nContent-Length: 2\r\n\r\nhi and more"[..])
+                .unwrap();
+        assert_eq!(res.status, 404);
+        assert_eq!(res.body, b"hi");
+
+        let res = read_response(&b"HTTP/1.0 200 OK\r\n\r\nuntil close"[..]).unwrap();
+        assert_eq!(res.body, b"until close");
+    }
+}
@@ -63,11 +63,11 @@ impl ToHostAddress for &str {
 
 /// Adds a force_lock method to Mutex, which will return the inner value even if its poisoned.
 pub(crate) trait ForceLock<T> {
-    fn force_lock(&self) -> MutexGuard<T>;
+    fn force_lock(&self) -> MutexGuard<'_, T>;
 }
 
 impl<T> ForceLock<T> for Mutex<T> {
-    fn force_lock(&self) -> MutexGuard<T> {
+    fn force_lock(&self) -> MutexGuard<'_, T> {
         match self.lock() {
             Ok(i) => i,
             Err(e) => e.into_inner(),
@@ -121,6 +121,36 @@ pub(crate) fn epoch() -> std::time::Duration {
         .expect("System time is before the Unix Epoch. Make sure your date is set correctly.")
 }
 
+/// Takes the first socket passed in by systemd socket activation, if there is one for this process.
+/// The socket can only be taken once, later calls return None.
+#[cfg(all(feature = "systemd", unix))]
+pub(crate) fn systemd_listener() -> Option<std::net::TcpListener> {
+    use std::{
+        env,
+        os::unix::io::FromRawFd,
+        sync::atomic::{AtomicBool, Ordering},
+    };
+
+    /// The first file descriptor systemd passes, after stdin, stdout and stderr.
+    const LISTEN_FDS_START: i32 = 3;
+    static TAKEN: AtomicBool = AtomicBool::new(false);
+
+    let pid = env::var("LISTEN_PID").ok()?;
+    let fds = env::var("LISTEN_FDS").ok()?;
+    if !listen_fds_for(&pid, &fds, std::process::id()) || TAKEN.swap(true, Ordering::SeqCst) {
+        return None;
+    }
+
+    // SAFETY: systemd leaves the socket open for this process, and it is only taken once
+    Some(unsafe { std::net::TcpListener::from_raw_fd(LISTEN_FDS_START) })
+}
+
+/// Checks if the `LISTEN_PID` and `LISTEN_FDS` environment variables pass at least one socket to the process with this pid.
+#[cfg(all(feature = "systemd", unix))]
+fn listen_fds_for(pid: &str, fds: &str, this_pid: u32) -> bool {
+    pid.trim().parse::<u32>() == Ok(this_pid) && fds.trim().parse::<u32>().is_ok_and(|x| x > 0)
+}
+
 #[cfg(test)]
 mod test {
     use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
@@ -193,7 +223,7 @@ mod test {
     #[test]
     fn test_from_ref_string_addr() {
         assert_eq!(
-            (&"127.0.0.1".to_owned()).to_address().unwrap(),
+            <&String as ToHostAddress>::to_address(&&"127.0.0.1".to_owned()).unwrap(),
             IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))
         );
     }
@@ -205,4 +235,16 @@ mod test {
             IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))
         );
     }
+
+    #[test]
+    #[cfg(all(feature = "systemd", unix))]
+    fn test_listen_fds_for() {
+        use super::listen_fds_for;
+
+        assert!(listen_fds_for("123", "1", 123));
+        assert!(listen_fds_for("123", "2", 123));
+        assert!(!listen_fds_for("124", "1", 123));
+        assert!(!listen_fds_for("123", "0", 123));
+        assert!(!listen_fds_for("abc", "1", 123));
+    }
 }
@@ -8,7 +8,7 @@ const CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ\
 
 /// Encodes a byte slice into a base64 string (with padding).
 pub fn encode(inp: &[u8]) -> String {
-    let end_len = (inp.len() + 2) / 3 * 4;
+    let end_len = inp.len().div_ceil(3) * 4;
     let mut out = String::with_capacity(end_len);
 
     for i in (0..inp.len()).step_by(3) {
@@ -56,8 +56,8 @@ pub fn decode(inp: &str) -> Option<Vec<u8>> {
                 'A'..='Z' => decode |= ((e - 65) as u32) << (6 * (3 - i)),
                 'a'..='z' => decode |= ((e - 71) as u32) << (6 * (3 - i)),
                 '0'..='9' => decode |= ((e + 4) as u32) << (6 * (3 - i)),
-                '+' => decode |= 62 << (6 * i),
-                '/' => decode |= 63 << (6 * i),
+                '+' => decode |= 62 << (6 * (3 - i)),
+                '/' => decode |= 63 << (6 * (3 - i)),
                 '=' => {
                     out.extend_from_slice(&decode.to_be_bytes()[1..i]);
                     continue 'o;
@@ -72,9 +72,26 @@ pub fn decode(inp: &str) -> Option<Vec<u8>> {
     Some(out)
 }
 
+/// Encodes a byte slice into a url safe base64 string (`-` and `_` instead of `+` and `/`), without padding.
+pub fn encode_url(inp: &[u8]) -> String {
+    encode(inp)
+        .trim_end_matches('=')
+        .replace('+', "-")
+        .replace('/', "_")
+}
+
+/// Decodes a url safe base64 string, with or without padding.
+pub fn decode_url(inp: &str) -> Option<Vec<u8>> {
+    let mut inp = inp.replace('-', "+").replace('_', "/");
+    while inp.len() % 4 != 0 {
+        inp.push('=');
+    }
+    decode(&inp)
+}
+
 #[cfg(test)]
 mod test {
-    use super::{decode, encode};
+    use super::{decode, decode_url, encode, encode_url};
 
     #[test]
     fn test_base64_encode() {
@@ -96,5 +113,13 @@ mod test {
         assert_eq!(decode("Zm9vYg==").unwrap(), b"foob");
         assert_eq!(decode("Zm9vYmE=").unwrap(), b"fooba");
         assert_eq!(decode("Zm9vYmFy").unwrap(), b"foobar");
+        assert_eq!(decode("+/+/").unwrap(), [0xfb, 0xff, 0xbf]);
+    }
+
+    #[test]
+    fn test_base64_url() {
+        assert_eq!(encode_url(&[0xfb, 0xff, 0xbf, 0xfe]), "-_-__g");
+        assert_eq!(decode_url("-_-__g").unwrap(), [0xfb, 0xff, 0xbf, 0xfe]);
+        assert_eq!(decode_url("Zm9vYg==").unwrap(), b"foob");
     }
 }
@@ -0,0 +1,249 @@
+//! Deserializing url encoded forms into structs with serde.
+//! Each key is a field, with its value parsed from a string into the field's type.
+//! Repeated keys can be collected into a `Vec`, and `Option` fields are `None` if their key is missing.
+
+use std::{error, fmt, str::FromStr};
+
+use serde::de::{
+    self,
+    value::{SeqDeserializer, StrDeserializer},
+    DeserializeOwned, IntoDeserializer, MapAccess, Visitor,
+};
+
+use crate::Query;
+
+/// An error deserializing a form, with a message from serde or a value that couldn't be parsed.
+#[derive(Debug)]
+pub struct FormError(String);
+
+/// Deserializes the pairs of a [`Query`] into a type.
+pub fn from_query<T: DeserializeOwned>(query: &Query) -> Result<T, FormError> {
+    T::deserialize(Form(query))
+}
+
+/// Deserializes a whole form as a map.
+struct Form<'a>(&'a Query);
+
+/// Deserializes the values of one key.
+/// Single values use the first one, and sequences use all of them.
+struct Values<'a>(Vec<&'a str>);
+
+/// Walks the keys of a form, in the order they first appear.
+struct Fields<'a> {
+    query: &'a Query,
+    keys: std::vec::IntoIter<&'a str>,
+    key: &'a str,
+}
+
+impl<'de> de::Deserializer<'de> for Form<'_> {
+    type Error = FormError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FormError> {
+        let mut keys = Vec::<&str>::new();
+        for [key, _] in self.0.iter() {
+            if !keys.contains(&key.as_str()) {
+                keys.push(key);
+            }
+        }
+
+        visitor.visit_map(Fields {
+            query: self.0,
+            keys: keys.into_iter(),
+            key: "",
+        })
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+impl<'de> MapAccess<'de> for Fields<'_> {
+    type Error = FormError;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, FormError> {
+        let Some(key) = self.keys.next() else {
+            return Ok(None);
+        };
+
+        self.key = key;
+        let key: StrDeserializer<FormError> = key.into_deserializer();
+        seed.deserialize(key).map(Some)
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, FormError> {
+        let values = self.query.get_all(self.key);
+        seed.deserialize(Values(values))
+            .map_err(|FormError(e)| FormError(format!("field `{}`: {e}", self.key)))
+    }
+}
+
+impl Values<'_> {
+    fn first(&self) -> &str {
+        self.0.first().copied().unwrap_or_default()
+    }
+
+    fn parse<T: FromStr>(&self) -> Result<T, FormError>
+    where
+        T::Err: fmt::Display,
+    {
+        let value = self.first();
+        value
+            .parse()
+            .map_err(|e| FormError(format!("invalid value {value:?} ({e})")))
+    }
+}
+
+/// Implements the deserialize methods for types that are parsed from a string.
+macro_rules! parse {
+    ($($method:ident => $visit:ident),* $(,)?) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FormError> {
+                visitor.$visit(self.parse()?)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for Values<'_> {
+    type Error = FormError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FormError> {
+        visitor.visit_str(self.first())
+    }
+
+    parse! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_i128 => visit_i128,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_u128 => visit_u128,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FormError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FormError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, FormError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FormError> {
+        let values = self.0.into_iter().map(|x| Values(vec![x]));
+        visitor.visit_seq(SeqDeserializer::new(values))
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, FormError> {
+        let value: StrDeserializer<FormError> = self.first().into_deserializer();
+        de::Deserializer::deserialize_enum(value, name, variants, visitor)
+    }
+
+    serde::forward_to_deserialize_any! {
+        str string bytes byte_buf unit_struct tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+impl<'de, 'a> IntoDeserializer<'de, FormError> for Values<'a> {
+    type Deserializer = Values<'a>;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        self
+    }
+}
+
+impl de::Error for FormError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        FormError(msg.to_string())
+    }
+}
+
+impl fmt::Display for FormError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl error::Error for FormError {}
+
+#[cfg(test)]
+mod test {
+    use serde::Deserialize;
+
+    use super::from_query;
+    use crate::Query;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(rename_all = "lowercase")]
+    enum Color {
+        Red,
+        Blue,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Signup {
+        name: String,
+        age: u8,
+        subscribe: Option<bool>,
+        color: Color,
+        tags: Vec<String>,
+    }
+
+    #[test]
+    fn test_form() {
+        let query = Query::from_body("name=Darren+Smith&age=25&color=blue&tags=a&tags=b%26c");
+        assert_eq!(
+            from_query::<Signup>(&query).unwrap(),
+            Signup {
+                name: "Darren Smith".to_owned(),
+                age: 25,
+                subscribe: None,
+                color: Color::Blue,
+                tags: vec!["a".to_owned(), "b&c".to_owned()],
+            }
+        );
+    }
+
+    #[test]
+    fn test_form_errors() {
+        let query = Query::from_body("name=Darren&age=old&color=red&tags=");
+        let err = from_query::<Signup>(&query).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("field `age`: invalid value \"old\""));
+
+        let query = Query::from_body("age=1&color=red&tags=");
+        let err = from_query::<Signup>(&query).unwrap_err();
+        assert_eq!(err.to_string(), "missing field `name`");
+    }
+}
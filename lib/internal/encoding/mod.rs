@@ -1,5 +1,8 @@
 //! Functions having to do with encoding, decoding, and hashing data.
 
 pub mod base64;
+#[cfg(feature = "serde")]
+pub(crate) mod form;
 pub mod sha1;
+pub mod sha256;
 pub mod url;
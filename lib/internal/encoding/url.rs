@@ -1,27 +1,33 @@
 //! Utilities for encoding and decoding strings.
 //! Currently only supports url encoding.
 
+use std::str;
+
 /// Decode a url encoded string.
 /// Supports `+` and `%` encoding.
-/// If the decode fails for any reason, [`None`] is returned.
+/// Percent encoded bytes are decoded as UTF-8, so multi-byte characters (ex: `%C3%A9` => `é`) work as expected.
+/// If the decode fails for any reason (invalid percent encoding or invalid UTF-8), [`None`] is returned.
 pub fn decode(url: &str) -> Option<String> {
-    let mut chars = url.chars();
-    let mut out = String::with_capacity(url.len());
+    let mut bytes = url.bytes();
+    let mut out = Vec::with_capacity(url.len());
 
-    while let Some(i) = chars.next() {
+    while let Some(i) = bytes.next() {
         match i {
-            '+' => out.push(' '),
-            '%' => {
-                let mut hex = String::new();
-                hex.push(chars.next()?);
-                hex.push(chars.next()?);
-                out.push(u8::from_str_radix(&hex, 16).ok()? as char);
+            b'+' => out.push(b' '),
+            b'%' => {
+                let hex = [bytes.next()?, bytes.next()?];
+                if !hex.iter().all(u8::is_ascii_hexdigit) {
+                    return None;
+                }
+
+                let hex = str::from_utf8(&hex).ok()?;
+                out.push(u8::from_str_radix(hex, 16).ok()?);
             }
             _ => out.push(i),
         }
     }
 
-    Some(out)
+    String::from_utf8(out).ok()
 }
 
 /// Encodes a string with url encoding.
@@ -34,12 +40,12 @@ pub fn encode(url: &str) -> String {
 
     let mut out = String::with_capacity(url.len());
 
-    for i in url.chars() {
-        if i.is_ascii() && ALLOWED_CHARS.contains(&(i as u8)) {
-            out.push(i);
+    for i in url.bytes() {
+        if ALLOWED_CHARS.contains(&i) {
+            out.push(i as char);
             continue;
         }
-        out.push_str(&format!("%{:02X}", i as u8));
+        out.push_str(&format!("%{i:02X}"));
     }
 
     out
@@ -64,6 +70,14 @@ mod test {
         assert_eq!(decode("hello%20world%"), None);
         assert_eq!(decode("hello%20world%2"), None);
         assert_eq!(decode("hello%20world%2G"), None);
+        assert_eq!(decode("hello%+1world"), None);
+        assert_eq!(decode("%FF"), None);
+    }
+
+    #[test]
+    fn test_url_utf8() {
+        assert_eq!(decode("caf%C3%A9").unwrap(), "café");
+        assert_eq!(encode("café"), "caf%C3%A9");
     }
 
     #[test]
@@ -1,20 +1,19 @@
-use std::{
-    cell::RefCell,
-    io::Read,
-    net::{Shutdown, TcpStream},
-    ops::Deref,
-    panic,
-    rc::Rc,
-    sync::{Arc, Mutex},
-};
+use std::{cell::RefCell, io::Read, net::Shutdown, ops::Deref, panic, rc::Rc, sync::Arc};
 
 use crate::{
     error::{HandleError, ParseError, Result, StreamError},
-    internal::common::any_string,
-    middleware::MiddleResult,
+    internal::{
+        common::{any_string, ForceLock},
+        encoding::url,
+    },
+    middleware::{MiddleResult, Responder, ResponseInfo},
     response::ResponseFlag,
-    route::RouteType,
-    trace, Content, Error, Request, Response, Server, Status,
+    response_writer::StreamStarted,
+    route::{Route, RoutePolicy, RouteType},
+    server::CloseReason,
+    trace,
+    transport::{Socket, Stream},
+    Content, Error, Header, HeaderType, HttpVersion, Method, Request, Response, Server, Status,
 };
 
 pub(crate) type Writeable = Box<RefCell<dyn Read + Send>>;
@@ -26,20 +25,124 @@ pub(crate) type Writeable = Box<RefCell<dyn Read + Send>>;
 /// - Lets Response::write write the response to the socket
 /// - Runs End Middleware
 /// - Optionally closes the socket
-pub(crate) fn handle<State>(stream: TcpStream, this: &Server<State>)
+pub(crate) fn handle<State>(stream: Box<dyn Stream>, this: &Server<State>)
+where
+    State: 'static + Send + Sync,
+{
+    handle_socket(open_socket(stream, this), this);
+}
+
+/// Like [`handle`], but for a socket that was already opened with [`open_socket`].
+pub(crate) fn handle_socket<State>(stream: Arc<Socket>, this: &Server<State>)
+where
+    State: 'static + Send + Sync,
+{
+    let mut pending = Vec::new();
+    loop {
+        let (req, close) = read_request(&stream, &mut pending, this);
+        let (req, res) = get_response(req, this);
+
+        if !respond(&stream, req.as_deref(), res, close, &mut pending, this) {
+            break;
+        }
+    }
+}
+
+/// The two pools used by [`crate::Server::start_split`].
+#[cfg(feature = "tcp")]
+pub(crate) struct SplitPools {
+    /// Reads requests from and writes responses to sockets.
+    pub io: crate::thread_pool::ThreadPool,
+    /// Runs middleware and route handlers.
+    pub handler: crate::thread_pool::ThreadPool,
+}
+
+/// Like [`handle`], but only the socket reads and writes run on the current thread (from the io pool).
+/// Once a request is read, middleware and the route handler are run on the handler pool, then the response is passed back to the io pool to be written.
+/// This way slow route handlers can't hold up reading and writing other sockets.
+///
+/// `pending` holds bytes read past the end of the last request, see [`Request::from_socket`].
+#[cfg(feature = "tcp")]
+pub(crate) fn handle_split<State>(
+    stream: Arc<Socket>,
+    mut pending: Vec<u8>,
+    this: Arc<Server<State>>,
+    pools: Arc<SplitPools>,
+) where
+    State: 'static + Send + Sync,
+{
+    let (req, close) = read_request(&stream, &mut pending, &this);
+
+    // Requests that failed to parse are handled right away, as there is no route handler to run
+    let req = match req {
+        Ok(req) => req,
+        Err(e) => {
+            let (req, res) = get_response(Err(e), &this);
+            respond(&stream, req.as_deref(), res, close, &mut pending, &this);
+            return;
+        }
+    };
+
+    let client = Some(req.address.ip());
+    let handler_pools = pools.clone();
+    pools.handler.execute(client, move || {
+        let (req, res) = get_response(Ok(req), &this);
+        // Nothing keeps the request after the response is made, so this will only fail if a handler smuggled out a copy.
+        // Without the request the connection can't be safely reused (its body may still be on the socket), so it is closed.
+        let (req, close) = match req.map(Rc::try_unwrap) {
+            Some(Err(req)) => {
+                trace!(
+                    Level::Error,
+                    "A handler kept a reference to the request for {} {}, closing the connection",
+                    req.method,
+                    req.path
+                );
+                (None, close.or(Some(CloseReason::Errored)))
+            }
+            req => (req.and_then(|x| x.ok()), close),
+        };
+
+        let io_pools = handler_pools.clone();
+        handler_pools.io.execute(client, move || {
+            if respond(&stream, req.as_ref(), res, close, &mut pending, &this) {
+                handle_split(stream, pending, this, io_pools);
+            }
+        });
+    });
+}
+
+/// Applies the socket options and wraps the socket so it can be shared with the Request.
+pub(crate) fn open_socket<State>(stream: Box<dyn Stream>, this: &Server<State>) -> Arc<Socket>
 where
     State: 'static + Send + Sync,
 {
     trace!(Level::Debug, "Opening socket {:?}", stream.peer_addr());
     stream.set_read_timeout(this.socket_timeout).unwrap();
     stream.set_write_timeout(this.socket_timeout).unwrap();
-    let stream = Arc::new(Mutex::new(stream));
-    loop {
-        let mut keep_alive = false;
-        let req = Request::from_socket(stream.clone());
+    if this.nodelay {
+        let _ = stream.set_nodelay(true);
+    }
+
+    let socket = Socket::new(stream);
+    socket.insert_data(this.response_finish());
+    Arc::new(socket)
+}
+
+/// Reads the next request from the socket.
+/// Also returns why the connection should be closed after the response, or None if the client wants it kept alive.
+fn read_request<State>(
+    stream: &Arc<Socket>,
+    pending: &mut Vec<u8>,
+    this: &Server<State>,
+) -> (Result<Request>, Option<CloseReason>)
+where
+    State: 'static + Send + Sync,
+{
+    let req = Request::from_socket(stream.clone(), pending, this.parse_limits());
 
-        if let Ok(req) = &req {
-            keep_alive = req.keep_alive();
+    let close = match &req {
+        Ok(req) => {
+            let keep_alive = req.keep_alive();
             trace!(
                 Level::Debug,
                 "{} {} {{ keep_alive: {} }}",
@@ -47,36 +150,164 @@ where
                 req.path,
                 keep_alive
             );
+            (!keep_alive).then_some(CloseReason::Completed)
         }
+        Err(Error::Stream(StreamError::TimedOut | StreamError::IdleTimeout)) => {
+            Some(CloseReason::TimedOut)
+        }
+        Err(Error::Stream(StreamError::UnexpectedEof)) => Some(CloseReason::ClientClosed),
+        Err(Error::Stream(StreamError::ConnectionReset)) => Some(CloseReason::ClientReset),
+        Err(_) => Some(CloseReason::Errored),
+    };
 
-        let (req, mut res) = get_response(req, this);
+    (req, close)
+}
 
-        if res.flag == ResponseFlag::End {
-            trace!(Level::Debug, "Ending socket");
-            break;
+/// Writes the response, runs the end middleware and closes the socket if needed.
+/// `pending` gets back any bytes of the next request that were read along with a streamed body.
+/// Returns true if the socket is still open and the next request should be read.
+fn respond<State>(
+    stream: &Arc<Socket>,
+    req: Option<&Request>,
+    mut res: Response,
+    close: Option<CloseReason>,
+    pending: &mut Vec<u8>,
+    this: &Server<State>,
+) -> bool
+where
+    State: 'static + Send + Sync,
+{
+    // A response written with a ResponseWriter has already been sent, so whatever the handler returned can't be
+    if stream.remove_data::<StreamStarted>().is_some()
+        && matches!(res.flag, ResponseFlag::None | ResponseFlag::Close)
+    {
+        trace!(
+            Level::Error,
+            "Handler returned a response after using a ResponseWriter, return Response::end() instead"
+        );
+        res = Response::end();
+    }
+
+    // Errors reading the request take priority over the handler asking to close, so they are counted correctly
+    let close = match (&res.flag, close) {
+        (ResponseFlag::Reset, _) => Some(CloseReason::Aborted),
+        (_, Some(reason)) if reason != CloseReason::Completed => Some(reason),
+        (ResponseFlag::End | ResponseFlag::Close, _) => Some(CloseReason::HandlerClosed),
+        (ResponseFlag::None, close) => {
+            close.or((!this.keep_alive).then_some(CloseReason::Completed))
         }
+    };
 
-        if let Err(e) = res.write(stream.clone(), &this.default_headers) {
-            trace!(Level::Debug, "Error writing to socket: {:?}", e);
+    if res.flag == ResponseFlag::Reset {
+        trace!(Level::Debug, "Aborting socket");
+        this.connection_stats.record(CloseReason::Aborted);
+        if let Err(e) = stream.force_lock().abort() {
+            trace!(Level::Debug, "Error aborting socket: {:?}", e);
         }
+        return false;
+    }
 
-        // End Middleware
-        if let Some(req) = req {
-            for i in this.middleware.iter().rev() {
-                if let Err(e) = panic::catch_unwind(panic::AssertUnwindSafe(|| i.end(&req, &res))) {
-                    trace!(Level::Error, "Error running end middleware: {:?}", e);
-                }
+    if res.flag == ResponseFlag::End {
+        trace!(Level::Debug, "Ending socket");
+        this.connection_stats
+            .record(close.unwrap_or(CloseReason::HandlerClosed));
+        return false;
+    }
+
+    // HTTP/1.0 has no chunked encoding, so the end of a streamed body is marked by closing the connection
+    let version = req.map_or(HttpVersion::Http11, |x| x.version);
+    let close = match version {
+        HttpVersion::Http10 if !res.data.is_static() => close.or(Some(CloseReason::Completed)),
+        _ => close,
+    };
+
+    // Connections are closed once they have served the max number of requests
+    let requests = stream.requests();
+    let close = match this.keep_alive_max {
+        Some(max) if requests >= max => {
+            if !res.headers.has(HeaderType::Connection) {
+                res.headers.push(Header::new("Connection", "close"));
             }
+            close.or(Some(CloseReason::Completed))
         }
+        _ => close,
+    };
 
-        if !keep_alive || res.flag == ResponseFlag::Close || !this.keep_alive {
-            trace!(Level::Debug, "Closing socket");
-            if let Err(e) = stream.lock().unwrap().shutdown(Shutdown::Both) {
-                trace!(Level::Debug, "Error closing socket: {:?}", e);
+    // The default headers are added here, so the header policy can remove them
+    this.finalize_response(&mut res);
+    // HTTP/1.0 connections are closed by default, so keeping one open has to be confirmed
+    if version == HttpVersion::Http10 && close.is_none() && !res.headers.has(HeaderType::Connection)
+    {
+        res.headers.push(Header::new("Connection", "keep-alive"));
+    }
+
+    // Let the client know how long it can leave the connection idle and how many more requests it can send
+    if close.is_none() && !res.headers.has("Keep-Alive") {
+        let mut params = Vec::new();
+        if let Some(timeout) = this.keep_alive_timeout {
+            params.push(format!("timeout={}", timeout.as_secs()));
+        }
+        if let Some(max) = this.keep_alive_max {
+            params.push(format!("max={}", max - requests));
+        }
+
+        if !params.is_empty() {
+            res.headers
+                .push(Header::new("Keep-Alive", params.join(", ")));
+        }
+    }
+
+    // With auto HEAD, responses to HEAD requests have their headers sent without the body
+    let head_only = this.auto_head && req.is_some_and(|x| x.method == Method::HEAD);
+
+    // A partly written response leaves the connection unusable
+    let mut sent = 0;
+    let close = match res.write_as(stream.clone(), &[], version, head_only, &mut sent) {
+        Ok(()) => close,
+        Err(e) => {
+            trace!(Level::Debug, "Error writing to socket: {:?}", e);
+            close.or(Some(match e {
+                Error::Stream(StreamError::ConnectionReset) => CloseReason::ClientReset,
+                _ => CloseReason::Errored,
+            }))
+        }
+    };
+
+    // End Middleware
+    if let Some(req) = req {
+        let info = ResponseInfo {
+            bytes_written: sent,
+            duration: req.received().elapsed(),
+            status: res.status,
+        };
+
+        for i in this.middleware.iter().rev() {
+            let end = || i.end_with_info(req, &res, &info);
+            if let Err(e) = panic::catch_unwind(panic::AssertUnwindSafe(end)) {
+                trace!(Level::Error, "Error running end middleware: {:?}", e);
             }
-            break;
         }
     }
+
+    // The next request can't be read until the rest of a streamed body is off the socket
+    let close = close.or_else(|| {
+        let limit = this.body_buffer_limit.unwrap_or(usize::MAX);
+        match req {
+            Some(req) if !req.finish_body(limit, pending) => Some(CloseReason::Completed),
+            _ => None,
+        }
+    });
+
+    if let Some(reason) = close {
+        trace!(Level::Debug, "Closing socket ({:?})", reason);
+        this.connection_stats.record(reason);
+        if let Err(e) = stream.lock().unwrap().shutdown(Shutdown::Both) {
+            trace!(Level::Debug, "Error closing socket: {:?}", e);
+        }
+        return false;
+    }
+
+    true
 }
 
 /// Gets the response from a request.
@@ -101,16 +332,29 @@ where
                 res = Ok(this_res);
                 break;
             }
+            Ok(MiddleResult::Defer(defer)) => match req {
+                Ok(req) => return (None, run_defer(defer, req, server)),
+                Err(e) => {
+                    trace!(Level::Error, "Can't defer a request that failed to parse");
+                    req = Err(e);
+                    break;
+                }
+            },
             Ok(MiddleResult::Abort) => break,
             Ok(MiddleResult::Continue) => {}
             Err(e) => return handle_error(e, req.map(Rc::new), server),
         }
     }
 
-    let req = req.map(Rc::new);
-    if res.is_err() {
-        if let Ok(req) = req.clone() {
-            res = handle_route(req, server);
+    let (req, mut res) = match (req, res) {
+        (Ok(req), Err(_)) => handle_route(req, server),
+        (req, res) => (req.map(Rc::new), res),
+    };
+
+    // Route middleware handed the request off, so there is nothing left to do with it
+    if let (Err(Error::None), Ok(res)) = (&req, &res) {
+        if res.flag == ResponseFlag::End {
+            return (None, Response::end());
         }
     }
 
@@ -121,6 +365,10 @@ where
         })) {
             Ok(MiddleResult::Send(res)) => return (req.ok(), res),
             Ok(MiddleResult::Abort) => break,
+            Ok(MiddleResult::Defer(_)) => {
+                trace!(Level::Error, "Only pre middleware can defer requests");
+                break;
+            }
             Ok(MiddleResult::Continue) => {}
             Err(e) => return handle_error(e, req, server),
         }
@@ -134,7 +382,9 @@ where
                 Ok(_) => &e,
             };
 
-            return (None, error_response(error, server));
+            // The request is still passed on, so end middleware sees error responses too
+            let res = error_response(error, server);
+            return (req.ok(), res);
         }
     };
 
@@ -142,33 +392,192 @@ where
 }
 
 /// Tries to find a route that matches the request.
-/// If it finds one, it will call the handler and return the result (assuming it doesn't panic).
-/// If it doesn't find one, it will return an Error of HandleError::NotFound.
-fn handle_route<State>(req: Rc<Request>, this: &Server<State>) -> Result<Response>
+/// If it finds one, it will run the route's middleware and handler and return the result (assuming they don't panic).
+/// The request is returned too, as it can't be shared until the route middleware has run.
+/// If it doesn't find one, it will return an Error of HandleError::NotFound, or HandleError::MethodNotAllowed if routes match the path with other methods (and [`Server::method_not_allowed`] is enabled).
+/// Unmatched OPTIONS requests are answered here if [`Server::auto_options`] is enabled.
+/// HEAD requests fall back to the GET routes if [`Server::auto_head`] is enabled.
+fn handle_route<State>(
+    mut req: Request,
+    this: &Server<State>,
+) -> (Result<Rc<Request>>, Result<Response>)
 where
     State: 'static + Send + Sync,
 {
     // Handle Route
-    let path = req.path.to_owned();
-    for route in this.routes.iter().rev() {
-        if let Some(params) = route.matches(req.clone()) {
-            *req.path_params.borrow_mut() = params;
-            let result = panic::catch_unwind(panic::AssertUnwindSafe(|| match &route.handler {
-                RouteType::Stateless(i) => (i)(&req),
-                RouteType::Stateful(i) => {
-                    (i)(this.state.clone().expect("State not initialized"), &req)
+    // With auto HEAD, HEAD requests fall back to the GET routes if no route matches them directly
+    let head = this.auto_head && req.method == Method::HEAD;
+    let candidates = this.route_candidates(&req.path);
+    let routes = || candidates.iter().map(|&i| &this.routes[i]);
+    let matched = routes()
+        .find_map(|route| Some((route, route.matches(&req)?)))
+        .or_else(|| {
+            routes()
+                .filter(|route| head && route.method() == Method::GET)
+                .find_map(|route| Some((route, route.matches_path(&req)?)))
+        });
+
+    if let Some((route, params)) = matched {
+        if params.iter().any(|(_, value)| url::decode(value).is_none()) {
+            return (Ok(Rc::new(req)), Err(ParseError::InvalidPathParam.into()));
+        }
+
+        if let Some(res) = route.slash_redirect(&req) {
+            return (Ok(Rc::new(req)), Ok(res));
+        }
+
+        let _ = req.path_params.set(params);
+        let _ = req.route.set(route.pattern());
+        if let Some(policy) = &route.header_policy {
+            req.extensions.insert(RoutePolicy(policy.clone()));
+        }
+        return run_route(route, req, this);
+    }
+
+    let req = Rc::new(req);
+    let res = unmatched_route(&req, this);
+    (Ok(req), res)
+}
+
+/// Runs a matched route's middleware and handler.
+/// Works like the server's middleware in [`get_response`], but the route middleware is inside the server's.
+fn run_route<State>(
+    route: &Route<State>,
+    req: Request,
+    this: &Server<State>,
+) -> (Result<Rc<Request>>, Result<Response>)
+where
+    State: 'static + Send + Sync,
+{
+    let panic_error = |error, req: Result<Rc<Request>>| {
+        let err = HandleError::Panic(Box::new(req.clone()), any_string(error).into_owned());
+        (req, Err(Error::Handle(Box::new(err))))
+    };
+
+    let mut req = Ok(req);
+    let mut res = Err(Error::None);
+    for i in route.middleware.iter().rev() {
+        match panic::catch_unwind(panic::AssertUnwindSafe(|| i.pre_raw(&mut req))) {
+            Ok(MiddleResult::Send(this_res)) => {
+                res = Ok(this_res);
+                break;
+            }
+            Ok(MiddleResult::Defer(defer)) => match req {
+                // The error is just a placeholder, as the request is now owned by the responder
+                Ok(req) => return (Err(Error::None), Ok(run_defer(defer, req, this))),
+                Err(e) => {
+                    trace!(Level::Error, "Can't defer a request that failed to parse");
+                    req = Err(e);
+                    break;
                 }
-            }));
+            },
+            Ok(MiddleResult::Abort) => break,
+            Ok(MiddleResult::Continue) => {}
+            Err(e) => return panic_error(e, req.map(Rc::new)),
+        }
+    }
 
-            let err = match result {
-                Ok(i) => return Ok(i),
-                Err(e) => any_string(e),
-            };
+    let req = req.map(Rc::new);
+    if res.is_err() {
+        if let Ok(req) = req.clone() {
+            res = run_handler(route, req, this);
+        }
+    }
 
-            return Err(Error::Handle(Box::new(HandleError::Panic(
-                Box::new(Ok(req)),
-                err.into_owned(),
-            ))));
+    for i in route.middleware.iter().rev() {
+        match panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            i.post_raw(req.clone(), &mut res)
+        })) {
+            Ok(MiddleResult::Send(res)) => return (req, Ok(res)),
+            Ok(MiddleResult::Abort) => break,
+            Ok(MiddleResult::Defer(_)) => {
+                trace!(Level::Error, "Only pre middleware can defer requests");
+                break;
+            }
+            Ok(MiddleResult::Continue) => {}
+            Err(e) => return panic_error(e, req),
+        }
+    }
+
+    (req, res)
+}
+
+/// Hands a request off to a function from [`MiddleResult::Defer`], catching panics.
+/// Returns a response that ends the connection without sending anything, as the [`Responder`] will write to it later.
+fn run_defer<State>(
+    defer: Box<dyn FnOnce(Responder) + Send>,
+    req: Request,
+    this: &Server<State>,
+) -> Response
+where
+    State: 'static + Send + Sync,
+{
+    trace!(
+        Level::Debug,
+        "Deferring request {} {}",
+        req.method,
+        req.path
+    );
+    let responder = Responder::new(req, this);
+    if let Err(e) = panic::catch_unwind(panic::AssertUnwindSafe(|| defer(responder))) {
+        trace!(Level::Error, "Error deferring request: {}", any_string(e));
+    }
+
+    Response::end()
+}
+
+/// Calls a route's handler, catching panics.
+fn run_handler<State>(
+    route: &Route<State>,
+    req: Rc<Request>,
+    this: &Server<State>,
+) -> Result<Response>
+where
+    State: 'static + Send + Sync,
+{
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| match &route.handler {
+        RouteType::Stateless(i) => (i)(&req),
+        RouteType::Stateful(i) => (i)(this.state.clone().expect("State not initialized"), &req),
+        #[cfg(feature = "async")]
+        RouteType::Async(i) => (this.async_executor)((i)(&req)),
+    }));
+
+    let err = match result {
+        Ok(mut res) => {
+            res.header_policy = route.header_policy.clone();
+            return Ok(res);
+        }
+        Err(e) => any_string(e),
+    };
+
+    Err(Error::Handle(Box::new(HandleError::Panic(
+        Box::new(Ok(req)),
+        err.into_owned(),
+    ))))
+}
+
+/// Gets the response for a request that didn't match any route.
+/// This is a 404, unless [`Server::method_not_allowed`] or [`Server::auto_options`] apply.
+fn unmatched_route<State>(req: &Request, this: &Server<State>) -> Result<Response>
+where
+    State: 'static + Send + Sync,
+{
+    let path = req.path.to_owned();
+    if this.method_not_allowed || this.auto_options {
+        let mut allowed = allowed_methods(req, this);
+        if !allowed.is_empty() {
+            if this.auto_options && req.method == Method::OPTIONS {
+                allowed.push(Method::OPTIONS);
+                return Ok(Response::new()
+                    .status(Status::NoContent)
+                    .header("Allow", allow_header(&allowed)));
+            }
+
+            if this.method_not_allowed {
+                return Err(Error::Handle(Box::new(HandleError::MethodNotAllowed(
+                    req.method, path, allowed,
+                ))));
+            }
         }
     }
 
@@ -177,6 +586,35 @@ where
     ))))
 }
 
+/// Gets the methods of the routes that match the request's path, in the order the routes were defined.
+fn allowed_methods<State>(req: &Request, this: &Server<State>) -> Vec<Method>
+where
+    State: 'static + Send + Sync,
+{
+    let mut allowed = Vec::new();
+    for &i in this.route_candidates(&req.path).iter().rev() {
+        let route = &this.routes[i];
+        let method = route.method();
+        if !allowed.contains(&method) && route.matches_path(req).is_some() {
+            allowed.push(method);
+        }
+    }
+
+    if this.auto_head && allowed.contains(&Method::GET) && !allowed.contains(&Method::HEAD) {
+        allowed.push(Method::HEAD);
+    }
+    allowed
+}
+
+/// Formats a list of methods for the `Allow` header.
+fn allow_header(methods: &[Method]) -> String {
+    methods
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
 /// Gets a response if there is an error.
 /// Can handle Parse, Handle and IO errors.
 pub fn error_response<State>(err: &Error, server: &Server<State>) -> Response
@@ -189,26 +627,246 @@ where
         }
         Error::Stream(e) => match e {
             StreamError::UnexpectedEof => Response::new().status(400).text("Unexpected EOF"),
+            StreamError::ClientDisconnected => {
+                Response::new().status(400).text("Client disconnected")
+            }
+            StreamError::TimedOut => Response::new()
+                .status(Status::RequestTimeOut)
+                .text("Request Timeout")
+                .content(Content::TXT)
+                .close(),
+            // There is no one to send a response to
+            StreamError::ConnectionReset | StreamError::IdleTimeout => Response::end(),
         },
-        Error::Parse(e) => Response::new().status(400).text(match e {
-            ParseError::NoSeparator => "No separator",
-            ParseError::NoMethod => "No method",
-            ParseError::NoPath => "No path",
-            ParseError::NoVersion => "No HTTP version",
-            ParseError::NoRequestLine => "No request line",
-            ParseError::InvalidQuery => "Invalid query",
-            ParseError::InvalidHeader => "Invalid header",
-            ParseError::InvalidMethod => "Invalid method",
-        }),
+        Error::Parse(
+            e
+            @ (ParseError::TooManyHeaders | ParseError::HeaderTooLarge | ParseError::HeadTooLarge),
+        ) => Response::new()
+            .status(Status::RequestHeaderFieldsTooLarge)
+            .text(e)
+            .content(Content::TXT)
+            .close(),
+        Error::Parse(ParseError::UriTooLong) => Response::new()
+            .status(Status::URITooLarge)
+            .text(ParseError::UriTooLong)
+            .content(Content::TXT)
+            .close(),
+        Error::Parse(ParseError::ExpectationFailed) => Response::new()
+            .status(Status::ExpectationFailed)
+            .text(ParseError::ExpectationFailed)
+            .content(Content::TXT)
+            .close(),
+        Error::Parse(ParseError::BodyTooLarge) => Response::new()
+            .status(Status::PayloadTooLarge)
+            .text(ParseError::BodyTooLarge)
+            .content(Content::TXT)
+            .close(),
+        Error::Parse(ParseError::UnsupportedVersion) => Response::new()
+            .status(Status::HTTPVersionNotSupported)
+            .text("HTTP Version Not Supported")
+            .content(Content::TXT)
+            .close(),
+        Error::Parse(e) => Response::new()
+            .status(400)
+            .text(match e {
+                ParseError::NoSeparator => "No separator",
+                ParseError::NoMethod => "No method",
+                ParseError::NoPath => "No path",
+                ParseError::NoVersion => "No HTTP version",
+                ParseError::NoRequestLine => "No request line",
+                ParseError::InvalidQuery => "Invalid query",
+                ParseError::InvalidHeader => "Invalid header",
+                ParseError::InvalidMethod => "Invalid method",
+                ParseError::InvalidPathParam => "Invalid path parameter",
+                ParseError::InvalidChunkedBody => "Invalid chunked body",
+                ParseError::InvalidContentLength => "Invalid Content-Length",
+                ParseError::InvalidTransferEncoding => "Invalid Transfer-Encoding",
+                ParseError::UnsupportedVersion
+                | ParseError::TooManyHeaders
+                | ParseError::HeaderTooLarge
+                | ParseError::HeadTooLarge
+                | ParseError::UriTooLong
+                | ParseError::ExpectationFailed
+                | ParseError::BodyTooLarge => unreachable!(),
+            })
+            // The rest of the request can't be found, so the connection can't be used again
+            .close(),
         Error::Handle(e) => match e.deref() {
             HandleError::NotFound(method, path) => Response::new()
                 .status(Status::NotFound)
                 .text(format!("Cannot {method} {path}"))
                 .content(Content::TXT),
+            HandleError::MethodNotAllowed(method, path, allowed) => Response::new()
+                .status(Status::MethodNotAllowed)
+                .text(format!("Cannot {method} {path}"))
+                .content(Content::TXT)
+                .header("Allow", allow_header(allowed)),
             HandleError::Panic(r, e) => {
-                (server.error_handler)(server.state.clone(), r, e.to_owned())
+                trace!(
+                    Level::Error,
+                    "Handler panicked on {}: `{}`",
+                    request_summary(r),
+                    e
+                );
+                // The reproduction has the request body, which can hold things like passwords, so it's kept out of error logs
+                #[cfg(feature = "tracing")]
+                if let Ok(req) = r.as_ref() {
+                    trace!(
+                        Level::Debug,
+                        "Reproduce panic with: {}",
+                        reproduce(req, &server.redacted_headers)
+                    );
+                }
+                match panic::catch_unwind(panic::AssertUnwindSafe(|| {
+                    (server.error_handler)(server.state.clone(), r, e.to_owned())
+                })) {
+                    Ok(res) => res,
+                    Err(_handler_err) => {
+                        trace!(
+                            Level::Error,
+                            "Error handler panicked on {}: `{}` (while handling: `{}`)",
+                            request_summary(r),
+                            any_string(_handler_err),
+                            e
+                        );
+                        fallback_error_response()
+                    }
+                }
             }
         },
+        Error::WebSocket(e) => e.clone().into(),
         Error::Io(e) => Response::new().status(500).text(e),
     }
 }
+
+/// The response sent if the error handler itself fails.
+/// This is hard-coded so it can't fail, and closes the connection because the state of the socket is unknown.
+fn fallback_error_response() -> Response {
+    Response::new()
+        .status(Status::InternalServerError)
+        .text("Internal Server Error")
+        .content(Content::TXT)
+        .close()
+}
+
+/// Gets a curl command to reproduce a request that panicked, with the server's [`Server::redacted_headers`] redacted.
+#[cfg(feature = "tracing")]
+fn reproduce(req: &Request, redacted: &[HeaderType]) -> String {
+    redacted
+        .iter()
+        .fold(req.reproducer(), |x, header| x.exclude(header.clone()))
+        .curl()
+}
+
+/// Gets a short description of a request for use in error logs.
+/// The request is identified by the ID from the [`RequestId`](crate::extension::RequestId) extension if it is used, otherwise by its connection ID and its number on that connection.
+/// Ex: `GET /hello (127.0.0.1:1234, connection 3 request 2)`
+#[cfg(feature = "tracing")]
+fn request_summary(req: &Result<Rc<Request>>) -> String {
+    let req = match req {
+        Ok(req) => req,
+        Err(_) => return "an invalid request".to_owned(),
+    };
+
+    #[cfg(feature = "extensions")]
+    if let Some(id) = req.extensions.get::<crate::extension::request_id::Id>() {
+        return format!(
+            "{} {} ({}, request {})",
+            req.method, req.path, req.address, id.0
+        );
+    }
+
+    format!(
+        "{} {} ({}, connection {} request {})",
+        req.method,
+        req.path,
+        req.address,
+        req.socket.id,
+        req.socket.requests()
+    )
+}
+
+#[cfg(test)]
+mod test {
+    #[cfg(feature = "tracing")]
+    use std::rc::Rc;
+
+    use crate::{
+        error::{Error, HandleError},
+        Server, Status,
+    };
+    #[cfg(feature = "tracing")]
+    use crate::{
+        testing::{TestRequest, TestServer},
+        Method, Response,
+    };
+
+    use super::error_response;
+    #[cfg(feature = "tracing")]
+    use super::{reproduce, request_summary};
+
+    #[test]
+    fn test_error_handler_panic() {
+        let mut server = Server::<()>::new("localhost", 8080);
+        server.error_handler(|_, _, _| panic!("Error handler panic"));
+
+        let err = Error::Handle(Box::new(HandleError::Panic(
+            Box::new(Err(Error::None)),
+            "Route panic".to_owned(),
+        )));
+        let res = error_response(&err, &server);
+        assert_eq!(res.status, Status::InternalServerError);
+    }
+
+    #[test]
+    #[cfg(feature = "tracing")]
+    fn test_reproduce() {
+        let mut server = Server::<()>::new("localhost", 8080);
+        server.route(Method::GET, "/", |req| {
+            Response::new().text(reproduce(req, &["X-Api-Key".into()]))
+        });
+        let server = TestServer::from(server);
+
+        let curl = TestRequest::get("/")
+            .header("X-Api-Key", "secret")
+            .header("Cookie", "session=secret")
+            .header("X-Other", "visible")
+            .send(&server)
+            .text()
+            .into_owned();
+        assert!(!curl.contains("secret"), "{}", curl);
+        assert!(curl.contains("-H 'X-Api-Key: [redacted]'"), "{}", curl);
+        assert!(curl.contains("-H 'X-Other: visible'"), "{}", curl);
+    }
+
+    #[test]
+    #[cfg(feature = "tracing")]
+    fn test_request_summary() {
+        let summary = |server: &mut Server| {
+            server.route(Method::GET, "/hello", |req| {
+                Response::new().text(request_summary(&Ok(Rc::new(req.clone_buffered()))))
+            });
+        };
+
+        let mut server = Server::<()>::new("localhost", 8080);
+        summary(&mut server);
+        let server = TestServer::from(server);
+        let text = TestRequest::get("/hello").send(&server).text().into_owned();
+        assert!(text.starts_with("GET /hello ("), "{}", text);
+        assert!(text.ends_with(" request 1)"), "{}", text);
+        assert!(text.contains(", connection "), "{}", text);
+
+        #[cfg(feature = "extensions")]
+        {
+            use crate::{extension::RequestId, Middleware};
+
+            let mut server = Server::<()>::new("localhost", 8080);
+            RequestId::new("X-Request-Id").attach(&mut server);
+            summary(&mut server);
+            let server = TestServer::from(server);
+            let text = TestRequest::get("/hello").send(&server).text().into_owned();
+            assert!(text.starts_with("GET /hello ("), "{}", text);
+            assert!(text.ends_with(", request 0)"), "{}", text);
+        }
+    }
+}
@@ -3,7 +3,11 @@
 //! These may be helpful but may not remain compatible between updates.
 //! No guarantees are made about their stability, they are likely to be changed or removed.
 
+#[cfg(feature = "oauth")]
+pub(crate) mod client;
 pub mod common;
 pub mod encoding;
 pub(crate) mod handle;
 pub mod path;
+pub(crate) mod router;
+pub(crate) mod socket;
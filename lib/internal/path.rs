@@ -1,7 +1,5 @@
 //! HTTP Path stuff
 
-use super::encoding::url;
-
 /// Http Path
 #[derive(Debug, PartialEq, Eq)]
 pub struct Path {
@@ -47,7 +45,8 @@ impl Path {
         }
     }
 
-    /// Match Path, returns None if it doesn't match and the path params if it does
+    /// Match Path, returns None if it doesn't match and the path params if it does.
+    /// The values of the path params are not url decoded.
     pub fn match_path(&self, path: String) -> Option<Vec<(String, String)>> {
         if self.parts == [PathPart::AnyAfter] {
             return Some(Vec::new());
@@ -64,9 +63,7 @@ impl Path {
                         return None;
                     }
                 }
-                PathPart::Param(x) => {
-                    out.push((x.to_owned(), url::decode(j).unwrap_or_else(|| j.to_owned())))
-                }
+                PathPart::Param(x) => out.push((x.to_owned(), j.to_owned())),
                 PathPart::AnyAfter => return Some(out),
                 PathPart::Any => {}
             }
@@ -78,6 +75,30 @@ impl Path {
 
         Some(out)
     }
+
+    /// Checks if a path starts with this path, segment by segment.
+    /// So `/api/{version}` is a prefix of `/api/v1/users`, but not of `/apis`.
+    pub fn match_prefix(&self, path: String) -> bool {
+        if self.raw.is_empty() {
+            return true;
+        }
+
+        let path = normalize_path(path);
+        let mut path = path.split('/');
+        for i in &self.parts {
+            let Some(j) = path.next() else {
+                return false;
+            };
+
+            match i {
+                PathPart::Normal(x) if x != j => return false,
+                PathPart::AnyAfter => return true,
+                _ => {}
+            }
+        }
+
+        true
+    }
 }
 
 impl PathPart {
@@ -171,6 +192,11 @@ mod test {
             Path::new("/cool/{bean}".to_owned()).match_path("/cool/Bean".to_owned()),
             Some(vec![("bean".to_owned(), "Bean".to_owned())])
         );
+
+        assert_eq!(
+            Path::new("/cool/{bean}".to_owned()).match_path("/cool/Bean%20s".to_owned()),
+            Some(vec![("bean".to_owned(), "Bean%20s".to_owned())])
+        );
     }
 
     #[test]
@@ -186,6 +212,16 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_match_prefix() {
+        let path = Path::new("/api/{version}".to_owned());
+        assert!(path.match_prefix("/api/v1".to_owned()));
+        assert!(path.match_prefix("/api/v1/users/".to_owned()));
+        assert!(!path.match_prefix("/api".to_owned()));
+        assert!(!path.match_prefix("/apis/v1".to_owned()));
+        assert!(Path::new("/".to_owned()).match_prefix("/anything".to_owned()));
+    }
+
     #[test]
     fn test_path_part_from_normal() {
         assert_eq!(
@@ -0,0 +1,188 @@
+//! A trie of route paths, so requests are only checked against the routes that could match them, instead of every route.
+
+use std::collections::HashMap;
+
+use super::path::{normalize_path, Path, PathPart};
+
+/// Route paths, split into a tree of their segments.
+/// Built from the server's routes when it starts, see [`crate::Server::routes`].
+///
+/// The router only finds which routes have a path that matches, so the route's own checks (the method, guards and trailing slash) still have to be run on the candidates.
+/// Routes are referred to by their index in the server's route list, so the usual precedence (the last defined route that matches wins) is kept by checking the candidates from the highest index down.
+#[derive(Debug, Default)]
+pub(crate) struct Router {
+    root: Node,
+    len: usize,
+}
+
+#[derive(Debug, Default)]
+struct Node {
+    /// Routes whose path ends at this node.
+    routes: Vec<usize>,
+    /// Routes with a `**` segment at this node, which match if there is at least one more segment.
+    any_after: Vec<usize>,
+    /// Children for normal segments.
+    normal: HashMap<String, Node>,
+    /// Child for param (`{name}`) and any (`*`) segments, which both match any one segment.
+    any: Option<Box<Node>>,
+}
+
+impl Router {
+    /// Builds a router for a list of route paths.
+    pub fn new<'a>(paths: impl Iterator<Item = &'a Path>) -> Self {
+        let mut root = Node::default();
+        let mut len = 0;
+        for path in paths {
+            root.insert(len, &path.parts);
+            len += 1;
+        }
+
+        Self { root, len }
+    }
+
+    /// The number of routes the router was built with.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Gets the indexes of the routes with a path that matches, from highest to lowest.
+    /// This is the same set of routes that [`Path::match_path`] would match.
+    pub fn candidates(&self, path: &str) -> Vec<usize> {
+        let path = normalize_path(path.to_owned());
+        let segments = path.split('/').collect::<Vec<_>>();
+
+        let mut out = Vec::new();
+        self.root.collect(&segments, &mut out);
+        out.sort_unstable_by(|a, b| b.cmp(a));
+        out
+    }
+}
+
+impl Node {
+    fn insert(&mut self, route: usize, parts: &[PathPart]) {
+        match parts.split_first() {
+            None => self.routes.push(route),
+            // Nothing after a `**` is checked
+            Some((PathPart::AnyAfter, _)) => self.any_after.push(route),
+            Some((PathPart::Normal(segment), rest)) => self
+                .normal
+                .entry(segment.to_owned())
+                .or_default()
+                .insert(route, rest),
+            Some((PathPart::Param(_) | PathPart::Any, rest)) => self
+                .any
+                .get_or_insert_with(Default::default)
+                .insert(route, rest),
+        }
+    }
+
+    fn collect(&self, segments: &[&str], out: &mut Vec<usize>) {
+        let (segment, rest) = match segments.split_first() {
+            Some(i) => i,
+            None => return out.extend(&self.routes),
+        };
+
+        out.extend(&self.any_after);
+        if let Some(child) = self.normal.get(*segment) {
+            child.collect(rest, out);
+        }
+
+        if let Some(child) = &self.any {
+            child.collect(rest, out);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Instant;
+
+    use super::Router;
+    use crate::internal::path::Path;
+
+    const PATTERNS: &[&str] = &[
+        "/",
+        "**",
+        "/api/**",
+        "/api/users",
+        "/api/users/{id}",
+        "/api/users/{id}/posts",
+        "/api/*/posts",
+        "/api/users/me",
+        "/static/**",
+        "/{page}",
+        "/a//b",
+    ];
+
+    const PATHS: &[&str] = &[
+        "/",
+        "",
+        "/api",
+        "/api/",
+        "/api/users",
+        "/api/users/5",
+        "/api/users/me",
+        "/api/users/5/posts",
+        "/api/teams/posts",
+        "/api/users/5/posts/1",
+        "/static/css/main.css",
+        "/about",
+        "/a//b",
+        "/a/b",
+    ];
+
+    fn linear(paths: &[Path], path: &str) -> Vec<usize> {
+        (0..paths.len())
+            .rev()
+            .filter(|&i| paths[i].match_path(path.to_owned()).is_some())
+            .collect()
+    }
+
+    #[test]
+    fn test_router_matches_linear() {
+        let paths = PATTERNS
+            .iter()
+            .map(|x| Path::new(x.to_string()))
+            .collect::<Vec<_>>();
+        let router = Router::new(paths.iter());
+
+        for path in PATHS {
+            assert_eq!(router.candidates(path), linear(&paths, path), "{}", path);
+        }
+    }
+
+    /// Compares the router to checking every route, for a server with a few hundred routes.
+    /// Run with `cargo test --release bench_router -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_router() {
+        let mut patterns = Vec::new();
+        for i in 0..100 {
+            patterns.push(format!("/api/v1/resource{i}"));
+            patterns.push(format!("/api/v1/resource{i}/{{id}}"));
+            patterns.push(format!("/api/v1/resource{i}/{{id}}/edit"));
+        }
+        patterns.push("/static/**".to_owned());
+
+        let paths = patterns.into_iter().map(Path::new).collect::<Vec<_>>();
+        let router = Router::new(paths.iter());
+        let requests = (0..100)
+            .map(|i| format!("/api/v1/resource{i}/{i}/edit"))
+            .chain(["/static/app.js".to_owned(), "/missing".to_owned()])
+            .collect::<Vec<_>>();
+
+        let time = |name: &str, matcher: &dyn Fn(&str) -> Option<usize>| {
+            let start = Instant::now();
+            for _ in 0..100 {
+                for path in &requests {
+                    std::hint::black_box(matcher(path));
+                }
+            }
+            let per = start.elapsed() / (100 * requests.len() as u32);
+            println!("{name}: {per:?} per request ({} routes)", paths.len());
+        };
+
+        time("linear", &|path| linear(&paths, path).first().copied());
+        time("router", &|path| router.candidates(path).first().copied());
+    }
+}
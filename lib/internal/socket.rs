@@ -0,0 +1,756 @@
+//! Helpers for working with the raw client sockets.
+
+use std::{
+    io::{self, BufRead, ErrorKind, IoSlice, Read, Write},
+    mem,
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    error::{Result, StreamError},
+    internal::common::ForceLock,
+    transport::{Socket, Stream},
+    Header,
+};
+
+/// How much to read from a socket at once when parsing requests.
+const READ_SIZE: usize = 8 * 1024;
+
+/// How much of a file to send with each `sendfile` window.
+/// Each window has to be sent within the socket's write timeout, like a write with [`write_all_deadline`].
+#[cfg(all(feature = "sendfile", target_os = "linux"))]
+const SENDFILE_CHUNK: u64 = 1024 * 1024;
+
+/// How many spare [`WriteBuffer`]s to keep for new connections.
+const BUFFER_POOL_SIZE: usize = 64;
+
+/// Buffers that have grown past this aren't put back in the pool, so one response with huge headers doesn't keep its memory around.
+const BUFFER_MAX_KEEP: usize = 16 * 1024;
+
+/// Spare buffers from closed connections.
+static BUFFER_POOL: Mutex<Vec<Vec<u8>>> = Mutex::new(Vec::new());
+
+/// A buffer to format response heads into, stored on the connection so each response on a keep-alive connection reuses it.
+/// When the connection is closed, the buffer goes back to a shared pool for the next connection to use.
+pub(crate) struct WriteBuffer(Mutex<Vec<u8>>);
+
+impl WriteBuffer {
+    /// Takes a buffer from the pool, or makes a new one if the pool is empty.
+    pub(crate) fn new() -> Self {
+        let buf = BUFFER_POOL.force_lock().pop().unwrap_or_default();
+        Self(Mutex::new(buf))
+    }
+
+    /// Locks the buffer, clearing anything left from the last response.
+    pub(crate) fn lock(&self) -> MutexGuard<'_, Vec<u8>> {
+        let mut buf = self.0.force_lock();
+        buf.clear();
+        buf
+    }
+}
+
+impl Drop for WriteBuffer {
+    fn drop(&mut self) {
+        let buf = mem::take(self.0.get_mut().unwrap_or_else(|e| e.into_inner()));
+        if buf.capacity() == 0 || buf.capacity() > BUFFER_MAX_KEEP {
+            return;
+        }
+
+        let mut pool = BUFFER_POOL.force_lock();
+        if pool.len() < BUFFER_POOL_SIZE {
+            pool.push(buf);
+        }
+    }
+}
+
+/// Writes all of `data` to the socket, failing with [`StreamError::ClientDisconnected`] if it takes longer than the socket's write timeout.
+///
+/// The socket's write timeout on its own only applies to each individual write call.
+/// So a client reading very slowly (or not at all, with a tiny receive window) could otherwise hold up a worker thread forever.
+/// If the socket has no write timeout, this is the same as [`Write::write_all`].
+pub(crate) fn write_all_deadline(stream: &mut dyn Stream, data: &[u8]) -> Result<()> {
+    write_vectored_deadline(stream, [data])
+}
+
+/// Writes all of the buffers to the socket in order, like [`write_all_deadline`].
+/// They are written with [`Write::write_vectored`], so for TCP a response head and body can go out in a single syscall without being copied together first.
+pub(crate) fn write_vectored_deadline<const N: usize>(
+    stream: &mut dyn Stream,
+    bufs: [&[u8]; N],
+) -> Result<()> {
+    if bufs.iter().all(|x| x.is_empty()) {
+        return Ok(());
+    }
+
+    let timeout = stream.write_timeout()?;
+    let result = write_until(stream, bufs, timeout.map(|x| Instant::now() + x));
+    if timeout.is_some() {
+        stream.set_write_timeout(timeout)?;
+    }
+    result
+}
+
+fn write_until<const N: usize>(
+    stream: &mut dyn Stream,
+    mut bufs: [&[u8]; N],
+    deadline: Option<Instant>,
+) -> Result<()> {
+    let mut start = 0;
+    loop {
+        while start < N && bufs[start].is_empty() {
+            start += 1;
+        }
+        if start == N {
+            return Ok(());
+        }
+
+        if let Some(deadline) = deadline {
+            let remaining = deadline
+                .checked_duration_since(Instant::now())
+                .filter(|x| !x.is_zero())
+                .ok_or(StreamError::ClientDisconnected)?;
+            stream.set_write_timeout(Some(remaining))?;
+        }
+
+        let slices = bufs.map(IoSlice::new);
+        match stream.write_vectored(&slices[start..]) {
+            Ok(0) => return Err(StreamError::ClientDisconnected.into()),
+            Ok(mut n) => {
+                for buf in &mut bufs[start..] {
+                    let written = n.min(buf.len());
+                    *buf = &buf[written..];
+                    n -= written;
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                return Err(StreamError::ClientDisconnected.into())
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Formats the size line that starts a chunk of a chunked body into `buf`.
+pub(crate) fn chunk_header(len: usize, buf: &mut [u8; 20]) -> &[u8] {
+    let mut rest = &mut buf[..];
+    write!(rest, "{len:X}\r\n").expect("Chunk size fits in buffer");
+    let written = 20 - rest.len();
+    &buf[..written]
+}
+
+/// Writes the response `head`, then copies a file from its current position to the end into the socket with `sendfile`, so the kernel moves the data without it being read into memory.
+/// The file is sent in windows of [`SENDFILE_CHUNK`], each as its own chunk if `encode_chunks` is set, and `report` is called after each one.
+/// The file's position is left at the end of what was sent.
+#[cfg(all(feature = "sendfile", target_os = "linux"))]
+pub(crate) fn sendfile(
+    stream: &mut dyn Stream,
+    socket: std::os::unix::io::RawFd,
+    head: &[u8],
+    file: &mut std::fs::File,
+    encode_chunks: bool,
+    report: &mut dyn FnMut(usize) -> Result<()>,
+) -> Result<()> {
+    use std::io::{Seek, SeekFrom};
+
+    let mut offset = file.stream_position()?;
+    let end = file.metadata()?.len();
+    let timeout = stream.write_timeout()?;
+    let mut head = head;
+
+    while offset < end {
+        let len = (end - offset).min(SENDFILE_CHUNK);
+        let mut size = [0; 20];
+        let size = match encode_chunks {
+            true => chunk_header(len as usize, &mut size),
+            false => &[],
+        };
+        write_vectored_deadline(stream, [head, size])?;
+        head = &[];
+
+        let deadline = timeout.map(|x| Instant::now() + x);
+        let result = sendfile_until(stream, socket, file, &mut offset, len, deadline);
+        stream.set_write_timeout(timeout)?;
+        result?;
+
+        if encode_chunks {
+            write_all_deadline(stream, b"\r\n")?;
+        }
+        report(len as usize)?;
+    }
+
+    file.seek(SeekFrom::Start(offset))?;
+    let end: &[u8] = match encode_chunks {
+        true => b"0\r\n\r\n",
+        false => &[],
+    };
+    write_vectored_deadline(stream, [head, end])
+}
+
+/// Sends `len` bytes of the file starting at `offset`, failing with [`StreamError::ClientDisconnected`] if it isn't done by the deadline.
+#[cfg(all(feature = "sendfile", target_os = "linux"))]
+fn sendfile_until(
+    stream: &mut dyn Stream,
+    socket: std::os::unix::io::RawFd,
+    file: &std::fs::File,
+    offset: &mut u64,
+    mut len: u64,
+    deadline: Option<Instant>,
+) -> Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    while len > 0 {
+        if let Some(deadline) = deadline {
+            let remaining = deadline
+                .checked_duration_since(Instant::now())
+                .filter(|x| !x.is_zero())
+                .ok_or(StreamError::ClientDisconnected)?;
+            stream.set_write_timeout(Some(remaining))?;
+        }
+
+        let mut off = *offset as libc::off_t;
+        // SAFETY: Both file descriptors are borrowed for the length of the call, and `off` is a valid pointer
+        let sent = unsafe { libc::sendfile(socket, file.as_raw_fd(), &mut off, len as usize) };
+        if sent < 0 {
+            let err = io::Error::last_os_error();
+            match err.kind() {
+                ErrorKind::Interrupted => continue,
+                ErrorKind::WouldBlock | ErrorKind::TimedOut => {
+                    return Err(StreamError::ClientDisconnected.into())
+                }
+                _ => return Err(err.into()),
+            }
+        }
+
+        // The file got shorter while it was being sent
+        if sent == 0 {
+            return Err(io::Error::from(ErrorKind::UnexpectedEof).into());
+        }
+
+        *offset += sent as u64;
+        len -= sent as u64;
+    }
+
+    Ok(())
+}
+
+/// Reads from a socket through a buffer that outlives each request.
+/// Bytes read past the end of one request (like the start of the next pipelined request) are left in `buf` for the next reader.
+pub(crate) struct ConnReader<'a> {
+    stream: &'a mut dyn Stream,
+    buf: &'a mut Vec<u8>,
+    pos: usize,
+    /// Reads fail with a timeout after this, see [`ConnReader::set_deadline`].
+    /// Also holds the socket's normal read timeout, which is never exceeded.
+    deadline: Option<(Instant, Option<Duration>)>,
+}
+
+impl<'a> ConnReader<'a> {
+    pub fn new(stream: &'a mut dyn Stream, buf: &'a mut Vec<u8>) -> Self {
+        Self {
+            stream,
+            buf,
+            pos: 0,
+            deadline: None,
+        }
+    }
+
+    /// Sets a time after which reads fail with [`ErrorKind::TimedOut`], no matter how much data has been received.
+    /// `timeout` is the socket's normal read timeout, which is restored when the deadline is removed with `None`.
+    pub fn set_deadline(
+        &mut self,
+        deadline: Option<Instant>,
+        timeout: Option<Duration>,
+    ) -> io::Result<()> {
+        self.deadline = deadline.map(|x| (x, timeout));
+        if deadline.is_none() {
+            self.stream.set_read_timeout(timeout)?;
+        }
+        Ok(())
+    }
+
+    /// Gets the underlying stream, to write interim responses like `100 Continue` while reading a request.
+    pub fn stream(&mut self) -> &mut dyn Stream {
+        self.stream
+    }
+
+    /// Takes up to `max` bytes that have already been read from the socket, without reading any more.
+    pub fn take_buffered(&mut self, max: usize) -> Vec<u8> {
+        let end = self.buf.len().min(self.pos.saturating_add(max));
+        let out = self.buf[self.pos..end].to_vec();
+        self.pos = end;
+        out
+    }
+}
+
+impl Read for ConnReader<'_> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let data = self.fill_buf()?;
+        let len = data.len().min(out.len());
+        out[..len].copy_from_slice(&data[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl BufRead for ConnReader<'_> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos >= self.buf.len() {
+            if let Some((deadline, timeout)) = self.deadline {
+                let left = deadline.saturating_duration_since(Instant::now());
+                if left.is_zero() {
+                    return Err(ErrorKind::TimedOut.into());
+                }
+                self.stream
+                    .set_read_timeout(Some(timeout.map_or(left, |x| x.min(left))))?;
+            }
+
+            self.pos = 0;
+            self.buf.clear();
+            self.buf.resize(READ_SIZE, 0);
+            match self.stream.read(self.buf) {
+                Ok(n) => self.buf.truncate(n),
+                Err(e) => {
+                    self.buf.clear();
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok(&self.buf[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.buf.len());
+    }
+}
+
+impl Drop for ConnReader<'_> {
+    fn drop(&mut self) {
+        self.buf.drain(..self.pos);
+    }
+}
+
+/// The longest chunk size line or trailer line that will be read from a chunked body.
+const MAX_CHUNK_LINE: usize = 8 * 1024;
+
+/// The body of a request that was too large to buffer, read straight from the socket.
+/// See [`crate::Request::body_reader`].
+pub(crate) struct BodyStream {
+    /// Body bytes that were already read (and decoded) from the socket along with the headers.
+    prefix: Vec<u8>,
+    pos: usize,
+    /// Where the rest of the body is read from.
+    raw: RawBody,
+    framing: Framing,
+}
+
+/// How the end of a streamed body is found.
+enum Framing {
+    /// From the `Content-Length` header, with the number of body bytes still waiting on the socket.
+    Length(usize),
+    /// With `Transfer-Encoding: chunked`.
+    Chunked(ChunkedDecoder),
+}
+
+/// The undecoded rest of a body: bytes that were read from the socket past the headers, then the socket itself.
+struct RawBody {
+    socket: Arc<Socket>,
+    buffered: Vec<u8>,
+    pos: usize,
+}
+
+impl BodyStream {
+    /// Makes a stream for a body with a known length.
+    /// `prefix` is the start of the body and `remaining` is how much of it is still on the socket.
+    pub fn new(socket: Arc<Socket>, prefix: Vec<u8>, remaining: usize) -> Self {
+        Self {
+            prefix,
+            pos: 0,
+            raw: RawBody {
+                socket,
+                buffered: Vec::new(),
+                pos: 0,
+            },
+            framing: Framing::Length(remaining),
+        }
+    }
+
+    /// Makes a stream for a chunked body, continuing from where `decoder` left off.
+    /// `prefix` is the already decoded start of the body and `buffered` is any undecoded data that was read from the socket after it.
+    pub fn chunked(
+        socket: Arc<Socket>,
+        prefix: Vec<u8>,
+        buffered: Vec<u8>,
+        decoder: ChunkedDecoder,
+    ) -> Self {
+        Self {
+            prefix,
+            pos: 0,
+            raw: RawBody {
+                socket,
+                buffered,
+                pos: 0,
+            },
+            framing: Framing::Chunked(decoder),
+        }
+    }
+
+    /// Gets the trailers of a chunked body.
+    /// Returns None if the body hasn't been read to the end yet.
+    pub fn trailers(&self) -> Option<&[Header]> {
+        match &self.framing {
+            Framing::Length(0) => Some(&[]),
+            Framing::Length(_) => None,
+            Framing::Chunked(decoder) => decoder.trailers(),
+        }
+    }
+
+    /// Reads and throws away the rest of the body, so the next request on the connection can be read.
+    /// Anything read from the socket past the end of the body (like a pipelined request) is put back at the start of `pending`.
+    /// Returns false if more than `limit` bytes are left or the read fails, in which case the connection should be closed instead.
+    pub fn discard(&mut self, limit: usize, pending: &mut Vec<u8>) -> bool {
+        self.pos = self.prefix.len();
+        match &self.framing {
+            Framing::Length(remaining) if *remaining > limit => return false,
+            Framing::Length(_) => {}
+            // The length isn't known ahead of time, so it's read until the limit
+            Framing::Chunked(_) => {
+                let limit = (limit as u64).saturating_add(1);
+                if io::copy(&mut self.take(limit), &mut io::sink()).is_err() {
+                    return false;
+                }
+            }
+        }
+
+        if io::copy(self, &mut io::sink()).is_err() || self.trailers().is_none() {
+            return false;
+        }
+
+        pending.splice(0..0, self.raw.buffered.drain(self.raw.pos..));
+        true
+    }
+}
+
+impl Read for BodyStream {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if self.pos < self.prefix.len() {
+            let len = (self.prefix.len() - self.pos).min(out.len());
+            out[..len].copy_from_slice(&self.prefix[self.pos..self.pos + len]);
+            self.pos += len;
+            return Ok(len);
+        }
+
+        match &mut self.framing {
+            Framing::Chunked(decoder) => decoder.read(&mut self.raw, out),
+            Framing::Length(remaining) => {
+                if *remaining == 0 || out.is_empty() {
+                    return Ok(0);
+                }
+
+                let len = (*remaining).min(out.len());
+                let read = self.raw.read(&mut out[..len])?;
+                if read == 0 {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+
+                *remaining -= read;
+                Ok(read)
+            }
+        }
+    }
+}
+
+impl Read for RawBody {
+    /// Reads from the buffer, or straight from the socket once it is empty, so bodies with a known length are never read past.
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if self.pos < self.buffered.len() {
+            let len = (self.buffered.len() - self.pos).min(out.len());
+            out[..len].copy_from_slice(&self.buffered[self.pos..self.pos + len]);
+            self.pos += len;
+            return Ok(len);
+        }
+
+        self.socket.force_lock().read(out)
+    }
+}
+
+impl BufRead for RawBody {
+    /// Refills the buffer from the socket, for reading the lines of a chunked body.
+    /// This can read past the end of the body, which [`BodyStream::discard`] hands back to the connection.
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos >= self.buffered.len() {
+            self.pos = 0;
+            self.buffered.clear();
+            self.buffered.resize(READ_SIZE, 0);
+            match self.socket.force_lock().read(&mut self.buffered) {
+                Ok(n) => self.buffered.truncate(n),
+                Err(e) => {
+                    self.buffered.clear();
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok(&self.buffered[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.buffered.len());
+    }
+}
+
+/// Decodes a body sent with `Transfer-Encoding: chunked`, including the trailer headers after the last chunk.
+/// Malformed chunks are reported as [`io::ErrorKind::InvalidData`] errors.
+#[derive(Default)]
+pub(crate) struct ChunkedDecoder {
+    /// Bytes left in the current chunk.
+    left: usize,
+    /// The trailers, once the last chunk has been read.
+    trailers: Option<Vec<Header>>,
+    /// The most trailers that can be sent, from [`crate::Server::max_header_count`].
+    max_trailers: Option<usize>,
+    /// The most bytes all the trailer lines can add up to, from [`crate::Server::max_head_size`].
+    max_trailer_size: Option<usize>,
+}
+
+impl ChunkedDecoder {
+    /// Makes a decoder that fails once more than `max_trailers` trailers or `max_trailer_size` bytes of trailers are sent.
+    pub fn new(max_trailers: Option<usize>, max_trailer_size: Option<usize>) -> Self {
+        Self {
+            max_trailers,
+            max_trailer_size,
+            ..Self::default()
+        }
+    }
+
+    /// Gets the trailers, or None if the last chunk hasn't been read yet.
+    pub fn trailers(&self) -> Option<&[Header]> {
+        self.trailers.as_deref()
+    }
+
+    /// Takes the trailers, or None if the last chunk hasn't been read yet.
+    pub fn into_trailers(self) -> Option<Vec<Header>> {
+        self.trailers
+    }
+
+    /// Reads decoded body bytes from the chunked data in `raw`.
+    /// Returns 0 once the last chunk and the trailers have been read.
+    /// Only reads as far as the end of the body, so data after it is left in `raw`.
+    pub fn read(&mut self, raw: &mut impl BufRead, out: &mut [u8]) -> io::Result<usize> {
+        if self.trailers.is_some() || out.is_empty() {
+            return Ok(0);
+        }
+
+        if self.left == 0 {
+            let line = read_chunk_line(raw)?;
+            // Chunk extensions (`;name=value`) are ignored.
+            // Anything other than plain hex digits (like `+5` or ` 5`) is rejected, as other servers could read the size differently.
+            let size = line.split(|&x| x == b';').next().unwrap_or_default();
+            let size = Some(size)
+                .filter(|x| !x.is_empty() && x.iter().all(u8::is_ascii_hexdigit))
+                .and_then(|x| usize::from_str_radix(std::str::from_utf8(x).ok()?, 16).ok())
+                .ok_or_else(|| invalid_chunk("invalid chunk size"))?;
+
+            if size == 0 {
+                let mut trailers = Vec::new();
+                let mut size = 0;
+                loop {
+                    let line = read_chunk_line(raw)?;
+                    if line.is_empty() {
+                        break;
+                    }
+
+                    // Trailers are held in memory like headers, so they get the same limits
+                    size += line.len() + 2;
+                    if self.max_trailers.is_some_and(|x| trailers.len() >= x)
+                        || self.max_trailer_size.is_some_and(|x| size > x)
+                    {
+                        return Err(invalid_chunk("too many trailers"));
+                    }
+
+                    let header = Header::from_string(String::from_utf8_lossy(&line))
+                        .map_err(|_| invalid_chunk("invalid trailer"))?;
+                    trailers.push(header);
+                }
+                self.trailers = Some(trailers);
+                return Ok(0);
+            }
+            self.left = size;
+        }
+
+        let len = self.left.min(out.len());
+        let read = raw.read(&mut out[..len])?;
+        if read == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        self.left -= read;
+        if self.left == 0 && !read_chunk_line(raw)?.is_empty() {
+            return Err(invalid_chunk("chunk is longer than its size"));
+        }
+        Ok(read)
+    }
+}
+
+/// Reads a line of a chunked body without the line ending.
+/// Only the line is consumed from `raw`, so anything after it is left for the next read.
+/// Lines have to end with `\r\n`, a bare `\n` is an error.
+fn read_chunk_line(raw: &mut impl BufRead) -> io::Result<Vec<u8>> {
+    let mut line = Vec::new();
+    loop {
+        let buf = match raw.fill_buf() {
+            Ok([]) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(buf) => buf,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        let (len, end) = match buf.iter().position(|&x| x == b'\n') {
+            Some(i) => (i, true),
+            None => (buf.len(), false),
+        };
+        line.extend_from_slice(&buf[..len]);
+        raw.consume(len + end as usize);
+
+        if line.len() > MAX_CHUNK_LINE {
+            return Err(invalid_chunk("line too long"));
+        }
+        if end {
+            break;
+        }
+    }
+
+    if line.pop() != Some(b'\r') {
+        return Err(invalid_chunk("line doesn't end with CRLF"));
+    }
+    Ok(line)
+}
+
+fn invalid_chunk(msg: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{self, IoSlice, Read, Write},
+        net::{Shutdown, SocketAddr},
+    };
+
+    use super::{chunk_header, write_vectored_deadline, ChunkedDecoder};
+    use crate::transport::Stream;
+
+    fn decode(mut raw: &[u8]) -> io::Result<(Vec<u8>, ChunkedDecoder, Vec<u8>)> {
+        let mut decoder = ChunkedDecoder::new(Some(2), Some(64));
+        let mut body = Vec::new();
+        let mut buf = [0; 3];
+        loop {
+            match decoder.read(&mut raw, &mut buf)? {
+                0 => break,
+                n => body.extend_from_slice(&buf[..n]),
+            }
+        }
+
+        let mut rest = Vec::new();
+        raw.read_to_end(&mut rest)?;
+        Ok((body, decoder, rest))
+    }
+
+    #[test]
+    fn test_chunked_decoder() {
+        let (body, decoder, rest) =
+            decode(b"5\r\nHello\r\n7;ext=1\r\n, World\r\n0\r\nExpires: never\r\n\r\nGET /")
+                .unwrap();
+        assert_eq!(body, b"Hello, World");
+        assert_eq!(rest, b"GET /");
+
+        let trailers = decoder.into_trailers().unwrap();
+        assert_eq!(trailers.len(), 1);
+        assert_eq!(trailers[0].value, "never");
+
+        assert!(decode(b"5\r\nHello, World\r\n0\r\n\r\n").is_err());
+        assert!(decode(b"x\r\nHello\r\n").is_err());
+        assert!(decode(b"+5\r\nHello\r\n0\r\n\r\n").is_err());
+        assert!(decode(b" 5\r\nHello\r\n0\r\n\r\n").is_err());
+        assert!(decode(b"\r\nHello\r\n0\r\n\r\n").is_err());
+        assert!(decode(b"5\nHello\r\n0\r\n\r\n").is_err());
+        assert!(decode(b"5\r\nHello\n0\r\n\r\n").is_err());
+        assert!(decode(b"5\r\nHel").is_err());
+
+        // Trailers are limited by count and total size
+        assert!(decode(b"0\r\nA: 1\r\nB: 2\r\n\r\n").is_ok());
+        assert!(decode(b"0\r\nA: 1\r\nB: 2\r\nC: 3\r\n\r\n").is_err());
+        let long = format!("0\r\nA: {}\r\n\r\n", "a".repeat(64));
+        assert!(decode(long.as_bytes()).is_err());
+    }
+
+    /// Accepts at most 5 bytes per write, counting how many writes were made.
+    #[derive(Default)]
+    struct Trickle {
+        written: Vec<u8>,
+        calls: usize,
+    }
+
+    impl Read for Trickle {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            Ok(0)
+        }
+    }
+
+    impl Write for Trickle {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.write_vectored(&[IoSlice::new(buf)])
+        }
+
+        fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+            self.calls += 1;
+            let mut left = 5;
+            for buf in bufs {
+                let take = left.min(buf.len());
+                self.written.extend_from_slice(&buf[..take]);
+                left -= take;
+            }
+            Ok(5 - left)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Stream for Trickle {
+        fn peer_addr(&self) -> io::Result<SocketAddr> {
+            Ok(SocketAddr::from(([0, 0, 0, 0], 0)))
+        }
+
+        fn try_clone(&self) -> io::Result<Box<dyn Stream>> {
+            Err(io::ErrorKind::Unsupported.into())
+        }
+
+        fn shutdown(&self, _how: Shutdown) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_write_vectored() {
+        let mut stream = Trickle::default();
+        write_vectored_deadline(&mut stream, [b"HTTP/1.1", b"", b" 200 ", b"OK"]).unwrap();
+        assert_eq!(stream.written, b"HTTP/1.1 200 OK");
+        assert_eq!(stream.calls, 3);
+
+        // Nothing to write doesn't touch the stream
+        write_vectored_deadline(&mut stream, [b"", b""]).unwrap();
+        assert_eq!(stream.calls, 3);
+    }
+
+    #[test]
+    fn test_chunk_header() {
+        let mut buf = [0; 20];
+        assert_eq!(chunk_header(0, &mut buf), b"0\r\n");
+        assert_eq!(chunk_header(0x4000, &mut buf), b"4000\r\n");
+        assert_eq!(chunk_header(usize::MAX, &mut buf), b"FFFFFFFFFFFFFFFF\r\n");
+    }
+}
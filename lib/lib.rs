@@ -1,4 +1,6 @@
-#![doc = include_str!("../README.md")]
+// The README's example starts a TCP server, so it only builds with the `tcp` feature
+#![cfg_attr(feature = "tcp", doc = include_str!("../README.md"))]
+#![cfg_attr(not(feature = "tcp"), doc = "🔥 A blazing fast web framework for Rust")]
 #![warn(missing_docs)]
 
 /// Current version of afire
@@ -19,6 +21,8 @@ mod consts {
 pub mod internal;
 
 // Import Internal Functions
+#[cfg(feature = "async")]
+pub mod async_handler;
 mod thread_pool;
 use http::*;
 use internal::{encoding, handle, path};
@@ -26,26 +30,43 @@ use internal::{encoding, handle, path};
 #[macro_use]
 pub mod trace;
 pub mod error;
+pub mod extract;
+pub mod guard;
 mod http;
 pub mod middleware;
+#[cfg(feature = "openapi")]
+pub mod openapi;
 mod request;
 mod response;
 mod route;
 mod server;
+pub mod testing;
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod transport;
 pub use self::{
     content_type::Content,
     cookie::{Cookie, SetCookie},
     error::Error,
     header::{Header, HeaderType},
-    http::{cookie, header, multipart, server_sent_events},
+    http::{
+        accept, cookie, header, mime, multipart, reproduce, response_writer, server_sent_events,
+        web_socket,
+    },
     method::Method,
     middleware::Middleware,
     query::Query,
     request::Request,
     response::Response,
-    route::Route,
-    server::Server,
-    status::Status,
+    route::{Group, Route, TrailingSlash},
+    server::{
+        BoundAddr, ConnectionStats, HeaderPolicy, OverflowPolicy, PoolMetrics, ReasonPhrase,
+        Server, StartHook, StartTime,
+    },
+    status::{Status, StatusClass},
+    thread_pool::{ClientShare, PoolStats},
+    type_map::TypeMap,
+    version::HttpVersion,
 };
 
 /// The Prelude is a collection of very commonly used *things* in afire.
@@ -53,8 +74,10 @@ pub use self::{
 pub mod prelude {
     pub use crate::{
         error::{self, Error},
-        middleware::{MiddleResult, Middleware},
+        middleware::{MiddleResult, Middleware, StatefulMiddleware},
+        response_writer::ResponseWriterExt,
         server_sent_events::ServerSentEventsExt,
+        web_socket::WebSocketExt,
         Content, Cookie, Header, HeaderType, Method, Query, Request, Response, Server, SetCookie,
         Status,
     };
@@ -69,24 +92,61 @@ pub mod extension {
     //! Includes helpful middleware like Serve Static, Rate Limit and Logger.
     //!
     //! ## All Feature
-    //! | Name            | Description                                           |
-    //! | --------------- | ----------------------------------------------------- |
-    //! | [`Date`]        | Add the Date header to responses. Required by HTTP.   |
-    //! | [`Head`]        | Add support for HTTP `HEAD` requests.                 |
-    //! | [`Logger`]      | Log incoming requests to the console / file.          |
-    //! | [`RateLimiter`] | Limit how many requests can be handled from a source. |
-    //! | [`RealIp`]      | Get the real IP of a client through a reverse proxy   |
-    //! | [`RequestId`]   | Add a Request-Id header to all requests.              |
-    //! | [`ServeStatic`] | Serve static files from a dir.                        |
-    //! | [`Trace`]       | Add support for the HTTP `TRACE` method.              |
+    //! | Name               | Description                                                |
+    //! | ------------------ | ---------------------------------------------------------- |
+    //! | [`Auth`]           | Require HTTP Basic or Bearer authentication.               |
+    //! | [`Cache`]          | Cache responses to GET requests in memory.                 |
+    //! | [`Compression`]    | Compress responses with gzip, deflate or brotli.           |
+    //! | [`Date`]           | Add the Date header to responses. Required by HTTP.        |
+    //! | [`Head`]           | Add support for HTTP `HEAD` requests.                      |
+    //! | [`Logger`]         | Log incoming requests to the console / file.               |
+    //! | [`MethodOverride`] | Let forms override the request method.                     |
+    //! | [`Metrics`]        | Serve request counts and latencies for Prometheus.         |
+    //! | [`OAuth`]          | Log users in with an OAuth 2.0 or OpenID Connect provider. |
+    //! | [`PathNormalizer`] | Remove dot-segments from paths, or redirect to clean ones. |
+    //! | [`Range`]          | Serve byte ranges of files and seekable streams.           |
+    //! | [`RateLimiter`]    | Limit how many requests can be handled from a source.      |
+    //! | [`RealIp`]         | Get the real IP of a client through a reverse proxy        |
+    //! | [`Redirect`]       | Redirect to relative targets with the right status code.   |
+    //! | [`RequestHistory`] | Keep the last few requests in memory for debugging.        |
+    //! | [`RequestId`]      | Add a Request-Id header to all requests.                   |
+    //! | [`Rewrite`]        | Rewrite request paths before routing.                      |
+    //! | [`ServeEmbedded`]  | Serve static files embedded in the binary.                 |
+    //! | [`ServeStatic`]    | Serve static files from a dir.                             |
+    //! | [`Sessions`]       | Cookie based sessions, stored in memory or a custom store. |
+    //! | [`Templates`]      | Render HTML templates from handlers, with any engine.      |
+    //! | [`Throttle`]       | Limit the bandwidth used by response bodies.               |
+    //! | [`Trace`]          | Add support for the HTTP `TRACE` method.                   |
+    //! | [`Version`]        | Serve app build information and uptime as JSON.            |
+    //! | [`WellKnown`]      | Serve robots.txt, the favicon and `/.well-known/` files.   |
+    #[cfg(feature = "compression")]
+    pub use crate::extensions::compression::{self, Compression};
+    #[cfg(feature = "oauth")]
+    pub use crate::extensions::oauth::{self, OAuth};
+    #[cfg(feature = "serde")]
+    pub use crate::extensions::templates::{self, Templates};
     pub use crate::extensions::{
+        auth::{self, Auth},
+        cache::{self, Cache},
         date::{self, Date},
         head::Head,
         logger::{self, Logger},
-        ratelimit::RateLimiter,
-        real_ip::RealIp,
-        request_id::RequestId,
+        method_override::MethodOverride,
+        metrics::{self, Metrics},
+        path_normalizer::PathNormalizer,
+        range::Range,
+        ratelimit::{self, RateLimiter},
+        real_ip::{self, RealIp},
+        redirect::{self, Redirect, RedirectResponse},
+        request_history::{self, RequestHistory},
+        request_id::{self, RequestId},
+        rewrite::Rewrite,
+        serve_embedded::ServeEmbedded,
         serve_static::{self, ServeStatic},
+        session::{self, Sessions},
+        throttle::{Throttle, ThrottleScope},
         trace::Trace,
+        version::Version,
+        well_known::WellKnown,
     };
 }
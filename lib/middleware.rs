@@ -2,9 +2,19 @@
 //! They can be used to Log Requests, Ratelimit Requests, add Analytics, etc.
 //! For more information, see the [Middleware Example](https://github.com/Basicprogrammer10/afire/blob/main/examples/basic/middleware.rs).
 
-use std::{any::type_name, rc::Rc};
+use std::{
+    any::type_name,
+    fmt::{self, Debug},
+    net::Shutdown,
+    rc::Rc,
+    sync::Arc,
+    time::Duration,
+};
 
-use crate::{error::Result, trace::emoji, Request, Response, Server};
+use crate::{
+    error::Result, server::ResponseFinish, trace::emoji, HeaderType, Method, Request, Response,
+    Server, Status,
+};
 
 /// A response from a middleware handler
 pub enum MiddleResult {
@@ -14,6 +24,97 @@ pub enum MiddleResult {
     Abort,
     /// Stop the middleware chain and send this response
     Send(Response),
+    /// Stop the middleware chain and hand the request off to be responded to later, see [`MiddleResult::defer`].
+    Defer(Box<dyn FnOnce(Responder) + Send>),
+}
+
+/// Sends the response to a request that was handed off with [`MiddleResult::Defer`].
+/// It owns the request, so it can be moved to another thread and used to respond whenever the response is ready.
+///
+/// The server stops reading from the connection once a request is deferred, so it is closed after the response is sent.
+/// Dropping the responder without sending a response closes the connection.
+/// End middleware is not run for deferred requests.
+pub struct Responder {
+    /// The request being responded to.
+    pub request: Request,
+    finish: Arc<ResponseFinish>,
+    auto_head: bool,
+}
+
+impl MiddleResult {
+    /// Stops the middleware chain and takes ownership of the request, so it can be responded to later, from any thread.
+    /// The function is called with a [`Responder`] right away, on the thread handling the connection, so it should hand the responder off somewhere instead of blocking.
+    /// This is useful for things like long-polling and request queues, as no worker thread is tied up while the request waits.
+    ///
+    /// Only pre middleware can defer requests.
+    /// If post middleware returns this, it is treated like [`MiddleResult::Abort`].
+    /// ## Example
+    /// ```rust
+    /// # use std::{thread, time::Duration};
+    /// # use afire::{Request, Response, Middleware, middleware::MiddleResult};
+    /// struct Slow;
+    ///
+    /// impl Middleware for Slow {
+    ///     fn pre(&self, req: &mut Request) -> MiddleResult {
+    ///         if req.path != "/slow" {
+    ///             return MiddleResult::Continue;
+    ///         }
+    ///
+    ///         MiddleResult::defer(|responder| {
+    ///             thread::spawn(move || {
+    ///                 thread::sleep(Duration::from_secs(1));
+    ///                 let _ = responder.send(Response::new().text("Finally!"));
+    ///             });
+    ///         })
+    ///     }
+    /// }
+    /// ```
+    pub fn defer(f: impl FnOnce(Responder) + Send + 'static) -> Self {
+        MiddleResult::Defer(Box::new(f))
+    }
+}
+
+impl Responder {
+    pub(crate) fn new<State>(request: Request, server: &Server<State>) -> Self
+    where
+        State: 'static + Send + Sync,
+    {
+        let finish = request
+            .socket
+            .get_or_insert_data(|| server.response_finish());
+        Self {
+            request,
+            finish,
+            auto_head: server.auto_head,
+        }
+    }
+
+    /// Sends the response to the client, then closes the connection.
+    /// The server's reason phrase setting, default headers and header policies (including the route's, if deferred from route middleware) are applied, just like for normal responses.
+    pub fn send(self, mut res: Response) -> Result<()> {
+        let req = self.request;
+        if !res.headers.has(HeaderType::Connection) {
+            res.headers.add(HeaderType::Connection, "close");
+        }
+
+        self.finish.apply_for(&req, &mut res);
+        let head_only = self.auto_head && req.method == Method::HEAD;
+        let result = res.write_as(req.socket.clone(), &[], req.version, head_only, &mut 0);
+        let _ = req.socket.lock().unwrap().shutdown(Shutdown::Both);
+        result
+    }
+}
+
+/// Information about a response once it has been sent, passed to [`Middleware::end_with_info`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResponseInfo {
+    /// The number of body bytes written to the socket.
+    /// This doesn't include the status line, headers or chunked encoding, and is less than the full body if the client disconnected partway through.
+    pub bytes_written: u64,
+    /// How long it took from receiving the request to finishing writing the response.
+    pub duration: Duration,
+    /// The status code that was sent.
+    pub status: Status,
 }
 
 /// Trait used to implement Middleware, which is code that runs before and after the routes - potentially modifying the request and response.
@@ -30,6 +131,12 @@ pub enum MiddleResult {
 /// - [`Middleware::post`]
 /// - [`Middleware::end_raw`]
 /// - [`Middleware::end`]
+/// - [`Middleware::end_with_info`]
+///
+/// ## Order
+/// Middleware with a higher [`Middleware::priority`] runs first.
+/// Middleware with the same priority runs in reverse order of attachment, so the last one attached runs first.
+/// The order middleware will run in can be checked with [`Server::middleware_chain`].
 ///
 pub trait Middleware {
     /// Middleware to run before routes.
@@ -43,6 +150,9 @@ pub trait Middleware {
     }
 
     /// Middleware to run Before Routes
+    ///
+    /// The request can be modified here, and the router will use the modified values.
+    /// Change the method and headers by setting their fields, and use [`Request::rewrite_path`] to change the path.
     fn pre(&self, _req: &mut Request) -> MiddleResult {
         MiddleResult::Continue
     }
@@ -74,6 +184,36 @@ pub trait Middleware {
     /// Middleware ot run after the response has been handled
     fn end(&self, _req: &Request, _res: &Response) {}
 
+    /// Middleware to run after the response has been sent, with how many bytes were written and how long it took.
+    /// This is what the server calls, and the default implementation calls [`Middleware::end`].
+    /// ## Example
+    /// ```rust
+    /// # use afire::{Request, Response, Middleware, middleware::ResponseInfo};
+    /// struct Log;
+    ///
+    /// impl Middleware for Log {
+    ///     fn end_with_info(&self, req: &Request, _res: &Response, info: &ResponseInfo) {
+    ///         println!("{} {} {} ({} bytes in {:?})", req.method, req.path, info.status.code(), info.bytes_written, info.duration);
+    ///     }
+    /// }
+    /// ```
+    fn end_with_info(&self, req: &Request, res: &Response, _info: &ResponseInfo) {
+        self.end(req, res);
+    }
+
+    /// The priority of this middleware, which decides where it goes in the server's middleware chain.
+    /// Middleware with a higher priority runs all of its hooks before middleware with a lower priority.
+    /// Defaults to `0`.
+    fn priority(&self) -> i32 {
+        0
+    }
+
+    /// The name of this middleware, used in [`Server::middleware_chain`].
+    /// Defaults to the type name.
+    fn name(&self) -> &str {
+        type_name::<Self>()
+    }
+
     /// Attach Middleware to a Server.
     /// If you want to get a reference to the server's state in your middleware state, you should override this method.
     fn attach<State>(self, server: &mut Server<State>)
@@ -83,6 +223,266 @@ pub trait Middleware {
     {
         trace!("{}Adding Middleware {}", emoji("📦"), type_name::<Self>());
 
-        server.middleware.push(Box::new(self));
+        server.insert_middleware(Box::new(self));
+    }
+}
+
+impl Debug for dyn Middleware + Send + Sync {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Middleware that is passed the server's state, so it doesn't have to hold its own copy.
+/// Attach it with [`Server::attach_stateful`] once the state has been set.
+///
+/// The hooks are the same as [`Middleware`]'s, with the state as the first argument.
+/// See [`Middleware`] for when each hook is run and how middleware is ordered.
+/// ## Example
+/// ```rust
+/// # use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
+/// # use afire::{Server, Request, Response, middleware::{MiddleResult, StatefulMiddleware}};
+/// #[derive(Default)]
+/// struct App {
+///     requests: AtomicUsize,
+/// }
+///
+/// struct Analytics;
+///
+/// impl StatefulMiddleware<App> for Analytics {
+///     fn end(&self, app: &Arc<App>, _req: &Request, _res: &Response) {
+///         app.requests.fetch_add(1, Ordering::Relaxed);
+///     }
+/// }
+///
+/// let mut server = Server::<App>::new("localhost", 8080).state(App::default());
+/// server.attach_stateful(Analytics);
+/// ```
+pub trait StatefulMiddleware<State> {
+    /// Middleware to run before routes, with the server state.
+    /// The default implementation calls [`StatefulMiddleware::pre`] if the [`Result`] is [`Ok`].
+    fn pre_raw(&self, state: &Arc<State>, req: &mut Result<Request>) -> MiddleResult {
+        if let Ok(req) = req {
+            return self.pre(state, req);
+        }
+        MiddleResult::Continue
+    }
+
+    /// Middleware to run before routes, with the server state.
+    /// See [`Middleware::pre`].
+    fn pre(&self, _state: &Arc<State>, _req: &mut Request) -> MiddleResult {
+        MiddleResult::Continue
+    }
+
+    /// Middleware to run after routes, with the server state.
+    /// The default implementation calls [`StatefulMiddleware::post`] if the [`Result`] is [`Ok`].
+    fn post_raw(
+        &self,
+        state: &Arc<State>,
+        req: Result<Rc<Request>>,
+        res: &mut Result<Response>,
+    ) -> MiddleResult {
+        if let (Ok(req), Ok(res)) = (req, res) {
+            return self.post(state, &req, res);
+        }
+        MiddleResult::Continue
+    }
+
+    /// Middleware to run after routes, with the server state.
+    fn post(&self, _state: &Arc<State>, _req: &Request, _res: &mut Response) -> MiddleResult {
+        MiddleResult::Continue
+    }
+
+    /// Middleware to run after the response has been handled, with the server state.
+    fn end(&self, _state: &Arc<State>, _req: &Request, _res: &Response) {}
+
+    /// Middleware to run after the response has been sent, with the server state.
+    /// See [`Middleware::end_with_info`].
+    fn end_with_info(
+        &self,
+        state: &Arc<State>,
+        req: &Request,
+        res: &Response,
+        _info: &ResponseInfo,
+    ) {
+        self.end(state, req, res);
+    }
+
+    /// The priority of this middleware, see [`Middleware::priority`].
+    fn priority(&self) -> i32 {
+        0
+    }
+
+    /// The name of this middleware, used in [`Server::middleware_chain`].
+    /// Defaults to the type name.
+    fn name(&self) -> &str {
+        type_name::<Self>()
+    }
+}
+
+/// Runs a [`StatefulMiddleware`] as normal middleware, passing it the state.
+pub(crate) struct WithState<M, State> {
+    pub(crate) middleware: M,
+    pub(crate) state: Arc<State>,
+}
+
+impl<M, State> Middleware for WithState<M, State>
+where
+    M: StatefulMiddleware<State>,
+{
+    fn pre_raw(&self, req: &mut Result<Request>) -> MiddleResult {
+        self.middleware.pre_raw(&self.state, req)
+    }
+
+    fn post_raw(&self, req: Result<Rc<Request>>, res: &mut Result<Response>) -> MiddleResult {
+        self.middleware.post_raw(&self.state, req, res)
+    }
+
+    fn end_with_info(&self, req: &Request, res: &Response, info: &ResponseInfo) {
+        self.middleware.end_with_info(&self.state, req, res, info);
+    }
+
+    fn priority(&self) -> i32 {
+        self.middleware.priority()
+    }
+
+    fn name(&self) -> &str {
+        self.middleware.name()
+    }
+}
+
+/// Adds middleware to a chain, after all middleware with the same or lower priority.
+/// Chains are run in reverse, so this puts it before them in the run order.
+pub(crate) fn insert(
+    chain: &mut Vec<Box<dyn Middleware + Send + Sync>>,
+    middleware: Box<dyn Middleware + Send + Sync>,
+) {
+    let priority = middleware.priority();
+    let index = chain
+        .iter()
+        .rposition(|x| x.priority() <= priority)
+        .map_or(0, |i| i + 1);
+    chain.insert(index, middleware);
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::Cursor,
+        sync::{Arc, Mutex},
+    };
+
+    use super::{MiddleResult, Middleware, ResponseInfo, StatefulMiddleware};
+    use crate::{
+        testing::{TestRequest, TestServer},
+        Method, Request, Response, Server, Status,
+    };
+
+    struct Record(&'static str, i32, Arc<Mutex<Vec<&'static str>>>);
+
+    impl Middleware for Record {
+        fn priority(&self) -> i32 {
+            self.1
+        }
+
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        fn pre(&self, _req: &mut Request) -> MiddleResult {
+            self.2.lock().unwrap().push(self.0);
+            MiddleResult::Continue
+        }
+    }
+
+    #[test]
+    fn test_priority() {
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let mut server = Server::<()>::new("localhost", 8080);
+        server.route(Method::GET, "/", |_req| Response::new());
+        for (name, priority) in [("a", 0), ("last", -10), ("b", 0), ("first", 10)] {
+            Record(name, priority, order.clone()).attach(&mut server);
+        }
+
+        let chain = server
+            .middleware_chain()
+            .map(|x| x.name())
+            .collect::<Vec<_>>();
+        assert_eq!(chain, ["first", "b", "a", "last"]);
+
+        TestRequest::get("/").send(&TestServer::from(server));
+        assert_eq!(*order.lock().unwrap(), ["first", "b", "a", "last"]);
+    }
+
+    struct Info(Arc<Mutex<Vec<ResponseInfo>>>);
+
+    impl Middleware for Info {
+        fn end_with_info(&self, _req: &Request, _res: &Response, info: &ResponseInfo) {
+            self.0.lock().unwrap().push(*info);
+        }
+    }
+
+    #[test]
+    fn test_end_with_info() {
+        let infos = Arc::new(Mutex::new(Vec::new()));
+        let mut server = Server::<()>::new("localhost", 8080).auto_head(true);
+        server.route(Method::GET, "/", |_req| Response::new().text("Hello"));
+        server.route(Method::GET, "/stream", |_req| {
+            Response::new().stream(Cursor::new(vec![b'a'; 20_000]))
+        });
+        Info(infos.clone()).attach(&mut server);
+        let server = TestServer::from(server);
+
+        TestRequest::get("/").send(&server);
+        TestRequest::get("/stream").send(&server);
+        TestRequest::new(Method::HEAD, "/").send(&server);
+        TestRequest::get("/missing").send(&server);
+
+        let infos = infos.lock().unwrap();
+        let sizes = infos.iter().map(|x| x.bytes_written).collect::<Vec<_>>();
+        let statuses = infos.iter().map(|x| x.status).collect::<Vec<_>>();
+        assert_eq!(sizes[..3], [5, 20_000, 0]);
+        assert_eq!(statuses[..3], [Status::Ok, Status::Ok, Status::Ok]);
+        assert_eq!(statuses[3], Status::NotFound);
+    }
+
+    struct Count;
+
+    impl StatefulMiddleware<Mutex<Vec<String>>> for Count {
+        fn pre(&self, state: &Arc<Mutex<Vec<String>>>, req: &mut Request) -> MiddleResult {
+            state.lock().unwrap().push(req.path.clone());
+            MiddleResult::Continue
+        }
+
+        fn post(
+            &self,
+            state: &Arc<Mutex<Vec<String>>>,
+            _req: &Request,
+            res: &mut Response,
+        ) -> MiddleResult {
+            res.headers
+                .add("X-Count", state.lock().unwrap().len().to_string());
+            MiddleResult::Continue
+        }
+    }
+
+    #[test]
+    fn test_stateful_middleware() {
+        let mut server = Server::new("localhost", 8080).state(Mutex::new(Vec::new()));
+        server.route(Method::GET, "/", |_req| Response::new());
+        server.attach_stateful(Count);
+        let chain = server
+            .middleware_chain()
+            .map(|x| x.name())
+            .collect::<Vec<_>>();
+        assert_eq!(chain, ["afire::middleware::test::Count"]);
+
+        let state = server.app();
+        let server = TestServer::from(server);
+        let res = TestRequest::get("/").send(&server);
+        assert_eq!(res.header("X-Count"), Some("1"));
+
+        TestRequest::get("/missing").send(&server);
+        assert_eq!(*state.lock().unwrap(), ["/", "/missing"]);
     }
 }
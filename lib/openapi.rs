@@ -0,0 +1,437 @@
+//! Generate an [OpenAPI 3](https://spec.openapis.org/oas/v3.0.3) document for a server's routes, and serve it with a Swagger UI page.
+//! Only available with the `openapi` feature.
+//!
+//! Routes are described with [`Route::summary`], [`Route::description`], [`Route::tag`], [`Route::request_body`] and [`Route::response`].
+//! Schemas are made from example values with [`Schema::example`], using their serde `Serialize` implementation, or can be written by hand as JSON.
+//! Path parameters like `{id}` are added automatically.
+//! ## Example
+//! ```rust
+//! # use afire::{Server, Response, Method, Status, openapi::{OpenApi, Schema}};
+//! # use serde::Serialize;
+//! #[derive(Serialize)]
+//! struct User {
+//!     id: u64,
+//!     name: String,
+//! }
+//!
+//! let mut server = Server::<()>::new("localhost", 8080);
+//! server
+//!     .route(Method::GET, "/users/{id}", |_req| Response::new())
+//!     .summary("Get a user")
+//!     .tag("users")
+//!     .response(Status::Ok, Schema::example(&User { id: 1, name: "Connor".into() }))
+//!     .response(Status::NotFound, None);
+//!
+//! // Serves the document at `/openapi.json` and Swagger UI at `/docs`
+//! OpenApi::new("Users API", "1.0.0").serve(&mut server);
+//! ```
+
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+use crate::{internal::path::PathPart, Content, Method, Response, Route, Server, Status};
+
+/// A Swagger UI page, with `{{TITLE}}`, `{{ASSETS}}` and `{{SPEC}}` to be replaced with the API title, asset url and document path.
+const SWAGGER_UI: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <meta name="viewport" content="width=device-width, initial-scale=1">
+  <title>{{TITLE}}</title>
+  <link rel="stylesheet" href="{{ASSETS}}/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="{{ASSETS}}/swagger-ui-bundle.js"></script>
+  <script>
+    window.ui = SwaggerUIBundle({ url: "{{SPEC}}", dom_id: "#swagger-ui" });
+  </script>
+</body>
+</html>
+"##;
+
+/// Builds and serves the OpenAPI document for a server.
+/// See the [module level docs](self) for an example.
+pub struct OpenApi {
+    title: String,
+    version: String,
+    description: Option<String>,
+    path: String,
+    ui_path: Option<String>,
+    ui_assets: String,
+}
+
+/// A JSON schema for a request or response body.
+#[derive(Debug, Clone)]
+pub struct Schema(Value);
+
+/// The documentation of a route, set with the methods on [`Route`].
+#[derive(Debug, Default)]
+pub(crate) struct Operation {
+    summary: Option<String>,
+    description: Option<String>,
+    tags: Vec<String>,
+    request: Option<Schema>,
+    responses: Vec<(Status, Option<Schema>)>,
+}
+
+impl OpenApi {
+    /// Creates a new OpenAPI document with the API's title and version.
+    /// By default, the document is served at `/openapi.json` and Swagger UI at `/docs`.
+    pub fn new(title: impl AsRef<str>, version: impl AsRef<str>) -> Self {
+        Self {
+            title: title.as_ref().to_owned(),
+            version: version.as_ref().to_owned(),
+            description: None,
+            path: "/openapi.json".to_owned(),
+            ui_path: Some("/docs".to_owned()),
+            ui_assets: "https://unpkg.com/swagger-ui-dist@5".to_owned(),
+        }
+    }
+
+    /// Sets the description of the API, which can use CommonMark.
+    pub fn description(self, description: impl AsRef<str>) -> Self {
+        Self {
+            description: Some(description.as_ref().to_owned()),
+            ..self
+        }
+    }
+
+    /// Sets the path the JSON document is served at.
+    /// Defaults to `/openapi.json`.
+    pub fn path(self, path: impl AsRef<str>) -> Self {
+        Self {
+            path: path.as_ref().to_owned(),
+            ..self
+        }
+    }
+
+    /// Sets the path the Swagger UI page is served at.
+    /// Defaults to `/docs`.
+    pub fn ui_path(self, path: impl AsRef<str>) -> Self {
+        Self {
+            ui_path: Some(path.as_ref().to_owned()),
+            ..self
+        }
+    }
+
+    /// Only serves the JSON document, without the Swagger UI page.
+    pub fn without_ui(self) -> Self {
+        Self {
+            ui_path: None,
+            ..self
+        }
+    }
+
+    /// Sets where the Swagger UI page loads its scripts and styles from, a folder with `swagger-ui.css` and `swagger-ui-bundle.js`.
+    /// Defaults to the `swagger-ui-dist` package on unpkg, so set this to serve them yourself if clients can't reach it.
+    pub fn ui_assets(self, url: impl AsRef<str>) -> Self {
+        Self {
+            ui_assets: url.as_ref().trim_end_matches('/').to_owned(),
+            ..self
+        }
+    }
+
+    /// Generates the document for the server's current routes.
+    ///
+    /// Routes with the [`Method::ANY`] or [`Method::CONNECT`] methods and routes with `*` or `**` wildcards are left out, as OpenAPI can't describe them.
+    /// If a route has no documented responses, a default response is used.
+    pub fn document<State: Send + Sync>(&self, server: &Server<State>) -> Value {
+        let mut paths = Map::new();
+        for route in server.routes.iter() {
+            let (path, params) = match openapi_path(route) {
+                Some(i) => i,
+                None => continue,
+            };
+
+            let method = match route.method() {
+                Method::ANY | Method::CONNECT => continue,
+                method => method.to_string().to_lowercase(),
+            };
+
+            let item = paths
+                .entry(path)
+                .or_insert_with(|| Value::Object(Map::new()));
+            item[method] = route.docs.operation(&params);
+        }
+
+        let mut info = json!({ "title": self.title, "version": self.version });
+        if let Some(description) = &self.description {
+            info["description"] = description.as_str().into();
+        }
+
+        json!({
+            "openapi": "3.0.3",
+            "info": info,
+            "paths": paths,
+        })
+    }
+
+    /// Adds routes to the server that serve the document, and the Swagger UI page if enabled.
+    /// The document is generated now, so call this after adding all of your other routes.
+    pub fn serve<State: Send + Sync + 'static>(self, server: &mut Server<State>) {
+        let document = self.document(server).to_string();
+        server.route(Method::GET, &self.path, move |_req| {
+            Response::new().text(&document).content(Content::JSON)
+        });
+
+        if let Some(ui_path) = &self.ui_path {
+            let page = SWAGGER_UI
+                .replace("{{TITLE}}", &html_escape(&self.title))
+                .replace("{{ASSETS}}", &html_escape(&self.ui_assets))
+                .replace("{{SPEC}}", &html_escape(&self.path));
+            server.route(Method::GET, ui_path, move |_req| {
+                Response::new().text(&page).content(Content::HTML)
+            });
+        }
+    }
+}
+
+impl Schema {
+    /// Makes a schema from an example value, using its serde `Serialize` implementation.
+    /// The types of the value's fields are used for the schema, and the value is included as the example.
+    /// Fields that are None in the example are typed as nullable, with no other type.
+    ///
+    /// Will <u>panic</u> if the value can not be serialized to JSON, like a map with non-string keys.
+    pub fn example(value: &impl Serialize) -> Self {
+        let value = serde_json::to_value(value).expect("Example could not be serialized");
+        let mut schema = infer_schema(&value);
+        schema["example"] = value;
+        Self(schema)
+    }
+}
+
+impl From<Value> for Schema {
+    /// Uses a JSON schema written by hand.
+    fn from(value: Value) -> Self {
+        Self(value)
+    }
+}
+
+impl Operation {
+    pub(crate) fn summary(&mut self, summary: &str) {
+        self.summary = Some(summary.to_owned());
+    }
+
+    pub(crate) fn description(&mut self, description: &str) {
+        self.description = Some(description.to_owned());
+    }
+
+    pub(crate) fn tag(&mut self, tag: &str) {
+        self.tags.push(tag.to_owned());
+    }
+
+    pub(crate) fn request(&mut self, schema: Schema) {
+        self.request = Some(schema);
+    }
+
+    pub(crate) fn response(&mut self, status: Status, schema: Option<Schema>) {
+        self.responses.push((status, schema));
+    }
+
+    /// Makes the operation object for the document, with the route's path parameters.
+    fn operation(&self, params: &[&str]) -> Value {
+        let mut out = Map::new();
+        if let Some(summary) = &self.summary {
+            out.insert("summary".to_owned(), summary.as_str().into());
+        }
+
+        if let Some(description) = &self.description {
+            out.insert("description".to_owned(), description.as_str().into());
+        }
+
+        if !self.tags.is_empty() {
+            out.insert("tags".to_owned(), self.tags.clone().into());
+        }
+
+        if !params.is_empty() {
+            let params = params
+                .iter()
+                .map(|name| {
+                    json!({
+                        "name": name,
+                        "in": "path",
+                        "required": true,
+                        "schema": { "type": "string" },
+                    })
+                })
+                .collect::<Vec<_>>();
+            out.insert("parameters".to_owned(), params.into());
+        }
+
+        if let Some(schema) = &self.request {
+            out.insert(
+                "requestBody".to_owned(),
+                json!({ "required": true, "content": json_content(schema) }),
+            );
+        }
+
+        let mut responses = Map::new();
+        for (status, schema) in self.responses.iter() {
+            let mut response = json!({ "description": status.reason_phrase() });
+            if let Some(schema) = schema {
+                response["content"] = json_content(schema);
+            }
+            responses.insert(status.code().to_string(), response);
+        }
+
+        if responses.is_empty() {
+            responses.insert("default".to_owned(), json!({ "description": "Response" }));
+        }
+
+        out.insert("responses".to_owned(), responses.into());
+        Value::Object(out)
+    }
+}
+
+/// Gets the path of a route in the form OpenAPI uses, with the names of its parameters.
+/// Returns None if the route has wildcards.
+fn openapi_path<State>(route: &Route<State>) -> Option<(String, Vec<&str>)> {
+    let mut path = String::new();
+    let mut params = Vec::new();
+    for part in route.path().parts.iter() {
+        path.push('/');
+        match part {
+            PathPart::Normal(segment) => path.push_str(segment),
+            PathPart::Param(name) => {
+                path.push_str(&format!("{{{name}}}"));
+                params.push(name.as_str());
+            }
+            PathPart::Any | PathPart::AnyAfter => return None,
+        }
+    }
+
+    // The root path is one empty segment
+    if path.is_empty() || path == "/" {
+        path = "/".to_owned();
+    }
+
+    Some((path, params))
+}
+
+/// The `content` of a request or response body with a JSON schema.
+fn json_content(schema: &Schema) -> Value {
+    json!({ "application/json": { "schema": schema.0 } })
+}
+
+/// Makes a JSON schema that describes a value.
+fn infer_schema(value: &Value) -> Value {
+    match value {
+        Value::Null => json!({ "nullable": true }),
+        Value::Bool(_) => json!({ "type": "boolean" }),
+        Value::Number(i) if i.is_f64() => json!({ "type": "number" }),
+        Value::Number(_) => json!({ "type": "integer" }),
+        Value::String(_) => json!({ "type": "string" }),
+        Value::Array(items) => json!({
+            "type": "array",
+            "items": items.first().map(infer_schema).unwrap_or_else(|| json!({})),
+        }),
+        Value::Object(fields) => {
+            let properties = fields
+                .iter()
+                .map(|(k, v)| (k.to_owned(), infer_schema(v)))
+                .collect::<Map<_, _>>();
+            json!({ "type": "object", "properties": properties })
+        }
+    }
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+    use serde::Serialize;
+    use serde_json::json;
+
+    use super::{OpenApi, Schema};
+    use crate::{
+        testing::{TestRequest, TestServer},
+        Method, Response, Server, Status,
+    };
+
+    #[derive(Serialize)]
+    struct User {
+        id: u64,
+        name: String,
+        score: f32,
+        tags: Vec<String>,
+        bio: Option<String>,
+    }
+
+    #[test]
+    fn test_schema_example() {
+        let user = User {
+            id: 1,
+            name: "Connor".to_owned(),
+            score: 0.5,
+            tags: vec!["admin".to_owned()],
+            bio: None,
+        };
+
+        let schema = Schema::example(&user).0;
+        assert_eq!(schema["type"], "object");
+        assert_eq!(schema["properties"]["id"]["type"], "integer");
+        assert_eq!(schema["properties"]["name"]["type"], "string");
+        assert_eq!(schema["properties"]["score"]["type"], "number");
+        assert_eq!(schema["properties"]["tags"]["items"]["type"], "string");
+        assert_eq!(schema["properties"]["bio"]["nullable"], true);
+        assert_eq!(schema["example"]["name"], "Connor");
+    }
+
+    #[test]
+    fn test_document() {
+        let mut server = Server::<()>::new("localhost", 8080);
+        server.route(Method::GET, "/", |_| Response::new());
+        server
+            .route(Method::POST, "/users/{id}/posts", |_| Response::new())
+            .summary("Create a post")
+            .tag("posts")
+            .request_body(json!({ "type": "string" }))
+            .response(Status::Created, None);
+        server.route(Method::GET, "/static/**", |_| Response::new());
+        server.route(Method::ANY, "/any", |_| Response::new());
+
+        let document = OpenApi::new("Test", "1.0").document(&server);
+        assert_eq!(document["info"]["title"], "Test");
+        assert_eq!(document["paths"].as_object().unwrap().len(), 2);
+        assert_eq!(
+            document["paths"]["/"]["get"]["responses"]["default"]["description"],
+            "Response"
+        );
+
+        let post = &document["paths"]["/users/{id}/posts"]["post"];
+        assert_eq!(post["summary"], "Create a post");
+        assert_eq!(post["tags"], json!(["posts"]));
+        assert_eq!(post["parameters"][0]["name"], "id");
+        assert_eq!(
+            post["requestBody"]["content"]["application/json"]["schema"]["type"],
+            "string"
+        );
+        assert_eq!(post["responses"]["201"]["description"], "Created");
+    }
+
+    #[test]
+    fn test_serve() {
+        let mut server = Server::<()>::new("localhost", 8080);
+        server
+            .route(Method::GET, "/hello", |_| Response::new())
+            .summary("Say hello");
+        OpenApi::new("Test", "1.0")
+            .path("/api.json")
+            .serve(&mut server);
+        let server = TestServer::from(server);
+
+        let res = TestRequest::get("/api.json").send(&server);
+        assert_eq!(res.status, Status::Ok);
+        let document = serde_json::from_str::<serde_json::Value>(&res.text()).unwrap();
+        assert_eq!(document["paths"]["/hello"]["get"]["summary"], "Say hello");
+
+        let res = TestRequest::get("/docs").send(&server);
+        assert!(res.text().contains("url: \"/api.json\""));
+    }
+}
@@ -1,22 +1,54 @@
 use std::{
     borrow::Cow,
-    cell::RefCell,
-    fmt::Debug,
-    io::{BufRead, BufReader, Read},
-    net::{SocketAddr, TcpStream},
+    cell::{OnceCell, RefCell, RefMut},
+    fmt::{Debug, Display},
+    io::{self, BufRead, Read},
+    net::SocketAddr,
     str::FromStr,
-    sync::{Arc, Mutex},
+    sync::Arc,
+    time::{Duration, Instant},
 };
 
 use crate::{
-    consts::BUFF_SIZE,
+    accept::{Accept, AcceptLanguage},
+    consts::{BUFF_SIZE, CHUNK_SIZE},
     cookie::CookieJar,
-    error::{ParseError, Result, StreamError},
+    error::{ParseError, Result, RouteError, StreamError},
     header::{HeaderType, Headers},
-    internal::common::ForceLock,
-    Cookie, Error, Header, Method, Query,
+    internal::{
+        common::ForceLock,
+        encoding::url,
+        socket::{write_all_deadline, BodyStream, ChunkedDecoder, ConnReader},
+    },
+    multipart::{form_boundary, MultipartError, MultipartStream},
+    reproduce::Reproducer,
+    transport::{ConnectionInfo, Socket},
+    Content, Cookie, Error, Header, HttpVersion, Method, Query, TypeMap,
 };
 
+/// Limits on the size of requests read by [`Request::from_socket`], from the server's config.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct ParseLimits {
+    /// See [`crate::Server::body_buffer_limit`].
+    pub body_buffer: Option<usize>,
+    /// See [`crate::Server::max_body_size`].
+    pub body_size: Option<usize>,
+    /// See [`crate::Server::max_header_count`].
+    pub header_count: Option<usize>,
+    /// See [`crate::Server::max_header_size`].
+    pub header_size: Option<usize>,
+    /// See [`crate::Server::max_head_size`].
+    pub head_size: Option<usize>,
+    /// See [`crate::Server::max_uri_length`].
+    pub uri_length: Option<usize>,
+    /// See [`crate::Server::head_timeout`].
+    pub head_timeout: Option<Duration>,
+    /// See [`crate::Server::keep_alive_timeout`].
+    pub keep_alive_timeout: Option<Duration>,
+    /// See [`crate::Server::socket_timeout`].
+    pub socket_timeout: Option<Duration>,
+}
+
 /// Http Request
 pub struct Request {
     /// Request method.
@@ -24,14 +56,24 @@ pub struct Request {
 
     /// Request path (not tokenized).
     /// The query string is not included, its in the `query` field.
+    /// For `CONNECT` requests this is empty, as the target is in the `authority` field.
     pub path: String,
 
-    /// HTTP version string.
-    /// Should usually be "HTTP/1.1".
-    pub version: String,
+    /// The host (and port) the request was sent to, if the client put it in the request line.
+    /// This is set for absolute-form targets (`GET http://example.com/path HTTP/1.1`), which are sent to proxies, and for the authority-form targets of `CONNECT` requests (`CONNECT example.com:443 HTTP/1.1`).
+    /// For other requests, the host is only in the `Host` header.
+    pub authority: Option<String>,
+
+    /// HTTP version of the request.
+    /// Requests with versions other than HTTP/1.x are rejected with a `505 HTTP Version Not Supported`.
+    pub version: HttpVersion,
+
+    /// Path Params, filled by the router.
+    /// The values are stored without url decoding.
+    pub(crate) path_params: OnceCell<Vec<(String, String)>>,
 
-    /// Path Params, filled by the router
-    pub(crate) path_params: RefCell<Vec<(String, String)>>,
+    /// The path of the route that matched, filled by the router.
+    pub(crate) route: OnceCell<String>,
 
     /// Request Query.
     pub query: Query,
@@ -44,25 +86,71 @@ pub struct Request {
     pub cookies: CookieJar,
 
     /// Request body, as a static byte vec.
+    /// If the body is larger than [`crate::Server::body_buffer_limit`] this is empty, and the body has to be read with [`Request::body_reader`].
     pub body: Arc<Vec<u8>>,
 
     /// Client socket address.
     /// If you are using a reverse proxy, this will be the address of the proxy (often localhost).
     pub address: SocketAddr,
 
-    /// The raw tcp socket
-    pub socket: Arc<Mutex<TcpStream>>,
+    /// Typed data attached to the request, by pre middleware for route handlers to use.
+    /// Unlike [`Socket`] storage, it only lasts for this request.
+    /// See [`TypeMap`] for an example.
+    pub extensions: TypeMap,
+
+    /// The raw client socket.
+    /// This is a [`std::net::TcpStream`] unless the connection was passed in with [`crate::Server::handle_stream`].
+    /// It is shared by every request on the same connection, and has a stable [`Socket::id`] and per-connection storage.
+    pub socket: Arc<Socket>,
+
+    /// The request line and headers exactly as they were received, see [`Request::raw_head`].
+    raw_head: Vec<u8>,
+
+    /// The path before it was first changed with [`Request::rewrite_path`].
+    original_path: Option<String>,
+
+    /// The certificate chain the client authenticated with, see [`Request::peer_certificates`].
+    peer_certificates: Option<Vec<Vec<u8>>>,
+
+    /// The local address the client connected to, see [`Request::local_addr`].
+    local_address: Option<SocketAddr>,
+
+    /// The details of the connection's encryption, see [`Request::connection_info`].
+    connection_info: Option<ConnectionInfo>,
+
+    /// The trailers sent after a chunked body that was buffered.
+    trailers: Headers,
+
+    /// The unread body, if it was too large to buffer.
+    body_stream: RefCell<Option<BodyStream>>,
+
+    /// When the request line was read, see [`Request::received`].
+    received: Instant,
 }
 
 impl Request {
+    /// Checks if the connection should be kept open after this request.
+    /// HTTP/1.1 connections are persistent unless the client sends `Connection: close`,
+    /// older versions need an explicit `Connection: keep-alive`.
     pub(crate) fn keep_alive(&self) -> bool {
-        self.headers
-            .get(HeaderType::Connection)
-            .map(|i| i.to_lowercase() == "keep-alive")
-            .unwrap_or(false)
+        let default = self.version == HttpVersion::Http11;
+        let connection = match self.headers.get(HeaderType::Connection) {
+            Some(i) => i,
+            None => return default,
+        };
+
+        let mut options = connection.split(',').map(str::trim);
+        if options.clone().any(|x| x.eq_ignore_ascii_case("close")) {
+            return false;
+        }
+
+        default || options.any(|x| x.eq_ignore_ascii_case("keep-alive"))
     }
 
     /// Get a path parameter by its name.
+    /// The value is url decoded, so `/greet/John%20Doe` will give `John Doe` for the path `/greet/{name}`.
+    /// If a path parameter has an invalid url encoding, the router will respond with a 400 Bad Request instead of calling the route handler.
+    /// See [`Request::param_raw`] to get the value without decoding.
     ///
     /// ## Example
     /// ```rust
@@ -82,13 +170,224 @@ impl Request {
     ///         .content(Content::TXT)
     /// });
     /// ```
-    pub fn param(&self, name: impl AsRef<str>) -> Option<String> {
-        let name = name.as_ref().to_owned();
+    pub fn param(&self, name: impl AsRef<str>) -> Option<Cow<'_, str>> {
+        let raw = self.param_raw(name)?;
+        if !raw.contains(['%', '+']) {
+            return Some(Cow::Borrowed(raw));
+        }
+
+        Some(Cow::Owned(
+            url::decode(raw).unwrap_or_else(|| raw.to_owned()),
+        ))
+    }
+
+    /// Get a path parameter by its name, url decoded and parsed into any type that implements [`FromStr`].
+    /// If the parameter is missing or fails to parse, a [`RouteError`] is returned, which can be turned into a `400 Bad Request` response.
+    /// ## Example
+    /// ```rust
+    /// # use afire::{Response, Method, Server};
+    /// # let mut server = Server::<()>::new("localhost", 8080);
+    /// server.route(Method::GET, "/post/{id}", |req| {
+    ///     match req.param_parsed::<u64>("id") {
+    ///         Ok(id) => Response::new().text(format!("Post #{id}")),
+    ///         Err(e) => e.into(),
+    ///     }
+    /// });
+    /// ```
+    pub fn param_parsed<T>(&self, name: impl AsRef<str>) -> std::result::Result<T, RouteError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let name = name.as_ref();
+        RouteError::parse(
+            || format!("path parameter `{name}`"),
+            self.param(name).as_deref(),
+        )
+    }
+
+    /// Gets the path of the route that matched this request, like `/users/{id}`.
+    /// Returns None before routing (in pre middleware), or if no route matched.
+    /// Unlike the request path, this has a small number of values, so it is useful for grouping requests in metrics or logs.
+    pub fn route(&self) -> Option<&str> {
+        self.route.get().map(|x| x.as_str())
+    }
+
+    /// Get a path parameter by its name, without url decoding it.
+    /// See [`Request::param`] for the decoded version.
+    pub fn param_raw(&self, name: impl AsRef<str>) -> Option<&str> {
+        let name = name.as_ref();
         self.path_params
-            .borrow()
+            .get()?
             .iter()
             .find(|x| x.0 == name)
-            .map(|i| i.1.to_owned())
+            .map(|i| i.1.as_str())
+    }
+
+    /// Gets the request line and headers exactly as they were received from the client, including the blank line at the end.
+    /// Unlike the parsed fields, nothing is normalized, so the path still contains the query and duplicate slashes, and cookies are still in the headers.
+    pub fn raw_head(&self) -> &[u8] {
+        &self.raw_head
+    }
+
+    /// Gets when the request line was received.
+    /// Use `req.received().elapsed()` to get how long the request has taken so far.
+    pub fn received(&self) -> Instant {
+        self.received
+    }
+
+    /// Gets a [`Reproducer`], to turn the request into a curl command or raw HTTP with custom redactions.
+    pub fn reproducer(&self) -> Reproducer<'_> {
+        Reproducer::new(self)
+    }
+
+    /// Gets a curl command that sends this request again, to make bug reports reproducible.
+    /// The `Authorization`, `Cookie` and `Proxy-Authorization` headers are redacted, use [`Request::reproducer`] to change this.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use afire::{Method, Response, Server};
+    /// # let mut server = Server::<()>::new("localhost", 8080);
+    /// server.route(Method::POST, "/api", |req| {
+    ///     // curl -X 'POST' 'http://localhost:8080/api?v=2' -H 'Content-Type: application/json' --data-binary '{"id": 1}'
+    ///     println!("{}", req.to_curl());
+    ///     Response::new()
+    /// });
+    /// ```
+    pub fn to_curl(&self) -> String {
+        self.reproducer().curl()
+    }
+
+    /// Gets the request as raw HTTP, with the same redactions as [`Request::to_curl`].
+    /// Useful for replaying with netcat or pasting into an HTTP client.
+    pub fn to_raw_http(&self) -> String {
+        self.reproducer().raw_http()
+    }
+
+    /// Changes the path of the request, before it is routed.
+    /// This is meant to be used in [`crate::Middleware::pre`], for things like stripping a deployment prefix or mapping legacy URLs.
+    /// The router, and any middleware that runs after, will see the new path.
+    ///
+    /// The path is normalized the same way as paths from the client.
+    /// If it includes a query string (`/new?page=2`), it replaces the request's query.
+    /// The path from the client is still available with [`Request::original_path`].
+    ///
+    /// The method and headers can be rewritten by setting their fields directly.
+    /// ## Example
+    /// ```rust
+    /// # use afire::{Request, Middleware, middleware::MiddleResult};
+    /// struct StripPrefix;
+    ///
+    /// impl Middleware for StripPrefix {
+    ///     fn pre(&self, req: &mut Request) -> MiddleResult {
+    ///         if let Some(path) = req.path.strip_prefix("/app") {
+    ///             let path = path.to_owned();
+    ///             req.rewrite_path(path);
+    ///         }
+    ///         MiddleResult::Continue
+    ///     }
+    /// }
+    /// ```
+    pub fn rewrite_path(&mut self, path: impl AsRef<str>) {
+        let (path, query) = parse_target(path.as_ref());
+        trace!(Level::Debug, "Rewriting path `{}` to `{}`", self.path, path);
+
+        let old = std::mem::replace(&mut self.path, path);
+        self.original_path.get_or_insert(old);
+        if let Some(query) = query {
+            self.query = query;
+        }
+    }
+
+    /// Parses the request's `Accept` header.
+    /// If the client didn't send one, it accepts any type (`*/*`).
+    pub fn accept(&self) -> Accept {
+        Accept::parse(self.headers.get(HeaderType::Accept).unwrap_or("*/*"))
+    }
+
+    /// Picks the best content type to send from the ones the handler can make, using the request's `Accept` header.
+    /// Ties go to the type listed first, which is also picked if the client didn't send an `Accept` header.
+    /// Returns `None` if the client accepts none of them.
+    /// See [`Accept::negotiate`].
+    /// ## Example
+    /// ```rust
+    /// # use afire::{Content, Method, Response, Server, Status};
+    /// # let mut server = Server::<()>::new("localhost", 8080);
+    /// server.route(Method::GET, "/user", |req| {
+    ///     match req.negotiate(&[Content::JSON, Content::HTML]) {
+    ///         Some(Content::HTML) => Response::new().text("<h1>Darren</h1>").content(Content::HTML),
+    ///         Some(_) => Response::new().text(r#"{"name":"Darren"}"#).content(Content::JSON),
+    ///         None => Response::new().status(Status::NotAcceptable),
+    ///     }
+    ///     .header("Vary", "Accept")
+    /// });
+    /// ```
+    pub fn negotiate<'a>(&self, offered: &[Content<'a>]) -> Option<Content<'a>> {
+        self.accept().negotiate(offered)
+    }
+
+    /// Parses the request's `Accept-Language` header.
+    /// If the client didn't send one, it accepts any language (`*`).
+    pub fn accept_language(&self) -> AcceptLanguage {
+        AcceptLanguage::parse(self.headers.get(HeaderType::AcceptLanguage).unwrap_or("*"))
+    }
+
+    /// Picks the best language from the ones available, using the request's `Accept-Language` header.
+    /// Ties go to the language listed first.
+    /// See [`AcceptLanguage::negotiate`].
+    /// ## Example
+    /// ```rust
+    /// # use afire::{Method, Response, Server};
+    /// # let mut server = Server::<()>::new("localhost", 8080);
+    /// server.route(Method::GET, "/", |req| {
+    ///     let text = match req.negotiate_language(&["en", "fr"]) {
+    ///         Some("fr") => "Bonjour",
+    ///         _ => "Hello",
+    ///     };
+    ///     Response::new().text(text).header("Vary", "Accept-Language")
+    /// });
+    /// ```
+    pub fn negotiate_language<'a>(&self, available: &[&'a str]) -> Option<&'a str> {
+        self.accept_language().negotiate(available)
+    }
+
+    /// Gets the path the client requested, before any [`Request::rewrite_path`] calls.
+    /// If the path was never rewritten, this is the same as [`Request::path`].
+    pub fn original_path(&self) -> &str {
+        self.original_path.as_deref().unwrap_or(&self.path)
+    }
+
+    /// Gets the query string exactly as the client sent it, without the `?`.
+    /// Returns None if the request target has no query.
+    pub(crate) fn raw_query(&self) -> Option<&str> {
+        let head = std::str::from_utf8(&self.raw_head).ok()?;
+        let target = head.lines().next()?.split(' ').nth(1)?;
+        target.split_once('?').map(|x| x.1)
+    }
+
+    /// Gets the address of the client, the same as [`Request::address`].
+    /// If you are using a reverse proxy, this will be the address of the proxy, see [`crate::extension::RealIp`] to get the client's address from its headers.
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Gets the local address the client connected to, which is useful when listening on more than one address.
+    /// Returns None if the transport doesn't have one (See [`crate::transport::Stream::local_addr`]).
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_address
+    }
+
+    /// Gets the negotiated protocol version, cipher suite and other details of an encrypted connection.
+    /// This is only set for TLS connections (with the `tls` feature), or other transports that implement [`crate::transport::Stream::connection_info`].
+    pub fn connection_info(&self) -> Option<&ConnectionInfo> {
+        self.connection_info.as_ref()
+    }
+
+    /// Gets the DER encoded certificate chain the client authenticated with.
+    /// The client's own certificate is first.
+    /// This is only set for TLS connections where the server asked for a client certificate (see `TlsConfig::with_client_auth` with the `tls` feature) and the client sent one.
+    pub fn peer_certificates(&self) -> Option<&[Vec<u8>]> {
+        self.peer_certificates.as_deref()
     }
 
     /// Gets the body of the request as a string.
@@ -98,32 +397,370 @@ impl Request {
         String::from_utf8_lossy(&self.body)
     }
 
-    /// Read a request from a TcpStream.
-    pub(crate) fn from_socket(raw_stream: Arc<Mutex<TcpStream>>) -> Result<Self> {
-        let stream = raw_stream.force_lock();
+    /// Parses an `application/x-www-form-urlencoded` request body, as sent by HTML forms, into a [`Query`].
+    /// Keys and values are url decoded, and keys can be repeated, see [`Query::get_all`].
+    /// If the request has a different `Content-Type`, or the body isn't valid UTF-8, a [`RouteError`] is returned.
+    ///
+    /// Streamed bodies (See [`crate::Server::body_buffer_limit`]) are read from the socket.
+    /// See [`Request::form_as`] to deserialize the form into a struct.
+    /// ## Example
+    /// ```rust
+    /// # use afire::{Method, Response, Server};
+    /// # let mut server = Server::<()>::new("localhost", 8080);
+    /// server.route(Method::POST, "/login", |req| {
+    ///     let form = match req.form() {
+    ///         Ok(form) => form,
+    ///         Err(e) => return e.into(),
+    ///     };
+    ///
+    ///     let user = form.get("user").unwrap_or("anonymous");
+    ///     Response::new().text(format!("Hello, {user}"))
+    /// });
+    /// ```
+    pub fn form(&self) -> std::result::Result<Query, RouteError> {
+        let content_type = self
+            .headers
+            .get(HeaderType::ContentType)
+            .unwrap_or_default();
+        let mime = content_type.split(';').next().unwrap_or_default().trim();
+        if !mime.eq_ignore_ascii_case("application/x-www-form-urlencoded") {
+            return Err(RouteError::InvalidBody(format!(
+                "expected a form content type, got {content_type:?}"
+            )));
+        }
+
+        let mut body = Vec::new();
+        self.body_reader()
+            .read_to_end(&mut body)
+            .map_err(|e| RouteError::InvalidBody(e.to_string()))?;
+        let body = String::from_utf8(body)
+            .map_err(|_| RouteError::InvalidBody("form body is not valid UTF-8".to_owned()))?;
+        Ok(Query::from_body(&body))
+    }
+
+    /// Deserializes an `application/x-www-form-urlencoded` request body into any type that implements [`serde::Deserialize`].
+    /// Each key is a field, with its value parsed from a string into the field's type.
+    /// Repeated keys can be collected into a `Vec`, `Option` fields are `None` if their key is missing, and unit enums are matched by name.
+    /// If the form can't be parsed (See [`Request::form`]), or doesn't fit the type, a [`RouteError`] with the reason is returned.
+    ///
+    /// Only available with the `serde` feature.
+    /// ## Example
+    /// ```rust
+    /// # use afire::{Method, Response, Server};
+    /// # use serde::Deserialize;
+    /// # let mut server = Server::<()>::new("localhost", 8080);
+    /// #[derive(Deserialize)]
+    /// struct Signup {
+    ///     name: String,
+    ///     age: u8,
+    ///     newsletter: Option<bool>,
+    /// }
+    ///
+    /// server.route(Method::POST, "/signup", |req| {
+    ///     let signup = match req.form_as::<Signup>() {
+    ///         Ok(signup) => signup,
+    ///         Err(e) => return e.into(),
+    ///     };
+    ///
+    ///     Response::new().text(format!("Welcome, {} ({})", signup.name, signup.age))
+    /// });
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn form_as<T: serde::de::DeserializeOwned>(&self) -> std::result::Result<T, RouteError> {
+        crate::internal::encoding::form::from_query(&self.form()?)
+            .map_err(|e| RouteError::InvalidBody(e.to_string()))
+    }
+
+    /// Deserializes a JSON request body into any type that implements [`serde::Deserialize`].
+    /// The request must have a JSON `Content-Type` (`application/json` or a `+json` type).
+    /// If it doesn't, or the body isn't valid for the type, a [`RouteError`] with the reason is returned, which can be turned into a `400 Bad Request` response.
+    ///
+    /// Streamed bodies (See [`crate::Server::body_buffer_limit`]) are read from the socket.
+    /// Only available with the `serde` feature.
+    /// ## Example
+    /// ```rust
+    /// # use afire::{Method, Response, Server};
+    /// # use serde::{Deserialize, Serialize};
+    /// # let mut server = Server::<()>::new("localhost", 8080);
+    /// #[derive(Deserialize, Serialize)]
+    /// struct User {
+    ///     name: String,
+    ///     age: u8,
+    /// }
+    ///
+    /// server.route(Method::POST, "/user", |req| {
+    ///     let user = match req.json::<User>() {
+    ///         Ok(user) => user,
+    ///         Err(e) => return e.into(),
+    ///     };
+    ///
+    ///     Response::new().json(&user)
+    /// });
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn json<T: serde::de::DeserializeOwned>(&self) -> std::result::Result<T, RouteError> {
+        let content_type = self
+            .headers
+            .get(HeaderType::ContentType)
+            .unwrap_or_default();
+        let mime = content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        if mime != "application/json" && !mime.ends_with("+json") {
+            return Err(RouteError::InvalidBody(format!(
+                "expected a JSON content type, got {content_type:?}"
+            )));
+        }
+
+        serde_json::from_reader(self.body_reader())
+            .map_err(|e| RouteError::InvalidBody(e.to_string()))
+    }
+
+    /// Gets a reader over the request body.
+    /// For bodies larger than [`crate::Server::body_buffer_limit`], this reads the body straight from the socket, so large uploads can be processed without holding them in memory.
+    /// Otherwise it just reads from [`Request::body`].
+    ///
+    /// A streamed body can only be read once, and only one reader can be used at a time; any others will be empty.
+    /// Any of the body left unread after the response is sent is discarded.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use std::{fs::File, io};
+    /// # use afire::{Method, Response, Server};
+    /// # let mut server = Server::<()>::new("localhost", 8080);
+    /// server.route(Method::POST, "/upload", |req| {
+    ///     let mut file = File::create("upload.bin").unwrap();
+    ///     let written = io::copy(&mut req.body_reader(), &mut file).unwrap();
+    ///     Response::new().text(format!("Saved {written} bytes"))
+    /// });
+    /// ```
+    pub fn body_reader(&self) -> impl Read + '_ {
+        let stream = self
+            .body_stream
+            .try_borrow_mut()
+            .ok()
+            .and_then(|x| RefMut::filter_map(x, Option::as_mut).ok());
+
+        BodyReader {
+            buffered: &self.body,
+            stream,
+        }
+    }
+
+    /// Parses a `multipart/form-data` body as it is read, see [`MultipartStream`].
+    /// Unlike [`crate::multipart::MultipartData`], this works on streamed bodies (see [`crate::Server::body_buffer_limit`]), so uploaded files can be written out without keeping the whole upload in memory.
+    ///
+    /// Returns an error if the request isn't `multipart/form-data` or has no boundary.
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use std::{fs::File, io};
+    /// # use afire::{Method, Response, Server, Status};
+    /// # let mut server = Server::<()>::new("localhost", 8080);
+    /// server.route(Method::POST, "/upload", |req| {
+    ///     let parts = match req.multipart() {
+    ///         Ok(i) => i.max_part_size(100 * 1024 * 1024),
+    ///         Err(_) => return Response::new().status(Status::BadRequest),
+    ///     };
+    ///
+    ///     for part in parts {
+    ///         let mut part = match part {
+    ///             Ok(i) => i,
+    ///             Err(_) => return Response::new().status(Status::BadRequest),
+    ///         };
+    ///         if part.filename.is_some() {
+    ///             let mut file = File::create(format!("{}.bin", part.name)).unwrap();
+    ///             io::copy(&mut part, &mut file).unwrap();
+    ///         }
+    ///     }
+    ///
+    ///     Response::new().text("Uploaded")
+    /// });
+    /// ```
+    pub fn multipart(&self) -> std::result::Result<MultipartStream<'_>, MultipartError> {
+        let (_, boundary) = form_boundary(self)?;
+        Ok(MultipartStream::new(boundary, self.body_reader()))
+    }
+
+    /// Gets the trailers: headers sent after a `Transfer-Encoding: chunked` body.
+    /// They are empty if the body wasn't chunked or the client didn't send any.
+    ///
+    /// Trailers come after the body, so for a streamed body (See [`crate::Server::body_buffer_limit`]) this returns None until it has been read to the end with [`Request::body_reader`].
+    /// ## Example
+    /// ```rust
+    /// # use std::io::Read;
+    /// # use afire::{Method, Response, Server, Status};
+    /// # let mut server = Server::<()>::new("localhost", 8080);
+    /// server.route(Method::POST, "/upload", |req| {
+    ///     let mut body = Vec::new();
+    ///     req.body_reader().read_to_end(&mut body).unwrap();
+    ///
+    ///     let trailers = req.trailers().unwrap_or_default();
+    ///     match trailers.get("Checksum") {
+    ///         Some(checksum) => Response::new().text(format!("{} bytes, checksum {checksum}", body.len())),
+    ///         None => Response::new().status(Status::BadRequest).text("Missing checksum"),
+    ///     }
+    /// });
+    /// ```
+    pub fn trailers(&self) -> Option<Headers> {
+        let stream = self.body_stream.try_borrow().ok()?;
+        match &*stream {
+            Some(stream) => stream.trailers().map(|x| Headers(x.to_vec())),
+            None => Some(self.trailers.clone()),
+        }
+    }
+
+    /// Checks if the body was too large to buffer, and has to be read with [`Request::body_reader`].
+    pub fn body_streamed(&self) -> bool {
+        // The stream is only ever borrowed by a body reader
+        self.body_stream
+            .try_borrow()
+            .map(|x| x.is_some())
+            .unwrap_or(true)
+    }
+
+    /// Discards any of a streamed body the handler didn't read, so the next request can be read from the connection.
+    /// Bytes read past the end of the body are put back into `pending`, see [`Request::from_socket`].
+    /// Returns false if the connection should be closed instead.
+    pub(crate) fn finish_body(&self, limit: usize, pending: &mut Vec<u8>) -> bool {
+        match self.body_stream.try_borrow_mut() {
+            Ok(mut stream) => stream.as_mut().is_none_or(|x| x.discard(limit, pending)),
+            Err(_) => false,
+        }
+    }
+
+    /// Copies the request, for handlers that take it by value (See [`crate::Server::legacy_route`]).
+    /// A streamed body is not copied, so the copy's body is only what was buffered.
+    pub(crate) fn clone_buffered(&self) -> Self {
+        Self {
+            method: self.method,
+            path: self.path.clone(),
+            authority: self.authority.clone(),
+            version: self.version,
+            path_params: self.path_params.clone(),
+            route: self.route.clone(),
+            query: self.query.clone(),
+            headers: self.headers.clone(),
+            cookies: self.cookies.clone(),
+            body: self.body.clone(),
+            address: self.address,
+            extensions: self.extensions.clone(),
+            socket: self.socket.clone(),
+            raw_head: self.raw_head.clone(),
+            original_path: self.original_path.clone(),
+            peer_certificates: self.peer_certificates.clone(),
+            local_address: self.local_address,
+            connection_info: self.connection_info.clone(),
+            trailers: self.trailers.clone(),
+            body_stream: RefCell::new(None),
+            received: self.received,
+        }
+    }
+
+    /// Read a request from a socket.
+    /// `pending` holds bytes that were read from the socket but not used by the last request, and is updated with any read past the end of this one.
+    /// Bodies larger than the body buffer limit are left on the socket, to be read with [`Request::body_reader`].
+    pub(crate) fn from_socket(
+        raw_stream: Arc<Socket>,
+        pending: &mut Vec<u8>,
+        limits: ParseLimits,
+    ) -> Result<Self> {
+        let mut stream = raw_stream.force_lock();
 
         trace!(Level::Debug, "Reading header");
         let peer_addr = stream.peer_addr()?;
-        let mut reader = BufReader::new(&*stream);
+        let peer_certificates = stream.peer_certificates();
+        let local_address = stream.local_addr().ok();
+        let connection_info = stream.connection_info();
+        let mut reader = ConnReader::new(&mut **stream, pending);
+
+        // Waiting for the next request on a keep-alive connection is limited by the keep-alive timeout.
+        // The first request has to arrive within the head timeout of the connection opening, so idle connections can't hold a worker.
+        // Nothing of the request has been received if this times out, so there is no one waiting for a response.
+        let opened = Instant::now();
+        let first = raw_stream.requests() == 0;
+        let idle_timeout = match first {
+            true => limits.head_timeout.or(limits.keep_alive_timeout),
+            false => limits.keep_alive_timeout,
+        };
+        if let Some(timeout) = idle_timeout {
+            reader.set_deadline(Some(opened + timeout), limits.socket_timeout)?;
+        }
+        reader.fill_buf().map_err(|e| match Error::from(e) {
+            Error::Stream(StreamError::TimedOut) => StreamError::IdleTimeout.into(),
+            e => e,
+        })?;
+        if idle_timeout.is_some() {
+            reader.set_deadline(None, limits.socket_timeout)?;
+        }
+
+        // The head timeout starts once the client starts sending the request, or when the connection opened for its first request
+        if let Some(timeout) = limits.head_timeout {
+            let start = if first { opened } else { Instant::now() };
+            reader.set_deadline(Some(start + timeout), limits.socket_timeout)?;
+        }
+        // The request line can be a bit longer than the URI limit, to fit the method and version
+        let uri_line = limits.uri_length.map(|x| x + 32);
+        let line_limit = match (uri_line, limits.head_size) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+
         let mut request_line = Vec::with_capacity(BUFF_SIZE);
-        reader
-            .read_until(10, &mut request_line)
-            .map_err(|_| StreamError::UnexpectedEof)?;
+        if !read_line_limited(&mut reader, &mut request_line, line_limit)? {
+            return Err(match uri_line {
+                Some(uri) if line_limit == Some(uri) => ParseError::UriTooLong,
+                _ => ParseError::HeadTooLarge,
+            }
+            .into());
+        }
+        let received = Instant::now();
+        raw_stream.count_request();
 
-        let (method, path, query, version) = parse_request_line(&request_line)?;
+        let target = request_line.split(|x| x.is_ascii_whitespace()).nth(1);
+        if let (Some(target), Some(limit)) = (target, limits.uri_length) {
+            if target.len() > limit {
+                return Err(ParseError::UriTooLong.into());
+            }
+        }
+
+        let (method, authority, path, query, version) = parse_request_line(&request_line)?;
 
+        let mut raw_head = request_line;
         let mut headers = Vec::new();
         let mut cookies = Vec::new();
+        let mut header_count = 0;
         loop {
+            // Header lines can't be longer than the header size limit (plus the CRLF), or what's left of the head size limit
+            let head_left = limits.head_size.map(|x| x.saturating_sub(raw_head.len()));
+            let line_limit = match (limits.header_size.map(|x| x + 2), head_left) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+
             let mut buff = Vec::with_capacity(BUFF_SIZE);
-            reader
-                .read_until(10, &mut buff)
-                .map_err(|_| StreamError::UnexpectedEof)?;
+            if !read_line_limited(&mut reader, &mut buff, line_limit)? {
+                return Err(match head_left {
+                    Some(left) if line_limit == Some(left) => ParseError::HeadTooLarge,
+                    _ => ParseError::HeaderTooLarge,
+                }
+                .into());
+            }
+
+            raw_head.extend_from_slice(&buff);
             let line = String::from_utf8_lossy(&buff);
             if line.len() <= 2 {
                 break;
             }
 
+            header_count += 1;
+            if limits.header_count.is_some_and(|x| header_count > x) {
+                return Err(ParseError::TooManyHeaders.into());
+            }
+
             let header = Header::from_string(&line[..line.len() - 2])?;
             if header.name != HeaderType::Cookie {
                 headers.push(header);
@@ -133,31 +770,103 @@ impl Request {
             cookies.extend(Cookie::from_string(&header.value));
         }
 
-        let content_len = headers
-            .iter()
-            .find(|i| i.name == HeaderType::ContentLength)
-            .map(|i| i.value.parse::<usize>().unwrap_or(0))
-            .unwrap_or(0);
-        let mut body = vec![0; content_len];
+        if limits.head_timeout.is_some() {
+            reader.set_deadline(None, limits.socket_timeout)?;
+        }
 
-        if content_len > 0 {
-            reader
-                .read_exact(&mut body)
-                .map_err(|_| StreamError::UnexpectedEof)?;
+        let (chunked, content_len) = body_framing(&headers)?;
+
+        // Clients sending `Expect: 100-continue` wait for an interim response before sending the body
+        if let Some(expect) = headers.iter().find(|i| i.name == HeaderType::Expect) {
+            if !expect.value.trim().eq_ignore_ascii_case("100-continue") {
+                return Err(ParseError::ExpectationFailed.into());
+            }
+
+            if version == HttpVersion::Http11 && (chunked || content_len > 0) {
+                trace!(Level::Debug, "Sending 100 Continue");
+                write_all_deadline(reader.stream(), b"HTTP/1.1 100 Continue\r\n\r\n")?;
+            }
         }
 
+        let mut body = Vec::new();
+        let mut body_stream = None;
+        let mut trailers = Vec::new();
+        let body_limit = limits.body_buffer;
+        if chunked {
+            let limit = body_limit.unwrap_or(usize::MAX);
+            let mut decoder = ChunkedDecoder::new(limits.header_count, limits.head_size);
+            let mut buf = vec![0; CHUNK_SIZE];
+            loop {
+                let read = decoder
+                    .read(&mut reader, &mut buf)
+                    .map_err(|e| match e.kind() {
+                        io::ErrorKind::InvalidData => Error::Parse(ParseError::InvalidChunkedBody),
+                        io::ErrorKind::UnexpectedEof => StreamError::UnexpectedEof.into(),
+                        _ => e.into(),
+                    })?;
+                if read == 0 {
+                    trailers = decoder.into_trailers().unwrap_or_default();
+                    break;
+                }
+
+                body.extend_from_slice(&buf[..read]);
+                if body.len() <= limit && limits.body_size.is_some_and(|x| body.len() > x) {
+                    return Err(ParseError::BodyTooLarge.into());
+                }
+
+                if body.len() > limit {
+                    trace!(Level::Debug, "Streaming chunked body");
+                    let buffered = reader.take_buffered(usize::MAX);
+                    body_stream = Some(BodyStream::chunked(
+                        raw_stream.clone(),
+                        std::mem::take(&mut body),
+                        buffered,
+                        decoder,
+                    ));
+                    break;
+                }
+            }
+        } else if body_limit.is_some_and(|limit| content_len > limit) {
+            trace!(Level::Debug, "Streaming {} byte body", content_len);
+            let prefix = reader.take_buffered(content_len);
+            let remaining = content_len - prefix.len();
+            body_stream = Some(BodyStream::new(raw_stream.clone(), prefix, remaining));
+        } else if limits.body_size.is_some_and(|limit| content_len > limit) {
+            return Err(ParseError::BodyTooLarge.into());
+        } else if content_len > 0 {
+            // The body is read as it arrives, so a large Content-Length with no body behind it doesn't allocate anything
+            (&mut reader)
+                .take(content_len as u64)
+                .read_to_end(&mut body)?;
+            if body.len() != content_len {
+                return Err(StreamError::UnexpectedEof.into());
+            }
+        }
+
+        drop(reader);
         drop(stream);
         Ok(Self {
             method,
             path,
+            authority,
             version,
-            path_params: RefCell::new(Vec::new()),
+            path_params: OnceCell::new(),
+            route: OnceCell::new(),
             query,
             headers: Headers(headers),
             cookies: CookieJar(cookies),
             body: Arc::new(body),
             address: peer_addr,
+            extensions: TypeMap::new(),
             socket: raw_stream,
+            raw_head,
+            original_path: None,
+            peer_certificates,
+            local_address,
+            connection_info,
+            trailers: Headers(trailers),
+            body_stream: RefCell::new(body_stream),
+            received,
         })
     }
 }
@@ -167,19 +876,122 @@ impl Debug for Request {
         f.debug_struct("Request")
             .field("method", &self.method)
             .field("path", &self.path)
+            .field("authority", &self.authority)
             .field("version", &self.version)
-            .field("path_params", &self.path_params.borrow())
+            .field("path_params", &self.path_params.get())
+            .field("route", &self.route.get())
             .field("query", &self.query)
             .field("headers", &self.headers)
             .field("cookies", &*self.cookies)
             .field("body", &self.body)
             .field("address", &self.address)
+            .field("extensions", &self.extensions)
+            .field("socket", &self.socket.id)
             .finish()
     }
 }
 
-/// Parse a request line into a method, path, query, and version
-pub(crate) fn parse_request_line(bytes: &[u8]) -> Result<(Method, String, Query, String)> {
+/// The reader returned by [`Request::body_reader`].
+struct BodyReader<'a> {
+    buffered: &'a [u8],
+    stream: Option<RefMut<'a, BodyStream>>,
+}
+
+impl Read for BodyReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.buffered.is_empty() {
+            return self.buffered.read(buf);
+        }
+
+        match &mut self.stream {
+            Some(stream) => stream.read(buf),
+            None => Ok(0),
+        }
+    }
+}
+
+/// Works out how the end of the request body is found from its headers.
+/// Returns if the body is chunked, and its length if it isn't.
+///
+/// Requests that could be framed more than one way are rejected instead of guessing (RFC 9112 §6.3).
+/// A proxy in front of the server may have guessed differently, letting part of the body be read as another request (request smuggling).
+fn body_framing(headers: &[Header]) -> Result<(bool, usize)> {
+    let codings = headers
+        .iter()
+        .filter(|i| i.name == HeaderType::TransferEncoding)
+        .flat_map(|i| i.value.split(','))
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+        .collect::<Vec<_>>();
+    let lengths = headers
+        .iter()
+        .filter(|i| i.name == HeaderType::ContentLength)
+        .flat_map(|i| i.value.split(','))
+        .map(str::trim)
+        .collect::<Vec<_>>();
+
+    if let Some((last, rest)) = codings.split_last() {
+        // Chunked has to be the last coding, and can only be applied once
+        let is_chunked = |x: &&str| x.eq_ignore_ascii_case("chunked");
+        if !is_chunked(last) || rest.iter().any(is_chunked) || !lengths.is_empty() {
+            return Err(ParseError::InvalidTransferEncoding.into());
+        }
+        return Ok((true, 0));
+    }
+
+    // Repeated Content-Length headers are fine as long as they all have the same value
+    let mut content_len = None;
+    for i in lengths {
+        if i.is_empty() || !i.bytes().all(|x| x.is_ascii_digit()) {
+            return Err(ParseError::InvalidContentLength.into());
+        }
+
+        let len = i
+            .parse::<usize>()
+            .map_err(|_| ParseError::InvalidContentLength)?;
+        if content_len.is_some_and(|x| x != len) {
+            return Err(ParseError::InvalidContentLength.into());
+        }
+        content_len = Some(len);
+    }
+
+    Ok((false, content_len.unwrap_or(0)))
+}
+
+/// Reads a line, including the trailing newline.
+/// If the stream ends before the newline, [`StreamError::UnexpectedEof`] is returned so partial requests are not handled.
+fn read_line(reader: &mut impl BufRead, buf: &mut Vec<u8>) -> Result<()> {
+    match reader.read_until(b'\n', buf) {
+        Ok(_) if buf.ends_with(b"\n") => Ok(()),
+        Ok(_) => Err(StreamError::UnexpectedEof.into()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Reads a line like [`read_line`], but only up to `limit` bytes.
+/// Returns false if the line is longer than the limit.
+fn read_line_limited(
+    reader: &mut impl BufRead,
+    buf: &mut Vec<u8>,
+    limit: Option<usize>,
+) -> Result<bool> {
+    let Some(limit) = limit else {
+        read_line(reader, buf)?;
+        return Ok(true);
+    };
+
+    match reader.by_ref().take(limit as u64).read_until(b'\n', buf) {
+        Ok(_) if buf.ends_with(b"\n") => Ok(true),
+        Ok(_) if buf.len() >= limit => Ok(false),
+        Ok(_) => Err(StreamError::UnexpectedEof.into()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Parse a request line into a method, authority, path, query, and version
+pub(crate) fn parse_request_line(
+    bytes: &[u8],
+) -> Result<(Method, Option<String>, String, Query, HttpVersion)> {
     let request_line = String::from_utf8_lossy(bytes);
     let mut parts = request_line.split_whitespace();
 
@@ -189,13 +1001,53 @@ pub(crate) fn parse_request_line(bytes: &[u8]) -> Result<(Method, String, Query,
     };
     let method =
         Method::from_str(raw_method).map_err(|_| Error::Parse(ParseError::InvalidMethod))?;
-    let mut raw_path = match parts.next() {
-        Some(i) => i.chars(),
+    let (authority, target) = match parts.next() {
+        Some(i) => split_authority(method, i),
         None => return Err(Error::Parse(ParseError::NoVersion)),
     };
+    let (mut final_path, query) = parse_target(target);
+    if final_path.is_empty() && authority.is_some() && method != Method::CONNECT {
+        final_path.push('/');
+    }
 
-    let mut final_path = String::new();
-    let mut final_query = String::new();
+    let query = query.unwrap_or_else(|| Query::from_body(""));
+    let version = match parts.next() {
+        Some(i) => {
+            HttpVersion::from_str(i).map_err(|_| Error::Parse(ParseError::UnsupportedVersion))?
+        }
+        None => return Err(Error::Parse(ParseError::NoVersion)),
+    };
+
+    Ok((method, authority, final_path, query, version))
+}
+
+/// Splits the authority off an absolute-form (`http://host/path`) or authority-form (`host:port`, only used by CONNECT) request target.
+/// Returns the authority, if there is one, and the rest of the target to be parsed as the path and query.
+fn split_authority(method: Method, target: &str) -> (Option<String>, &str) {
+    if method == Method::CONNECT {
+        return (Some(target.to_owned()), "");
+    }
+
+    let rest = match target.split_once("://") {
+        Some((scheme, rest))
+            if scheme.eq_ignore_ascii_case("http") || scheme.eq_ignore_ascii_case("https") =>
+        {
+            rest
+        }
+        _ => return (None, target),
+    };
+
+    let end = rest.find(['/', '?']).unwrap_or(rest.len());
+    (Some(rest[..end].to_owned()), &rest[end..])
+}
+
+/// Splits a request target into its path and query.
+/// Backslashes are turned into slashes and repeated slashes are collapsed.
+/// The query is None if the target has no `?`.
+fn parse_target(target: &str) -> (String, Option<Query>) {
+    let mut raw_path = target.chars();
+    let mut path = String::new();
+    let mut query = None;
     let mut last_is_slash = false;
     while let Some(i) = raw_path.next() {
         match i {
@@ -205,24 +1057,18 @@ pub(crate) fn parse_request_line(bytes: &[u8]) -> Result<(Method, String, Query,
                 }
 
                 last_is_slash = true;
-                final_path.push('/');
+                path.push('/');
             }
             '?' => {
-                final_query.extend(raw_path);
+                query = Some(Query::from_body(raw_path.as_str()));
                 break;
             }
             _ => {
                 last_is_slash = false;
-                final_path.push(i);
+                path.push(i);
             }
         }
     }
 
-    let query = Query::from_body(&final_query);
-    let version = match parts.next() {
-        Some(i) => i.to_owned(),
-        None => return Err(Error::Parse(ParseError::NoVersion)),
-    };
-
-    Ok((method, final_path, query, version))
+    (path, query)
 }
@@ -1,17 +1,29 @@
+use std::any::Any;
 use std::cell::RefCell;
 use std::fmt::{self, Debug, Display, Formatter};
-use std::io::{ErrorKind, Read, Write};
-use std::net::TcpStream;
-use std::sync::{Arc, Mutex};
+use std::fs::File;
+use std::io::{self, ErrorKind, Read, Seek, Write};
+use std::path::Path;
+use std::sync::Arc;
 
 use crate::consts;
 use crate::header::{HeaderType, Headers};
 use crate::http::status::Status;
 use crate::{
-    error::Result, header::headers_to_string, internal::handle::Writeable, Content, Header,
-    SetCookie,
+    error::Result,
+    internal::{
+        handle::Writeable,
+        socket::{chunk_header, write_all_deadline, write_vectored_deadline, WriteBuffer},
+    },
+    mime,
+    multipart::MultipartBuilder,
+    transport::{Socket, Stream},
+    Content, Header, HeaderPolicy, HttpVersion, SetCookie,
 };
 
+#[cfg(all(feature = "sendfile", target_os = "linux"))]
+use crate::internal::socket::sendfile;
+
 /// Http Response
 #[derive(Debug)]
 pub struct Response {
@@ -34,8 +46,20 @@ pub struct Response {
     /// - Close: Set the Connection header to close and will close the connection after the response is sent.
     /// - End: End the connection without sending a response
     pub flag: ResponseFlag,
+
+    /// The header policy of the route that made this response, see [`crate::Route::header_policy`].
+    pub(crate) header_policy: Option<Arc<HeaderPolicy>>,
+
+    /// Called as the body is written, see [`Response::on_progress`].
+    pub(crate) on_progress: Option<Progress>,
+
+    /// If the body was set by the handler (like with [`Response::text`]), rather than being the default `OK` from [`Response::new`].
+    pub(crate) explicit_body: bool,
 }
 
+/// A callback set with [`Response::on_progress`].
+pub(crate) struct Progress(Box<dyn FnMut(u64, Option<u64>) -> bool + Send>);
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum ResponseFlag {
     /// No Flag
@@ -44,16 +68,30 @@ pub enum ResponseFlag {
     Close,
     /// End the connection without sending a response
     End,
+    /// Abort the connection with a TCP reset, without sending a response
+    Reset,
 }
 
 /// Response Data.
-/// Can be either a Static Vec<u8> or a Stream (impl [`Read`]).
-/// Static responses are sent in one go, while streams are sent in chunks (chunked transfer encoding).
+/// Can be either a Static Vec<u8>, a Stream (impl [`Read`]), a seekable stream or a File.
+/// Static responses are sent in one go, while streams and files are sent in chunks (chunked transfer encoding).
 pub enum ResponseBody {
     Static(Vec<u8>),
     Stream(Writeable),
+    /// A stream that can also seek (impl [`Read`] + [`Seek`]), sent from its current position to the end.
+    /// Unlike a normal stream, parts of it can be sent for range requests, see [`crate::extension::Range`].
+    RangedStream(Box<dyn ReadSeek + Send>),
+    /// A file, sent from its current position to the end.
+    /// With the `sendfile` feature on Linux, it is copied straight to the socket by the kernel, without being read into memory.
+    File(File),
 }
 
+/// A reader that can also seek.
+/// Implemented for everything that is [`Read`] + [`Seek`], used for [`ResponseBody::RangedStream`].
+pub trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek> ReadSeek for T {}
+
 impl Response {
     /// Create a new Blank Response
     ///
@@ -73,6 +111,24 @@ impl Response {
             headers: Default::default(),
             reason: None,
             flag: ResponseFlag::None,
+            header_policy: None,
+            on_progress: None,
+            explicit_body: false,
+        }
+    }
+
+    /// Creates a Response with the given status and no body.
+    /// Useful for body-less replies like `204 No Content` or `304 Not Modified`.
+    /// ## Example
+    /// ```rust
+    /// # use afire::{Response, Status};
+    /// Response::empty(Status::NoContent);
+    /// ```
+    pub fn empty(status: impl Into<Status>) -> Self {
+        Self {
+            status: status.into(),
+            data: ResponseBody::empty(),
+            ..Self::new()
         }
     }
 
@@ -84,6 +140,30 @@ impl Response {
         }
     }
 
+    /// Creates a new Response that aborts the connection with a TCP reset (RST), instead of sending anything.
+    /// Unlike [`Response::end`] or [`Response::close`], the connection is not closed gracefully, so the server doesn't keep any state about it after it is closed.
+    /// This is useful for cutting off abusive clients, like ones flagged by a rate limiter.
+    /// ## Example
+    /// ```rust
+    /// # use afire::{Request, Response, Middleware, middleware::MiddleResult};
+    /// struct BlockList(Vec<std::net::IpAddr>);
+    ///
+    /// impl Middleware for BlockList {
+    ///     fn pre(&self, req: &mut Request) -> MiddleResult {
+    ///         if self.0.contains(&req.address.ip()) {
+    ///             return MiddleResult::Send(Response::reset());
+    ///         }
+    ///         MiddleResult::Continue
+    ///     }
+    /// }
+    /// ```
+    pub fn reset() -> Self {
+        Self {
+            flag: ResponseFlag::Reset,
+            ..Default::default()
+        }
+    }
+
     /// Add a status code to a Response.
     /// This accepts [`Status`] as well as a [`u16`].
     /// ## Example
@@ -129,10 +209,93 @@ impl Response {
     pub fn text(self, text: impl Display) -> Self {
         Self {
             data: text.to_string().as_bytes().to_vec().into(),
+            explicit_body: true,
             ..self
         }
     }
 
+    /// Serializes a value to JSON as the body of the Response, and sets the `Content-Type` to JSON.
+    /// If the value can't be serialized (like a map with non-string keys), the status is set to `500 Internal Server Error` and the body to the error.
+    /// Only available with the `serde` feature.
+    /// ## Example
+    /// ```rust
+    /// # use afire::Response;
+    /// # use std::collections::HashMap;
+    /// let scores = HashMap::from([("alice", 10), ("bob", 7)]);
+    /// let response = Response::new().json(&scores);
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn json(self, value: &impl serde::Serialize) -> Self {
+        match serde_json::to_vec(value) {
+            Ok(i) => Self {
+                data: i.into(),
+                explicit_body: true,
+                ..self
+            }
+            .content(Content::JSON),
+            Err(e) => {
+                trace!(Level::Error, "Error serializing JSON response: {}", e);
+                Self {
+                    status: Status::InternalServerError,
+                    data: format!("Error serializing response: {e}")
+                        .into_bytes()
+                        .into(),
+                    explicit_body: true,
+                    ..self
+                }
+                .content(Content::TXT)
+            }
+        }
+    }
+
+    /// Add HTML as data to a Response, and set the `Content-Type` to HTML.
+    /// Like [`Response::text`], it accepts any type that implements Display, such as the output of a template.
+    /// ## Example
+    /// ```rust
+    /// # use afire::Response;
+    /// let response = Response::new()
+    ///     .html("<h1>Hello from afire!</h1>");
+    /// ```
+    pub fn html(self, html: impl Display) -> Self {
+        self.text(html).content(Content::HTML)
+    }
+
+    /// Streams a file as the body of the Response, with a `Content-Type` from its extension (See [`crate::mime`]).
+    /// Files with an unknown extension are sent as `application/octet-stream`.
+    /// The file is sent as a file body, like [`Response::stream`], so it can use `sendfile` with the `sendfile` feature.
+    ///
+    /// If the file doesn't exist, the status is set to `404 Not Found`, and if it can't be opened for another reason, `500 Internal Server Error`.
+    /// ## Example
+    /// ```rust,no_run
+    /// # use afire::{Response, Method, Server};
+    /// # let mut server = Server::<()>::new("localhost", 8080);
+    /// server.route(Method::GET, "/report", |_| {
+    ///     Response::new().file("reports/latest.pdf")
+    /// });
+    /// ```
+    pub fn file(self, path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                return self.status(Status::NotFound).text("Not Found");
+            }
+            Err(e) => {
+                trace!(Level::Error, "Error opening file {:?}: {}", path, e);
+                return self
+                    .status(Status::InternalServerError)
+                    .text("Internal Server Error");
+            }
+        };
+
+        let content_type = path
+            .extension()
+            .and_then(|x| mime::from_extension(&x.to_string_lossy()))
+            .map_or_else(|| "application/octet-stream".to_owned(), mime::content_type);
+        self.stream(file)
+            .header(HeaderType::ContentType, content_type)
+    }
+
     /// Add raw bytes as data to a Response.
     /// This response type is considered static and will be sent in one go, not chunked.
     /// ## Example
@@ -145,12 +308,28 @@ impl Response {
     pub fn bytes(self, bytes: &[u8]) -> Self {
         Self {
             data: bytes.to_vec().into(),
+            explicit_body: true,
             ..self
         }
     }
 
+    /// Add a `multipart/form-data` body to a Response.
+    /// This sets the Content-Type header, including the boundary from the builder.
+    /// See [`MultipartBuilder`] for an example.
+    pub fn multipart(self, body: MultipartBuilder) -> Self {
+        let content_type = body.content_type();
+        Self {
+            data: body.build().into(),
+            explicit_body: true,
+            ..self
+        }
+        .header(HeaderType::ContentType, content_type)
+    }
+
     /// Add a stream as data to a Response.
     /// This response type is considered dynamic and will be streamed to the client in chunks using `Transfer-Encoding: chunked`.
+    ///
+    /// If the stream is a [`File`], it is kept as a file body, so it can be sent with `sendfile` when the `sendfile` feature is enabled.
     /// ## Example
     /// ```rust,no_run
     /// # use afire::{Response, Method, Server};
@@ -164,8 +343,40 @@ impl Response {
     /// });
     /// ```
     pub fn stream(self, stream: impl Read + Send + 'static) -> Self {
+        let mut stream = Some(stream);
+        let data = match (&mut stream as &mut dyn Any).downcast_mut::<Option<File>>() {
+            Some(file) => ResponseBody::File(file.take().unwrap()),
+            None => ResponseBody::Stream(Box::new(RefCell::new(stream.unwrap()))),
+        };
+
         Self {
-            data: ResponseBody::Stream(Box::new(RefCell::new(stream))),
+            data,
+            explicit_body: true,
+            ..self
+        }
+    }
+
+    /// Add a seekable stream as data to a Response.
+    /// It is sent like [`Response::stream`], but as the stream can seek, the [`crate::extension::Range`] extension can send just the requested part of it.
+    /// This is useful for serving data from things like archives or block stores, which are not files but can still be read from any position.
+    /// ## Example
+    /// ```rust
+    /// # #[cfg(feature = "extensions")] {
+    /// # use afire::{Response, Method, Middleware, Server, extension::Range};
+    /// # use std::io::Cursor;
+    /// let mut server = Server::<()>::new("localhost", 8080);
+    /// Range.attach(&mut server);
+    ///
+    /// server.route(Method::GET, "/data", |_| {
+    ///     let data = Cursor::new(vec![0; 1024]);
+    ///     Response::new().ranged_stream(data)
+    /// });
+    /// # }
+    /// ```
+    pub fn ranged_stream(self, stream: impl Read + Seek + Send + 'static) -> Self {
+        Self {
+            data: ResponseBody::RangedStream(Box::new(stream)),
+            explicit_body: true,
             ..self
         }
     }
@@ -220,6 +431,7 @@ impl Response {
 
     /// Add a cookie to a response.
     /// The [`SetCookie`] will be converted to a [`Header`] and added to the Response.
+    /// An error is logged if the cookie breaks its prefix or SameSite rules, see [`SetCookie::validate`].
     /// ## Example
     /// ```
     /// # use afire::{Response, SetCookie};
@@ -229,6 +441,7 @@ impl Response {
     ///     .cookie(SetCookie::new("name2", "value2"));
     /// ```
     pub fn cookie(mut self, cookie: SetCookie) -> Self {
+        check_cookie(&cookie);
         self.headers
             .push(Header::new("Set-Cookie", cookie.to_string()));
         self
@@ -249,6 +462,7 @@ impl Response {
         let mut new = Vec::new();
 
         for c in cookie {
+            check_cookie(c);
             new.push(Header::new("Set-Cookie", c.to_string()));
         }
 
@@ -269,6 +483,40 @@ impl Response {
         self
     }
 
+    /// Sets a function to call as the body is written to the socket, with the number of bytes written so far and the total size if it is known.
+    /// Streamed bodies have no known total.
+    /// The byte counts are of the body as sent, so after any compression or other changes by middleware.
+    ///
+    /// Return `false` to stop sending the body, which closes the connection.
+    /// This can be used to enforce transfer quotas, or to update progress metrics for large downloads.
+    /// ## Example
+    /// ```rust
+    /// # use std::sync::{Arc, atomic::{AtomicU64, Ordering}};
+    /// # use afire::{Method, Response, Server};
+    /// # let mut server = Server::<()>::new("localhost", 8080);
+    /// let downloaded = Arc::new(AtomicU64::new(0));
+    /// server.route(Method::GET, "/download", move |_req| {
+    ///     let downloaded = downloaded.clone();
+    ///     let mut last = 0;
+    ///     Response::new()
+    ///         .bytes(&[0; 1024 * 1024])
+    ///         .on_progress(move |written, _total| {
+    ///             downloaded.fetch_add(written - last, Ordering::Relaxed);
+    ///             last = written;
+    ///             true
+    ///         })
+    /// });
+    /// ```
+    pub fn on_progress(
+        self,
+        on_progress: impl FnMut(u64, Option<u64>) -> bool + Send + 'static,
+    ) -> Self {
+        Self {
+            on_progress: Some(Progress(Box::new(on_progress))),
+            ..self
+        }
+    }
+
     /// Lets you modify the Response with a function before it is sent to the client.
     /// This can be used to have middleware that modifies the Response on specific routes.
     pub fn modifier(self, modifier: impl Fn(Response) -> Response) -> Self {
@@ -276,54 +524,137 @@ impl Response {
     }
 
     // TODO: Make crate local
-    /// Writes a Response to a TcpStream.
+    /// Writes a Response to a socket.
     /// Will take care of adding default headers and closing the connection if needed.
-    pub fn write(
+    pub fn write(&mut self, stream: Arc<Socket>, default_headers: &[Header]) -> Result<()> {
+        self.write_as(stream, default_headers, HttpVersion::Http11, false, &mut 0)
+    }
+
+    /// Writes a Response to a socket, for a client using the given HTTP version.
+    /// HTTP/1.0 clients don't support chunked encoding, so streamed bodies are sent as is with `Connection: close`, and the connection must be closed after.
+    /// If `head_only` is set, as for a `HEAD` request, the headers are the same but the body is not sent (or read, for streams).
+    /// The number of body bytes sent is stored in `sent`, which is set even if writing fails partway through.
+    pub(crate) fn write_as(
         &mut self,
-        stream: Arc<Mutex<TcpStream>>,
+        stream: Arc<Socket>,
         default_headers: &[Header],
+        version: HttpVersion,
+        head_only: bool,
+        sent: &mut u64,
     ) -> Result<()> {
-        // Add default headers to response
-        // Only the ones that aren't already in the response
-        for i in default_headers {
-            if !self.headers.has(&i.name) {
-                self.headers.push(i.clone());
-            }
+        self.add_default_headers(default_headers);
+
+        // 1xx, 204 and 304 responses can't have a body
+        if !body_allowed(self.status) {
+            self.strip_body();
         }
 
         let static_body = self.data.is_static();
+        let chunked = !static_body && version == HttpVersion::Http11;
 
         // Add content-length header to response if we are sending a static body
-        if static_body && !self.headers.has(HeaderType::ContentLength) {
+        if body_allowed(self.status) && static_body && !self.headers.has(HeaderType::ContentLength)
+        {
             self.headers.push(self.data.content_len());
         }
 
-        // Add Connection: close if response is set to close
-        if self.flag == ResponseFlag::Close && !self.headers.has(HeaderType::Connection) {
+        // Add Connection: close if response is set to close, or the end of the body is marked by closing
+        let closes = self.flag == ResponseFlag::Close || (!static_body && !chunked);
+        if closes && !self.headers.has(HeaderType::Connection) {
             self.headers.push(Header::new("Connection", "close"));
         }
 
-        if !static_body && !self.headers.has(HeaderType::TransferEncoding) {
+        if body_allowed(self.status) && chunked && !self.headers.has(HeaderType::TransferEncoding) {
             self.headers
                 .push(Header::new("Transfer-Encoding", "chunked"));
         }
 
-        // Convert the response to a string
-        let response = format!(
-            "HTTP/1.1 {} {}\r\n{}\r\n\r\n",
-            self.status.code(),
-            self.reason
-                .to_owned()
-                .unwrap_or_else(|| self.status.reason_phrase().to_owned()),
-            headers_to_string(&self.headers)
-        );
+        // Format the status line and headers into the connection's buffer
+        let buffer = stream.get_or_insert_data(WriteBuffer::new);
+        let mut head = buffer.lock();
+        let reason = match &self.reason {
+            Some(i) => i.as_str(),
+            None => self.status.reason_phrase(),
+        };
+        write!(head, "HTTP/1.1 {} {}\r\n", self.status.code(), reason)?;
+        for i in self.headers.iter() {
+            write!(head, "{i}\r\n")?;
+        }
+        head.extend_from_slice(b"\r\n");
 
         let mut stream = stream.lock().unwrap();
-        stream.write_all(response.as_bytes())?;
-        self.data.write(&mut stream)?;
+        if head_only {
+            write_all_deadline(&mut **stream, &head)?;
+            return Ok(());
+        }
+
+        self.data.write(
+            &mut **stream,
+            &head,
+            self.on_progress.as_mut(),
+            chunked,
+            sent,
+        )?;
 
         Ok(())
     }
+
+    /// Adds the default headers that aren't already in the response.
+    pub(crate) fn add_default_headers(&mut self, default_headers: &[Header]) {
+        for i in default_headers {
+            if !self.headers.has(&i.name) {
+                self.headers.push(i.clone());
+            }
+        }
+    }
+
+    /// Removes the body of a response that is not allowed to have one.
+    /// Logs a warning if the handler set a body, other than the default `OK` from [`Response::new`].
+    fn strip_body(&mut self) {
+        if self.has_explicit_body() {
+            trace!(
+                Level::Error,
+                "Removing body from {} response, as it can't have one",
+                self.status.code()
+            );
+        }
+
+        // A 304 can keep its Content-Length, as it refers to the body that would have been sent
+        let keep_len = self.status == Status::NotModified;
+        self.data = ResponseBody::empty();
+        self.headers.retain(|x| {
+            x.name != HeaderType::TransferEncoding
+                && (keep_len || x.name != HeaderType::ContentLength)
+        });
+    }
+
+    /// Checks if the handler set a body, rather than leaving the default one from [`Response::new`].
+    /// Bodies set directly through [`Response::data`] aren't tracked, so they only count if they are a stream.
+    fn has_explicit_body(&self) -> bool {
+        match &self.data {
+            ResponseBody::Static(data) => self.explicit_body && !data.is_empty(),
+            ResponseBody::Stream(_) | ResponseBody::RangedStream(_) | ResponseBody::File(_) => true,
+        }
+    }
+}
+
+/// Logs an error if a cookie would be rejected by browsers, see [`SetCookie::validate`].
+fn check_cookie(cookie: &SetCookie) {
+    if let Err(e) = cookie.validate() {
+        trace!(
+            Level::Error,
+            "Cookie `{}` will be rejected by browsers: {}",
+            cookie.cookie.name,
+            e
+        );
+    }
+}
+
+/// Checks if a response with the given status is allowed to have a body.
+/// Informational (1xx), `204 No Content` and `304 Not Modified` responses can't have one.
+fn body_allowed(status: Status) -> bool {
+    let code = status.code();
+    !(100..200).contains(&code) && code != 204 && code != 304
 }
 
 impl Default for Response {
@@ -338,7 +669,7 @@ impl ResponseBody {
     }
 
     /// Checks if the ResponseBody is static.
-    fn is_static(&self) -> bool {
+    pub(crate) fn is_static(&self) -> bool {
         matches!(self, ResponseBody::Static(_))
     }
 
@@ -352,30 +683,70 @@ impl ResponseBody {
         Header::new("Content-Length", len.to_string())
     }
 
-    /// Writes a ResponseBody to a TcpStream.
+    /// Writes the response `head` and then the ResponseBody to a socket.
     /// Either in one go if it is static or in chunks if it is a stream.
-    fn write(&mut self, stream: &mut TcpStream) -> Result<()> {
+    /// The head is sent in the same write as the first part of the body.
+    /// Streams are only sent with chunked encoding if `encode_chunks` is set, otherwise the connection has to be closed to mark the end of the body.
+    /// Static bodies are also written in chunks if there is a progress callback, so it is called more than once.
+    /// Each write must finish within the socket's write timeout, see [`write_all_deadline`].
+    /// The number of body bytes is counted in `written`, not including the head or chunk framing.
+    fn write(
+        &mut self,
+        stream: &mut dyn Stream,
+        head: &[u8],
+        mut progress: Option<&mut Progress>,
+        encode_chunks: bool,
+        written: &mut u64,
+    ) -> Result<()> {
+        let total = match self {
+            ResponseBody::Static(data) => Some(data.len() as u64),
+            ResponseBody::Stream(_) | ResponseBody::RangedStream(_) | ResponseBody::File(_) => None,
+        };
+        let chunked = progress.is_some();
+
+        *written = 0;
+        let mut report = |len: usize| -> Result<()> {
+            *written += len as u64;
+            let progress = match &mut progress {
+                Some(i) => i,
+                None => return Ok(()),
+            };
+
+            match (progress.0)(*written, total) {
+                true => Ok(()),
+                false => Err(io::Error::other("stopped by progress callback").into()),
+            }
+        };
+
         match self {
-            ResponseBody::Static(data) => stream.write_all(data)?,
+            ResponseBody::Static(data) => {
+                let chunk = match chunked {
+                    true => consts::CHUNK_SIZE,
+                    false => data.len().max(1),
+                };
+                let mut head = head;
+                for i in data.chunks(chunk) {
+                    write_vectored_deadline(stream, [head, i])?;
+                    head = &[];
+                    report(i.len())?;
+                }
+
+                // Empty bodies have no chunks to send the head with
+                write_all_deadline(stream, head)?;
+            }
             ResponseBody::Stream(data) => {
-                let data = data.get_mut();
-                loop {
-                    let mut chunk = vec![0; consts::CHUNK_SIZE];
-                    let read = match data.read(&mut chunk) {
-                        Ok(0) => break,
-                        Ok(n) => n,
-                        Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
-                        Err(e) => return Err(e.into()),
-                    };
-
-                    let mut section = format!("{read:X}\r\n").as_bytes().to_vec();
-                    section.extend(&chunk[..read]);
-                    section.extend(b"\r\n");
-
-                    stream.write_all(&section)?;
+                write_reader(stream, head, data.get_mut(), encode_chunks, &mut report)?
+            }
+            ResponseBody::RangedStream(data) => {
+                write_reader(stream, head, data, encode_chunks, &mut report)?
+            }
+            ResponseBody::File(file) => {
+                #[cfg(all(feature = "sendfile", target_os = "linux"))]
+                if let Some(socket) = stream.raw_fd() {
+                    return sendfile(stream, socket, head, file, encode_chunks, &mut report);
                 }
 
-                stream.write_all(b"0\r\n\r\n")?;
+                write_reader(stream, head, file, encode_chunks, &mut report)?
             }
         };
 
@@ -383,6 +754,43 @@ impl ResponseBody {
     }
 }
 
+/// Writes the response `head`, then everything from a reader to the socket, in chunks of [`consts::CHUNK_SIZE`].
+/// The chunks are sent with chunked encoding if `encode_chunks` is set.
+fn write_reader(
+    stream: &mut dyn Stream,
+    mut head: &[u8],
+    data: &mut dyn Read,
+    encode_chunks: bool,
+    report: &mut dyn FnMut(usize) -> Result<()>,
+) -> Result<()> {
+    let mut chunk = vec![0; consts::CHUNK_SIZE];
+    loop {
+        let read = match data.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+
+        if encode_chunks {
+            let mut size = [0; 20];
+            let size = chunk_header(read, &mut size);
+            write_vectored_deadline(stream, [head, size, &chunk[..read], b"\r\n"])?;
+        } else {
+            write_vectored_deadline(stream, [head, &chunk[..read]])?;
+        }
+
+        head = &[];
+        report(read)?;
+    }
+
+    let end: &[u8] = match encode_chunks {
+        true => b"0\r\n\r\n",
+        false => &[],
+    };
+    write_vectored_deadline(stream, [head, end])
+}
+
 impl From<Vec<u8>> for ResponseBody {
     fn from(x: Vec<u8>) -> Self {
         ResponseBody::Static(x)
@@ -395,11 +803,42 @@ impl From<Writeable> for ResponseBody {
     }
 }
 
+impl Debug for Progress {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("Progress")
+    }
+}
+
 impl Debug for ResponseBody {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
             Self::Static(arg) => f.debug_tuple("Static").field(arg).finish(),
             Self::Stream(_arg) => f.debug_tuple("Stream").finish(),
+            Self::RangedStream(_arg) => f.debug_tuple("RangedStream").finish(),
+            Self::File(arg) => f.debug_tuple("File").field(arg).finish(),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::Response;
+    use crate::Status;
+
+    #[test]
+    fn test_explicit_body() {
+        assert!(!Response::new().has_explicit_body());
+        assert!(!Response::empty(Status::NoContent).has_explicit_body());
+        assert!(!Response::new().status(304).has_explicit_body());
+
+        // A body that happens to match the default still counts
+        assert!(Response::new().text("OK").has_explicit_body());
+        assert!(Response::new().bytes(b"OK").has_explicit_body());
+        assert!(!Response::new().text("").has_explicit_body());
+        assert!(Response::new()
+            .stream(Cursor::new(Vec::new()))
+            .has_explicit_body());
+    }
+}
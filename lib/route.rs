@@ -2,20 +2,58 @@ use std::fmt::{self, Debug};
 use std::rc::Rc;
 use std::sync::Arc;
 
-use crate::{path::Path, Method, Request, Response};
+use crate::{
+    error::Result,
+    extract::Handler,
+    guard::Guard,
+    middleware::{self, MiddleResult, Middleware, ResponseInfo},
+    path::Path,
+    trace::emoji,
+    HeaderPolicy, HeaderType, Method, Request, Response, Server, Status,
+};
+
+#[cfg(feature = "openapi")]
+use crate::openapi::{Operation, Schema};
 
 type StatelessRoute = Box<dyn Fn(&Request) -> Response + Send + Sync>;
 type StatefulRoute<State> = Box<dyn Fn(Arc<State>, &Request) -> Response + Send + Sync>;
+#[cfg(feature = "async")]
+type AsyncRoute =
+    Box<dyn for<'a> Fn(&'a Request) -> crate::async_handler::BoxFuture<'a> + Send + Sync>;
 
 pub enum RouteType<State> {
     Stateless(StatelessRoute),
     Stateful(StatefulRoute<State>),
+    /// Run with the [`crate::Server::async_executor`].
+    #[cfg(feature = "async")]
+    Async(AsyncRoute),
+}
+
+/// Stored in the extensions of requests that matched a route with a [`Route::header_policy`].
+/// Responses written from the handler with a [`crate::response_writer::ResponseWriter`] use it, as they are sent before the route's policy would normally be applied.
+pub(crate) struct RoutePolicy(pub Arc<HeaderPolicy>);
+
+/// How a route treats a trailing slash on the request path.
+/// Set per route with [`Route::trailing_slash`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TrailingSlash {
+    /// `/users` and `/users/` both match the route.
+    /// This is the default.
+    #[default]
+    Ignore,
+    /// The request path must have a trailing slash only if the route's path does.
+    /// So a route defined as `/users` will not match `/users/`.
+    Exact,
+    /// Requests with a different trailing slash than the route's path are sent a `308 Permanent Redirect` to the route's form.
+    /// So with a route defined as `/users/`, a request to `/users?page=2` is redirected to `/users/?page=2`.
+    /// The redirect uses the path and query the client sent, from before any [`Request::rewrite_path`].
+    Redirect,
 }
 
 /// Defines a route.
 ///
-/// You should not use this directly.
-/// It will be created automatically when using [`crate::Server::route`] or [`crate::Server::stateful_route`].
+/// You should not need to make this directly.
+/// It will be created automatically when using [`crate::Server::route`] or [`crate::Server::stateful_route`], which return a reference to it for setting route options.
 #[derive(Debug)]
 pub struct Route<State> {
     /// Route Method (GET, POST, ANY, etc.)
@@ -24,8 +62,27 @@ pub struct Route<State> {
     /// Route path, in its tokenized form.
     path: Path,
 
+    /// If the route path was defined with a trailing slash.
+    slash: bool,
+
+    /// How to handle trailing slashes on requests.
+    trailing_slash: TrailingSlash,
+
     /// Route Handler, either stateless or stateful.
     pub(crate) handler: RouteType<State>,
+
+    /// Header policy applied to the route's responses, after the server's.
+    pub(crate) header_policy: Option<Arc<HeaderPolicy>>,
+
+    /// Middleware that only runs for this route, see [`Route::with`].
+    pub(crate) middleware: Vec<Box<dyn Middleware + Send + Sync>>,
+
+    /// Checks requests must pass for the route to match, see [`Route::guard`].
+    guards: Vec<Box<dyn Guard>>,
+
+    /// Documentation for the OpenAPI document, see [`crate::openapi`].
+    #[cfg(feature = "openapi")]
+    pub(crate) docs: Operation,
 }
 
 impl<State> Route<State> {
@@ -33,8 +90,15 @@ impl<State> Route<State> {
     pub(crate) fn new(method: Method, path: String, handler: StatelessRoute) -> Self {
         Self {
             method,
+            slash: has_slash(&path),
             path: Path::new(path),
+            trailing_slash: TrailingSlash::default(),
             handler: RouteType::Stateless(handler),
+            header_policy: None,
+            middleware: Vec::new(),
+            guards: Vec::new(),
+            #[cfg(feature = "openapi")]
+            docs: Operation::default(),
         }
     }
 
@@ -46,11 +110,181 @@ impl<State> Route<State> {
     ) -> Self {
         Self {
             method,
+            slash: has_slash(&path),
             path: Path::new(path),
+            trailing_slash: TrailingSlash::default(),
             handler: RouteType::Stateful(handler),
+            header_policy: None,
+            middleware: Vec::new(),
+            guards: Vec::new(),
+            #[cfg(feature = "openapi")]
+            docs: Operation::default(),
         }
     }
 
+    /// Create a new async route.
+    #[cfg(feature = "async")]
+    pub(crate) fn new_async(method: Method, path: String, handler: AsyncRoute) -> Self {
+        Self {
+            method,
+            slash: has_slash(&path),
+            path: Path::new(path),
+            trailing_slash: TrailingSlash::default(),
+            handler: RouteType::Async(handler),
+            header_policy: None,
+            middleware: Vec::new(),
+            guards: Vec::new(),
+            #[cfg(feature = "openapi")]
+            docs: Operation::default(),
+        }
+    }
+
+    /// Sets how the route handles a trailing slash on the request path.
+    /// By default, trailing slashes are ignored, see [`TrailingSlash`] for the options.
+    /// ## Example
+    /// ```rust
+    /// # use afire::{Server, Response, Method, TrailingSlash};
+    /// # let mut server = Server::<()>::new("localhost", 8080);
+    /// // Requests to `/users/` will be redirected to `/users`
+    /// server
+    ///     .route(Method::GET, "/users", |_req| Response::new().text("Users"))
+    ///     .trailing_slash(TrailingSlash::Redirect);
+    /// ```
+    pub fn trailing_slash(&mut self, trailing_slash: TrailingSlash) -> &mut Self {
+        self.trailing_slash = trailing_slash;
+        self
+    }
+
+    /// Sets a header policy for the route's responses.
+    /// It is applied after the server's [`crate::Server::header_policy`], so it can set headers the server policy removes.
+    /// ## Example
+    /// ```rust
+    /// # use afire::{Server, Response, Method, HeaderPolicy};
+    /// # let mut server = Server::<()>::new("localhost", 8080);
+    /// // Let this page be embedded by other sites
+    /// server
+    ///     .route(Method::GET, "/embed", |_req| Response::new().text("Embed"))
+    ///     .header_policy(HeaderPolicy::new().remove("X-Frame-Options"));
+    /// ```
+    pub fn header_policy(&mut self, header_policy: HeaderPolicy) -> &mut Self {
+        self.header_policy = Some(Arc::new(header_policy));
+        self
+    }
+
+    /// Attach middleware that only runs for this route.
+    /// Its pre hooks run after the server's pre middleware, once the route has been matched (so path parameters are available), and its post hooks run before the server's post middleware.
+    /// Route middleware is ordered by [`Middleware::priority`] like the server's, and its end hooks are not run.
+    ///
+    /// Note: The middleware's own [`Middleware::attach`] is not called.
+    /// ## Example
+    /// ```rust
+    /// # use afire::{Server, Response, Method, Middleware, middleware::MiddleResult, Request, Status};
+    /// struct Auth;
+    ///
+    /// impl Middleware for Auth {
+    ///     fn pre(&self, req: &mut Request) -> MiddleResult {
+    ///         if req.headers.has("Authorization") {
+    ///             return MiddleResult::Continue;
+    ///         }
+    ///         MiddleResult::Send(Response::new().status(Status::Unauthorized))
+    ///     }
+    /// }
+    ///
+    /// # let mut server = Server::<()>::new("localhost", 8080);
+    /// server.route(Method::GET, "/", |_req| Response::new().text("Public"));
+    /// server
+    ///     .route(Method::GET, "/admin", |_req| Response::new().text("Secret"))
+    ///     .with(Auth);
+    /// ```
+    pub fn with(&mut self, middleware: impl Middleware + Send + Sync + 'static) -> &mut Self {
+        trace!(
+            "{}Adding Middleware {} to route {} {}",
+            emoji("📦"),
+            std::any::type_name_of_val(&middleware),
+            self.method,
+            self.path.raw
+        );
+
+        middleware::insert(&mut self.middleware, Box::new(middleware));
+        self
+    }
+
+    /// Adds a guard, which requests have to pass for the route to match.
+    /// Requests that don't pass all of the route's guards can match other routes, or get a `404 Not Found` if none match.
+    /// See the [`crate::guard`] module for the built-in guards, and any `Fn(&Request) -> bool` can be used too.
+    /// ## Example
+    /// ```rust
+    /// # use afire::{Server, Response, Method, guard::{Host, HasQuery}};
+    /// # let mut server = Server::<()>::new("localhost", 8080);
+    /// server.route(Method::GET, "/", |_req| Response::new().text("Home"));
+    /// server
+    ///     .route(Method::GET, "/", |_req| Response::new().text("Admin"))
+    ///     .guard(Host("admin.example.com"));
+    /// server
+    ///     .route(Method::GET, "/search", |_req| Response::new().text("Beta search"))
+    ///     .guard(HasQuery("beta"))
+    ///     .guard(|req: &afire::Request| req.headers.has("Cookie"));
+    /// ```
+    pub fn guard(&mut self, guard: impl Guard + 'static) -> &mut Self {
+        self.guards.push(Box::new(guard));
+        self
+    }
+
+    /// Sets a short summary of what the route does, for the OpenAPI document.
+    /// Only available with the `openapi` feature, see the [`crate::openapi`] module.
+    #[cfg(feature = "openapi")]
+    pub fn summary(&mut self, summary: impl AsRef<str>) -> &mut Self {
+        self.docs.summary(summary.as_ref());
+        self
+    }
+
+    /// Sets a longer description of the route, which can use CommonMark, for the OpenAPI document.
+    /// Only available with the `openapi` feature, see the [`crate::openapi`] module.
+    #[cfg(feature = "openapi")]
+    pub fn description(&mut self, description: impl AsRef<str>) -> &mut Self {
+        self.docs.description(description.as_ref());
+        self
+    }
+
+    /// Adds a tag to the route, which Swagger UI uses to group routes.
+    /// Only available with the `openapi` feature, see the [`crate::openapi`] module.
+    #[cfg(feature = "openapi")]
+    pub fn tag(&mut self, tag: impl AsRef<str>) -> &mut Self {
+        self.docs.tag(tag.as_ref());
+        self
+    }
+
+    /// Sets the schema of the route's JSON request body, for the OpenAPI document.
+    /// Only available with the `openapi` feature, see the [`crate::openapi`] module.
+    #[cfg(feature = "openapi")]
+    pub fn request_body(&mut self, schema: impl Into<Schema>) -> &mut Self {
+        self.docs.request(schema.into());
+        self
+    }
+
+    /// Adds a response the route can send, with the schema of its JSON body if it has one, for the OpenAPI document.
+    /// Only available with the `openapi` feature, see the [`crate::openapi`] module.
+    #[cfg(feature = "openapi")]
+    pub fn response(&mut self, status: Status, schema: impl Into<Option<Schema>>) -> &mut Self {
+        self.docs.response(status, schema.into());
+        self
+    }
+
+    /// The method the route handles.
+    pub(crate) fn method(&self) -> Method {
+        self.method
+    }
+
+    /// The route's path, in its tokenized form.
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The path the route was defined with, like `/users/{id}`.
+    pub(crate) fn pattern(&self) -> String {
+        format!("/{}", self.path.raw)
+    }
+
     /// Checks if the route is stateful.
     pub(crate) fn is_stateful(&self) -> bool {
         matches!(self.handler, RouteType::Stateful(_))
@@ -58,12 +292,256 @@ impl<State> Route<State> {
 
     /// Checks if a Request matches the route.
     /// Returns the path parameters if it does.
-    pub(crate) fn matches(&self, req: Rc<Request>) -> Option<Vec<(String, String)>> {
+    pub(crate) fn matches(&self, req: &Request) -> Option<Vec<(String, String)>> {
         if self.method != Method::ANY && self.method != req.method {
             return None;
         }
-        self.path.match_path(req.path.clone())
+
+        self.matches_path(req)
+    }
+
+    /// Checks if a Request's path matches the route and it passes the route's guards, ignoring the method.
+    /// Returns the path parameters if it does.
+    pub(crate) fn matches_path(&self, req: &Request) -> Option<Vec<(String, String)>> {
+        if self.trailing_slash == TrailingSlash::Exact && has_slash(&req.path) != self.slash {
+            return None;
+        }
+
+        let params = self.path.match_path(req.path.clone())?;
+        self.guards.iter().all(|x| x.check(req)).then_some(params)
     }
+
+    /// If the route redirects to its trailing slash form and the request path is not in that form, returns the redirect response.
+    /// This should only be called on matching routes.
+    pub(crate) fn slash_redirect(&self, req: &Request) -> Option<Response> {
+        if self.trailing_slash != TrailingSlash::Redirect || has_slash(&req.path) == self.slash {
+            return None;
+        }
+
+        // The client doesn't know about any rewrites, so the redirect is to its own path and query
+        let mut location = req.original_path().trim_end_matches('/').to_owned();
+        if self.slash {
+            location.push('/');
+        }
+
+        if let Some(query) = req.raw_query().filter(|x| !x.is_empty()) {
+            location.push('?');
+            location.push_str(query);
+        }
+
+        Some(
+            Response::new()
+                .status(Status::PermanentRedirect)
+                .header(HeaderType::Location, location),
+        )
+    }
+}
+
+/// A group of routes that share a path prefix and middleware.
+/// Made with [`crate::Server::group`].
+///
+/// Routes added to the group have the group's prefix added to their path.
+/// Middleware attached to the group with [`Group::attach`] only runs for requests with a path under the prefix.
+/// ## Example
+/// ```rust
+/// # use afire::{Server, Response, Method, Middleware, middleware::MiddleResult, Request, Status};
+/// struct Auth;
+///
+/// impl Middleware for Auth {
+///     fn pre(&self, req: &mut Request) -> MiddleResult {
+///         if req.headers.has("Authorization") {
+///             return MiddleResult::Continue;
+///         }
+///         MiddleResult::Send(Response::new().status(Status::Unauthorized))
+///     }
+/// }
+///
+/// # let mut server = Server::<()>::new("localhost", 8080);
+/// server.group("/api/v1", |api| {
+///     api.attach(Auth);
+///
+///     // GET /api/v1/users
+///     api.route(Method::GET, "/users", |_req| Response::new().text("[]"));
+///     api.group("/users/{id}", |user| {
+///         // GET /api/v1/users/{id}/posts
+///         user.route(Method::GET, "/posts", |req| {
+///             Response::new().text(req.param("id").unwrap())
+///         });
+///     });
+/// });
+/// ```
+pub struct Group<'a, State: 'static + Send + Sync> {
+    server: &'a mut Server<State>,
+    prefix: String,
+}
+
+/// Middleware that only runs for requests with a path under a prefix.
+/// Used for middleware attached to a [`Group`].
+/// Requests that failed to parse have no path, so they are never in scope.
+struct Scoped {
+    prefix: Path,
+    middleware: Box<dyn Middleware + Send + Sync>,
+}
+
+impl<'a, State: 'static + Send + Sync> Group<'a, State> {
+    /// Creates a new group, with the prefix normalized to start with a slash and not end with one.
+    pub(crate) fn new(server: &'a mut Server<State>, prefix: &str) -> Self {
+        Self {
+            server,
+            prefix: join_path("", prefix),
+        }
+    }
+
+    /// Gets the group's path prefix, including the prefixes of any parent groups.
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// Create a new route in the group.
+    /// Is the same as [`crate::Server::route`], but the path is relative to the group's prefix.
+    pub fn route(
+        &mut self,
+        method: Method,
+        path: impl AsRef<str>,
+        handler: impl Fn(&Request) -> Response + Send + Sync + 'static,
+    ) -> &mut Route<State> {
+        let path = join_path(&self.prefix, path.as_ref());
+        self.server.route(method, path, handler)
+    }
+
+    /// Create a new stateful route in the group.
+    /// Is the same as [`crate::Server::stateful_route`], but the path is relative to the group's prefix.
+    pub fn stateful_route(
+        &mut self,
+        method: Method,
+        path: impl AsRef<str>,
+        handler: impl Fn(Arc<State>, &Request) -> Response + Send + Sync + 'static,
+    ) -> &mut Route<State> {
+        let path = join_path(&self.prefix, path.as_ref());
+        self.server.stateful_route(method, path, handler)
+    }
+
+    /// Create a new route with extractors in the group.
+    /// Is the same as [`crate::Server::extract_route`], but the path is relative to the group's prefix.
+    pub fn extract_route<Args>(
+        &mut self,
+        method: Method,
+        path: impl AsRef<str>,
+        handler: impl Handler<State, Args>,
+    ) -> &mut Route<State> {
+        let path = join_path(&self.prefix, path.as_ref());
+        self.server.extract_route(method, path, handler)
+    }
+
+    /// Create a new cached route in the group.
+    /// Is the same as [`crate::Server::cached_route`], but the path is relative to the group's prefix.
+    #[cfg(feature = "extensions")]
+    pub fn cached_route(
+        &mut self,
+        method: Method,
+        path: impl AsRef<str>,
+        ttl: std::time::Duration,
+        handler: impl Fn(&Request) -> Response + Send + Sync + 'static,
+    ) -> &mut Route<State> {
+        let path = join_path(&self.prefix, path.as_ref());
+        self.server.cached_route(method, path, ttl, handler)
+    }
+
+    /// Create a new async route in the group.
+    /// Is the same as [`crate::Server::route_async`], but the path is relative to the group's prefix.
+    #[cfg(feature = "async")]
+    pub fn route_async(
+        &mut self,
+        method: Method,
+        path: impl AsRef<str>,
+        handler: impl AsyncFn(&Request) -> Response + Send + Sync + 'static,
+    ) -> &mut Route<State> {
+        let path = join_path(&self.prefix, path.as_ref());
+        self.server.route_async(method, path, handler)
+    }
+
+    /// Create a nested group, with a prefix relative to this group's prefix.
+    /// Middleware attached to this group also runs for the nested group's routes.
+    pub fn group(&mut self, prefix: impl AsRef<str>, group: impl FnOnce(&mut Group<State>)) {
+        let prefix = join_path(&self.prefix, prefix.as_ref());
+        group(&mut Group::new(self.server, &prefix));
+    }
+
+    /// Attach middleware that only runs for requests with a path under the group's prefix.
+    /// It is added to the server's middleware, so it runs in the same order as other middleware, based on its priority and when it was attached.
+    ///
+    /// Note: The middleware's own [`Middleware::attach`] is not called.
+    pub fn attach(&mut self, middleware: impl Middleware + Send + Sync + 'static) {
+        trace!(
+            "{}Adding Middleware {} to group {}",
+            emoji("📦"),
+            std::any::type_name_of_val(&middleware),
+            self.prefix
+        );
+
+        self.server.insert_middleware(Box::new(Scoped {
+            prefix: Path::new(self.prefix.clone()),
+            middleware: Box::new(middleware),
+        }));
+    }
+}
+
+impl Scoped {
+    fn in_scope(&self, req: &Request) -> bool {
+        self.prefix.match_prefix(req.path.clone())
+    }
+}
+
+impl Middleware for Scoped {
+    fn priority(&self) -> i32 {
+        self.middleware.priority()
+    }
+
+    fn name(&self) -> &str {
+        self.middleware.name()
+    }
+
+    fn pre_raw(&self, req: &mut Result<Request>) -> MiddleResult {
+        match req {
+            Ok(i) if self.in_scope(i) => self.middleware.pre_raw(req),
+            _ => MiddleResult::Continue,
+        }
+    }
+
+    fn post_raw(&self, req: Result<Rc<Request>>, res: &mut Result<Response>) -> MiddleResult {
+        match &req {
+            Ok(i) if self.in_scope(i) => self.middleware.post_raw(req, res),
+            _ => MiddleResult::Continue,
+        }
+    }
+
+    fn end_raw(&self, req: &Result<Request>, res: &Result<Response>) {
+        if matches!(req, Ok(i) if self.in_scope(i)) {
+            self.middleware.end_raw(req, res);
+        }
+    }
+
+    fn end_with_info(&self, req: &Request, res: &Response, info: &ResponseInfo) {
+        if self.in_scope(req) {
+            self.middleware.end_with_info(req, res, info);
+        }
+    }
+}
+
+/// Joins a group prefix and a path, making sure there is exactly one slash between them.
+/// The trailing slash of `path` is kept, so routes can still use [`TrailingSlash::Exact`].
+fn join_path(prefix: &str, path: &str) -> String {
+    let prefix = prefix.trim_end_matches('/');
+    match path.trim_start_matches('/') {
+        "" if prefix.is_empty() => "/".to_owned(),
+        "" => prefix.to_owned(),
+        i => format!("{prefix}/{i}"),
+    }
+}
+
+/// Checks if a path ends with a slash, not counting the root path (`/`).
+fn has_slash(path: &str) -> bool {
+    path.len() > 1 && path.ends_with('/')
 }
 
 impl<State> Debug for RouteType<State> {
@@ -71,6 +549,112 @@ impl<State> Debug for RouteType<State> {
         match self {
             RouteType::Stateless(_) => f.write_str("stateless"),
             RouteType::Stateful(_) => f.write_str("stateful"),
+            #[cfg(feature = "async")]
+            RouteType::Async(_) => f.write_str("async"),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        guard::{HeaderEquals, Host, QueryEquals},
+        header::Headers,
+        middleware::{MiddleResult, Middleware},
+        testing::{TestRequest, TestServer},
+        Method, Request, Response, Server, Status,
+    };
+
+    /// Adds its name to the `X-Order` header, in both pre and post.
+    struct Order(&'static str);
+
+    fn push(headers: &mut Headers, value: String) {
+        match headers.get_mut("X-Order") {
+            Some(i) => i.push_str(&value),
+            None => headers.add("X-Order", value),
+        }
+    }
+
+    impl Middleware for Order {
+        fn pre(&self, req: &mut Request) -> MiddleResult {
+            push(&mut req.headers, format!("{}>", self.0));
+            MiddleResult::Continue
+        }
+
+        fn post(&self, req: &Request, res: &mut Response) -> MiddleResult {
+            push(&mut res.headers, format!("<{}", self.0));
+            res.headers
+                .add("X-Param", req.param("id").unwrap_or_default());
+            MiddleResult::Continue
+        }
+    }
+
+    struct Deny;
+
+    impl Middleware for Deny {
+        fn pre(&self, _req: &mut Request) -> MiddleResult {
+            MiddleResult::Send(Response::new().status(Status::Forbidden))
+        }
+    }
+
+    #[test]
+    fn test_route_middleware() {
+        let mut server = Server::<()>::new("localhost", 8080);
+        Order("global").attach(&mut server);
+        server
+            .route(Method::GET, "/users/{id}", |req| {
+                let order = req.headers.get("X-Order").unwrap_or_default();
+                Response::new().header("X-Order", format!("{order}handler"))
+            })
+            .with(Order("route"));
+        server.route(Method::GET, "/open", |_| Response::new());
+        server
+            .route(Method::GET, "/admin", |_| Response::new())
+            .with(Deny);
+        let server = TestServer::from(server);
+
+        let res = TestRequest::get("/users/5").send(&server);
+        assert_eq!(
+            res.header("X-Order"),
+            Some("global>route>handler<route<global")
+        );
+        assert_eq!(res.header("X-Param"), Some("5"));
+
+        let res = TestRequest::get("/open").send(&server);
+        assert_eq!(res.header("X-Order"), Some("<global"));
+
+        let res = TestRequest::get("/admin").send(&server);
+        assert_eq!(res.status, Status::Forbidden);
+    }
+
+    #[test]
+    fn test_route_guards() {
+        let mut server = Server::<()>::new("localhost", 8080).method_not_allowed(true);
+        server.route(Method::GET, "/", |_| Response::new().text("home"));
+        server
+            .route(Method::GET, "/", |_| Response::new().text("admin"))
+            .guard(Host("admin.example.com"))
+            .guard(HeaderEquals("X-Key", "secret"));
+        server
+            .route(Method::POST, "/beta", |_| Response::new().text("beta"))
+            .guard(QueryEquals("v", "2"));
+        let server = TestServer::from(server);
+
+        let admin = |host: &str, key: &str| {
+            TestRequest::get("/")
+                .header("Host", host)
+                .header("X-Key", key)
+                .send(&server)
+                .text()
+                .into_owned()
+        };
+        assert_eq!(admin("Admin.Example.com:8080", "secret"), "admin");
+        assert_eq!(admin("admin.example.com", "wrong"), "home");
+        assert_eq!(admin("example.com", "secret"), "home");
+
+        let res = TestRequest::post("/beta?v=2").send(&server);
+        assert_eq!(res.text(), "beta");
+        let res = TestRequest::post("/beta?v=1").send(&server);
+        assert_eq!(res.status, Status::NotFound);
+    }
+}
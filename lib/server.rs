@@ -1,18 +1,60 @@
 // Import STD libraries
 use std::any::type_name;
-use std::net::{IpAddr, SocketAddr, TcpListener};
+use std::net::{IpAddr, SocketAddr};
 use std::rc::Rc;
 use std::str;
-use std::sync::Arc;
-use std::time::Duration;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Condvar, Mutex, OnceLock,
+};
+use std::time::{Duration, Instant, SystemTime};
+
+#[cfg(feature = "tcp")]
+use std::{
+    io, iter,
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+    thread,
+};
 
 // Import local files
 use crate::{
-    error::Result, error::StartupError, handle::handle, header::Headers,
-    internal::common::ToHostAddress, thread_pool::ThreadPool, trace::emoji, Content, Header,
-    HeaderType, Method, Middleware, Request, Response, Route, Status, VERSION,
+    error::Result,
+    error::StartupError,
+    extract::Handler,
+    handle::handle,
+    header::Headers,
+    internal::common::{ForceLock, ToHostAddress},
+    internal::router::Router,
+    middleware::{self, StatefulMiddleware, WithState},
+    request::ParseLimits,
+    route::RoutePolicy,
+    thread_pool::{PoolStats, ThreadHook},
+    trace::emoji,
+    transport::Stream,
+    Content, Group, Header, HeaderType, Method, Middleware, Request, Response, Route, Status,
+    VERSION,
+};
+
+#[cfg(feature = "tcp")]
+use crate::{
+    handle::{handle_socket, handle_split, open_socket, SplitPools},
+    thread_pool::{Scaling, ThreadHooks, ThreadPool},
+    transport::Socket,
 };
 
+#[cfg(feature = "tls")]
+use crate::tls::TlsConfig;
+
+#[cfg(feature = "extensions")]
+use crate::extensions::cache::{cached_handler, CacheStore};
+
+#[cfg(feature = "async")]
+use crate::async_handler::{block_on, AsyncExecutor, BoxFuture};
+
+/// A function run once the server is listening, see [`Server::on_start`].
+pub type StartHook = Arc<dyn Fn(SocketAddr) + Send + Sync>;
+
 type ErrorHandler<State> =
     Box<dyn Fn(Option<Arc<State>>, &Box<Result<Rc<Request>>>, String) -> Response + Send + Sync>;
 
@@ -24,11 +66,25 @@ pub struct Server<State: 'static + Send + Sync = ()> {
     /// Ip address to listen on.
     pub ip: IpAddr,
 
+    /// An already bound listener to accept connections from, instead of binding [`Server::ip`] and [`Server::port`].
+    /// See [`Server::listener`].
+    #[cfg(feature = "tcp")]
+    listener: Option<TcpListener>,
+
+    /// More addresses to listen on, along with the main one, see [`Server::bind_also`].
+    #[cfg(feature = "tcp")]
+    also_bind: Vec<SocketAddr>,
+
     /// Routes to handle.
-    pub routes: Vec<Route<State>>,
+    /// Only changed through the route methods, which reset the router, see [`Server::routes`].
+    pub(crate) routes: Vec<Route<State>>,
+
+    /// Index of the route paths, built when the server starts and reset when a route is added.
+    router: OnceLock<Router>,
 
     // Other stuff
-    /// Middleware
+    /// Middleware, sorted by priority and run in reverse order.
+    /// Use [`Middleware::attach`] to add middleware in the right place, or [`Server::middleware_chain`] to see the order it will run in.
     pub middleware: Vec<Box<dyn Middleware + Send + Sync>>,
 
     /// Server wide App State
@@ -37,6 +93,10 @@ pub struct Server<State: 'static + Send + Sync = ()> {
     /// Default response for internal server errors
     pub error_handler: ErrorHandler<State>,
 
+    /// Headers to redact in the curl command logged when a handler panics, on top of `Authorization`, `Cookie` and `Proxy-Authorization`.
+    /// See [`Server::redact_header`].
+    pub redacted_headers: Vec<HeaderType>,
+
     /// Headers automatically added to every response.
     pub default_headers: Headers,
 
@@ -45,8 +105,363 @@ pub struct Server<State: 'static + Send + Sync = ()> {
     /// This is enabled by default.
     pub keep_alive: bool,
 
+    /// How long a keep-alive connection can sit idle waiting for its next request, see [`Server::keep_alive_timeout`].
+    /// Defaults to 60 seconds, None leaves it to the [`Server::socket_timeout`].
+    pub keep_alive_timeout: Option<Duration>,
+
+    /// The most requests that can be sent over one connection, see [`Server::keep_alive_max`].
+    /// By default there is no limit.
+    pub keep_alive_max: Option<u64>,
+
     /// Socket Timeout
     pub socket_timeout: Option<Duration>,
+
+    /// If small writes are sent right away instead of being held back to combine with later ones (`TCP_NODELAY`).
+    /// See [`Server::nodelay`].
+    /// Enabled by default.
+    pub nodelay: bool,
+
+    /// The largest request body that will be read into memory.
+    /// Larger bodies are left on the socket, to be read with [`Request::body_reader`].
+    /// By default all bodies are buffered, up to the [`Server::max_body_size`].
+    pub body_buffer_limit: Option<usize>,
+
+    /// The largest request body that will be read into memory before it is rejected, see [`Server::max_body_size`].
+    /// Defaults to 16 MiB, None allows any size.
+    pub max_body_size: Option<usize>,
+
+    /// The max number of headers a request can have, see [`Server::max_header_count`].
+    /// Defaults to 100, None allows any number.
+    pub max_header_count: Option<usize>,
+
+    /// The max length of a single header line in bytes, see [`Server::max_header_size`].
+    /// Defaults to 8 KiB, None allows any length.
+    pub max_header_size: Option<usize>,
+
+    /// The max length of the request line and headers together in bytes, see [`Server::max_head_size`].
+    /// Defaults to 64 KiB, None allows any length.
+    pub max_head_size: Option<usize>,
+
+    /// The max length of the request target (the path and query) in bytes, see [`Server::max_uri_length`].
+    /// Defaults to 8 KiB, None allows any length.
+    pub max_uri_length: Option<usize>,
+
+    /// How long a client has to send the whole request line and headers, see [`Server::head_timeout`].
+    /// Defaults to 30 seconds, None removes the limit.
+    pub head_timeout: Option<Duration>,
+
+    /// When the server was started.
+    /// This is set when the server starts listening, and can be cloned into route handlers or middleware to get the uptime.
+    pub start_time: StartTime,
+
+    /// The address the server is listening on.
+    /// This is set when the server starts listening, so it has the real port when bound to port `0`.
+    pub bound_addr: BoundAddr,
+
+    /// Run once the server is listening, with the address it is bound to, see [`Server::on_start`].
+    pub on_start: Option<StartHook>,
+
+    /// Live statistics about the server's thread pools.
+    /// Only updated when the server is started with [`Server::start_threaded`] or [`Server::start_split`].
+    pub pool_stats: PoolMetrics,
+
+    /// The max number of accepted connections that can be waiting for a worker thread.
+    /// Once the queue is full, new connections are handled with the [`Server::overflow_policy`].
+    /// Defaults to 1024.
+    pub queue_limit: usize,
+
+    /// What to do with new connections when the queue is full, or there are already [`Server::max_connections`] open.
+    /// Defaults to [`OverflowPolicy::Block`].
+    pub overflow_policy: OverflowPolicy,
+
+    /// The max number of connections that can be open at once, including ones waiting in the queue.
+    /// Once reached, new connections are handled with the [`Server::overflow_policy`].
+    /// By default there is no limit.
+    pub max_connections: Option<usize>,
+
+    /// If queued jobs should be taken from each client IP in turn, instead of in the order they arrived.
+    /// See [`Server::fair_scheduling`].
+    /// Disabled by default.
+    pub fair_scheduling: bool,
+
+    /// The most worker threads each pool can grow to when connections are waiting for a worker.
+    /// See [`Server::max_threads`].
+    /// By default the pools stay at the size they were started with.
+    pub max_threads: Option<usize>,
+
+    /// How long a worker added by [`Server::max_threads`] waits for work before stopping.
+    /// Defaults to 60 seconds.
+    pub idle_thread_timeout: Duration,
+
+    /// The start of the worker thread names, see [`Server::thread_prefix`].
+    /// Defaults to `afire`.
+    pub thread_prefix: String,
+
+    /// Run on each worker thread when it starts, see [`Server::thread_init`].
+    pub thread_init: Option<ThreadHook>,
+
+    /// Run on each worker thread when it stops, see [`Server::thread_teardown`].
+    pub thread_teardown: Option<ThreadHook>,
+
+    /// If requests to a path that only has routes for other methods get a `405 Method Not Allowed` with an `Allow` header, instead of a `404 Not Found`.
+    /// See [`Server::method_not_allowed`].
+    /// Disabled by default.
+    pub method_not_allowed: bool,
+
+    /// If `OPTIONS` requests to paths with routes get an automatic `204 No Content` with an `Allow` header.
+    /// See [`Server::auto_options`].
+    /// Disabled by default.
+    pub auto_options: bool,
+
+    /// If `HEAD` requests are answered by the `GET` routes, with the same headers and no body.
+    /// See [`Server::auto_head`].
+    /// Disabled by default.
+    pub auto_head: bool,
+
+    /// What reason phrase to put in the status line of responses.
+    /// Defaults to [`ReasonPhrase::Custom`].
+    pub reason_phrase: ReasonPhrase,
+
+    /// Headers to force onto or strip from every response, after the [`Server::default_headers`] are added.
+    /// See [`HeaderPolicy`].
+    pub header_policy: HeaderPolicy,
+
+    /// Runs the futures returned by async routes, see [`Server::async_executor`].
+    #[cfg(feature = "async")]
+    pub async_executor: AsyncExecutor,
+
+    /// Counts of why connections were closed.
+    /// Clones share the same counters, so this can be moved into route handlers before the server is started.
+    pub connection_stats: Arc<ConnectionStats>,
+
+    /// The responses cached by routes added with [`Server::cached_route`].
+    /// Use [`Server::cache`] to get a handle for purging them.
+    #[cfg(feature = "extensions")]
+    pub route_cache: CacheStore,
+}
+
+/// Counts of why the server's connections were closed, for diagnosing connection churn.
+/// See [`Server::connection_stats`].
+#[derive(Debug, Default)]
+pub struct ConnectionStats {
+    pub(crate) completed: AtomicUsize,
+    pub(crate) timed_out: AtomicUsize,
+    pub(crate) client_closed: AtomicUsize,
+    pub(crate) client_reset: AtomicUsize,
+    pub(crate) handler_closed: AtomicUsize,
+    pub(crate) aborted: AtomicUsize,
+    pub(crate) errored: AtomicUsize,
+    open: Mutex<usize>,
+    #[cfg(feature = "tcp")]
+    closed: Condvar,
+}
+
+/// Counts a connection as open until it is dropped, see [`ConnectionStats::open`].
+/// It is stored on the connection's [`Socket`], so connections taken over by a handler (like WebSockets) are counted until the socket is dropped.
+#[cfg(feature = "tcp")]
+pub(crate) struct OpenConnection(Arc<ConnectionStats>);
+
+/// Why a connection was closed, see [`ConnectionStats`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CloseReason {
+    Completed,
+    TimedOut,
+    ClientClosed,
+    ClientReset,
+    HandlerClosed,
+    Aborted,
+    Errored,
+}
+
+/// What reason phrase to send in the status line of a response (the `OK` in `HTTP/1.1 200 OK`).
+/// See [`Server::reason_phrase`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReasonPhrase {
+    /// Use the reason set with [`Response::reason`], or the canonical one for the status code if none was set.
+    #[default]
+    Custom,
+    /// Always use the canonical reason phrase for the status code, ignoring [`Response::reason`].
+    Canonical,
+    /// Leave the reason phrase out, like HTTP/2 does (`HTTP/1.1 200 `).
+    /// Clients must ignore the reason phrase, so this is valid HTTP/1.1.
+    Omit,
+}
+
+impl ReasonPhrase {
+    /// Applies the policy to a response before it is written.
+    pub(crate) fn apply(self, res: &mut Response) {
+        match self {
+            ReasonPhrase::Custom => {}
+            ReasonPhrase::Canonical => res.reason = None,
+            ReasonPhrase::Omit => res.reason = Some(String::new()),
+        }
+    }
+}
+
+/// The server config that is applied to every response right before it is written, see [`Server::finalize_response`].
+/// It is stored on each connection, so responses written from outside the server (with a [`crate::response_writer::ResponseWriter`]) are finalized the same way.
+pub(crate) struct ResponseFinish {
+    reason_phrase: ReasonPhrase,
+    default_headers: Headers,
+    header_policy: HeaderPolicy,
+}
+
+impl ResponseFinish {
+    /// Applies the reason phrase, default headers and header policies to a response, like [`Server::finalize_response`].
+    pub(crate) fn apply(&self, res: &mut Response) {
+        finalize_response(
+            self.reason_phrase,
+            &self.default_headers,
+            &self.header_policy,
+            res,
+        );
+    }
+
+    /// Applies to a response that is written outside of the server, like by a [`crate::response_writer::ResponseWriter`] or [`crate::middleware::Responder`].
+    /// The policy of the route the request matched is used too, unless the response already has one.
+    pub(crate) fn apply_for(&self, req: &Request, res: &mut Response) {
+        if res.header_policy.is_none() {
+            res.header_policy = req.extensions.get::<RoutePolicy>().map(|x| x.0.clone());
+        }
+        self.apply(res);
+    }
+}
+
+/// Shared by [`Server::finalize_response`] and [`ResponseFinish::apply`], so every way of writing a response gets the same headers.
+fn finalize_response(
+    reason_phrase: ReasonPhrase,
+    default_headers: &Headers,
+    header_policy: &HeaderPolicy,
+    res: &mut Response,
+) {
+    reason_phrase.apply(res);
+    res.add_default_headers(default_headers);
+    header_policy.apply(res);
+    if let Some(policy) = res.header_policy.take() {
+        policy.apply(res);
+    }
+}
+
+/// Rules for the headers of responses, applied right before they are written.
+/// Handlers and middleware can't get around these, so they are useful for security headers and hiding server details.
+///
+/// Headers are removed before the forced headers are set, so a header can be removed and then set to a new value.
+/// The framing headers (`Content-Length`, `Transfer-Encoding` and `Connection`) are added after the policy is applied, so they can't be changed with it.
+/// Header names are compared case-insensitively.
+///
+/// Set for the whole server with [`Server::header_policy`], or for a single route with [`Route::header_policy`].
+/// Route policies are applied after the server's.
+///
+/// ## Example
+/// ```rust
+/// # use afire::{Server, HeaderPolicy, HeaderType};
+/// let mut server = Server::<()>::new("localhost", 8080).header_policy(
+///     HeaderPolicy::new()
+///         // Don't send `Server: afire/x.y.z`
+///         .remove(HeaderType::Server)
+///         .remove("X-Powered-By")
+///         .set("X-Content-Type-Options", "nosniff")
+///         .set("Strict-Transport-Security", "max-age=63072000"),
+/// );
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct HeaderPolicy {
+    remove: Vec<HeaderType>,
+    set: Vec<Header>,
+}
+
+impl HeaderPolicy {
+    /// Creates an empty policy, which leaves responses unchanged.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Never send a header, no matter what the handler or [`Server::default_headers`] set it to.
+    pub fn remove(mut self, name: impl Into<HeaderType>) -> Self {
+        self.remove.push(name.into());
+        self
+    }
+
+    /// Always send a header with this value, replacing any value the handler set.
+    pub fn set(mut self, name: impl Into<HeaderType>, value: impl AsRef<str>) -> Self {
+        self.set.push(Header::new(name, value));
+        self
+    }
+
+    /// Applies the policy to a response before it is written.
+    pub(crate) fn apply(&self, res: &mut Response) {
+        let same = |a: &HeaderType, b: &HeaderType| {
+            a == b || a.to_string().eq_ignore_ascii_case(&b.to_string())
+        };
+
+        res.headers
+            .retain(|x| !self.remove.iter().any(|name| same(&x.name, name)));
+        for header in &self.set {
+            res.headers.retain(|x| !same(&x.name, &header.name));
+            res.headers.push(header.clone());
+        }
+    }
+}
+
+/// What to do with a new connection when the thread pool's queue is full, or the [`Server::max_connections`] limit is reached.
+/// See [`Server::overflow_policy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Stop accepting connections until there is room in the queue, or another connection closes.
+    /// New connections will wait in the OS's listen backlog.
+    #[default]
+    Block,
+    /// Respond with a `503 Service Unavailable` and close the connection.
+    Reject,
+    /// Close the connection without sending anything.
+    Drop,
+}
+
+/// When a server was started.
+/// Cloning this is cheap and every clone refers to the same server, so it can be moved into route handlers before the server is started.
+///
+/// ## Example
+/// ```rust
+/// # use afire::{Server, Method, Response};
+/// let mut server = Server::<()>::new("localhost", 8080);
+/// let start_time = server.start_time.clone();
+///
+/// server.route(Method::GET, "/uptime", move |_req| {
+///     let uptime = start_time.uptime().unwrap_or_default();
+///     Response::new().text(format!("Up for {} seconds", uptime.as_secs()))
+/// });
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct StartTime(Arc<OnceLock<(SystemTime, Instant)>>);
+
+/// The address a server is listening on, which is known once it has started.
+/// Like [`StartTime`], clones share the same address, so this can be cloned before the server is started and used to find the port it was bound to.
+///
+/// ## Example
+/// ```rust,no_run
+/// # #[cfg(feature = "tcp")] {
+/// # use std::thread;
+/// # use afire::Server;
+/// // Port 0 lets the OS pick a free port
+/// let server = Server::<()>::new("127.0.0.1", 0);
+/// let addr = server.bound_addr.clone();
+/// thread::spawn(move || server.start_threaded(4).unwrap());
+///
+/// println!("Listening on {}", addr.wait());
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct BoundAddr(Arc<(Mutex<Option<SocketAddr>>, Condvar)>);
+
+/// Live statistics about the server's thread pools.
+/// Like [`StartTime`], clones share the same statistics, so this can be moved into route handlers before the server is started.
+#[derive(Debug, Clone, Default)]
+pub struct PoolMetrics {
+    /// The pool that reads requests and writes responses.
+    /// When started with [`Server::start_threaded`], this is the only pool, so it runs the route handlers as well.
+    pub io: Arc<PoolStats>,
+    /// The pool that runs middleware and route handlers, when started with [`Server::start_split`].
+    pub handler: Arc<PoolStats>,
 }
 
 /// Implementations for Server
@@ -66,7 +481,12 @@ impl<State: Send + Sync> Server<State> {
         Server {
             port,
             ip: raw_ip.to_address().unwrap(),
+            #[cfg(feature = "tcp")]
+            listener: None,
+            #[cfg(feature = "tcp")]
+            also_bind: Vec::new(),
             routes: Vec::new(),
+            router: OnceLock::new(),
             middleware: Vec::new(),
 
             error_handler: Box::new(|_state, _req, err| {
@@ -75,10 +495,44 @@ impl<State: Send + Sync> Server<State> {
                     .text(format!("Internal Server Error :/\nError: {err}"))
                     .content(Content::TXT)
             }),
+            redacted_headers: Vec::new(),
 
             default_headers: Headers(vec![Header::new("Server", format!("afire/{VERSION}"))]),
             keep_alive: true,
             socket_timeout: None,
+            nodelay: true,
+            keep_alive_timeout: Some(Duration::from_secs(60)),
+            keep_alive_max: None,
+            body_buffer_limit: None,
+            max_body_size: Some(16 * 1024 * 1024),
+            max_header_count: Some(100),
+            max_header_size: Some(8 * 1024),
+            max_head_size: Some(64 * 1024),
+            max_uri_length: Some(8 * 1024),
+            head_timeout: Some(Duration::from_secs(30)),
+            start_time: StartTime::default(),
+            bound_addr: BoundAddr::default(),
+            on_start: None,
+            pool_stats: PoolMetrics::default(),
+            queue_limit: 1024,
+            overflow_policy: OverflowPolicy::Block,
+            max_connections: None,
+            fair_scheduling: false,
+            max_threads: None,
+            idle_thread_timeout: Duration::from_secs(60),
+            thread_prefix: "afire".to_owned(),
+            thread_init: None,
+            thread_teardown: None,
+            method_not_allowed: false,
+            auto_options: false,
+            auto_head: false,
+            reason_phrase: ReasonPhrase::Custom,
+            header_policy: HeaderPolicy::default(),
+            connection_stats: Arc::default(),
+            #[cfg(feature = "extensions")]
+            route_cache: CacheStore::default(),
+            #[cfg(feature = "async")]
+            async_executor: Box::new(|future| block_on(future)),
             state: None,
         }
     }
@@ -99,14 +553,13 @@ impl<State: Send + Sync> Server<State> {
     /// // This is blocking
     /// server.start().unwrap();
     /// ```
+    #[cfg(feature = "tcp")]
     pub fn start(&self) -> Result<()> {
         trace!("{}Starting Server [{}:{}]", emoji("✨"), self.ip, self.port);
         self.check()?;
 
-        let listener = TcpListener::bind(SocketAddr::new(self.ip, self.port))?;
-
-        for event in listener.incoming() {
-            handle(event?, self);
+        for event in self.listen()? {
+            handle(Box::new(event?), self);
         }
 
         // We should never get Here
@@ -131,6 +584,7 @@ impl<State: Send + Sync> Server<State> {
     /// // This is blocking
     /// server.start_threaded(4).unwrap();
     /// ```
+    #[cfg(feature = "tcp")]
     pub fn start_threaded(self, threads: usize) -> Result<()> {
         trace!(
             "{}Starting Server [{}:{}] ({} threads)",
@@ -139,21 +593,264 @@ impl<State: Send + Sync> Server<State> {
             self.port,
             threads
         );
+        self.run_threaded(threads, |stream, _| Some(Box::new(stream)))
+    }
+
+    /// Start the server with a threadpool of `threads` threads, serving HTTPS with the certificate in `tls`.
+    /// The TLS handshake runs on the worker threads, and connections that fail it are closed.
+    /// See the [`crate::tls`] module for more details.
+    ///
+    /// Just like [`Server::start`], this is blocking.
+    /// Will return an error if the server cant bind to the specified address, or of you are using stateful routes and have not set the state. (See [`Server::state`])
+    ///
+    /// ## Example
+    /// ```rust,no_run
+    /// # use afire::{Server, tls::TlsConfig};
+    /// // Creates a server on localhost (127.0.0.1) port 8443
+    /// let mut server = Server::<()>::new("localhost", 8443);
+    ///
+    /// /* Define Routes, Attach Middleware, etc. */
+    ///
+    /// // Starts the server with 4 threads
+    /// // This is blocking
+    /// let tls = TlsConfig::from_files("cert.pem", "key.pem").unwrap();
+    /// server.start_tls(4, tls).unwrap();
+    /// ```
+    #[cfg(feature = "tls")]
+    pub fn start_tls(self, threads: usize, tls: TlsConfig) -> Result<()> {
+        trace!(
+            "{}Starting Server [https://{}:{}] ({} threads)",
+            emoji("✨"),
+            self.ip,
+            self.port,
+            threads
+        );
+
+        self.run_threaded(threads, move |stream, this| {
+            let addr = stream.peer_addr();
+            match tls.accept(stream, this.head_timeout, this.socket_timeout) {
+                Ok(stream) => Some(Box::new(stream)),
+                Err(e) => {
+                    trace!(Level::Debug, "TLS handshake with {:?} failed: {}", addr, e);
+                    None
+                }
+            }
+        })
+    }
+
+    /// Accepts connections and handles them on a threadpool.
+    /// `accept` is run on the worker thread to turn each socket into a [`Stream`], or None if it should be closed.
+    #[cfg(feature = "tcp")]
+    fn run_threaded(
+        self,
+        threads: usize,
+        accept: impl Fn(TcpStream, &Self) -> Option<Box<dyn Stream>> + Send + Sync + 'static,
+    ) -> Result<()> {
+        self.check()?;
+
+        let incoming = self.listen()?;
+        let pool = ThreadPool::new(
+            &format!("{}-worker", self.thread_prefix),
+            self.pool_scaling(threads),
+            self.thread_hooks(),
+            self.fair_scheduling,
+            self.pool_stats.io.clone(),
+        );
+        let this = Arc::new(self);
+        let accept = Arc::new(accept);
+        let mut saturation = Saturation::default();
+
+        for event in incoming {
+            let Some((stream, open)) = this.admit(event, &pool, &mut saturation) else {
+                continue;
+            };
+
+            let this = this.clone();
+            let accept = accept.clone();
+            let client = stream.peer_addr().ok().map(|x| x.ip());
+            pool.execute(client, move || {
+                if let Some(stream) = accept(stream, &this) {
+                    let stream = open_socket(stream, &this);
+                    stream.insert_data(open);
+                    handle_socket(stream, &this);
+                }
+            });
+        }
+
+        // We should never get Here
+        unreachable!()
+    }
+
+    /// Start the server with two thread pools: `io_threads` threads that read requests and write responses, and `handler_threads` threads that run middleware and route handlers.
+    /// With [`Server::start_threaded`], a slow route handler keeps its socket's thread busy, so under load, reading and writing other sockets has to wait.
+    /// Splitting the pools keeps slow handlers from starving socket reads and writes, which makes latency more consistent for mixed workloads.
+    /// The queue length and number of busy threads in each pool can be read from [`Server::pool_stats`].
+    ///
+    /// Note that sockets are still blocking, so an idle keep-alive connection holds an io thread while it waits for the next request.
+    ///
+    /// Just like [`Server::start`], this is blocking.
+    /// Will return an error if the server cant bind to the specified address, or of you are using stateful routes and have not set the state. (See [`Server::state`])
+    ///
+    /// ## Example
+    /// ```rust,no_run
+    /// # use afire::Server;
+    /// // Creates a server on localhost (127.0.0.1) port 8080
+    /// let mut server = Server::<()>::new("localhost", 8080);
+    ///
+    /// /* Define Routes, Attach Middleware, etc. */
+    ///
+    /// // Starts the server with 4 io threads and 16 handler threads
+    /// // This is blocking
+    /// server.start_split(4, 16).unwrap();
+    /// ```
+    #[cfg(feature = "tcp")]
+    pub fn start_split(self, io_threads: usize, handler_threads: usize) -> Result<()> {
+        trace!(
+            "{}Starting Server [{}:{}] ({} io threads, {} handler threads)",
+            emoji("✨"),
+            self.ip,
+            self.port,
+            io_threads,
+            handler_threads
+        );
         self.check()?;
 
-        let listener = TcpListener::bind(SocketAddr::new(self.ip, self.port))?;
-        let pool = ThreadPool::new(threads);
+        let incoming = self.listen()?;
+        let pools = Arc::new(SplitPools {
+            io: ThreadPool::new(
+                &format!("{}-io", self.thread_prefix),
+                self.pool_scaling(io_threads),
+                self.thread_hooks(),
+                self.fair_scheduling,
+                self.pool_stats.io.clone(),
+            ),
+            handler: ThreadPool::new(
+                &format!("{}-handler", self.thread_prefix),
+                self.pool_scaling(handler_threads),
+                self.thread_hooks(),
+                self.fair_scheduling,
+                self.pool_stats.handler.clone(),
+            ),
+        });
         let this = Arc::new(self);
+        let mut saturation = Saturation::default();
+
+        for event in incoming {
+            let Some((stream, open)) = this.admit(event, &pools.io, &mut saturation) else {
+                continue;
+            };
 
-        for event in listener.incoming() {
             let this = this.clone();
-            pool.execute(move || handle(event.unwrap(), &this));
+            let pools_ref = pools.clone();
+            let client = stream.peer_addr().ok().map(|x| x.ip());
+            pools.io.execute(client, move || {
+                let stream = open_socket(Box::new(stream), &this);
+                stream.insert_data(open);
+                handle_split(stream, Vec::new(), this, pools_ref)
+            });
         }
 
         // We should never get Here
         unreachable!()
     }
 
+    /// Handles a connection from a custom transport, for runtimes that don't use afire's TCP event loop (See [`crate::transport`]).
+    /// Requests are read from the stream and run through the middleware and routes until the connection is closed, so this blocks the current thread.
+    /// Will return an error if you are using stateful routes and have not set the state. (See [`Server::state`])
+    ///
+    /// ## Example
+    /// ```rust,no_run
+    /// # #[cfg(feature = "tcp")] {
+    /// # use std::{sync::Arc, thread};
+    /// # use afire::{Server, Response, Method};
+    /// # fn accept() -> std::net::TcpStream { unimplemented!() }
+    /// let mut server = Server::<()>::new("localhost", 8080);
+    /// server.route(Method::GET, "/", |_req| Response::new().text("Hello"));
+    /// let server = Arc::new(server);
+    ///
+    /// loop {
+    ///     // Get a connection from your transport
+    ///     let stream = accept();
+    ///     let server = server.clone();
+    ///     thread::spawn(move || server.handle_stream(stream).unwrap());
+    /// }
+    /// # }
+    /// ```
+    pub fn handle_stream(&self, stream: impl Stream + 'static) -> Result<()> {
+        self.check()?;
+        self.start_time.set();
+        handle(Box::new(stream), self);
+        Ok(())
+    }
+
+    /// Accept connections from a listener that is already bound, instead of binding [`Server::ip`] and [`Server::port`] when the server starts.
+    /// The ip and port are set to the listener's address, so binding to port `0` and then reading [`Server::port`] gets the port the OS picked.
+    /// This is useful for tests, and for sockets passed in by a supervisor process.
+    /// ## Example
+    /// ```rust
+    /// # use std::net::TcpListener;
+    /// # use afire::Server;
+    /// let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    /// let server = Server::<()>::new("localhost", 8080).listener(listener);
+    /// assert_ne!(server.port, 0);
+    /// ```
+    #[cfg(feature = "tcp")]
+    pub fn listener(self, listener: TcpListener) -> Self {
+        let (ip, port) = match listener.local_addr() {
+            Ok(addr) => (addr.ip(), addr.port()),
+            Err(_) => (self.ip, self.port),
+        };
+        trace!("{}Using existing listener [{}:{}]", emoji("👂"), ip, port);
+
+        Server {
+            ip,
+            port,
+            listener: Some(listener),
+            ..self
+        }
+    }
+
+    /// Listen on another address as well as [`Server::ip`] and [`Server::port`], like an IPv6 address alongside an IPv4 one, or a second port.
+    /// Connections from every address share the same routes, middleware and thread pools.
+    /// Can be called more than once to listen on more addresses.
+    ///
+    /// [`Server::bound_addr`] is the main address, and [`Server::on_start`] is run for each address.
+    /// Will <u>panic</u> if the host is not a valid address.
+    /// ## Example
+    /// ```rust
+    /// # use std::net::Ipv6Addr;
+    /// # use afire::Server;
+    /// let server = Server::<()>::new("127.0.0.1", 8080)
+    ///     .bind_also(Ipv6Addr::LOCALHOST, 8080)
+    ///     .bind_also("127.0.0.1", 8081);
+    /// ```
+    #[cfg(feature = "tcp")]
+    pub fn bind_also(self, host: impl ToHostAddress, port: u16) -> Self {
+        let addr = SocketAddr::new(host.to_address().unwrap(), port);
+        trace!("{}Also listening on {}", emoji("👂"), addr);
+
+        let mut also_bind = self.also_bind;
+        also_bind.push(addr);
+        Server { also_bind, ..self }
+    }
+
+    /// Accept connections from the socket passed in by systemd socket activation, if there is one.
+    /// Otherwise, the server binds [`Server::ip`] and [`Server::port`] as normal, so the same binary can be run with or without a systemd socket unit.
+    /// Only the first socket is used, and only if the `LISTEN_PID` and `LISTEN_FDS` environment variables are for this process.
+    /// Only available on unix with the `systemd` feature.
+    /// ## Example
+    /// ```rust,no_run
+    /// # use afire::Server;
+    /// let server = Server::<()>::new("localhost", 8080).systemd_listener();
+    /// ```
+    #[cfg(all(feature = "systemd", unix))]
+    pub fn systemd_listener(self) -> Self {
+        match crate::internal::common::systemd_listener() {
+            Some(listener) => self.listener(listener),
+            None => self,
+        }
+    }
+
     /// Add a new default header to the server.
     /// This will be added to every response if it is not already present.
     ///
@@ -204,95 +901,723 @@ impl<State: Send + Sync> Server<State> {
         }
     }
 
-    /// Set the keep alive state of the server.
-    /// This will determine if the server will keep the connection alive after a request.
-    /// By default this is true.
-    /// If you aren't using a threadpool, you may want to set this to false.
+    /// Set if small writes are sent to the client right away (`TCP_NODELAY`).
+    /// afire writes each part of a response (like the head and body of a static response) in one go, so holding back small writes to combine them (Nagle's algorithm) only adds latency.
+    /// Enabled by default.
     /// ## Example
     /// ```rust
     /// # use afire::Server;
-    /// // Create a server for localhost on port 8080
     /// let mut server = Server::<()>::new("localhost", 8080)
-    ///     // Disable Keep Alive
-    ///     .keep_alive(false);
+    ///     .nodelay(false);
     /// ```
-    pub fn keep_alive(self, keep_alive: bool) -> Self {
-        trace!("{}Setting Keep Alive to {}", emoji("🔁"), keep_alive);
+    pub fn nodelay(self, nodelay: bool) -> Self {
+        trace!("{}Setting Nodelay to {}", emoji("🐢"), nodelay);
 
-        Server { keep_alive, ..self }
+        Server { nodelay, ..self }
     }
 
-    /// Set the state of a server.
-    /// The state will be available to stateful routes ([`Server::stateful_route`]) and middleware.
-    /// It is not mutable, so you will need to use an atomic or sync type to mutate it.
+    /// Set the largest request body that will be read into memory before calling the route handler.
+    /// Larger bodies are streamed from the socket with [`Request::body_reader`] instead, so [`Request::body`] will be empty.
+    /// Middleware that read the body (like form parsers) won't see streamed bodies.
+    /// By default all bodies are buffered, up to the [`Server::max_body_size`].
     ///
     /// ## Example
-    /// ```rust,no_run
-    /// # use afire::{Server, Response, Method};
-    /// # use std::sync::atomic::{AtomicU32, Ordering};
+    /// ```rust
+    /// # use afire::Server;
     /// // Create a server for localhost on port 8080
-    /// // Note: We can omit the type parameter here because we are setting the state
-    /// let mut server = Server::new("localhost", 8080)
-    ///     // Set server wide state
-    ///     .state(AtomicU32::new(0));
-    ///
-    /// // Add a stateful route to increment the state
-    /// server.stateful_route(Method::GET, "/", |state, _req| {
-    ///     Response::new().text(state.fetch_add(1, Ordering::Relaxed))
-    /// });
+    /// let mut server = Server::<()>::new("localhost", 8080)
+    ///     // Stream bodies over 1 MiB
+    ///     .body_buffer_limit(1024 * 1024);
     /// ```
-    pub fn state(self, state: State) -> Self {
+    pub fn body_buffer_limit(self, body_buffer_limit: usize) -> Self {
         trace!(
-            "{}Setting Server State [{}]",
-            emoji("📦️"),
-            type_name::<State>()
+            "{}Setting Body Buffer Limit to {}",
+            emoji("📦"),
+            body_buffer_limit
         );
 
-        Self {
-            state: Some(Arc::new(state)),
+        Server {
+            body_buffer_limit: Some(body_buffer_limit),
             ..self
         }
     }
 
-    /// Set the panic handler, which is called if a route or middleware panics.
-    /// This is only available if the `panic_handler` feature is enabled.
-    /// If you don't set it, the default response is 500 "Internal Server Error :/".
-    /// Be sure that your panic handler wont panic, because that will just panic the whole application.
+    /// Set the largest request body that will be read into memory.
+    /// Requests with a larger body get a `413 Payload Too Large`, and the connection is closed.
+    /// Bodies larger than the [`Server::body_buffer_limit`] are streamed instead of read into memory, so they aren't limited by this.
+    /// Defaults to 16 MiB, set the [`Server::max_body_size`] field to None to remove the limit.
+    ///
     /// ## Example
     /// ```rust
-    /// # use afire::{Server, Response, Status};
-    /// # let mut server = Server::<()>::new("localhost", 8080);
-    /// // Set the panic handler response
-    /// server.error_handler(|_state, _req, err| {
-    ///     Response::new()
-    ///         .status(Status::InternalServerError)
-    ///         .text(format!("Internal Server Error: {}", err))
-    /// });
+    /// # use afire::Server;
+    /// let mut server = Server::<()>::new("localhost", 8080)
+    ///     .max_body_size(1024 * 1024);
     /// ```
-    pub fn error_handler(
-        &mut self,
-        res: impl Fn(Option<Arc<State>>, &Box<Result<Rc<Request>>>, String) -> Response
-            + Send
-            + Sync
-            + 'static,
-    ) {
-        trace!("{}Setting Error Handler", emoji("✌"));
+    pub fn max_body_size(self, max_body_size: usize) -> Self {
+        trace!("{}Setting Max Body Size to {}", emoji("📦"), max_body_size);
 
-        self.error_handler = Box::new(res);
+        Server {
+            max_body_size: Some(max_body_size),
+            ..self
+        }
     }
 
-    /// Create a new route.
-    /// The path can contain parameters, which are defined with `{...}`, as well as wildcards, which are defined with `*`.
-    /// (`**` lets you math anything after the wildcard, including `/`)
+    /// Set the max number of headers a request can have.
+    /// Requests with more get a `431 Request Header Fields Too Large`, and the connection is closed.
+    /// Defaults to 100, set the [`Server::max_header_count`] field to None to remove the limit.
+    /// The trailers after a chunked body have the same limit, along with [`Server::max_head_size`] for their total size; too many is a bad chunked body.
+    ///
     /// ## Example
     /// ```rust
-    /// # use afire::{Server, Response, Header, Method, Content};
-    /// # let mut server = Server::<()>::new("localhost", 8080);
-    /// // Define a route
-    /// server.route(Method::GET, "/greet/{name}", |req| {
-    ///     let name = req.param("name").unwrap();
-    ///
-    ///     Response::new()
+    /// # use afire::Server;
+    /// let mut server = Server::<()>::new("localhost", 8080)
+    ///     .max_header_count(50)
+    ///     .max_header_size(4 * 1024)
+    ///     .max_head_size(16 * 1024);
+    /// ```
+    pub fn max_header_count(self, max_header_count: usize) -> Self {
+        trace!(
+            "{}Setting Max Header Count to {}",
+            emoji("📜"),
+            max_header_count
+        );
+
+        Server {
+            max_header_count: Some(max_header_count),
+            ..self
+        }
+    }
+
+    /// Set the max length in bytes of a single header line (`Name: value`).
+    /// Requests with a longer header get a `431 Request Header Fields Too Large`, and the connection is closed.
+    /// Defaults to 8 KiB, set the [`Server::max_header_size`] field to None to remove the limit.
+    pub fn max_header_size(self, max_header_size: usize) -> Self {
+        trace!(
+            "{}Setting Max Header Size to {}",
+            emoji("📜"),
+            max_header_size
+        );
+
+        Server {
+            max_header_size: Some(max_header_size),
+            ..self
+        }
+    }
+
+    /// Set the max length in bytes of the request line and all the headers together.
+    /// Larger requests get a `431 Request Header Fields Too Large`, and the connection is closed.
+    /// Defaults to 64 KiB, set the [`Server::max_head_size`] field to None to remove the limit.
+    pub fn max_head_size(self, max_head_size: usize) -> Self {
+        trace!("{}Setting Max Head Size to {}", emoji("📜"), max_head_size);
+
+        Server {
+            max_head_size: Some(max_head_size),
+            ..self
+        }
+    }
+
+    /// Set the max length in bytes of the request target, which is the path and query string.
+    /// Requests with a longer target get a `414 URI Too Long` as soon as the limit is passed, instead of buffering the rest of it, and the connection is closed.
+    /// Defaults to 8 KiB, set the [`Server::max_uri_length`] field to None to remove the limit (it is still limited by [`Server::max_head_size`]).
+    ///
+    /// ## Example
+    /// ```rust
+    /// # use afire::Server;
+    /// let mut server = Server::<()>::new("localhost", 8080)
+    ///     .max_uri_length(2048);
+    /// ```
+    pub fn max_uri_length(self, max_uri_length: usize) -> Self {
+        trace!(
+            "{}Setting Max URI Length to {}",
+            emoji("📜"),
+            max_uri_length
+        );
+
+        Server {
+            max_uri_length: Some(max_uri_length),
+            ..self
+        }
+    }
+
+    /// Set how long a client has to send the whole request line and headers.
+    /// For the first request on a connection this starts when the connection is opened, and for later ones when the first byte of the request arrives.
+    /// Unlike the [`Server::socket_timeout`], which limits each read, this can't be dodged by sending a byte at a time (a slowloris attack).
+    /// Clients that are too slow get a `408 Request Timeout`, and the connection is closed.
+    /// Connections that don't send anything are closed without a response, so opening idle connections can't use up the worker threads.
+    /// With [`Server::start_tls`], the TLS handshake also has to finish within this time.
+    ///
+    /// Defaults to 30 seconds, set the [`Server::head_timeout`] field to None to remove the limit.
+    /// ## Example
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use afire::Server;
+    /// let mut server = Server::<()>::new("localhost", 8080)
+    ///     .head_timeout(Duration::from_secs(10));
+    /// ```
+    pub fn head_timeout(self, head_timeout: Duration) -> Self {
+        trace!("{}Setting Head Timeout to {:?}", emoji("⏳"), head_timeout);
+
+        Server {
+            head_timeout: Some(head_timeout),
+            ..self
+        }
+    }
+
+    /// Gets the limits to parse requests with.
+    pub(crate) fn parse_limits(&self) -> ParseLimits {
+        ParseLimits {
+            body_buffer: self.body_buffer_limit,
+            body_size: self.max_body_size,
+            header_count: self.max_header_count,
+            header_size: self.max_header_size,
+            head_size: self.max_head_size,
+            uri_length: self.max_uri_length,
+            head_timeout: self.head_timeout,
+            keep_alive_timeout: self.keep_alive_timeout,
+            socket_timeout: self.socket_timeout,
+        }
+    }
+
+    /// Set how the futures returned by async routes are run.
+    /// The executor is called on the worker thread handling the request, and must run the future to completion.
+    /// By default this is [`crate::async_handler::block_on`], which can't run futures that need a specific runtime, like tokio's.
+    /// Only available with the `async` feature.
+    /// ## Example
+    /// ```rust
+    /// # use afire::{Server, async_handler::block_on};
+    /// // Create a server for localhost on port 8080
+    /// let mut server = Server::<()>::new("localhost", 8080)
+    ///     // With tokio, this would be `move |future| handle.block_on(future)`
+    ///     .async_executor(|future| block_on(future));
+    /// ```
+    #[cfg(feature = "async")]
+    pub fn async_executor(
+        self,
+        executor: impl Fn(BoxFuture<'_>) -> Response + Send + Sync + 'static,
+    ) -> Self {
+        trace!("{}Setting Async Executor", emoji("⚡"));
+
+        Server {
+            async_executor: Box::new(executor),
+            ..self
+        }
+    }
+
+    /// Set the keep alive state of the server.
+    /// This will determine if the server will keep the connection alive after a request.
+    /// By default this is true.
+    /// If you aren't using a threadpool, you may want to set this to false.
+    /// ## Example
+    /// ```rust
+    /// # use afire::Server;
+    /// // Create a server for localhost on port 8080
+    /// let mut server = Server::<()>::new("localhost", 8080)
+    ///     // Disable Keep Alive
+    ///     .keep_alive(false);
+    /// ```
+    pub fn keep_alive(self, keep_alive: bool) -> Self {
+        trace!("{}Setting Keep Alive to {}", emoji("🔁"), keep_alive);
+
+        Server { keep_alive, ..self }
+    }
+
+    /// Set how long a keep-alive connection can wait for its next request before it is closed.
+    /// This is only used between requests, the first request on a connection is limited by the [`Server::head_timeout`] (or this, if there is no head timeout).
+    /// The timeout is sent to clients in the `Keep-Alive` header, so they know not to reuse a connection that is about to be closed.
+    /// Idle connections are closed without a response, the client hasn't sent anything that a `408 Request Timeout` could answer.
+    ///
+    /// Defaults to 60 seconds, set the [`Server::keep_alive_timeout`] field to None to only use the socket timeout.
+    /// ## Example
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use afire::Server;
+    /// let mut server = Server::<()>::new("localhost", 8080)
+    ///     .keep_alive_timeout(Duration::from_secs(5));
+    /// ```
+    pub fn keep_alive_timeout(self, keep_alive_timeout: Duration) -> Self {
+        trace!(
+            "{}Setting Keep Alive Timeout to {:?}",
+            emoji("🔁"),
+            keep_alive_timeout
+        );
+
+        Server {
+            keep_alive_timeout: Some(keep_alive_timeout),
+            ..self
+        }
+    }
+
+    /// Set the most requests that can be sent over one keep-alive connection.
+    /// The response to the last request has `Connection: close`, and the connection is closed after it is sent.
+    /// How many requests are left is sent to clients in the `Keep-Alive` header.
+    /// By default there is no limit.
+    /// ## Example
+    /// ```rust
+    /// # use afire::Server;
+    /// let mut server = Server::<()>::new("localhost", 8080)
+    ///     .keep_alive_max(1000);
+    /// ```
+    pub fn keep_alive_max(self, keep_alive_max: u64) -> Self {
+        trace!(
+            "{}Setting Keep Alive Max to {}",
+            emoji("🔁"),
+            keep_alive_max
+        );
+
+        Server {
+            keep_alive_max: Some(keep_alive_max),
+            ..self
+        }
+    }
+
+    /// Set the max number of accepted connections that can be waiting for a worker thread.
+    /// Only used by [`Server::start_threaded`] and [`Server::start_split`].
+    /// Defaults to 1024.
+    /// ## Example
+    /// ```rust
+    /// # use afire::{Server, OverflowPolicy};
+    /// // Create a server for localhost on port 8080
+    /// let mut server = Server::<()>::new("localhost", 8080)
+    ///     // Respond with a 503 once 64 connections are waiting
+    ///     .queue_limit(64)
+    ///     .overflow_policy(OverflowPolicy::Reject);
+    /// ```
+    pub fn queue_limit(self, queue_limit: usize) -> Self {
+        trace!("{}Setting Queue Limit to {}", emoji("📥"), queue_limit);
+
+        Server {
+            queue_limit,
+            ..self
+        }
+    }
+
+    /// Set the max number of connections that can be open at once.
+    /// This includes connections being handled, idle keep-alive connections and connections waiting in the queue.
+    /// Once the limit is reached, new connections are handled with the [`Server::overflow_policy`].
+    ///
+    /// The number of open connections can be read from [`ConnectionStats::open`].
+    /// Only used by [`Server::start_threaded`] and [`Server::start_split`].
+    /// By default there is no limit.
+    /// ## Example
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use afire::{Server, OverflowPolicy};
+    /// // Create a server for localhost on port 8080
+    /// let mut server = Server::<()>::new("localhost", 8080)
+    ///     // Respond with a 503 once 256 connections are open
+    ///     .max_connections(256)
+    ///     .overflow_policy(OverflowPolicy::Reject)
+    ///     // Close idle connections so they don't hold a slot forever
+    ///     .socket_timeout(Duration::from_secs(10));
+    /// ```
+    pub fn max_connections(self, max_connections: usize) -> Self {
+        trace!(
+            "{}Setting Max Connections to {}",
+            emoji("🔌"),
+            max_connections
+        );
+
+        Server {
+            max_connections: Some(max_connections),
+            ..self
+        }
+    }
+
+    /// Set what reason phrase to send in the status line of responses.
+    /// This is applied to every response the server writes, so it can be used to hide custom reason phrases from compliance scanners.
+    /// ## Example
+    /// ```rust
+    /// # use afire::{Server, ReasonPhrase};
+    /// // Create a server for localhost on port 8080
+    /// let mut server = Server::<()>::new("localhost", 8080)
+    ///     // Send status lines like `HTTP/1.1 404 `
+    ///     .reason_phrase(ReasonPhrase::Omit);
+    /// ```
+    pub fn reason_phrase(self, reason_phrase: ReasonPhrase) -> Self {
+        trace!("{}Setting Reason Phrase to {:?}", emoji("🏷"), reason_phrase);
+
+        Server {
+            reason_phrase,
+            ..self
+        }
+    }
+
+    /// Enable fair scheduling of the thread pools.
+    /// When workers are busy, the queued work is taken from each client IP in turn, instead of in the order it arrived.
+    /// This stops one client with lots of connections (or requests, with [`Server::start_split`]) from making everyone else wait behind it.
+    ///
+    /// How much of each pool every client is using can be seen with [`crate::PoolStats::clients`].
+    /// Only used by [`Server::start_threaded`] and [`Server::start_split`].
+    /// ## Example
+    /// ```rust
+    /// # use afire::Server;
+    /// // Create a server for localhost on port 8080
+    /// let mut server = Server::<()>::new("localhost", 8080)
+    ///     .fair_scheduling(true);
+    /// ```
+    pub fn fair_scheduling(self, fair_scheduling: bool) -> Self {
+        trace!(
+            "{}Setting Fair Scheduling to {}",
+            emoji("⚖"),
+            fair_scheduling
+        );
+
+        Server {
+            fair_scheduling,
+            ..self
+        }
+    }
+
+    /// Let the thread pools grow up to `max_threads` workers when there is more work waiting than idle workers to pick it up.
+    /// The extra workers stop once they have been idle for the [`Server::idle_thread_timeout`], so the pools shrink back to the size they were started with.
+    /// With [`Server::start_split`], each pool can grow to this size.
+    ///
+    /// The current size of each pool, along with the queue length and the number of jobs that panicked, can be read from [`Server::pool_stats`].
+    /// Only used by [`Server::start_threaded`] and [`Server::start_split`].
+    /// ## Example
+    /// ```rust,no_run
+    /// # #[cfg(feature = "tcp")] {
+    /// # use afire::Server;
+    /// let mut server = Server::<()>::new("localhost", 8080)
+    ///     .max_threads(32);
+    ///
+    /// // Starts with 4 threads, and grows to 32 under load
+    /// server.start_threaded(4).unwrap();
+    /// # }
+    /// ```
+    pub fn max_threads(self, max_threads: usize) -> Self {
+        trace!("{}Setting Max Threads to {}", emoji("🧵"), max_threads);
+
+        Server {
+            max_threads: Some(max_threads),
+            ..self
+        }
+    }
+
+    /// Set how long a worker added by [`Server::max_threads`] waits for work before stopping.
+    /// Defaults to 60 seconds.
+    /// ## Example
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use afire::Server;
+    /// let mut server = Server::<()>::new("localhost", 8080)
+    ///     .max_threads(32)
+    ///     .idle_thread_timeout(Duration::from_secs(10));
+    /// ```
+    pub fn idle_thread_timeout(self, idle_thread_timeout: Duration) -> Self {
+        trace!(
+            "{}Setting Idle Thread Timeout to {:?}",
+            emoji("💤"),
+            idle_thread_timeout
+        );
+
+        Server {
+            idle_thread_timeout,
+            ..self
+        }
+    }
+
+    /// Gets how a pool started with `threads` workers can grow.
+    #[cfg(feature = "tcp")]
+    fn pool_scaling(&self, threads: usize) -> Scaling {
+        Scaling {
+            min: threads,
+            max: self.max_threads.unwrap_or(threads),
+            idle: self.idle_thread_timeout,
+        }
+    }
+
+    /// Set the start of the worker thread names, which show up in panic messages, debuggers and profilers.
+    /// With [`Server::start_threaded`] the workers are named `{prefix}-worker-{id}`, and with [`Server::start_split`] they are named `{prefix}-io-{id}` and `{prefix}-handler-{id}`.
+    /// Defaults to `afire`.
+    /// ## Example
+    /// ```rust
+    /// # use afire::Server;
+    /// // Workers will be named `api-worker-0`, `api-worker-1`, ...
+    /// let mut server = Server::<()>::new("localhost", 8080)
+    ///     .thread_prefix("api");
+    /// ```
+    pub fn thread_prefix(self, thread_prefix: impl AsRef<str>) -> Self {
+        let thread_prefix = thread_prefix.as_ref().to_owned();
+        trace!("{}Setting Thread Prefix to {}", emoji("🏷"), thread_prefix);
+
+        Server {
+            thread_prefix,
+            ..self
+        }
+    }
+
+    /// Set a function to run on each worker thread when it starts, before it handles any connections.
+    /// This is useful for setting up per-thread state, like thread-locals, logging context or profiler registration.
+    /// Only used by [`Server::start_threaded`] and [`Server::start_split`].
+    /// ## Example
+    /// ```rust
+    /// # use std::thread;
+    /// # use afire::Server;
+    /// let mut server = Server::<()>::new("localhost", 8080)
+    ///     .thread_init(|| println!("Started {:?}", thread::current().name()))
+    ///     .thread_teardown(|| println!("Stopped {:?}", thread::current().name()));
+    /// ```
+    pub fn thread_init(self, init: impl Fn() + Send + Sync + 'static) -> Self {
+        trace!("{}Setting Thread Init Hook", emoji("🪝"));
+
+        Server {
+            thread_init: Some(Arc::new(init)),
+            ..self
+        }
+    }
+
+    /// Set a function to run once the server is listening, with the address it is bound to.
+    /// When bound to port `0`, this is how to find the port the OS picked, see also [`Server::bound_addr`].
+    /// ## Example
+    /// ```rust
+    /// # use afire::Server;
+    /// let server = Server::<()>::new("127.0.0.1", 0)
+    ///     .on_start(|addr| println!("Listening on http://{addr}"));
+    /// ```
+    pub fn on_start(self, on_start: impl Fn(SocketAddr) + Send + Sync + 'static) -> Self {
+        trace!("{}Setting Start Hook", emoji("🪝"));
+
+        Server {
+            on_start: Some(Arc::new(on_start)),
+            ..self
+        }
+    }
+
+    /// Set a function to run on each worker thread when it stops, after its last connection.
+    /// Workers only stop once they have been idle for the [`Server::idle_thread_timeout`], which only happens to the extra workers added with [`Server::max_threads`].
+    /// See [`Server::thread_init`] for an example.
+    pub fn thread_teardown(self, teardown: impl Fn() + Send + Sync + 'static) -> Self {
+        trace!("{}Setting Thread Teardown Hook", emoji("🪝"));
+
+        Server {
+            thread_teardown: Some(Arc::new(teardown)),
+            ..self
+        }
+    }
+
+    /// Gets the hooks to run on the worker threads.
+    #[cfg(feature = "tcp")]
+    fn thread_hooks(&self) -> ThreadHooks {
+        ThreadHooks {
+            init: self.thread_init.clone(),
+            teardown: self.thread_teardown.clone(),
+        }
+    }
+
+    /// Set if requests with the wrong method for a path get a `405 Method Not Allowed` response.
+    /// When enabled, a request that doesn't match any route, but whose path matches routes with other methods, is sent a 405 with an `Allow` header listing those methods.
+    /// When disabled, it is sent a `404 Not Found` like any other unmatched request.
+    /// Disabled by default.
+    /// ## Example
+    /// ```rust
+    /// # use afire::Server;
+    /// // Create a server for localhost on port 8080
+    /// let mut server = Server::<()>::new("localhost", 8080)
+    ///     // Respond with 405 Method Not Allowed when only the method is wrong
+    ///     .method_not_allowed(true);
+    /// ```
+    pub fn method_not_allowed(self, method_not_allowed: bool) -> Self {
+        trace!(
+            "{}Setting Method Not Allowed to {}",
+            emoji("🚫"),
+            method_not_allowed
+        );
+
+        Server {
+            method_not_allowed,
+            ..self
+        }
+    }
+
+    /// Set if `OPTIONS` requests are answered automatically.
+    /// When enabled, an `OPTIONS` request that doesn't match any route, but whose path matches routes with other methods, is sent a `204 No Content` with an `Allow` header listing those methods.
+    /// Routes defined for `OPTIONS` (or [`Method::ANY`]) still take priority.
+    ///
+    /// The response goes through post middleware like any other, so a CORS middleware can add its headers to answer preflight requests.
+    /// Disabled by default.
+    /// ## Example
+    /// ```rust
+    /// # use afire::{Server, Method, Response};
+    /// let mut server = Server::<()>::new("localhost", 8080)
+    ///     .auto_options(true);
+    ///
+    /// server.route(Method::GET, "/users", |_req| Response::new());
+    /// server.route(Method::POST, "/users", |_req| Response::new());
+    /// // `OPTIONS /users` gets `Allow: GET, POST, OPTIONS`
+    /// ```
+    pub fn auto_options(self, auto_options: bool) -> Self {
+        trace!("{}Setting Auto Options to {}", emoji("📋"), auto_options);
+
+        Server {
+            auto_options,
+            ..self
+        }
+    }
+
+    /// Set if `HEAD` requests are answered automatically.
+    /// When enabled, a `HEAD` request that doesn't match any route is handled by the `GET` route for its path.
+    /// The response is sent with the same headers the `GET` request would get, including `Content-Length` for static bodies, but the body is not sent.
+    /// Streamed and file bodies are not read at all, so they keep their `Transfer-Encoding: chunked` header instead of getting a length.
+    ///
+    /// Routes defined for `HEAD` (or [`Method::ANY`]) still take priority, but their bodies are also left out.
+    /// Handlers can tell these requests apart as [`Request::method`] is still `HEAD`.
+    /// This replaces the [`Head`](crate::extension::Head) extension, which reads streams to find their length.
+    /// Disabled by default.
+    /// ## Example
+    /// ```rust
+    /// # use afire::{Server, Method, Response};
+    /// let mut server = Server::<()>::new("localhost", 8080)
+    ///     .auto_head(true);
+    ///
+    /// // `HEAD /` gets the headers of this response, including `Content-Length: 13`
+    /// server.route(Method::GET, "/", |_req| Response::new().text("Hello, World!"));
+    /// ```
+    pub fn auto_head(self, auto_head: bool) -> Self {
+        trace!("{}Setting Auto Head to {}", emoji("🗣"), auto_head);
+
+        Server { auto_head, ..self }
+    }
+
+    /// Set the headers to force onto or strip from every response.
+    /// See [`HeaderPolicy`] for an example.
+    pub fn header_policy(self, header_policy: HeaderPolicy) -> Self {
+        trace!("{}Setting Header Policy", emoji("🛡"));
+
+        Server {
+            header_policy,
+            ..self
+        }
+    }
+
+    /// Applies the reason phrase, default headers and header policies to a response before it is written.
+    pub(crate) fn finalize_response(&self, res: &mut Response) {
+        finalize_response(
+            self.reason_phrase,
+            &self.default_headers,
+            &self.header_policy,
+            res,
+        );
+    }
+
+    /// Copies the config used by [`Server::finalize_response`], to be stored on a connection.
+    pub(crate) fn response_finish(&self) -> ResponseFinish {
+        ResponseFinish {
+            reason_phrase: self.reason_phrase,
+            default_headers: self.default_headers.clone(),
+            header_policy: self.header_policy.clone(),
+        }
+    }
+
+    /// Set what to do with new connections when the queue is full.
+    /// See [`OverflowPolicy`] for the options and [`Server::queue_limit`] for an example.
+    pub fn overflow_policy(self, overflow_policy: OverflowPolicy) -> Self {
+        trace!(
+            "{}Setting Overflow Policy to {:?}",
+            emoji("📥"),
+            overflow_policy
+        );
+
+        Server {
+            overflow_policy,
+            ..self
+        }
+    }
+
+    /// Set the state of a server.
+    /// The state will be available to stateful routes ([`Server::stateful_route`]) and middleware.
+    /// It is not mutable, so you will need to use an atomic or sync type to mutate it.
+    ///
+    /// ## Example
+    /// ```rust,no_run
+    /// # use afire::{Server, Response, Method};
+    /// # use std::sync::atomic::{AtomicU32, Ordering};
+    /// // Create a server for localhost on port 8080
+    /// // Note: We can omit the type parameter here because we are setting the state
+    /// let mut server = Server::new("localhost", 8080)
+    ///     // Set server wide state
+    ///     .state(AtomicU32::new(0));
+    ///
+    /// // Add a stateful route to increment the state
+    /// server.stateful_route(Method::GET, "/", |state, _req| {
+    ///     Response::new().text(state.fetch_add(1, Ordering::Relaxed))
+    /// });
+    /// ```
+    pub fn state(self, state: State) -> Self {
+        trace!(
+            "{}Setting Server State [{}]",
+            emoji("📦️"),
+            type_name::<State>()
+        );
+
+        Self {
+            state: Some(Arc::new(state)),
+            ..self
+        }
+    }
+
+    /// Set the panic handler, which is called if a route or middleware panics.
+    /// This is only available if the `panic_handler` feature is enabled.
+    /// If you don't set it, the default response is 500 "Internal Server Error :/".
+    /// If the error handler itself panics, a minimal hard-coded 500 response is sent instead and the connection is closed.
+    /// Panics are traced at [`crate::trace::Level::Error`], and a curl command to reproduce the request (see [`Request::to_curl`]) at [`crate::trace::Level::Debug`], as it includes the request body.
+    /// ## Example
+    /// ```rust
+    /// # use afire::{Server, Response, Status};
+    /// # let mut server = Server::<()>::new("localhost", 8080);
+    /// // Set the panic handler response
+    /// server.error_handler(|_state, _req, err| {
+    ///     Response::new()
+    ///         .status(Status::InternalServerError)
+    ///         .text(format!("Internal Server Error: {}", err))
+    /// });
+    /// ```
+    pub fn error_handler(
+        &mut self,
+        res: impl Fn(Option<Arc<State>>, &Box<Result<Rc<Request>>>, String) -> Response
+            + Send
+            + Sync
+            + 'static,
+    ) {
+        trace!("{}Setting Error Handler", emoji("✌"));
+
+        self.error_handler = Box::new(res);
+    }
+
+    /// Redacts a header in the curl command logged when a handler panics (see [`Server::error_handler`]), for headers that hold secrets like API keys.
+    /// The header is still included, but with its value replaced by `[redacted]`.
+    /// `Authorization`, `Cookie` and `Proxy-Authorization` are always redacted.
+    /// ## Example
+    /// ```rust
+    /// # use afire::Server;
+    /// let mut server = Server::<()>::new("localhost", 8080)
+    ///     .redact_header("X-Api-Key");
+    /// ```
+    pub fn redact_header(self, header: impl Into<HeaderType>) -> Self {
+        let header = header.into();
+        trace!("{}Redacting {} in panic logs", emoji("🙈"), header);
+
+        let mut redacted_headers = self.redacted_headers;
+        redacted_headers.push(header);
+        Server {
+            redacted_headers,
+            ..self
+        }
+    }
+
+    /// Create a new route.
+    /// The path can contain parameters, which are defined with `{...}`, as well as wildcards, which are defined with `*`.
+    /// (`**` lets you math anything after the wildcard, including `/`)
+    /// Returns a reference to the new [`Route`], which can be used to set route options like [`Route::trailing_slash`].
+    /// ## Example
+    /// ```rust
+    /// # use afire::{Server, Response, Header, Method, Content};
+    /// # let mut server = Server::<()>::new("localhost", 8080);
+    /// // Define a route
+    /// server.route(Method::GET, "/greet/{name}", |req| {
+    ///     let name = req.param("name").unwrap();
+    ///
+    ///     Response::new()
     ///         .text(format!("Hello, {}!", name))
     ///         .content(Content::TXT)
     /// });
@@ -302,13 +1627,86 @@ impl<State: Send + Sync> Server<State> {
         method: Method,
         path: impl AsRef<str>,
         handler: impl Fn(&Request) -> Response + Send + Sync + 'static,
-    ) -> &mut Self {
+    ) -> &mut Route<State> {
         let path = path.as_ref().to_owned();
         trace!("{}Adding Route {} {}", emoji("🚗"), method, path);
 
         self.routes
             .push(Route::new(method, path, Box::new(handler)));
-        self
+        self.router.take();
+        self.routes.last_mut().unwrap()
+    }
+
+    /// Create a new route with a handler that takes the request by value, like the `fn(Request) -> Response` handlers of older versions of afire.
+    /// This lets a large app move its handlers over to [`Server::route`] one at a time, instead of all at once.
+    ///
+    /// The handler is passed a copy of the request, so changes it makes to the request are not seen by middleware.
+    /// Bodies too large to buffer (See [`Server::body_buffer_limit`]) are not included in the copy.
+    /// ## Example
+    /// ```rust
+    /// # use afire::{Server, Response, Request, Method};
+    /// # let mut server = Server::<()>::new("localhost", 8080);
+    /// fn hello(req: Request) -> Response {
+    ///     Response::new().text(format!("Hello, {}", req.path))
+    /// }
+    ///
+    /// #[allow(deprecated)]
+    /// server.legacy_route(Method::GET, "/hello", hello);
+    /// ```
+    #[deprecated(note = "Handlers should take a `&Request`, see `Server::route`")]
+    pub fn legacy_route(
+        &mut self,
+        method: Method,
+        path: impl AsRef<str>,
+        handler: impl Fn(Request) -> Response + Send + Sync + 'static,
+    ) -> &mut Route<State> {
+        let path = path.as_ref().to_owned();
+        trace!("{}Adding Legacy Route {} {}", emoji("🚗"), method, path);
+
+        self.routes.push(Route::new(
+            method,
+            path,
+            Box::new(move |req| handler(req.clone_buffered())),
+        ));
+        self.router.take();
+        self.routes.last_mut().unwrap()
+    }
+
+    /// Create a new async route.
+    /// Is the same as [`Server::route`], but the handler is an async function.
+    /// The returned future is run to completion on the worker thread with the [`Server::async_executor`].
+    /// Only available with the `async` feature, see the [`crate::async_handler`] module for more info.
+    /// ## Example
+    /// ```rust
+    /// # use afire::{Server, Response, Method};
+    /// # async fn lookup(name: &str) -> String { name.to_uppercase() }
+    /// # let mut server = Server::<()>::new("localhost", 8080);
+    /// server.route_async(Method::GET, "/greet/{name}", async |req| {
+    ///     let name = lookup(&req.param("name").unwrap()).await;
+    ///     Response::new().text(format!("Hello, {}", name))
+    /// });
+    /// ```
+    #[cfg(feature = "async")]
+    pub fn route_async(
+        &mut self,
+        method: Method,
+        path: impl AsRef<str>,
+        handler: impl AsyncFn(&Request) -> Response + Send + Sync + 'static,
+    ) -> &mut Route<State> {
+        let path = path.as_ref().to_owned();
+        trace!("{}Adding Route {} {}", emoji("🚗"), method, path);
+
+        let handler = Arc::new(handler);
+        self.routes.push(Route::new_async(
+            method,
+            path,
+            Box::new(move |req| {
+                let handler = handler.clone();
+                Box::pin(async move { handler(req).await })
+            }),
+        ));
+        self.router.take();
+        self.routes.last_mut().unwrap()
     }
 
     /// Create a new stateful route.
@@ -333,13 +1731,176 @@ impl<State: Send + Sync> Server<State> {
         method: Method,
         path: impl AsRef<str>,
         handler: impl Fn(Arc<State>, &Request) -> Response + Send + Sync + 'static,
-    ) -> &mut Self {
+    ) -> &mut Route<State> {
         let path = path.as_ref().to_owned();
         trace!("{}Adding Route {} {}", emoji("🚗"), method, path);
 
         self.routes
             .push(Route::new_stateful(method, path, Box::new(handler)));
-        self
+        self.router.take();
+        self.routes.last_mut().unwrap()
+    }
+
+    /// Create a new route with a handler that takes [extractors](crate::extract) as arguments.
+    /// Each argument is taken from the request before the handler runs, and if one can't be (like a path parameter that isn't a number), its error response is sent instead.
+    /// The handler can return anything that converts into a [`Response`].
+    ///
+    /// If an argument needs the state (like [`crate::extract::State`]), the route is stateful, see [`Server::stateful_route`].
+    /// ## Example
+    /// ```rust
+    /// # use afire::{Server, Response, Method, Query, extract::Path};
+    /// # let mut server = Server::<()>::new("localhost", 8080);
+    /// server.extract_route(
+    ///     Method::GET,
+    ///     "/greet/{name}",
+    ///     |Path((name,)): Path<(String,)>, query: Query| {
+    ///         let greeting = query.get("greeting").unwrap_or("Hello");
+    ///         Response::new().text(format!("{greeting}, {name}!"))
+    ///     },
+    /// );
+    /// ```
+    pub fn extract_route<Args, H: Handler<State, Args>>(
+        &mut self,
+        method: Method,
+        path: impl AsRef<str>,
+        handler: H,
+    ) -> &mut Route<State> {
+        let path = path.as_ref().to_owned();
+        trace!("{}Adding Route {} {}", emoji("🚗"), method, path);
+
+        let route = match H::STATEFUL {
+            true => Route::new_stateful(
+                method,
+                path,
+                Box::new(move |state, req| handler.call(req, Some(&state))),
+            ),
+            false => Route::new(method, path, Box::new(move |req| handler.call(req, None))),
+        };
+        self.routes.push(route);
+        self.router.take();
+        self.routes.last_mut().unwrap()
+    }
+
+    /// Create a new route whose responses are cached in memory for `ttl`.
+    /// Later GET requests for the same path and query are answered from the cache without calling the handler, until the entry expires or is purged with [`Server::cache`].
+    ///
+    /// Responses are cached with the same rules as the [`crate::extension::Cache`] middleware, including the `Vary`, `Age` and `X-Cache` headers.
+    /// Only available with the `extensions` feature.
+    /// ## Example
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use afire::{Server, Response, Method};
+    /// # let mut server = Server::<()>::new("localhost", 8080);
+    /// server.cached_route(Method::GET, "/expensive", Duration::from_secs(60), |_req| {
+    ///     // Something slow
+    ///     Response::new().text("Done")
+    /// });
+    ///
+    /// let cache = server.cache();
+    /// server.route(Method::POST, "/expensive", move |_req| {
+    ///     cache.purge("/expensive");
+    ///     Response::new().text("Purged")
+    /// });
+    /// ```
+    #[cfg(feature = "extensions")]
+    pub fn cached_route(
+        &mut self,
+        method: Method,
+        path: impl AsRef<str>,
+        ttl: Duration,
+        handler: impl Fn(&Request) -> Response + Send + Sync + 'static,
+    ) -> &mut Route<State> {
+        let handler = cached_handler(self.route_cache.clone(), ttl, handler);
+        self.route(method, path, handler)
+    }
+
+    /// Gets a handle to the responses cached by [`Server::cached_route`], to purge them from route handlers or other threads.
+    /// Only available with the `extensions` feature.
+    #[cfg(feature = "extensions")]
+    pub fn cache(&self) -> CacheStore {
+        self.route_cache.clone()
+    }
+
+    /// Gets the attached middleware, in the order it runs.
+    /// Middleware is ordered by [`Middleware::priority`], then by when it was attached (last attached first).
+    ///
+    /// ## Example
+    /// ```rust
+    /// # #[cfg(feature = "extensions")] {
+    /// # use afire::{Server, Middleware, extension::{Logger, RequestId}};
+    /// let mut server = Server::<()>::new("localhost", 8080);
+    /// Logger::new().attach(&mut server);
+    /// RequestId::new("X-Request-Id").attach(&mut server);
+    ///
+    /// // The logger runs last, even though it was attached first
+    /// let chain = server.middleware_chain().map(|x| x.name()).collect::<Vec<_>>();
+    /// assert_eq!(chain, ["afire::extensions::request_id::RequestId", "afire::extensions::logger::Logger"]);
+    /// # }
+    /// ```
+    pub fn middleware_chain(&self) -> impl Iterator<Item = &(dyn Middleware + Send + Sync)> {
+        self.middleware.iter().rev().map(|x| &**x)
+    }
+
+    /// Attaches middleware that is passed the server's state in its hooks, see [`StatefulMiddleware`].
+    /// The state is given to the middleware now, so it must be set first with [`Server::state`].
+    /// Will <u>panic</u> if the server has no state.
+    /// ## Example
+    /// ```rust
+    /// # use std::sync::Arc;
+    /// # use afire::{Server, Request, middleware::{MiddleResult, StatefulMiddleware}};
+    /// struct Maintenance;
+    ///
+    /// impl StatefulMiddleware<bool> for Maintenance {
+    ///     fn pre(&self, down: &Arc<bool>, _req: &mut Request) -> MiddleResult {
+    ///         match **down {
+    ///             true => MiddleResult::Abort,
+    ///             false => MiddleResult::Continue,
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// let mut server = Server::<bool>::new("localhost", 8080).state(false);
+    /// server.attach_stateful(Maintenance);
+    /// ```
+    pub fn attach_stateful(
+        &mut self,
+        middleware: impl StatefulMiddleware<State> + Send + Sync + 'static,
+    ) {
+        trace!("{}Adding Middleware {}", emoji("📦"), middleware.name());
+
+        let state = self
+            .state
+            .clone()
+            .expect("Server state must be set before attaching stateful middleware");
+        self.insert_middleware(Box::new(WithState { middleware, state }));
+    }
+
+    /// Adds middleware to the chain, after all middleware with the same or lower priority.
+    pub(crate) fn insert_middleware(&mut self, middleware: Box<dyn Middleware + Send + Sync>) {
+        middleware::insert(&mut self.middleware, middleware);
+    }
+
+    /// Define a group of routes that share a path prefix, and can have their own middleware.
+    /// The routes and middleware are added to the server when this is called.
+    /// See [`Group`] for an example.
+    pub fn group(&mut self, prefix: impl AsRef<str>, group: impl FnOnce(&mut Group<State>)) {
+        let prefix = prefix.as_ref();
+        trace!("{}Adding Group {}", emoji("🗂"), prefix);
+        group(&mut Group::new(self, prefix));
+    }
+
+    /// Gets the routes that have been defined, in the order they were added.
+    /// When the server starts, a router is built from their paths, so each request is only checked against the routes that could match it.
+    /// Routes can only be added with methods like [`Server::route`], so the router can't get out of date.
+    /// ## Example
+    /// ```rust
+    /// # use afire::{Server, Response, Method};
+    /// let mut server = Server::<()>::new("localhost", 8080);
+    /// server.route(Method::GET, "/", |_req| Response::new());
+    /// assert_eq!(server.routes().len(), 1);
+    /// ```
+    pub fn routes(&self) -> &[Route<State>] {
+        &self.routes
     }
 
     /// Gets a reference to the current server state set outside of stateful routes.
@@ -366,6 +1927,334 @@ impl<State: Send + Sync> Server<State> {
             return Err(StartupError::InvalidSocketTimeout.into());
         }
 
+        self.router
+            .get_or_init(|| Router::new(self.routes.iter().map(Route::path)));
         Ok(())
     }
+
+    /// Gets the indexes of the routes whose path matches, from the last defined route to the first.
+    /// Falls back to every route if the router hasn't been built yet.
+    pub(crate) fn route_candidates(&self, path: &str) -> Vec<usize> {
+        match self.router.get() {
+            Some(router) => {
+                debug_assert_eq!(router.len(), self.routes.len(), "Router is out of date");
+                router.candidates(path)
+            }
+            None => (0..self.routes.len()).rev().collect(),
+        }
+    }
+
+    /// Binds all of the server's listeners, then returns the connections accepted from any of them.
+    /// Also records that the server has started, and runs the [`Server::on_start`] hook for each listener.
+    ///
+    /// With more than one listener (See [`Server::bind_also`]), each is accepted from on its own thread, and the connections are sent back to be handled in the order they arrive.
+    #[cfg(feature = "tcp")]
+    fn listen(&self) -> Result<Box<dyn Iterator<Item = io::Result<TcpStream>>>> {
+        let mut listeners = vec![self.bind()?];
+        for addr in self.also_bind.iter() {
+            listeners.push(TcpListener::bind(addr)?);
+        }
+
+        self.start_time.set();
+        for (i, listener) in listeners.iter().enumerate() {
+            let addr = listener.local_addr()?;
+            trace!(Level::Debug, "Listening on {}", addr);
+            if i == 0 {
+                self.bound_addr.set(addr);
+            }
+
+            if let Some(on_start) = &self.on_start {
+                on_start(addr);
+            }
+        }
+
+        let accept = |listener: TcpListener| iter::repeat_with(move || listener.accept());
+        if listeners.len() == 1 {
+            let listener = listeners.remove(0);
+            return Ok(Box::new(accept(listener).map(|x| Ok(x?.0))));
+        }
+
+        let (tx, rx) = mpsc::sync_channel(0);
+        for listener in listeners {
+            let tx = tx.clone();
+            thread::Builder::new()
+                .name(format!("{}-accept", self.thread_prefix))
+                .spawn(move || {
+                    for event in accept(listener) {
+                        if tx.send(event.map(|x| x.0)).is_err() {
+                            break;
+                        }
+                    }
+                })?;
+        }
+
+        Ok(Box::new(rx.into_iter()))
+    }
+
+    /// Gets the listener to accept connections from, binding [`Server::ip`] and [`Server::port`] if the server wasn't given one.
+    #[cfg(feature = "tcp")]
+    fn bind(&self) -> std::io::Result<TcpListener> {
+        match &self.listener {
+            Some(listener) => listener.try_clone(),
+            None => TcpListener::bind(SocketAddr::new(self.ip, self.port)),
+        }
+    }
+
+    #[cfg(feature = "tcp")]
+    /// Decides if a newly accepted connection should be queued on the pool.
+    /// If the queue is full or there are too many open connections, the [`Server::overflow_policy`] is applied, and None is returned if the connection was turned away.
+    /// The returned [`OpenConnection`] should be kept until the connection is closed.
+    fn admit(
+        &self,
+        event: std::io::Result<TcpStream>,
+        pool: &ThreadPool,
+        saturation: &mut Saturation,
+    ) -> Option<(TcpStream, OpenConnection)> {
+        let stream = match event {
+            Ok(stream) => stream,
+            Err(e) => {
+                trace!(Level::Error, "Error accepting connection: {}", e);
+                return None;
+            }
+        };
+
+        let stats = pool.stats();
+        if let Some(max) = self.max_connections.map(|x| x.max(1)) {
+            if self.connection_stats.open() >= max {
+                if self.overflow_policy != OverflowPolicy::Block {
+                    return self.turn_away(stream, stats, "Connection limit reached");
+                }
+
+                trace!(
+                    Level::Debug,
+                    "Connection limit reached, waiting for a connection to close"
+                );
+                while !self
+                    .connection_stats
+                    .wait_for_room(max, Duration::from_secs(1))
+                {}
+            }
+        }
+
+        let limit = self.queue_limit.max(1);
+        if stats.queued() < limit {
+            saturation.update(false, stats);
+            return Some((stream, OpenConnection::new(self.connection_stats.clone())));
+        }
+
+        saturation.update(true, stats);
+        if self.overflow_policy == OverflowPolicy::Block {
+            while !pool.wait_for_room(limit, Duration::from_secs(1)) {
+                saturation.update(true, stats);
+            }
+            return Some((stream, OpenConnection::new(self.connection_stats.clone())));
+        }
+
+        self.turn_away(stream, stats, "Queue full")
+    }
+
+    /// Turns away a connection with the [`Server::overflow_policy`], either closing it or sending a `503 Service Unavailable`.
+    #[cfg(feature = "tcp")]
+    fn turn_away<T>(&self, stream: TcpStream, stats: &PoolStats, reason: &str) -> Option<T> {
+        // Counted before the connection is closed, so it's up to date by the time the client sees it
+        stats.overflowed.fetch_add(1, Ordering::Relaxed);
+        if self.overflow_policy == OverflowPolicy::Drop {
+            trace!(
+                Level::Debug,
+                "{}, dropping {:?}",
+                reason,
+                stream.peer_addr()
+            );
+            return None;
+        }
+
+        trace!(
+            Level::Debug,
+            "{}, rejecting {:?}",
+            reason,
+            stream.peer_addr()
+        );
+        let _ =
+            stream.set_write_timeout(Some(self.socket_timeout.unwrap_or(Duration::from_secs(1))));
+        let mut res = Response::new()
+            .status(Status::ServiceUnavailable)
+            .text("Service Unavailable")
+            .content(Content::TXT)
+            .close();
+        self.finalize_response(&mut res);
+        let _ = res.write(Arc::new(Socket::new(Box::new(stream))), &[]);
+
+        None
+    }
+}
+
+/// Tracks how long a pool's queue has been full, to warn when it stays saturated.
+#[cfg(feature = "tcp")]
+#[derive(Default)]
+struct Saturation {
+    since: Option<Instant>,
+    last_warning: Option<Instant>,
+}
+
+#[cfg(feature = "tcp")]
+impl Saturation {
+    /// How long the queue has to stay full before warning.
+    const THRESHOLD: Duration = Duration::from_secs(1);
+    /// The minimum time between warnings.
+    const INTERVAL: Duration = Duration::from_secs(10);
+
+    fn update(&mut self, full: bool, stats: &PoolStats) {
+        if !full {
+            self.since = None;
+            return;
+        }
+
+        let since = *self.since.get_or_insert_with(Instant::now);
+        if since.elapsed() < Self::THRESHOLD
+            || self.last_warning.map(|x| x.elapsed() < Self::INTERVAL) == Some(true)
+        {
+            return;
+        }
+
+        self.last_warning = Some(Instant::now());
+        trace!(
+            Level::Error,
+            "Connection queue has been full for {:.1?} ({} queued, {} of {} threads busy, {} connections turned away)",
+            since.elapsed(),
+            stats.queued(),
+            stats.active(),
+            stats.threads(),
+            stats.overflowed()
+        );
+    }
+}
+
+impl ConnectionStats {
+    /// Connections closed after a response because keep-alive was disabled, or the client asked for the connection to be closed.
+    pub fn completed(&self) -> usize {
+        self.completed.load(Ordering::Relaxed)
+    }
+
+    /// Connections closed because the client didn't send a request within the socket timeout, including idle keep-alive connections.
+    pub fn timed_out(&self) -> usize {
+        self.timed_out.load(Ordering::Relaxed)
+    }
+
+    /// Connections the client closed while the server was waiting for a request.
+    pub fn client_closed(&self) -> usize {
+        self.client_closed.load(Ordering::Relaxed)
+    }
+
+    /// Connections the client reset or aborted.
+    pub fn client_reset(&self) -> usize {
+        self.client_reset.load(Ordering::Relaxed)
+    }
+
+    /// Connections closed because a route or middleware asked, with [`Response::close`] or [`Response::end`].
+    pub fn handler_closed(&self) -> usize {
+        self.handler_closed.load(Ordering::Relaxed)
+    }
+
+    /// Connections aborted with a TCP reset by [`Response::reset`].
+    pub fn aborted(&self) -> usize {
+        self.aborted.load(Ordering::Relaxed)
+    }
+
+    /// Connections closed because of any other error, like a malformed request.
+    pub fn errored(&self) -> usize {
+        self.errored.load(Ordering::Relaxed)
+    }
+
+    /// Connections that are currently open.
+    /// Only counted when the server is started with [`Server::start_threaded`], [`Server::start_split`] or [`Server::start_tls`].
+    pub fn open(&self) -> usize {
+        *self.open.force_lock()
+    }
+
+    /// Waits for there to be less than `max` open connections.
+    /// Returns false if the timeout was reached first.
+    #[cfg(feature = "tcp")]
+    fn wait_for_room(&self, max: usize, timeout: Duration) -> bool {
+        let open = self.open.force_lock();
+        let (open, _) = self
+            .closed
+            .wait_timeout_while(open, timeout, |open| *open >= max)
+            .unwrap_or_else(|e| e.into_inner());
+        *open < max
+    }
+
+    pub(crate) fn record(&self, reason: CloseReason) {
+        let counter = match reason {
+            CloseReason::Completed => &self.completed,
+            CloseReason::TimedOut => &self.timed_out,
+            CloseReason::ClientClosed => &self.client_closed,
+            CloseReason::ClientReset => &self.client_reset,
+            CloseReason::HandlerClosed => &self.handler_closed,
+            CloseReason::Aborted => &self.aborted,
+            CloseReason::Errored => &self.errored,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(feature = "tcp")]
+impl OpenConnection {
+    fn new(stats: Arc<ConnectionStats>) -> Self {
+        *stats.open.force_lock() += 1;
+        Self(stats)
+    }
+}
+
+#[cfg(feature = "tcp")]
+impl Drop for OpenConnection {
+    fn drop(&mut self) {
+        *self.0.open.force_lock() -= 1;
+        self.0.closed.notify_one();
+    }
+}
+
+impl BoundAddr {
+    /// Records the address the server is listening on, waking anything waiting for it.
+    #[cfg(feature = "tcp")]
+    fn set(&self, addr: SocketAddr) {
+        *self.0 .0.force_lock() = Some(addr);
+        self.0 .1.notify_all();
+    }
+
+    /// Gets the address the server is listening on.
+    /// Returns None if the server has not started listening yet.
+    pub fn get(&self) -> Option<SocketAddr> {
+        *self.0 .0.force_lock()
+    }
+
+    /// Blocks until the server is listening, then returns its address.
+    pub fn wait(&self) -> SocketAddr {
+        let mut addr = self.0 .0.force_lock();
+        loop {
+            if let Some(addr) = *addr {
+                return addr;
+            }
+            addr = self.0 .1.wait(addr).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+}
+
+impl StartTime {
+    /// Records the current time as the start time.
+    /// If the server was already started, the original time is kept.
+    fn set(&self) {
+        let _ = self.0.set((SystemTime::now(), Instant::now()));
+    }
+
+    /// Gets the wall clock time the server was started at.
+    /// Returns None if the server has not been started yet.
+    pub fn started_at(&self) -> Option<SystemTime> {
+        self.0.get().map(|x| x.0)
+    }
+
+    /// Gets how long the server has been running.
+    /// Returns None if the server has not been started yet.
+    pub fn uptime(&self) -> Option<Duration> {
+        self.0.get().map(|x| x.1.elapsed())
+    }
 }
@@ -0,0 +1,347 @@
+//! Test routes and middleware without binding a port.
+//!
+//! A [`TestServer`] runs requests through the same parsing, middleware, router and error handling as a real connection, using an in-memory [`Stream`] with [`Server::handle_stream`].
+//! Requests are built with [`TestRequest`] and the bytes written back are parsed into a [`TestResponse`].
+//!
+//! ## Example
+//! ```rust
+//! # use afire::{Server, Method, Response, Status, testing::{TestServer, TestRequest}};
+//! let mut server = Server::<()>::new("localhost", 8080);
+//! server.route(Method::POST, "/greet", |req| {
+//!     let name = String::from_utf8_lossy(&req.body);
+//!     Response::new().text(format!("Hello, {name}"))
+//! });
+//!
+//! let server = TestServer::from(server);
+//! let res = TestRequest::post("/greet")
+//!     .header("X-Test", "true")
+//!     .body("Darren")
+//!     .send(&server);
+//!
+//! assert_eq!(res.status, Status::Ok);
+//! assert_eq!(res.text(), "Hello, Darren");
+//! ```
+
+use std::{
+    borrow::Cow,
+    fmt::Write as _,
+    io::{self, Cursor, Read, Write},
+    net::{Shutdown, SocketAddr},
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    header::Headers, internal::common::ForceLock, transport::Stream, Header, HeaderType,
+    HttpVersion, Method, Server, Status,
+};
+
+/// A server that handles [`TestRequest`]s in-process.
+/// Made from a [`Server`] with all its routes and middleware already added.
+pub struct TestServer<State: 'static + Send + Sync = ()> {
+    server: Server<State>,
+}
+
+/// A request to send to a [`TestServer`].
+/// A `Content-Length` header is added for the body if one is not set.
+#[derive(Debug, Clone)]
+pub struct TestRequest {
+    method: Method,
+    path: String,
+    version: HttpVersion,
+    headers: Vec<Header>,
+    body: Vec<u8>,
+    address: SocketAddr,
+}
+
+/// A response from a [`TestServer`].
+#[derive(Debug, Clone)]
+pub struct TestResponse {
+    /// Response status code.
+    pub status: Status,
+    /// The reason phrase from the status line.
+    pub reason: String,
+    /// Response headers.
+    pub headers: Headers,
+    /// Response body.
+    /// Chunked bodies are decoded.
+    pub body: Vec<u8>,
+}
+
+/// A connection made from a request, that collects everything written to it.
+#[derive(Clone)]
+struct TestStream {
+    input: Arc<Mutex<Cursor<Vec<u8>>>>,
+    output: Arc<Mutex<Vec<u8>>>,
+    address: SocketAddr,
+}
+
+impl<State: 'static + Send + Sync> TestServer<State> {
+    /// Sends a request through the server, returning the response.
+    /// Panics if the server can not handle requests (See [`Server::handle_stream`]) or if it does not send a valid response, like when a middleware ends the connection.
+    pub fn send(&self, req: TestRequest) -> TestResponse {
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let stream = TestStream {
+            input: Arc::new(Mutex::new(Cursor::new(req.encode()))),
+            output: output.clone(),
+            address: req.address,
+        };
+
+        self.server
+            .handle_stream(stream)
+            .expect("Server can not handle requests");
+        let output = output.force_lock();
+        TestResponse::parse(&output).expect("Server did not send a valid response")
+    }
+
+    /// Gets a reference to the underlying server.
+    pub fn server(&self) -> &Server<State> {
+        &self.server
+    }
+}
+
+impl<State: 'static + Send + Sync> From<Server<State>> for TestServer<State> {
+    fn from(server: Server<State>) -> Self {
+        Self { server }
+    }
+}
+
+impl TestRequest {
+    /// Create a new request with a method and path.
+    /// The path can include a query string, like `/search?q=afire`.
+    pub fn new(method: Method, path: impl AsRef<str>) -> Self {
+        Self {
+            method,
+            path: path.as_ref().to_owned(),
+            version: HttpVersion::Http11,
+            headers: Vec::new(),
+            body: Vec::new(),
+            address: SocketAddr::from(([127, 0, 0, 1], 0)),
+        }
+    }
+
+    /// Create a new GET request.
+    pub fn get(path: impl AsRef<str>) -> Self {
+        Self::new(Method::GET, path)
+    }
+
+    /// Create a new POST request.
+    pub fn post(path: impl AsRef<str>) -> Self {
+        Self::new(Method::POST, path)
+    }
+
+    /// Create a new PUT request.
+    pub fn put(path: impl AsRef<str>) -> Self {
+        Self::new(Method::PUT, path)
+    }
+
+    /// Create a new PATCH request.
+    pub fn patch(path: impl AsRef<str>) -> Self {
+        Self::new(Method::PATCH, path)
+    }
+
+    /// Create a new DELETE request.
+    pub fn delete(path: impl AsRef<str>) -> Self {
+        Self::new(Method::DELETE, path)
+    }
+
+    /// Add a header to the request.
+    pub fn header(mut self, key: impl Into<HeaderType>, value: impl AsRef<str>) -> Self {
+        self.headers.push(Header::new(key, value));
+        self
+    }
+
+    /// Set the request body.
+    pub fn body(self, body: impl AsRef<[u8]>) -> Self {
+        Self {
+            body: body.as_ref().to_vec(),
+            ..self
+        }
+    }
+
+    /// Set the HTTP version of the request.
+    /// Defaults to HTTP/1.1.
+    pub fn version(self, version: HttpVersion) -> Self {
+        Self { version, ..self }
+    }
+
+    /// Set the address the request comes from.
+    /// Defaults to `127.0.0.1:0`.
+    pub fn address(self, address: SocketAddr) -> Self {
+        Self { address, ..self }
+    }
+
+    /// Sends the request to a server, returning the response.
+    /// Same as [`TestServer::send`].
+    pub fn send<State: 'static + Send + Sync>(self, server: &TestServer<State>) -> TestResponse {
+        server.send(self)
+    }
+
+    /// Encodes the request as it would be sent over a connection.
+    fn encode(&self) -> Vec<u8> {
+        let mut out = format!("{} {} {}\r\n", self.method, self.path, self.version);
+        let has = |name: HeaderType| self.headers.iter().any(|x| x.name == name);
+        if !has(HeaderType::ContentLength) && !has(HeaderType::TransferEncoding) {
+            let _ = write!(out, "Content-Length: {}\r\n", self.body.len());
+        }
+        for i in &self.headers {
+            let _ = write!(out, "{i}\r\n");
+        }
+        out.push_str("\r\n");
+
+        let mut out = out.into_bytes();
+        out.extend_from_slice(&self.body);
+        out
+    }
+}
+
+impl TestResponse {
+    /// Gets the body as text.
+    /// Invalid UTF-8 is replaced with `�`.
+    pub fn text(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.body)
+    }
+
+    /// Gets the value of a header, if it is present.
+    pub fn header(&self, name: impl Into<HeaderType>) -> Option<&str> {
+        self.headers.get(name)
+    }
+
+    /// Parses the first response written to a connection.
+    fn parse(data: &[u8]) -> Option<Self> {
+        let end = data.windows(4).position(|x| x == b"\r\n\r\n")?;
+        let head = std::str::from_utf8(&data[..end]).ok()?;
+        let mut lines = head.split("\r\n");
+
+        let mut status_line = lines.next()?.splitn(3, ' ');
+        status_line.next()?.parse::<HttpVersion>().ok()?;
+        let status = Status::from(status_line.next()?.parse::<u16>().ok()?);
+        let reason = status_line.next().unwrap_or_default().to_owned();
+
+        let headers = Headers(
+            lines
+                .map(Header::from_string)
+                .collect::<Result<Vec<_>, _>>()
+                .ok()?,
+        );
+
+        let rest = &data[end + 4..];
+        let chunked = headers
+            .get(HeaderType::TransferEncoding)
+            .is_some_and(|x| x.eq_ignore_ascii_case("chunked"));
+        let body = if chunked {
+            decode_chunked(rest)?
+        } else if let Some(len) = headers.get(HeaderType::ContentLength) {
+            rest.get(..len.parse().ok()?)?.to_vec()
+        } else {
+            rest.to_vec()
+        };
+
+        Some(Self {
+            status,
+            reason,
+            headers,
+            body,
+        })
+    }
+}
+
+/// Decodes a chunked body, ignoring chunk extensions and trailers.
+fn decode_chunked(mut data: &[u8]) -> Option<Vec<u8>> {
+    let mut body = Vec::new();
+    loop {
+        let line_end = data.windows(2).position(|x| x == b"\r\n")?;
+        let line = std::str::from_utf8(&data[..line_end]).ok()?;
+        let size = line.split(';').next()?.trim();
+        let size = usize::from_str_radix(size, 16).ok()?;
+        data = &data[line_end + 2..];
+
+        if size == 0 {
+            return Some(body);
+        }
+
+        body.extend_from_slice(data.get(..size)?);
+        data = data.get(size + 2..)?;
+    }
+}
+
+impl Read for TestStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.input.force_lock().read(buf)
+    }
+}
+
+impl Write for TestStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.force_lock().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Stream for TestStream {
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.address)
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn Stream>> {
+        Ok(Box::new(self.clone()))
+    }
+
+    fn shutdown(&self, _how: Shutdown) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{TestRequest, TestServer};
+    use crate::{Method, Response, Server, Status};
+
+    fn server() -> TestServer {
+        let mut server = Server::<()>::new("localhost", 8080).method_not_allowed(true);
+        server.route(Method::GET, "/hello/{name}", |req| {
+            Response::new()
+                .text(format!("Hello, {}", req.param("name").unwrap()))
+                .header("X-Greeting", "true")
+        });
+        server.route(Method::POST, "/echo", |req| {
+            Response::new()
+                .bytes(&req.body)
+                .header("X-Test", req.headers.get("X-Test").unwrap_or_default())
+        });
+        server.route(Method::GET, "/stream", |_req| {
+            Response::new().stream(&b"streamed"[..])
+        });
+        server.into()
+    }
+
+    #[test]
+    fn test_send() {
+        let server = server();
+
+        let res = TestRequest::get("/hello/Darren").send(&server);
+        assert_eq!(res.status, Status::Ok);
+        assert_eq!(res.reason, "OK");
+        assert_eq!(res.header("X-Greeting"), Some("true"));
+        assert_eq!(res.text(), "Hello, Darren");
+
+        let res = TestRequest::post("/echo")
+            .header("X-Test", "yes")
+            .body("body")
+            .send(&server);
+        assert_eq!(res.header("X-Test"), Some("yes"));
+        assert_eq!(res.body, b"body");
+
+        let res = TestRequest::get("/stream").send(&server);
+        assert_eq!(res.text(), "streamed");
+
+        let res = TestRequest::delete("/echo").send(&server);
+        assert_eq!(res.status, Status::MethodNotAllowed);
+        assert_eq!(res.header("Allow"), Some("POST"));
+
+        let res = TestRequest::get("/missing").send(&server);
+        assert_eq!(res.status, Status::NotFound);
+    }
+}
@@ -1,27 +1,108 @@
 //! A thread pool implementation.
 //! Used for handling multiple connections at once.
+//! Only the TCP event loop runs a pool, but [`PoolStats`] is always available.
+#![cfg_attr(not(feature = "tcp"), allow(dead_code))]
 
-use std::sync::{mpsc, Arc, Mutex};
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Condvar, Mutex, PoisonError,
+};
 use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
 use crate::internal::common::ForceLock;
 
-/// Messages that can be handled by the pool's workers.
-enum Message {
-    /// Stops the worker.
-    Kill,
-    /// A job to be executed by the worker.
-    Job(Box<dyn FnOnce() + 'static + Send>),
-}
+type Job = Box<dyn FnOnce() + 'static + Send>;
+pub(crate) type ThreadHook = Arc<dyn Fn() + Send + Sync>;
 
 /// A thread pool.
 pub(crate) struct ThreadPool {
-    /// The number of threads in the pool.
-    threads: usize,
+    /// The prefix of the worker thread names.
+    name: String,
     /// Handle to each worker thread.
-    workers: Vec<Worker>,
-    /// The channel used to send messages to the workers.
-    sender: mpsc::Sender<Message>,
+    /// Workers that stopped after being idle are removed when new ones are added.
+    workers: Mutex<Vec<Worker>>,
+    /// The id of the next worker to be added.
+    next_id: AtomicUsize,
+    /// How many workers the pool can have.
+    scaling: Scaling,
+    /// Run on each worker thread when it starts and stops.
+    hooks: ThreadHooks,
+    /// The queue shared with the workers.
+    queue: Arc<Queue>,
+    /// Live statistics about the pool.
+    stats: Arc<PoolStats>,
+}
+
+/// How a [`ThreadPool`] grows and shrinks.
+/// When `min` and `max` are the same the pool is a fixed size.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Scaling {
+    /// The number of workers the pool starts with, and never shrinks below.
+    pub min: usize,
+    /// The most workers the pool can grow to when jobs are waiting.
+    pub max: usize,
+    /// How long a worker above `min` waits for a job before stopping.
+    pub idle: Duration,
+}
+
+/// Functions run on each worker thread, see [`crate::Server::thread_init`].
+#[derive(Clone, Default)]
+pub(crate) struct ThreadHooks {
+    /// Run when a worker starts, before it takes any jobs.
+    pub init: Option<ThreadHook>,
+    /// Run when a worker stops, after its last job.
+    pub teardown: Option<ThreadHook>,
+}
+
+/// Jobs waiting for a worker.
+struct Queue {
+    jobs: Mutex<Jobs>,
+    /// Notified when a job is added or the pool is stopped.
+    available: Condvar,
+}
+
+/// The jobs in a [`Queue`], grouped by client.
+/// When fair scheduling is off, all jobs are in one group, so they run in the order they were added.
+#[derive(Default)]
+struct Jobs {
+    /// If jobs should be taken from each client in turn.
+    fair: bool,
+    /// Set when the pool is dropped, workers stop once the queue is empty.
+    stopped: bool,
+    /// The clients with queued jobs, in the order they will be served.
+    order: VecDeque<Option<IpAddr>>,
+    /// The queued jobs of each client.
+    clients: HashMap<Option<IpAddr>, VecDeque<(Option<IpAddr>, Job)>>,
+}
+
+/// Live statistics about one of the server's thread pools.
+/// See [`crate::Server::pool_stats`].
+#[derive(Debug, Default)]
+pub struct PoolStats {
+    threads: AtomicUsize,
+    queued: AtomicUsize,
+    active: AtomicUsize,
+    panicked: AtomicUsize,
+    pub(crate) overflowed: AtomicUsize,
+    /// Jobs queued and running for each client that has any.
+    clients: Mutex<HashMap<IpAddr, ClientShare>>,
+    /// Notified when a worker takes a job from the queue.
+    freed: Condvar,
+    freed_lock: Mutex<()>,
+}
+
+/// How much of a thread pool one client is using.
+/// See [`PoolStats::clients`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ClientShare {
+    /// The number of the client's jobs waiting for a worker.
+    pub queued: usize,
+    /// The number of workers running one of the client's jobs.
+    pub active: usize,
 }
 
 /// A worker thread.
@@ -32,43 +113,218 @@ struct Worker {
 }
 
 impl ThreadPool {
-    /// Creates a new thread pool with the specified number of threads.
-    /// Panics if `size` is 0.
-    pub(crate) fn new(size: usize) -> Self {
-        assert!(size > 0);
+    /// Creates a new thread pool that starts with `scaling.min` threads, recording its statistics into `stats`.
+    /// The worker threads are named `{name}-{id}`, and run the `hooks` when they start and stop.
+    /// If `fair` is set, queued jobs are taken from each client in turn, instead of in the order they were added.
+    /// Panics if `scaling.min` is 0.
+    pub(crate) fn new(
+        name: &str,
+        scaling: Scaling,
+        hooks: ThreadHooks,
+        fair: bool,
+        stats: Arc<PoolStats>,
+    ) -> Self {
+        assert!(scaling.min > 0);
+        let scaling = Scaling {
+            max: scaling.max.max(scaling.min),
+            ..scaling
+        };
 
-        let (tx, rx) = mpsc::channel();
-        let mut workers = Vec::with_capacity(size);
+        let queue = Arc::new(Queue {
+            jobs: Mutex::new(Jobs {
+                fair,
+                ..Jobs::default()
+            }),
+            available: Condvar::new(),
+        });
 
-        let receiver = Arc::new(Mutex::new(rx));
-        for i in 0..size {
-            workers.push(Worker::new(i, Arc::clone(&receiver)));
-        }
+        let pool = Self {
+            name: name.to_owned(),
+            workers: Mutex::new(Vec::with_capacity(scaling.min)),
+            next_id: AtomicUsize::new(0),
+            scaling,
+            hooks,
+            queue,
+            stats,
+        };
 
-        Self {
-            threads: size,
-            sender: tx,
-            workers,
+        let mut workers = pool.workers.force_lock();
+        for _ in 0..scaling.min {
+            pool.stats.threads.fetch_add(1, Ordering::Relaxed);
+            workers.push(pool.spawn());
         }
+        drop(workers);
+
+        pool
     }
 
     /// Executes a job on the thread pool.
-    pub(crate) fn execute(&self, f: impl FnOnce() + 'static + Send) {
-        let job = Message::Job(Box::new(f));
-        self.sender.send(job).unwrap();
+    /// `client` is the address of the client the job is for, used for fair scheduling and [`PoolStats::clients`].
+    /// If there are more jobs waiting than idle workers, and the pool is below its max size, another worker is added.
+    pub(crate) fn execute(&self, client: Option<IpAddr>, f: impl FnOnce() + 'static + Send) {
+        self.stats.queued.fetch_add(1, Ordering::Relaxed);
+        self.stats.update_client(client, |x| x.queued += 1);
+        self.queue.jobs.force_lock().push(client, Box::new(f));
+        self.queue.available.notify_one();
+
+        if self.scaling.max > self.scaling.min {
+            self.grow();
+        }
+    }
+
+    /// Adds a worker if the queued jobs can't all be picked up by idle workers.
+    fn grow(&self) {
+        let mut workers = self.workers.force_lock();
+        let threads = self.stats.threads();
+        let idle = threads.saturating_sub(self.stats.active());
+        if threads >= self.scaling.max || self.stats.queued() <= idle {
+            return;
+        }
+
+        // Stopped workers have already exited, so dropping their handles doesn't leak anything
+        workers.retain(|x| x.handle.as_ref().is_some_and(|x| !x.is_finished()));
+        self.stats.threads.fetch_add(1, Ordering::Relaxed);
+        workers.push(self.spawn());
+    }
+
+    /// Starts a new worker thread.
+    /// The caller is responsible for counting it in [`PoolStats::threads`].
+    fn spawn(&self) -> Worker {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        Worker::new(
+            format!("{}-{id}", self.name),
+            id,
+            self.scaling,
+            self.hooks.clone(),
+            self.queue.clone(),
+            self.stats.clone(),
+        )
+    }
+
+    /// Gets the pool's statistics.
+    pub(crate) fn stats(&self) -> &PoolStats {
+        &self.stats
+    }
+
+    /// Blocks until fewer than `limit` jobs are waiting in the queue, or `timeout` has passed.
+    /// Returns true if there is room in the queue.
+    pub(crate) fn wait_for_room(&self, limit: usize, timeout: Duration) -> bool {
+        let start = Instant::now();
+        let mut guard = self.stats.freed_lock.force_lock();
+        while self.stats.queued() >= limit {
+            let elapsed = start.elapsed();
+            if elapsed >= timeout {
+                return false;
+            }
+
+            // Wake up every so often in case a notification is missed between checking the queue and waiting
+            let wait = (timeout - elapsed).min(Duration::from_millis(10));
+            guard = match self.stats.freed.wait_timeout(guard, wait) {
+                Ok((guard, _)) => guard,
+                Err(e) => e.into_inner().0,
+            };
+        }
+
+        true
+    }
+}
+
+impl Jobs {
+    fn push(&mut self, client: Option<IpAddr>, job: Job) {
+        let key = if self.fair { client } else { None };
+        let jobs = self.clients.entry(key).or_default();
+        if jobs.is_empty() {
+            self.order.push_back(key);
+        }
+        jobs.push_back((client, job));
+    }
+
+    /// Takes the next job, from the client at the front of the order.
+    /// If the client has more jobs, it is moved to the back.
+    fn pop(&mut self) -> Option<(Option<IpAddr>, Job)> {
+        let key = self.order.pop_front()?;
+        let jobs = self.clients.get_mut(&key)?;
+        let job = jobs.pop_front();
+        if jobs.is_empty() {
+            self.clients.remove(&key);
+        } else {
+            self.order.push_back(key);
+        }
+        job
+    }
+}
+
+impl PoolStats {
+    /// The number of worker threads in the pool.
+    /// With [`crate::Server::max_threads`] set, this changes as the pool grows and shrinks.
+    pub fn threads(&self) -> usize {
+        self.threads.load(Ordering::Relaxed)
+    }
+
+    /// The number of jobs waiting for a free worker.
+    pub fn queued(&self) -> usize {
+        self.queued.load(Ordering::Relaxed)
+    }
+
+    /// The number of workers currently running a job.
+    pub fn active(&self) -> usize {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    /// The number of jobs that panicked.
+    /// The panic is caught, so the worker carries on with the next job.
+    pub fn panicked(&self) -> usize {
+        self.panicked.load(Ordering::Relaxed)
+    }
+
+    /// The number of connections that were rejected or dropped because the queue was full.
+    /// See [`crate::Server::overflow_policy`].
+    pub fn overflowed(&self) -> usize {
+        self.overflowed.load(Ordering::Relaxed)
+    }
+
+    /// How many queued and running jobs each client has, for finding clients that are taking up most of the pool.
+    /// Only clients with at least one queued or running job are included.
+    /// With [`crate::Server::start_threaded`] each job is a connection, with [`crate::Server::start_split`] handler pool jobs are single requests.
+    pub fn clients(&self) -> HashMap<IpAddr, ClientShare> {
+        self.clients.force_lock().clone()
+    }
+
+    fn update_client(&self, client: Option<IpAddr>, update: impl FnOnce(&mut ClientShare)) {
+        let Some(client) = client else {
+            return;
+        };
+
+        let mut clients = self.clients.force_lock();
+        let share = clients.entry(client).or_default();
+        update(share);
+        if *share == ClientShare::default() {
+            clients.remove(&client);
+        }
     }
 }
 
 impl Worker {
     /// Creates a new worker thread.
-    fn new(id: usize, rx: Arc<Mutex<mpsc::Receiver<Message>>>) -> Self {
+    fn new(
+        name: String,
+        id: usize,
+        scaling: Scaling,
+        hooks: ThreadHooks,
+        queue: Arc<Queue>,
+        stats: Arc<PoolStats>,
+    ) -> Self {
         let handle = thread::Builder::new()
-            .name(format!("Worker {id}"))
-            .spawn(move || loop {
-                let job = rx.force_lock().recv().unwrap();
-                match job {
-                    Message::Job(job) => job(),
-                    Message::Kill => break,
+            .name(name)
+            .spawn(move || {
+                if let Some(init) = &hooks.init {
+                    init();
+                }
+
+                Self::run(scaling, &queue, &stats);
+
+                if let Some(teardown) = &hooks.teardown {
+                    teardown();
                 }
             })
             .expect("Error creating worker thread");
@@ -78,19 +334,270 @@ impl Worker {
             handle: Some(handle),
         }
     }
+
+    /// Runs jobs from the queue until the pool is stopped.
+    /// Workers above `scaling.min` stop once they have been idle for `scaling.idle`.
+    fn run(scaling: Scaling, queue: &Queue, stats: &PoolStats) {
+        loop {
+            let mut jobs = queue.jobs.force_lock();
+            let (client, job) = loop {
+                if let Some(job) = jobs.pop() {
+                    break job;
+                }
+
+                if jobs.stopped {
+                    return;
+                }
+
+                if stats.threads() <= scaling.min {
+                    jobs = match queue.available.wait(jobs) {
+                        Ok(i) => i,
+                        Err(e) => e.into_inner(),
+                    };
+                    continue;
+                }
+
+                let (i, timeout) = queue
+                    .available
+                    .wait_timeout(jobs, scaling.idle)
+                    .unwrap_or_else(PoisonError::into_inner);
+                jobs = i;
+
+                // Only stop if that doesn't take the pool below its min size
+                if timeout.timed_out()
+                    && jobs.order.is_empty()
+                    && stats
+                        .threads
+                        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |x| {
+                            (x > scaling.min).then(|| x - 1)
+                        })
+                        .is_ok()
+                {
+                    return;
+                }
+            };
+            drop(jobs);
+
+            stats.queued.fetch_sub(1, Ordering::Relaxed);
+            stats.update_client(client, |x| {
+                x.queued -= 1;
+                x.active += 1;
+            });
+            stats.freed.notify_one();
+            stats.active.fetch_add(1, Ordering::Relaxed);
+            if panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+                stats.panicked.fetch_add(1, Ordering::Relaxed);
+            }
+            stats.active.fetch_sub(1, Ordering::Relaxed);
+            stats.update_client(client, |x| x.active -= 1);
+        }
+    }
 }
 
 impl Drop for ThreadPool {
-    /// Stops all workers with a [`Message::Kill`] message, and waits for them to finish.
+    /// Stops all workers once the queue is empty, and waits for them to finish.
     fn drop(&mut self) {
-        for _ in 0..self.threads {
-            self.sender.send(Message::Kill).unwrap();
-        }
+        self.queue.jobs.force_lock().stopped = true;
+        self.queue.available.notify_all();
 
-        for worker in &mut self.workers {
+        for worker in self.workers.force_lock().iter_mut() {
             if let Some(thread) = worker.handle.take() {
                 thread.join().unwrap();
             }
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::{
+        net::{IpAddr, Ipv4Addr},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            mpsc, Arc, Mutex,
+        },
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use super::{ClientShare, Jobs, PoolStats, Scaling, ThreadHooks, ThreadPool};
+
+    fn fixed(size: usize) -> Scaling {
+        Scaling {
+            min: size,
+            max: size,
+            idle: Duration::MAX,
+        }
+    }
+
+    fn order(fair: bool) -> Vec<u8> {
+        let ran = Arc::new(Mutex::new(Vec::new()));
+        let mut jobs = Jobs {
+            fair,
+            ..Jobs::default()
+        };
+
+        for (i, client) in [1, 1, 1, 2, 3, 2].iter().enumerate() {
+            let ran = ran.clone();
+            let ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, *client));
+            jobs.push(
+                Some(ip),
+                Box::new(move || ran.lock().unwrap().push(i as u8)),
+            );
+        }
+
+        while let Some((_, job)) = jobs.pop() {
+            job();
+        }
+
+        let ran = ran.lock().unwrap().clone();
+        ran
+    }
+
+    #[test]
+    fn test_fair_order() {
+        assert_eq!(order(false), [0, 1, 2, 3, 4, 5]);
+        assert_eq!(order(true), [0, 3, 4, 1, 5, 2]);
+    }
+
+    #[test]
+    fn test_client_stats() {
+        let stats = Arc::new(PoolStats::default());
+        let pool = ThreadPool::new(
+            "Test",
+            fixed(1),
+            ThreadHooks::default(),
+            true,
+            stats.clone(),
+        );
+        let client = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+        let (started_tx, started) = mpsc::channel();
+        let (finish, finish_rx) = mpsc::channel::<()>();
+        pool.execute(Some(client), move || {
+            started_tx.send(()).unwrap();
+            finish_rx.recv().unwrap();
+        });
+        pool.execute(Some(client), || {});
+        started.recv().unwrap();
+
+        assert_eq!(
+            stats.clients()[&client],
+            ClientShare {
+                queued: 1,
+                active: 1
+            }
+        );
+
+        finish.send(()).unwrap();
+        drop(pool);
+        assert!(stats.clients().is_empty());
+    }
+
+    #[test]
+    fn test_panic_recovery() {
+        let stats = Arc::new(PoolStats::default());
+        let pool = ThreadPool::new(
+            "Test",
+            fixed(1),
+            ThreadHooks::default(),
+            false,
+            stats.clone(),
+        );
+
+        let (tx, rx) = mpsc::channel();
+        pool.execute(None, || panic!("Job panicked"));
+        pool.execute(None, move || tx.send(()).unwrap());
+
+        // The worker carries on after the panic
+        rx.recv().unwrap();
+        drop(pool);
+        assert_eq!(stats.panicked(), 1);
+        assert_eq!(stats.active(), 0);
+        assert_eq!(stats.threads(), 1);
+    }
+
+    #[test]
+    fn test_autoscale() {
+        let stats = Arc::new(PoolStats::default());
+        let scaling = Scaling {
+            min: 1,
+            max: 3,
+            idle: Duration::from_millis(50),
+        };
+        let pool = ThreadPool::new(
+            "Test",
+            scaling,
+            ThreadHooks::default(),
+            false,
+            stats.clone(),
+        );
+        assert_eq!(stats.threads(), 1);
+
+        let (started_tx, started) = mpsc::channel();
+        let (finish, finish_rx) = mpsc::channel::<()>();
+        let finish_rx = Arc::new(Mutex::new(finish_rx));
+        for _ in 0..5 {
+            let started_tx = started_tx.clone();
+            let finish_rx = finish_rx.clone();
+            pool.execute(None, move || {
+                started_tx.send(()).unwrap();
+                finish_rx.lock().unwrap().recv().unwrap();
+            });
+        }
+
+        // Grows to the max size, with the rest of the jobs left in the queue
+        for _ in 0..3 {
+            started.recv().unwrap();
+        }
+        assert_eq!(stats.threads(), 3);
+        assert_eq!(stats.queued(), 2);
+
+        for _ in 0..5 {
+            finish.send(()).unwrap();
+        }
+
+        // Shrinks back to the min size once the extra workers are idle
+        let start = Instant::now();
+        while stats.threads() > 1 && start.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(stats.threads(), 1);
+        assert_eq!(stats.queued(), 0);
+    }
+
+    #[test]
+    fn test_thread_hooks() {
+        let started = Arc::new(AtomicUsize::new(0));
+        let stopped = Arc::new(AtomicUsize::new(0));
+        let hooks = ThreadHooks {
+            init: Some(Arc::new({
+                let started = started.clone();
+                move || {
+                    started.fetch_add(1, Ordering::Relaxed);
+                }
+            })),
+            teardown: Some(Arc::new({
+                let stopped = stopped.clone();
+                move || {
+                    stopped.fetch_add(1, Ordering::Relaxed);
+                }
+            })),
+        };
+
+        let stats = Arc::new(PoolStats::default());
+        let pool = ThreadPool::new("test-worker", fixed(2), hooks, false, stats);
+        let (tx, rx) = mpsc::channel();
+        pool.execute(None, move || {
+            tx.send(thread::current().name().map(ToOwned::to_owned))
+                .unwrap()
+        });
+
+        let name = rx.recv().unwrap().unwrap();
+        assert!(["test-worker-0", "test-worker-1"].contains(&name.as_str()));
+
+        drop(pool);
+        assert_eq!(started.load(Ordering::Relaxed), 2);
+        assert_eq!(stopped.load(Ordering::Relaxed), 2);
+    }
+}
@@ -0,0 +1,300 @@
+//! HTTPS support, using [rustls](https://docs.rs/rustls).
+//! Enabled with the `tls` feature.
+//!
+//! Load a certificate chain and private key into a [`TlsConfig`], then start the server with [`crate::Server::start_tls`].
+//! Connections that fail the TLS handshake (like plain HTTP requests) are logged at [`crate::trace::Level::Debug`] and closed, without taking up a worker for longer than the handshake.
+//! The handshake has to finish within the [`crate::Server::head_timeout`].
+//!
+//! ## Example
+//! ```rust,no_run
+//! # use afire::{Server, Method, Response, tls::TlsConfig};
+//! let mut server = Server::<()>::new("localhost", 8443);
+//! server.route(Method::GET, "/", |_req| Response::new().text("Hello over HTTPS"));
+//!
+//! let tls = TlsConfig::from_files("cert.pem", "key.pem").unwrap();
+//! server.start_tls(4, tls).unwrap();
+//! ```
+//!
+//! Note that WebSockets are not supported over TLS yet.
+
+use std::{
+    fs,
+    io::{self, Read, Write},
+    net::{Shutdown, SocketAddr, TcpStream},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use rustls::{
+    crypto::{ring, CryptoProvider},
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+    server::WebPkiClientVerifier,
+    RootCertStore, ServerConfig, ServerConnection, StreamOwned,
+};
+
+use crate::transport::{ConnectionInfo, Stream};
+
+/// The certificate and settings used to accept TLS connections.
+/// See the [module docs](crate::tls) for an example.
+#[derive(Clone)]
+pub struct TlsConfig {
+    pub(crate) config: Arc<ServerConfig>,
+}
+
+impl TlsConfig {
+    /// Creates a config from a PEM encoded certificate chain and private key.
+    /// The chain should start with the server's certificate, followed by any intermediates.
+    /// Returns an [`io::ErrorKind::InvalidData`] error if the certificates or key can't be parsed, or the key doesn't match the certificate.
+    pub fn from_pem(cert_chain: impl AsRef<[u8]>, key: impl AsRef<[u8]>) -> io::Result<Self> {
+        Self::build(cert_chain.as_ref(), key.as_ref(), None)
+    }
+
+    /// Creates a config from PEM encoded certificate chain and private key files.
+    /// See [`TlsConfig::from_pem`].
+    pub fn from_files(cert_chain: impl AsRef<Path>, key: impl AsRef<Path>) -> io::Result<Self> {
+        Self::from_pem(fs::read(cert_chain)?, fs::read(key)?)
+    }
+
+    /// Creates a config to ask clients for a certificate signed by one of the PEM encoded `roots`.
+    /// If `required` is true, clients without a valid certificate will fail the handshake, otherwise the certificate is optional.
+    /// The certificates a client sent can be read with [`crate::Request::peer_certificates`].
+    pub fn with_client_auth(
+        cert_chain: impl AsRef<[u8]>,
+        key: impl AsRef<[u8]>,
+        roots: impl AsRef<[u8]>,
+        required: bool,
+    ) -> io::Result<Self> {
+        Self::build(
+            cert_chain.as_ref(),
+            key.as_ref(),
+            Some((roots.as_ref(), required)),
+        )
+    }
+
+    /// Uses a rustls config directly, for settings not covered by the other constructors.
+    pub fn from_rustls(config: Arc<ServerConfig>) -> Self {
+        Self { config }
+    }
+
+    fn build(
+        cert_chain: &[u8],
+        key: &[u8],
+        client_auth: Option<(&[u8], bool)>,
+    ) -> io::Result<Self> {
+        let provider = Arc::new(ring::default_provider());
+        let cert_chain = CertificateDer::pem_slice_iter(cert_chain)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(invalid_data)?;
+        let key = PrivateKeyDer::from_pem_slice(key).map_err(invalid_data)?;
+
+        let builder = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(invalid_data)?;
+        let builder = match client_auth {
+            Some((roots, required)) => {
+                builder.with_client_cert_verifier(client_verifier(roots, required, provider)?)
+            }
+            None => builder.with_no_client_auth(),
+        };
+
+        let config = builder
+            .with_single_cert(cert_chain, key)
+            .map_err(invalid_data)?;
+        Ok(Self {
+            config: Arc::new(config),
+        })
+    }
+
+    /// Runs the TLS handshake on a new connection.
+    /// The whole handshake has to finish within `deadline` (the [`crate::Server::head_timeout`]), so clients can't hold a worker by sending it a byte at a time.
+    /// `timeout` is the socket timeout, which also limits each read and write.
+    pub(crate) fn accept(
+        &self,
+        mut socket: TcpStream,
+        deadline: Option<Duration>,
+        timeout: Option<Duration>,
+    ) -> io::Result<TlsStream> {
+        let mut conn = ServerConnection::new(self.config.clone()).map_err(invalid_data)?;
+        let mut handshake = HandshakeSocket {
+            socket: &mut socket,
+            deadline: deadline.map(|x| Instant::now() + x),
+            timeout,
+        };
+        while conn.is_handshaking() {
+            conn.complete_io(&mut handshake)?;
+        }
+
+        socket.set_read_timeout(timeout)?;
+        socket.set_write_timeout(timeout)?;
+        Ok(TlsStream {
+            stream: StreamOwned::new(conn, socket),
+            aborted: AtomicBool::new(false),
+        })
+    }
+}
+
+/// The socket of a connection that is still in the TLS handshake.
+/// rustls keeps reading until the handshake is done, so each read and write is limited to what's left of the deadline.
+struct HandshakeSocket<'a> {
+    socket: &'a mut TcpStream,
+    deadline: Option<Instant>,
+    /// The socket timeout, which limits each read and write if it is shorter than what's left of the deadline.
+    timeout: Option<Duration>,
+}
+
+impl HandshakeSocket<'_> {
+    /// Sets the socket's timeouts for the next read or write, or fails if the deadline has passed.
+    fn start(&mut self) -> io::Result<()> {
+        let timeout = match self.deadline {
+            Some(deadline) => {
+                let left = deadline.saturating_duration_since(Instant::now());
+                if left.is_zero() {
+                    return Err(io::ErrorKind::TimedOut.into());
+                }
+                Some(self.timeout.map_or(left, |x| x.min(left)))
+            }
+            None => self.timeout,
+        };
+
+        self.socket.set_read_timeout(timeout)?;
+        self.socket.set_write_timeout(timeout)
+    }
+}
+
+impl Read for HandshakeSocket<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.start()?;
+        self.socket.read(buf)
+    }
+}
+
+impl Write for HandshakeSocket<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.start()?;
+        self.socket.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.socket.flush()
+    }
+}
+
+fn client_verifier(
+    roots: &[u8],
+    required: bool,
+    provider: Arc<CryptoProvider>,
+) -> io::Result<Arc<dyn rustls::server::danger::ClientCertVerifier>> {
+    let mut store = RootCertStore::empty();
+    for cert in CertificateDer::pem_slice_iter(roots) {
+        store
+            .add(cert.map_err(invalid_data)?)
+            .map_err(invalid_data)?;
+    }
+
+    let mut verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(store), provider);
+    if !required {
+        verifier = verifier.allow_unauthenticated();
+    }
+
+    verifier.build().map_err(invalid_data)
+}
+
+fn invalid_data(err: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+}
+
+/// A TLS connection to a client.
+pub(crate) struct TlsStream {
+    stream: StreamOwned<ServerConnection, TcpStream>,
+    /// If the connection was aborted, in which case no close_notify is sent.
+    aborted: AtomicBool,
+}
+
+impl Read for TlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.read(buf)
+    }
+}
+
+impl Write for TlsStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl Drop for TlsStream {
+    fn drop(&mut self) {
+        if self.aborted.load(Ordering::Relaxed) {
+            return;
+        }
+
+        self.stream.conn.send_close_notify();
+        let _ = self.stream.conn.complete_io(&mut self.stream.sock);
+        let _ = self.stream.sock.shutdown(Shutdown::Both);
+    }
+}
+
+impl Stream for TlsStream {
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.stream.sock.peer_addr()
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.stream.sock.local_addr()
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn Stream>> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "TLS streams can't be cloned",
+        ))
+    }
+
+    fn shutdown(&self, _how: Shutdown) -> io::Result<()> {
+        // The TLS connection needs to send a close_notify alert before the socket is shut down, which needs a mutable reference.
+        // So this is done when the stream is dropped instead.
+        Ok(())
+    }
+
+    fn abort(&self) -> io::Result<()> {
+        self.aborted.store(true, Ordering::Relaxed);
+        Stream::abort(&self.stream.sock)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.stream.sock.set_read_timeout(timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.stream.sock.set_write_timeout(timeout)
+    }
+
+    fn write_timeout(&self) -> io::Result<Option<Duration>> {
+        self.stream.sock.write_timeout()
+    }
+
+    fn peer_certificates(&self) -> Option<Vec<Vec<u8>>> {
+        let certs = self.stream.conn.peer_certificates()?;
+        Some(certs.iter().map(|x| x.to_vec()).collect())
+    }
+
+    fn connection_info(&self) -> Option<ConnectionInfo> {
+        let conn = &self.stream.conn;
+        Some(ConnectionInfo {
+            protocol: format!("{:?}", conn.protocol_version()?),
+            cipher_suite: format!("{:?}", conn.negotiated_cipher_suite()?.suite()),
+            alpn_protocol: conn
+                .alpn_protocol()
+                .map(|x| String::from_utf8_lossy(x).into_owned()),
+            server_name: conn.server_name().map(str::to_owned),
+        })
+    }
+}
@@ -24,7 +24,7 @@ static FORMATTER_PRESENT: AtomicBool = AtomicBool::new(false);
 /// Used to control the verbosity of afire's internal logging.
 /// The default log level is [`Level::Off`].
 #[repr(u8)]
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Level {
     /// Disables all logging.
     Off = 0,
@@ -85,6 +85,29 @@ pub fn set_log_formatter(formatter: impl Formatter + Send + Sync + 'static) {
     *FORMATTER.write().unwrap() = Some(Box::new(formatter));
 }
 
+/// Sets a function to handle afire's log messages, instead of printing them.
+/// This is a simpler way to use [`set_log_formatter`], for passing afire's logs (including the startup messages) to another logging system like `log`, `tracing` or syslog.
+/// The messages passed to the handler are never colorized.
+/// ## Example
+/// ```rust
+/// use afire::trace::{self, Level};
+///
+/// trace::set_log_level(Level::Trace);
+/// trace::set_log_handler(|level, msg| match level {
+///     Level::Error => eprintln!("afire error: {msg}"),
+///     _ => println!("afire: {msg}"),
+/// });
+/// ```
+pub fn set_log_handler(handler: impl Fn(Level, &str) + Send + Sync + 'static) {
+    set_log_formatter(HandlerFormatter(handler));
+}
+
+/// Removes the log formatter or handler, going back to the [`DefaultFormatter`].
+pub fn reset_log_formatter() {
+    FORMATTER_PRESENT.store(false, Ordering::Relaxed);
+    *FORMATTER.write().unwrap() = None;
+}
+
 /// Logs a message at the specified log level.
 /// Hidden from the docs, as it is only intended for internal use through the [`trace!`] macro.
 #[doc(hidden)]
@@ -139,6 +162,15 @@ pub trait Formatter {
     fn format(&self, level: Level, color: bool, msg: String);
 }
 
+/// A formatter that passes messages to a function, see [`set_log_handler`].
+struct HandlerFormatter<F>(F);
+
+impl<F: Fn(Level, &str)> Formatter for HandlerFormatter<F> {
+    fn format(&self, level: Level, _color: bool, msg: String) {
+        (self.0)(level, &msg)
+    }
+}
+
 /// The default log formatter.
 /// afire will use this if no custom formatter is set.
 ///
@@ -161,3 +193,29 @@ impl Formatter for DefaultFormatter {
         );
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use super::{_trace, reset_log_formatter, set_log_handler, set_log_level, Level};
+
+    #[test]
+    fn test_log_handler() {
+        let logs = Arc::new(Mutex::new(Vec::new()));
+        let handler_logs = logs.clone();
+        set_log_handler(move |level, msg| {
+            handler_logs.lock().unwrap().push((level, msg.to_owned()))
+        });
+
+        set_log_level(Level::Trace);
+        _trace(Level::Trace, format_args!("Starting {}", 1));
+        _trace(Level::Debug, format_args!("Hidden"));
+        set_log_level(Level::Error);
+        reset_log_formatter();
+
+        let logs = logs.lock().unwrap();
+        assert!(logs.contains(&(Level::Trace, "Starting 1".to_owned())));
+        assert!(!logs.iter().any(|x| x.1 == "Hidden"));
+    }
+}
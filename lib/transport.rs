@@ -0,0 +1,444 @@
+//! The connection layer that afire reads requests from and writes responses to.
+//!
+//! By default (with the `tcp` feature), afire accepts [`TcpStream`]s itself with [`crate::Server::start`] and friends.
+//! The request parsing, router and middleware pipeline only depend on the [`Stream`] trait though, so other transports (like WASI sockets or an in-memory pipe) can drive them with [`crate::Server::handle_stream`].
+//! Disabling the default features removes the TCP event loop entirely.
+//!
+//! ## Example
+//! ```rust
+//! # use std::{io::{self, Read, Write}, net::{Shutdown, SocketAddr}, time::Duration};
+//! # use afire::transport::Stream;
+//! /// A connection from some custom transport.
+//! struct MyStream;
+//!
+//! # impl Read for MyStream { fn read(&mut self, _: &mut [u8]) -> io::Result<usize> { Ok(0) } }
+//! # impl Write for MyStream {
+//! #     fn write(&mut self, buf: &[u8]) -> io::Result<usize> { Ok(buf.len()) }
+//! #     fn flush(&mut self) -> io::Result<()> { Ok(()) }
+//! # }
+//! impl Stream for MyStream {
+//!     fn peer_addr(&self) -> io::Result<SocketAddr> {
+//!         Ok(SocketAddr::from(([127, 0, 0, 1], 0)))
+//!     }
+//!
+//!     fn try_clone(&self) -> io::Result<Box<dyn Stream>> {
+//!         Ok(Box::new(MyStream))
+//!     }
+//!
+//!     fn shutdown(&self, _how: Shutdown) -> io::Result<()> {
+//!         Ok(())
+//!     }
+//! }
+//! ```
+
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    fmt::{self, Debug},
+    io::{self, Read, Write},
+    net::{Shutdown, SocketAddr},
+    ops::Deref,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+#[cfg(feature = "tcp")]
+use std::net::TcpStream;
+#[cfg(all(feature = "tcp", unix))]
+use std::os::unix::io::AsRawFd;
+#[cfg(unix)]
+use std::os::unix::io::RawFd;
+
+use crate::internal::common::ForceLock;
+
+/// The ID of the next socket to be opened.
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+type Storage = HashMap<TypeId, Arc<dyn Any + Send + Sync>>;
+
+/// A shared connection to a client.
+/// This is what [`crate::Request::socket`] holds, and it is the same for every request sent over a keep-alive connection.
+///
+/// It derefs to the mutex around the [`Stream`], so it can be locked to read from or write to the client directly.
+/// It also has a small type-keyed storage, so middleware can cache things that only need to be worked out once per connection.
+///
+/// ## Example
+/// ```rust
+/// # use afire::{middleware::{Middleware, MiddleResult}, Request};
+/// /// Counts the requests sent over each connection.
+/// struct RequestCount;
+///
+/// struct Count(std::sync::atomic::AtomicUsize);
+///
+/// impl Middleware for RequestCount {
+///     fn pre(&self, req: &mut Request) -> MiddleResult {
+///         let count = req
+///             .socket
+///             .get_or_insert_data(|| Count(Default::default()));
+///         let n = count.0.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+///         println!("Request #{} on connection {}", n + 1, req.socket.id);
+///         MiddleResult::Continue
+///     }
+/// }
+/// ```
+pub struct Socket {
+    /// A unique ID for this connection.
+    /// IDs are never reused while the program is running, so they can be used as keys for per-connection state kept elsewhere.
+    pub id: u64,
+    requests: AtomicU64,
+    stream: Mutex<Box<dyn Stream>>,
+    storage: Mutex<Storage>,
+}
+
+impl Socket {
+    /// Wraps a stream for a new connection, giving it the next ID.
+    pub(crate) fn new(stream: Box<dyn Stream>) -> Self {
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            requests: AtomicU64::new(0),
+            stream: Mutex::new(stream),
+            storage: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The number of requests that have been read from this connection, including the one being handled.
+    pub fn requests(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
+    }
+
+    /// Counts a new request read from this connection.
+    pub(crate) fn count_request(&self) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Gets the value of type `T` stored on this connection.
+    pub fn get_data<T: Any + Send + Sync>(&self) -> Option<Arc<T>> {
+        let storage = self.storage.force_lock();
+        let value = storage.get(&TypeId::of::<T>())?.clone();
+        value.downcast().ok()
+    }
+
+    /// Stores a value on this connection, replacing and returning any existing value of the same type.
+    pub fn insert_data<T: Any + Send + Sync>(&self, value: T) -> Option<Arc<T>> {
+        let old = self
+            .storage
+            .force_lock()
+            .insert(TypeId::of::<T>(), Arc::new(value))?;
+        old.downcast().ok()
+    }
+
+    /// Gets the value of type `T` stored on this connection, or stores the result of `init` if there isn't one yet.
+    /// `init` runs while the storage is locked, so it must not access this connection's storage itself.
+    pub fn get_or_insert_data<T: Any + Send + Sync>(&self, init: impl FnOnce() -> T) -> Arc<T> {
+        let value = self
+            .storage
+            .force_lock()
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Arc::new(init()))
+            .clone();
+        value.downcast().expect("Storage keyed by TypeId")
+    }
+
+    /// Removes and returns the value of type `T` stored on this connection.
+    pub fn remove_data<T: Any + Send + Sync>(&self) -> Option<Arc<T>> {
+        let old = self.storage.force_lock().remove(&TypeId::of::<T>())?;
+        old.downcast().ok()
+    }
+}
+
+impl Deref for Socket {
+    type Target = Mutex<Box<dyn Stream>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.stream
+    }
+}
+
+impl Debug for Socket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Socket").field("id", &self.id).finish()
+    }
+}
+
+/// A bidirectional connection to a client.
+/// Implemented for [`TcpStream`] when the `tcp` feature is enabled.
+///
+/// The timeout methods default to doing nothing, for transports that don't support them.
+pub trait Stream: Read + Write + Send {
+    /// The address of the client.
+    /// This is used for [`crate::Request::address`], so transports without an address should return a placeholder like `0.0.0.0:0`.
+    fn peer_addr(&self) -> io::Result<SocketAddr>;
+
+    /// The local address the client connected to.
+    /// This is used for [`crate::Request::local_addr`].
+    /// Defaults to an `Unsupported` error, for transports without an address.
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    /// Creates a new handle to the same connection.
+    /// Reads and writes to either handle must go to the same client.
+    /// This is used by WebSockets to read and write from different threads.
+    fn try_clone(&self) -> io::Result<Box<dyn Stream>>;
+
+    /// Shuts down the read half, write half or both halves of the connection.
+    fn shutdown(&self, how: Shutdown) -> io::Result<()>;
+
+    /// Closes the connection abortively, for [`crate::Response::reset`].
+    /// For TCP on Unix this sets `SO_LINGER` to zero, so a reset (RST) is sent when the stream is dropped instead of a FIN.
+    /// Defaults to shutting down the connection normally.
+    fn abort(&self) -> io::Result<()> {
+        self.shutdown(Shutdown::Both)
+    }
+
+    /// Sets how long reads can block before failing.
+    fn set_read_timeout(&self, _timeout: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Sets how long writes can block before failing.
+    fn set_write_timeout(&self, _timeout: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Gets the current write timeout.
+    fn write_timeout(&self) -> io::Result<Option<Duration>> {
+        Ok(None)
+    }
+
+    /// Sets if small writes should be sent right away, instead of waiting to be combined with later writes.
+    /// For TCP this is `TCP_NODELAY`, which turns off Nagle's algorithm.
+    fn set_nodelay(&self, _nodelay: bool) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Gets the DER encoded certificate chain the client authenticated with, for transports like TLS that support it.
+    /// This is used for [`crate::Request::peer_certificates`].
+    fn peer_certificates(&self) -> Option<Vec<Vec<u8>>> {
+        None
+    }
+
+    /// Gets the details of the encryption, for transports like TLS that encrypt the connection.
+    /// This is used for [`crate::Request::connection_info`].
+    fn connection_info(&self) -> Option<ConnectionInfo> {
+        None
+    }
+
+    /// Gets the file descriptor of the socket, if bytes written to it go straight to the client.
+    /// With the `sendfile` feature on Linux, file bodies are copied to this with `sendfile`, instead of being read into memory and written in chunks.
+    /// Transports that change the data on the way, like TLS, must return None, which is the default.
+    #[cfg(unix)]
+    fn raw_fd(&self) -> Option<RawFd> {
+        None
+    }
+}
+
+/// Details of an encrypted connection, from [`Stream::connection_info`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionInfo {
+    /// The negotiated protocol version, like `TLSv1_3`.
+    pub protocol: String,
+    /// The negotiated cipher suite, like `TLS13_AES_256_GCM_SHA384`.
+    pub cipher_suite: String,
+    /// The application protocol agreed on with ALPN, if the client asked for one.
+    pub alpn_protocol: Option<String>,
+    /// The host name the client asked for with SNI, if it sent one.
+    pub server_name: Option<String>,
+}
+
+#[cfg(feature = "tcp")]
+impl Stream for TcpStream {
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        TcpStream::peer_addr(self)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        TcpStream::local_addr(self)
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn Stream>> {
+        Ok(Box::new(TcpStream::try_clone(self)?))
+    }
+
+    fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        TcpStream::shutdown(self, how)
+    }
+
+    #[cfg(unix)]
+    fn abort(&self) -> io::Result<()> {
+        let linger = libc::linger {
+            l_onoff: 1,
+            l_linger: 0,
+        };
+        // SAFETY: The file descriptor is borrowed for the length of the call, and `linger` is valid for the size given
+        let result = unsafe {
+            libc::setsockopt(
+                self.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_LINGER,
+                &linger as *const libc::linger as *const libc::c_void,
+                std::mem::size_of::<libc::linger>() as libc::socklen_t,
+            )
+        };
+
+        match result {
+            0 => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_write_timeout(self, timeout)
+    }
+
+    fn write_timeout(&self) -> io::Result<Option<Duration>> {
+        TcpStream::write_timeout(self)
+    }
+
+    fn set_nodelay(&self, nodelay: bool) -> io::Result<()> {
+        TcpStream::set_nodelay(self, nodelay)
+    }
+
+    #[cfg(unix)]
+    fn raw_fd(&self) -> Option<RawFd> {
+        Some(self.as_raw_fd())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{self, Cursor, Read, Write},
+        net::{Shutdown, SocketAddr},
+        sync::{Arc, Mutex},
+    };
+
+    use super::{Socket, Stream};
+    use crate::{Method, Response, Server};
+
+    /// A connection that reads from a fixed buffer and collects everything written to it.
+    struct MemoryStream {
+        input: Cursor<Vec<u8>>,
+        output: Arc<Mutex<Vec<u8>>>,
+    }
+
+    impl Read for MemoryStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for MemoryStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Stream for MemoryStream {
+        fn peer_addr(&self) -> io::Result<SocketAddr> {
+            Ok(SocketAddr::from(([0, 0, 0, 0], 0)))
+        }
+
+        fn try_clone(&self) -> io::Result<Box<dyn Stream>> {
+            Err(io::ErrorKind::Unsupported.into())
+        }
+
+        fn shutdown(&self, _how: Shutdown) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn memory_socket() -> Socket {
+        Socket::new(Box::new(MemoryStream {
+            input: Cursor::new(Vec::new()),
+            output: Arc::default(),
+        }))
+    }
+
+    #[test]
+    fn test_socket_storage() {
+        let socket = memory_socket();
+        assert!(memory_socket().id > socket.id);
+
+        assert_eq!(socket.get_data::<u32>(), None);
+        assert_eq!(socket.insert_data(5u32), None);
+        assert_eq!(socket.insert_data(6u32).as_deref(), Some(&5));
+        assert_eq!(*socket.get_or_insert_data(|| 7u32), 6);
+        assert_eq!(*socket.get_or_insert_data(|| "hi"), "hi");
+        assert_eq!(socket.remove_data::<u32>().as_deref(), Some(&6));
+        assert_eq!(socket.get_data::<u32>(), None);
+        assert_eq!(socket.get_data::<&str>().as_deref(), Some(&"hi"));
+    }
+
+    #[test]
+    fn test_handle_stream() {
+        let mut server = Server::<()>::new("localhost", 8080);
+        server.route(Method::GET, "/", |_| Response::new().text("Hello"));
+
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let stream = MemoryStream {
+            input: Cursor::new(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n".to_vec()),
+            output: output.clone(),
+        };
+        server.handle_stream(stream).unwrap();
+
+        let output = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+        assert!(output.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(output.ends_with("\r\n\r\nHello"));
+    }
+
+    #[test]
+    fn test_pipelined_streamed_body() {
+        let mut server = Server::<()>::new("localhost", 8080).body_buffer_limit(4);
+        server.route(Method::POST, "/", |req| {
+            let mut body = String::new();
+            req.body_reader().read_to_string(&mut body).unwrap();
+            Response::new().text(format!("{}:{}", req.body_streamed(), body))
+        });
+        server.route(Method::POST, "/skip", |_| Response::new().text("Skipped"));
+
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let stream = MemoryStream {
+            input: Cursor::new(
+                b"POST / HTTP/1.1\r\nContent-Length: 3\r\n\r\nabc\
+                  POST / HTTP/1.1\r\nContent-Length: 11\r\n\r\nhello world\
+                  POST /skip HTTP/1.1\r\nContent-Length: 5\r\n\r\nextra\
+                  POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n6\r\nchunky\r\n0\r\n\r\n\
+                  POST /skip HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n6\r\nchunky\r\n0\r\n\r\n\
+                  POST / HTTP/1.1\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    .to_vec(),
+            ),
+            output: output.clone(),
+        };
+        server.handle_stream(stream).unwrap();
+
+        let output = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+        let bodies = output
+            .split("HTTP/1.1 ")
+            .skip(1)
+            .map(|x| x.split("\r\n\r\n").nth(1).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            bodies,
+            [
+                "false:abc",
+                "true:hello world",
+                "Skipped",
+                "true:chunky",
+                "Skipped",
+                "false:"
+            ]
+        );
+    }
+}
@@ -0,0 +1,2873 @@
+//! HTTP conformance tests.
+//! Each test starts a real server and talks to it over loopback with a raw TcpStream, to catch protocol regressions that the unit tests miss.
+
+#[cfg(feature = "tls")]
+use std::convert::TryInto;
+use std::{
+    cell::RefCell,
+    convert::TryFrom,
+    fs,
+    io::{BufRead, BufReader, Read, Write},
+    net::{Shutdown, TcpListener, TcpStream},
+    path::PathBuf,
+    rc::Rc,
+    sync::{mpsc, Arc, Barrier},
+    thread,
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "compression")]
+use afire::extension::Compression;
+#[cfg(feature = "extensions")]
+use afire::extension::{
+    PathNormalizer, Range, Rewrite, ServeEmbedded, ServeStatic, Throttle, WellKnown,
+};
+#[cfg(all(feature = "extensions", feature = "serde"))]
+use afire::extension::{RequestHistory, RequestId};
+#[cfg(feature = "tls")]
+use afire::tls::TlsConfig;
+use afire::{
+    multipart::{MultipartBuilder, MultipartData},
+    prelude::*,
+    server_sent_events::{Broadcaster, Event, ServerSentEventsExt},
+    web_socket::{OpCode, TxType},
+    HeaderPolicy, OverflowPolicy, ReasonPhrase, TrailingSlash,
+};
+
+#[cfg(feature = "tls")]
+use rustls::{
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+    ClientConfig, ClientConnection, RootCertStore, StreamOwned,
+};
+
+/// A parsed HTTP response, as received by the client.
+struct RawResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl RawResponse {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|x| x.0.eq_ignore_ascii_case(name))
+            .map(|x| x.1.as_str())
+    }
+}
+
+/// Starts the server on a background thread and waits for it to accept connections, returning the port it's bound to.
+/// Servers are created with port 0, so the OS picks a free port and tests can run in parallel with anything else on the machine.
+fn start(server: Server) -> u16 {
+    start_with(server, |x| x.start())
+}
+
+fn start_with(
+    server: Server,
+    start: impl FnOnce(Server) -> afire::error::Result<()> + Send + 'static,
+) -> u16 {
+    let addr = server.bound_addr.clone();
+    thread::spawn(move || start(server).unwrap());
+
+    for _ in 0..100 {
+        if let Some(addr) = addr.get() {
+            return addr.port();
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    panic!("Server never started");
+}
+
+fn connect(port: u16) -> BufReader<TcpStream> {
+    let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    BufReader::new(stream)
+}
+
+fn send(stream: &mut BufReader<TcpStream>, raw: &str) {
+    stream.get_mut().write_all(raw.as_bytes()).unwrap();
+}
+
+/// Reads one response off the socket.
+/// Chunked bodies are decoded, otherwise Content-Length is used.
+fn read_response(stream: &mut BufReader<TcpStream>) -> RawResponse {
+    let mut res = read_head(stream);
+    if res.header("Transfer-Encoding") == Some("chunked") {
+        loop {
+            let mut size = String::new();
+            stream.read_line(&mut size).unwrap();
+            let size = usize::from_str_radix(size.trim(), 16).unwrap();
+
+            let mut chunk = vec![0; size + 2];
+            stream.read_exact(&mut chunk).unwrap();
+            assert_eq!(&chunk[size..], b"\r\n");
+            if size == 0 {
+                break;
+            }
+            res.body.extend_from_slice(&chunk[..size]);
+        }
+    } else if let Some(len) = res.header("Content-Length") {
+        res.body = vec![0; len.parse().unwrap()];
+        stream.read_exact(&mut res.body).unwrap();
+    }
+
+    res
+}
+
+/// Reads the status line and headers of a response off the socket, leaving the body.
+fn read_head(stream: &mut impl BufRead) -> RawResponse {
+    let mut line = String::new();
+    stream.read_line(&mut line).unwrap();
+    let status = line.split(' ').nth(1).unwrap().parse().unwrap();
+
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        stream.read_line(&mut line).unwrap();
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+
+        let (key, value) = line.split_once(':').unwrap();
+        headers.push((key.to_owned(), value.trim().to_owned()));
+    }
+
+    RawResponse {
+        status,
+        headers,
+        body: Vec::new(),
+    }
+}
+
+/// Checks that the server has closed the connection.
+fn assert_closed(stream: &mut BufReader<TcpStream>) {
+    let mut buf = Vec::new();
+    assert_eq!(stream.read_to_end(&mut buf).unwrap(), 0);
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("afire-{name}-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn keep_alive() {
+    let mut server = Server::<()>::new("127.0.0.1", 0);
+    server.route(Method::GET, "/", |_| Response::new().text("Hello"));
+    let mut stream = connect(start(server));
+
+    for _ in 0..3 {
+        send(&mut stream, "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        let res = read_response(&mut stream);
+        assert_eq!(res.status, 200);
+        assert_eq!(res.body, b"Hello");
+    }
+
+    send(
+        &mut stream,
+        "GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+    );
+    assert_eq!(read_response(&mut stream).status, 200);
+    assert_closed(&mut stream);
+}
+
+#[test]
+fn chunked_response() {
+    let body = (0..50_000).map(|x| (x % 251) as u8).collect::<Vec<_>>();
+    let expected = body.clone();
+
+    let mut server = Server::<()>::new("127.0.0.1", 0);
+    server.route(Method::GET, "/", move |_| {
+        Response::new().stream(std::io::Cursor::new(body.clone()))
+    });
+    let mut stream = connect(start(server));
+
+    send(&mut stream, "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    let res = read_response(&mut stream);
+    assert_eq!(res.status, 200);
+    assert_eq!(res.header("Transfer-Encoding"), Some("chunked"));
+    assert_eq!(res.body, expected);
+
+    // The socket should still be usable after the terminating chunk
+    send(&mut stream, "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    assert_eq!(read_response(&mut stream).body, expected);
+}
+
+#[test]
+#[cfg(feature = "extensions")]
+fn static_etag() {
+    let dir = temp_dir("etag");
+    fs::write(dir.join("index.html"), "<h1>Hello</h1>").unwrap();
+
+    let mut server = Server::<()>::new("127.0.0.1", 0);
+    ServeStatic::new(dir.to_string_lossy()).attach(&mut server);
+    let mut stream = connect(start(server));
+
+    send(&mut stream, "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    let res = read_response(&mut stream);
+    assert_eq!(res.status, 200);
+    assert_eq!(res.body, b"<h1>Hello</h1>");
+    let etag = res.header("ETag").unwrap().to_owned();
+
+    send(
+        &mut stream,
+        &format!("GET / HTTP/1.1\r\nHost: localhost\r\nIf-None-Match: W/{etag}\r\n\r\n"),
+    );
+    let res = read_response(&mut stream);
+    assert_eq!(res.status, 304);
+    assert_eq!(res.header("ETag"), Some(etag.as_str()));
+    assert!(res.body.is_empty());
+
+    send(
+        &mut stream,
+        "GET / HTTP/1.1\r\nHost: localhost\r\nIf-None-Match: \"nope\"\r\n\r\n",
+    );
+    assert_eq!(read_response(&mut stream).status, 200);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn malformed_requests() {
+    let mut server = Server::<()>::new("127.0.0.1", 0);
+    server.route(Method::GET, "/", |_| Response::new().text("Hello"));
+    let port = start(server);
+
+    for raw in [
+        "GARBAGE\r\n\r\n",
+        "GET\r\n\r\n",
+        "GET / HTTP/1.1\r\nNoColon\r\n\r\n",
+    ] {
+        let mut stream = connect(port);
+        send(&mut stream, raw);
+        assert_eq!(read_response(&mut stream).status, 400, "{raw:?}");
+        assert_closed(&mut stream);
+    }
+
+    // Client closes the connection halfway through the headers
+    let mut stream = connect(port);
+    send(&mut stream, "GET / HTTP/1.1\r\nHost: loc");
+    stream.get_ref().shutdown(Shutdown::Write).unwrap();
+    assert_eq!(read_response(&mut stream).status, 400);
+}
+
+#[test]
+fn websocket_handshake() {
+    let mut server = Server::<()>::new("127.0.0.1", 0);
+    server.route(Method::GET, "/ws", |req| {
+        req.ws().unwrap();
+        Response::end()
+    });
+    let mut stream = connect(start(server));
+
+    // Example key and accept value from RFC 6455
+    send(
+        &mut stream,
+        "GET /ws HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n",
+    );
+    let res = read_response(&mut stream);
+    assert_eq!(res.status, 101);
+    assert_eq!(res.header("Upgrade"), Some("websocket"));
+    assert_eq!(
+        res.header("Sec-WebSocket-Accept"),
+        Some("s3pPLMBiTxaQ9kYGzzhZRbK+xOo=")
+    );
+}
+
+#[test]
+fn bodyless_statuses() {
+    let mut server = Server::<()>::new("127.0.0.1", 0);
+    server.route(Method::GET, "/no-content", |_| {
+        Response::new().status(Status::NoContent).text("ignored")
+    });
+    server.route(Method::GET, "/not-modified", |_| {
+        Response::new()
+            .status(Status::NotModified)
+            .stream(std::io::Cursor::new(b"ignored".to_vec()))
+    });
+    server.route(Method::GET, "/", |_| Response::new().text("Hello"));
+    let mut stream = connect(start(server));
+
+    for path in ["/no-content", "/not-modified"] {
+        send(
+            &mut stream,
+            &format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n"),
+        );
+        let res = read_response(&mut stream);
+        assert!(res.header("Content-Length").is_none());
+        assert!(res.header("Transfer-Encoding").is_none());
+    }
+
+    // Nothing extra should have been sent, so the next response parses cleanly
+    send(&mut stream, "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    assert_eq!(read_response(&mut stream).body, b"Hello");
+}
+
+#[test]
+fn split_pools() {
+    let mut server = Server::<()>::new("127.0.0.1", 0);
+    server.route(Method::GET, "/slow", |_| {
+        thread::sleep(Duration::from_millis(1000));
+        Response::new().text("Slow")
+    });
+    server.route(Method::GET, "/", |_| Response::new().text("Hello"));
+    let stats = server.pool_stats.clone();
+    let port = start_with(server, |x| x.start_split(1, 2));
+
+    let mut slow = connect(port);
+    send(&mut slow, "GET /slow HTTP/1.1\r\nConnection: close\r\n\r\n");
+    thread::sleep(Duration::from_millis(100));
+
+    // The only io thread should be free to handle other sockets while the slow handler runs
+    let start = Instant::now();
+    let mut fast = connect(port);
+    send(&mut fast, "GET / HTTP/1.1\r\nConnection: close\r\n\r\n");
+    assert_eq!(read_response(&mut fast).body, b"Hello");
+    assert!(start.elapsed() < Duration::from_millis(500));
+
+    assert_eq!(stats.io.threads(), 1);
+    assert_eq!(stats.handler.threads(), 2);
+    // The fast handler's job can still be finishing up after its response is sent
+    let start = Instant::now();
+    while stats.handler.active() > 1 && start.elapsed() < Duration::from_millis(500) {
+        thread::sleep(Duration::from_millis(5));
+    }
+    assert_eq!(stats.handler.active(), 1);
+
+    assert_eq!(read_response(&mut slow).body, b"Slow");
+}
+
+#[test]
+fn split_kept_request() {
+    thread_local! {
+        static KEPT: RefCell<Vec<Rc<Request>>> = const { RefCell::new(Vec::new()) };
+    }
+
+    struct Keep;
+    impl Middleware for Keep {
+        fn post_raw(
+            &self,
+            req: afire::error::Result<Rc<Request>>,
+            _res: &mut afire::error::Result<Response>,
+        ) -> MiddleResult {
+            if let Ok(req) = req {
+                KEPT.with(|x| x.borrow_mut().push(req));
+            }
+            MiddleResult::Continue
+        }
+    }
+
+    let mut server = Server::<()>::new("127.0.0.1", 0);
+    server.route(Method::GET, "/", |_| Response::new().text("Hello"));
+    Keep.attach(&mut server);
+    let port = start_with(server, |x| x.start_split(1, 1));
+
+    // The response is still sent, but the connection can't be reused without the request
+    let mut stream = connect(port);
+    send(&mut stream, "GET / HTTP/1.1\r\n\r\n");
+    assert_eq!(read_response(&mut stream).body, b"Hello");
+    assert_closed(&mut stream);
+}
+
+#[test]
+fn trailing_slash() {
+    struct StripPrefix;
+    impl Middleware for StripPrefix {
+        fn pre(&self, req: &mut Request) -> MiddleResult {
+            if let Some(path) = req.path.strip_prefix("/app") {
+                let path = path.to_owned();
+                req.rewrite_path(path);
+            }
+            MiddleResult::Continue
+        }
+    }
+
+    let mut server = Server::<()>::new("127.0.0.1", 0);
+    StripPrefix.attach(&mut server);
+    server.route(Method::GET, "/ignore", |_| Response::new().text("Ignore"));
+    server
+        .route(Method::GET, "/exact", |_| Response::new().text("Exact"))
+        .trailing_slash(TrailingSlash::Exact);
+    server
+        .route(Method::GET, "/redirect/", |_| {
+            Response::new().text("Redirect")
+        })
+        .trailing_slash(TrailingSlash::Redirect);
+    let mut stream = connect(start(server));
+
+    let mut get = |path: &str| {
+        send(
+            &mut stream,
+            &format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n"),
+        );
+        read_response(&mut stream)
+    };
+
+    assert_eq!(get("/ignore").status, 200);
+    assert_eq!(get("/ignore/").status, 200);
+    assert_eq!(get("/exact").status, 200);
+    assert_eq!(get("/exact/").status, 404);
+    assert_eq!(get("/redirect/").status, 200);
+
+    let res = get("/redirect?a=b%20c");
+    assert_eq!(res.status, 308);
+    assert_eq!(res.header("Location"), Some("/redirect/?a=b%20c"));
+
+    // The query is kept as the client sent it
+    let res = get("/redirect?a=b+c&path=%2F&flag&a=2");
+    assert_eq!(
+        res.header("Location"),
+        Some("/redirect/?a=b+c&path=%2F&flag&a=2")
+    );
+
+    // Rewritten requests are redirected to the path the client used
+    let res = get("/app/redirect?a=1");
+    assert_eq!(res.status, 308);
+    assert_eq!(res.header("Location"), Some("/app/redirect/?a=1"));
+}
+
+#[test]
+fn queue_overflow() {
+    let mut server = Server::<()>::new("127.0.0.1", 0)
+        .queue_limit(1)
+        .overflow_policy(OverflowPolicy::Reject);
+    server.route(Method::GET, "/", |_| {
+        thread::sleep(Duration::from_millis(500));
+        Response::new().text("Hello")
+    });
+    let stats = server.pool_stats.clone();
+    let port = start_with(server, |x| x.start_threaded(1));
+    thread::sleep(Duration::from_millis(100));
+
+    // The first connection occupies the only worker and the second fills the queue
+    let mut streams = Vec::new();
+    for _ in 0..2 {
+        let mut stream = connect(port);
+        send(&mut stream, "GET / HTTP/1.1\r\nConnection: close\r\n\r\n");
+        streams.push(stream);
+        thread::sleep(Duration::from_millis(100));
+    }
+    assert_eq!(stats.io.queued(), 1);
+
+    let mut rejected = connect(port);
+    let res = read_response(&mut rejected);
+    assert_eq!(res.status, 503);
+    assert_closed(&mut rejected);
+    assert_eq!(stats.io.overflowed(), 1);
+
+    for mut stream in streams {
+        assert_eq!(read_response(&mut stream).body, b"Hello");
+    }
+}
+
+#[test]
+fn multipart_round_trip() {
+    let mut server = Server::<()>::new("127.0.0.1", 0);
+    server.route(Method::POST, "/", |req| {
+        let form = MultipartData::try_from(req).unwrap();
+        let file = form.get("file").unwrap();
+        Response::new().text(format!(
+            "{} {:?}",
+            form.get("name").unwrap().name,
+            String::from_utf8_lossy(file.data).trim_end()
+        ))
+    });
+    let mut stream = connect(start(server));
+
+    let form =
+        MultipartBuilder::new()
+            .field("name", "afire")
+            .file("file", "a.txt", "text/plain", "Hello");
+    let content_type = form.content_type();
+    let body = form.build();
+    send(
+        &mut stream,
+        &format!(
+            "POST / HTTP/1.1\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n{}",
+            content_type,
+            body.len(),
+            String::from_utf8(body).unwrap()
+        ),
+    );
+    assert_eq!(read_response(&mut stream).body, b"name \"Hello\"");
+}
+
+#[test]
+fn reason_phrase() {
+    fn status_line(policy: ReasonPhrase) -> String {
+        let mut server = Server::<()>::new("127.0.0.1", 0).reason_phrase(policy);
+        server.route(Method::GET, "/", |_| Response::new().reason("Totally Fine"));
+        let mut stream = connect(start(server));
+
+        send(&mut stream, "GET / HTTP/1.1\r\nConnection: close\r\n\r\n");
+        let mut line = String::new();
+        stream.read_line(&mut line).unwrap();
+        line
+    }
+
+    assert_eq!(
+        status_line(ReasonPhrase::Custom),
+        "HTTP/1.1 200 Totally Fine\r\n"
+    );
+    assert_eq!(status_line(ReasonPhrase::Canonical), "HTTP/1.1 200 OK\r\n");
+    assert_eq!(status_line(ReasonPhrase::Omit), "HTTP/1.1 200 \r\n");
+}
+
+#[test]
+#[cfg(feature = "extensions")]
+fn rewrite() {
+    let mut server = Server::<()>::new("127.0.0.1", 0);
+    Rewrite::new()
+        .strip_prefix("/app")
+        .path("/old", "/new?from=old")
+        .attach(&mut server);
+    server.route(Method::GET, "/new", |req| {
+        Response::new().text(format!(
+            "{} {} {:?}",
+            req.path,
+            req.original_path(),
+            req.query.get("from")
+        ))
+    });
+    let mut stream = connect(start(server));
+
+    let mut get = |path: &str| {
+        send(
+            &mut stream,
+            &format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n"),
+        );
+        read_response(&mut stream)
+    };
+
+    assert_eq!(get("/app/new").body, b"/new /app/new None");
+    assert_eq!(get("/app/old").body, b"/new /app/old Some(\"old\")");
+    assert_eq!(get("/apple/new").status, 404);
+}
+
+#[test]
+#[cfg(feature = "tls")]
+fn tls() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/tls");
+    let read = |name: &str| fs::read(dir.join(name)).unwrap();
+
+    let mut server = Server::<()>::new("127.0.0.1", 0).socket_timeout(Duration::from_secs(5));
+    server.route(Method::GET, "/", |req| {
+        let certs = req.peer_certificates().map_or(0, |x| x.len());
+        Response::new().text(format!("Hello {}", certs))
+    });
+    server.route(Method::GET, "/info", |req| {
+        let info = req.connection_info().unwrap();
+        Response::new().text(format!(
+            "{} {:?} {}",
+            info.protocol,
+            info.server_name,
+            req.local_addr().unwrap()
+        ))
+    });
+    let tls = TlsConfig::with_client_auth(
+        read("server.pem"),
+        read("server.key"),
+        read("ca.pem"),
+        false,
+    )
+    .unwrap();
+    let port = start_with(server, |x| x.start_tls(2, tls));
+
+    // A plain HTTP request should fail the handshake and be closed
+    let mut stream = connect(port);
+    send(&mut stream, "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    let mut buf = Vec::new();
+    let _ = stream.read_to_end(&mut buf);
+    assert!(!buf.starts_with(b"HTTP/1.1"));
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut roots = RootCertStore::empty();
+    for cert in CertificateDer::pem_slice_iter(&read("ca.pem")) {
+        roots.add(cert.unwrap()).unwrap();
+    }
+    let client_config = |cert: bool| {
+        let builder = ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots.clone());
+        Arc::new(if cert {
+            builder
+                .with_client_auth_cert(
+                    CertificateDer::pem_slice_iter(&read("client.pem"))
+                        .collect::<Result<_, _>>()
+                        .unwrap(),
+                    PrivateKeyDer::from_pem_slice(&read("client.key")).unwrap(),
+                )
+                .unwrap()
+        } else {
+            builder.with_no_client_auth()
+        })
+    };
+
+    for (cert, path, expected) in [
+        (false, "/", "Hello 0"),
+        (true, "/", "Hello 1"),
+        (
+            false,
+            "/info",
+            &format!("TLSv1_3 Some(\"localhost\") 127.0.0.1:{port}"),
+        ),
+    ] {
+        let conn =
+            ClientConnection::new(client_config(cert), "localhost".try_into().unwrap()).unwrap();
+        let socket = connect(port).into_inner();
+        let mut stream = BufReader::new(StreamOwned::new(conn, socket));
+        let req = format!("GET {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+        stream.get_mut().write_all(req.as_bytes()).unwrap();
+
+        let mut res = String::new();
+        stream.read_to_string(&mut res).unwrap();
+        assert!(res.starts_with("HTTP/1.1 200 OK\r\n"), "{:?}", res);
+        assert!(res.ends_with(expected), "{:?}", res);
+    }
+}
+
+#[test]
+fn connection_close_reasons() {
+    let mut server = Server::<()>::new("127.0.0.1", 0).socket_timeout(Duration::from_millis(300));
+    server.route(Method::GET, "/", |_| Response::new().text("Hello"));
+    server.route(Method::GET, "/close", |_| Response::new().close());
+    server.route(Method::GET, "/reset", |_| Response::reset());
+    let stats = server.connection_stats.clone();
+    let port = start_with(server, |x| x.start_threaded(4));
+
+    // Counters are updated on the server's threads, so give them a moment to catch up
+    let wait_for = |name: &str, check: &dyn Fn() -> bool| {
+        let start = Instant::now();
+        while !check() {
+            assert!(start.elapsed() < Duration::from_secs(2), "{}", name);
+            thread::sleep(Duration::from_millis(10));
+        }
+    };
+
+    let mut stream = connect(port);
+    send(&mut stream, "GET / HTTP/1.1\r\nConnection: close\r\n\r\n");
+    read_response(&mut stream);
+    assert_closed(&mut stream);
+    wait_for("completed", &|| stats.completed() == 1);
+
+    let mut stream = connect(port);
+    send(&mut stream, "GET /close HTTP/1.1\r\n\r\n");
+    read_response(&mut stream);
+    wait_for("handler_closed", &|| stats.handler_closed() == 1);
+
+    let mut stream = connect(port);
+    assert_closed(&mut stream);
+    wait_for("timed_out", &|| stats.timed_out() == 1);
+
+    drop(connect(port));
+    wait_for("client_closed", &|| stats.client_closed() == 1);
+
+    let mut stream = connect(port);
+    send(&mut stream, "GET /reset HTTP/1.1\r\n\r\n");
+    let err = stream.read_to_end(&mut Vec::new()).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::ConnectionReset);
+    wait_for("aborted", &|| stats.aborted() == 1);
+
+    let mut stream = connect(port);
+    send(&mut stream, "GARBAGE\r\n\r\n");
+    read_response(&mut stream);
+    wait_for("errored", &|| stats.errored() == 1);
+}
+
+#[test]
+fn streamed_body() {
+    let mut server = Server::<()>::new("127.0.0.1", 0).body_buffer_limit(1024);
+    server.route(Method::POST, "/", |req| {
+        let mut sum = 0u64;
+        let mut buf = [0; 4096];
+        let mut reader = req.body_reader();
+        loop {
+            match reader.read(&mut buf).unwrap() {
+                0 => break,
+                n => sum += buf[..n].iter().map(|&x| x as u64).sum::<u64>(),
+            }
+        }
+        Response::new().text(format!("{} {}", req.body.len(), sum))
+    });
+    let port = start(server);
+
+    // A body much larger than the limit, so it has to be streamed
+    let body = (0..200_000).map(|x| (x % 251) as u8).collect::<Vec<_>>();
+    let expected = body.iter().map(|&x| x as u64).sum::<u64>();
+    let mut stream = connect(port);
+    send(
+        &mut stream,
+        &format!("POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n", body.len()),
+    );
+    stream.get_mut().write_all(&body).unwrap();
+    let res = read_response(&mut stream);
+    assert_eq!(res.body, format!("0 {}", expected).as_bytes());
+
+    // The connection is still usable afterwards
+    send(
+        &mut stream,
+        "POST / HTTP/1.1\r\nContent-Length: 2\r\n\r\n\x01\x02",
+    );
+    assert_eq!(read_response(&mut stream).body, b"2 3");
+}
+
+#[test]
+fn socket_storage() {
+    struct Hits(std::sync::atomic::AtomicUsize);
+
+    let mut server = Server::<()>::new("127.0.0.1", 0);
+    server.route(Method::GET, "/", |req| {
+        let hits = req.socket.get_or_insert_data(|| Hits(Default::default()));
+        let n = hits.0.fetch_add(1, std::sync::atomic::Ordering::Relaxed) + 1;
+        Response::new().text(format!("{} {}", req.socket.id, n))
+    });
+    let port = start(server);
+
+    let get = |stream: &mut BufReader<TcpStream>| {
+        send(stream, "GET / HTTP/1.1\r\n\r\n");
+        let body = String::from_utf8(read_response(stream).body).unwrap();
+        let (id, n) = body.split_once(' ').unwrap();
+        (id.parse::<u64>().unwrap(), n.parse::<usize>().unwrap())
+    };
+
+    // Storage is kept across keep-alive requests
+    let mut stream = connect(port);
+    let (id, n) = get(&mut stream);
+    assert_eq!(n, 1);
+    assert_eq!(get(&mut stream), (id, 2));
+    drop(stream);
+
+    // But each connection gets its own ID and storage
+    let (other_id, n) = get(&mut connect(port));
+    assert_ne!(other_id, id);
+    assert_eq!(n, 1);
+}
+
+#[test]
+#[cfg(feature = "async")]
+fn async_route() {
+    use std::{
+        future::Future,
+        pin::Pin,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Mutex,
+        },
+        task::{Context, Poll, Waker},
+    };
+
+    /// Resolves after a background thread sleeps, like a reply from a database client would.
+    fn delay(value: String) -> impl Future<Output = String> {
+        struct Delay(Arc<Mutex<(Option<String>, Option<Waker>)>>);
+
+        impl Future for Delay {
+            type Output = String;
+
+            fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<String> {
+                let mut inner = self.0.lock().unwrap();
+                match inner.0.take() {
+                    Some(i) => Poll::Ready(i),
+                    None => {
+                        inner.1 = Some(cx.waker().clone());
+                        Poll::Pending
+                    }
+                }
+            }
+        }
+
+        let inner = Arc::new(Mutex::new((None, None::<Waker>)));
+        let remote = inner.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            let mut remote = remote.lock().unwrap();
+            remote.0 = Some(value);
+            if let Some(waker) = remote.1.take() {
+                waker.wake();
+            }
+        });
+
+        Delay(inner)
+    }
+
+    let polls = Arc::new(AtomicUsize::new(0));
+    let executor_polls = polls.clone();
+    let mut server = Server::<()>::new("127.0.0.1", 0).async_executor(move |future| {
+        executor_polls.fetch_add(1, Ordering::Relaxed);
+        afire::async_handler::block_on(future)
+    });
+    server.route_async(Method::GET, "/greet/{name}", async |req| {
+        let name = delay(req.param("name").unwrap().into_owned()).await;
+        Response::new().text(format!("Hello, {}", name))
+    });
+    server.route(Method::GET, "/sync", |_| Response::new().text("Sync"));
+    let port = start(server);
+
+    let mut stream = connect(port);
+    send(&mut stream, "GET /greet/Ferris HTTP/1.1\r\n\r\n");
+    assert_eq!(read_response(&mut stream).body, b"Hello, Ferris");
+    send(&mut stream, "GET /sync HTTP/1.1\r\n\r\n");
+    assert_eq!(read_response(&mut stream).body, b"Sync");
+    assert_eq!(polls.load(Ordering::Relaxed), 1);
+}
+
+#[test]
+#[cfg(feature = "extensions")]
+fn throttle() {
+    let mut server = Server::<()>::new("127.0.0.1", 0);
+    server.route(Method::GET, "/slow", |_| Response::new().bytes(&[7; 6000]));
+    server.route(Method::GET, "/fast", |_| Response::new().bytes(&[7; 6000]));
+    Throttle::new(20_000)
+        .burst(2000)
+        .filter(|req| req.path == "/slow")
+        .attach(&mut server);
+    let port = start(server);
+
+    // 2000 bytes are sent right away, the other 4000 take 200ms
+    let mut stream = connect(port);
+    let start = Instant::now();
+    send(&mut stream, "GET /slow HTTP/1.1\r\n\r\n");
+    let res = read_response(&mut stream);
+    assert!(start.elapsed() >= Duration::from_millis(150));
+    assert_eq!(res.header("Transfer-Encoding"), Some("chunked"));
+    assert_eq!(res.body, [7; 6000]);
+
+    let start = Instant::now();
+    send(&mut stream, "GET /fast HTTP/1.1\r\n\r\n");
+    let res = read_response(&mut stream);
+    assert!(start.elapsed() < Duration::from_millis(100));
+    assert_eq!(res.header("Content-Length"), Some("6000"));
+}
+
+#[test]
+#[cfg(feature = "compression")]
+fn compression() {
+    let text = "The quick brown fox jumps over the lazy dog. ".repeat(100);
+    let mut server = Server::<()>::new("127.0.0.1", 0);
+    let body = text.clone();
+    server.route(Method::GET, "/text", move |_| {
+        Response::new()
+            .text(&body)
+            .header("ETag", "\"abc\"")
+            .content(Content::TXT)
+    });
+    let body = text.clone();
+    server.route(Method::GET, "/stream", move |_| {
+        Response::new().stream(std::io::Cursor::new(body.clone().into_bytes()))
+    });
+    server.route(Method::GET, "/small", |_| Response::new().text("Hello"));
+    server.route(Method::GET, "/image", |_| {
+        Response::new()
+            .bytes(&[0; 4096])
+            .content(Content::Custom("image/png"))
+    });
+    Compression::new().attach(&mut server);
+    let port = start(server);
+
+    let mut stream = connect(port);
+    let mut get = |path: &str, accept: &str| {
+        send(
+            &mut stream,
+            &format!(
+                "GET {} HTTP/1.1\r\nAccept-Encoding: {}\r\n\r\n",
+                path, accept
+            ),
+        );
+        read_response(&mut stream)
+    };
+    let decode = |res: &RawResponse| {
+        let mut out = String::new();
+        match res.header("Content-Encoding") {
+            Some("gzip") => flate2::read::GzDecoder::new(&res.body[..])
+                .read_to_string(&mut out)
+                .unwrap(),
+            Some("deflate") => flate2::read::ZlibDecoder::new(&res.body[..])
+                .read_to_string(&mut out)
+                .unwrap(),
+            Some("br") => brotli::Decompressor::new(&res.body[..], 4096)
+                .read_to_string(&mut out)
+                .unwrap(),
+            _ => panic!("Unexpected encoding {:?}", res.header("Content-Encoding")),
+        };
+        out
+    };
+
+    for (accept, encoding) in [
+        ("gzip, deflate, br", "br"),
+        ("gzip", "gzip"),
+        ("deflate, gzip;q=0.5", "deflate"),
+    ] {
+        let res = get("/text", accept);
+        assert_eq!(res.header("Content-Encoding"), Some(encoding));
+        assert_eq!(res.header("Vary"), Some("Accept-Encoding"));
+        assert_eq!(res.header("ETag"), Some("W/\"abc\""));
+        assert_eq!(
+            res.header("Content-Length"),
+            Some(res.body.len().to_string().as_str())
+        );
+        assert!(res.body.len() < text.len());
+        assert_eq!(decode(&res), text);
+    }
+
+    let res = get("/stream", "gzip");
+    assert_eq!(res.header("Transfer-Encoding"), Some("chunked"));
+    assert_eq!(decode(&res), text);
+
+    let res = get("/text", "identity");
+    assert_eq!(res.header("Content-Encoding"), None);
+    assert_eq!(res.header("Vary"), Some("Accept-Encoding"));
+    assert_eq!(res.body, text.as_bytes());
+
+    assert_eq!(get("/small", "gzip").header("Content-Encoding"), None);
+    assert_eq!(get("/image", "gzip").header("Content-Encoding"), None);
+}
+
+#[test]
+#[cfg(feature = "extensions")]
+fn well_known() {
+    let mut server = Server::<()>::new("127.0.0.1", 0);
+    server.route(Method::ANY, "**", |_| {
+        Response::new().status(404).text("Not Found")
+    });
+    let well_known = WellKnown::new()
+        .robots("User-agent: *\nDisallow: /admin\n")
+        .favicon(&b"\x89PNG\r\n\x1a\nfake"[..])
+        .security_txt("Contact: mailto:security@example.com\n")
+        .well_known("/assetlinks.json", "application/json", "[]");
+    well_known.attach(&mut server);
+    let port = start(server);
+
+    let mut stream = connect(port);
+    let mut get = |method: &str, path: &str| {
+        send(
+            &mut stream,
+            &format!("{} {} HTTP/1.1\r\n\r\n", method, path),
+        );
+        read_response(&mut stream)
+    };
+
+    let res = get("GET", "/robots.txt");
+    assert_eq!(res.status, 200);
+    assert_eq!(res.header("Cache-Control"), Some("public, max-age=86400"));
+    assert_eq!(res.body, b"User-agent: *\nDisallow: /admin\n");
+
+    let res = get("GET", "/favicon.ico");
+    assert_eq!(res.header("Content-Type"), Some("image/png"));
+    assert_eq!(res.body, b"\x89PNG\r\n\x1a\nfake");
+
+    let res = get("GET", "/.well-known/security.txt");
+    assert_eq!(res.body, b"Contact: mailto:security@example.com\n");
+    let res = get("GET", "/.well-known/assetlinks.json");
+    assert_eq!(res.header("Content-Type"), Some("application/json"));
+
+    assert_eq!(get("POST", "/robots.txt").status, 404);
+    assert_eq!(get("GET", "/.well-known/other").status, 404);
+
+    // HEAD requests get the headers without the body
+    drop(stream);
+    let mut stream = connect(port);
+    send(&mut stream, "HEAD /robots.txt HTTP/1.1\r\n\r\n");
+    let mut head = String::new();
+    loop {
+        let mut line = String::new();
+        stream.read_line(&mut line).unwrap();
+        head.push_str(&line);
+        if line == "\r\n" {
+            break;
+        }
+    }
+    assert!(head.contains("Content-Length: 31\r\n"), "{}", head);
+}
+
+#[test]
+fn header_policy() {
+    let mut server = Server::<()>::new("127.0.0.1", 0).header_policy(
+        HeaderPolicy::new()
+            .remove(HeaderType::Server)
+            .remove("X-Powered-By")
+            .set("X-Content-Type-Options", "nosniff")
+            .set("X-Frame-Options", "DENY"),
+    );
+    server.route(Method::GET, "/", |_| {
+        Response::new()
+            .text("Hello")
+            .header("x-powered-by", "PHP/5.4")
+            .header("X-Content-Type-Options", "none")
+    });
+    server
+        .route(Method::GET, "/embed", |_| Response::new().text("Embed"))
+        .header_policy(HeaderPolicy::new().remove("X-Frame-Options"));
+    let port = start(server);
+
+    let mut stream = connect(port);
+    send(&mut stream, "GET / HTTP/1.1\r\n\r\n");
+    let res = read_response(&mut stream);
+    assert_eq!(res.header("Server"), None);
+    assert_eq!(res.header("X-Powered-By"), None);
+    assert_eq!(res.header("X-Frame-Options"), Some("DENY"));
+    let nosniff = res
+        .headers
+        .iter()
+        .filter(|x| x.0.eq_ignore_ascii_case("X-Content-Type-Options"))
+        .map(|x| x.1.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(nosniff, ["nosniff"]);
+    assert_eq!(res.header("Content-Length"), Some("5"));
+
+    send(&mut stream, "GET /embed HTTP/1.1\r\n\r\n");
+    let res = read_response(&mut stream);
+    assert_eq!(res.header("X-Frame-Options"), None);
+    assert_eq!(res.header("X-Content-Type-Options"), Some("nosniff"));
+
+    // Errors get the server policy too
+    send(&mut stream, "GET /missing HTTP/1.1\r\n\r\n");
+    let res = read_response(&mut stream);
+    assert_eq!(res.status, 404);
+    assert_eq!(res.header("Server"), None);
+    assert_eq!(res.header("X-Frame-Options"), Some("DENY"));
+}
+
+#[test]
+fn multipart_stream() {
+    let mut server = Server::<()>::new("127.0.0.1", 0).body_buffer_limit(1024);
+    server.route(Method::POST, "/", |req| {
+        let parts = match req.multipart() {
+            Ok(i) => i.max_part_size(300_000),
+            Err(_) => return Response::new().status(Status::BadRequest),
+        };
+
+        let mut out = format!("{}", req.body_streamed());
+        for part in parts {
+            let mut part = match part {
+                Ok(i) => i,
+                Err(_) => return Response::new().status(Status::BadRequest),
+            };
+            let mut data = Vec::new();
+            if part.read_to_end(&mut data).is_err() {
+                return Response::new().status(Status::PayloadTooLarge);
+            }
+            out.push_str(&format!(" {}={}", part.name, data.len()));
+        }
+        Response::new().text(out)
+    });
+    let port = start(server);
+
+    let upload = |file: usize| {
+        let builder = MultipartBuilder::new().field("name", "afire").file(
+            "file",
+            "a.bin",
+            "application/octet-stream",
+            vec![b'-'; file],
+        );
+        let content_type = builder.content_type();
+        let body = builder.build();
+
+        let mut stream = connect(port);
+        send(
+            &mut stream,
+            &format!(
+                "POST / HTTP/1.1\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n",
+                content_type,
+                body.len()
+            ),
+        );
+        stream.get_mut().write_all(&body).unwrap();
+        read_response(&mut stream)
+    };
+
+    let res = upload(200_000);
+    assert_eq!(res.body, b"true name=5 file=200000");
+
+    let res = upload(400_000);
+    assert_eq!(res.status, 413);
+}
+
+#[test]
+fn reproduce_request() {
+    let mut server = Server::<()>::new("127.0.0.1", 0);
+    server.route(Method::POST, "/api", |req| {
+        Response::new().text(format!("{}\n{}", req.to_curl(), req.to_raw_http()))
+    });
+    let port = start(server);
+
+    let mut stream = connect(port);
+    send(
+        &mut stream,
+        "POST /api?v=2&q=a%20b HTTP/1.1\r\nHost: example.com\r\nAuthorization: Bearer secret\r\nX-Note: it's\r\nContent-Length: 9\r\n\r\n{\"id\": 1}",
+    );
+    let res = read_response(&mut stream);
+    let body = String::from_utf8(res.body).unwrap();
+    let (curl, raw) = body.split_once('\n').unwrap();
+
+    assert_eq!(
+        curl,
+        "curl -X 'POST' 'http://example.com/api?v=2&q=a%20b' -H 'Authorization: [redacted]' \
+         -H 'X-Note: it'\\''s' --data-binary '{\"id\": 1}'"
+    );
+    assert_eq!(
+        raw,
+        "POST /api?v=2&q=a%20b HTTP/1.1\r\nHost: example.com\r\nAuthorization: [redacted]\r\n\
+         X-Note: it's\r\nContent-Length: 9\r\n\r\n{\"id\": 1}"
+    );
+}
+
+#[test]
+fn response_progress() {
+    let progress = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut server = Server::<()>::new("127.0.0.1", 0);
+    let log = progress.clone();
+    server.route(Method::GET, "/static", move |_req| {
+        let log = log.clone();
+        Response::new()
+            .bytes(&vec![b'a'; 40_000])
+            .on_progress(move |written, total| {
+                log.lock().unwrap().push((written, total));
+                true
+            })
+    });
+    server.route(Method::GET, "/quota", |_req| {
+        Response::new()
+            .stream(std::io::repeat(b'b').take(1_000_000))
+            .on_progress(|written, _| written < 50_000)
+    });
+    let port = start(server);
+
+    let mut stream = connect(port);
+    send(&mut stream, "GET /static HTTP/1.1\r\n\r\n");
+    assert_eq!(read_response(&mut stream).body.len(), 40_000);
+    drop(stream);
+
+    // The last callback can run after the client has the whole body
+    let start = Instant::now();
+    while progress.lock().unwrap().last() != Some(&(40_000, Some(40_000))) {
+        assert!(start.elapsed() < Duration::from_secs(5));
+        thread::sleep(Duration::from_millis(5));
+    }
+    let progress = progress.lock().unwrap();
+    assert!(progress.len() > 1);
+    assert_eq!(progress.last(), Some(&(40_000, Some(40_000))));
+    assert!(progress.windows(2).all(|x| x[0].0 < x[1].0));
+
+    // Stopping the transfer closes the connection before the last chunk
+    let mut stream = connect(port);
+    send(&mut stream, "GET /quota HTTP/1.1\r\n\r\n");
+    let mut raw = Vec::new();
+    stream.read_to_end(&mut raw).unwrap();
+    assert!(raw.len() < 100_000);
+    assert!(!raw.ends_with(b"0\r\n\r\n"));
+}
+
+#[test]
+#[cfg(feature = "oauth")]
+fn oauth_login() {
+    use afire::{
+        extension::oauth::{OAuth, Provider},
+        internal::encoding::{base64, sha256},
+    };
+
+    // A fake provider, that checks the PKCE challenge from the login redirect and sends back the id token claims it's given
+    let challenge = Arc::new(std::sync::Mutex::new(String::new()));
+    let id_claims = Arc::new(std::sync::Mutex::new(String::new()));
+    let mut provider = Server::<()>::new("127.0.0.1", 0);
+    let expected = challenge.clone();
+    let token_claims = id_claims.clone();
+    provider.route(Method::POST, "/token", move |req| {
+        let form = Query::from_body(&req.body_str());
+        let verifier = form.get("code_verifier").unwrap_or_default();
+        let pkce = base64::encode_url(&sha256::hash(verifier.as_bytes()));
+        if form.get("code") != Some("good-code") || pkce != *expected.lock().unwrap() {
+            return Response::new()
+                .status(400)
+                .text(r#"{"error": "invalid_grant"}"#);
+        }
+
+        let claims = base64::encode_url(token_claims.lock().unwrap().as_bytes());
+        Response::new()
+            .text(format!(
+                r#"{{"access_token": "token", "expires_in": 60, "id_token": "e30.{claims}.sig"}}"#
+            ))
+            .content(Content::JSON)
+    });
+    let issuer = format!("http://127.0.0.1:{}", start(provider));
+
+    // The redirect uri needs the server's port before it starts, so it's bound first
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let callback = format!(
+        "http://127.0.0.1:{}/auth/callback",
+        listener.local_addr().unwrap().port()
+    );
+    let mut server = Server::<()>::new("127.0.0.1", 0).listener(listener);
+    let oauth = OAuth::new(
+        Provider::new(format!("{issuer}/authorize"), format!("{issuer}/token")).issuer(&issuer),
+        "client",
+        &callback,
+    );
+    let sessions = oauth.sessions();
+    oauth.attach(&mut server);
+    server.route(Method::GET, "/me", move |req| {
+        match sessions.identity(req) {
+            Some(user) => {
+                Response::new().text(format!("{} {}", user.subject, user.claim("email").unwrap()))
+            }
+            None => Response::new().status(401),
+        }
+    });
+    let port = start(server);
+
+    let get = |path: &str, cookies: &str| {
+        let mut stream = connect(port);
+        send(
+            &mut stream,
+            &format!("GET {path} HTTP/1.1\r\nCookie: {cookies}\r\n\r\n"),
+        );
+        read_response(&mut stream)
+    };
+    let cookie = |res: &RawResponse, name: &str| {
+        res.headers
+            .iter()
+            .filter(|x| x.0.eq_ignore_ascii_case("Set-Cookie"))
+            .find_map(|x| x.1.strip_prefix(&format!("{name}=")))
+            .map(|x| x.split(';').next().unwrap().to_owned())
+            .unwrap()
+    };
+
+    // Starts a login, returning its state and nonce
+    let login = || {
+        let res = get("/auth/login?redirect=/me", "");
+        assert_eq!(res.status, 302);
+        let location = res.header("Location").unwrap().to_owned();
+        assert!(location.starts_with(&format!(
+            "{issuer}/authorize?response_type=code&client_id=client&redirect_uri={}&",
+            callback.replace(':', "%3A").replace('/', "%2F")
+        )));
+        let param = |name: &str| {
+            location
+                .split(['?', '&'])
+                .find_map(|x| x.strip_prefix(&format!("{name}=")))
+                .unwrap()
+                .to_owned()
+        };
+        let state = param("state");
+        *challenge.lock().unwrap() = param("code_challenge");
+        assert_eq!(cookie(&res, "afire_oauth_state"), state);
+        (state, param("nonce"))
+    };
+    let exp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+        + 60;
+    let claims = |iss: &str, exp: u64, nonce: &str| {
+        format!(
+            r#"{{"sub": "user-1", "aud": "client", "iss": "{iss}", "exp": {exp}, "nonce": "{nonce}", "email": "a@b.c"}}"#
+        )
+    };
+
+    // The id token has to be from the provider's issuer, unexpired, and for this login
+    for (iss, exp, nonce) in [
+        ("http://evil.com", exp, None),
+        (issuer.as_str(), exp - 120, None),
+        (issuer.as_str(), exp, Some("other")),
+    ] {
+        let (state, login_nonce) = login();
+        *id_claims.lock().unwrap() = claims(iss, exp, nonce.unwrap_or(&login_nonce));
+        let res = get(
+            &format!("/auth/callback?code=good-code&state={state}"),
+            &format!("afire_oauth_state={state}"),
+        );
+        assert_eq!(res.status, 502);
+    }
+    let (state, nonce) = login();
+    *id_claims.lock().unwrap() = claims(&issuer, exp, &nonce);
+
+    // The state has to match the cookie
+    let res = get(
+        &format!("/auth/callback?code=good-code&state={state}"),
+        "afire_oauth_state=other",
+    );
+    assert_eq!(res.status, 400);
+
+    let res = get(
+        &format!("/auth/callback?code=good-code&state={state}"),
+        &format!("afire_oauth_state={state}"),
+    );
+    assert_eq!(res.status, 302);
+    assert_eq!(res.header("Location"), Some("/me"));
+    let session = cookie(&res, "afire_session");
+
+    // The state can only be used once
+    let res = get(
+        &format!("/auth/callback?code=good-code&state={state}"),
+        &format!("afire_oauth_state={state}"),
+    );
+    assert_eq!(res.status, 400);
+
+    let res = get("/me", &format!("afire_session={session}"));
+    assert_eq!(res.body, b"user-1 a@b.c");
+
+    get("/auth/logout", &format!("afire_session={session}"));
+    assert_eq!(get("/me", &format!("afire_session={session}")).status, 401);
+}
+
+#[test]
+#[cfg(feature = "extensions")]
+fn mime_registry() {
+    afire::mime::register(".webmanifest", "application/manifest+json");
+
+    let dir = temp_dir("mime");
+    fs::write(dir.join("index.html"), "<h1>Hello</h1>").unwrap();
+    fs::write(dir.join("app.webmanifest"), "{}").unwrap();
+    fs::write(dir.join("image.png"), "png").unwrap();
+    fs::write(dir.join("video.3gp"), "3gp").unwrap();
+
+    let mut server = Server::<()>::new("127.0.0.1", 0);
+    server.route(Method::GET, "/manifest", |_| {
+        Response::new()
+            .text("{}")
+            .content(Content::Custom("application/manifest+json"))
+    });
+    ServeStatic::new(dir.to_string_lossy())
+        .mime_type(".3gp", "video/3gpp")
+        .attach(&mut server);
+    let mut stream = connect(start(server));
+
+    let mut content_type = |path: &str| {
+        send(
+            &mut stream,
+            &format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n"),
+        );
+        let res = read_response(&mut stream);
+        assert_eq!(res.status, 200, "{path}");
+        res.header("Content-Type").unwrap().to_owned()
+    };
+
+    assert_eq!(content_type("/index.html"), "text/html; charset=utf-8");
+    assert_eq!(
+        content_type("/app.webmanifest"),
+        "application/manifest+json; charset=utf-8"
+    );
+    assert_eq!(
+        content_type("/manifest"),
+        "application/manifest+json; charset=utf-8"
+    );
+    assert_eq!(content_type("/image.png"), "image/png");
+    assert_eq!(content_type("/video.3gp"), "video/3gpp");
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+#[cfg(feature = "serde")]
+fn websocket_json() {
+    let mut server = Server::<()>::new("127.0.0.1", 0);
+    server.route(Method::GET, "/ws", |req| {
+        let ws = req.ws().unwrap();
+        for i in &ws {
+            match i.as_json::<serde_json::Value>() {
+                Ok(i) => ws
+                    .send_json(&serde_json::json!({ "n": i["n"].as_i64().unwrap() + 1 }))
+                    .unwrap(),
+                Err(e) => ws.send(e),
+            }
+        }
+        Response::end()
+    });
+    let mut stream = connect(start(server));
+
+    send(
+        &mut stream,
+        "GET /ws HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n",
+    );
+    assert_eq!(read_response(&mut stream).status, 101);
+
+    let mut message = |text: &str| {
+        let mask = [1, 2, 3, 4];
+        let mut frame = vec![0x81, 0x80 | text.len() as u8];
+        frame.extend_from_slice(&mask);
+        frame.extend(text.bytes().enumerate().map(|(i, x)| x ^ mask[i % 4]));
+        stream.get_mut().write_all(&frame).unwrap();
+
+        let mut head = [0; 2];
+        stream.read_exact(&mut head).unwrap();
+        assert_eq!(head[0], 0x81);
+        let mut payload = vec![0; head[1] as usize];
+        stream.read_exact(&mut payload).unwrap();
+        String::from_utf8(payload).unwrap()
+    };
+
+    assert_eq!(message(r#"{"n": 1}"#), r#"{"n":2}"#);
+    assert_eq!(message(r#"{"n": 41}"#), r#"{"n":42}"#);
+    assert!(message("nope").starts_with("Invalid JSON: "));
+}
+
+#[test]
+fn sse_backfill() {
+    let mut server = Server::<()>::new("127.0.0.1", 0);
+    server.route(Method::GET, "/sse", |req| {
+        let stream = req.sse().unwrap().retry(1000).backfill(|last| {
+            (last + 1..4).map(|i| Event::new("update").id(i).data(format!("missed {i}")))
+        });
+        stream.send_id("update", 4, "live");
+        stream.close();
+        Response::end()
+    });
+    let port = start(server);
+
+    let events = |last_id: Option<u32>| {
+        let mut stream = connect(port);
+        let last_id = last_id.map_or_else(String::new, |x| format!("Last-Event-ID: {x}\r\n"));
+        send(&mut stream, &format!("GET /sse HTTP/1.1\r\n{last_id}\r\n"));
+
+        let mut out = String::new();
+        loop {
+            let mut line = String::new();
+            stream.read_line(&mut line).unwrap();
+            out.push_str(&line);
+            if line == "data: live\n" {
+                break out;
+            }
+        }
+    };
+
+    let fresh = events(None);
+    assert!(fresh.contains("\r\n\r\nretry: 1000\n\nid: 4\nevent: update\ndata: live\n"));
+
+    let resumed = events(Some(1));
+    assert!(resumed.ends_with(
+        "retry: 1000\n\nid: 2\nevent: update\ndata: missed 2\n\n\
+         id: 3\nevent: update\ndata: missed 3\n\nid: 4\nevent: update\ndata: live\n"
+    ));
+}
+
+#[test]
+fn route_groups() {
+    struct Tag(&'static str);
+    impl Middleware for Tag {
+        fn post(&self, _req: &Request, res: &mut Response) -> MiddleResult {
+            res.headers.add("X-Group", self.0);
+            MiddleResult::Continue
+        }
+    }
+
+    struct Deny;
+    impl Middleware for Deny {
+        fn pre(&self, _req: &mut Request) -> MiddleResult {
+            MiddleResult::Send(Response::new().status(403).text("Forbidden"))
+        }
+    }
+
+    let mut server = Server::<()>::new("127.0.0.1", 0);
+    server.route(Method::GET, "/apis", |_| Response::new().text("outside"));
+    server.group("/api/", |api| {
+        api.attach(Tag("api"));
+        api.route(Method::GET, "/", |_| Response::new().text("index"));
+        api.route(Method::GET, "users", |_| Response::new().text("users"));
+        api.group("/users/{id}", |user| {
+            user.route(Method::GET, "/posts", |req| {
+                Response::new().text(format!("posts of {}", req.param("id").unwrap()))
+            });
+        });
+        api.group("/admin", |admin| {
+            admin.attach(Deny);
+            admin.route(Method::GET, "/", |_| Response::new().text("admin"));
+        });
+    });
+    let mut stream = connect(start(server));
+
+    let mut get = |path: &str| {
+        send(
+            &mut stream,
+            &format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n"),
+        );
+        let res = read_response(&mut stream);
+        let tag = res.header("X-Group").map(ToOwned::to_owned);
+        (res.status, String::from_utf8(res.body).unwrap(), tag)
+    };
+
+    let api = Some("api".to_owned());
+    assert_eq!(get("/api"), (200, "index".to_owned(), api.clone()));
+    assert_eq!(get("/api/users"), (200, "users".to_owned(), api.clone()));
+    assert_eq!(
+        get("/api/users/7/posts"),
+        (200, "posts of 7".to_owned(), api.clone())
+    );
+    assert_eq!(
+        get("/api/admin"),
+        (403, "Forbidden".to_owned(), api.clone())
+    );
+    assert_eq!(get("/api/missing").0, 404);
+    assert_eq!(get("/apis"), (200, "outside".to_owned(), None));
+}
+
+#[test]
+#[allow(deprecated)]
+fn legacy_route() {
+    fn echo(req: Request) -> Response {
+        let body = String::from_utf8_lossy(&req.body).into_owned();
+        Response::new().text(format!("{} {}", req.param("name").unwrap(), body))
+    }
+
+    let mut server = Server::<()>::new("127.0.0.1", 0);
+    server.legacy_route(Method::POST, "/echo/{name}", echo);
+    server.route(Method::GET, "/new", |_| Response::new().text("new"));
+    let mut stream = connect(start(server));
+
+    send(
+        &mut stream,
+        "POST /echo/Bob HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\n\r\nhello",
+    );
+    assert_eq!(read_response(&mut stream).body, b"Bob hello");
+
+    send(&mut stream, "GET /new HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    assert_eq!(read_response(&mut stream).body, b"new");
+}
+
+#[test]
+fn typed_params() {
+    let mut server = Server::<()>::new("127.0.0.1", 0);
+    server.route(Method::GET, "/post/{id}", |req| {
+        let id = match req.param_parsed::<u32>("id") {
+            Ok(i) => i,
+            Err(e) => return e.into(),
+        };
+        match req.query.get_parsed::<u8>("page") {
+            Ok(page) => Response::new().text(format!("{id}:{page}")),
+            Err(e) => e.into(),
+        }
+    });
+    let mut stream = connect(start(server));
+
+    let mut get = |path: &str| {
+        send(
+            &mut stream,
+            &format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n"),
+        );
+        let res = read_response(&mut stream);
+        (res.status, String::from_utf8(res.body).unwrap())
+    };
+
+    assert_eq!(get("/post/7?page=2"), (200, "7:2".to_owned()));
+    assert_eq!(
+        get("/post/abc?page=2"),
+        (
+            400,
+            "Invalid path parameter `id` (\"abc\"): invalid digit found in string".to_owned()
+        )
+    );
+    assert_eq!(
+        get("/post/7"),
+        (400, "Missing query parameter `page`".to_owned())
+    );
+    assert_eq!(get("/post/7?page=300").0, 400);
+}
+
+#[test]
+#[cfg(all(feature = "extensions", feature = "serde"))]
+fn request_history() {
+    let mut server = Server::<()>::new("127.0.0.1", 0);
+    server.route(Method::GET, "/ok", |_| Response::new().text("ok"));
+    server.route(Method::GET, "/panic", |_| panic!("oh no"));
+    RequestId::new("X-Request-Id").attach(&mut server);
+    let history = RequestHistory::new(2).path("/debug/requests");
+    let records = history.history();
+    history.attach(&mut server);
+    let mut stream = connect(start(server));
+
+    let mut get = |path: &str| {
+        send(
+            &mut stream,
+            &format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n"),
+        );
+        read_response(&mut stream)
+    };
+
+    get("/ok");
+    assert_eq!(get("/panic").status, 500);
+    assert_eq!(get("/missing").status, 404);
+
+    // End middleware runs after the response is written
+    let start = Instant::now();
+    while records.last().unwrap().path != "/missing" {
+        assert!(start.elapsed() < Duration::from_secs(5));
+        thread::sleep(Duration::from_millis(5));
+    }
+    let entries = records.entries();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].path, "/panic");
+    assert_eq!(entries[0].status, 500);
+    assert_eq!(
+        entries[0].error.as_deref(),
+        Some("Route handler panicked: oh no")
+    );
+    assert_eq!(entries[0].request_id.as_deref(), Some("1"));
+    assert!(entries[0].duration.is_some());
+    assert_eq!(entries[1].status, 404);
+
+    let res = get("/debug/requests");
+    assert_eq!(
+        res.header("Content-Type"),
+        Some("application/json; charset=utf-8")
+    );
+    let json: serde_json::Value = serde_json::from_slice(&res.body).unwrap();
+    assert_eq!(json[0]["path"], "/missing");
+    assert_eq!(json[0]["request_id"], "2");
+    assert_eq!(json[1]["error"], "Route handler panicked: oh no");
+}
+
+#[test]
+#[cfg(feature = "serde")]
+fn json_body() {
+    let mut server = Server::<()>::new("127.0.0.1", 0);
+    server.route(Method::POST, "/echo", |req| match req
+        .json::<std::collections::HashMap<String, u32>>()
+    {
+        Ok(i) => Response::new().json(&i),
+        Err(e) => e.into(),
+    });
+    let mut stream = connect(start(server));
+
+    let mut post = |content_type: &str, body: &str| {
+        send(
+            &mut stream,
+            &format!(
+                "POST /echo HTTP/1.1\r\nHost: localhost\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len()
+            ),
+        );
+        read_response(&mut stream)
+    };
+
+    let res = post("application/json; charset=utf-8", r#"{"a":1}"#);
+    assert_eq!(res.status, 200);
+    assert_eq!(
+        res.header("Content-Type"),
+        Some("application/json; charset=utf-8")
+    );
+    assert_eq!(res.body, br#"{"a":1}"#);
+
+    let res = post("text/plain", r#"{"a":1}"#);
+    assert_eq!(res.status, 400);
+    assert_eq!(
+        String::from_utf8(res.body).unwrap(),
+        "Invalid body: expected a JSON content type, got \"text/plain\""
+    );
+
+    let res = post("application/json", r#"{"a":"one"}"#);
+    assert_eq!(res.status, 400);
+    assert!(String::from_utf8(res.body)
+        .unwrap()
+        .starts_with("Invalid body: invalid type"));
+}
+
+#[test]
+fn method_not_allowed() {
+    let mut server = Server::<()>::new("127.0.0.1", 0).method_not_allowed(true);
+    server.route(Method::GET, "/item/{id}", |_req| Response::new());
+    server.route(Method::DELETE, "/item/{id}", |_req| Response::new());
+    server.route(Method::POST, "/items", |_req| Response::new());
+    let mut stream = connect(start(server));
+
+    send(
+        &mut stream,
+        "PUT /item/1 HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\n\r\n",
+    );
+    let res = read_response(&mut stream);
+    assert_eq!(res.status, 405);
+    assert_eq!(res.header("Allow"), Some("GET, DELETE"));
+    assert_eq!(res.body, b"Cannot PUT /item/1");
+
+    send(
+        &mut stream,
+        "GET /nothing HTTP/1.1\r\nHost: localhost\r\n\r\n",
+    );
+    assert_eq!(read_response(&mut stream).status, 404);
+
+    // Disabled by default
+    let mut server = Server::<()>::new("127.0.0.1", 0);
+    server.route(Method::POST, "/items", |_req| Response::new());
+    let mut stream = connect(start(server));
+    send(
+        &mut stream,
+        "GET /items HTTP/1.1\r\nHost: localhost\r\n\r\n",
+    );
+    let res = read_response(&mut stream);
+    assert_eq!(res.status, 404);
+    assert_eq!(res.header("Allow"), None);
+}
+
+#[test]
+fn auto_options() {
+    let mut server = Server::<()>::new("127.0.0.1", 0).auto_options(true);
+    server.route(Method::GET, "/users", |_req| Response::new());
+    server.route(Method::POST, "/users", |_req| Response::new());
+    server.route(Method::OPTIONS, "/custom", |_req| {
+        Response::new().text("custom")
+    });
+    server.route(Method::GET, "/custom", |_req| Response::new());
+    let mut stream = connect(start(server));
+
+    send(
+        &mut stream,
+        "OPTIONS /users HTTP/1.1\r\nHost: localhost\r\n\r\n",
+    );
+    let res = read_response(&mut stream);
+    assert_eq!(res.status, 204);
+    assert_eq!(res.header("Allow"), Some("GET, POST, OPTIONS"));
+
+    send(
+        &mut stream,
+        "OPTIONS /custom HTTP/1.1\r\nHost: localhost\r\n\r\n",
+    );
+    let res = read_response(&mut stream);
+    assert_eq!(res.status, 200);
+    assert_eq!(res.body, b"custom");
+
+    send(
+        &mut stream,
+        "OPTIONS /missing HTTP/1.1\r\nHost: localhost\r\n\r\n",
+    );
+    assert_eq!(read_response(&mut stream).status, 404);
+}
+
+#[test]
+fn connection_limit() {
+    let mut server = Server::<()>::new("127.0.0.1", 0)
+        .max_connections(1)
+        .overflow_policy(OverflowPolicy::Reject);
+    server.route(Method::GET, "/", |_| Response::new().text("Hello"));
+    let stats = server.connection_stats.clone();
+    let port = start_with(server, |x| x.start_threaded(4));
+    thread::sleep(Duration::from_millis(100));
+
+    // A keep-alive connection holds the only slot
+    let mut first = connect(port);
+    send(&mut first, "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    assert_eq!(read_response(&mut first).body, b"Hello");
+    assert_eq!(stats.open(), 1);
+
+    let mut rejected = connect(port);
+    assert_eq!(read_response(&mut rejected).status, 503);
+    assert_closed(&mut rejected);
+
+    drop(first);
+    let start = Instant::now();
+    while stats.open() > 0 {
+        assert!(start.elapsed() < Duration::from_secs(5));
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    let mut stream = connect(port);
+    send(&mut stream, "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    assert_eq!(read_response(&mut stream).body, b"Hello");
+}
+
+#[test]
+fn http_1_0() {
+    let mut server = Server::<()>::new("127.0.0.1", 0);
+    server.route(Method::GET, "/", |req| Response::new().text(req.version));
+    server.route(Method::GET, "/stream", |_req| {
+        Response::new().stream(&b"streamed body"[..])
+    });
+    let port = start(server);
+
+    // Closed after the response by default
+    let mut stream = connect(port);
+    send(&mut stream, "GET / HTTP/1.0\r\n\r\n");
+    let res = read_response(&mut stream);
+    assert_eq!(res.body, b"HTTP/1.0");
+    assert_eq!(res.header("Connection"), None);
+    assert_closed(&mut stream);
+
+    // Kept open if asked, until a streamed body has to be ended by closing
+    let mut stream = connect(port);
+    send(
+        &mut stream,
+        "GET / HTTP/1.0\r\nConnection: keep-alive\r\n\r\n",
+    );
+    let res = read_response(&mut stream);
+    assert_eq!(res.header("Connection"), Some("keep-alive"));
+
+    send(
+        &mut stream,
+        "GET /stream HTTP/1.0\r\nConnection: keep-alive\r\n\r\n",
+    );
+    let res = read_response(&mut stream);
+    assert_eq!(res.header("Transfer-Encoding"), None);
+    assert_eq!(res.header("Connection"), Some("close"));
+    let mut body = Vec::new();
+    stream.read_to_end(&mut body).unwrap();
+    assert_eq!(body, b"streamed body");
+
+    let mut stream = connect(port);
+    send(&mut stream, "GET / HTTP/2.0\r\n\r\n");
+    assert_eq!(read_response(&mut stream).status, 505);
+    assert_closed(&mut stream);
+}
+
+#[test]
+fn chunked_request() {
+    let mut server = Server::<()>::new("127.0.0.1", 0).body_buffer_limit(8);
+    server.route(Method::POST, "/", |req| {
+        let mut body = String::new();
+        req.body_reader().read_to_string(&mut body).unwrap();
+        let trailers = req.trailers().unwrap();
+        Response::new().text(format!(
+            "{body}|{}|{}",
+            req.body_streamed(),
+            trailers.get("Checksum").unwrap_or("none")
+        ))
+    });
+    let mut stream = connect(start(server));
+
+    // Buffered, with the next request pipelined right after the body
+    send(
+        &mut stream,
+        "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n0\r\n\r\n\
+         POST / HTTP/1.1\r\nTransfer-Encoding: gzip, chunked\r\n\r\n2;x=y\r\nhi\r\n0\r\nChecksum: 42\r\n\r\n",
+    );
+    assert_eq!(read_response(&mut stream).body, b"abc|false|none");
+    assert_eq!(read_response(&mut stream).body, b"hi|false|42");
+
+    // Streamed past the buffer limit, with trailers read at the end
+    send(
+        &mut stream,
+        "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n6\r\nHello,\r\n6\r\n World\r\n1\r\n!\r\n0\r\nChecksum: 7\r\n\r\n",
+    );
+    assert_eq!(read_response(&mut stream).body, b"Hello, World!|true|7");
+
+    send(
+        &mut stream,
+        "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\nabc\r\n0\r\n\r\n",
+    );
+    assert_eq!(read_response(&mut stream).status, 400);
+}
+
+#[test]
+#[cfg(feature = "extensions")]
+fn static_range() {
+    let dir = temp_dir("range");
+    fs::write(dir.join("data.txt"), "0123456789").unwrap();
+
+    let mut server = Server::<()>::new("127.0.0.1", 0);
+    Range.attach(&mut server);
+    ServeStatic::new(dir.to_string_lossy()).attach(&mut server);
+    let mut stream = connect(start(server));
+
+    send(
+        &mut stream,
+        "GET /data.txt HTTP/1.1\r\nHost: localhost\r\n\r\n",
+    );
+    let res = read_response(&mut stream);
+    assert_eq!(res.status, 200);
+    assert_eq!(res.header("Accept-Ranges"), Some("bytes"));
+    let etag = res.header("ETag").unwrap().to_owned();
+
+    send(
+        &mut stream,
+        &format!("GET /data.txt HTTP/1.1\r\nRange: bytes=-4\r\nIf-Range: {etag}\r\n\r\n"),
+    );
+    let res = read_response(&mut stream);
+    assert_eq!(res.status, 206);
+    assert_eq!(res.header("Content-Range"), Some("bytes 6-9/10"));
+    assert_eq!(res.body, b"6789");
+
+    send(
+        &mut stream,
+        "GET /data.txt HTTP/1.1\r\nRange: bytes=20-\r\n\r\n",
+    );
+    let res = read_response(&mut stream);
+    assert_eq!(res.status, 416);
+    assert_eq!(res.header("Content-Range"), Some("bytes */10"));
+    assert!(res.body.is_empty());
+}
+
+#[test]
+#[cfg(feature = "extensions")]
+fn static_spa_fallback() {
+    let dir = temp_dir("spa");
+    fs::write(dir.join("index.html"), "<div id=app>").unwrap();
+    fs::write(dir.join("app.js"), "render()").unwrap();
+
+    let mut server = Server::<()>::new("127.0.0.1", 0);
+    ServeStatic::new(dir.to_string_lossy())
+        .spa_fallback("/index.html")
+        .attach(&mut server);
+    let mut stream = connect(start(server));
+
+    let mut get = |path: &str| {
+        send(
+            &mut stream,
+            &format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n"),
+        );
+        let res = read_response(&mut stream);
+        (res.status, String::from_utf8(res.body).unwrap())
+    };
+
+    assert_eq!(get("/dashboard/settings"), (200, "<div id=app>".to_owned()));
+    assert_eq!(get("/app.js"), (200, "render()".to_owned()));
+    assert_eq!(get("/missing.js").0, 404);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+#[cfg(feature = "extensions")]
+fn serve_embedded() {
+    let mut server = Server::<()>::new("127.0.0.1", 0);
+    server.route(Method::GET, "/assets/live", |_| {
+        Response::new().text("route")
+    });
+    ServeEmbedded::new([
+        ("index.html", &b"<h1>Home</h1>"[..]),
+        ("app.js", &b"start()"[..]),
+    ])
+    .path("/assets")
+    .attach(&mut server);
+    let mut stream = connect(start(server));
+
+    send(
+        &mut stream,
+        "GET /assets/ HTTP/1.1\r\nHost: localhost\r\n\r\n",
+    );
+    let res = read_response(&mut stream);
+    assert_eq!(res.body, b"<h1>Home</h1>");
+    assert_eq!(res.header("Content-Type"), Some("text/html; charset=utf-8"));
+
+    send(
+        &mut stream,
+        "GET /assets/app.js HTTP/1.1\r\nHost: localhost\r\n\r\n",
+    );
+    let res = read_response(&mut stream);
+    assert_eq!(res.body, b"start()");
+    let etag = res.header("ETag").unwrap().to_owned();
+
+    send(
+        &mut stream,
+        &format!("GET /assets/app.js HTTP/1.1\r\nHost: localhost\r\nIf-None-Match: {etag}\r\n\r\n"),
+    );
+    assert_eq!(read_response(&mut stream).status, 304);
+
+    send(
+        &mut stream,
+        "GET /assets/live HTTP/1.1\r\nHost: localhost\r\n\r\n",
+    );
+    assert_eq!(read_response(&mut stream).body, b"route");
+
+    send(
+        &mut stream,
+        "GET /app.js HTTP/1.1\r\nHost: localhost\r\n\r\n",
+    );
+    assert_eq!(read_response(&mut stream).status, 404);
+}
+
+#[test]
+fn header_limits() {
+    let mut server = Server::<()>::new("127.0.0.1", 0)
+        .max_header_count(3)
+        .max_header_size(64)
+        .max_head_size(150);
+    server.route(Method::GET, "/", |_| Response::new().text("ok"));
+    let port = start(server);
+
+    let long = "a".repeat(100);
+    let many = "X-A: 1\r\n".repeat(4);
+    let huge = format!("X-A: {}\r\n", "a".repeat(50)).repeat(3);
+    for raw in [
+        format!("GET / HTTP/1.1\r\nX-Long: {long}\r\n\r\n"),
+        format!("GET / HTTP/1.1\r\n{many}\r\n"),
+        format!("GET / HTTP/1.1\r\n{huge}\r\n"),
+        format!("GET /{long}{long}{long} HTTP/1.1\r\n\r\n"),
+    ] {
+        let mut stream = connect(port);
+        send(&mut stream, &raw);
+        assert_eq!(read_response(&mut stream).status, 431, "{raw:?}");
+        assert_closed(&mut stream);
+    }
+
+    // Right at the limits
+    let mut stream = connect(port);
+    let exact = format!("X-Long: {}", "a".repeat(64 - 8));
+    send(
+        &mut stream,
+        &format!("GET / HTTP/1.1\r\n{exact}\r\nA: 1\r\nB: 2\r\n\r\n"),
+    );
+    assert_eq!(read_response(&mut stream).status, 200);
+}
+
+#[test]
+fn uri_limit() {
+    let mut server = Server::<()>::new("127.0.0.1", 0).max_uri_length(100);
+    server.route(Method::GET, "**", |_| Response::new().text("ok"));
+    let port = start(server);
+
+    // Too long for the request line limit, and too long once the whole line is read
+    for len in [200, 101] {
+        let mut stream = connect(port);
+        send(
+            &mut stream,
+            &format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(len - 1)),
+        );
+        assert_eq!(read_response(&mut stream).status, 414, "{len}");
+        assert_closed(&mut stream);
+    }
+
+    let mut stream = connect(port);
+    send(
+        &mut stream,
+        &format!("GET /{}?a=b HTTP/1.1\r\n\r\n", "a".repeat(95)),
+    );
+    assert_eq!(read_response(&mut stream).status, 200);
+}
+
+#[test]
+fn head_timeout() {
+    let mut server = Server::<()>::new("127.0.0.1", 0).head_timeout(Duration::from_millis(300));
+    server.route(Method::GET, "/", |_| Response::new().text("ok"));
+    let port = start(server);
+
+    // Sending a byte at a time doesn't reset the deadline
+    let mut stream = connect(port);
+    let start = Instant::now();
+    for i in "GET / HTTP/1.1\r\n".bytes() {
+        stream.get_mut().write_all(&[i]).unwrap();
+        thread::sleep(Duration::from_millis(50));
+        if start.elapsed() > Duration::from_millis(200) {
+            break;
+        }
+    }
+    assert_eq!(read_response(&mut stream).status, 408);
+    assert!(start.elapsed() < Duration::from_secs(3));
+
+    // A partial head after a keep-alive request still gets a 408
+    let mut stream = connect(port);
+    send(&mut stream, "GET / HTTP/1.1\r\n\r\n");
+    assert_eq!(read_response(&mut stream).status, 200);
+    send(&mut stream, "GET / HT");
+    assert_eq!(read_response(&mut stream).status, 408);
+    assert_closed(&mut stream);
+
+    // Waiting between keep-alive requests is fine
+    let mut stream = connect(port);
+    send(&mut stream, "GET / HTTP/1.1\r\n\r\n");
+    assert_eq!(read_response(&mut stream).status, 200);
+    thread::sleep(Duration::from_millis(500));
+    send(&mut stream, "GET / HTTP/1.1\r\n\r\n");
+    assert_eq!(read_response(&mut stream).status, 200);
+    drop(stream);
+
+    // But a new connection that never sends anything is closed without a response, freeing the only worker
+    let start = Instant::now();
+    let mut idle = connect(port);
+    assert_closed(&mut idle);
+    assert!(start.elapsed() < Duration::from_secs(3));
+
+    let mut stream = connect(port);
+    send(&mut stream, "GET / HTTP/1.1\r\n\r\n");
+    assert_eq!(read_response(&mut stream).status, 200);
+}
+
+#[test]
+fn file_body() {
+    let dir = temp_dir("sendfile");
+    let path = dir.join("large.bin");
+    // Bigger than one sendfile window, so it is sent in more than one chunk
+    let data = (0..3 * 1024 * 1024 + 7)
+        .map(|x| (x % 251) as u8)
+        .collect::<Vec<_>>();
+    fs::write(&path, &data).unwrap();
+
+    let mut server = Server::<()>::new("127.0.0.1", 0);
+    let file_path = path.clone();
+    server.route(Method::GET, "/", move |_| {
+        Response::new().stream(fs::File::open(&file_path).unwrap())
+    });
+    let file_path = path.clone();
+    server.route(Method::GET, "/offset", move |_| {
+        use std::io::{Seek, SeekFrom};
+        let mut file = fs::File::open(&file_path).unwrap();
+        file.seek(SeekFrom::Start(1000)).unwrap();
+        Response::new().stream(file)
+    });
+    let port = start(server);
+
+    let mut stream = connect(port);
+    send(&mut stream, "GET / HTTP/1.1\r\n\r\n");
+    let res = read_response(&mut stream);
+    assert_eq!(res.header("Transfer-Encoding"), Some("chunked"));
+    assert!(res.body == data);
+
+    // The connection can be reused after the file
+    send(
+        &mut stream,
+        "GET /offset HTTP/1.1\r\nConnection: close\r\n\r\n",
+    );
+    assert!(read_response(&mut stream).body == data[1000..]);
+
+    // HTTP/1.0 clients get the raw file, ended by closing the connection
+    let mut stream = connect(port);
+    send(&mut stream, "GET / HTTP/1.0\r\n\r\n");
+    let mut raw = Vec::new();
+    stream.read_to_end(&mut raw).unwrap();
+    let start = raw.windows(4).position(|x| x == b"\r\n\r\n").unwrap() + 4;
+    assert!(raw[start..] == data[..]);
+}
+
+#[test]
+fn keep_alive_limits() {
+    let mut server = Server::<()>::new("127.0.0.1", 0)
+        .keep_alive_max(2)
+        .keep_alive_timeout(Duration::from_secs(1));
+    server.route(Method::GET, "/", |_| Response::new().text("ok"));
+    let port = start(server);
+
+    let mut stream = connect(port);
+    send(&mut stream, "GET / HTTP/1.1\r\n\r\n");
+    let res = read_response(&mut stream);
+    assert_eq!(res.header("Keep-Alive"), Some("timeout=1, max=1"));
+
+    // The last request allowed on the connection
+    send(&mut stream, "GET / HTTP/1.1\r\n\r\n");
+    let res = read_response(&mut stream);
+    assert_eq!(res.header("Connection"), Some("close"));
+    assert_eq!(res.header("Keep-Alive"), None);
+    assert_closed(&mut stream);
+
+    // Idle connections are closed after the timeout, without a response
+    let mut stream = connect(port);
+    send(&mut stream, "GET / HTTP/1.1\r\n\r\n");
+    assert_eq!(read_response(&mut stream).status, 200);
+    let start = Instant::now();
+    assert_closed(&mut stream);
+    assert!(start.elapsed() >= Duration::from_millis(900));
+    assert!(start.elapsed() < Duration::from_secs(3));
+}
+
+#[test]
+fn auto_head() {
+    let mut server = Server::<()>::new("127.0.0.1", 0)
+        .auto_head(true)
+        .method_not_allowed(true);
+    server.route(Method::GET, "/", |_req| {
+        Response::new().text("Hello, World!")
+    });
+    server.route(Method::GET, "/stream", |_req| {
+        Response::new().stream(std::io::Cursor::new(b"streamed".to_vec()))
+    });
+    server.route(Method::HEAD, "/custom", |_req| {
+        Response::new().header("X-Custom", "true")
+    });
+    let mut stream = connect(start(server));
+
+    send(&mut stream, "HEAD / HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    let res = read_head(&mut stream);
+    assert_eq!(res.status, 200);
+    assert_eq!(res.header("Content-Length"), Some("13"));
+
+    // The body of a stream isn't sent
+    send(
+        &mut stream,
+        "HEAD /stream HTTP/1.1\r\nHost: localhost\r\n\r\n",
+    );
+    let res = read_head(&mut stream);
+    assert_eq!(res.header("Transfer-Encoding"), Some("chunked"));
+
+    send(
+        &mut stream,
+        "HEAD /custom HTTP/1.1\r\nHost: localhost\r\n\r\n",
+    );
+    let res = read_head(&mut stream);
+    assert_eq!(res.header("X-Custom"), Some("true"));
+
+    // Nothing else was written, so the next response is read correctly
+    send(&mut stream, "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    assert_eq!(read_response(&mut stream).body, b"Hello, World!");
+
+    send(&mut stream, "PUT / HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    let res = read_response(&mut stream);
+    assert_eq!(res.status, 405);
+    assert_eq!(res.header("Allow"), Some("GET, HEAD"));
+}
+
+#[test]
+#[cfg(feature = "serde")]
+fn form_body() {
+    let mut server = Server::<()>::new("127.0.0.1", 0);
+    server.route(Method::POST, "/raw", |req| match req.form() {
+        Ok(form) => Response::new().text(form.get_all("tag").join(",")),
+        Err(e) => e.into(),
+    });
+    server.route(Method::POST, "/typed", |req| match req
+        .form_as::<std::collections::HashMap<String, u32>>()
+    {
+        Ok(i) => Response::new().json(&i),
+        Err(e) => e.into(),
+    });
+    let mut stream = connect(start(server));
+
+    let mut post = |path: &str, content_type: &str, body: &str| {
+        send(
+            &mut stream,
+            &format!(
+                "POST {path} HTTP/1.1\r\nHost: localhost\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len()
+            ),
+        );
+        read_response(&mut stream)
+    };
+
+    let form = "application/x-www-form-urlencoded";
+    let res = post("/raw", form, "tag=a+b&tag=c%26d&other=1");
+    assert_eq!(res.body, b"a b,c&d");
+
+    let res = post("/typed", form, "a=1&b=2");
+    assert_eq!(res.status, 200);
+    let json: serde_json::Value = serde_json::from_slice(&res.body).unwrap();
+    assert_eq!(json["b"], 2);
+
+    assert_eq!(post("/typed", form, "a=one").status, 400);
+    assert_eq!(post("/raw", "text/plain", "tag=a").status, 400);
+}
+
+#[test]
+fn typed_bodies() {
+    let dir = temp_dir("typed-bodies");
+    fs::write(dir.join("style.css"), "body {}").unwrap();
+
+    let mut server = Server::<()>::new("127.0.0.1", 0);
+    server.route(Method::GET, "/html", |_req| {
+        Response::new().html("<p>Hi</p>")
+    });
+    let file_dir = dir.clone();
+    server.route(Method::GET, "/file/{name}", move |req| {
+        Response::new().file(file_dir.join(req.param("name").unwrap().as_ref()))
+    });
+    let mut stream = connect(start(server));
+
+    send(&mut stream, "GET /html HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    let res = read_response(&mut stream);
+    assert_eq!(res.body, b"<p>Hi</p>");
+    assert_eq!(res.header("Content-Type"), Some("text/html; charset=utf-8"));
+
+    send(
+        &mut stream,
+        "GET /file/style.css HTTP/1.1\r\nHost: localhost\r\n\r\n",
+    );
+    let res = read_response(&mut stream);
+    assert_eq!(res.body, b"body {}");
+    assert_eq!(res.header("Content-Type"), Some("text/css; charset=utf-8"));
+
+    send(
+        &mut stream,
+        "GET /file/missing.txt HTTP/1.1\r\nHost: localhost\r\n\r\n",
+    );
+    assert_eq!(read_response(&mut stream).status, 404);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn websocket_limits() {
+    let mut server = Server::<()>::new("127.0.0.1", 0);
+    server.route(Method::GET, "/ws", |req| {
+        let ws = req.ws().unwrap().max_message_size(8).max_frames(3);
+        for i in &ws {
+            if let TxType::Text(text) = i {
+                ws.send(text);
+            }
+        }
+        Response::end()
+    });
+    let mut stream = connect(start(server));
+
+    send(
+        &mut stream,
+        "GET /ws HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n",
+    );
+    assert_eq!(read_response(&mut stream).status, 101);
+
+    let mut writer = stream.get_ref().try_clone().unwrap();
+    let mut frame = |opcode: u8, fin: bool, text: &str| {
+        let mask = [1, 2, 3, 4];
+        let mut frame = vec![(fin as u8) << 7 | opcode, 0x80 | text.len() as u8];
+        frame.extend_from_slice(&mask);
+        frame.extend(text.bytes().enumerate().map(|(i, x)| x ^ mask[i % 4]));
+        writer.write_all(&frame).unwrap();
+    };
+
+    // A fragmented message is put back together
+    frame(1, false, "he");
+    frame(0, true, "llo");
+    let mut echo = [0; 7];
+    stream.read_exact(&mut echo).unwrap();
+    assert_eq!(echo[..2], [0x81, 5]);
+    assert_eq!(&echo[2..], b"hello");
+
+    frame(1, false, "way");
+    frame(0, false, "too");
+    frame(0, true, "big");
+
+    // The second message is over the max size, so the socket is closed with 1009
+    let mut close = [0; 4];
+    stream.read_exact(&mut close).unwrap();
+    assert_eq!(close[..2], [0x88, 2]);
+    assert_eq!(u16::from_be_bytes([close[2], close[3]]), 1009);
+}
+
+#[test]
+fn sse_keep_alive() {
+    let mut server = Server::<()>::new("127.0.0.1", 0);
+    server.route(Method::GET, "/sse", |req| {
+        let stream = req
+            .sse()
+            .unwrap()
+            .keep_alive(Some(Duration::from_millis(50)));
+        thread::sleep(Duration::from_millis(120));
+        stream.send_event(Event::new("done").retry(2000).data("a\nb"));
+        stream.close();
+        Response::end()
+    });
+    let mut stream = connect(start(server));
+    send(&mut stream, "GET /sse HTTP/1.1\r\n\r\n");
+
+    let mut out = String::new();
+    loop {
+        let mut line = String::new();
+        stream.read_line(&mut line).unwrap();
+        out.push_str(&line);
+        if line == "data: b\n" {
+            break;
+        }
+    }
+
+    assert!(out.contains("\r\n\r\n: keep-alive\n\n: keep-alive\n\n"));
+    assert!(out.ends_with("event: done\nretry: 2000\ndata: a\ndata: b\n"));
+}
+
+#[test]
+fn response_writer() {
+    let barrier = Arc::new(Barrier::new(2));
+    let mut server = Server::<()>::new("127.0.0.1", 0)
+        .default_header("X-Powered-By", "afire")
+        .reason_phrase(ReasonPhrase::Canonical)
+        .header_policy(
+            HeaderPolicy::new()
+                .remove("X-Powered-By")
+                .set("X-Content-Type-Options", "nosniff"),
+        );
+    let handler_barrier = barrier.clone();
+    server.route(Method::GET, "/progress", move |req| {
+        let mut writer = req
+            .begin_stream(
+                Response::new()
+                    .status(Status::Accepted)
+                    .content(Content::TXT),
+            )
+            .unwrap();
+        writer.write_all(b"first").unwrap();
+        writer.flush().unwrap();
+        handler_barrier.wait();
+        writer.write_all(b"second").unwrap();
+        Response::end()
+    });
+    server.route(Method::GET, "/forgot-end", |req| {
+        let mut writer = req.begin_stream(Response::new()).unwrap();
+        writer.write_all(b"streamed").unwrap();
+        Response::new().text("not sent")
+    });
+    server
+        .route(Method::GET, "/framed", |req| {
+            req.begin_stream(
+                Response::new()
+                    .reason("Fine")
+                    .header("X-Frame-Options", "DENY"),
+            )
+            .unwrap();
+            Response::end()
+        })
+        .header_policy(HeaderPolicy::new().remove("X-Frame-Options"));
+    let port = start(server);
+
+    // The first chunk arrives before the handler finishes
+    let mut stream = connect(port);
+    send(&mut stream, "GET /progress HTTP/1.1\r\n\r\n");
+    let res = read_head(&mut stream);
+    assert_eq!(res.status, 202);
+    assert_eq!(res.header("Transfer-Encoding"), Some("chunked"));
+    assert_eq!(
+        res.header("Content-Type"),
+        Some("text/plain; charset=utf-8")
+    );
+    let mut chunk = String::new();
+    stream.read_line(&mut chunk).unwrap();
+    stream.read_line(&mut chunk).unwrap();
+    assert_eq!(chunk, "5\r\nfirst\r\n");
+
+    barrier.wait();
+    let mut rest = Vec::new();
+    stream.read_to_end(&mut rest).unwrap();
+    assert_eq!(rest, b"6\r\nsecond\r\n0\r\n\r\n");
+
+    // HTTP/1.0 clients get the body as is, ended by closing the connection
+    let mut stream = connect(port);
+    send(&mut stream, "GET /progress HTTP/1.0\r\n\r\n");
+    let res = read_head(&mut stream);
+    assert_eq!(res.header("Transfer-Encoding"), None);
+    assert_eq!(res.header("Connection"), Some("close"));
+    barrier.wait();
+    let mut body = Vec::new();
+    stream.read_to_end(&mut body).unwrap();
+    assert_eq!(body, b"firstsecond");
+
+    // A response returned after streaming isn't sent as a second response
+    let mut stream = connect(port);
+    send(&mut stream, "GET /forgot-end HTTP/1.1\r\n\r\n");
+    assert_eq!(read_response(&mut stream).body, b"streamed");
+    assert_closed(&mut stream);
+
+    // The head is finished like any other response, with the server's and route's policies
+    let mut stream = connect(port);
+    send(&mut stream, "GET /framed HTTP/1.1\r\n\r\n");
+    let mut head = String::new();
+    while !head.ends_with("\r\n\r\n") {
+        stream.read_line(&mut head).unwrap();
+    }
+    assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+    let res = read_head(&mut BufReader::new(head.as_bytes()));
+    assert!(res.header("Server").unwrap().starts_with("afire/"));
+    assert_eq!(res.header("X-Powered-By"), None);
+    assert_eq!(res.header("X-Content-Type-Options"), Some("nosniff"));
+    assert_eq!(res.header("X-Frame-Options"), None);
+}
+
+#[test]
+fn deferred_middleware() {
+    struct Later(&'static str);
+    impl Middleware for Later {
+        fn pre(&self, req: &mut Request) -> MiddleResult {
+            if req.path != self.0 {
+                return MiddleResult::Continue;
+            }
+
+            MiddleResult::defer(|responder| {
+                thread::spawn(move || {
+                    thread::sleep(Duration::from_millis(20));
+                    let body = format!("later {}", responder.request.path);
+                    responder.send(Response::new().text(body)).unwrap();
+                });
+            })
+        }
+    }
+
+    struct PostDefer;
+    impl Middleware for PostDefer {
+        fn post(&self, _req: &Request, _res: &mut Response) -> MiddleResult {
+            MiddleResult::defer(|_| unreachable!())
+        }
+    }
+
+    let mut server = Server::<()>::new("127.0.0.1", 0)
+        .default_header("X-Server", "afire")
+        .default_header("X-Frame-Options", "DENY")
+        .header_policy(HeaderPolicy::new().set("X-Content-Type-Options", "nosniff"));
+    Later("/server").attach(&mut server);
+    server
+        .route(Method::GET, "/route", |_| unreachable!())
+        .with(Later("/route"))
+        .header_policy(HeaderPolicy::new().remove("X-Frame-Options"));
+    server
+        .route(Method::GET, "/post", |_| Response::new().text("post"))
+        .with(PostDefer);
+    let port = start(server);
+
+    for path in ["/server", "/route"] {
+        let mut stream = connect(port);
+        send(&mut stream, &format!("GET {path} HTTP/1.1\r\n\r\n"));
+        let res = read_response(&mut stream);
+        assert_eq!(res.body, format!("later {path}").as_bytes());
+        assert_eq!(res.header("Connection"), Some("close"));
+        assert_eq!(res.header("X-Server"), Some("afire"));
+        assert_eq!(res.header("X-Content-Type-Options"), Some("nosniff"));
+        let frame_options = (path == "/server").then_some("DENY");
+        assert_eq!(res.header("X-Frame-Options"), frame_options);
+        assert_closed(&mut stream);
+    }
+
+    let mut stream = connect(port);
+    send(&mut stream, "GET /post HTTP/1.1\r\n\r\n");
+    let res = read_response(&mut stream);
+    assert_eq!(res.body, b"post");
+}
+
+#[test]
+fn sse_broadcast() {
+    let broadcaster = Broadcaster::new();
+    let mut server = Server::<()>::new("127.0.0.1", 0);
+    let clients = broadcaster.clone();
+    server.route(Method::GET, "/sse/{topic}", move |req| {
+        let stream = req.sse().unwrap();
+        match req.param("topic").unwrap().into_owned() {
+            topic if topic == "all" => clients.add(stream),
+            topic => clients.add_filtered(stream, move |x| x.event_type() == topic),
+        }
+        Response::end()
+    });
+    let port = start(server);
+
+    let subscribe = |topic: &str| {
+        let mut stream = connect(port);
+        send(&mut stream, &format!("GET /sse/{topic} HTTP/1.1\r\n\r\n"));
+        let mut line = String::new();
+        while line != "\r\n" {
+            line.clear();
+            stream.read_line(&mut line).unwrap();
+        }
+        stream
+    };
+    let read_event = |stream: &mut BufReader<TcpStream>| {
+        let mut out = String::new();
+        while !out.ends_with("\n\n") {
+            stream.read_line(&mut out).unwrap();
+        }
+        out
+    };
+
+    let mut all = subscribe("all");
+    let mut news = subscribe("news");
+    while broadcaster.len() < 2 {
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    broadcaster.broadcast(Event::new("sports").data(1));
+    broadcaster.broadcast(Event::new("news").data(2));
+    assert_eq!(read_event(&mut all), "event: sports\ndata: 1\n\n");
+    assert_eq!(read_event(&mut all), "event: news\ndata: 2\n\n");
+    assert_eq!(read_event(&mut news), "event: news\ndata: 2\n\n");
+
+    // Disconnected clients are removed once a write to them fails
+    drop(news);
+    let start = Instant::now();
+    while broadcaster.len() > 1 && start.elapsed() < Duration::from_secs(5) {
+        broadcaster.broadcast(Event::new("news").data(3));
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(broadcaster.len(), 1);
+}
+
+#[test]
+fn request_target_forms() {
+    let mut server = Server::<()>::new("127.0.0.1", 0);
+    server.route(Method::ANY, "**", |req| {
+        Response::new().text(format!(
+            "{} {:?} {} {}",
+            req.method, req.authority, req.path, req.query
+        ))
+    });
+    server.route(Method::GET, "/addr", |req| {
+        assert!(req.connection_info().is_none());
+        assert_eq!(req.peer_addr(), req.address);
+        Response::new().text(req.local_addr().unwrap())
+    });
+    let port = start(server);
+    let mut stream = connect(port);
+
+    send(&mut stream, "GET /addr HTTP/1.1\r\n\r\n");
+    assert_eq!(
+        read_response(&mut stream).body,
+        format!("127.0.0.1:{port}").as_bytes()
+    );
+
+    for (target, expected) in [
+        ("/a/b?c=d", r#"GET None /a/b ?c=d"#),
+        (
+            "http://example.com:8080/a/b?c=d",
+            r#"GET Some("example.com:8080") /a/b ?c=d"#,
+        ),
+        ("HTTPS://example.com", r#"GET Some("example.com") / "#),
+        (
+            "http://example.com?c=d",
+            r#"GET Some("example.com") / ?c=d"#,
+        ),
+    ] {
+        send(
+            &mut stream,
+            &format!("GET {target} HTTP/1.1\r\nHost: localhost\r\n\r\n"),
+        );
+        let res = read_response(&mut stream);
+        assert_eq!(String::from_utf8(res.body).unwrap(), expected, "{target}");
+    }
+
+    send(
+        &mut stream,
+        "CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\n\r\n",
+    );
+    let res = read_response(&mut stream);
+    assert_eq!(res.body, br#"CONNECT Some("example.com:443")  "#);
+}
+
+#[test]
+#[cfg(feature = "extensions")]
+fn path_normalizer() {
+    let server = |normalizer: PathNormalizer| {
+        let mut server = Server::<()>::new("127.0.0.1", 0);
+        normalizer.attach(&mut server);
+        server.route(Method::GET, "**", |req| Response::new().text(&req.path));
+        connect(start(server))
+    };
+    let get = |stream: &mut BufReader<TcpStream>, path: &str| {
+        send(
+            stream,
+            &format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n"),
+        );
+        read_response(stream)
+    };
+
+    let mut rewrite = server(PathNormalizer::new());
+    assert_eq!(get(&mut rewrite, "/a/./b/../c").body, b"/a/c");
+    assert_eq!(get(&mut rewrite, "/a/%2e%2E/b").body, b"/b");
+
+    let mut redirect = server(PathNormalizer::new().redirect(Status::PermanentRedirect));
+    let res = get(&mut redirect, "/a//./b/../c?x=1");
+    assert_eq!(res.status, 308);
+    assert_eq!(res.header("Location"), Some("/a/c?x=1"));
+    assert_eq!(get(&mut redirect, "/a/c?x=1").body, b"/a/c");
+
+    let mut keep_dots = server(
+        PathNormalizer::new()
+            .redirect(Status::MovedPermanently)
+            .dot_segments(false),
+    );
+    let res = get(&mut keep_dots, "//a/../b");
+    assert_eq!(res.status, 301);
+    assert_eq!(res.header("Location"), Some("/a/../b"));
+}
+
+#[test]
+fn existing_listener() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut server = Server::<()>::new("127.0.0.1", 0).listener(listener);
+    server.route(Method::GET, "/", |_| Response::new().text("Hello"));
+    assert_ne!(server.port, 0);
+
+    let port = start_with(server, |x| x.start_threaded(2));
+    let mut stream = connect(port);
+    send(&mut stream, "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    let res = read_response(&mut stream);
+    assert_eq!(res.status, 200);
+    assert_eq!(res.body, b"Hello");
+}
+
+#[test]
+fn port_zero() {
+    let (tx, rx) = mpsc::channel();
+    let mut server = Server::<()>::new("127.0.0.1", 0).on_start(move |addr| tx.send(addr).unwrap());
+    server.route(Method::GET, "/", |_| Response::new().text("Hello"));
+    let bound = server.bound_addr.clone();
+    assert_eq!(bound.get(), None);
+    thread::spawn(move || server.start_threaded(2).unwrap());
+
+    let addr = bound.wait();
+    assert_ne!(addr.port(), 0);
+    assert_eq!(rx.recv_timeout(Duration::from_secs(5)).unwrap(), addr);
+
+    let mut stream = connect(addr.port());
+    send(&mut stream, "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    assert_eq!(read_response(&mut stream).body, b"Hello");
+}
+
+#[test]
+fn multiple_listeners() {
+    let (tx, rx) = mpsc::channel();
+    let mut server = Server::<()>::new("127.0.0.1", 0)
+        .bind_also("127.0.0.1", 0)
+        .on_start(move |addr| tx.send(addr.port()).unwrap());
+    server.route(Method::GET, "/", |req| {
+        Response::new().text(req.local_addr().unwrap().port())
+    });
+    start_with(server, |x| x.start_threaded(2));
+
+    let ports = [rx.recv().unwrap(), rx.recv().unwrap()];
+    assert_ne!(ports[0], ports[1]);
+    for port in [ports[0], ports[1], ports[0]] {
+        let mut stream = connect(port);
+        send(&mut stream, "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert_eq!(read_response(&mut stream).body, port.to_string().as_bytes());
+    }
+}
+
+#[test]
+fn expect_continue() {
+    let mut server = Server::<()>::new("127.0.0.1", 0);
+    server.route(Method::POST, "/", |req| Response::new().bytes(&req.body));
+    let mut stream = connect(start(server));
+
+    // The body is only sent once the server accepts the head
+    send(
+        &mut stream,
+        "POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\nExpect: 100-continue\r\n\r\n",
+    );
+    assert_eq!(read_head(&mut stream).status, 100);
+    send(&mut stream, "Hello");
+    let res = read_response(&mut stream);
+    assert_eq!(res.status, 200);
+    assert_eq!(res.body, b"Hello");
+
+    // No interim response without a body
+    send(
+        &mut stream,
+        "POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\nExpect: 100-continue\r\n\r\n",
+    );
+    assert_eq!(read_response(&mut stream).status, 200);
+
+    send(
+        &mut stream,
+        "POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\nExpect: something-else\r\n\r\n",
+    );
+    let res = read_response(&mut stream);
+    assert_eq!(res.status, 417);
+    assert_eq!(res.header("Connection"), Some("close"));
+    assert_closed(&mut stream);
+}
+
+#[test]
+fn websocket_send_after_close() {
+    let (tx, rx) = mpsc::channel();
+    let mut server = Server::<()>::new("127.0.0.1", 0);
+    server.route(Method::GET, "/ws", move |req| {
+        let ws = req.ws().unwrap();
+        for _ in &ws {}
+
+        // The client is gone, so these are dropped instead of panicking
+        for _ in 0..50 {
+            ws.send("hello");
+            ws.send_binary(vec![1, 2, 3]);
+            thread::sleep(Duration::from_millis(5));
+        }
+        ws.send_stream(std::io::Cursor::new(vec![0; 1024]), OpCode::Binary);
+        tx.send(()).unwrap();
+        Response::end()
+    });
+    let mut stream = connect(start(server));
+
+    send(
+        &mut stream,
+        "GET /ws HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n",
+    );
+    assert_eq!(read_response(&mut stream).status, 101);
+    drop(stream);
+
+    rx.recv_timeout(Duration::from_secs(5)).unwrap();
+}
+
+#[test]
+fn request_smuggling() {
+    let mut server = Server::<()>::new("127.0.0.1", 0).max_body_size(16);
+    server.route(Method::POST, "/", |req| {
+        Response::new().text(String::from_utf8_lossy(&req.body).into_owned())
+    });
+    server.route(Method::GET, "/smuggled", |_| {
+        Response::new().text("smuggled")
+    });
+    let port = start(server);
+
+    // Each of these could be framed more than one way, so the request after them must not be read
+    for head in [
+        "Content-Length: abc",
+        "Content-Length: +5",
+        "Content-Length: 5\r\nContent-Length: 10",
+        "Content-Length: 5, 10",
+        "Transfer-Encoding: gzip",
+        "Transfer-Encoding: chunked, gzip",
+        "Transfer-Encoding: chunked, chunked",
+        "Transfer-Encoding: chunked\r\nContent-Length: 5",
+        "Content-Length: 5\r\nTransfer-Encoding: chunked",
+    ] {
+        let mut stream = connect(port);
+        send(
+            &mut stream,
+            &format!("POST / HTTP/1.1\r\n{head}\r\n\r\n0\r\n\r\nGET /smuggled HTTP/1.1\r\n\r\n"),
+        );
+        let res = read_response(&mut stream);
+        assert_eq!(res.status, 400, "{head:?}");
+        assert_eq!(res.header("Connection"), Some("close"), "{head:?}");
+        assert_closed(&mut stream);
+    }
+
+    // Chunk sizes have to be plain hex digits and lines have to end with CRLF
+    for body in [
+        "+5\r\nhello\r\n0\r\n\r\n",
+        " 5\r\nhello\r\n0\r\n\r\n",
+        "5\nhello\r\n0\r\n\r\n",
+        "5\r\nhello\n0\r\n\r\n",
+    ] {
+        let mut stream = connect(port);
+        send(
+            &mut stream,
+            &format!("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n{body}GET /smuggled HTTP/1.1\r\n\r\n"),
+        );
+        let res = read_response(&mut stream);
+        assert_eq!(res.status, 400, "{body:?}");
+        assert_eq!(res.header("Connection"), Some("close"), "{body:?}");
+        assert_closed(&mut stream);
+    }
+
+    // Matching repeated lengths are the same as one
+    let mut stream = connect(port);
+    send(
+        &mut stream,
+        "POST / HTTP/1.1\r\nContent-Length: 5\r\nContent-Length: 5\r\n\r\nhello",
+    );
+    assert_eq!(read_response(&mut stream).body, b"hello");
+    drop(stream);
+
+    // Bodies over the max size are rejected, even without a body buffer limit
+    for raw in [
+        format!("POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n", usize::MAX),
+        "POST / HTTP/1.1\r\nContent-Length: 17\r\n\r\n".to_owned(),
+        format!(
+            "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n11\r\n{}\r\n0\r\n\r\n",
+            "a".repeat(17)
+        ),
+    ] {
+        let mut stream = connect(port);
+        send(&mut stream, &raw);
+        assert_eq!(read_response(&mut stream).status, 413, "{raw:?}");
+        assert_closed(&mut stream);
+    }
+}
+
+#[test]
+#[cfg(feature = "tls")]
+fn tls_handshake_timeout() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/tls");
+    let read = |name: &str| fs::read(dir.join(name)).unwrap();
+
+    let mut server = Server::<()>::new("127.0.0.1", 0).head_timeout(Duration::from_millis(300));
+    server.route(Method::GET, "/", |_| Response::new().text("ok"));
+    let tls = TlsConfig::from_pem(read("server.pem"), read("server.key")).unwrap();
+    let port = start_with(server, |x| x.start_tls(1, tls));
+
+    // Neither sending nothing nor a byte at a time holds the only worker past the deadline
+    for drip in [false, true] {
+        let mut stream = connect(port);
+        let start = Instant::now();
+        if drip {
+            // The start of a TLS ClientHello record
+            for i in [0x16, 0x03, 0x01, 0x02, 0x00, 0x01] {
+                if stream.get_mut().write_all(&[i]).is_err() {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(100));
+            }
+        }
+        assert_closed(&mut stream);
+        assert!(start.elapsed() < Duration::from_secs(3), "{}", drip);
+    }
+}